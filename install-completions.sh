@@ -26,7 +26,7 @@ case "$SHELL_NAME" in
     mkdir -p "$ZSH_COMPLETIONS_DIR"
     
     # Generate completion
-    zb completion zsh > "$ZSH_COMPLETIONS_DIR/_zb"
+    zb completions zsh > "$ZSH_COMPLETIONS_DIR/_zb"
     echo -e "${GREEN}✓${NC} Generated zsh completions at $ZSH_COMPLETIONS_DIR/_zb"
     
     # Add completion function to shell config if not already there
@@ -64,7 +64,7 @@ case "$SHELL_NAME" in
     mkdir -p "$BASH_COMPLETIONS_DIR"
     
     # Generate completion
-    zb completion bash > "$BASH_COMPLETIONS_DIR/zb"
+    zb completions bash > "$BASH_COMPLETIONS_DIR/zb"
     echo -e "${GREEN}✓${NC} Generated bash completions at $BASH_COMPLETIONS_DIR/zb"
     
     # Add sourcing to bashrc if not already there
@@ -94,7 +94,7 @@ case "$SHELL_NAME" in
     mkdir -p "$FISH_COMPLETIONS_DIR"
     
     # Generate completion
-    zb completion fish > "$FISH_COMPLETIONS_DIR/zb.fish"
+    zb completions fish > "$FISH_COMPLETIONS_DIR/zb.fish"
     echo -e "${GREEN}✓${NC} Generated fish completions at $FISH_COMPLETIONS_DIR/zb.fish"
     
     echo ""
@@ -107,9 +107,9 @@ case "$SHELL_NAME" in
     echo "Supported shells: zsh, bash, fish"
     echo ""
     echo "You can manually generate completions with:"
-    echo -e "  ${CYAN}zb completion bash${NC}  # for bash"
-    echo -e "  ${CYAN}zb completion zsh${NC}   # for zsh"
-    echo -e "  ${CYAN}zb completion fish${NC}  # for fish"
+    echo -e "  ${CYAN}zb completions bash${NC}  # for bash"
+    echo -e "  ${CYAN}zb completions zsh${NC}   # for zsh"
+    echo -e "  ${CYAN}zb completions fish${NC}  # for fish"
     exit 1
     ;;
 esac
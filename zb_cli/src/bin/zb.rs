@@ -1,30 +1,62 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use console::style;
+use std::time::Instant;
 use zb_cli::{
-    cli::{Cli, Commands},
+    cli::{Cli, Commands, ServicesCommands},
     commands,
     init::ensure_init,
-    utils::get_root_path,
+    utils::{get_root_path, set_strict},
 };
-use zb_io::create_installer;
+use zb_io::{Notifier, create_installer};
 
 #[tokio::main]
 async fn main() {
+    // Answers `COMPLETE=<shell> zb ...` requests from the dynamic completion
+    // scripts and exits; a no-op otherwise.
+    clap_complete::CompleteEnv::with_factory(Cli::command).complete();
+
     let cli = Cli::parse();
+    // `zb outdated` reserves exit code 1 for "updates are available".
+    let error_code = if matches!(
+        cli.command,
+        Commands::Outdated { .. } | Commands::Audit { .. }
+    ) {
+        2
+    } else {
+        1
+    };
+
+    let json = cli.command.wants_json();
 
     if let Err(e) = run(cli).await {
+        if json {
+            eprintln!("{}", e.to_json());
+            std::process::exit(error_code);
+        }
         eprintln!("{} {}", style("error:").red().bold(), e);
-        std::process::exit(1);
+        if let Some(hint) = e.hint() {
+            eprintln!("{} {}", style("hint:").cyan().bold(), hint);
+        }
+        std::process::exit(error_code);
     }
 }
 
 async fn run(cli: Cli) -> Result<(), zb_core::Error> {
-    if let Commands::Completion { shell } = cli.command {
-        return commands::completion::execute(shell);
+    if let Commands::Completions {
+        shell,
+        static_script,
+    } = cli.command
+    {
+        return commands::completion::execute(shell, static_script);
     }
 
+    set_strict(cli.strict);
     let root = get_root_path(cli.root);
-    let prefix = cli.prefix.unwrap_or_else(|| {
+    let relocate_to = match &cli.command {
+        Commands::Relocate { to, .. } => to.clone(),
+        _ => None,
+    };
+    let prefix = cli.prefix.clone().or(relocate_to).unwrap_or_else(|| {
         // On macOS, Mach-O binaries have fixed-size path fields so the prefix
         // must be no longer than the original Homebrew prefix (/opt/homebrew = 13 chars).
         // Using root directly (/opt/zerobrew = 13 chars) keeps us within that limit.
@@ -35,37 +67,294 @@ async fn run(cli: Cli) -> Result<(), zb_core::Error> {
         }
     });
 
-    if let Commands::Init { no_modify_path } = cli.command {
-        return commands::init::execute(&root, &prefix, no_modify_path);
+    // Commands that only need the paths run before anything is opened.
+    match cli.command {
+        Commands::Init { no_modify_path } => {
+            return commands::init::execute(&root, &prefix, no_modify_path);
+        }
+        Commands::Setup => return commands::setup::execute(&root, &prefix, cli.concurrency).await,
+        Commands::Shellenv => return commands::shellenv::execute(&root, &prefix),
+        Commands::Reset { yes } => return commands::reset::execute(&root, &prefix, yes),
+        _ => {}
     }
 
-    if !matches!(cli.command, Commands::Reset { .. }) {
-        ensure_init(&root, &prefix, cli.auto_init)?;
+    ensure_init(&root, &prefix, cli.auto_init || cli.auto_init_yes)?;
+
+    let snapshots = zb_io::SnapshotStore::new(&root, &prefix);
+    if let Commands::Snapshot { command } = cli.command {
+        return commands::snapshot::execute(&snapshots, command);
     }
 
+    // The installer opens the database and Cellar; the index, store, linker,
+    // download cache and plugins wait for the first command that uses them.
     let mut installer = create_installer(&root, &prefix, cli.concurrency)?;
+    installer.set_debug_http(cli.debug_http);
+    installer.set_accept_licenses(cli.accept_licenses);
+    installer.set_strict(cli.strict);
 
-    match cli.command {
-        Commands::Init { .. } => unreachable!(),
-        Commands::Completion { .. } => unreachable!(),
+    let notification = cli
+        .command
+        .notification_label()
+        .zip(Notifier::from_config(&installer.config().notify));
+    let start = Instant::now();
+
+    let result = match cli.command {
+        Commands::Init { .. } | Commands::Setup | Commands::Shellenv | Commands::Reset { .. } => {
+            unreachable!()
+        }
+        Commands::Completions { .. } => unreachable!(),
         Commands::Install {
-            formulas,
+            mut formulas,
             no_link,
-            build_from_source,
-        } => commands::install::execute(&mut installer, formulas, no_link, build_from_source).await,
+            mut build_from_source,
+            locked,
+            dry_run,
+            lazy,
+            note,
+            force,
+            offline,
+        } => {
+            if lazy {
+                installer.set_lazy_launcher(Some(commands::materialize::launcher(&root, &prefix)?));
+            }
+            installer.set_force(force);
+            installer.set_offline(offline);
+            let lockfile = if locked {
+                let lockfile = zb_io::Lockfile::read(std::path::Path::new(zb_io::LOCKFILE_NAME))?;
+                formulas = lockfile.roots.clone();
+                build_from_source = lockfile.build_from_source;
+                Some(lockfile)
+            } else {
+                None
+            };
+            if dry_run {
+                return commands::install::dry_run(
+                    &installer,
+                    &formulas,
+                    build_from_source,
+                    lockfile.as_ref(),
+                )
+                .await;
+            }
+            commands::install::execute(
+                &mut installer,
+                formulas,
+                no_link,
+                build_from_source,
+                note.as_deref(),
+                lockfile.as_ref(),
+            )
+            .await
+        }
         Commands::Bundle { command } => commands::bundle::execute(&mut installer, command).await,
-        Commands::Uninstall { formulas, all } => {
-            commands::uninstall::execute(&mut installer, formulas, all)
+        Commands::ImportFrom {
+            file,
+            format,
+            dry_run,
+            no_link,
+        } => commands::import::execute(&mut installer, &file, format, dry_run, no_link).await,
+        Commands::Reinstall { formulas } => {
+            commands::reinstall::execute(&mut installer, &formulas).await
+        }
+        Commands::Uninstall {
+            formulas,
+            all,
+            yes,
+            ignore_dependencies,
+            zap,
+            force,
+            backup,
+        } => {
+            installer.set_ignore_dependencies(ignore_dependencies);
+            installer.set_zap(zap);
+            // Backed up files are safe to delete.
+            installer.set_force(force || backup);
+            let backups = root.join("backups");
+            commands::uninstall::execute(
+                &mut installer,
+                formulas,
+                all,
+                yes,
+                backup.then_some(backups.as_path()),
+            )
+        }
+        Commands::Migrate {
+            yes,
+            force,
+            unlink,
+            snapshot,
+        } => {
+            let snapshots = snapshot.then_some(&snapshots);
+            commands::migrate::execute(&mut installer, yes, force, unlink, snapshots).await
+        }
+        Commands::Pin { formulas } => commands::pin::pin(&mut installer, formulas),
+        Commands::Unpin { formulas } => commands::pin::unpin(&mut installer, formulas),
+        Commands::Upgrade {
+            formulas,
+            preview: true,
+            ..
+        } => commands::upgrade::preview(&mut installer, formulas).await,
+        Commands::Upgrade {
+            formulas,
+            snapshot,
+            yes,
+            ..
+        } => {
+            let snapshots = snapshot.then_some(&snapshots);
+            commands::upgrade::execute(&mut installer, formulas, snapshots, yes).await
+        }
+        Commands::Snapshot { .. } => unreachable!(),
+        Commands::Set { command } => commands::set::execute(&mut installer, command).await,
+        Commands::Queue { command } => commands::queue::execute(&mut installer, command).await,
+        Commands::List {
+            versions,
+            notes,
+            long,
+            json,
+            tree,
+        } => commands::list::execute(&mut installer, versions, notes, long, json, tree),
+        Commands::Leaves => commands::leaves::execute(&mut installer),
+        Commands::Report { format, output } => {
+            commands::report::execute(&mut installer, format, output).await
+        }
+        Commands::Audit { security, offline } => {
+            if commands::audit::execute(&mut installer, security, offline).await? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::Outdated { diff, quiet, json } => {
+            if commands::outdated::execute(&mut installer, diff, quiet, json).await? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::Info {
+            formula,
+            installed_files_size,
+        } => commands::info::execute(&mut installer, formula, installed_files_size).await,
+        Commands::Deps {
+            formulas,
+            tree,
+            installed_only,
+            include_build,
+            json,
+            closure,
+        } => {
+            commands::deps::execute(
+                &installer,
+                formulas,
+                tree,
+                installed_only,
+                include_build,
+                json,
+                closure,
+            )
+            .await
+        }
+        Commands::Cat { formula } => commands::cat::execute(&installer, &formula).await,
+        Commands::Test { formulas } => commands::test::execute(&installer, formulas).await,
+        Commands::Materialize { formula } => {
+            commands::materialize::execute(&mut installer, &formula)
+        }
+        Commands::Link {
+            formula,
+            only,
+            dry_run,
+            overwrite,
+        } => commands::link::execute(&mut installer, &formula, &only, dry_run, overwrite),
+        Commands::Unlink {
+            formula,
+            only,
+            dry_run,
+        } => commands::unlink::execute(&mut installer, &formula, &only, dry_run),
+        Commands::Relocate { from, to } => {
+            commands::relocate::execute(&mut installer, &prefix, &from, to.as_deref())
         }
-        Commands::Migrate { yes, force } => {
-            commands::migrate::execute(&mut installer, yes, force).await
+        Commands::Plan {
+            formulas,
+            build_from_source,
+            json,
+            apply,
+            lock,
+        } => {
+            commands::plan::execute(
+                &mut installer,
+                formulas,
+                build_from_source,
+                json,
+                apply,
+                lock,
+            )
+            .await
+        }
+        Commands::VerifyPlan { hash } => commands::verify_plan::execute(&installer, &hash),
+        Commands::Tap { name, remote } => {
+            commands::tap::tap(&mut installer, name.as_deref(), remote.as_deref()).await
+        }
+        Commands::Untap { names, force } => commands::tap::untap(&mut installer, &names, force),
+        Commands::Update {
+            pin_index,
+            unpin_index,
+        } => commands::update::execute(&mut installer, pin_index.as_deref(), unpin_index).await,
+        Commands::Stats { reset } => commands::stats::execute(&mut installer, reset),
+        Commands::Search { query, limit } => {
+            commands::search::execute(&installer, &query.join(" "), limit)
+        }
+        Commands::Cleanup {
+            prune,
+            compact,
+            unused_for,
+            dry_run,
+        } => commands::cleanup::execute(&mut installer, prune, compact, unused_for, dry_run),
+        Commands::Gc {
+            dry_run,
+            min_age,
+            aggressive,
+            prune_cache_older_than,
+            keep_latest_kegs,
+        } => commands::gc::execute(
+            &mut installer,
+            dry_run,
+            min_age,
+            aggressive,
+            prune_cache_older_than,
+            keep_latest_kegs,
+        ),
+        Commands::Cache { command } => commands::cache::execute(&installer, command).await,
+        Commands::Services { command } => {
+            commands::services::execute(&mut installer, command.unwrap_or(ServicesCommands::List))
+                .await
         }
-        Commands::List => commands::list::execute(&mut installer),
-        Commands::Info { formula } => commands::info::execute(&mut installer, formula),
-        Commands::Gc => commands::gc::execute(&mut installer),
-        Commands::Reset { yes } => commands::reset::execute(&root, &prefix, yes),
-        Commands::Run { formula, args } => {
-            commands::run::execute(&mut installer, formula, args).await
+        Commands::Doctor {
+            store,
+            adopt,
+            delete_orphans,
+            shell,
+        } => commands::doctor::execute(&mut installer, &root, store, adopt, delete_orphans, shell),
+        Commands::Bottle {
+            formula,
+            tag,
+            output,
+            root_url,
+        } => commands::bottle::execute(&installer, &formula, tag, &output, root_url.as_deref()),
+        Commands::Exec { with, command } => {
+            commands::exec::execute(&installer, &prefix, with, command)
         }
+        Commands::Shell { formulas } => {
+            commands::shell::execute(&mut installer, &root, &prefix, formulas).await
+        }
+        Commands::Run { mut command } => {
+            let formula = command.remove(0);
+            commands::run::execute(&mut installer, formula, command).await
+        }
+    };
+
+    if let Some((label, notifier)) = notification
+        && let Err(e) = notifier.finished(&label, start.elapsed(), result.is_ok())
+    {
+        eprintln!("{} {}", style("Warning:").yellow().bold(), e);
     }
+
+    result
 }
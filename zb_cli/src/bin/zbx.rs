@@ -5,13 +5,16 @@ use std::process::Command;
 fn main() {
     let args: Vec<String> = env::args().skip(1).collect();
 
-    if args.is_empty() {
+    if args.is_empty() || matches!(args[0].as_str(), "-h" | "--help") {
         eprintln!("zbx - Run a command from a formula without linking it");
         eprintln!();
-        eprintln!("Usage: zbx <formula> [args...]");
+        eprintln!("Usage: zbx <formula>[@version] [args...]");
+        eprintln!();
+        eprintln!("Every argument after the formula is passed to the command as is.");
         eprintln!();
         eprintln!("Examples:");
         eprintln!("  zbx jq --version");
+        eprintln!("  zbx jq@1.7 --version");
         eprintln!("  zbx wget https://example.com");
         std::process::exit(1);
     }
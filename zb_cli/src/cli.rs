@@ -1,5 +1,9 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use clap_complete::engine::ArgValueCompleter;
 use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::commands::completion::formula_names;
 
 #[derive(Parser)]
 #[command(name = "zb")]
@@ -19,14 +23,27 @@ pub struct Cli {
     )]
     pub concurrency: usize,
 
-    #[arg(
-        long = "auto-init",
-        alias = "yes",
-        global = true,
-        env = "ZEROBREW_AUTO_INIT"
-    )]
+    #[arg(long = "auto-init", global = true, env = "ZEROBREW_AUTO_INIT")]
     pub auto_init: bool,
 
+    // The older spelling of `--auto-init`, accepted before the subcommand.
+    // It isn't global because commands that confirm something take a
+    // `--yes` of their own.
+    #[arg(long = "yes", hide = true)]
+    pub auto_init_yes: bool,
+
+    #[arg(long, global = true, env = "ZEROBREW_DEBUG_HTTP")]
+    pub debug_http: bool,
+
+    #[arg(long, global = true, env = "ZEROBREW_ACCEPT_LICENSES")]
+    pub accept_licenses: bool,
+
+    // Warnings (deprecated formulas, unverifiable sources, skipped opt
+    // links, index entries that no longer parse) fail the command, and
+    // nothing prompts: confirmations need --yes.
+    #[arg(long, global = true, env = "ZEROBREW_STRICT")]
+    pub strict: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -41,10 +58,47 @@ fn parse_concurrency(value: &str) -> Result<usize, String> {
     Ok(parsed)
 }
 
+fn parse_keep_count(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("at least the installed version has to be kept (1)".to_string()),
+        Ok(count) => Ok(count),
+        Err(_) => Err(format!(
+            "invalid value '{value}': expected a positive integer"
+        )),
+    }
+}
+
+/// `<n>` seconds, or `<n>` followed by `s`, `m`, `h` or `d`.
+fn parse_age(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => value.split_at(split),
+        None => (value, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid age '{value}': expected e.g. 30m, 12h or 7d"))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("invalid age '{value}': unit must be s, m, h or d")),
+    };
+    Ok(Duration::from_secs(number.saturating_mul(unit_secs)))
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Cli;
-    use clap::Parser;
+    use super::{CacheCommands, Cli, Commands, parse_age, parse_keep_count};
+    use clap::{CommandFactory, Parser};
+    use std::time::Duration;
+
+    #[test]
+    fn command_definition_is_consistent() {
+        // Completion builds every subcommand, which trips clap's checks
+        // (e.g. a global flag clashing with a subcommand's) that parsing doesn't.
+        Cli::command().debug_assert();
+    }
 
     #[test]
     fn accepts_positive_concurrency() {
@@ -59,39 +113,411 @@ mod tests {
         let err = result.err().map(|e| e.to_string()).unwrap_or_default();
         assert!(err.contains("at least 1"));
     }
+
+    #[test]
+    fn outdated_quiet_conflicts_with_diff() {
+        let cli = Cli::try_parse_from(["zb", "outdated", "-q"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Outdated {
+                quiet: true,
+                diff: false,
+                json: false
+            }
+        ));
+        assert!(Cli::try_parse_from(["zb", "outdated", "--quiet", "--diff"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "outdated", "--quiet", "--json"]).is_err());
+    }
+
+    #[test]
+    fn json_commands_want_json_errors() {
+        let cli = Cli::try_parse_from(["zb", "plan", "jq", "--json"]).unwrap();
+        assert!(cli.command.wants_json());
+        let cli = Cli::try_parse_from(["zb", "plan", "jq"]).unwrap();
+        assert!(!cli.command.wants_json());
+    }
+
+    #[test]
+    fn strict_is_accepted_after_the_subcommand() {
+        let cli = Cli::try_parse_from(["zb", "install", "jq", "--strict"]).unwrap();
+        assert!(cli.strict);
+        assert!(!Cli::try_parse_from(["zb", "install", "jq"]).unwrap().strict);
+    }
+
+    #[test]
+    fn cache_serve_listens_on_the_peer_port_by_default() {
+        let cli = Cli::try_parse_from(["zb", "cache", "serve"]).unwrap();
+        let Commands::Cache {
+            command: CacheCommands::Serve { listen },
+        } = cli.command
+        else {
+            panic!("expected cache serve");
+        };
+        assert_eq!(listen, "0.0.0.0:7480");
+        assert!(Cli::try_parse_from(["zb", "cache", "pull"]).is_err());
+    }
+
+    #[test]
+    fn plan_applies_or_prints_json_but_not_both() {
+        assert!(Cli::try_parse_from(["zb", "plan", "jq", "--apply"]).is_ok());
+        assert!(Cli::try_parse_from(["zb", "plan", "jq", "--json", "--apply"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "plan"]).is_err());
+    }
+
+    #[test]
+    fn gc_keeps_at_least_the_installed_keg() {
+        assert_eq!(parse_keep_count("2"), Ok(2));
+        assert!(parse_keep_count("0").is_err());
+        assert!(parse_keep_count("-1").is_err());
+    }
+
+    #[test]
+    fn uninstall_and_upgrade_take_long_yes() {
+        let cli = Cli::try_parse_from(["zb", "uninstall", "--yes", "jq", "wget"]).unwrap();
+        assert!(matches!(cli.command, Commands::Uninstall { yes: true, .. }));
+        let cli = Cli::try_parse_from(["zb", "upgrade", "--yes"]).unwrap();
+        assert!(matches!(cli.command, Commands::Upgrade { yes: true, .. }));
+    }
+
+    #[test]
+    fn doctor_orphan_handling_needs_the_store_pass() {
+        assert!(Cli::try_parse_from(["zb", "doctor", "--adopt"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "doctor", "--delete-orphans"]).is_err());
+        let cli = Cli::try_parse_from(["zb", "doctor", "--store", "--adopt"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Doctor {
+                store: true,
+                adopt: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn yes_before_the_subcommand_still_means_auto_init() {
+        let cli = Cli::try_parse_from(["zb", "--yes", "list"]).unwrap();
+        assert!(cli.auto_init_yes);
+        let cli = Cli::try_parse_from(["zb", "--yes", "migrate", "--yes"]).unwrap();
+        assert!(cli.auto_init_yes);
+        assert!(matches!(cli.command, Commands::Migrate { yes: true, .. }));
+    }
+
+    #[test]
+    fn run_passes_every_argument_after_the_formula_through() {
+        let cli = Cli::try_parse_from(["zb", "run", "jq@1.7", "--auto-init", "--help", "--", "-V"])
+            .unwrap();
+        assert!(!cli.auto_init);
+        let Commands::Run { command } = cli.command else {
+            panic!("expected zb run");
+        };
+        assert_eq!(command, ["jq@1.7", "--auto-init", "--help", "--", "-V"]);
+    }
+
+    #[test]
+    fn ages_take_an_optional_unit() {
+        assert_eq!(parse_age("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_age("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_age("2d"), Ok(Duration::from_secs(172_800)));
+        assert!(parse_age("2w").is_err());
+        assert!(parse_age("h").is_err());
+    }
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     Install {
-        #[arg(required = true, num_args = 1..)]
+        #[arg(
+            required_unless_present = "locked",
+            num_args = 1..,
+            add = ArgValueCompleter::new(formula_names)
+        )]
         formulas: Vec<String>,
         #[arg(long)]
         no_link: bool,
         #[arg(long, short = 's')]
         build_from_source: bool,
+        // Install exactly what zb.lock pins, failing if the plan drifted.
+        #[arg(long, conflicts_with_all = ["formulas", "build_from_source"])]
+        locked: bool,
+        // Show the plan, download sizes and disk estimate; change nothing.
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+        #[arg(long, env = "ZEROBREW_LAZY", conflicts_with = "no_link")]
+        lazy: bool,
+        #[arg(long)]
+        note: Option<String>,
+        // Install even when a formula conflicts with an installed one.
+        #[arg(long)]
+        force: bool,
+        // Resolve formulas from the local index only, without staleness warnings.
+        #[arg(long)]
+        offline: bool,
     },
     Bundle {
         #[command(subcommand)]
         command: Option<BundleCommands>,
     },
+    ImportFrom {
+        file: PathBuf,
+        #[arg(long, value_enum)]
+        format: Option<ImportFormat>,
+        #[arg(long)]
+        dry_run: bool,
+        #[arg(long)]
+        no_link: bool,
+    },
     Uninstall {
         #[arg(required_unless_present = "all", num_args = 1..)]
         formulas: Vec<String>,
         #[arg(long)]
         all: bool,
+        #[arg(long, short = 'y')]
+        yes: bool,
+        #[arg(long)]
+        ignore_dependencies: bool,
+        // Casks: also remove the preferences, caches and support files their zap stanza names.
+        #[arg(long)]
+        zap: bool,
+        // Delete keg files edited or added since the install.
+        #[arg(long)]
+        force: bool,
+        // Copy keg files edited or added since the install to the backups directory first.
+        #[arg(long, conflicts_with = "force")]
+        backup: bool,
+    },
+    Reinstall {
+        #[arg(required = true, num_args = 1.., add = ArgValueCompleter::new(formula_names))]
+        formulas: Vec<String>,
+    },
+    Pin {
+        #[arg(add = ArgValueCompleter::new(formula_names))]
+        formulas: Vec<String>,
+    },
+    Unpin {
+        #[arg(required = true, num_args = 1..)]
+        formulas: Vec<String>,
+    },
+    Upgrade {
+        formulas: Vec<String>,
+        #[arg(long, env = "ZEROBREW_SNAPSHOT")]
+        snapshot: bool,
+        #[arg(long, conflicts_with = "snapshot")]
+        preview: bool,
+        #[arg(long, short = 'y')]
+        yes: bool,
     },
     Migrate {
         #[arg(long, short = 'y')]
         yes: bool,
         #[arg(long)]
         force: bool,
+        #[arg(long, conflicts_with = "force")]
+        unlink: bool,
+        #[arg(long, env = "ZEROBREW_SNAPSHOT")]
+        snapshot: bool,
+    },
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommands,
+    },
+    Set {
+        #[command(subcommand)]
+        command: SetCommands,
+    },
+    Queue {
+        #[command(subcommand)]
+        command: QueueCommands,
+    },
+    List {
+        #[arg(long)]
+        versions: bool,
+        #[arg(long)]
+        notes: bool,
+        #[arg(long, short = 'l')]
+        long: bool,
+        #[arg(long, conflicts_with_all = ["long", "versions", "notes"])]
+        json: bool,
+        #[arg(long, conflicts_with_all = ["long", "versions", "notes", "json"])]
+        tree: bool,
+    },
+    Leaves,
+    // Without a name, lists the taps.
+    Tap {
+        name: Option<String>,
+        #[arg(requires = "name")]
+        remote: Option<String>,
+    },
+    Untap {
+        #[arg(required = true, num_args = 1..)]
+        names: Vec<String>,
+        #[arg(long)]
+        force: bool,
+    },
+    Update {
+        #[arg(long, value_name = "DATE_OR_SHA", conflicts_with = "unpin_index")]
+        pin_index: Option<String>,
+        #[arg(long)]
+        unpin_index: bool,
+    },
+    Stats {
+        #[arg(long)]
+        reset: bool,
+    },
+    Search {
+        #[arg(required = true, num_args = 1..)]
+        query: Vec<String>,
+        #[arg(long, default_value = "20")]
+        limit: usize,
+    },
+    Outdated {
+        #[arg(long, conflicts_with = "quiet")]
+        diff: bool,
+        #[arg(long, short = 'q')]
+        quiet: bool,
+        #[arg(long, conflicts_with = "quiet")]
+        json: bool,
+    },
+    Audit {
+        #[arg(long)]
+        security: bool,
+        #[arg(long, requires = "security")]
+        offline: bool,
+    },
+    Report {
+        #[arg(long, value_enum, default_value = "markdown")]
+        format: ReportFormat,
+        #[arg(long, short = 'o', value_name = "FILE")]
+        output: Option<PathBuf>,
     },
-    List,
     Info {
+        #[arg(add = ArgValueCompleter::new(formula_names))]
+        formula: String,
+        #[arg(long)]
+        installed_files_size: bool,
+    },
+    Deps {
+        #[arg(
+            required = true,
+            num_args = 1..,
+            add = ArgValueCompleter::new(formula_names)
+        )]
+        formulas: Vec<String>,
+        #[arg(long)]
+        tree: bool,
+        #[arg(long)]
+        installed_only: bool,
+        #[arg(long)]
+        include_build: bool,
+        #[arg(long, conflicts_with = "tree")]
+        json: bool,
+        // The whole resolved graph, flat, for tools that consume it
+        #[arg(long, requires = "json", conflicts_with = "installed_only")]
+        closure: bool,
+    },
+    Cat {
+        #[arg(add = ArgValueCompleter::new(formula_names))]
         formula: String,
     },
-    Gc,
+    Test {
+        #[arg(required = true, num_args = 1..)]
+        formulas: Vec<String>,
+    },
+    Materialize {
+        formula: String,
+    },
+    Link {
+        formula: String,
+        #[arg(long, value_name = "PATH")]
+        only: Vec<String>,
+        #[arg(long)]
+        dry_run: bool,
+        #[arg(long)]
+        overwrite: bool,
+    },
+    Unlink {
+        formula: String,
+        #[arg(long, value_name = "PATH")]
+        only: Vec<String>,
+        #[arg(long)]
+        dry_run: bool,
+    },
+    // What installing the formulas would change, without changing it (unless --apply).
+    Plan {
+        #[arg(
+            required = true,
+            num_args = 1..,
+            add = ArgValueCompleter::new(formula_names)
+        )]
+        formulas: Vec<String>,
+        #[arg(long, short = 's')]
+        build_from_source: bool,
+        #[arg(long)]
+        json: bool,
+        #[arg(long, conflicts_with = "json")]
+        apply: bool,
+        // Write the resolved set to zb.lock for `zb install --locked`.
+        #[arg(long)]
+        lock: bool,
+    },
+    VerifyPlan {
+        hash: String,
+    },
+    Relocate {
+        #[arg(long, value_name = "OLD_PREFIX")]
+        from: PathBuf,
+        #[arg(long, value_name = "NEW_PREFIX")]
+        to: Option<PathBuf>,
+    },
+    Gc {
+        #[arg(long)]
+        dry_run: bool,
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        min_age: Option<Duration>,
+        #[arg(long)]
+        aggressive: bool,
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        prune_cache_older_than: Option<Duration>,
+        #[arg(long, value_name = "N", value_parser = parse_keep_count)]
+        keep_latest_kegs: Option<usize>,
+    },
+    Cleanup {
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        prune: Option<Duration>,
+        #[arg(long)]
+        compact: bool,
+        #[arg(
+            long,
+            value_name = "AGE",
+            value_parser = parse_age,
+            default_value = "30d",
+            requires = "compact"
+        )]
+        unused_for: Duration,
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    // Without a subcommand, lists the services.
+    Services {
+        #[command(subcommand)]
+        command: Option<ServicesCommands>,
+    },
+    Doctor {
+        // Write the store pass's refcount repairs instead of only reporting them.
+        #[arg(long)]
+        store: bool,
+        #[arg(long, requires = "store", conflicts_with = "delete_orphans")]
+        adopt: bool,
+        #[arg(long, requires = "store")]
+        delete_orphans: bool,
+        // Only check the zerobrew block in the shell profile.
+        #[arg(long, conflicts_with_all = ["store", "adopt", "delete_orphans"])]
+        shell: bool,
+    },
     Reset {
         #[arg(long, short = 'y')]
         yes: bool,
@@ -100,18 +526,101 @@ pub enum Commands {
         #[arg(long)]
         no_modify_path: bool,
     },
-    Completion {
+    Setup,
+    Shellenv,
+    #[command(alias = "completion")]
+    Completions {
         #[arg(value_enum)]
         shell: clap_complete::shells::Shell,
+        #[arg(long = "static")]
+        static_script: bool,
+    },
+    Bottle {
+        formula: String,
+        #[arg(long)]
+        tag: Option<String>,
+        #[arg(long, short = 'o', value_name = "DIR", default_value = ".")]
+        output: PathBuf,
+        #[arg(long, value_name = "URL")]
+        root_url: Option<String>,
+    },
+    Exec {
+        #[arg(long = "with", value_name = "FORMULA")]
+        with: Vec<String>,
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            required = true,
+            num_args = 1..
+        )]
+        command: Vec<String>,
     },
+    Shell {
+        #[arg(required = true, num_args = 1..)]
+        formulas: Vec<String>,
+    },
+    // Everything after the formula goes to the tool untouched, even flags
+    // zb itself knows, so it is one trailing positional.
     #[command(disable_help_flag = true)]
     Run {
-        formula: String,
-        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
-        args: Vec<String>,
+        #[arg(
+            required = true,
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            value_name = "FORMULA[@VERSION] [ARGS]"
+        )]
+        command: Vec<String>,
     },
 }
 
+impl Commands {
+    /// How a long-running install-like command is named in its completion
+    /// notification, or `None` for commands that never notify.
+    pub fn notification_label(&self) -> Option<String> {
+        match self {
+            Commands::Install { dry_run: true, .. } => None,
+            Commands::Install { locked: true, .. } => Some("zb install --locked".to_string()),
+            Commands::Install { formulas, .. } => {
+                Some(format!("zb install {}", formulas.join(" ")))
+            }
+            Commands::Bundle {
+                command: None | Some(BundleCommands::Install { .. }),
+            } => Some("zb bundle".to_string()),
+            Commands::ImportFrom { dry_run: false, .. } => Some("zb import-from".to_string()),
+            Commands::Migrate { .. } => Some("zb migrate".to_string()),
+            Commands::Upgrade { preview: true, .. } => None,
+            Commands::Upgrade { formulas, .. } if formulas.is_empty() => {
+                Some("zb upgrade".to_string())
+            }
+            Commands::Upgrade { formulas, .. } => {
+                Some(format!("zb upgrade {}", formulas.join(" ")))
+            }
+            Commands::Set {
+                command: SetCommands::Install { name, .. },
+            } => Some(format!("zb set install {name}")),
+            Commands::Set {
+                command: SetCommands::Upgrade { name },
+            } => Some(format!("zb set upgrade {name}")),
+            Commands::Queue {
+                command: QueueCommands::Run { .. },
+            } => Some("zb queue run".to_string()),
+            _ => None,
+        }
+    }
+
+    /// Whether the command was asked for `--json` output, in which case a
+    /// failure is reported as JSON too.
+    pub fn wants_json(&self) -> bool {
+        matches!(
+            self,
+            Commands::List { json: true, .. }
+                | Commands::Outdated { json: true, .. }
+                | Commands::Deps { json: true, .. }
+                | Commands::Plan { json: true, .. }
+        )
+    }
+}
+
 #[derive(Subcommand)]
 pub enum BundleCommands {
     Install {
@@ -127,3 +636,113 @@ pub enum BundleCommands {
         force: bool,
     },
 }
+
+#[derive(Subcommand)]
+pub enum SetCommands {
+    List,
+    Show {
+        name: String,
+    },
+    Create {
+        name: String,
+        #[arg(required = true, num_args = 1..)]
+        formulas: Vec<String>,
+        #[arg(long)]
+        force: bool,
+    },
+    Delete {
+        name: String,
+    },
+    Install {
+        name: String,
+        #[arg(long)]
+        no_link: bool,
+    },
+    Upgrade {
+        name: String,
+    },
+    Uninstall {
+        name: String,
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum QueueCommands {
+    Add {
+        #[arg(required = true, num_args = 1..)]
+        formulas: Vec<String>,
+    },
+    List,
+    Remove {
+        #[arg(required = true, num_args = 1..)]
+        formulas: Vec<String>,
+    },
+    Run {
+        #[arg(long)]
+        no_link: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    // Share the download cache with other machines on the network (read-only).
+    Serve {
+        #[arg(long, value_name = "ADDR", default_value_t = format!("0.0.0.0:{}", zb_io::DEFAULT_PEER_PORT))]
+        listen: String,
+    },
+    // Fetch the downloads a machine running `zb cache serve` has and this one doesn't.
+    Pull {
+        #[arg(long, value_name = "HOST")]
+        from: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ServicesCommands {
+    List,
+    Start {
+        #[arg(add = ArgValueCompleter::new(formula_names))]
+        formula: String,
+    },
+    Stop {
+        #[arg(add = ArgValueCompleter::new(formula_names))]
+        formula: String,
+    },
+    Restart {
+        #[arg(add = ArgValueCompleter::new(formula_names))]
+        formula: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SnapshotCommands {
+    List,
+    Create {
+        #[arg(long, default_value = "manual")]
+        label: String,
+    },
+    Restore {
+        id: Option<String>,
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    Remove {
+        id: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ImportFormat {
+    Brewfile,
+    Aqua,
+    ToolVersions,
+    Mise,
+}
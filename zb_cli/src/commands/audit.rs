@@ -0,0 +1,83 @@
+use chrono::{DateTime, Local};
+use console::style;
+
+/// Prints formulas with known vulnerabilities and returns whether there
+/// were any.
+///
+/// Like `zb outdated`, exits with 1 when something is affected and 2 on
+/// errors.
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    security: bool,
+    offline: bool,
+) -> Result<bool, zb_core::Error> {
+    if !security {
+        return Err(zb_core::Error::InvalidArgument {
+            message: "nothing to audit; pass --security".to_string(),
+        });
+    }
+
+    let report = installer.security_audit(offline).await?;
+    if report.offline {
+        let fetched = report
+            .fetched_at
+            .and_then(|ts| DateTime::from_timestamp(ts, 0))
+            .map(|dt| {
+                dt.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "never".to_string());
+        eprintln!(
+            "{} Using advisories fetched {fetched}",
+            style("Note:").yellow().bold()
+        );
+    }
+
+    for finding in &report.findings {
+        println!(
+            "{} {}",
+            style(&finding.name).bold(),
+            style(&finding.version).dim()
+        );
+        for advisory in &finding.advisories {
+            let aliases = if advisory.aliases.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", advisory.aliases.join(", "))
+            };
+            println!(
+                "    {} {}{}",
+                style("✗").red(),
+                style(&advisory.id).red(),
+                aliases
+            );
+            if let Some(summary) = &advisory.summary {
+                println!("      {}", style(summary).dim());
+            }
+            if !advisory.fixed.is_empty() {
+                println!(
+                    "      fixed in {}",
+                    style(advisory.fixed.join(", ")).green()
+                );
+            }
+        }
+    }
+    if !report.unchecked.is_empty() {
+        eprintln!(
+            "{} No advisory data for {}; run without --offline to refresh",
+            style("Warning:").yellow().bold(),
+            report.unchecked.join(", ")
+        );
+    }
+
+    if report.findings.is_empty() {
+        println!(
+            "{} No known vulnerabilities in {} formulas.",
+            style("✓").green(),
+            report.checked
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
@@ -0,0 +1,65 @@
+use console::style;
+use std::path::Path;
+
+use zb_io::{BottleCellar, Installer};
+
+use crate::utils::normalize_formula_name;
+
+pub fn execute(
+    installer: &Installer,
+    formula: &str,
+    tag: Option<String>,
+    output: &Path,
+    root_url: Option<&str>,
+) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(formula)?;
+    let tag = match tag.or_else(zb_io::host_bottle_tag) {
+        Some(tag) => tag,
+        None => {
+            return Err(zb_core::Error::InvalidArgument {
+                message: "could not detect the bottle tag for this machine; pass --tag".to_string(),
+            });
+        }
+    };
+
+    println!(
+        "{} Bottling {} for {}...",
+        style("==>").cyan().bold(),
+        style(&name).bold(),
+        tag
+    );
+
+    let bottle = installer.bottle(&name, &tag, output)?;
+
+    println!(
+        "    {} {}",
+        style("✓").green(),
+        style(bottle.path.display()).bold()
+    );
+    println!("    {} {}", style("sha256:").dim(), bottle.sha256);
+    if bottle.relocated > 0 {
+        println!(
+            "    {} {} file(s) with prefix placeholders",
+            style("relocated:").dim(),
+            bottle.relocated
+        );
+    }
+
+    if let BottleCellar::Fixed(cellar) = &bottle.cellar {
+        eprintln!(
+            "{} {} binary file(s) embed {}; the bottle will only pour into that cellar:",
+            style("Warning:").yellow().bold(),
+            bottle.unrelocatable.len(),
+            cellar
+        );
+        for path in &bottle.unrelocatable {
+            eprintln!("    • {}", path.display());
+        }
+    }
+
+    println!();
+    println!("{} Add to the formula:", style("==>").cyan().bold());
+    print!("{}", bottle.stanza(root_url));
+
+    Ok(())
+}
@@ -1,7 +1,8 @@
 use console::style;
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::path::{Path, PathBuf};
 use std::time::Instant;
+use zb_io::tap::normalize_tap_name;
 
 use super::install;
 use crate::cli::BundleCommands;
@@ -26,7 +27,10 @@ async fn install_from_file(
     manifest_path: &Path,
     no_link: bool,
 ) -> Result<(), zb_core::Error> {
-    let formulas = load_manifest(manifest_path)?;
+    let manifest = load_manifest(manifest_path)?;
+    tap_missing(installer, &manifest.taps).await?;
+
+    let formulas = manifest.formulas;
     println!(
         "{} Installing {} formulas from {}...",
         style("==>").cyan().bold(),
@@ -34,10 +38,10 @@ async fn install_from_file(
         manifest_path.display()
     );
 
+    // One install resolves the whole manifest as a single plan, so shared
+    // dependencies are fetched once and nothing is linked until it all fits.
     let start = Instant::now();
-    for formula in formulas {
-        install::execute(installer, vec![formula], no_link, false).await?;
-    }
+    install::execute(installer, formulas, no_link, false, None, None).await?;
 
     println!(
         "{} Finished installing manifest in {:.2}s",
@@ -47,6 +51,28 @@ async fn install_from_file(
     Ok(())
 }
 
+/// Tap the Brewfile's taps that aren't tapped yet, so short names from
+/// them resolve when the manifest is planned.
+async fn tap_missing(
+    installer: &mut zb_io::Installer,
+    taps: &[(String, Option<String>)],
+) -> Result<(), zb_core::Error> {
+    let tapped: HashSet<String> = installer
+        .list_taps()?
+        .into_iter()
+        .map(|info| info.name)
+        .collect();
+    for (name, remote) in taps {
+        let tap = normalize_tap_name(name)?;
+        // Homebrew's own taps are served by the formula API.
+        if tap.starts_with("homebrew/") || tapped.contains(&tap) {
+            continue;
+        }
+        super::tap::tap(installer, Some(&tap), remote.as_deref()).await?;
+    }
+    Ok(())
+}
+
 fn dump_to_file(
     installer: &mut zb_io::Installer,
     file_path: &Path,
@@ -62,12 +88,8 @@ fn dump_to_file(
     }
 
     let installed = installer.list_installed()?;
-    let mut content = String::new();
-    for keg in &installed {
-        content.push_str(&format!("brew \"{}\"\n", keg.name));
-    }
-
-    std::fs::write(file_path, content).map_err(|e| zb_core::Error::FileError {
+    let names: Vec<String> = installed.iter().map(|keg| keg.name.clone()).collect();
+    std::fs::write(file_path, brewfile(&names)).map_err(|e| zb_core::Error::FileError {
         message: format!("failed to write {}: {}", file_path.display(), e),
     })?;
 
@@ -81,13 +103,52 @@ fn dump_to_file(
     Ok(())
 }
 
-fn load_manifest(path: &Path) -> Result<Vec<String>, zb_core::Error> {
-    let contents = std::fs::read_to_string(path).map_err(|e| zb_core::Error::FileError {
-        message: format!("failed to read manifest {}: {}", path.display(), e),
-    })?;
+/// A Brewfile for `names`: the taps of tap formulas first, then formulas,
+/// then casks, the way `brew bundle dump` orders them.
+fn brewfile(names: &[String]) -> String {
+    let mut taps = BTreeSet::new();
+    let mut brews = Vec::new();
+    let mut casks = Vec::new();
+    for name in names {
+        if let Some(token) = name.strip_prefix("cask:") {
+            casks.push(token);
+            continue;
+        }
+        if let Some((tap, _)) = name.rsplit_once('/') {
+            taps.insert(tap);
+        }
+        brews.push(name.as_str());
+    }
+
+    let mut content = String::new();
+    for tap in taps {
+        content.push_str(&format!("tap \"{tap}\"\n"));
+    }
+    for brew in brews {
+        content.push_str(&format!("brew \"{brew}\"\n"));
+    }
+    for cask in casks {
+        content.push_str(&format!("cask \"{cask}\"\n"));
+    }
+    content
+}
+
+/// What a Brewfile (or a plain list of formula names) asks for.
+#[derive(Debug)]
+pub(crate) struct Brewfile {
+    /// Formulas and `cask:` tokens, deduplicated, in file order.
+    pub formulas: Vec<String>,
+    /// `tap` lines as `(name, remote)`.
+    pub taps: Vec<(String, Option<String>)>,
+    /// Lines with no zerobrew equivalent, as written.
+    pub unsupported: Vec<String>,
+}
 
+pub(crate) fn parse_brewfile(contents: &str) -> Brewfile {
     let mut formulas = Vec::new();
     let mut seen: HashSet<String> = HashSet::new();
+    let mut taps = Vec::new();
+    let mut unsupported = Vec::new();
 
     for line in contents.lines() {
         // Handle inline comments by splitting on '#' and taking the first part
@@ -96,20 +157,58 @@ fn load_manifest(path: &Path) -> Result<Vec<String>, zb_core::Error> {
             continue;
         }
 
-        if let Some(parsed) = parse_brewfile_entry(entry)
-            && seen.insert(parsed.clone())
-        {
+        if let Some(tap) = parse_tap_entry(entry) {
+            taps.push(tap);
+            continue;
+        }
+        let Some(parsed) = parse_brewfile_entry(entry) else {
+            unsupported.push(entry.to_string());
+            continue;
+        };
+        if seen.insert(parsed.clone()) {
             formulas.push(parsed);
         }
     }
 
-    if formulas.is_empty() {
+    Brewfile {
+        formulas,
+        taps,
+        unsupported,
+    }
+}
+
+fn load_manifest(path: &Path) -> Result<Brewfile, zb_core::Error> {
+    let contents = std::fs::read_to_string(path).map_err(|e| zb_core::Error::FileError {
+        message: format!("failed to read manifest {}: {}", path.display(), e),
+    })?;
+    let brewfile = parse_brewfile(&contents);
+
+    if !brewfile.unsupported.is_empty() {
+        eprintln!(
+            "{} Skipping {} entries zerobrew can't install:",
+            style("Warning:").yellow().bold(),
+            brewfile.unsupported.len()
+        );
+        for entry in &brewfile.unsupported {
+            eprintln!("    • {entry}");
+        }
+    }
+
+    if brewfile.formulas.is_empty() {
         return Err(zb_core::Error::FileError {
             message: format!("manifest {} did not contain any formulas", path.display()),
         });
     }
 
-    Ok(formulas)
+    Ok(brewfile)
+}
+
+/// `tap "owner/repo"`, optionally followed by `, "remote"`.
+fn parse_tap_entry(line: &str) -> Option<(String, Option<String>)> {
+    let name = parse_quoted_directive(line, "tap")?;
+    let (_, after) = line["tap".len()..].split_once(name)?;
+    let remote = parse_quoted_directive(after[1..].trim_start(), ",");
+    Some((name.to_string(), remote.map(str::to_string)))
 }
 
 fn parse_brewfile_entry(line: &str) -> Option<String> {
@@ -125,6 +224,12 @@ fn parse_brewfile_entry(line: &str) -> Option<String> {
         return Some(formula.to_string());
     }
 
+    // mas, vscode, whalebrew, ... have no zerobrew equivalent.
+    let directive = line.split_whitespace().next()?;
+    if parse_quoted_directive(line, directive).is_some() {
+        return None;
+    }
+
     Some(line.to_string())
 }
 
@@ -158,7 +263,7 @@ mod tests {
         )
         .unwrap();
 
-        let entries = load_manifest(file.path()).unwrap();
+        let entries = load_manifest(file.path()).unwrap().formulas;
         assert_eq!(entries, vec!["jq", "wget", "git"]);
     }
 
@@ -171,7 +276,7 @@ mod tests {
         )
         .unwrap();
 
-        let entries = load_manifest(file.path()).unwrap();
+        let entries = load_manifest(file.path()).unwrap().formulas;
         assert_eq!(entries, vec!["jq", "wget", "git"]);
     }
 
@@ -212,8 +317,57 @@ mod tests {
         )
         .unwrap();
 
-        let entries = load_manifest(file.path()).unwrap();
-        assert_eq!(entries, vec!["wget", "cask:docker-desktop"]);
+        let manifest = load_manifest(file.path()).unwrap();
+        assert_eq!(manifest.formulas, vec!["wget", "cask:docker-desktop"]);
+        assert_eq!(manifest.taps, vec![("homebrew/cask".to_string(), None)]);
+        assert!(manifest.unsupported.is_empty());
+    }
+
+    #[test]
+    fn parse_brewfile_reads_tap_remotes() {
+        let brewfile = parse_brewfile(
+            "tap \"acme/tools\", \"https://git.example.com/acme/homebrew-tools\"\ntap 'hashicorp/tap'\nbrew \"acme/tools/widget\"\n",
+        );
+        assert_eq!(
+            brewfile.taps,
+            vec![
+                (
+                    "acme/tools".to_string(),
+                    Some("https://git.example.com/acme/homebrew-tools".to_string())
+                ),
+                ("hashicorp/tap".to_string(), None),
+            ]
+        );
+        assert_eq!(brewfile.formulas, vec!["acme/tools/widget"]);
+    }
+
+    #[test]
+    fn brewfile_lists_taps_then_formulas_then_casks() {
+        let names = [
+            "cask:docker-desktop",
+            "hashicorp/tap/terraform",
+            "jq",
+            "hashicorp/tap/vault",
+        ]
+        .map(String::from);
+        assert_eq!(
+            brewfile(&names),
+            "tap \"hashicorp/tap\"\n\
+             brew \"hashicorp/tap/terraform\"\n\
+             brew \"jq\"\n\
+             brew \"hashicorp/tap/vault\"\n\
+             cask \"docker-desktop\"\n"
+        );
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "{}", brewfile(&names)).unwrap();
+        let manifest = load_manifest(file.path()).unwrap();
+        assert_eq!(manifest.taps, vec![("hashicorp/tap".to_string(), None)]);
+        let mut entries = manifest.formulas;
+        entries.sort();
+        let mut expected = names.to_vec();
+        expected.sort();
+        assert_eq!(entries, expected);
     }
 
     #[test]
@@ -237,4 +391,14 @@ mod tests {
     fn parse_brewfile_entry_skips_tap_directive() {
         assert_eq!(parse_brewfile_entry("tap \"homebrew/core\""), None);
     }
+
+    #[test]
+    fn parse_brewfile_entry_skips_other_directives() {
+        assert_eq!(parse_brewfile_entry("mas \"Xcode\", id: 497799835"), None);
+        assert_eq!(
+            parse_brewfile_entry("vscode \"rust-lang.rust-analyzer\""),
+            None
+        );
+        assert_eq!(parse_brewfile_entry("jq"), Some("jq".to_string()));
+    }
 }
@@ -0,0 +1,70 @@
+use console::style;
+
+use crate::cli::CacheCommands;
+use crate::utils::{format_bytes, warn};
+
+pub async fn execute(
+    installer: &zb_io::Installer,
+    command: CacheCommands,
+) -> Result<(), zb_core::Error> {
+    match command {
+        CacheCommands::Serve { listen } => serve(installer, &listen).await,
+        CacheCommands::Pull { from } => pull(installer, &from).await,
+    }
+}
+
+async fn serve(installer: &zb_io::Installer, listen: &str) -> Result<(), zb_core::Error> {
+    let listener = tokio::net::TcpListener::bind(listen).await.map_err(|e| {
+        zb_core::Error::NetworkFailure {
+            message: format!("failed to listen on {listen}: {e}"),
+        }
+    })?;
+    let address = listener
+        .local_addr()
+        .map(|address| address.to_string())
+        .unwrap_or_else(|_| listen.to_string());
+
+    let blobs = installer.cached_blobs()?;
+    println!(
+        "{} Serving {} cached downloads ({}) on {}",
+        style("==>").cyan().bold(),
+        style(blobs.len()).green().bold(),
+        format_bytes(blobs.iter().map(|(_, len)| len).sum()),
+        style(&address).bold()
+    );
+    println!(
+        "    Run {} on the other machine; press Ctrl-C to stop.",
+        style("zb cache pull --from <this host>").bold()
+    );
+    installer.serve_cache(listener).await
+}
+
+async fn pull(installer: &zb_io::Installer, from: &str) -> Result<(), zb_core::Error> {
+    println!(
+        "{} Pulling cached downloads from {}...",
+        style("==>").cyan().bold(),
+        style(from).bold()
+    );
+    let pull = installer.pull_cache(from).await?;
+
+    for (sha256, len) in &pull.fetched {
+        println!(
+            "    {} {} {}",
+            style("✓").green(),
+            &sha256[..12],
+            style(format!("({})", format_bytes(*len))).dim()
+        );
+    }
+    for (sha256, reason) in &pull.rejected {
+        warn(format!("rejected {}: {reason}", &sha256[..12]))?;
+    }
+
+    println!(
+        "{} Fetched {} downloads ({}), {} already cached",
+        style("==>").cyan().bold(),
+        style(pull.fetched.len()).green().bold(),
+        format_bytes(pull.bytes()),
+        pull.already_cached
+    );
+    Ok(())
+}
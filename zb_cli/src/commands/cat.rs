@@ -0,0 +1,240 @@
+use console::style;
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+use zb_io::Installer;
+
+use crate::utils::normalize_formula_name;
+
+const KEYWORDS: &[&str] = &[
+    "class", "module", "def", "end", "do", "if", "elsif", "else", "unless", "case", "when",
+    "while", "until", "return", "require", "include", "extend", "then", "and", "or", "not", "true",
+    "false", "nil", "self",
+];
+
+/// Formula DSL methods worth calling out when auditing what a formula does.
+const DSL: &[&str] = &[
+    "desc",
+    "homepage",
+    "url",
+    "mirror",
+    "sha256",
+    "license",
+    "head",
+    "version",
+    "revision",
+    "bottle",
+    "livecheck",
+    "depends_on",
+    "uses_from_macos",
+    "conflicts_with",
+    "keg_only",
+    "patch",
+    "resource",
+    "service",
+    "install",
+    "post_install",
+    "caveats",
+    "test",
+    "system",
+    "inreplace",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Plain,
+    Keyword,
+    Dsl,
+    Str,
+    Symbol,
+    Comment,
+}
+
+pub async fn execute(installer: &Installer, formula: &str) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(formula)?;
+    if name.starts_with("cask:") {
+        return Err(zb_core::Error::InvalidArgument {
+            message: format!("casks have no formula source: {name}"),
+        });
+    }
+
+    let source = installer.formula_source(&name).await?;
+
+    if !std::io::stdout().is_terminal() {
+        print!("{}", source.contents);
+        return Ok(());
+    }
+
+    eprintln!(
+        "{} {}",
+        style("==>").cyan().bold(),
+        style(&source.origin).dim()
+    );
+    let highlighted: String = source
+        .contents
+        .lines()
+        .map(|line| highlight_line(line) + "\n")
+        .collect();
+    page(&highlighted);
+    Ok(())
+}
+
+/// Send `text` through `$PAGER` (default `less -RFX`), printing it directly if
+/// no pager can be started.
+fn page(text: &str) {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "less -RFX".to_string());
+    let mut parts = pager.split_whitespace();
+    let Some(program) = parts.next() else {
+        print!("{text}");
+        return;
+    };
+
+    match Command::new(program)
+        .args(parts)
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                // The user quitting the pager early closes the pipe; that's fine.
+                let _ = stdin.write_all(text.as_bytes());
+            }
+            let _ = child.wait();
+        }
+        Err(_) => print!("{text}"),
+    }
+}
+
+fn highlight_line(line: &str) -> String {
+    tokenize(line)
+        .into_iter()
+        .map(|(token, text)| match token {
+            Token::Plain => text.to_string(),
+            Token::Keyword => style(text).magenta().bold().to_string(),
+            Token::Dsl => style(text).cyan().to_string(),
+            Token::Str => style(text).green().to_string(),
+            Token::Symbol => style(text).yellow().to_string(),
+            Token::Comment => style(text).dim().to_string(),
+        })
+        .collect()
+}
+
+/// Split one line of Ruby into coarse tokens. This is deliberately shallow:
+/// heredocs and `%w[]` literals are left plain, which is fine for reading.
+fn tokenize(line: &str) -> Vec<(Token, &str)> {
+    let bytes = line.as_bytes();
+    let mut tokens = Vec::new();
+    let mut plain_start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let (token, end) = match c {
+            b'#' => (Token::Comment, bytes.len()),
+            b'"' | b'\'' => {
+                let mut j = i + 1;
+                while j < bytes.len() && bytes[j] != c {
+                    j += if bytes[j] == b'\\' { 2 } else { 1 };
+                }
+                (Token::Str, (j + 1).min(bytes.len()))
+            }
+            b':' if bytes.get(i + 1).is_some_and(|b| is_ident_start(*b))
+                && (i == 0 || bytes[i - 1] != b':') =>
+            {
+                (Token::Symbol, ident_end(bytes, i + 1))
+            }
+            _ if is_ident_start(c) && (i == 0 || !is_ident(bytes[i - 1])) => {
+                let end = ident_end(bytes, i);
+                let word = &line[i..end];
+                // `key:` in keyword arguments and hashes reads like a symbol.
+                if bytes.get(end) == Some(&b':') && bytes.get(end + 1) != Some(&b':') {
+                    (Token::Symbol, end + 1)
+                } else if KEYWORDS.contains(&word) {
+                    (Token::Keyword, end)
+                } else if DSL.contains(&word) {
+                    (Token::Dsl, end)
+                } else {
+                    i = end;
+                    continue;
+                }
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        if plain_start < i {
+            tokens.push((Token::Plain, &line[plain_start..i]));
+        }
+        tokens.push((token, &line[i..end]));
+        i = end;
+        plain_start = end;
+    }
+    if plain_start < bytes.len() {
+        tokens.push((Token::Plain, &line[plain_start..]));
+    }
+    tokens
+}
+
+fn is_ident_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_'
+}
+
+fn is_ident(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'?' || b == b'!'
+}
+
+fn ident_end(bytes: &[u8], start: usize) -> usize {
+    let mut end = start;
+    while end < bytes.len() && is_ident(bytes[end]) {
+        end += 1;
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenizes_formula_dsl() {
+        assert_eq!(
+            tokenize(r#"  depends_on "openssl@3" => :build # needed for TLS"#),
+            vec![
+                (Token::Plain, "  "),
+                (Token::Dsl, "depends_on"),
+                (Token::Plain, " "),
+                (Token::Str, "\"openssl@3\""),
+                (Token::Plain, " => "),
+                (Token::Symbol, ":build"),
+                (Token::Plain, " "),
+                (Token::Comment, "# needed for TLS"),
+            ]
+        );
+    }
+
+    #[test]
+    fn strings_hide_comment_markers_and_keywords() {
+        assert_eq!(
+            tokenize(r#"system "make", "install #1 end", prefix: prefix"#),
+            vec![
+                (Token::Dsl, "system"),
+                (Token::Plain, " "),
+                (Token::Str, "\"make\""),
+                (Token::Plain, ", "),
+                (Token::Str, "\"install #1 end\""),
+                (Token::Plain, ", "),
+                (Token::Symbol, "prefix:"),
+                (Token::Plain, " prefix"),
+            ]
+        );
+        assert_eq!(
+            tokenize("Hardware::CPU.arm?"),
+            vec![(Token::Plain, "Hardware::CPU.arm?")]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use console::style;
+use std::time::Duration;
+
+use crate::utils::format_bytes;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    prune: Option<Duration>,
+    compact: bool,
+    unused_for: Duration,
+    dry_run: bool,
+) -> Result<(), zb_core::Error> {
+    let cache_max_age = prune.unwrap_or_else(|| {
+        Duration::from_secs(installer.config().cache.max_age_days.saturating_mul(86_400))
+    });
+    println!("{} Cleaning up...", style("==>").cyan().bold());
+    let cache_max_bytes = installer
+        .config()
+        .cache
+        .max_size_gb
+        .map(|gb| gb.saturating_mul(1 << 30));
+    let report = installer.cleanup(&zb_io::CleanupOptions {
+        dry_run,
+        superseded_kegs: true,
+        cache_max_age: Some(cache_max_age),
+        cache_max_bytes,
+        ..zb_io::CleanupOptions::default()
+    })?;
+    print_removed("superseded kegs", &report.kegs, false, dry_run);
+    print_removed(
+        "unreferenced store entries",
+        &report.store_entries,
+        true,
+        dry_run,
+    );
+    let downloads = match cache_max_bytes {
+        Some(max) => format!(
+            "downloads older than {} or over {}",
+            format_age(cache_max_age),
+            format_bytes(max)
+        ),
+        None => format!("downloads older than {}", format_age(cache_max_age)),
+    };
+    print_removed(&downloads, &report.blobs, true, dry_run);
+    let count = report.kegs.len() + report.store_entries.len() + report.blobs.len();
+    if count == 0 {
+        println!("Nothing to clean up.");
+    } else {
+        println!(
+            "{} {} {} items, {} {}",
+            style("==>").cyan().bold(),
+            if dry_run { "Would remove" } else { "Removed" },
+            style(count).green().bold(),
+            if dry_run { "freeing" } else { "freed" },
+            style(format_bytes(report.bytes())).bold()
+        );
+    }
+
+    if !compact {
+        return Ok(());
+    }
+
+    println!(
+        "{} Compacting store entries unused for {}...",
+        style("==>").cyan().bold(),
+        format_age(unused_for)
+    );
+    let compacted = installer.compact_store(unused_for, dry_run)?;
+    if compacted.is_empty() {
+        println!("No store entries to compact.");
+        return Ok(());
+    }
+
+    let mut unpacked_total = 0;
+    let mut archived_total = 0;
+    for entry in &compacted {
+        let key = entry.store_key.get(..12).unwrap_or(&entry.store_key);
+        unpacked_total += entry.unpacked_bytes;
+        match entry.archived_bytes {
+            Some(archived) => {
+                archived_total += archived;
+                println!(
+                    "    {} Compacted {} ({} → {})",
+                    style("✓").green(),
+                    key,
+                    format_bytes(entry.unpacked_bytes),
+                    format_bytes(archived)
+                );
+            }
+            None => println!(
+                "    {} Would compact {} ({})",
+                style("○").dim(),
+                key,
+                format_bytes(entry.unpacked_bytes)
+            ),
+        }
+    }
+
+    if dry_run {
+        println!(
+            "{} Would compact {} store entries ({})",
+            style("==>").cyan().bold(),
+            style(compacted.len()).green().bold(),
+            format_bytes(unpacked_total)
+        );
+    } else {
+        println!(
+            "{} Compacted {} store entries, freeing {}",
+            style("==>").cyan().bold(),
+            style(compacted.len()).green().bold(),
+            format_bytes(unpacked_total.saturating_sub(archived_total))
+        );
+    }
+    Ok(())
+}
+
+/// List removed `items` under `kind`; hash labels are `shorten`ed.
+pub(super) fn print_removed(
+    kind: &str,
+    items: &[zb_io::CleanedItem],
+    shorten: bool,
+    dry_run: bool,
+) {
+    if items.is_empty() {
+        return;
+    }
+    println!("    {}", style(kind).dim());
+    for item in items {
+        let label = item.label.get(..12).filter(|_| shorten);
+        if dry_run {
+            print!("    {} Would remove", style("○").dim());
+        } else {
+            print!("    {} Removed", style("✓").green());
+        }
+        println!(
+            " {} ({})",
+            label.unwrap_or(&item.label),
+            format_bytes(item.bytes)
+        );
+    }
+}
+
+fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        secs if secs >= 86_400 => format!("{} days", secs / 86_400),
+        secs => format!("{} hours", secs / 3_600),
+    }
+}
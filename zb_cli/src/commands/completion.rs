@@ -1,7 +1,15 @@
 use clap::{CommandFactory, Parser};
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::Shells;
 use clap_complete::generate;
+use std::ffi::OsStr;
 use std::io;
 
+use crate::utils::get_root_path;
+
+/// Formula names offered per completion request.
+const FORMULA_COMPLETIONS: usize = 50;
+
 #[derive(Parser)]
 #[command(name = "zb")]
 #[command(about = "Zerobrew - A fast Homebrew-compatible package installer")]
@@ -11,8 +19,89 @@ pub struct Cli {
     command: crate::cli::Commands,
 }
 
-pub fn execute(shell: clap_complete::shells::Shell) -> Result<(), zb_core::Error> {
+/// Print the completion script for `shell`. By default it calls back into
+/// `zb` on every completion, so formula arguments complete too; the static
+/// script only knows subcommands and flags.
+pub fn execute(
+    shell: clap_complete::shells::Shell,
+    static_script: bool,
+) -> Result<(), zb_core::Error> {
     let mut cmd = crate::cli::Cli::command();
-    generate(shell, &mut cmd, "zb", &mut io::stdout());
-    Ok(())
+    if static_script {
+        generate(shell, &mut cmd, "zb", &mut io::stdout());
+        return Ok(());
+    }
+
+    let name = shell.to_string();
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(&name)
+        .ok_or_else(|| zb_core::Error::InvalidArgument {
+            message: format!("no dynamic completion for {name}; use --static"),
+        })?;
+    completer
+        .write_registration("COMPLETE", "zb", "zb", "zb", &mut io::stdout())
+        .map_err(|e| zb_core::Error::FileError {
+            message: format!("failed to write completion script: {e}"),
+        })
+}
+
+/// Completes formula names: installed ones first, then the rest of the
+/// local index, most installed first.
+///
+/// Used by the dynamic completion scripts (`zb completions <shell>`); a
+/// database or index that hasn't been created yet simply yields nothing.
+pub fn formula_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(prefix) = current.to_str() else {
+        return Vec::new();
+    };
+    let db = get_root_path(None).join("db");
+    let installed = zb_io::Database::open_read_only(&db.join("zb.sqlite3"))
+        .and_then(|db| db.list_installed())
+        .map(|kegs| kegs.into_iter().map(|keg| keg.name).collect())
+        .unwrap_or_default();
+    let known = zb_io::FormulaIndex::open_read_only(&db.join("index.sqlite3"))
+        .and_then(|index| index.complete(prefix, FORMULA_COMPLETIONS))
+        .unwrap_or_default();
+    merge_names(prefix, installed, known, FORMULA_COMPLETIONS)
+}
+
+/// Up to `limit` candidates starting with `prefix`, `installed` ahead of
+/// `known`, each name once. Casks aren't formula arguments and are left out.
+fn merge_names(
+    prefix: &str,
+    installed: Vec<String>,
+    known: Vec<String>,
+    limit: usize,
+) -> Vec<CompletionCandidate> {
+    let installed: Vec<String> = installed
+        .into_iter()
+        .filter(|name| name.starts_with(prefix) && !name.starts_with("cask:"))
+        .collect();
+    let known = known.into_iter().filter(|name| !installed.contains(name));
+    installed
+        .iter()
+        .map(|name| CompletionCandidate::new(name).help(Some("installed".into())))
+        .chain(known.map(CompletionCandidate::new))
+        .take(limit)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn installed_names_come_first_without_duplicates() {
+        let names: Vec<String> = merge_names(
+            "jq",
+            vec!["cask:jqapp".into(), "jq".into(), "ripgrep".into()],
+            vec!["jq".into(), "jql".into(), "jqp".into()],
+            3,
+        )
+        .iter()
+        .map(|candidate| candidate.get_value().to_string_lossy().into_owned())
+        .collect();
+        assert_eq!(names, ["jq", "jql", "jqp"]);
+    }
 }
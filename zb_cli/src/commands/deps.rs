@@ -0,0 +1,368 @@
+use console::style;
+use std::collections::BTreeMap;
+use zb_core::{Formula, resolve_closure};
+
+use crate::utils::{TreeNode, normalize_formula_name, tree_lines};
+
+/// One formula in a rendered dependency tree.
+#[derive(Debug, PartialEq)]
+struct Node {
+    name: String,
+    version: String,
+    installed: bool,
+    build: bool,
+    dependencies: Vec<Node>,
+}
+
+pub async fn execute(
+    installer: &zb_io::Installer,
+    formulas: Vec<String>,
+    tree: bool,
+    installed_only: bool,
+    include_build: bool,
+    json: bool,
+    closure: bool,
+) -> Result<(), zb_core::Error> {
+    let names = formulas
+        .iter()
+        .map(|formula| normalize_formula_name(formula))
+        .collect::<Result<Vec<_>, _>>()?;
+    let formulas = installer.dependency_formulas(&names, include_build).await?;
+    let is_installed = |name: &str| installer.is_installed(name);
+
+    if closure {
+        let bottle_tag = |formula: &Formula| installer.select_bottle(formula).ok().map(|b| b.tag);
+        let graph = closure_graph(&names, &formulas, include_build, &bottle_tag)?;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&graph).expect("JSON values always serialize")
+        );
+        return Ok(());
+    }
+
+    if tree || json {
+        let roots: Vec<Node> = names
+            .iter()
+            .filter_map(|name| {
+                build_node(
+                    name,
+                    false,
+                    &formulas,
+                    include_build,
+                    installed_only,
+                    &is_installed,
+                    &mut Vec::new(),
+                )
+            })
+            .collect();
+        if json {
+            let roots: Vec<_> = roots.iter().map(json_node).collect();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&roots).expect("JSON values always serialize")
+            );
+        } else {
+            let roots: Vec<TreeNode> = roots.iter().map(tree_node).collect();
+            for line in tree_lines(&roots) {
+                println!("{line}");
+            }
+        }
+        return Ok(());
+    }
+
+    // A flat list, dependencies before the formulas that need them.
+    let mut roots = names.clone();
+    if include_build {
+        for name in &names {
+            if let Some(formula) = formulas.get(name) {
+                roots.extend(
+                    formula
+                        .all_build_dependencies()
+                        .into_iter()
+                        .filter(|dep| formulas.contains_key(dep)),
+                );
+            }
+        }
+    }
+    for name in resolve_closure(&roots, &formulas)? {
+        if names.contains(&name) || (installed_only && !is_installed(&name)) {
+            continue;
+        }
+        println!("{name}");
+    }
+    Ok(())
+}
+
+/// Every formula the roots resolve to, dependencies first, with the edges
+/// between them. Build edges only leave the roots, as in the tree.
+fn closure_graph(
+    names: &[String],
+    formulas: &BTreeMap<String, Formula>,
+    include_build: bool,
+    bottle_tag: &dyn Fn(&Formula) -> Option<String>,
+) -> Result<serde_json::Value, zb_core::Error> {
+    let mut roots = names.to_vec();
+    let mut edges = Vec::new();
+    for name in names {
+        let Some(formula) = formulas.get(name) else {
+            continue;
+        };
+        if include_build {
+            for dep in formula.all_build_dependencies() {
+                if formulas.contains_key(&dep) {
+                    edges.push(serde_json::json!({ "from": name, "to": dep, "kind": "build" }));
+                    roots.push(dep);
+                }
+            }
+        }
+    }
+
+    let ordered = resolve_closure(&roots, formulas)?;
+    let mut nodes = Vec::with_capacity(ordered.len());
+    for name in &ordered {
+        let formula = &formulas[name];
+        for dep in formula
+            .dependencies
+            .iter()
+            .filter(|dep| formulas.contains_key(*dep))
+        {
+            edges.push(serde_json::json!({ "from": name, "to": dep, "kind": "runtime" }));
+        }
+        nodes.push(serde_json::json!({
+            "name": name,
+            "version": formula.effective_version(),
+            "bottle_tag": bottle_tag(formula),
+            "root": names.contains(name),
+        }));
+    }
+
+    Ok(serde_json::json!({
+        "platform": zb_io::host_bottle_tag(),
+        "roots": names,
+        "formulas": nodes,
+        "edges": edges,
+    }))
+}
+
+/// The tree under `name`. Build dependencies are only followed for the
+/// roots, since that's all a source build of them needs; cycles are cut.
+fn build_node(
+    name: &str,
+    build: bool,
+    formulas: &BTreeMap<String, Formula>,
+    include_build: bool,
+    installed_only: bool,
+    is_installed: &dyn Fn(&str) -> bool,
+    ancestors: &mut Vec<String>,
+) -> Option<Node> {
+    let formula = formulas.get(name)?;
+    let installed = is_installed(name);
+    if installed_only && !ancestors.is_empty() && !installed {
+        return None;
+    }
+
+    let mut children: Vec<(String, bool)> = formula
+        .dependencies
+        .iter()
+        .map(|dep| (dep.clone(), false))
+        .collect();
+    if include_build && ancestors.is_empty() {
+        children.extend(
+            formula
+                .all_build_dependencies()
+                .into_iter()
+                .map(|dep| (dep, true)),
+        );
+    }
+    children.sort();
+    children.dedup_by(|a, b| a.0 == b.0);
+
+    ancestors.push(name.to_string());
+    let mut dependencies = Vec::with_capacity(children.len());
+    for (dep, build) in children {
+        if ancestors.contains(&dep) {
+            continue;
+        }
+        dependencies.extend(build_node(
+            &dep,
+            build,
+            formulas,
+            include_build,
+            installed_only,
+            is_installed,
+            ancestors,
+        ));
+    }
+    ancestors.pop();
+
+    Some(Node {
+        name: name.to_string(),
+        version: formula.effective_version(),
+        installed,
+        build,
+        dependencies,
+    })
+}
+
+fn tree_node(node: &Node) -> TreeNode {
+    TreeNode {
+        label: node_label(node),
+        children: node.dependencies.iter().map(tree_node).collect(),
+    }
+}
+
+fn node_label(node: &Node) -> String {
+    let mut label = format!(
+        "{} {}",
+        style(&node.name).bold(),
+        style(&node.version).dim()
+    );
+    if node.build {
+        label.push_str(&format!(" {}", style("(build)").dim()));
+    }
+    if node.installed {
+        label.push_str(&format!(" {}", style("✓").green()));
+    }
+    label
+}
+
+fn json_node(node: &Node) -> serde_json::Value {
+    serde_json::json!({
+        "name": node.name,
+        "version": node.version,
+        "installed": node.installed,
+        "build": node.build,
+        "dependencies": node.dependencies.iter().map(json_node).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formula(name: &str, deps: &[&str], build: &[&str]) -> (String, Formula) {
+        let formula = serde_json::from_value(serde_json::json!({
+            "name": name,
+            "versions": { "stable": "1.0" },
+            "dependencies": deps,
+            "build_dependencies": build,
+            "bottle": { "stable": { "files": {} } },
+        }))
+        .unwrap();
+        (name.to_string(), formula)
+    }
+
+    fn names(nodes: &[Node]) -> Vec<&str> {
+        nodes.iter().map(|node| node.name.as_str()).collect()
+    }
+
+    #[test]
+    fn tree_follows_build_dependencies_of_roots_and_prunes_uninstalled() {
+        let formulas: BTreeMap<_, _> = [
+            formula("wget", &["openssl@3", "libidn2"], &["pkgconf"]),
+            formula("openssl@3", &["ca-certificates"], &["perl"]),
+            formula("libidn2", &[], &[]),
+            formula("ca-certificates", &[], &[]),
+            formula("pkgconf", &[], &[]),
+        ]
+        .into();
+        let installed = |name: &str| name != "libidn2";
+
+        let root = build_node(
+            "wget",
+            false,
+            &formulas,
+            true,
+            false,
+            &installed,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            names(&root.dependencies),
+            ["libidn2", "openssl@3", "pkgconf"]
+        );
+        assert!(root.dependencies[2].build);
+        // perl isn't fetched for a dependency's own build, so it's skipped.
+        assert_eq!(
+            names(&root.dependencies[1].dependencies),
+            ["ca-certificates"]
+        );
+
+        let root = build_node(
+            "wget",
+            false,
+            &formulas,
+            false,
+            true,
+            &installed,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(names(&root.dependencies), ["openssl@3"]);
+    }
+
+    #[test]
+    fn closure_lists_every_formula_with_its_edges() {
+        let formulas: BTreeMap<_, _> = [
+            formula("wget", &["openssl@3", "libidn2"], &["pkgconf"]),
+            formula("openssl@3", &["ca-certificates"], &[]),
+            formula("libidn2", &[], &[]),
+            formula("ca-certificates", &[], &[]),
+            formula("pkgconf", &[], &[]),
+        ]
+        .into();
+        let bottle_tag =
+            |formula: &Formula| (formula.name != "pkgconf").then(|| "arm64_sequoia".to_string());
+
+        let graph = closure_graph(&["wget".to_string()], &formulas, true, &bottle_tag).unwrap();
+        let nodes: Vec<&str> = graph["formulas"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|node| node["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(nodes.len(), 5);
+        assert_eq!(nodes.last(), Some(&"wget"));
+        let position = |name| nodes.iter().position(|n| *n == name).unwrap();
+        assert!(position("ca-certificates") < position("openssl@3"));
+
+        let pkgconf = &graph["formulas"][position("pkgconf")];
+        assert_eq!(pkgconf["bottle_tag"], serde_json::Value::Null);
+        assert_eq!(pkgconf["root"], false);
+        assert!(
+            graph["edges"]
+                .as_array()
+                .unwrap()
+                .contains(&serde_json::json!({
+                    "from": "wget", "to": "pkgconf", "kind": "build"
+                }))
+        );
+        assert!(
+            graph["edges"]
+                .as_array()
+                .unwrap()
+                .contains(&serde_json::json!({
+                    "from": "openssl@3", "to": "ca-certificates", "kind": "runtime"
+                }))
+        );
+    }
+
+    #[test]
+    fn tree_cuts_dependency_cycles() {
+        let formulas: BTreeMap<_, _> =
+            [formula("a", &["b"], &[]), formula("b", &["a"], &[])].into();
+        let root = build_node(
+            "a",
+            false,
+            &formulas,
+            false,
+            false,
+            &|_| false,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(names(&root.dependencies), ["b"]);
+        assert!(root.dependencies[0].dependencies.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+use console::style;
+use std::path::Path;
+
+use zb_io::{Diagnostic, Installer, LinkAudit, OrphanAction, StoreAudit};
+
+use crate::init::{InitError, managed_block_damage, shell_profile};
+
+pub fn execute(
+    installer: &mut Installer,
+    root: &Path,
+    repair: bool,
+    adopt: bool,
+    delete_orphans: bool,
+    shell: bool,
+) -> Result<(), zb_core::Error> {
+    if shell {
+        return check_shell_profile();
+    }
+
+    let orphans = if adopt {
+        OrphanAction::Adopt
+    } else if delete_orphans {
+        OrphanAction::Delete
+    } else {
+        OrphanAction::Report
+    };
+
+    println!("{} Auditing store...", style("==>").cyan().bold());
+    let audit = installer.audit_store(repair, orphans)?;
+    print_store_audit(&audit);
+
+    println!("{} Auditing links...", style("==>").cyan().bold());
+    print_link_audit(&installer.audit_links()?);
+
+    println!("{} Checking prefix...", style("==>").cyan().bold());
+    print_diagnostics(&installer.diagnose(root)?);
+
+    Ok(())
+}
+
+fn check_shell_profile() -> Result<(), zb_core::Error> {
+    let profile = shell_profile().map_err(|e| match e {
+        InitError::Message(message) => zb_core::Error::ExecutionError { message },
+    })?;
+    println!(
+        "{} Checking {}...",
+        style("==>").cyan().bold(),
+        profile.display()
+    );
+    let contents = std::fs::read_to_string(&profile).unwrap_or_default();
+    if !contents.contains("zerobrew") {
+        println!(
+            "    {} No zerobrew block; run `zb init` to add one",
+            style("•").dim()
+        );
+        return Ok(());
+    }
+
+    let damage = managed_block_damage(&contents);
+    if damage.is_empty() {
+        println!("    {} zerobrew block intact", style("✓").green());
+        return Ok(());
+    }
+    for problem in &damage {
+        eprintln!("{} {problem}", style("Warning:").yellow().bold());
+    }
+    eprintln!(
+        "    {}",
+        style("run `zb init` to repair it; the profile is backed up first").dim()
+    );
+    Ok(())
+}
+
+fn print_diagnostics(diagnostics: &[Diagnostic]) {
+    if diagnostics.is_empty() {
+        println!("    {} No problems found", style("✓").green());
+        return;
+    }
+    for diagnostic in diagnostics {
+        eprintln!(
+            "{} {}",
+            style("Warning:").yellow().bold(),
+            diagnostic.problem
+        );
+        for path in &diagnostic.paths {
+            eprintln!("    • {}", path.display());
+        }
+        eprintln!("    {}", style(&diagnostic.remedy).dim());
+    }
+}
+
+fn print_link_audit(audit: &LinkAudit) {
+    if audit.is_clean() {
+        print!(
+            "    {} {} links consistent",
+            style("✓").green(),
+            audit.checked
+        );
+        if audit.excluded > 0 {
+            print!(
+                " {}",
+                style(format!(
+                    "({} paths excluded by link_exclude)",
+                    audit.excluded
+                ))
+                .dim()
+            );
+        }
+        println!();
+        return;
+    }
+
+    if !audit.broken.is_empty() {
+        eprintln!(
+            "{} {} recorded links are missing or point elsewhere; relink their formulas with `zb link`:",
+            style("Warning:").yellow().bold(),
+            audit.broken.len()
+        );
+        for link in &audit.broken {
+            eprintln!("    • {}", link.display());
+        }
+    }
+    if !audit.excluded_but_linked.is_empty() {
+        eprintln!(
+            "{} {} paths excluded by link_exclude are still linked; run `zb link` on their formulas to drop them:",
+            style("Warning:").yellow().bold(),
+            audit.excluded_but_linked.len()
+        );
+        for (name, path) in &audit.excluded_but_linked {
+            eprintln!("    • {} ({name})", path.display());
+        }
+    }
+}
+
+fn print_store_audit(audit: &StoreAudit) {
+    if audit.is_clean() {
+        println!(
+            "    {} {} store entries consistent",
+            style("✓").green(),
+            audit.checked
+        );
+        return;
+    }
+
+    for fix in &audit.refcount_fixes {
+        let recorded = fix
+            .recorded
+            .map(|count| count.to_string())
+            .unwrap_or_else(|| "missing".to_string());
+        if audit.repaired {
+            println!(
+                "    {} Fixed refcount for {}: {} → {}",
+                style("✓").green(),
+                short_key(&fix.store_key),
+                style(recorded).red(),
+                style(fix.actual).green()
+            );
+        } else {
+            println!(
+                "    {} Refcount for {}: recorded {}, actual {}",
+                style("•").dim(),
+                short_key(&fix.store_key),
+                style(recorded).yellow(),
+                fix.actual
+            );
+        }
+    }
+    if !audit.refcount_fixes.is_empty() && !audit.repaired {
+        eprintln!(
+            "{} {} store refcounts are out of date; rerun with --store to correct them",
+            style("Note:").yellow().bold(),
+            audit.refcount_fixes.len()
+        );
+    }
+
+    for key in &audit.orphans {
+        let outcome = match audit.orphan_action {
+            OrphanAction::Report => style("not in database").yellow().to_string(),
+            OrphanAction::Adopt => style("adopted").green().to_string(),
+            OrphanAction::Delete => style("deleted").green().to_string(),
+        };
+        println!(
+            "    {} Orphan {}: {}",
+            style("•").dim(),
+            short_key(key),
+            outcome
+        );
+    }
+    if !audit.orphans.is_empty() && audit.orphan_action == OrphanAction::Report {
+        eprintln!(
+            "{} {} orphaned store entries; rerun with --store --adopt to let `zb gc` reclaim them or --store --delete-orphans to remove them now",
+            style("Note:").yellow().bold(),
+            audit.orphans.len()
+        );
+    }
+
+    if !audit.missing_entries.is_empty() {
+        eprintln!(
+            "{} {} store entries referenced by installed formulas are missing; reinstall them to restore:",
+            style("Warning:").yellow().bold(),
+            audit.missing_entries.len()
+        );
+        for key in &audit.missing_entries {
+            eprintln!("    • {}", short_key(key));
+        }
+    }
+}
+
+fn short_key(key: &str) -> &str {
+    key.get(..12).unwrap_or(key)
+}
@@ -0,0 +1,162 @@
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use zb_core::formula_token;
+use zb_io::Installer;
+
+use crate::utils::normalize_formula_name;
+
+/// Run `command` with the prefix (and any `--with` kegs) on the search paths.
+///
+/// The environment only applies to the spawned process; nothing in the user's
+/// shell or profile is touched.
+pub fn execute(
+    installer: &Installer,
+    prefix: &Path,
+    with: Vec<String>,
+    command: Vec<String>,
+) -> Result<(), zb_core::Error> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| zb_core::Error::InvalidArgument {
+            message: "no command given".to_string(),
+        })?;
+
+    let mut kegs = Vec::with_capacity(with.len());
+    for formula in &with {
+        let name = normalize_formula_name(formula)?;
+        let installed = installer
+            .get_installed(&name)
+            .ok_or_else(|| zb_core::Error::NotInstalled { name: name.clone() })?;
+        kegs.push(installer.keg_path(formula_token(&installed.name), &installed.version));
+    }
+
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+    for (key, value) in exec_env(prefix, &kegs, |key| std::env::var(key).ok()) {
+        cmd.env(key, value);
+    }
+
+    let err = cmd.exec();
+
+    Err(zb_core::Error::ExecutionError {
+        message: format!("failed to execute '{}': {}", program, err),
+    })
+}
+
+/// Environment overrides for `zb exec`.
+///
+/// `kegs` come first so a requested keg-only formula shadows anything linked into
+/// the prefix; existing values from `current` are kept at the end.
+pub(crate) fn exec_env(
+    prefix: &Path,
+    kegs: &[PathBuf],
+    current: impl Fn(&str) -> Option<String>,
+) -> Vec<(String, String)> {
+    let roots: Vec<&Path> = kegs
+        .iter()
+        .map(PathBuf::as_path)
+        .chain(std::iter::once(prefix))
+        .collect();
+    let dirs = |subdirs: &[&str]| -> Vec<PathBuf> {
+        roots
+            .iter()
+            .flat_map(|root| subdirs.iter().map(move |sub| root.join(sub)))
+            .collect()
+    };
+
+    let library_var = if cfg!(target_os = "macos") {
+        "DYLD_FALLBACK_LIBRARY_PATH"
+    } else {
+        "LD_LIBRARY_PATH"
+    };
+
+    let mut env = vec![
+        ("PATH", dirs(&["bin", "sbin"])),
+        (
+            "PKG_CONFIG_PATH",
+            dirs(&["lib/pkgconfig", "share/pkgconfig"]),
+        ),
+        ("CPATH", dirs(&["include"])),
+        ("LIBRARY_PATH", dirs(&["lib"])),
+        (library_var, dirs(&["lib"])),
+    ]
+    .into_iter()
+    .map(|(key, paths)| (key.to_string(), join_paths(&paths, current(key))))
+    .collect::<Vec<_>>();
+
+    env.push(("ZEROBREW_PREFIX".to_string(), prefix.display().to_string()));
+
+    if let Some(ca_bundle) = zb_io::find_ca_bundle_from_prefix(prefix) {
+        let ca_bundle = ca_bundle.display().to_string();
+        env.push(("CURL_CA_BUNDLE".to_string(), ca_bundle.clone()));
+        env.push(("SSL_CERT_FILE".to_string(), ca_bundle));
+    }
+    if let Some(ca_dir) = zb_io::find_ca_dir(prefix) {
+        env.push(("SSL_CERT_DIR".to_string(), ca_dir.display().to_string()));
+    }
+
+    env
+}
+
+fn join_paths(paths: &[PathBuf], existing: Option<String>) -> String {
+    paths
+        .iter()
+        .map(|p| p.display().to_string())
+        .chain(existing.filter(|e| !e.is_empty()))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup<'a>(env: &'a [(String, String)], key: &str) -> Option<&'a str> {
+        env.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn exec_env_prepends_prefix_and_keeps_existing() {
+        let env = exec_env(Path::new("/opt/zb"), &[], |key| {
+            (key == "PATH").then(|| "/usr/bin:/bin".to_string())
+        });
+
+        assert_eq!(
+            lookup(&env, "PATH"),
+            Some("/opt/zb/bin:/opt/zb/sbin:/usr/bin:/bin")
+        );
+        assert_eq!(
+            lookup(&env, "PKG_CONFIG_PATH"),
+            Some("/opt/zb/lib/pkgconfig:/opt/zb/share/pkgconfig")
+        );
+        assert_eq!(lookup(&env, "CPATH"), Some("/opt/zb/include"));
+        assert_eq!(lookup(&env, "ZEROBREW_PREFIX"), Some("/opt/zb"));
+    }
+
+    #[test]
+    fn exec_env_puts_requested_kegs_first() {
+        let keg = PathBuf::from("/opt/zb/Cellar/openssl@3/3.3.0");
+        let env = exec_env(Path::new("/opt/zb"), &[keg], |_| None);
+
+        assert_eq!(
+            lookup(&env, "LIBRARY_PATH"),
+            Some("/opt/zb/Cellar/openssl@3/3.3.0/lib:/opt/zb/lib")
+        );
+        let library_var = if cfg!(target_os = "macos") {
+            "DYLD_FALLBACK_LIBRARY_PATH"
+        } else {
+            "LD_LIBRARY_PATH"
+        };
+        assert_eq!(
+            lookup(&env, library_var),
+            Some("/opt/zb/Cellar/openssl@3/3.3.0/lib:/opt/zb/lib")
+        );
+        assert!(
+            lookup(&env, "PATH")
+                .unwrap()
+                .starts_with("/opt/zb/Cellar/openssl@3/3.3.0/bin:")
+        );
+    }
+}
@@ -1,22 +1,75 @@
 use console::style;
+use std::time::Duration;
+
+use super::cleanup::print_removed;
+use crate::utils::format_bytes;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    dry_run: bool,
+    min_age: Option<Duration>,
+    aggressive: bool,
+    prune_cache_older_than: Option<Duration>,
+    keep_latest_kegs: Option<usize>,
+) -> Result<(), zb_core::Error> {
+    // `--aggressive` keeps only installed kegs and prunes the download cache
+    // to its configured age, unless a threshold is given explicitly.
+    let (prune_cache_older_than, keep_latest_kegs) = if aggressive {
+        let max_age_days = installer.config().cache.max_age_days;
+        (
+            prune_cache_older_than.or(Some(Duration::from_secs(
+                max_age_days.saturating_mul(86_400),
+            ))),
+            keep_latest_kegs.or(Some(1)),
+        )
+    } else {
+        (prune_cache_older_than, keep_latest_kegs)
+    };
 
-pub fn execute(installer: &mut zb_io::Installer) -> Result<(), zb_core::Error> {
     println!(
         "{} Running garbage collection...",
         style("==>").cyan().bold()
     );
-    let removed = installer.gc()?;
+    let report = installer.cleanup(&zb_io::CleanupOptions {
+        dry_run,
+        keep_latest_kegs,
+        min_age,
+        cache_max_age: prune_cache_older_than,
+        ..zb_io::CleanupOptions::default()
+    })?;
 
-    if removed.is_empty() {
-        println!("No unreferenced store entries to remove.");
-    } else {
-        for key in &removed {
-            println!("    {} Removed {}", style("✓").green(), &key[..12]);
-        }
+    let mut categories = Vec::new();
+    if keep_latest_kegs.is_some() {
+        categories.push(("kegs", &report.kegs, false));
+    }
+    categories.push(("store entries", &report.store_entries, true));
+    if prune_cache_older_than.is_some() {
+        categories.push(("downloads", &report.blobs, true));
+    }
+    for (kind, items, shorten) in &categories {
+        print_removed(kind, items, *shorten, dry_run);
+    }
+
+    let count = report.kegs.len() + report.store_entries.len() + report.blobs.len();
+    if count == 0 {
+        println!("Nothing to remove.");
+        return Ok(());
+    }
+    println!(
+        "{} {} {} items, {} {}",
+        style("==>").cyan().bold(),
+        if dry_run { "Would remove" } else { "Removed" },
+        style(count).green().bold(),
+        if dry_run { "freeing" } else { "freed" },
+        style(format_bytes(report.bytes())).bold()
+    );
+    for (kind, items, _) in &categories {
+        let bytes = items.iter().map(|item| item.bytes).sum();
         println!(
-            "{} Removed {} store entries",
-            style("==>").cyan().bold(),
-            style(removed.len()).green().bold()
+            "    {:<14} {:>5}  {:>10}",
+            kind,
+            items.len(),
+            format_bytes(bytes)
         );
     }
 
@@ -0,0 +1,514 @@
+use console::style;
+use std::collections::HashSet;
+use std::path::Path;
+
+use super::{bundle, install};
+use crate::cli::ImportFormat;
+
+/// asdf / mise plugin names that map onto a homebrew-core formula.
+const KNOWN_TOOLS: &[(&str, &str)] = &[
+    ("awscli", "awscli"),
+    ("bat", "bat"),
+    ("cmake", "cmake"),
+    ("deno", "deno"),
+    ("direnv", "direnv"),
+    ("elixir", "elixir"),
+    ("erlang", "erlang"),
+    ("fd", "fd"),
+    ("fzf", "fzf"),
+    ("gh", "gh"),
+    ("github-cli", "gh"),
+    ("go", "go"),
+    ("golang", "go"),
+    ("golangci-lint", "golangci-lint"),
+    ("gradle", "gradle"),
+    ("hadolint", "hadolint"),
+    ("helm", "helm"),
+    ("java", "openjdk"),
+    ("jq", "jq"),
+    ("just", "just"),
+    ("k9s", "k9s"),
+    ("kotlin", "kotlin"),
+    ("kubectl", "kubernetes-cli"),
+    ("kustomize", "kustomize"),
+    ("lazygit", "lazygit"),
+    ("lua", "lua"),
+    ("maven", "maven"),
+    ("neovim", "neovim"),
+    ("node", "node"),
+    ("nodejs", "node"),
+    ("php", "php"),
+    ("pnpm", "pnpm"),
+    ("poetry", "poetry"),
+    ("protoc", "protobuf"),
+    ("python", "python"),
+    ("ripgrep", "ripgrep"),
+    ("ruby", "ruby"),
+    ("rust", "rust"),
+    ("scala", "scala"),
+    ("shellcheck", "shellcheck"),
+    ("starship", "starship"),
+    ("tflint", "tflint"),
+    ("uv", "uv"),
+    ("yarn", "yarn"),
+    ("yq", "yq"),
+    ("zig", "zig"),
+    ("zoxide", "zoxide"),
+];
+
+/// aqua registry package names (`owner/repo`) that map onto a homebrew-core formula.
+const KNOWN_AQUA_PACKAGES: &[(&str, &str)] = &[
+    ("ajeetdsouza/zoxide", "zoxide"),
+    ("astral-sh/uv", "uv"),
+    ("aws/aws-cli", "awscli"),
+    ("BurntSushi/ripgrep", "ripgrep"),
+    ("casey/just", "just"),
+    ("cli/cli", "gh"),
+    ("dandavison/delta", "git-delta"),
+    ("denoland/deno", "deno"),
+    ("derailed/k9s", "k9s"),
+    ("direnv/direnv", "direnv"),
+    ("golang/go", "go"),
+    ("golangci/golangci-lint", "golangci-lint"),
+    ("hadolint/hadolint", "hadolint"),
+    ("helm/helm", "helm"),
+    ("jesseduffield/lazygit", "lazygit"),
+    ("jqlang/jq", "jq"),
+    ("junegunn/fzf", "fzf"),
+    ("koalaman/shellcheck", "shellcheck"),
+    ("kubernetes/kubectl", "kubernetes-cli"),
+    ("kubernetes-sigs/kustomize", "kustomize"),
+    ("mikefarah/yq", "yq"),
+    ("neovim/neovim", "neovim"),
+    ("protocolbuffers/protobuf", "protobuf"),
+    ("sharkdp/bat", "bat"),
+    ("sharkdp/fd", "fd"),
+    ("starship/starship", "starship"),
+    ("stedolan/jq", "jq"),
+    ("terraform-linters/tflint", "tflint"),
+];
+
+/// One entry read from a foreign manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ImportEntry {
+    /// The tool as it was written in the source file.
+    tool: String,
+    /// Version requested by the source file, if any.
+    version: Option<String>,
+    /// Formula (or `cask:` token) it translates to, if we know one.
+    formula: Option<String>,
+}
+
+impl ImportEntry {
+    fn new(tool: &str, version: Option<&str>, formula: Option<String>) -> Self {
+        Self {
+            tool: tool.to_string(),
+            version: version
+                .map(str::trim)
+                .filter(|v| !v.is_empty() && *v != "latest")
+                .map(str::to_string),
+            formula,
+        }
+    }
+}
+
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    file: &Path,
+    format: Option<ImportFormat>,
+    dry_run: bool,
+    no_link: bool,
+) -> Result<(), zb_core::Error> {
+    let format = match format.or_else(|| detect_format(file)) {
+        Some(format) => format,
+        None => {
+            return Err(zb_core::Error::InvalidArgument {
+                message: format!(
+                    "could not detect the format of {} (use --format)",
+                    file.display()
+                ),
+            });
+        }
+    };
+
+    let contents = std::fs::read_to_string(file).map_err(|e| zb_core::Error::FileError {
+        message: format!("failed to read {}: {}", file.display(), e),
+    })?;
+    let entries = parse(format, &contents)?;
+
+    println!(
+        "{} Importing {} entries from {}...",
+        style("==>").cyan().bold(),
+        style(entries.len()).green().bold(),
+        file.display()
+    );
+
+    let mut formulas = Vec::new();
+    let mut seen = HashSet::new();
+    let mut unmapped = Vec::new();
+    for entry in &entries {
+        let Some(formula) = &entry.formula else {
+            unmapped.push(entry);
+            continue;
+        };
+
+        let version = entry
+            .version
+            .as_deref()
+            .map(|v| format!(" {}", style(format!("(wants {v})")).dim()))
+            .unwrap_or_default();
+        if entry.tool == *formula {
+            println!("    {} {}{}", style("✓").green(), formula, version);
+        } else {
+            println!(
+                "    {} {} → {}{}",
+                style("✓").green(),
+                entry.tool,
+                style(formula).bold(),
+                version
+            );
+        }
+
+        if seen.insert(formula.clone()) {
+            formulas.push(formula.clone());
+        }
+    }
+
+    for entry in &unmapped {
+        println!(
+            "    {} {} {}",
+            style("✗").red(),
+            entry.tool,
+            style("(no known formula)").dim()
+        );
+    }
+
+    if entries
+        .iter()
+        .any(|e| e.formula.is_some() && e.version.is_some())
+    {
+        eprintln!(
+            "{} Version pins are not honored; the current stable version of each formula will be installed.",
+            style("Note:").yellow().bold()
+        );
+    }
+
+    if !unmapped.is_empty() {
+        eprintln!(
+            "{} Skipping {} unmapped {}.",
+            style("Warning:").yellow().bold(),
+            unmapped.len(),
+            if unmapped.len() == 1 {
+                "entry"
+            } else {
+                "entries"
+            }
+        );
+    }
+
+    if formulas.is_empty() {
+        return Err(zb_core::Error::FileError {
+            message: format!(
+                "{} did not contain any tools zerobrew can install",
+                file.display()
+            ),
+        });
+    }
+
+    if dry_run {
+        println!(
+            "{} Dry run: would install {}",
+            style("==>").cyan().bold(),
+            style(formulas.join(" ")).bold()
+        );
+        let (casks, formulas): (Vec<_>, Vec<_>) = formulas
+            .into_iter()
+            .partition(|name| name.starts_with("cask:"));
+        if !formulas.is_empty() {
+            let plan = installer.plan(&formulas).await?;
+            println!(
+                "    {} {} ({} packages)",
+                style("plan").dim(),
+                installer.plan_hash(&plan),
+                plan.items.len()
+            );
+        }
+        if !casks.is_empty() {
+            println!("    {} casks are not part of the plan hash", casks.len());
+        }
+        return Ok(());
+    }
+
+    install::execute(installer, formulas, no_link, false, None, None).await
+}
+
+fn detect_format(path: &Path) -> Option<ImportFormat> {
+    let name = path.file_name()?.to_str()?;
+    match name {
+        "Brewfile" => Some(ImportFormat::Brewfile),
+        ".tool-versions" => Some(ImportFormat::ToolVersions),
+        "aqua.yaml" | "aqua.yml" | ".aqua.yaml" | ".aqua.yml" => Some(ImportFormat::Aqua),
+        "mise.toml" | ".mise.toml" | "mise.local.toml" | ".rtx.toml" => Some(ImportFormat::Mise),
+        _ if name.ends_with(".Brewfile") => Some(ImportFormat::Brewfile),
+        _ => None,
+    }
+}
+
+fn parse(format: ImportFormat, contents: &str) -> Result<Vec<ImportEntry>, zb_core::Error> {
+    match format {
+        ImportFormat::Brewfile => Ok(parse_brewfile(contents)),
+        ImportFormat::Aqua => Ok(parse_aqua(contents)),
+        ImportFormat::ToolVersions => Ok(parse_tool_versions(contents)),
+        ImportFormat::Mise => parse_mise(contents),
+    }
+}
+
+fn lookup(table: &[(&str, &str)], name: &str) -> Option<String> {
+    table
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, formula)| formula.to_string())
+}
+
+// Brewfiles are read the way `zb bundle install` reads them. Taps are
+// reported rather than tapped; `zb bundle install` taps them.
+fn parse_brewfile(contents: &str) -> Vec<ImportEntry> {
+    let brewfile = bundle::parse_brewfile(contents);
+    let formulas = brewfile
+        .formulas
+        .into_iter()
+        .map(|formula| ImportEntry::new(&formula, None, Some(formula.clone())));
+    let taps = brewfile
+        .taps
+        .into_iter()
+        .map(|(name, _)| ImportEntry::new(&format!("tap {name}"), None, None));
+    let unsupported = brewfile
+        .unsupported
+        .into_iter()
+        .map(|line| ImportEntry::new(&line, None, None));
+    formulas.chain(taps).chain(unsupported).collect()
+}
+
+fn parse_tool_versions(contents: &str) -> Vec<ImportEntry> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut fields = line.split_whitespace();
+            let tool = fields.next()?;
+            Some(ImportEntry::new(
+                tool,
+                fields.next(),
+                lookup(KNOWN_TOOLS, tool),
+            ))
+        })
+        .collect()
+}
+
+fn parse_aqua(contents: &str) -> Vec<ImportEntry> {
+    let mut entries = Vec::new();
+    let mut in_packages = false;
+    let mut current: Option<(String, Option<String>)> = None;
+
+    let mut flush = |current: &mut Option<(String, Option<String>)>| {
+        if let Some((name, version)) = current.take() {
+            entries.push(ImportEntry::new(
+                &name,
+                version.as_deref(),
+                lookup(KNOWN_AQUA_PACKAGES, &name),
+            ));
+        }
+    };
+
+    for line in contents.lines() {
+        let stripped = line.split(" #").next().unwrap_or("").trim_end();
+        if stripped.trim().is_empty() || stripped.trim_start().starts_with('#') {
+            continue;
+        }
+
+        if !stripped.starts_with([' ', '-']) {
+            flush(&mut current);
+            in_packages = stripped.trim() == "packages:";
+            continue;
+        }
+        if !in_packages {
+            continue;
+        }
+
+        let mut item = stripped.trim_start();
+        if let Some(rest) = item.strip_prefix('-') {
+            flush(&mut current);
+            item = rest.trim_start();
+        }
+
+        let Some((key, value)) = item.split_once(':') else {
+            continue;
+        };
+        let value = unquote(value.trim());
+        match key.trim() {
+            "name" => {
+                let (name, version) = match value.split_once('@') {
+                    Some((name, version)) => (name, Some(version.to_string())),
+                    None => (value, None),
+                };
+                current = Some((name.to_string(), version));
+            }
+            "version" => {
+                if let Some((_, version)) = current.as_mut() {
+                    *version = Some(value.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    flush(&mut current);
+
+    entries
+}
+
+fn parse_mise(contents: &str) -> Result<Vec<ImportEntry>, zb_core::Error> {
+    let document: toml::Table = contents.parse().map_err(|e| zb_core::Error::FileError {
+        message: format!("failed to parse mise config: {e}"),
+    })?;
+    let Some(tools) = document.get("tools").and_then(toml::Value::as_table) else {
+        return Ok(Vec::new());
+    };
+
+    Ok(tools
+        .iter()
+        .map(|(tool, value)| {
+            let formula = match tool.split_once(':') {
+                Some(("aqua", package)) => lookup(KNOWN_AQUA_PACKAGES, package),
+                Some(("core" | "asdf", plugin)) => lookup(KNOWN_TOOLS, plugin),
+                // npm:, cargo:, pipx:, go:, ... install through another ecosystem.
+                Some(_) => None,
+                None => lookup(KNOWN_TOOLS, tool),
+            };
+            ImportEntry::new(tool, mise_version(value).as_deref(), formula)
+        })
+        .collect())
+}
+
+/// The version a mise tool entry asks for: `"20"`, the first of
+/// `["3.11", "3.12"]`, or the `version` of `{ version = "1.5" }` and of a
+/// `[tools.<name>]` table.
+fn mise_version(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(version) => Some(version.clone()),
+        toml::Value::Integer(version) => Some(version.to_string()),
+        toml::Value::Float(version) => Some(version.to_string()),
+        toml::Value::Array(versions) => versions.first().and_then(mise_version),
+        toml::Value::Table(options) => options.get("version").and_then(mise_version),
+        _ => None,
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapped(entries: &[ImportEntry]) -> Vec<&str> {
+        entries
+            .iter()
+            .filter_map(|e| e.formula.as_deref())
+            .collect()
+    }
+
+    fn unmapped(entries: &[ImportEntry]) -> Vec<&str> {
+        entries
+            .iter()
+            .filter(|e| e.formula.is_none())
+            .map(|e| e.tool.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn detect_format_from_file_name() {
+        assert_eq!(
+            detect_format(Path::new("/work/Brewfile")),
+            Some(ImportFormat::Brewfile)
+        );
+        assert_eq!(
+            detect_format(Path::new(".tool-versions")),
+            Some(ImportFormat::ToolVersions)
+        );
+        assert_eq!(
+            detect_format(Path::new("aqua.yaml")),
+            Some(ImportFormat::Aqua)
+        );
+        assert_eq!(
+            detect_format(Path::new(".mise.toml")),
+            Some(ImportFormat::Mise)
+        );
+        assert_eq!(detect_format(Path::new("requirements.txt")), None);
+    }
+
+    #[test]
+    fn parse_brewfile_maps_brew_and_cask_and_reports_others() {
+        let entries = parse_brewfile(
+            "tap \"homebrew/cask\"\nbrew \"wget\"\ncask \"firefox\"\nmas \"Xcode\", id: 497799835\n",
+        );
+        assert_eq!(mapped(&entries), vec!["wget", "cask:firefox"]);
+        assert_eq!(
+            unmapped(&entries),
+            vec!["tap homebrew/cask", "mas \"Xcode\", id: 497799835"]
+        );
+    }
+
+    #[test]
+    fn parse_tool_versions_maps_known_plugins() {
+        let entries =
+            parse_tool_versions("# pinned\nnodejs 20.11.0\ngolang 1.22.0 # comment\nfoobar 1.0\n");
+        assert_eq!(mapped(&entries), vec!["node", "go"]);
+        assert_eq!(unmapped(&entries), vec!["foobar"]);
+        assert_eq!(entries[0].version.as_deref(), Some("20.11.0"));
+    }
+
+    #[test]
+    fn parse_aqua_reads_inline_and_separate_versions() {
+        let entries = parse_aqua(
+            "registries:\n- type: standard\n  ref: v4.0.0\npackages:\n- name: cli/cli@v2.40.0\n- name: junegunn/fzf\n  version: 0.45.0\n- name: example/unknown@v1\n",
+        );
+        assert_eq!(mapped(&entries), vec!["gh", "fzf"]);
+        assert_eq!(unmapped(&entries), vec!["example/unknown"]);
+        assert_eq!(entries[0].version.as_deref(), Some("v2.40.0"));
+        assert_eq!(entries[1].version.as_deref(), Some("0.45.0"));
+    }
+
+    #[test]
+    fn parse_mise_reads_tools_table() {
+        let entries = parse_mise(
+            "[env]\nFOO = \"bar\"\n\n[tools]\nnode = \"20\"\npython = [\"3.11\", \"3.12\"]\n\"aqua:BurntSushi/ripgrep\" = \"latest\"\n\"npm:prettier\" = \"3\"\nkubectl = { version = \"1.29\" }\n",
+        )
+        .unwrap();
+        // Keys come back sorted.
+        assert_eq!(
+            mapped(&entries),
+            vec!["ripgrep", "kubernetes-cli", "node", "python"]
+        );
+        assert_eq!(unmapped(&entries), vec!["npm:prettier"]);
+        assert_eq!(entries[0].version, None);
+        assert_eq!(entries[4].version.as_deref(), Some("3.11"));
+    }
+
+    #[test]
+    fn parse_mise_reads_tool_sub_tables_and_reports_unknown_tools() {
+        let entries = parse_mise(
+            "[tools.go]\nversion = \"1.22\"\npostinstall = \"go version\"\n\n[tools.terraform]\nversion = \"1.7\"\n\n[tools]\njq = [{ version = \"1.7\" }]\n",
+        )
+        .unwrap();
+        assert_eq!(mapped(&entries), vec!["go", "jq"]);
+        assert_eq!(unmapped(&entries), vec!["terraform"]);
+        assert_eq!(entries[0].version.as_deref(), Some("1.22"));
+        assert_eq!(entries[1].version.as_deref(), Some("1.7"));
+        assert!(parse_mise("[tools\nnode = 20").is_err());
+    }
+}
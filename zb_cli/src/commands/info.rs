@@ -1,21 +1,137 @@
 use chrono::{DateTime, Local};
 use console::style;
 
-pub fn execute(installer: &mut zb_io::Installer, formula: String) -> Result<(), zb_core::Error> {
-    if let Some(keg) = installer.get_installed(&formula) {
-        print_field("Name:", style(&keg.name).bold());
+use crate::utils::{format_bytes, normalize_formula_name};
+
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    formula: String,
+    installed_files_size: bool,
+) -> Result<(), zb_core::Error> {
+    let formula = normalize_formula_name(&formula)?;
+    let keg = installer.get_installed(&formula);
+    let remote = match installer.fetch_formula(&formula).await {
+        Ok(remote) => Some(remote),
+        // Without API metadata there's nothing to show for a formula that
+        // isn't installed either.
+        Err(e) if keg.is_none() => return Err(e),
+        Err(e) => {
+            eprintln!(
+                "{} Couldn't fetch formula metadata: {e}",
+                style("Warning:").yellow().bold()
+            );
+            None
+        }
+    };
+
+    print_field("Name:", style(&formula).bold());
+    if let Some(remote) = &remote {
+        print_remote(installer, remote);
+    }
+
+    if let Some(keg) = keg {
         print_field("Version:", &keg.version);
+        print_field(
+            "Keg:",
+            installer
+                .keg_path(zb_core::formula_token(&keg.name), &keg.version)
+                .display(),
+        );
+        if installed_files_size {
+            match installer.keg_size(&keg.name, &keg.version) {
+                Some(bytes) => print_field("Size:", format_bytes(bytes)),
+                None => print_field("Size:", style("keg missing from the Cellar").yellow()),
+            }
+        }
+        let linked = installer.linked_files(&keg.name)?.len();
+        if linked == 0 {
+            print_field("Linked:", style("no").yellow());
+        } else {
+            print_field("Linked:", format!("{linked} files"));
+        }
         print_field("Store key:", &keg.store_key[..12]);
         print_field("Installed:", format_timestamp(keg.installed_at));
+        if let Some(source) = installer.bottle_source(&keg.name) {
+            print_field("Source:", source);
+        }
+        if let Some(note) = installer.install_note(&keg.name) {
+            print_field("Note:", note);
+        }
+
+        for acceptance in installer.license_acceptances(&keg.name)? {
+            print_field(
+                "Accepted:",
+                format!(
+                    "{} {}",
+                    acceptance.license,
+                    style(format!(
+                        "(for {} via {}, {})",
+                        acceptance.version,
+                        acceptance.accepted_via,
+                        format_timestamp(acceptance.accepted_at)
+                    ))
+                    .dim()
+                ),
+            );
+        }
+
+        let history = installer.upgrade_history(&keg.name)?;
+        if !history.is_empty() {
+            println!("{}", style("Upgrade notes:").dim());
+            for record in history {
+                println!(
+                    "    {} → {}: {} {}",
+                    record.from_version,
+                    record.to_version,
+                    record.change,
+                    style(format!("({})", format_timestamp(record.recorded_at))).dim()
+                );
+            }
+        }
     } else {
-        println!("Formula '{}' is not installed.", formula);
+        print_field("Version:", style("not installed").dim());
     }
 
     Ok(())
 }
 
+/// What the API knows about the formula, independent of any local install.
+fn print_remote(installer: &zb_io::Installer, formula: &zb_core::Formula) {
+    if let Some(desc) = &formula.desc {
+        print_field("Desc:", desc);
+    }
+    if let Some(homepage) = &formula.homepage {
+        print_field("Homepage:", homepage);
+    }
+    if let Some(license) = &formula.license {
+        print_field("License:", license);
+    }
+    let latest = formula.effective_version();
+    if formula.deprecated {
+        print_field(
+            "Latest:",
+            format!("{latest} {}", style("(deprecated)").yellow()),
+        );
+    } else {
+        print_field("Latest:", latest);
+    }
+    if formula.dependencies.is_empty() {
+        print_field("Depends on:", style("nothing").dim());
+    } else {
+        print_field("Depends on:", formula.dependencies.join(", "));
+    }
+    match installer.select_bottle(formula) {
+        Ok(bottle) => print_field("Bottle:", bottle.tag),
+        Err(_) if formula.has_source_url() => print_field(
+            "Bottle:",
+            style("none for this platform (builds from source)").yellow(),
+        ),
+        Err(_) => print_field("Bottle:", style("none for this platform").red()),
+    }
+}
+
 fn print_field(label: &str, value: impl std::fmt::Display) {
-    println!("{:<10}  {}", style(label).dim(), value);
+    println!("{:<11}  {}", style(label).dim(), value);
 }
 
 fn format_timestamp(timestamp: i64) -> String {
@@ -3,15 +3,20 @@ use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
-use zb_io::{InstallProgress, ProgressCallback};
+use zb_io::{InstallProgress, PlanAction, ProgressCallback};
 
-use crate::utils::{normalize_formula_name, suggest_homebrew};
+use crate::utils::{
+    confirm, format_bytes, interactive, normalize_formula_name, path_activation_warning,
+    suggest_homebrew, warn,
+};
 
 pub async fn execute(
     installer: &mut zb_io::Installer,
     formulas: Vec<String>,
     no_link: bool,
     build_from_source: bool,
+    note: Option<&str>,
+    lockfile: Option<&zb_io::Lockfile>,
 ) -> Result<(), zb_core::Error> {
     let start = Instant::now();
     println!(
@@ -20,6 +25,11 @@ pub async fn execute(
         style(formulas.join(", ")).bold()
     );
 
+    for warning in installer.preflight() {
+        warn(warning.problem)?;
+        eprintln!("    {}", style(warning.remedy).dim());
+    }
+
     let mut normalized_names = Vec::new();
     let mut cask_names = Vec::new();
     for formula in &formulas {
@@ -39,6 +49,7 @@ pub async fn execute(
     }
 
     let mut installed_count = 0usize;
+    let mut plan_hash = None;
 
     if !normalized_names.is_empty() {
         let plan = match installer
@@ -54,6 +65,10 @@ pub async fn execute(
             }
         };
 
+        if let Some(lockfile) = lockfile {
+            installer.check_lockfile(lockfile, &plan)?;
+        }
+        plan_hash = Some(installer.plan_hash(&plan));
         println!(
             "{} Resolving dependencies ({} packages)...",
             style("==>").cyan().bold(),
@@ -63,178 +78,430 @@ pub async fn execute(
             println!(
                 "    {} {}",
                 style(&item.formula.name).green(),
-                style(&item.formula.versions.stable).dim()
+                style(item.formula.effective_version()).dim()
             );
         }
+        installed_count += run_plan(installer, plan, no_link, &formulas).await?;
+    }
 
-        let multi = MultiProgress::new();
-        let bars: Arc<Mutex<HashMap<String, ProgressBar>>> = Arc::new(Mutex::new(HashMap::new()));
+    if !cask_names.is_empty() {
+        println!(
+            "{} Installing casks ({} packages)...",
+            style("==>").cyan().bold(),
+            cask_names.len()
+        );
+        let result = installer.install_casks(&cask_names, !no_link).await?;
+        installed_count += result.installed;
+    }
 
-        let download_style = ProgressStyle::default_bar()
-            .template("    {prefix:<16} {bar:25.cyan/dim} {bytes:>10}/{total_bytes:<10} {eta:>6}")
-            .unwrap()
-            .progress_chars("━━╸");
+    if let Some(note) = note {
+        for name in normalized_names.iter().chain(&cask_names) {
+            installer.set_install_note(name, note)?;
+        }
+    }
 
-        let spinner_style = ProgressStyle::default_spinner()
-            .template("    {prefix:<16} {spinner:.cyan} {msg}")
-            .unwrap()
-            .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏");
+    let elapsed = start.elapsed();
+    println!();
+    println!(
+        "{} Installed {} packages in {:.2}s",
+        style("==>").cyan().bold(),
+        style(installed_count).green().bold(),
+        elapsed.as_secs_f64()
+    );
+    if let Some(hash) = plan_hash {
+        println!("    {} {}", style("plan").dim(), style(hash).dim());
+    }
+    if !no_link && installer.config().shell.path_check {
+        warn_if_unreachable(installer, &normalized_names)?;
+    }
+
+    Ok(())
+}
 
-        let done_style = ProgressStyle::default_spinner()
-            .template("    {prefix:<16} {msg}")
-            .unwrap();
+/// Print what installing `formulas` would do, fetch and take up on disk,
+/// without changing anything.
+pub async fn dry_run(
+    installer: &zb_io::Installer,
+    formulas: &[String],
+    build_from_source: bool,
+    lockfile: Option<&zb_io::Lockfile>,
+) -> Result<(), zb_core::Error> {
+    let mut names = Vec::with_capacity(formulas.len());
+    for formula in formulas {
+        let name = normalize_formula_name(formula)?;
+        if name.starts_with("cask:") {
+            return Err(zb_core::Error::InvalidArgument {
+                message: format!("--dry-run covers formulas only; {name} is a cask"),
+            });
+        }
+        names.push(name);
+    }
 
+    let plan = installer
+        .plan_with_options(&names, build_from_source)
+        .await?;
+    if let Some(lockfile) = lockfile {
+        installer.check_lockfile(lockfile, &plan)?;
+    }
+    warn_if_stale(installer, &plan)?;
+    let run = installer.dry_run(&plan).await?;
+
+    println!(
+        "{} Dry run: {} ({} packages)",
+        style("==>").cyan().bold(),
+        style(formulas.join(", ")).bold(),
+        run.items.len()
+    );
+    let (mut installs, mut changes, mut unchanged) = (0, 0, 0);
+    for item in &run.items {
+        let (marker, what) = match &item.action {
+            Some(PlanAction::Install { version, .. }) => {
+                installs += 1;
+                (style("+").green().bold(), format!("install {version}"))
+            }
+            Some(PlanAction::Upgrade { from, to, .. }) => {
+                changes += 1;
+                (style("~").yellow().bold(), format!("upgrade {from} → {to}"))
+            }
+            Some(PlanAction::Reinstall { version, .. }) => {
+                changes += 1;
+                (style("~").yellow().bold(), format!("reinstall {version}"))
+            }
+            Some(PlanAction::Autoremove { .. }) | None => {
+                unchanged += 1;
+                (
+                    style("=").dim(),
+                    format!("skip {} (installed)", item.version),
+                )
+            }
+        };
+        let size = if item.action.is_none() {
+            String::new()
+        } else if item.cached {
+            "cached".to_string()
+        } else {
+            item.download_bytes
+                .map(format_bytes)
+                .unwrap_or_else(|| "size unknown".to_string())
+        };
         println!(
-            "{} Downloading and installing formulas...",
-            style("==>").cyan().bold()
+            "  {} {} {} {} {}",
+            marker,
+            style(&item.name).bold(),
+            what,
+            style(format!("[{}]", item.method)).dim(),
+            style(size).dim()
         );
+    }
 
-        let bars_clone = bars.clone();
-        let multi_clone = multi.clone();
-        let download_style_clone = download_style.clone();
-        let spinner_style_clone = spinner_style.clone();
-        let done_style_clone = done_style.clone();
-
-        let progress_callback: Arc<ProgressCallback> = Arc::new(Box::new(move |event| {
-            let mut bars = bars_clone.lock().unwrap();
-            match event {
-                InstallProgress::DownloadStarted { name, total_bytes } => {
-                    let pb = if let Some(total) = total_bytes {
-                        let pb = multi_clone.add(ProgressBar::new(total));
-                        pb.set_style(download_style_clone.clone());
-                        pb
-                    } else {
-                        let pb = multi_clone.add(ProgressBar::new_spinner());
-                        pb.set_style(spinner_style_clone.clone());
-                        pb.set_message("downloading...");
-                        pb.enable_steady_tick(std::time::Duration::from_millis(80));
-                        pb
-                    };
-                    pb.set_prefix(name.clone());
-                    bars.insert(name, pb);
-                }
-                InstallProgress::DownloadProgress {
-                    name,
-                    downloaded,
-                    total_bytes,
-                } => {
-                    if let Some(pb) = bars.get(&name)
-                        && total_bytes.is_some()
-                    {
-                        pb.set_position(downloaded);
-                    }
+    println!(
+        "Plan: {} to install, {} to change, {} already installed.",
+        style(installs).green().bold(),
+        style(changes).yellow().bold(),
+        unchanged
+    );
+    let unknown = match run.unknown_sizes() {
+        0 => String::new(),
+        n => format!(" ({n} of unknown size)"),
+    };
+    println!(
+        "    Download: {}{unknown}, disk: about {}",
+        format_bytes(run.download_bytes),
+        format_bytes(run.disk_estimate)
+    );
+    println!("    {}", style("Nothing was changed.").dim());
+    Ok(())
+}
+
+/// Confirm, download and install a resolved plan with progress output,
+/// returning how many formulas were installed.
+pub(crate) async fn run_plan(
+    installer: &mut zb_io::Installer,
+    plan: zb_io::InstallPlan,
+    no_link: bool,
+    formulas: &[String],
+) -> Result<usize, zb_core::Error> {
+    warn_if_stale(installer, &plan)?;
+    for notice in installer.upgrade_notices(&plan) {
+        for change in &notice.changes {
+            warn(format!(
+                "{} {} → {}: {}",
+                style(&notice.name).bold(),
+                notice.from_version,
+                notice.to_version,
+                change
+            ))?;
+        }
+    }
+    confirm_licenses(installer, &plan)?;
+
+    let multi = MultiProgress::new();
+    let bars: Arc<Mutex<HashMap<String, ProgressBar>>> = Arc::new(Mutex::new(HashMap::new()));
+
+    let download_style = ProgressStyle::default_bar()
+        .template("    {prefix:<16} {bar:25.cyan/dim} {bytes:>10}/{total_bytes:<10} {eta:>6}")
+        .unwrap()
+        .progress_chars("━━╸");
+
+    let spinner_style = ProgressStyle::default_spinner()
+        .template("    {prefix:<16} {spinner:.cyan} {msg}")
+        .unwrap()
+        .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏");
+
+    let done_style = ProgressStyle::default_spinner()
+        .template("    {prefix:<16} {msg}")
+        .unwrap();
+
+    println!(
+        "{} Downloading and installing formulas...",
+        style("==>").cyan().bold()
+    );
+
+    let keg_only: Arc<Mutex<Vec<(String, String)>>> = Arc::new(Mutex::new(Vec::new()));
+    let keg_only_clone = keg_only.clone();
+    let bars_clone = bars.clone();
+    let multi_clone = multi.clone();
+    let download_style_clone = download_style.clone();
+    let spinner_style_clone = spinner_style.clone();
+    let done_style_clone = done_style.clone();
+
+    let progress_callback: Arc<ProgressCallback> = Arc::new(Box::new(move |event| {
+        let mut bars = bars_clone.lock().unwrap();
+        match event {
+            InstallProgress::DownloadStarted {
+                id,
+                name,
+                total_bytes,
+            } => {
+                let pb = if let Some(total) = total_bytes {
+                    let pb = multi_clone.add(ProgressBar::new(total));
+                    pb.set_style(download_style_clone.clone());
+                    pb
+                } else {
+                    let pb = multi_clone.add(ProgressBar::new_spinner());
+                    pb.set_style(spinner_style_clone.clone());
+                    pb.set_message("downloading...");
+                    pb.enable_steady_tick(std::time::Duration::from_millis(80));
+                    pb
+                };
+                pb.set_prefix(name);
+                bars.insert(id, pb);
+            }
+            InstallProgress::DownloadProgress {
+                id,
+                downloaded,
+                total_bytes,
+                ..
+            } => {
+                if let Some(pb) = bars.get(&id)
+                    && total_bytes.is_some()
+                {
+                    pb.set_position(downloaded);
                 }
-                InstallProgress::DownloadCompleted { name, total_bytes } => {
-                    if let Some(pb) = bars.get(&name) {
-                        if total_bytes > 0 {
-                            pb.set_position(total_bytes);
-                        }
-                        pb.set_style(spinner_style_clone.clone());
-                        pb.set_message("unpacking...");
-                        pb.enable_steady_tick(std::time::Duration::from_millis(80));
+            }
+            InstallProgress::DownloadCompleted {
+                id, total_bytes, ..
+            } => {
+                if let Some(pb) = bars.get(&id) {
+                    if total_bytes > 0 {
+                        pb.set_position(total_bytes);
                     }
+                    pb.set_style(spinner_style_clone.clone());
+                    pb.set_message("unpacking...");
+                    pb.enable_steady_tick(std::time::Duration::from_millis(80));
                 }
-                InstallProgress::UnpackStarted { name } => {
-                    if let Some(pb) = bars.get(&name) {
-                        pb.set_message("unpacking...");
-                    }
+            }
+            InstallProgress::UnpackStarted { id, .. } => {
+                if let Some(pb) = bars.get(&id) {
+                    pb.set_message("unpacking...");
                 }
-                InstallProgress::UnpackCompleted { name } => {
-                    if let Some(pb) = bars.get(&name) {
-                        pb.set_message("unpacked");
-                    }
+            }
+            InstallProgress::UnpackCompleted { id, .. } => {
+                if let Some(pb) = bars.get(&id) {
+                    pb.set_message("unpacked");
                 }
-                InstallProgress::LinkStarted { name } => {
-                    if let Some(pb) = bars.get(&name) {
-                        pb.set_message("linking...");
-                    }
+            }
+            InstallProgress::LinkStarted { id, .. } => {
+                if let Some(pb) = bars.get(&id) {
+                    pb.set_message("linking...");
                 }
-                InstallProgress::LinkCompleted { name } => {
-                    if let Some(pb) = bars.get(&name) {
-                        pb.set_message("linked");
-                    }
+            }
+            InstallProgress::LinkCompleted { id, .. } => {
+                if let Some(pb) = bars.get(&id) {
+                    pb.set_message("linked");
                 }
-                InstallProgress::LinkSkipped { name, reason } => {
-                    if let Some(pb) = bars.get(&name) {
-                        pb.set_message(format!("keg-only ({})", reason));
-                    }
+            }
+            InstallProgress::LinkSkipped { id, name, reason } => {
+                if let Some(pb) = bars.get(&id) {
+                    pb.set_message(format!("keg-only ({})", reason));
                 }
-                InstallProgress::InstallCompleted { name } => {
-                    if let Some(pb) = bars.get(&name) {
-                        pb.set_style(done_style_clone.clone());
-                        pb.set_message(format!("{} installed", style("✓").green()));
-                        pb.finish();
-                    }
+                keg_only_clone.lock().unwrap().push((name, reason));
+            }
+            InstallProgress::InstallCompleted { id, .. } => {
+                if let Some(pb) = bars.get(&id) {
+                    pb.set_style(done_style_clone.clone());
+                    pb.set_message(format!("{} installed", style("✓").green()));
+                    pb.finish();
                 }
             }
-        }));
+        }
+    }));
 
-        let result_val = installer
-            .execute_with_progress(plan, !no_link, Some(progress_callback))
-            .await;
+    let result_val = installer
+        .execute_with_progress(plan, !no_link, Some(progress_callback))
+        .await;
 
-        {
-            let bars = bars.lock().unwrap();
-            for (_, pb) in bars.iter() {
-                if !pb.is_finished() {
-                    pb.finish();
-                }
+    {
+        let bars = bars.lock().unwrap();
+        for (_, pb) in bars.iter() {
+            if !pb.is_finished() {
+                pb.finish();
             }
         }
+    }
 
-        let result = match result_val {
-            Ok(r) => r,
-            Err(ref e @ zb_core::Error::LinkConflict { ref conflicts }) => {
-                eprintln!();
-                eprintln!(
-                    "{} The link step did not complete successfully.",
-                    style("Error:").red().bold()
-                );
-                eprintln!("The formula was installed, but is not symlinked into the prefix.");
-                eprintln!();
-                eprintln!("Possible conflicting files:");
-                for c in conflicts {
-                    if let Some(ref owner) = c.owned_by {
-                        eprintln!(
-                            "  {} (symlink belonging to {})",
-                            c.path.display(),
-                            style(owner).yellow()
-                        );
-                    } else {
-                        eprintln!("  {}", c.path.display());
-                    }
+    let result = match result_val {
+        Ok(r) => r,
+        Err(ref e @ zb_core::Error::LinkConflict { ref conflicts }) => {
+            eprintln!();
+            eprintln!(
+                "{} The link step did not complete successfully.",
+                style("Error:").red().bold()
+            );
+            eprintln!("The formula was installed, but is not symlinked into the prefix.");
+            eprintln!();
+            eprintln!("Possible conflicting files:");
+            for c in conflicts {
+                if let Some(ref owner) = c.owned_by {
+                    eprintln!(
+                        "  {} (symlink belonging to {})",
+                        c.path.display(),
+                        style(owner).yellow()
+                    );
+                } else {
+                    eprintln!("  {}", c.path.display());
                 }
-                eprintln!();
-                return Err(e.clone());
             }
-            Err(e) => {
-                for formula in &formulas {
-                    suggest_homebrew(formula, &e);
-                }
-                return Err(e);
+            eprintln!();
+            return Err(e.clone());
+        }
+        Err(e) => {
+            for formula in formulas {
+                suggest_homebrew(formula, &e);
             }
-        };
-        installed_count += result.installed;
-    }
+            return Err(e);
+        }
+    };
 
-    if !cask_names.is_empty() {
-        println!(
-            "{} Installing casks ({} packages)...",
-            style("==>").cyan().bold(),
-            cask_names.len()
-        );
-        let result = installer.install_casks(&cask_names, !no_link).await?;
-        installed_count += result.installed;
+    for (name, reason) in keg_only.lock().unwrap().iter() {
+        print_keg_only_caveats(installer, name, reason);
     }
+    Ok(result.installed)
+}
 
-    let elapsed = start.elapsed();
-    println!();
+/// Explain that `name` was left unlinked, and how to use it anyway.
+fn print_keg_only_caveats(installer: &zb_io::Installer, name: &str, reason: &str) {
     println!(
-        "{} Installed {} packages in {:.2}s",
+        "{} {} is keg-only: {}",
         style("==>").cyan().bold(),
-        style(installed_count).green().bold(),
-        elapsed.as_secs_f64()
+        style(name).bold(),
+        reason
     );
+    println!(
+        "    It was not linked into {}.",
+        installer.prefix().display()
+    );
+    let exports = installer.keg_only_exports(name);
+    if exports.is_empty() {
+        return;
+    }
+    println!("    To use it, add to your shell profile:");
+    for export in exports {
+        println!("      {export}");
+    }
+}
+
+/// Point out when the plan was resolved from an old local index.
+fn warn_if_stale(
+    installer: &zb_io::Installer,
+    plan: &zb_io::InstallPlan,
+) -> Result<(), zb_core::Error> {
+    if let Some(days) = installer.stale_metadata_days(plan) {
+        warn(format!(
+            "formula metadata is from a local index {days} days old; versions may be out of date"
+        ))?;
+        let hint = match installer.index_pin() {
+            Some(pin) => format!(
+                "the index is pinned to {}; `zb update --unpin-index` tracks the API again, or pass --offline to install from it as is",
+                pin.requested
+            ),
+            None => "run `zb update` to refresh it, or pass --offline to install from it as is"
+                .to_string(),
+        };
+        eprintln!("    {}", style(hint).dim());
+    }
+    Ok(())
+}
+
+/// Point out when the executables just linked won't run from this shell.
+fn warn_if_unreachable(
+    installer: &zb_io::Installer,
+    names: &[String],
+) -> Result<(), zb_core::Error> {
+    let prefix_bin = installer.prefix().join("bin");
+    let mut binaries = Vec::new();
+    for name in names {
+        for link in installer.linked_files(name)? {
+            if link.parent() == Some(prefix_bin.as_path())
+                && let Some(file_name) = link.file_name()
+            {
+                binaries.push(file_name.to_string_lossy().into_owned());
+            }
+        }
+    }
+    if binaries.is_empty() {
+        return Ok(());
+    }
 
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let shell_loaded = std::env::var_os("ZEROBREW_PREFIX").is_some();
+    if let Some(warning) = path_activation_warning(&path, &prefix_bin, &binaries, shell_loaded) {
+        eprintln!("{} {warning}", style("Warning:").yellow().bold());
+    }
     Ok(())
 }
+
+/// Ask the user to accept licenses the config gates. Non-interactive runs
+/// fall through to the installer, which refuses without `--accept-licenses`.
+fn confirm_licenses(
+    installer: &mut zb_io::Installer,
+    plan: &zb_io::InstallPlan,
+) -> Result<(), zb_core::Error> {
+    let pending = installer.licenses_to_accept(plan);
+    if pending.is_empty() || installer.accepts_licenses() {
+        return Ok(());
+    }
+
+    println!(
+        "{} These formulas require accepting their license:",
+        style("==>").cyan().bold()
+    );
+    for requirement in &pending {
+        println!(
+            "    {} {} {}",
+            style(&requirement.name).bold(),
+            style(&requirement.version).dim(),
+            style(&requirement.license).yellow()
+        );
+    }
+    if !interactive() {
+        return Ok(());
+    }
+
+    if !confirm("Accept these licenses?")? {
+        return Err(zb_core::Error::InvalidArgument {
+            message: "license not accepted".to_string(),
+        });
+    }
+    installer.accept_licenses(&pending, "prompt")
+}
@@ -0,0 +1,7 @@
+/// Print installed formulas nothing else depends on, one per line.
+pub fn execute(installer: &mut zb_io::Installer) -> Result<(), zb_core::Error> {
+    for name in installer.leaves()? {
+        println!("{name}");
+    }
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use console::style;
+
+use zb_io::Installer;
+
+use crate::utils::normalize_formula_name;
+
+pub fn execute(
+    installer: &mut Installer,
+    formula: &str,
+    only: &[String],
+    dry_run: bool,
+    overwrite: bool,
+) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(formula)?;
+    if dry_run {
+        return preview(installer, &name, only, overwrite);
+    }
+    let linked = installer.link(&name, only, overwrite)?;
+
+    println!(
+        "{} Linked {} ({} files)",
+        style("==>").cyan().bold(),
+        style(&name).bold(),
+        style(linked.len()).green().bold()
+    );
+    if !only.is_empty() {
+        for file in &linked {
+            println!("    {}", style(file.link_path.display()).dim());
+        }
+    }
+    Ok(())
+}
+
+fn preview(
+    installer: &Installer,
+    name: &str,
+    only: &[String],
+    overwrite: bool,
+) -> Result<(), zb_core::Error> {
+    let preview = installer.preview_link(name, only)?;
+    if preview.links.is_empty() {
+        println!("{name} is already linked.");
+        return Ok(());
+    }
+
+    println!(
+        "{} Would link {} ({} files)",
+        style("==>").cyan().bold(),
+        style(name).bold(),
+        style(preview.links.len()).green().bold()
+    );
+    for file in &preview.links {
+        println!(
+            "    {} {} -> {}",
+            style("○").dim(),
+            file.link_path.display(),
+            style(file.target_path.display()).dim()
+        );
+    }
+
+    if preview.conflicts.is_empty() {
+        return Ok(());
+    }
+    println!();
+    if overwrite {
+        println!("Would overwrite:");
+    } else {
+        println!(
+            "{} These files are in the way; pass --overwrite to replace them:",
+            style("Warning:").yellow().bold()
+        );
+    }
+    for conflict in &preview.conflicts {
+        match &conflict.owned_by {
+            Some(owner) => println!(
+                "    {} (belongs to {})",
+                conflict.path.display(),
+                style(owner).yellow()
+            ),
+            None => println!("    {}", conflict.path.display()),
+        }
+    }
+    Ok(())
+}
@@ -1,15 +1,256 @@
+use chrono::{DateTime, Local};
 use console::style;
+use std::collections::{BTreeMap, BTreeSet};
 
-pub fn execute(installer: &mut zb_io::Installer) -> Result<(), zb_core::Error> {
+use crate::utils::{TreeNode, format_bytes, tree_lines};
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    versions: bool,
+    notes: bool,
+    long: bool,
+    json: bool,
+    tree: bool,
+) -> Result<(), zb_core::Error> {
     let installed = installer.list_installed()?;
 
+    if tree {
+        if installed.is_empty() {
+            println!("No formulas installed.");
+            return Ok(());
+        }
+        let mut dependencies = BTreeMap::new();
+        for keg in &installed {
+            dependencies.insert(
+                keg.name.clone(),
+                installer.installed_dependencies(&keg.name)?,
+            );
+        }
+        let label = |name: &str| {
+            let version = installed
+                .iter()
+                .find(|keg| keg.name == name)
+                .map(|keg| keg.version.as_str())
+                .unwrap_or_default();
+            let mut label = format!("{} {}", style(name).bold(), style(version).dim());
+            if installer.is_pinned(name) {
+                label.push_str(&format!(" {}", style("(pinned)").dim()));
+            }
+            label
+        };
+        for line in tree_lines(&forest(&installer.leaves()?, &dependencies, &label)) {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+
+    if json {
+        let entries: Vec<_> = installed
+            .iter()
+            .map(|keg| {
+                json_entry(
+                    keg,
+                    installer.keg_size(&keg.name, &keg.version),
+                    installer.is_linked(&keg.name),
+                    installer.install_note(&keg.name),
+                )
+            })
+            .collect();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&entries).expect("JSON values always serialize")
+        );
+        return Ok(());
+    }
+
     if installed.is_empty() {
         println!("No formulas installed.");
     } else {
+        let name_width = installed.iter().map(|k| k.name.len()).max().unwrap_or(0);
+        let version_width = installed.iter().map(|k| k.version.len()).max().unwrap_or(0);
+
         for keg in installed {
-            println!("{} {}", style(&keg.name).bold(), style(&keg.version).dim());
+            let retained = if versions {
+                installer.retained_versions(&keg.name)?
+            } else {
+                Vec::new()
+            };
+
+            if long {
+                let size = installer
+                    .keg_size(&keg.name, &keg.version)
+                    .map(format_bytes)
+                    .unwrap_or_else(|| "—".to_string());
+                let linked = if installer.is_linked(&keg.name) {
+                    style("linked").green()
+                } else {
+                    style("not linked").yellow()
+                };
+                print!(
+                    "{} {} {:>10}  {}  {}",
+                    style(format!("{:name_width$}", keg.name)).bold(),
+                    style(format!("{:version_width$}", keg.version)).dim(),
+                    size,
+                    format_date(keg.installed_at),
+                    linked
+                );
+            } else {
+                print!("{} {}", style(&keg.name).bold(), style(&keg.version).dim());
+            }
+
+            if installer.is_lazy(&keg.name) {
+                print!(" {}", style("(not materialized yet)").dim());
+            } else if !retained.is_empty() {
+                let retained: Vec<&str> = retained.iter().map(|r| r.version.as_str()).collect();
+                print!(
+                    " {}",
+                    style(format!("(retained: {})", retained.join(", "))).dim()
+                );
+            }
+            println!();
+
+            if notes && let Some(note) = installer.install_note(&keg.name) {
+                println!("    {}", style(note).italic());
+            }
         }
     }
 
     Ok(())
 }
+
+/// Trees rooted at `leaves` following `dependencies`. Formulas only reachable
+/// through a dependency cycle get a tree of their own so none go missing.
+fn forest(
+    leaves: &[String],
+    dependencies: &BTreeMap<String, Vec<String>>,
+    label: &dyn Fn(&str) -> String,
+) -> Vec<TreeNode> {
+    fn node(
+        name: &str,
+        dependencies: &BTreeMap<String, Vec<String>>,
+        label: &dyn Fn(&str) -> String,
+        ancestors: &mut Vec<String>,
+        seen: &mut BTreeSet<String>,
+    ) -> TreeNode {
+        seen.insert(name.to_string());
+        ancestors.push(name.to_string());
+        let mut children = Vec::new();
+        for dep in dependencies.get(name).into_iter().flatten() {
+            if !ancestors.contains(dep) {
+                children.push(node(dep, dependencies, label, ancestors, seen));
+            }
+        }
+        ancestors.pop();
+        TreeNode {
+            label: label(name),
+            children,
+        }
+    }
+
+    let mut seen = BTreeSet::new();
+    let mut roots: Vec<TreeNode> = leaves
+        .iter()
+        .map(|leaf| node(leaf, dependencies, label, &mut Vec::new(), &mut seen))
+        .collect();
+    for name in dependencies.keys() {
+        if !seen.contains(name) {
+            roots.push(node(name, dependencies, label, &mut Vec::new(), &mut seen));
+        }
+    }
+    roots
+}
+
+fn json_entry(
+    keg: &zb_io::InstalledKeg,
+    size: Option<u64>,
+    linked: bool,
+    note: Option<String>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "name": keg.name,
+        "version": keg.version,
+        "size": size,
+        "installed_at": keg.installed_at,
+        "linked": linked,
+        "note": note,
+    })
+}
+
+fn format_date(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|dt| {
+            dt.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forest_shares_dependencies_and_keeps_cycles() {
+        let dependencies: BTreeMap<String, Vec<String>> = [
+            ("wget", vec!["libidn2", "openssl@3"]),
+            ("curl", vec!["openssl@3"]),
+            ("openssl@3", vec!["ca-certificates"]),
+            ("ca-certificates", vec![]),
+            ("libidn2", vec![]),
+            ("a", vec!["b"]),
+            ("b", vec!["a"]),
+        ]
+        .into_iter()
+        .map(|(name, deps)| {
+            (
+                name.to_string(),
+                deps.into_iter().map(String::from).collect(),
+            )
+        })
+        .collect();
+        let leaves = ["curl".to_string(), "wget".to_string()];
+
+        let lines = tree_lines(&forest(&leaves, &dependencies, &|name| name.to_string()));
+        assert_eq!(
+            lines,
+            [
+                "curl",
+                "└── openssl@3",
+                "    └── ca-certificates",
+                "wget",
+                "├── libidn2",
+                "└── openssl@3",
+                "    └── ca-certificates",
+                "a",
+                "└── b",
+            ]
+        );
+    }
+
+    #[test]
+    fn json_entry_reports_size_link_state_and_unix_install_time() {
+        let keg = zb_io::InstalledKeg {
+            name: "jq".to_string(),
+            version: "1.7.1".to_string(),
+            store_key: "abc".to_string(),
+            installed_at: 1_700_000_000,
+        };
+
+        assert_eq!(
+            json_entry(&keg, Some(2048), true, None),
+            serde_json::json!({
+                "name": "jq",
+                "version": "1.7.1",
+                "size": 2048,
+                "installed_at": 1_700_000_000,
+                "linked": true,
+                "note": null,
+            })
+        );
+        assert_eq!(
+            json_entry(&keg, None, false, None)["size"],
+            serde_json::Value::Null
+        );
+    }
+}
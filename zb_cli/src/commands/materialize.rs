@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use console::style;
+
+use zb_io::{Installer, LazyLauncher};
+
+use crate::utils::normalize_formula_name;
+
+/// How lazy shims call back into this zb binary, pinned to the current root
+/// and prefix so they work regardless of the caller's environment.
+pub fn launcher(root: &Path, prefix: &Path) -> Result<LazyLauncher, zb_core::Error> {
+    let program = std::env::current_exe().map_err(|e| zb_core::Error::ExecutionError {
+        message: format!("failed to locate the zb executable: {e}"),
+    })?;
+    Ok(LazyLauncher::new(
+        program,
+        vec![
+            "--root".to_string(),
+            root.to_string_lossy().into_owned(),
+            "--prefix".to_string(),
+            prefix.to_string_lossy().into_owned(),
+        ],
+    ))
+}
+
+pub fn execute(installer: &mut Installer, formula: &str) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(formula)?;
+    if installer.materialize_lazy(&name)? {
+        println!(
+            "{} Materialized {}",
+            style("==>").cyan().bold(),
+            style(&name).bold()
+        );
+    } else {
+        println!("{name} is already materialized.");
+    }
+    Ok(())
+}
@@ -1,11 +1,15 @@
 use console::style;
-use std::io::{self, Write};
 use std::process::Command;
 
+use super::snapshot;
+use crate::utils::confirm;
+
 pub async fn execute(
     installer: &mut zb_io::Installer,
     yes: bool,
     force: bool,
+    unlink: bool,
+    snapshots: Option<&zb_io::SnapshotStore>,
 ) -> Result<(), zb_core::Error> {
     println!(
         "{} Fetching installed Homebrew packages...",
@@ -73,18 +77,16 @@ pub async fn execute(
     }
     println!();
 
-    if !yes {
-        print!("Continue with migration? [y/N] ");
-        io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        if !input.trim().eq_ignore_ascii_case("y") {
-            println!("Aborted.");
-            return Ok(());
-        }
+    if !yes && !confirm("Continue with migration?")? {
+        println!("Aborted.");
+        return Ok(());
     }
 
+    let taken = match snapshots {
+        Some(store) => Some(snapshot::create(store, "migrate")?),
+        None => None,
+    };
+
     println!();
     println!(
         "{} Migrating {} formulas to zerobrew...",
@@ -144,34 +146,50 @@ pub async fn execute(
             println!("    • {}", name);
         }
         println!();
+        if let Some(snapshot) = &taken {
+            snapshot::print_restore_hint(snapshot);
+        }
     }
 
+    // Unlinking keeps Homebrew's kegs, so `brew link` undoes the switch.
+    let (verb, doing, done) = if unlink {
+        ("unlink", "Unlinking", "Unlinked")
+    } else {
+        ("uninstall", "Uninstalling", "Uninstalled")
+    };
+
     if success_count == 0 {
-        println!("No formulas were successfully migrated. Skipping uninstall from Homebrew.");
+        println!("No formulas were successfully migrated. Skipping {verb} from Homebrew.");
         return Ok(());
     }
 
-    println!();
-    if !yes {
-        print!(
-            "Uninstall {} formula(s) from Homebrew? [y/N] ",
-            style(success_count).green()
+    if !Command::new("brew")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+    {
+        println!();
+        println!(
+            "{} brew can't be run here, so Homebrew's copies were left in place.",
+            style("Note:").yellow().bold()
         );
-        io::stdout().flush().unwrap();
+        return Ok(());
+    }
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        if !input.trim().eq_ignore_ascii_case("y") {
-            println!("Skipped uninstall from Homebrew.");
-            return Ok(());
-        }
+    println!();
+    if !yes
+        && !confirm(&format!(
+            "{} {} formula(s) from Homebrew?",
+            if unlink { "Unlink" } else { "Uninstall" },
+            style(success_count).green()
+        ))?
+    {
+        println!("Skipped {verb} from Homebrew.");
+        return Ok(());
     }
 
     println!();
-    println!(
-        "{} Uninstalling from Homebrew...",
-        style("==>").cyan().bold()
-    );
+    println!("{} {doing} from Homebrew...", style("==>").cyan().bold());
 
     let mut uninstalled = 0;
     let mut uninstall_failed: Vec<String> = Vec::new();
@@ -183,7 +201,7 @@ pub async fn execute(
 
         print!("    {} {}...", style("○").dim(), pkg.name);
 
-        let mut args = vec!["uninstall"];
+        let mut args = vec![verb];
         if force {
             args.push("--force");
         }
@@ -192,7 +210,7 @@ pub async fn execute(
         let status = Command::new("brew")
             .args(&args)
             .status()
-            .map_err(|e| format!("Failed to run brew uninstall: {}", e));
+            .map_err(|e| format!("Failed to run brew {verb}: {}", e));
 
         match status {
             Ok(s) if s.success() => {
@@ -213,7 +231,7 @@ pub async fn execute(
 
     println!();
     println!(
-        "{} Uninstalled {} of {} formula(s) from Homebrew",
+        "{} {done} {} of {} formula(s) from Homebrew",
         style("==>").cyan().bold(),
         style(uninstalled).green().bold(),
         success_count
@@ -221,15 +239,19 @@ pub async fn execute(
 
     if !uninstall_failed.is_empty() {
         println!(
-            "{} Failed to uninstall {} formula(s) from Homebrew:",
+            "{} Failed to {verb} {} formula(s) from Homebrew:",
             style("Warning:").yellow().bold(),
             uninstall_failed.len()
         );
         for name in &uninstall_failed {
             println!("    • {}", name);
         }
-        println!("You may need to uninstall these manually with:");
-        println!("    brew uninstall --force <formula>");
+        println!("You may need to {verb} these manually with:");
+        if unlink {
+            println!("    brew unlink <formula>");
+        } else {
+            println!("    brew uninstall --force <formula>");
+        }
     }
 
     Ok(())
@@ -1,11 +1,44 @@
+pub mod audit;
+pub mod bottle;
 pub mod bundle;
+pub mod cache;
+pub mod cat;
+pub mod cleanup;
 pub mod completion;
+pub mod deps;
+pub mod doctor;
+pub mod exec;
 pub mod gc;
+pub mod import;
 pub mod info;
 pub mod init;
 pub mod install;
+pub mod leaves;
+pub mod link;
 pub mod list;
+pub mod materialize;
 pub mod migrate;
+pub mod outdated;
+pub mod pin;
+pub mod plan;
+pub mod queue;
+pub mod reinstall;
+pub mod relocate;
+pub mod report;
 pub mod reset;
 pub mod run;
+pub mod search;
+pub mod services;
+pub mod set;
+pub mod setup;
+pub mod shell;
+pub mod shellenv;
+pub mod snapshot;
+pub mod stats;
+pub mod tap;
+pub mod test;
 pub mod uninstall;
+pub mod unlink;
+pub mod update;
+pub mod upgrade;
+pub mod verify_plan;
@@ -0,0 +1,211 @@
+use console::style;
+
+use crate::utils::format_bytes;
+
+/// Prints outdated formulas and returns whether there were any.
+///
+/// `zb` exits with 0 when everything is up to date, 1 when something is
+/// outdated and 2 on errors, so scripts can act on the status alone.
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    diff: bool,
+    quiet: bool,
+    json: bool,
+) -> Result<bool, zb_core::Error> {
+    let outdated = installer.outdated().await?;
+
+    if json {
+        let mut entries = Vec::with_capacity(outdated.len());
+        for item in &outdated {
+            let details = if diff {
+                Some(installer.outdated_diff(item).await)
+            } else {
+                None
+            };
+            let entry = json_entry(item, installer.is_pinned(&item.name), details.as_ref());
+            entries.push(entry);
+        }
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&entries).expect("JSON values always serialize")
+        );
+        return Ok(!outdated.is_empty());
+    }
+
+    if outdated.is_empty() {
+        if !quiet {
+            println!("All formulas are up to date.");
+        }
+        return Ok(false);
+    }
+
+    for item in &outdated {
+        if quiet {
+            println!("{}", item.name);
+            continue;
+        }
+
+        let pinned = if installer.is_pinned(&item.name) {
+            format!(" {}", style("(pinned)").yellow())
+        } else {
+            String::new()
+        };
+        println!(
+            "{} {} → {}{pinned}",
+            style(&item.name).bold(),
+            style(&item.installed_version).dim(),
+            style(&item.current_version).green()
+        );
+
+        if diff {
+            let details = installer.outdated_diff(item).await;
+            print_diff(&details);
+        }
+    }
+
+    Ok(true)
+}
+
+fn json_entry(
+    item: &zb_io::OutdatedFormula,
+    pinned: bool,
+    diff: Option<&zb_io::OutdatedDiff>,
+) -> serde_json::Value {
+    let mut entry = serde_json::json!({
+        "name": item.name,
+        "installed_version": item.installed_version,
+        "current_version": item.current_version,
+        "pinned": pinned,
+    });
+    if let Some(diff) = diff {
+        entry["diff"] = serde_json::json!({
+            "added_dependencies": diff.added_dependencies,
+            "removed_dependencies": diff.removed_dependencies,
+            "dependencies_known": diff.dependencies_known,
+            "installed_bottle_size": diff.installed_bottle_size,
+            "current_bottle_size": diff.current_bottle_size,
+            "release_notes_url": diff.release_notes_url,
+        });
+    }
+    entry
+}
+
+fn print_diff(diff: &zb_io::OutdatedDiff) {
+    if !diff.dependencies_known {
+        println!(
+            "    {} {}",
+            style("dependencies:").dim(),
+            style("unknown").dim()
+        );
+    } else if diff.added_dependencies.is_empty() && diff.removed_dependencies.is_empty() {
+        println!("    {} unchanged", style("dependencies:").dim());
+    } else {
+        let changes: Vec<String> = diff
+            .added_dependencies
+            .iter()
+            .map(|d| style(format!("+{d}")).green().to_string())
+            .chain(
+                diff.removed_dependencies
+                    .iter()
+                    .map(|d| style(format!("-{d}")).red().to_string()),
+            )
+            .collect();
+        println!("    {} {}", style("dependencies:").dim(), changes.join(" "));
+    }
+
+    if let Some(size) = format_size_change(diff.installed_bottle_size, diff.current_bottle_size) {
+        println!("    {} {}", style("bottle size:").dim(), size);
+    }
+
+    if let Some(url) = &diff.release_notes_url {
+        println!(
+            "    {} {}",
+            style("release notes:").dim(),
+            style(url).cyan()
+        );
+    }
+}
+
+fn format_size_change(installed: Option<u64>, current: Option<u64>) -> Option<String> {
+    match (installed, current) {
+        (Some(old), Some(new)) => {
+            let delta = new as i64 - old as i64;
+            let sign = if delta < 0 { "-" } else { "+" };
+            Some(format!(
+                "{} → {} ({}{})",
+                format_bytes(old),
+                format_bytes(new),
+                sign,
+                format_bytes(delta.unsigned_abs())
+            ))
+        }
+        (None, Some(new)) => Some(format_bytes(new)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_size_change_shows_signed_delta() {
+        assert_eq!(
+            format_size_change(Some(2 * 1024 * 1024), Some(3 * 1024 * 1024)).as_deref(),
+            Some("2.0 MB → 3.0 MB (+1.0 MB)")
+        );
+        assert_eq!(
+            format_size_change(Some(2048), Some(1024)).as_deref(),
+            Some("2.0 KB → 1.0 KB (-1.0 KB)")
+        );
+        assert_eq!(
+            format_size_change(None, Some(512)).as_deref(),
+            Some("512 B")
+        );
+        assert_eq!(format_size_change(Some(512), None), None);
+    }
+
+    #[test]
+    fn json_entry_includes_diff_only_when_requested() {
+        let formula: zb_core::Formula = serde_json::from_value(serde_json::json!({
+            "name": "jq",
+            "versions": { "stable": "1.8.0" },
+            "dependencies": ["oniguruma"],
+            "bottle": { "stable": { "files": {} } },
+        }))
+        .unwrap();
+        let item = zb_io::OutdatedFormula {
+            name: "jq".to_string(),
+            installed_version: "1.7.1".to_string(),
+            current_version: "1.8.0".to_string(),
+            formula,
+        };
+
+        let plain = json_entry(&item, true, None);
+        assert_eq!(
+            plain,
+            serde_json::json!({
+                "name": "jq",
+                "installed_version": "1.7.1",
+                "current_version": "1.8.0",
+                "pinned": true,
+            })
+        );
+
+        let diff = zb_io::OutdatedDiff {
+            added_dependencies: vec!["oniguruma".to_string()],
+            removed_dependencies: Vec::new(),
+            dependencies_known: true,
+            installed_bottle_size: Some(1024),
+            current_bottle_size: None,
+            release_notes_url: None,
+        };
+        let detailed = json_entry(&item, false, Some(&diff));
+        assert_eq!(
+            detailed["diff"]["added_dependencies"],
+            serde_json::json!(["oniguruma"])
+        );
+        assert_eq!(detailed["diff"]["installed_bottle_size"], 1024);
+        assert!(detailed["diff"]["current_bottle_size"].is_null());
+    }
+}
@@ -0,0 +1,54 @@
+use console::style;
+
+use crate::utils::normalize_formula_name;
+
+/// Pin `formulas`, or list what is pinned when none are given.
+pub fn pin(installer: &mut zb_io::Installer, formulas: Vec<String>) -> Result<(), zb_core::Error> {
+    if formulas.is_empty() {
+        let pinned = installer.pinned_formulas()?;
+        if pinned.is_empty() {
+            println!("No formulas pinned.");
+        }
+        for name in pinned {
+            let version = installer
+                .get_installed(&name)
+                .map(|keg| keg.version)
+                .unwrap_or_default();
+            println!("{} {}", style(&name).bold(), style(version).dim());
+        }
+        return Ok(());
+    }
+
+    for formula in formulas {
+        let name = normalize_formula_name(&formula)?;
+        if installer.pin(&name)? {
+            println!(
+                "{} Pinned {}",
+                style("==>").cyan().bold(),
+                style(&name).bold()
+            );
+        } else {
+            println!("{name} is already pinned.");
+        }
+    }
+    Ok(())
+}
+
+pub fn unpin(
+    installer: &mut zb_io::Installer,
+    formulas: Vec<String>,
+) -> Result<(), zb_core::Error> {
+    for formula in formulas {
+        let name = normalize_formula_name(&formula)?;
+        if installer.unpin(&name)? {
+            println!(
+                "{} Unpinned {}",
+                style("==>").cyan().bold(),
+                style(&name).bold()
+            );
+        } else {
+            println!("{name} is not pinned.");
+        }
+    }
+    Ok(())
+}
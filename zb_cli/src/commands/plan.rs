@@ -0,0 +1,140 @@
+use console::style;
+use std::path::Path;
+use zb_io::{PlanAction, PlanDiff};
+
+use super::install::run_plan;
+use crate::utils::normalize_formula_name;
+
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    formulas: Vec<String>,
+    build_from_source: bool,
+    json: bool,
+    apply: bool,
+    lock: bool,
+) -> Result<(), zb_core::Error> {
+    let mut names = Vec::with_capacity(formulas.len());
+    for formula in &formulas {
+        let name = normalize_formula_name(formula)?;
+        if name.starts_with("cask:") {
+            return Err(zb_core::Error::InvalidArgument {
+                message: format!("zb plan covers formulas only; install {name} with zb install"),
+            });
+        }
+        names.push(name);
+    }
+
+    let mut plan = installer
+        .plan_with_options(&names, build_from_source)
+        .await?;
+    let diff = installer.diff_plan(&plan)?;
+    if lock {
+        let path = Path::new(zb_io::LOCKFILE_NAME);
+        installer
+            .lockfile(&plan, &names, build_from_source)
+            .write(path)?;
+        if !json {
+            println!(
+                "{} Locked {} formulas in {}",
+                style("==>").cyan().bold(),
+                style(plan.items.len()).green().bold(),
+                style(path.display()).bold()
+            );
+        }
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&diff).expect("plan diffs always serialize")
+        );
+        return Ok(());
+    }
+
+    print_diff(&diff);
+    if !apply || diff.is_empty() {
+        return Ok(());
+    }
+
+    // Execute exactly what was shown: unchanged formulas are left alone.
+    plan.items.retain(|item| {
+        diff.actions
+            .iter()
+            .any(|action| action.name() == item.install_name)
+    });
+    if !plan.items.is_empty() {
+        run_plan(installer, plan, false, &formulas).await?;
+    }
+    for action in &diff.actions {
+        if let PlanAction::Autoremove { name, .. } = action {
+            installer.uninstall_all_versions(name)?;
+            println!("    {} Removed {}", style("✓").green(), style(name).bold());
+        }
+    }
+    println!("{} Plan applied", style("==>").cyan().bold());
+    Ok(())
+}
+
+fn print_diff(diff: &PlanDiff) {
+    if diff.is_empty() {
+        println!("No changes. The installation already matches the plan.");
+        return;
+    }
+
+    println!("{} Planned changes", style("==>").cyan().bold());
+    let (mut installs, mut upgrades, mut removals) = (0, 0, 0);
+    for action in &diff.actions {
+        match action {
+            PlanAction::Install { name, version } => {
+                installs += 1;
+                println!(
+                    "  {} install {} {}",
+                    style("+").green().bold(),
+                    style(name).bold(),
+                    version
+                );
+            }
+            PlanAction::Upgrade { name, from, to } => {
+                upgrades += 1;
+                println!(
+                    "  {} upgrade {} {} → {}",
+                    style("~").yellow().bold(),
+                    style(name).bold(),
+                    style(from).dim(),
+                    to
+                );
+            }
+            PlanAction::Reinstall { name, version } => {
+                upgrades += 1;
+                println!(
+                    "  {} reinstall {} {} {}",
+                    style("~").yellow().bold(),
+                    style(name).bold(),
+                    version,
+                    style("(different build)").dim()
+                );
+            }
+            PlanAction::Autoremove { name, version } => {
+                removals += 1;
+                println!(
+                    "  {} autoremove {} {}",
+                    style("-").red().bold(),
+                    style(name).bold(),
+                    style(version).dim()
+                );
+            }
+        }
+    }
+    if !diff.unchanged.is_empty() {
+        println!(
+            "    {}",
+            style(format!("({} unchanged)", diff.unchanged.len())).dim()
+        );
+    }
+    println!(
+        "Plan: {} to install, {} to change, {} to remove.",
+        style(installs).green().bold(),
+        style(upgrades).yellow().bold(),
+        style(removals).red().bold()
+    );
+}
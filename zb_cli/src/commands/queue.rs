@@ -0,0 +1,79 @@
+use console::style;
+
+use super::install;
+use crate::cli::QueueCommands;
+use crate::utils::normalize_formula_name;
+
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    command: QueueCommands,
+) -> Result<(), zb_core::Error> {
+    match command {
+        QueueCommands::Add { formulas } => {
+            for formula in formulas {
+                let name = normalize_formula_name(&formula)?;
+                if installer.is_installed(&name) {
+                    println!("{name} is already installed.");
+                } else if installer.queue_install(&name)? {
+                    println!(
+                        "{} Queued {}",
+                        style("==>").cyan().bold(),
+                        style(&name).bold()
+                    );
+                } else {
+                    println!("{name} is already queued.");
+                }
+            }
+            Ok(())
+        }
+        QueueCommands::List => {
+            let queued = installer.queued_installs()?;
+            if queued.is_empty() {
+                println!("The install queue is empty.");
+            }
+            for name in queued {
+                println!("{name}");
+            }
+            Ok(())
+        }
+        QueueCommands::Remove { formulas } => {
+            let mut names = Vec::with_capacity(formulas.len());
+            for formula in formulas {
+                names.push(normalize_formula_name(&formula)?);
+            }
+            let removed = installer.dequeue_installs(&names)?;
+            println!(
+                "{} Removed {} from the queue",
+                style("==>").cyan().bold(),
+                style(removed).green().bold()
+            );
+            Ok(())
+        }
+        QueueCommands::Run { no_link } => run(installer, no_link).await,
+    }
+}
+
+/// Install everything queued as one plan, after a single index refresh.
+async fn run(installer: &mut zb_io::Installer, no_link: bool) -> Result<(), zb_core::Error> {
+    let (installed, pending): (Vec<String>, Vec<String>) = installer
+        .queued_installs()?
+        .into_iter()
+        .partition(|name| installer.is_installed(name));
+    installer.dequeue_installs(&installed)?;
+    if pending.is_empty() {
+        println!("The install queue is empty.");
+        return Ok(());
+    }
+
+    println!("{} Updating formula index...", style("==>").cyan().bold());
+    if let Err(e) = installer.update_index().await {
+        eprintln!(
+            "{} Couldn't refresh the formula index: {e}",
+            style("Warning:").yellow().bold()
+        );
+    }
+
+    install::execute(installer, pending.clone(), no_link, false, None, None).await?;
+    installer.dequeue_installs(&pending)?;
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use console::style;
+
+use zb_io::{Installer, ReinstallSource};
+
+use crate::utils::normalize_formula_name;
+
+pub async fn execute(installer: &mut Installer, formulas: &[String]) -> Result<(), zb_core::Error> {
+    let mut names = Vec::with_capacity(formulas.len());
+    for formula in formulas {
+        names.push(normalize_formula_name(formula)?);
+    }
+
+    println!(
+        "{} Reinstalling {}...",
+        style("==>").cyan().bold(),
+        style(names.join(", ")).bold()
+    );
+
+    let mut first_error = None;
+    for name in &names {
+        match installer.reinstall(name).await {
+            Ok(source) => {
+                let from = match source {
+                    ReinstallSource::Store => "from the store",
+                    ReinstallSource::CachedBottle => "from the cached bottle",
+                    ReinstallSource::Download => "from a fresh download",
+                };
+                println!("    {} {} {}", style("✓").green(), name, style(from).dim());
+            }
+            Err(e) => {
+                println!("    {} {}", style("✗").red(), name);
+                eprintln!(
+                    "{} Failed to reinstall {}: {}",
+                    style("Error:").red().bold(),
+                    style(name).bold(),
+                    e
+                );
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,48 @@
+use console::style;
+use std::path::Path;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    prefix: &Path,
+    from: &Path,
+    to: Option<&Path>,
+) -> Result<(), zb_core::Error> {
+    if let Some(to) = to
+        && to != prefix
+    {
+        return Err(zb_core::Error::InvalidArgument {
+            message: format!(
+                "--to {} doesn't match --prefix {}",
+                to.display(),
+                prefix.display()
+            ),
+        });
+    }
+
+    println!(
+        "{} Relocating {} -> {}",
+        style("==>").cyan().bold(),
+        from.display(),
+        prefix.display()
+    );
+    let report = installer.relocate(from)?;
+
+    println!(
+        "{} Re-patched {} kegs, relinked {}",
+        style("==>").cyan().bold(),
+        style(report.rematerialized.len()).green().bold(),
+        style(report.relinked.len()).green().bold()
+    );
+    for name in &report.relinked {
+        println!("    {} {}", style("✓").green(), name);
+    }
+    if !report.needs_reinstall.is_empty() {
+        eprintln!(
+            "{} These kegs still reference {} until reinstalled: {}",
+            style("Warning:").yellow().bold(),
+            from.display(),
+            report.needs_reinstall.join(", ")
+        );
+    }
+    Ok(())
+}
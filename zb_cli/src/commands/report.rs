@@ -0,0 +1,298 @@
+use chrono::{DateTime, Local};
+use console::style;
+use std::path::PathBuf;
+
+use crate::cli::ReportFormat;
+use crate::utils::format_bytes;
+
+/// Upgrade notes included in a report.
+const HISTORY_ENTRIES: usize = 20;
+
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    format: ReportFormat,
+    output: Option<PathBuf>,
+) -> Result<(), zb_core::Error> {
+    let report = installer.environment_report(HISTORY_ENTRIES).await?;
+    let generated = Local::now();
+    let document = match format {
+        ReportFormat::Markdown => render_markdown(&report, generated),
+        ReportFormat::Html => render_html(&report, generated),
+    };
+
+    let Some(path) = output else {
+        print!("{document}");
+        return Ok(());
+    };
+    std::fs::write(&path, document).map_err(|e| zb_core::Error::FileError {
+        message: format!("failed to write {}: {e}", path.display()),
+    })?;
+    println!(
+        "{} Wrote report for {} formulas to {}",
+        style("==>").cyan().bold(),
+        style(report.formulas.len()).green().bold(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// One table row per formula, in the order the columns are rendered.
+fn rows(report: &zb_io::EnvironmentReport) -> Vec<[String; 7]> {
+    report
+        .formulas
+        .iter()
+        .map(|formula| {
+            let status = match &formula.current_version {
+                Some(current) => format!("outdated → {current}"),
+                None if report.outdated_known => "up to date".to_string(),
+                None => "unknown".to_string(),
+            };
+            [
+                formula.name.clone(),
+                formula.version.clone(),
+                formula.license.clone().unwrap_or_else(|| "—".to_string()),
+                formula
+                    .size
+                    .map(format_bytes)
+                    .unwrap_or_else(|| "—".to_string()),
+                format_date(formula.installed_at),
+                status,
+                formula.source.clone().unwrap_or_else(|| "—".to_string()),
+            ]
+        })
+        .collect()
+}
+
+const COLUMNS: [&str; 7] = [
+    "Formula",
+    "Version",
+    "License",
+    "Size",
+    "Installed",
+    "Status",
+    "Source",
+];
+
+fn summary(report: &zb_io::EnvironmentReport, generated: DateTime<Local>) -> Vec<(String, String)> {
+    let outdated = report
+        .formulas
+        .iter()
+        .filter(|formula| formula.current_version.is_some())
+        .count();
+    let total = report
+        .formulas
+        .iter()
+        .filter_map(|formula| formula.size)
+        .sum::<u64>();
+    let formulas = if report.outdated_known {
+        format!("{} ({outdated} outdated)", report.formulas.len())
+    } else {
+        format!("{} (outdated status unavailable)", report.formulas.len())
+    };
+    vec![
+        (
+            "Generated".to_string(),
+            generated.format("%Y-%m-%d %H:%M").to_string(),
+        ),
+        (
+            "Platform".to_string(),
+            report
+                .platform
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string()),
+        ),
+        ("Prefix".to_string(), report.prefix.display().to_string()),
+        ("Formulas".to_string(), formulas),
+        ("Disk usage".to_string(), format_bytes(total)),
+    ]
+}
+
+fn render_markdown(report: &zb_io::EnvironmentReport, generated: DateTime<Local>) -> String {
+    let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+    let mut out = String::from("# zerobrew environment\n\n");
+    for (label, value) in summary(report, generated) {
+        out.push_str(&format!("- **{label}:** {}\n", cell(&value)));
+    }
+
+    out.push_str("\n## Formulas\n\n");
+    if report.formulas.is_empty() {
+        out.push_str("No formulas installed.\n");
+    } else {
+        out.push_str(&format!("| {} |\n", COLUMNS.join(" | ")));
+        out.push_str(&format!("|{}\n", "---|".repeat(COLUMNS.len())));
+        for row in rows(report) {
+            let cells: Vec<String> = row.iter().map(|text| cell(text)).collect();
+            out.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+    }
+
+    let notes: Vec<_> = report
+        .formulas
+        .iter()
+        .filter_map(|formula| Some((&formula.name, formula.note.as_ref()?)))
+        .collect();
+    if !notes.is_empty() {
+        out.push_str("\n## Notes\n\n");
+        for (name, note) in notes {
+            out.push_str(&format!("- **{name}:** {}\n", cell(note)));
+        }
+    }
+
+    if !report.history.is_empty() {
+        out.push_str("\n## Recent upgrades\n\n");
+        for record in &report.history {
+            out.push_str(&format!(
+                "- {} **{}** {} → {}: {}\n",
+                format_date(record.recorded_at),
+                record.name,
+                record.from_version,
+                record.to_version,
+                cell(&record.change)
+            ));
+        }
+    }
+    out
+}
+
+fn render_html(report: &zb_io::EnvironmentReport, generated: DateTime<Local>) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>zerobrew environment</title>\n<style>\n\
+         body { font-family: system-ui, sans-serif; margin: 2rem; }\n\
+         table { border-collapse: collapse; }\n\
+         th, td { border: 1px solid #ccc; padding: 0.25rem 0.5rem; text-align: left; }\n\
+         .outdated { color: #b35900; }\n\
+         </style>\n</head>\n<body>\n<h1>zerobrew environment</h1>\n<ul>\n",
+    );
+    for (label, value) in summary(report, generated) {
+        out.push_str(&format!(
+            "<li><strong>{label}:</strong> {}</li>\n",
+            escape_html(&value)
+        ));
+    }
+    out.push_str("</ul>\n<h2>Formulas</h2>\n");
+
+    if report.formulas.is_empty() {
+        out.push_str("<p>No formulas installed.</p>\n");
+    } else {
+        out.push_str("<table>\n<tr>");
+        for column in COLUMNS {
+            out.push_str(&format!("<th>{column}</th>"));
+        }
+        out.push_str("</tr>\n");
+        for (formula, row) in report.formulas.iter().zip(rows(report)) {
+            if formula.current_version.is_some() {
+                out.push_str("<tr class=\"outdated\">");
+            } else {
+                out.push_str("<tr>");
+            }
+            for text in row {
+                out.push_str(&format!("<td>{}</td>", escape_html(&text)));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n");
+    }
+
+    let notes: Vec<_> = report
+        .formulas
+        .iter()
+        .filter_map(|formula| Some((&formula.name, formula.note.as_ref()?)))
+        .collect();
+    if !notes.is_empty() {
+        out.push_str("<h2>Notes</h2>\n<ul>\n");
+        for (name, note) in notes {
+            out.push_str(&format!(
+                "<li><strong>{}:</strong> {}</li>\n",
+                escape_html(name),
+                escape_html(note)
+            ));
+        }
+        out.push_str("</ul>\n");
+    }
+
+    if !report.history.is_empty() {
+        out.push_str("<h2>Recent upgrades</h2>\n<ul>\n");
+        for record in &report.history {
+            out.push_str(&format!(
+                "<li>{} <strong>{}</strong> {} → {}: {}</li>\n",
+                format_date(record.recorded_at),
+                escape_html(&record.name),
+                escape_html(&record.from_version),
+                escape_html(&record.to_version),
+                escape_html(&record.change)
+            ));
+        }
+        out.push_str("</ul>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_date(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|dt| dt.with_timezone(&Local).format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> zb_io::EnvironmentReport {
+        zb_io::EnvironmentReport {
+            platform: Some("x86_64_linux".to_string()),
+            prefix: PathBuf::from("/opt/zerobrew/prefix"),
+            formulas: vec![
+                zb_io::ReportedFormula {
+                    name: "jq".to_string(),
+                    version: "1.7.1".to_string(),
+                    license: Some("MIT".to_string()),
+                    size: Some(1536),
+                    note: Some("used by <deploy> | scripts".to_string()),
+                    current_version: Some("1.8.0".to_string()),
+                    ..Default::default()
+                },
+                zb_io::ReportedFormula {
+                    name: "wget".to_string(),
+                    version: "1.25.0".to_string(),
+                    ..Default::default()
+                },
+            ],
+            outdated_known: true,
+            history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn markdown_lists_formulas_with_status_and_escapes_cells() {
+        let markdown = render_markdown(&report(), Local::now());
+        assert!(markdown.contains("- **Formulas:** 2 (1 outdated)\n"));
+        assert!(markdown.contains("| jq | 1.7.1 | MIT | 1.5 KB |"));
+        assert!(markdown.contains("| outdated → 1.8.0 | — |"));
+        assert!(markdown.contains("| wget | 1.25.0 | — | — |"));
+        assert!(markdown.contains("| up to date | — |"));
+        assert!(markdown.contains("- **jq:** used by <deploy> \\| scripts\n"));
+        assert!(!markdown.contains("Recent upgrades"));
+    }
+
+    #[test]
+    fn html_escapes_text_and_marks_outdated_rows() {
+        let mut report = report();
+        report.outdated_known = false;
+        report.formulas[0].current_version = None;
+        let html = render_html(&report, Local::now());
+        assert!(html.contains("used by &lt;deploy&gt; | scripts"));
+        assert!(!html.contains("class=\"outdated\""));
+        assert!(html.contains("<td>unknown</td>"));
+        assert!(html.contains("2 (outdated status unavailable)"));
+        assert!(html.ends_with("</html>\n"));
+    }
+}
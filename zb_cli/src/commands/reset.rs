@@ -1,9 +1,9 @@
 use console::style;
-use std::io::{self, Write};
 use std::path::Path;
 use std::process::Command;
 
 use crate::init::{InitError, run_init};
+use crate::utils::{confirm, interactive};
 
 pub fn execute(root: &Path, prefix: &Path, yes: bool) -> Result<(), zb_core::Error> {
     if !root.exists() && !prefix.exists() {
@@ -18,12 +18,7 @@ pub fn execute(root: &Path, prefix: &Path, yes: bool) -> Result<(), zb_core::Err
         );
         println!("      • {}", root.display());
         println!("      • {}", prefix.display());
-        print!("Continue? [y/N] ");
-        io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        if !input.trim().eq_ignore_ascii_case("y") {
+        if !confirm("Continue?")? {
             println!("Aborted.");
             return Ok(());
         }
@@ -62,7 +57,7 @@ pub fn execute(root: &Path, prefix: &Path, yes: bool) -> Result<(), zb_core::Err
 
         // Only fall back to sudo if we couldn't clear contents AND stdout is a terminal
         if failed {
-            if !std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+            if !interactive() {
                 eprintln!(
                     "{} Failed to clear {} (permission denied, non-interactive mode)",
                     style("error:").red().bold(),
@@ -2,41 +2,37 @@ use console::style;
 use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use zb_core::formula_token;
+use zb_core::{formula_token, version_matches};
 use zb_io::Installer;
 
 use crate::utils::normalize_formula_name;
 
 /// Prepare a package for execution by ensuring it's installed
 /// Returns the path to the executable
+///
+/// `formula@version` runs that version when it is installed or retained,
+/// or when it is the one the formula ships now; a formula whose own name
+/// contains `@` (`python@3.12`) is still found by that name.
 pub async fn prepare_execution(
     installer: &mut Installer,
     formula: &str,
 ) -> Result<PathBuf, zb_core::Error> {
     let normalized = normalize_formula_name(formula)?;
 
-    let was_installed = installer.is_installed(&normalized);
-
-    if !was_installed {
-        println!(
-            "{} Installing {} temporarily...",
-            style("==>").cyan().bold(),
-            style(&normalized).green()
-        );
-
-        let plan = installer.plan(std::slice::from_ref(&normalized)).await?;
-        installer.execute(plan, false).await?;
-    }
-
-    let installed =
-        installer
-            .get_installed(&normalized)
-            .ok_or_else(|| zb_core::Error::NotInstalled {
-                name: normalized.clone(),
-            })?;
+    let (name, keg_path) = match normalized.rsplit_once('@') {
+        Some((name, version)) if !installer.is_installed(&normalized) => {
+            match installer.version_keg(name, version)? {
+                Some((_, keg_path)) => (name.to_string(), keg_path),
+                None => resolve_version(installer, &normalized, name, version).await?,
+            }
+        }
+        _ => (
+            normalized.clone(),
+            ensure_installed(installer, &normalized).await?,
+        ),
+    };
 
-    let executable_name = formula_token(&installed.name);
-    let keg_path = installer.keg_path(executable_name, &installed.version);
+    let executable_name = formula_token(&name);
     let bin_path = keg_path.join("bin").join(executable_name);
 
     if !bin_path.exists() {
@@ -51,6 +47,63 @@ pub async fn prepare_execution(
     Ok(bin_path)
 }
 
+/// The keg for `name@version` when nothing local has it: a formula really
+/// named `spec`, or `name` when the version it ships now is the one asked for.
+async fn resolve_version(
+    installer: &mut Installer,
+    spec: &str,
+    name: &str,
+    version: &str,
+) -> Result<(String, PathBuf), zb_core::Error> {
+    match installer.fetch_formula(spec).await {
+        Ok(_) => return Ok((spec.to_string(), ensure_installed(installer, spec).await?)),
+        Err(zb_core::Error::MissingFormula { .. }) => {}
+        Err(e) => return Err(e),
+    }
+
+    let current = installer.fetch_formula(name).await?.effective_version();
+    if !version_matches(version, &current) {
+        return Err(zb_core::Error::VersionUnavailable {
+            name: name.to_string(),
+            version: version.to_string(),
+            current,
+        });
+    }
+    if let Some(installed) = installer.get_installed(name) {
+        return Err(zb_core::Error::InvalidArgument {
+            message: format!(
+                "{name} {} is installed; run `zb upgrade {name}` to get {current}",
+                installed.version
+            ),
+        });
+    }
+    Ok((name.to_string(), ensure_installed(installer, name).await?))
+}
+
+/// The keg of `name`, installing it unlinked first if needed.
+async fn ensure_installed(
+    installer: &mut Installer,
+    name: &str,
+) -> Result<PathBuf, zb_core::Error> {
+    if !installer.is_installed(name) {
+        println!(
+            "{} Installing {} temporarily...",
+            style("==>").cyan().bold(),
+            style(name).green()
+        );
+
+        let plan = installer.plan(&[name.to_string()]).await?;
+        installer.execute(plan, false).await?;
+    }
+
+    let installed = installer
+        .get_installed(name)
+        .ok_or_else(|| zb_core::Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+    Ok(installer.keg_path(formula_token(&installed.name), &installed.version))
+}
+
 pub async fn execute(
     installer: &mut Installer,
     formula: String,
@@ -339,6 +392,84 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn run_selects_an_installed_version() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("versioned");
+        let bottle_sha = sha256_hex(&bottle);
+
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{
+                "name": "versioned",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{}": {{
+                                "url": "{}/bottles/versioned.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            tag,
+            mock_server.uri(),
+            bottle_sha
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/versioned.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/bottles/versioned.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(mock_server.uri()),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+        );
+
+        // The version it ships now is installed temporarily.
+        let bin_path = prepare_execution(&mut installer, "versioned@1.0.0")
+            .await
+            .unwrap();
+        assert!(bin_path.ends_with("versioned/1.0.0/bin/versioned"));
+        assert!(installer.is_installed("versioned"));
+
+        // Trailing components can be left out.
+        let bin_path = prepare_execution(&mut installer, "versioned@1.0")
+            .await
+            .unwrap();
+        assert!(bin_path.ends_with("versioned/1.0.0/bin/versioned"));
+
+        let err = prepare_execution(&mut installer, "versioned@0.9")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            zb_core::Error::VersionUnavailable { ref current, .. } if current == "1.0.0"
+        ));
+    }
+
     #[tokio::test]
     async fn run_fails_for_missing_formula() {
         let mock_server = MockServer::start().await;
@@ -0,0 +1,36 @@
+use console::style;
+
+pub fn execute(
+    installer: &zb_io::Installer,
+    query: &str,
+    limit: usize,
+) -> Result<(), zb_core::Error> {
+    let hits = installer.search(query, limit)?;
+
+    if hits.is_empty() {
+        println!("No formulas match \"{query}\".");
+        return Ok(());
+    }
+    if hits.iter().all(|hit| hit.fuzzy) {
+        println!("No exact matches for \"{query}\". Did you mean:");
+    }
+
+    for hit in &hits {
+        let installed = if installer.is_installed(&hit.name) {
+            format!(" {}", style("✓").green())
+        } else {
+            String::new()
+        };
+        println!(
+            "{} {}{}",
+            style(&hit.name).bold(),
+            style(&hit.version).dim(),
+            installed
+        );
+        if let Some(desc) = &hit.desc {
+            println!("    {}", style(desc).dim());
+        }
+    }
+
+    Ok(())
+}
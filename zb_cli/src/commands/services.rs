@@ -0,0 +1,119 @@
+use console::style;
+use zb_io::ServiceManager;
+
+use crate::cli::ServicesCommands;
+use crate::utils::normalize_formula_name;
+
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    command: ServicesCommands,
+) -> Result<(), zb_core::Error> {
+    let services =
+        ServiceManager::for_current_user().ok_or_else(|| zb_core::Error::ExecutionError {
+            message: "HOME is not set, so there is no service directory".to_string(),
+        })?;
+
+    match command {
+        ServicesCommands::List => {
+            list(&services);
+            Ok(())
+        }
+        ServicesCommands::Start { formula } => {
+            let name = normalize_formula_name(&formula)?;
+            if let Some(unit) = services.find(&name)
+                && services.is_running(&unit)
+            {
+                println!(
+                    "{} is already started; use {} to restart it.",
+                    style(&name).bold(),
+                    style(format!("zb services restart {name}")).bold()
+                );
+                return Ok(());
+            }
+            start(installer, &services, &name).await
+        }
+        ServicesCommands::Stop { formula } => {
+            let name = normalize_formula_name(&formula)?;
+            if !stop(&services, &name)? {
+                println!("{} is not started.", style(&name).bold());
+            }
+            Ok(())
+        }
+        ServicesCommands::Restart { formula } => {
+            let name = normalize_formula_name(&formula)?;
+            stop(&services, &name)?;
+            start(installer, &services, &name).await
+        }
+    }
+}
+
+fn list(services: &ServiceManager) {
+    let units = services.list();
+    if units.is_empty() {
+        println!("No services started.");
+        return;
+    }
+    let width = units
+        .iter()
+        .map(|unit| unit.formula.len())
+        .max()
+        .unwrap_or(0)
+        .max("Name".len());
+    println!(
+        "{}",
+        style(format!("{:<width$}  {:<7}  File", "Name", "Status")).bold()
+    );
+    for unit in units {
+        let status = if services.is_running(&unit) {
+            style(format!("{:<7}", "started")).green()
+        } else {
+            style(format!("{:<7}", "stopped")).yellow()
+        };
+        println!(
+            "{:<width$}  {}  {}",
+            unit.formula,
+            status,
+            style(unit.path.display()).dim()
+        );
+    }
+}
+
+async fn start(
+    installer: &mut zb_io::Installer,
+    services: &ServiceManager,
+    name: &str,
+) -> Result<(), zb_core::Error> {
+    let definition = installer
+        .write_service_definition(name, services.kind())
+        .await?;
+    let unit = services.start(name, &definition)?;
+    println!(
+        "{} Started {} {}",
+        style("==>").cyan().bold(),
+        style(name).bold(),
+        style(format!("({})", unit.label)).dim()
+    );
+    Ok(())
+}
+
+/// Stop `name`'s service and unregister it. Returns `false` when none was
+/// registered.
+fn stop(services: &ServiceManager, name: &str) -> Result<bool, zb_core::Error> {
+    let Some(unit) = services.find(name) else {
+        return Ok(false);
+    };
+    // A unit that was never loaded fails to stop, but still has to go.
+    if let Err(e) = services.stop(&unit)
+        && services.is_running(&unit)
+    {
+        return Err(e);
+    }
+    services.remove(&unit)?;
+    println!(
+        "{} Stopped {} {}",
+        style("==>").cyan().bold(),
+        style(name).bold(),
+        style(format!("({})", unit.label)).dim()
+    );
+    Ok(true)
+}
@@ -0,0 +1,152 @@
+use console::style;
+use std::collections::BTreeSet;
+
+use super::{install, uninstall};
+use crate::cli::SetCommands;
+use crate::utils::normalize_formula_name;
+
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    command: SetCommands,
+) -> Result<(), zb_core::Error> {
+    match command {
+        SetCommands::List => list(installer),
+        SetCommands::Show { name } => {
+            for formula in installer.formula_set(&name)? {
+                print_member(installer, &formula);
+            }
+            Ok(())
+        }
+        SetCommands::Create {
+            name,
+            formulas,
+            force,
+        } => create(installer, &name, formulas, force),
+        SetCommands::Delete { name } => {
+            installer.delete_set(&name)?;
+            println!("{} Deleted set {}", style("==>").cyan().bold(), name);
+            Ok(())
+        }
+        SetCommands::Install { name, no_link } => {
+            let missing: Vec<String> = installer
+                .formula_set(&name)?
+                .into_iter()
+                .filter(|formula| !installer.is_installed(formula))
+                .collect();
+            if missing.is_empty() {
+                println!("Everything in set {name} is already installed.");
+                return Ok(());
+            }
+            install::execute(installer, missing, no_link, false, None, None).await
+        }
+        SetCommands::Upgrade { name } => {
+            let members: BTreeSet<String> = installer.formula_set(&name)?.into_iter().collect();
+            let outdated: Vec<String> = installer
+                .outdated()
+                .await?
+                .into_iter()
+                .map(|item| item.name)
+                .filter(|formula| members.contains(formula) && !installer.is_pinned(formula))
+                .collect();
+            if outdated.is_empty() {
+                println!("Everything in set {name} is up to date.");
+                return Ok(());
+            }
+            install::execute(installer, outdated, false, false, None, None).await
+        }
+        SetCommands::Uninstall { name, yes } => {
+            let formulas = removable_members(installer, &name)?;
+            if formulas.is_empty() {
+                println!("Nothing from set {name} is installed.");
+                return Ok(());
+            }
+            uninstall::execute(installer, formulas, false, yes, None)
+        }
+    }
+}
+
+fn list(installer: &zb_io::Installer) -> Result<(), zb_core::Error> {
+    let sets = installer.formula_sets()?;
+    if sets.is_empty() {
+        println!("No sets defined.");
+        return Ok(());
+    }
+
+    for (name, formulas) in sets {
+        println!("{} {}", style(name).bold(), style(formulas.join(" ")).dim());
+    }
+    Ok(())
+}
+
+fn create(
+    installer: &mut zb_io::Installer,
+    name: &str,
+    formulas: Vec<String>,
+    force: bool,
+) -> Result<(), zb_core::Error> {
+    if !force && installer.formula_set(name).is_ok() {
+        return Err(zb_core::Error::InvalidArgument {
+            message: format!("set '{name}' already exists (use --force to replace it)"),
+        });
+    }
+
+    let mut members = Vec::with_capacity(formulas.len());
+    for formula in formulas {
+        let formula = normalize_formula_name(&formula)?;
+        if !members.contains(&formula) {
+            members.push(formula);
+        }
+    }
+    installer.save_set(name, &members)?;
+
+    println!(
+        "{} Saved set {} ({} formulas)",
+        style("==>").cyan().bold(),
+        style(name).bold(),
+        style(members.len()).green().bold()
+    );
+    Ok(())
+}
+
+fn print_member(installer: &zb_io::Installer, formula: &str) {
+    match installer.get_installed(formula) {
+        Some(keg) => println!("{} {}", style(formula).bold(), style(&keg.version).dim()),
+        None => println!(
+            "{} {}",
+            style(formula).bold(),
+            style("(not installed)").dim()
+        ),
+    }
+}
+
+/// Installed members of the set, minus any that another set still claims.
+fn removable_members(
+    installer: &zb_io::Installer,
+    name: &str,
+) -> Result<Vec<String>, zb_core::Error> {
+    let members = installer.formula_set(name)?;
+    let sets = installer.formula_sets()?;
+
+    let mut removable = Vec::new();
+    for formula in members {
+        if !installer.is_installed(&formula) {
+            continue;
+        }
+        let others: Vec<&str> = sets
+            .iter()
+            .filter(|(other, formulas)| other != name && formulas.contains(&formula))
+            .map(|(other, _)| other.as_str())
+            .collect();
+        if others.is_empty() {
+            removable.push(formula);
+        } else {
+            eprintln!(
+                "{} Keeping {}: also in set {}",
+                style("Note:").yellow().bold(),
+                style(&formula).bold(),
+                others.join(", ")
+            );
+        }
+    }
+    Ok(removable)
+}
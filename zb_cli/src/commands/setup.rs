@@ -0,0 +1,225 @@
+use console::style;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::init::{InitError, ShellIntegration, run_init_with};
+use crate::utils::interactive;
+
+use super::migrate;
+
+/// Answers collected by the wizard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Choices {
+    root: PathBuf,
+    prefix: PathBuf,
+    shell: Option<ShellIntegration>,
+    cache_max_age_days: u64,
+    cache_max_size_gb: Option<u64>,
+    analytics: bool,
+}
+
+pub async fn execute(root: &Path, prefix: &Path, concurrency: usize) -> Result<(), zb_core::Error> {
+    if !interactive() {
+        return Err(zb_core::Error::InvalidArgument {
+            message: "zb setup needs an interactive terminal; use `zb init` instead".to_string(),
+        });
+    }
+
+    println!("{} Setting up zerobrew", style("==>").cyan().bold());
+    println!(
+        "    {}",
+        style("Press Enter to keep the default shown in brackets.").dim()
+    );
+    println!();
+
+    let root = PathBuf::from(ask(
+        "Root (store, database, cache)",
+        &root.display().to_string(),
+    ));
+    let prefix = PathBuf::from(ask(
+        "Prefix (where formulas are linked)",
+        &prefix.display().to_string(),
+    ));
+
+    println!();
+    println!("Shell integration:");
+    println!("    1) managed block with the full environment in your shell profile");
+    println!("    2) managed block that runs `zb shellenv`, so it follows zb upgrades");
+    println!("    3) none, I'll set up PATH myself");
+    let shell = loop {
+        match ask("Choice", "1").as_str() {
+            "1" => break Some(ShellIntegration::ManagedBlock),
+            "2" => break Some(ShellIntegration::Shellenv),
+            "3" => break None,
+            _ => println!("    Enter 1, 2 or 3."),
+        }
+    };
+
+    println!();
+    let defaults = zb_io::CacheConfig::default();
+    let cache_max_age_days = ask_parsed(
+        "Prune downloads older than this many days",
+        defaults.max_age_days,
+    );
+    let cache_max_size_gb = loop {
+        let answer = ask(
+            "Cap the download cache at this many GB (blank for no cap)",
+            "",
+        );
+        if answer.is_empty() {
+            break None;
+        }
+        match answer.parse() {
+            Ok(gb) => break Some(gb),
+            Err(_) => println!("    Enter a whole number of GB."),
+        }
+    };
+
+    println!();
+    let analytics = confirm(
+        "Record download and cache statistics for `zb stats`? They stay on this machine.",
+        true,
+    );
+
+    let choices = Choices {
+        root,
+        prefix,
+        shell,
+        cache_max_age_days,
+        cache_max_size_gb,
+        analytics,
+    };
+
+    println!();
+    run_init_with(&choices.root, &choices.prefix, choices.shell).map_err(|e| match e {
+        InitError::Message(msg) => zb_core::Error::StoreCorruption { message: msg },
+    })?;
+    write_config(&choices.root.join("config.toml"), &render_config(&choices))?;
+
+    if brew_available() {
+        println!();
+        if confirm("Import the formulas installed by Homebrew now?", false) {
+            let mut installer =
+                zb_io::create_installer(&choices.root, &choices.prefix, concurrency)?;
+            migrate::execute(&mut installer, false, false, false, None).await?;
+        }
+    }
+
+    println!();
+    println!("{} zerobrew is set up.", style("==>").cyan().bold());
+    if choices.shell.is_some() {
+        println!(
+            "    {}",
+            style("Open a new terminal (or `exec $SHELL`) to pick up the environment.").dim()
+        );
+    }
+    Ok(())
+}
+
+/// The settings chosen in the wizard as `config.toml` contents.
+fn render_config(choices: &Choices) -> String {
+    let mut config = String::from("# Written by `zb setup`.\n\n[cache]\n");
+    config.push_str(&format!("max_age_days = {}\n", choices.cache_max_age_days));
+    if let Some(gb) = choices.cache_max_size_gb {
+        config.push_str(&format!("max_size_gb = {gb}\n"));
+    }
+    config.push_str(&format!("\n[analytics]\nenabled = {}\n", choices.analytics));
+    config
+}
+
+/// Write `contents` to `path`, unless a config is already there: the
+/// settings are then printed to merge by hand.
+fn write_config(path: &Path, contents: &str) -> Result<(), zb_core::Error> {
+    if path.exists() {
+        println!(
+            "{} {} already exists; add these settings to it yourself:",
+            style("Note:").yellow().bold(),
+            path.display()
+        );
+        println!();
+        println!("{contents}");
+        return Ok(());
+    }
+
+    std::fs::write(path, contents).map_err(|e| zb_core::Error::FileError {
+        message: format!("failed to write {}: {e}", path.display()),
+    })?;
+    println!(
+        "    {} Wrote settings to {}",
+        style("✓").green(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn brew_available() -> bool {
+    Command::new("brew")
+        .arg("--prefix")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Ask `question`, returning the trimmed answer or `default` when it's empty.
+fn ask(question: &str, default: &str) -> String {
+    if default.is_empty() {
+        print!("{question}: ");
+    } else {
+        print!("{question} [{default}]: ");
+    }
+    io::stdout().flush().unwrap();
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    match input.trim() {
+        "" => default.to_string(),
+        answer => answer.to_string(),
+    }
+}
+
+fn ask_parsed(question: &str, default: u64) -> u64 {
+    loop {
+        match ask(question, &default.to_string()).parse() {
+            Ok(value) => return value,
+            Err(_) => println!("    Enter a whole number."),
+        }
+    }
+}
+
+fn confirm(question: &str, default: bool) -> bool {
+    let hint = if default { "Y/n" } else { "y/N" };
+    print!("{question} [{hint}] ");
+    io::stdout().flush().unwrap();
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    match input.trim() {
+        "" => default,
+        answer => answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendered_config_round_trips_through_the_parser() {
+        let mut choices = Choices {
+            root: PathBuf::from("/opt/zerobrew"),
+            prefix: PathBuf::from("/opt/zerobrew/prefix"),
+            shell: Some(ShellIntegration::Shellenv),
+            cache_max_age_days: 14,
+            cache_max_size_gb: Some(20),
+            analytics: false,
+        };
+        let config = zb_io::Config::parse(&render_config(&choices)).unwrap();
+        assert_eq!(config.cache.max_age_days, 14);
+        assert_eq!(config.cache.max_size_gb, Some(20));
+        assert!(!config.analytics.enabled);
+
+        choices.cache_max_size_gb = None;
+        let config = zb_io::Config::parse(&render_config(&choices)).unwrap();
+        assert_eq!(config.cache.max_size_gb, None);
+    }
+}
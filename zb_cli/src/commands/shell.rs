@@ -0,0 +1,161 @@
+use console::style;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use zb_core::formula_token;
+use zb_io::{Installer, Linker};
+
+use crate::commands::exec::exec_env;
+use crate::utils::normalize_formula_name;
+
+/// Throwaway prefix under `<root>/shells` that only has the requested kegs linked.
+///
+/// The kegs themselves stay in the shared cellar; only the symlink farm is
+/// per-shell, and it is removed when the guard is dropped.
+struct Overlay {
+    path: PathBuf,
+}
+
+impl Overlay {
+    fn create(root: &Path) -> Result<Self, zb_core::Error> {
+        let path = root.join("shells").join(format!(
+            "{}-{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp()
+        ));
+        fs::create_dir_all(&path).map_err(|e| zb_core::Error::FileError {
+            message: format!("failed to create shell prefix {}: {e}", path.display()),
+        })?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for Overlay {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+pub async fn execute(
+    installer: &mut Installer,
+    root: &Path,
+    prefix: &Path,
+    formulas: Vec<String>,
+) -> Result<(), zb_core::Error> {
+    let mut names = Vec::with_capacity(formulas.len());
+    for formula in &formulas {
+        let name = normalize_formula_name(formula)?;
+        if name.starts_with("cask:") {
+            return Err(zb_core::Error::InvalidArgument {
+                message: format!("casks cannot be used in a shell: {name}"),
+            });
+        }
+        names.push(name);
+    }
+
+    let missing: Vec<String> = names
+        .iter()
+        .filter(|name| !installer.is_installed(name))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        println!(
+            "{} Installing {} without linking...",
+            style("==>").cyan().bold(),
+            style(missing.join(", ")).green()
+        );
+        let plan = installer.plan(&missing).await?;
+        installer.execute(plan, false).await?;
+    }
+
+    let overlay = Overlay::create(root)?;
+    let linker = Linker::new(&overlay.path).map_err(|e| zb_core::Error::FileError {
+        message: format!("failed to prepare shell prefix: {e}"),
+    })?;
+    for name in &names {
+        let installed = installer
+            .get_installed(name)
+            .ok_or_else(|| zb_core::Error::NotInstalled { name: name.clone() })?;
+        let keg_path = installer.keg_path(formula_token(&installed.name), &installed.version);
+        linker.link_keg(&keg_path)?;
+    }
+
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+    let mut cmd = Command::new(&shell);
+    for (key, value) in exec_env(&overlay.path, &[], |key| {
+        let value = std::env::var(key).ok()?;
+        Some(without_prefix_entries(&value, prefix))
+    }) {
+        // Commands run inside the shell still manage the real installation.
+        if key != "ZEROBREW_PREFIX" {
+            cmd.env(key, value);
+        }
+    }
+    cmd.env("ZEROBREW_SHELL", &overlay.path);
+
+    println!(
+        "{} Entering shell with {} (exit to leave)",
+        style("==>").cyan().bold(),
+        style(names.join(", ")).green()
+    );
+
+    let mut child = cmd.spawn().map_err(|e| zb_core::Error::ExecutionError {
+        message: format!("failed to start '{shell}': {e}"),
+    })?;
+
+    // Ctrl-C belongs to the subshell; if it also killed zb the overlay would leak.
+    let previous = unsafe { libc::signal(libc::SIGINT, libc::SIG_IGN) };
+    let status = child.wait();
+    unsafe { libc::signal(libc::SIGINT, previous) };
+
+    let status = status.map_err(|e| zb_core::Error::ExecutionError {
+        message: format!("failed to wait for '{shell}': {e}"),
+    })?;
+
+    println!("{} Left shell", style("==>").cyan().bold());
+    drop(overlay);
+
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// Drop entries that point into the main prefix so only the overlay's formulas
+/// are visible inside the shell.
+fn without_prefix_entries(value: &str, prefix: &Path) -> String {
+    value
+        .split(':')
+        .filter(|entry| !entry.is_empty() && !Path::new(entry).starts_with(prefix))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn without_prefix_entries_hides_main_prefix() {
+        assert_eq!(
+            without_prefix_entries(
+                "/opt/zerobrew/prefix/bin:/usr/bin:/opt/zerobrew/prefix/sbin:/bin",
+                Path::new("/opt/zerobrew/prefix")
+            ),
+            "/usr/bin:/bin"
+        );
+    }
+
+    #[test]
+    fn overlay_is_removed_on_drop() {
+        let tmp = tempfile::tempdir().unwrap();
+        let overlay = Overlay::create(tmp.path()).unwrap();
+        let path = overlay.path.clone();
+        Linker::new(&path).unwrap();
+        assert!(path.join("bin").is_dir());
+
+        drop(overlay);
+        assert!(!path.exists());
+    }
+}
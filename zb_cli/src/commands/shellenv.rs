@@ -0,0 +1,11 @@
+use std::path::Path;
+
+use crate::init::{InitError, shellenv};
+
+pub fn execute(root: &Path, prefix: &Path) -> Result<(), zb_core::Error> {
+    let env = shellenv(root, prefix).map_err(|e| match e {
+        InitError::Message(msg) => zb_core::Error::StoreCorruption { message: msg },
+    })?;
+    println!("{}", env.trim());
+    Ok(())
+}
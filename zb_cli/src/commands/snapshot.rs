@@ -0,0 +1,129 @@
+use console::style;
+
+use crate::cli::SnapshotCommands;
+use crate::utils::confirm;
+use zb_io::{SnapshotInfo, SnapshotStore};
+
+pub fn execute(store: &SnapshotStore, command: SnapshotCommands) -> Result<(), zb_core::Error> {
+    match command {
+        SnapshotCommands::List => list(store),
+        SnapshotCommands::Create { label } => {
+            create(store, &label)?;
+            Ok(())
+        }
+        SnapshotCommands::Restore { id, yes } => restore(store, id, yes),
+        SnapshotCommands::Remove { id } => {
+            store.remove(&id)?;
+            println!("{} Removed snapshot {}", style("==>").cyan().bold(), id);
+            Ok(())
+        }
+    }
+}
+
+/// Take a snapshot and tell the user how to get back to it.
+pub fn create(store: &SnapshotStore, label: &str) -> Result<SnapshotInfo, zb_core::Error> {
+    let snapshot = store.create(label)?;
+    println!(
+        "{} Snapshot {} saved ({} links)",
+        style("==>").cyan().bold(),
+        style(&snapshot.id).bold(),
+        snapshot.links
+    );
+    Ok(snapshot)
+}
+
+/// Point the user at the snapshot taken before an operation that did not fully succeed.
+pub fn print_restore_hint(snapshot: &SnapshotInfo) {
+    eprintln!(
+        "{} Restore the previous state with: {}",
+        style("Note:").yellow().bold(),
+        style(format!("zb snapshot restore {}", snapshot.id)).cyan()
+    );
+}
+
+fn list(store: &SnapshotStore) -> Result<(), zb_core::Error> {
+    let snapshots = store.list()?;
+    if snapshots.is_empty() {
+        println!("No snapshots.");
+        return Ok(());
+    }
+
+    for snapshot in snapshots {
+        let when = chrono::DateTime::from_timestamp(snapshot.created_at, 0)
+            .map(|t| {
+                t.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        println!(
+            "{:<14} {}  {:<10} {}",
+            style(&snapshot.id).bold(),
+            style(when).dim(),
+            snapshot.label,
+            style(format!("{} links", snapshot.links)).dim()
+        );
+    }
+    Ok(())
+}
+
+fn restore(store: &SnapshotStore, id: Option<String>, yes: bool) -> Result<(), zb_core::Error> {
+    let snapshot = match id {
+        Some(id) => store
+            .list()?
+            .into_iter()
+            .find(|s| s.id == id)
+            .ok_or_else(|| zb_core::Error::InvalidArgument {
+                message: format!("no snapshot with id '{id}'"),
+            })?,
+        None => store
+            .latest()?
+            .ok_or_else(|| zb_core::Error::InvalidArgument {
+                message: "no snapshots to restore".to_string(),
+            })?,
+    };
+
+    if !yes
+        && !confirm(&format!(
+            "Restore prefix links and database from snapshot {} ({})?",
+            style(&snapshot.id).bold(),
+            snapshot.label
+        ))?
+    {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let summary = store.restore(&snapshot.id)?;
+    println!(
+        "{} Restored snapshot {}: {} links restored, {} removed",
+        style("==>").cyan().bold(),
+        style(&snapshot.id).bold(),
+        style(summary.restored).green(),
+        summary.removed
+    );
+
+    if !summary.skipped.is_empty() {
+        eprintln!(
+            "{} Left {} path(s) alone because a regular file now exists there:",
+            style("Warning:").yellow().bold(),
+            summary.skipped.len()
+        );
+        for path in &summary.skipped {
+            eprintln!("    • {}", path.display());
+        }
+    }
+
+    if !summary.dangling.is_empty() {
+        eprintln!(
+            "{} {} restored link(s) point at kegs that no longer exist; reinstall the affected formulas:",
+            style("Warning:").yellow().bold(),
+            summary.dangling.len()
+        );
+        for path in &summary.dangling {
+            eprintln!("    • {}", path.display());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use console::style;
+
+use crate::utils::format_bytes;
+
+pub fn execute(installer: &mut zb_io::Installer, reset: bool) -> Result<(), zb_core::Error> {
+    if reset {
+        installer.reset_download_stats()?;
+        println!("{} Download statistics reset.", style("==>").cyan().bold());
+        return Ok(());
+    }
+
+    let stats = installer.download_stats()?;
+    let downloads: u64 = stats.hosts.iter().map(|host| host.downloads).sum();
+    if downloads == 0 && stats.cache_hits == 0 && stats.store_hits == 0 {
+        println!("No downloads recorded yet.");
+        return Ok(());
+    }
+
+    println!("{} Downloads", style("==>").cyan().bold());
+    if stats.hosts.is_empty() {
+        println!("    {}", style("none").dim());
+    }
+    let width = stats.hosts.iter().map(|h| h.host.len()).max().unwrap_or(0);
+    for host in &stats.hosts {
+        println!(
+            "    {:width$}  {:>5} bottles  {:>9}  {}",
+            host.host,
+            host.downloads,
+            format_bytes(host.bytes),
+            style(format_throughput(host.bytes, host.millis)).dim()
+        );
+    }
+
+    println!("{} Savings", style("==>").cyan().bold());
+    println!(
+        "    Blob cache:  {} of {} bottles ({}), {} not downloaded again",
+        stats.cache_hits,
+        stats.cache_hits + downloads,
+        hit_rate(stats.cache_hits, downloads),
+        format_bytes(stats.cache_bytes)
+    );
+    println!(
+        "    Store dedup: {} bottles ({}) reused without extracting",
+        stats.store_hits,
+        format_bytes(stats.store_bytes)
+    );
+
+    Ok(())
+}
+
+fn hit_rate(hits: u64, misses: u64) -> String {
+    match hits + misses {
+        0 => "n/a".to_string(),
+        total => format!("{:.0}%", hits as f64 * 100.0 / total as f64),
+    }
+}
+
+fn format_throughput(bytes: u64, millis: u64) -> String {
+    if millis == 0 {
+        return "avg n/a".to_string();
+    }
+    let per_second = (bytes as f64 * 1000.0 / millis as f64) as u64;
+    format!("avg {}/s", format_bytes(per_second))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_handle_empty_counters() {
+        assert_eq!(hit_rate(0, 0), "n/a");
+        assert_eq!(hit_rate(1, 3), "25%");
+        assert_eq!(format_throughput(2048, 0), "avg n/a");
+        assert_eq!(format_throughput(2048, 500), "avg 4.0 KB/s");
+    }
+}
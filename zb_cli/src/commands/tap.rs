@@ -0,0 +1,55 @@
+use console::style;
+
+/// Tap `name` (from `remote` when given), or list the taps when no name is
+/// given.
+pub async fn tap(
+    installer: &mut zb_io::Installer,
+    name: Option<&str>,
+    remote: Option<&str>,
+) -> Result<(), zb_core::Error> {
+    let Some(name) = name else {
+        let taps = installer.list_taps()?;
+        if taps.is_empty() {
+            println!("No taps.");
+        }
+        for tap in taps {
+            println!(
+                "{} {}",
+                style(&tap.name).bold(),
+                style(format!("({} formulas, {})", tap.formulas, tap.remote)).dim()
+            );
+        }
+        return Ok(());
+    };
+
+    println!(
+        "{} Tapping {}...",
+        style("==>").cyan().bold(),
+        style(name).bold()
+    );
+    let tap = installer.tap(name, remote).await?;
+    println!(
+        "    {} Tapped {} {}",
+        style("✓").green(),
+        style(&tap.name).bold(),
+        style(format!("({} formulas)", tap.formulas)).dim()
+    );
+    Ok(())
+}
+
+pub fn untap(
+    installer: &mut zb_io::Installer,
+    names: &[String],
+    force: bool,
+) -> Result<(), zb_core::Error> {
+    for name in names {
+        let tap = installer.untap(name, force)?;
+        println!(
+            "{} Untapped {} {}",
+            style("==>").cyan().bold(),
+            style(&tap.name).bold(),
+            style(format!("({} formulas)", tap.formulas)).dim()
+        );
+    }
+    Ok(())
+}
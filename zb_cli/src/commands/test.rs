@@ -0,0 +1,60 @@
+use console::style;
+
+use zb_io::{CheckStatus, Installer, SmokeReport};
+
+use crate::utils::normalize_formula_name;
+
+pub async fn execute(installer: &Installer, formulas: Vec<String>) -> Result<(), zb_core::Error> {
+    let mut failed = Vec::new();
+
+    for formula in &formulas {
+        let name = normalize_formula_name(formula)?;
+        let report = installer.smoke_test(&name).await?;
+
+        println!(
+            "{} Testing {} {}",
+            style("==>").cyan().bold(),
+            style(&report.formula).bold(),
+            style(&report.version).dim()
+        );
+        print_report(&report);
+
+        if !report.passed() {
+            failed.push(report.formula);
+        }
+    }
+
+    if failed.is_empty() {
+        return Ok(());
+    }
+    Err(zb_core::Error::ExecutionError {
+        message: format!("tests failed for: {}", failed.join(", ")),
+    })
+}
+
+fn print_report(report: &SmokeReport) {
+    for check in &report.checks {
+        let label = if check.detail.is_empty() {
+            check.name.clone()
+        } else {
+            format!("{} ({})", check.name, check.detail)
+        };
+        match &check.status {
+            CheckStatus::Passed => println!("    {} {}", style("✓").green(), label),
+            CheckStatus::Failed(reason) => {
+                println!(
+                    "    {} {}: {}",
+                    style("✗").red(),
+                    label,
+                    style(reason).red()
+                )
+            }
+            CheckStatus::Skipped(reason) => println!(
+                "    {} {}: {}",
+                style("-").dim(),
+                style(&label).dim(),
+                style(reason).dim()
+            ),
+        }
+    }
+}
@@ -1,10 +1,14 @@
-use crate::utils::normalize_formula_name;
+use crate::utils::{choose_items, confirm, normalize_formula_name};
 use console::style;
+use std::path::Path;
+use zb_io::ServiceManager;
 
 pub fn execute(
     installer: &mut zb_io::Installer,
     formulas: Vec<String>,
     all: bool,
+    yes: bool,
+    backup: Option<&Path>,
 ) -> Result<(), zb_core::Error> {
     let formulas = if all {
         let installed = installer.list_installed()?;
@@ -21,18 +25,65 @@ pub fn execute(
         normalized
     };
 
+    let formulas = match choose_items(&formulas, yes) {
+        Some(keep) => formulas
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(name, keep)| keep.then_some(name))
+            .collect(),
+        None => formulas,
+    };
+    if formulas.is_empty() {
+        println!("Nothing selected.");
+        return Ok(());
+    }
+
+    let formulas = match ServiceManager::for_current_user() {
+        Some(services) => release_services(&services, formulas, yes)?,
+        None => formulas,
+    };
+    if formulas.is_empty() {
+        return Ok(());
+    }
+    // Dependents go first so removing them frees their dependencies.
+    let formulas = installer.uninstall_order(&formulas);
+
     println!(
         "{} Uninstalling {}...",
         style("==>").cyan().bold(),
         style(formulas.join(", ")).bold()
     );
 
+    if let Some(dir) = backup {
+        for name in &formulas {
+            let changed = installer.changed_keg_files(name)?;
+            if changed.is_empty() {
+                continue;
+            }
+            let path = installer.backup_keg_files(name, &changed, dir)?;
+            println!(
+                "    Kept {} changed files of {} in {}",
+                changed.len(),
+                style(name).bold(),
+                style(path.display()).dim()
+            );
+        }
+    }
+
     let mut errors: Vec<(String, zb_core::Error)> = Vec::new();
+    // Removing everything leaves no retained version to promote.
+    let uninstall = |installer: &mut zb_io::Installer, name: &str| {
+        if all {
+            installer.uninstall_all_versions(name)
+        } else {
+            installer.uninstall(name)
+        }
+    };
 
     if formulas.len() > 1 {
         for name in &formulas {
             print!("    {} {}...", style("○").dim(), name);
-            match installer.uninstall(name) {
+            match uninstall(installer, name) {
                 Ok(()) => println!(" {}", style("✓").green()),
                 Err(e) => {
                     println!(" {}", style("✗").red());
@@ -40,10 +91,21 @@ pub fn execute(
                 }
             }
         }
-    } else if let Err(e) = installer.uninstall(&formulas[0]) {
+    } else if let Err(e) = uninstall(installer, &formulas[0]) {
         errors.push((formulas[0].clone(), e));
     }
 
+    for name in &formulas {
+        if let Some(keg) = installer.get_installed(name) {
+            println!(
+                "{} {} {} is the active version again",
+                style("==>").cyan().bold(),
+                style(name).bold(),
+                keg.version
+            );
+        }
+    }
+
     if errors.is_empty() {
         Ok(())
     } else {
@@ -59,3 +121,84 @@ pub fn execute(
         Err(errors.remove(0).1)
     }
 }
+
+/// Stop and unregister services for formulas about to be removed, so no unit is
+/// left pointing at a deleted keg. Formulas whose service could not be released
+/// are dropped from the list.
+fn release_services(
+    services: &ServiceManager,
+    formulas: Vec<String>,
+    yes: bool,
+) -> Result<Vec<String>, zb_core::Error> {
+    let mut keep = Vec::with_capacity(formulas.len());
+
+    for name in formulas {
+        let Some(unit) = services.find(&name) else {
+            keep.push(name);
+            continue;
+        };
+
+        let running = services.is_running(&unit);
+        if !yes
+            && !confirm(&format!(
+                "{} has a {} service ({}). Stop it and remove the unit?",
+                style(&name).bold(),
+                if running { "running" } else { "registered" },
+                unit.path.display()
+            ))?
+        {
+            eprintln!(
+                "{} Skipping {}: its service is still registered",
+                style("Note:").yellow().bold(),
+                style(&name).bold()
+            );
+            continue;
+        }
+
+        if let Err(e) = services.stop(&unit)
+            && running
+        {
+            eprintln!(
+                "{} Skipping {}: failed to stop its service: {}",
+                style("Error:").red().bold(),
+                style(&name).bold(),
+                e
+            );
+            continue;
+        }
+
+        if let Err(e) = services.remove(&unit) {
+            eprintln!(
+                "{} Skipping {}: {}",
+                style("Error:").red().bold(),
+                style(&name).bold(),
+                e
+            );
+            continue;
+        }
+
+        println!(
+            "{} Stopped and removed service {}",
+            style("==>").cyan().bold(),
+            unit.label
+        );
+        keep.push(name);
+    }
+
+    Ok(keep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zb_io::ServiceKind;
+
+    #[test]
+    fn formulas_without_services_pass_through() {
+        let tmp = tempfile::tempdir().unwrap();
+        let services = ServiceManager::new(tmp.path().to_path_buf(), ServiceKind::Systemd);
+
+        let kept = release_services(&services, vec!["jq".into(), "wget".into()], false).unwrap();
+        assert_eq!(kept, vec!["jq", "wget"]);
+    }
+}
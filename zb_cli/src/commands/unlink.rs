@@ -0,0 +1,37 @@
+use console::style;
+
+use zb_io::Installer;
+
+use crate::utils::normalize_formula_name;
+
+pub fn execute(
+    installer: &mut Installer,
+    formula: &str,
+    only: &[String],
+    dry_run: bool,
+) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(formula)?;
+    let unlinked = if dry_run {
+        installer.preview_unlink(&name, only)?
+    } else {
+        installer.unlink(&name, only)?
+    };
+
+    if unlinked.is_empty() {
+        println!("No links of {name} to remove.");
+        return Ok(());
+    }
+    println!(
+        "{} {} {} ({} files)",
+        style("==>").cyan().bold(),
+        if dry_run { "Would unlink" } else { "Unlinked" },
+        style(&name).bold(),
+        style(unlinked.len()).green().bold()
+    );
+    if dry_run || !only.is_empty() {
+        for path in &unlinked {
+            println!("    {}", style(path.display()).dim());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use console::style;
+
+use zb_io::Installer;
+
+use crate::utils::format_bytes;
+
+/// Changed formula names are listed individually up to this many.
+const MAX_LISTED: usize = 20;
+
+pub async fn execute(
+    installer: &mut Installer,
+    pin: Option<&str>,
+    unpin: bool,
+) -> Result<(), zb_core::Error> {
+    if let Some(pin) = pin {
+        println!(
+            "{} Pinning formula index to {}...",
+            style("==>").cyan().bold(),
+            style(pin).bold()
+        );
+        let (pin, update) = installer.pin_index(pin).await?;
+        println!(
+            "{} Pinned to snapshot {}; {} formulas indexed ({} downloaded)",
+            style("==>").cyan().bold(),
+            style(pin.sha.get(..12).unwrap_or(&pin.sha)).green().bold(),
+            update.total,
+            format_bytes(update.bytes_downloaded)
+        );
+        println!(
+            "    {}",
+            style("Core formulas resolve from this snapshot until `zb update --unpin-index`.")
+                .dim()
+        );
+        return Ok(());
+    }
+
+    if !installer.list_taps()?.is_empty() {
+        println!("{} Updating taps...", style("==>").cyan().bold());
+        for tap in installer.update_taps().await? {
+            println!(
+                "    {} {} {}",
+                style("✓").green(),
+                tap.name,
+                style(format!("({} formulas)", tap.formulas)).dim()
+            );
+        }
+    }
+
+    if unpin {
+        match installer.unpin_index()? {
+            Some(pin) => println!(
+                "{} Unpinned formula index from {}",
+                style("==>").cyan().bold(),
+                pin.requested
+            ),
+            None => println!("The formula index isn't pinned."),
+        }
+    } else if let Some(pin) = installer.index_pin() {
+        println!(
+            "The formula index is pinned to {} ({}); not updating.",
+            style(&pin.requested).bold(),
+            pin.sha.get(..12).unwrap_or(&pin.sha)
+        );
+        println!(
+            "    {}",
+            style("Run `zb update --unpin-index` to follow the current API again.").dim()
+        );
+        return Ok(());
+    }
+
+    println!("{} Updating formula index...", style("==>").cyan().bold());
+    let update = installer.update_index().await?;
+
+    if update.is_empty() {
+        println!(
+            "Already up to date ({} formulas, {} checked).",
+            update.total,
+            format_bytes(update.bytes_downloaded)
+        );
+        return Ok(());
+    }
+
+    for (label, names) in [
+        ("New", &update.added),
+        ("Updated", &update.changed),
+        ("Removed", &update.removed),
+    ] {
+        if names.is_empty() || names.len() > MAX_LISTED {
+            continue;
+        }
+        println!(
+            "    {} {}",
+            style(format!("{label}:")).dim(),
+            names.join(", ")
+        );
+    }
+
+    println!(
+        "{} {} new, {} updated, {} removed; {} formulas indexed ({} downloaded{})",
+        style("==>").cyan().bold(),
+        style(update.added.len()).green().bold(),
+        style(update.changed.len()).green().bold(),
+        style(update.removed.len()).green().bold(),
+        update.total,
+        format_bytes(update.bytes_downloaded),
+        if update.full { ", full refresh" } else { "" }
+    );
+
+    Ok(())
+}
@@ -0,0 +1,274 @@
+use console::style;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use zb_core::formula_token;
+use zb_io::ServiceManager;
+
+use super::{install, snapshot};
+use crate::utils::{choose_items, normalize_formula_name};
+
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    formulas: Vec<String>,
+    snapshots: Option<&zb_io::SnapshotStore>,
+    yes: bool,
+) -> Result<(), zb_core::Error> {
+    let start = Instant::now();
+    let names = formulas
+        .iter()
+        .map(|formula| normalize_formula_name(formula))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut plan = installer.plan_upgrade(&names).await?;
+    let mut upgrades = installer.planned_upgrades(&plan);
+    if names.is_empty() {
+        let pinned = installer.pinned_formulas()?;
+        if !pinned.is_empty() {
+            println!(
+                "{} {}",
+                style("Not upgrading pinned:").dim(),
+                pinned.join(", ")
+            );
+        }
+    }
+    if upgrades.is_empty() {
+        println!("All formulas are up to date.");
+        return Ok(());
+    }
+
+    let labels: Vec<String> = upgrades
+        .iter()
+        .map(|upgrade| {
+            format!(
+                "{} {} → {}",
+                style(&upgrade.name).bold(),
+                style(&upgrade.from_version).dim(),
+                style(&upgrade.to_version).green()
+            )
+        })
+        .collect();
+    if let Some(keep) = choose_items(&labels, yes) {
+        let kept: Vec<String> = upgrades
+            .iter()
+            .zip(keep)
+            .filter(|(_, keep)| *keep)
+            .map(|(upgrade, _)| upgrade.name.clone())
+            .collect();
+        if kept.is_empty() {
+            println!("Nothing selected.");
+            return Ok(());
+        }
+        // Plan again for what is left; a deselected formula is still
+        // upgraded when a kept one depends on its newer version.
+        if kept.len() < upgrades.len() {
+            plan = installer.plan_upgrade(&kept).await?;
+            upgrades = installer.planned_upgrades(&plan);
+        }
+    }
+
+    println!(
+        "{} Upgrading {} formulas...",
+        style("==>").cyan().bold(),
+        style(upgrades.len()).green().bold()
+    );
+    for upgrade in &upgrades {
+        println!(
+            "    {} {} → {}",
+            style(&upgrade.name).bold(),
+            style(&upgrade.from_version).dim(),
+            style(&upgrade.to_version).green()
+        );
+    }
+    let new_dependencies: Vec<&str> = plan
+        .items
+        .iter()
+        .map(|item| item.install_name.as_str())
+        .filter(|name| !upgrades.iter().any(|upgrade| upgrade.name == *name))
+        .collect();
+    if !new_dependencies.is_empty() {
+        println!(
+            "    {} {}",
+            style("new dependencies:").dim(),
+            new_dependencies.join(", ")
+        );
+    }
+    let plan_hash = installer.plan_hash(&plan);
+
+    let taken = match snapshots {
+        Some(store) => Some(snapshot::create(store, "upgrade")?),
+        None => None,
+    };
+
+    let names: Vec<String> = upgrades
+        .iter()
+        .map(|upgrade| upgrade.name.clone())
+        .collect();
+    if let Err(e) = install::run_plan(installer, plan, false, &names).await {
+        if let Some(snapshot) = &taken {
+            snapshot::print_restore_hint(snapshot);
+        }
+        return Err(e);
+    }
+    let removed = installer.remove_superseded(&upgrades)?;
+
+    println!();
+    println!(
+        "{} Upgraded {} formulas in {:.2}s",
+        style("==>").cyan().bold(),
+        style(upgrades.len()).green().bold(),
+        start.elapsed().as_secs_f64()
+    );
+    if !removed.is_empty() {
+        println!("    {} {}", style("removed").dim(), removed.join(", "));
+    }
+    println!("    {} {}", style("plan").dim(), style(plan_hash).dim());
+
+    Ok(())
+}
+
+/// Show what upgrading would disturb on a live system, without changing
+/// anything: linked binaries that change, processes with the old kegs open
+/// and services that need a restart.
+pub async fn preview(
+    installer: &mut zb_io::Installer,
+    formulas: Vec<String>,
+) -> Result<(), zb_core::Error> {
+    let names = formulas
+        .iter()
+        .map(|formula| normalize_formula_name(formula))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let plan = installer.plan_upgrade(&names).await?;
+    let upgrades = installer.planned_upgrades(&plan);
+    if upgrades.is_empty() {
+        println!("All formulas are up to date.");
+        return Ok(());
+    }
+
+    println!(
+        "{} Would upgrade {} formulas",
+        style("==>").cyan().bold(),
+        style(upgrades.len()).green().bold()
+    );
+    for upgrade in &upgrades {
+        println!(
+            "    {} {} → {}",
+            style(&upgrade.name).bold(),
+            style(&upgrade.from_version).dim(),
+            style(&upgrade.to_version).green()
+        );
+    }
+
+    println!(
+        "{} Linked binaries that will change",
+        style("==>").cyan().bold()
+    );
+    let mut changed = false;
+    for upgrade in &upgrades {
+        let binaries = linked_binaries(&installer.linked_files(&upgrade.name)?, installer.prefix());
+        if !binaries.is_empty() {
+            changed = true;
+            println!(
+                "    {}: {}",
+                style(&upgrade.name).bold(),
+                binaries.join(", ")
+            );
+        }
+    }
+    if !changed {
+        println!("    {}", style("none").dim());
+    }
+
+    // lsof reports resolved paths, so compare against resolved kegs.
+    let kegs: Vec<(&str, PathBuf)> = upgrades
+        .iter()
+        .map(|upgrade| {
+            let keg = installer.keg_path(formula_token(&upgrade.name), &upgrade.from_version);
+            (upgrade.name.as_str(), keg.canonicalize().unwrap_or(keg))
+        })
+        .collect();
+    println!(
+        "{} Processes using kegs being replaced",
+        style("==>").cyan().bold()
+    );
+    let dirs: Vec<PathBuf> = kegs.iter().map(|(_, keg)| keg.clone()).collect();
+    match zb_io::processes_using(&dirs) {
+        None => println!(
+            "    {}",
+            style("lsof is unavailable; couldn't check running processes").yellow()
+        ),
+        Some(processes) if processes.is_empty() => println!("    {}", style("none").dim()),
+        Some(processes) => {
+            for process in processes {
+                let users: Vec<&str> = kegs
+                    .iter()
+                    .filter(|(_, keg)| process.paths.iter().any(|path| path.starts_with(keg)))
+                    .map(|(name, _)| *name)
+                    .collect();
+                println!(
+                    "    {} {} {}",
+                    style(process.pid).dim(),
+                    style(&process.command).bold(),
+                    style(format!("(uses {})", users.join(", "))).dim()
+                );
+            }
+        }
+    }
+
+    println!("{} Services to restart", style("==>").cyan().bold());
+    let units: Vec<_> = match ServiceManager::for_current_user() {
+        Some(services) => upgrades
+            .iter()
+            .filter_map(|upgrade| services.find(&upgrade.name))
+            .map(|unit| {
+                let running = services.is_running(&unit);
+                (unit, running)
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    if units.is_empty() {
+        println!("    {}", style("none").dim());
+    }
+    for (unit, running) in units {
+        let state = if running {
+            style("running, restart after upgrading").yellow()
+        } else {
+            style("stopped, picks up the new version on next start").dim()
+        };
+        println!("    {} {}", style(&unit.label).bold(), state);
+    }
+
+    Ok(())
+}
+
+/// Names of the executables among `links` that sit in the prefix's `bin` or `sbin`.
+fn linked_binaries(links: &[PathBuf], prefix: &Path) -> Vec<String> {
+    let bin_dirs = [prefix.join("bin"), prefix.join("sbin")];
+    links
+        .iter()
+        .filter(|link| {
+            link.parent()
+                .is_some_and(|dir| bin_dirs.iter().any(|bin| bin == dir))
+        })
+        .filter_map(|link| link.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linked_binaries_only_counts_bin_and_sbin() {
+        let prefix = Path::new("/opt/zerobrew");
+        let links = [
+            prefix.join("bin/openssl"),
+            prefix.join("sbin/nginx"),
+            prefix.join("lib/libssl.3.dylib"),
+            prefix.join("bin/sub/tool"),
+        ];
+        assert_eq!(linked_binaries(&links, prefix), ["openssl", "nginx"]);
+    }
+}
@@ -0,0 +1,81 @@
+use console::style;
+
+use zb_io::{EntryStatus, Installer};
+
+pub fn execute(installer: &Installer, hash: &str) -> Result<(), zb_core::Error> {
+    let verification = installer.verify_plan(hash)?;
+    let recorded_at = chrono::DateTime::from_timestamp(verification.recorded_at, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| "an unknown time".to_string());
+
+    println!(
+        "{} Verifying plan {} (recorded {})",
+        style("==>").cyan().bold(),
+        style(&verification.hash).bold(),
+        recorded_at
+    );
+
+    if verification.platform != verification.current_platform {
+        println!(
+            "    {} platform: planned for {}, running on {}",
+            style("✗").red(),
+            verification.platform,
+            verification.current_platform
+        );
+    }
+
+    for (entry, status) in &verification.entries {
+        match status {
+            EntryStatus::Matches => println!(
+                "    {} {} {}",
+                style("✓").green(),
+                entry.name,
+                style(&entry.version).dim()
+            ),
+            EntryStatus::Differs {
+                installed_version,
+                installed_store_key,
+            } => {
+                let detail = if *installed_version == entry.version {
+                    format!(
+                        "same version, different build ({} → {})",
+                        short(&entry.store_key),
+                        short(installed_store_key)
+                    )
+                } else {
+                    format!("{} → {}", entry.version, installed_version)
+                };
+                println!(
+                    "    {} {}: {}",
+                    style("✗").red(),
+                    entry.name,
+                    style(detail).red()
+                );
+            }
+            EntryStatus::NotInstalled => println!(
+                "    {} {}: {}",
+                style("✗").red(),
+                entry.name,
+                style("not installed").red()
+            ),
+        }
+    }
+
+    if verification.matches() {
+        println!(
+            "{} Installation matches the plan",
+            style("==>").cyan().bold()
+        );
+        return Ok(());
+    }
+    Err(zb_core::Error::ExecutionError {
+        message: format!(
+            "installation does not match plan {}",
+            short(&verification.hash)
+        ),
+    })
+}
+
+fn short(key: &str) -> &str {
+    key.get(..12).unwrap_or(key)
+}
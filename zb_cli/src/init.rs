@@ -33,7 +33,27 @@ pub fn is_writable(path: &Path) -> bool {
 /// prefix must be no longer than the original.  `/opt/homebrew` = 13 chars.
 const MAX_PREFIX_LEN_MACOS: usize = 13;
 
+/// How `zb init` hooks zerobrew into the shell profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellIntegration {
+    /// The full environment, written into a managed block.
+    ManagedBlock,
+    /// A managed block that only evaluates `zb shellenv`.
+    Shellenv,
+}
+
 pub fn run_init(root: &Path, prefix: &Path, no_modify_path: bool) -> Result<(), InitError> {
+    let integration = (!no_modify_path).then_some(ShellIntegration::ManagedBlock);
+    run_init_with(root, prefix, integration)
+}
+
+/// Create the directories under `root` and `prefix` and set up the shell
+/// profile with `integration`, or leave it alone with `None`.
+pub fn run_init_with(
+    root: &Path,
+    prefix: &Path,
+    integration: Option<ShellIntegration>,
+) -> Result<(), InitError> {
     // On macOS, warn early if the chosen prefix is too long for Mach-O patching.
     if cfg!(target_os = "macos") {
         let prefix_str = prefix.to_string_lossy();
@@ -56,14 +76,7 @@ pub fn run_init(root: &Path, prefix: &Path, no_modify_path: bool) -> Result<(),
 
     println!("{} Initializing zerobrew...", style("==>").cyan().bold());
 
-    let zerobrew_dir = match std::env::var("ZEROBREW_DIR") {
-        Ok(dir) => dir,
-        Err(_) => {
-            let home = std::env::var("HOME")
-                .map_err(|_| InitError::Message("HOME not set".to_string()))?;
-            format!("{}/.zerobrew", home)
-        }
-    };
+    let zerobrew_dir = zerobrew_dir()?;
     let zerobrew_bin = format!("{}/bin", zerobrew_dir);
 
     let dirs_to_create: Vec<PathBuf> = vec![
@@ -145,30 +158,163 @@ pub fn run_init(root: &Path, prefix: &Path, no_modify_path: bool) -> Result<(),
         }
     }
 
-    add_to_path(prefix, &zerobrew_dir, &zerobrew_bin, root, no_modify_path)?;
+    add_to_path(prefix, &zerobrew_dir, &zerobrew_bin, root, integration)?;
 
     println!("{} Initialization complete!", style("==>").cyan().bold());
 
     Ok(())
 }
 
+/// Where the `zb` binary itself is installed.
+fn zerobrew_dir() -> Result<String, InitError> {
+    match std::env::var("ZEROBREW_DIR") {
+        Ok(dir) => Ok(dir),
+        Err(_) => {
+            let home = std::env::var("HOME")
+                .map_err(|_| InitError::Message("HOME not set".to_string()))?;
+            Ok(format!("{}/.zerobrew", home))
+        }
+    }
+}
+
 const ZB_BLOCK_START: &str = "# >>> zerobrew >>>";
 const ZB_BLOCK_END: &str = "# <<< zerobrew <<<";
 
-fn upsert_managed_block(existing: &str, managed_block: &str) -> String {
-    if let Some(start_idx) = existing.find(ZB_BLOCK_START)
-        && let Some(end_rel_idx) = existing[start_idx..].find(ZB_BLOCK_END)
-    {
-        let mut end_idx = start_idx + end_rel_idx + ZB_BLOCK_END.len();
-        if existing[end_idx..].starts_with("\r\n") {
-            end_idx += 2;
-        } else if existing[end_idx..].starts_with('\n') {
-            end_idx += 1;
+/// Ways the zerobrew markers in a shell profile can be damaged, typically by
+/// hand edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockDamage {
+    /// A start marker with no end marker after it.
+    UnterminatedStart,
+    /// An end marker with no start marker before it.
+    OrphanEnd,
+    /// A block inside another one.
+    Nested,
+    /// More than one complete block.
+    Duplicated,
+}
+
+impl std::fmt::Display for BlockDamage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnterminatedStart => write!(f, "a `{ZB_BLOCK_START}` marker is never closed"),
+            Self::OrphanEnd => write!(f, "a `{ZB_BLOCK_END}` marker has no opening marker"),
+            Self::Nested => write!(f, "zerobrew blocks are nested"),
+            Self::Duplicated => write!(f, "the zerobrew block appears more than once"),
         }
+    }
+}
+
+/// The zerobrew regions of a profile, as inclusive line ranges, and what is
+/// wrong with their markers.
+struct BlockScan {
+    regions: Vec<(usize, usize)>,
+    damage: Vec<BlockDamage>,
+}
+
+fn is_marker(line: &str, marker: &str) -> bool {
+    line.trim() == marker
+}
+
+/// Lines zerobrew writes into its block, for finding where a block with a
+/// missing marker starts or ends.
+fn is_zerobrew_line(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    line.contains("zerobrew") || line.contains("_zb_")
+}
+
+fn scan_managed_blocks(lines: &[&str]) -> BlockScan {
+    let mut regions = Vec::new();
+    let mut damage = Vec::new();
+    let mut complete = 0;
+    let mut i = 0;
+    while i < lines.len() {
+        if is_marker(lines[i], ZB_BLOCK_END) {
+            // The start marker is gone; the block starts at its `# zerobrew`
+            // header, when that survived.
+            let floor = regions.last().map_or(0, |&(_, end)| end + 1);
+            let start = (floor..i)
+                .rev()
+                .find(|&k| lines[k].trim() == "# zerobrew")
+                .unwrap_or(i);
+            regions.push((start, i));
+            damage.push(BlockDamage::OrphanEnd);
+            i += 1;
+            continue;
+        }
+        if !is_marker(lines[i], ZB_BLOCK_START) {
+            i += 1;
+            continue;
+        }
+
+        let mut depth = 1;
+        let mut end = i + 1;
+        while end < lines.len() {
+            if is_marker(lines[end], ZB_BLOCK_START) {
+                depth += 1;
+                if !damage.contains(&BlockDamage::Nested) {
+                    damage.push(BlockDamage::Nested);
+                }
+            } else if is_marker(lines[end], ZB_BLOCK_END) {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            end += 1;
+        }
+        if end < lines.len() {
+            complete += 1;
+        } else {
+            // Never closed: the block runs to the last line zerobrew wrote,
+            // plus the `end` closing a fish block.
+            damage.push(BlockDamage::UnterminatedStart);
+            end = (i..lines.len())
+                .rev()
+                .find(|&k| is_zerobrew_line(lines[k]) || is_marker(lines[k], ZB_BLOCK_END))
+                .unwrap_or(i);
+            if lines.get(end + 1).is_some_and(|line| line.trim() == "end") {
+                end += 1;
+            }
+        }
+        regions.push((i, end));
+        i = end + 1;
+    }
+    if complete > 1 {
+        damage.push(BlockDamage::Duplicated);
+    }
+    BlockScan { regions, damage }
+}
+
+/// What is wrong with the zerobrew block markers in `existing`; empty when
+/// there is at most one well-formed block.
+pub fn managed_block_damage(existing: &str) -> Vec<BlockDamage> {
+    let lines: Vec<&str> = existing.split_inclusive('\n').collect();
+    scan_managed_blocks(&lines).damage
+}
+
+/// `existing` with `managed_block` in place of its zerobrew block. Damaged
+/// blocks are repaired: everything zerobrew wrote is dropped and a single
+/// block takes the place of the first one.
+fn upsert_managed_block(existing: &str, managed_block: &str) -> String {
+    let lines: Vec<&str> = existing.split_inclusive('\n').collect();
+    let scan = scan_managed_blocks(&lines);
+    if let Some(&(first, _)) = scan.regions.first() {
         let mut out = String::with_capacity(existing.len() + managed_block.len());
-        out.push_str(&existing[..start_idx]);
-        out.push_str(managed_block);
-        out.push_str(&existing[end_idx..]);
+        let mut regions = scan.regions.iter().peekable();
+        for (index, line) in lines.iter().enumerate() {
+            while regions.next_if(|&&(_, end)| end < index).is_some() {}
+            if index == first {
+                out.push_str(managed_block);
+            }
+            if regions
+                .peek()
+                .is_some_and(|&&(start, end)| (start..=end).contains(&index))
+            {
+                continue;
+            }
+            out.push_str(line);
+        }
         return out;
     }
 
@@ -185,23 +331,38 @@ fn upsert_managed_block(existing: &str, managed_block: &str) -> String {
     }
 }
 
-fn add_to_path(
-    prefix: &Path,
-    zerobrew_dir: &str,
-    zerobrew_bin: &str,
-    root: &Path,
-    no_modify_path: bool,
-) -> Result<(), InitError> {
-    enum ShellConfigKind {
-        Posix,
-        Fish,
-    }
+/// Copy the profile at `path` aside before a repair rewrites it.
+fn backup_profile(path: &str, contents: &str) -> Result<PathBuf, InitError> {
+    let suffix = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let backup = PathBuf::from(format!("{path}.zb-backup-{suffix}"));
+    std::fs::write(&backup, contents).map_err(|e| {
+        InitError::Message(format!(
+            "Failed to back up {path} to {}: {e}",
+            backup.display()
+        ))
+    })?;
+    Ok(backup)
+}
 
-    let shell = std::env::var("SHELL").unwrap_or_default();
+/// The profile `zb init` writes its block into for `$SHELL`.
+pub fn shell_profile() -> Result<PathBuf, InitError> {
     let home = std::env::var("HOME").map_err(|_| InitError::Message("HOME not set".to_string()))?;
+    Ok(PathBuf::from(shell_config_target(&home).0))
+}
+
+enum ShellConfigKind {
+    Posix,
+    Fish,
+}
 
-    let (config_file, shell_kind) = if shell.contains("zsh") {
-        let zdotdir = std::env::var("ZDOTDIR").unwrap_or_else(|_| home.clone());
+/// The profile `$SHELL` reads at startup, and its syntax.
+fn shell_config_target(home: &str) -> (String, ShellConfigKind) {
+    let shell = std::env::var("SHELL").unwrap_or_default();
+    if shell.contains("zsh") {
+        let zdotdir = std::env::var("ZDOTDIR").unwrap_or_else(|_| home.to_string());
         let zshenv = format!("{}/.zshenv", zdotdir);
         let zshrc = format!("{}/.zshrc", zdotdir);
         let home_zshrc = format!("{}/.zshrc", home);
@@ -227,15 +388,20 @@ fn add_to_path(
         )
     } else {
         (format!("{}/.profile", home), ShellConfigKind::Posix)
-    };
-
-    let prefix_bin = prefix.join("bin");
-    let existing_config = std::fs::read_to_string(&config_file).unwrap_or_default();
+    }
+}
 
-    if !no_modify_path {
-        let block_body = match shell_kind {
-            ShellConfigKind::Posix => format!(
-                r#"
+/// Environment exports for `root` and `prefix` in `shell_kind` syntax.
+fn env_block(
+    shell_kind: &ShellConfigKind,
+    zerobrew_dir: &str,
+    zerobrew_bin: &str,
+    root: &Path,
+    prefix: &Path,
+) -> String {
+    match shell_kind {
+        ShellConfigKind::Posix => format!(
+            r#"
 # zerobrew
 export ZEROBREW_DIR={zerobrew_dir}
 export ZEROBREW_BIN={zerobrew_bin}
@@ -246,102 +412,165 @@ export PKG_CONFIG_PATH="$ZEROBREW_PREFIX/lib/pkgconfig:${{PKG_CONFIG_PATH:-}}"
 # SSL/TLS certificates (only if ca-certificates is installed)
 if [ -z "${{CURL_CA_BUNDLE:-}}" ] || [ -z "${{SSL_CERT_FILE:-}}" ]; then
   if [ -f "$ZEROBREW_PREFIX/opt/ca-certificates/share/ca-certificates/cacert.pem" ]; then
-    [ -z "${{CURL_CA_BUNDLE:-}}" ] && export CURL_CA_BUNDLE="$ZEROBREW_PREFIX/opt/ca-certificates/share/ca-certificates/cacert.pem"
-    [ -z "${{SSL_CERT_FILE:-}}" ] && export SSL_CERT_FILE="$ZEROBREW_PREFIX/opt/ca-certificates/share/ca-certificates/cacert.pem"
+[ -z "${{CURL_CA_BUNDLE:-}}" ] && export CURL_CA_BUNDLE="$ZEROBREW_PREFIX/opt/ca-certificates/share/ca-certificates/cacert.pem"
+[ -z "${{SSL_CERT_FILE:-}}" ] && export SSL_CERT_FILE="$ZEROBREW_PREFIX/opt/ca-certificates/share/ca-certificates/cacert.pem"
   elif [ -f "$ZEROBREW_PREFIX/etc/ca-certificates/cacert.pem" ]; then
-    [ -z "${{CURL_CA_BUNDLE:-}}" ] && export CURL_CA_BUNDLE="$ZEROBREW_PREFIX/etc/ca-certificates/cacert.pem"
-    [ -z "${{SSL_CERT_FILE:-}}" ] && export SSL_CERT_FILE="$ZEROBREW_PREFIX/etc/ca-certificates/cacert.pem"
+[ -z "${{CURL_CA_BUNDLE:-}}" ] && export CURL_CA_BUNDLE="$ZEROBREW_PREFIX/etc/ca-certificates/cacert.pem"
+[ -z "${{SSL_CERT_FILE:-}}" ] && export SSL_CERT_FILE="$ZEROBREW_PREFIX/etc/ca-certificates/cacert.pem"
   elif [ -f "$ZEROBREW_PREFIX/etc/openssl/cert.pem" ]; then
-    [ -z "${{CURL_CA_BUNDLE:-}}" ] && export CURL_CA_BUNDLE="$ZEROBREW_PREFIX/etc/openssl/cert.pem"
-    [ -z "${{SSL_CERT_FILE:-}}" ] && export SSL_CERT_FILE="$ZEROBREW_PREFIX/etc/openssl/cert.pem"
+[ -z "${{CURL_CA_BUNDLE:-}}" ] && export CURL_CA_BUNDLE="$ZEROBREW_PREFIX/etc/openssl/cert.pem"
+[ -z "${{SSL_CERT_FILE:-}}" ] && export SSL_CERT_FILE="$ZEROBREW_PREFIX/etc/openssl/cert.pem"
   elif [ -f "$ZEROBREW_PREFIX/share/ca-certificates/cacert.pem" ]; then
-    [ -z "${{CURL_CA_BUNDLE:-}}" ] && export CURL_CA_BUNDLE="$ZEROBREW_PREFIX/share/ca-certificates/cacert.pem"
-    [ -z "${{SSL_CERT_FILE:-}}" ] && export SSL_CERT_FILE="$ZEROBREW_PREFIX/share/ca-certificates/cacert.pem"
+[ -z "${{CURL_CA_BUNDLE:-}}" ] && export CURL_CA_BUNDLE="$ZEROBREW_PREFIX/share/ca-certificates/cacert.pem"
+[ -z "${{SSL_CERT_FILE:-}}" ] && export SSL_CERT_FILE="$ZEROBREW_PREFIX/share/ca-certificates/cacert.pem"
   fi
 fi
 
 if [ -z "${{SSL_CERT_DIR:-}}" ]; then
   if [ -d "$ZEROBREW_PREFIX/etc/ca-certificates" ]; then
-    export SSL_CERT_DIR="$ZEROBREW_PREFIX/etc/ca-certificates"
+export SSL_CERT_DIR="$ZEROBREW_PREFIX/etc/ca-certificates"
   elif [ -d "$ZEROBREW_PREFIX/etc/openssl/certs" ]; then
-    export SSL_CERT_DIR="$ZEROBREW_PREFIX/etc/openssl/certs"
+export SSL_CERT_DIR="$ZEROBREW_PREFIX/etc/openssl/certs"
   elif [ -d "$ZEROBREW_PREFIX/share/ca-certificates" ]; then
-    export SSL_CERT_DIR="$ZEROBREW_PREFIX/share/ca-certificates"
+export SSL_CERT_DIR="$ZEROBREW_PREFIX/share/ca-certificates"
   fi
 fi
 
 # Helper function to safely append to PATH
 _zb_path_append() {{
-    local argpath="$1"
-    case ":${{PATH}}:" in
-        *:"$argpath":*) ;;
-        *) export PATH="$argpath:$PATH" ;;
-    esac;
+local argpath="$1"
+case ":${{PATH}}:" in
+    *:"$argpath":*) ;;
+    *) export PATH="$argpath:$PATH" ;;
+esac;
 }}
 
 _zb_path_append "$ZEROBREW_BIN"
 _zb_path_append "$ZEROBREW_PREFIX/bin"
 "#,
-                zerobrew_dir = zerobrew_dir,
-                zerobrew_bin = zerobrew_bin,
-                root = root.display(),
-                prefix = prefix.display()
-            ),
-            ShellConfigKind::Fish => format!(
-                r#"
+            zerobrew_dir = zerobrew_dir,
+            zerobrew_bin = zerobrew_bin,
+            root = root.display(),
+            prefix = prefix.display()
+        ),
+        ShellConfigKind::Fish => format!(
+            r#"
 # zerobrew
 set -gx ZEROBREW_DIR "{zerobrew_dir}"
 set -gx ZEROBREW_BIN "{zerobrew_bin}"
 set -gx ZEROBREW_ROOT "{root}"
 set -gx ZEROBREW_PREFIX "{prefix}"
 if set -q PKG_CONFIG_PATH
-    set -gx PKG_CONFIG_PATH "$ZEROBREW_PREFIX/lib/pkgconfig" $PKG_CONFIG_PATH
+set -gx PKG_CONFIG_PATH "$ZEROBREW_PREFIX/lib/pkgconfig" $PKG_CONFIG_PATH
 else
-    set -gx PKG_CONFIG_PATH "$ZEROBREW_PREFIX/lib/pkgconfig"
+set -gx PKG_CONFIG_PATH "$ZEROBREW_PREFIX/lib/pkgconfig"
 end
 
 # SSL/TLS certificates (only if ca-certificates is installed)
 if not set -q CURL_CA_BUNDLE; or not set -q SSL_CERT_FILE
-    if test -f "$ZEROBREW_PREFIX/opt/ca-certificates/share/ca-certificates/cacert.pem"
-        set -q CURL_CA_BUNDLE; or set -gx CURL_CA_BUNDLE "$ZEROBREW_PREFIX/opt/ca-certificates/share/ca-certificates/cacert.pem"
-        set -q SSL_CERT_FILE; or set -gx SSL_CERT_FILE "$ZEROBREW_PREFIX/opt/ca-certificates/share/ca-certificates/cacert.pem"
-    else if test -f "$ZEROBREW_PREFIX/etc/ca-certificates/cacert.pem"
-        set -q CURL_CA_BUNDLE; or set -gx CURL_CA_BUNDLE "$ZEROBREW_PREFIX/etc/ca-certificates/cacert.pem"
-        set -q SSL_CERT_FILE; or set -gx SSL_CERT_FILE "$ZEROBREW_PREFIX/etc/ca-certificates/cacert.pem"
-    else if test -f "$ZEROBREW_PREFIX/etc/openssl/cert.pem"
-        set -q CURL_CA_BUNDLE; or set -gx CURL_CA_BUNDLE "$ZEROBREW_PREFIX/etc/openssl/cert.pem"
-        set -q SSL_CERT_FILE; or set -gx SSL_CERT_FILE "$ZEROBREW_PREFIX/etc/openssl/cert.pem"
-    else if test -f "$ZEROBREW_PREFIX/share/ca-certificates/cacert.pem"
-        set -q CURL_CA_BUNDLE; or set -gx CURL_CA_BUNDLE "$ZEROBREW_PREFIX/share/ca-certificates/cacert.pem"
-        set -q SSL_CERT_FILE; or set -gx SSL_CERT_FILE "$ZEROBREW_PREFIX/share/ca-certificates/cacert.pem"
-    end
+if test -f "$ZEROBREW_PREFIX/opt/ca-certificates/share/ca-certificates/cacert.pem"
+    set -q CURL_CA_BUNDLE; or set -gx CURL_CA_BUNDLE "$ZEROBREW_PREFIX/opt/ca-certificates/share/ca-certificates/cacert.pem"
+    set -q SSL_CERT_FILE; or set -gx SSL_CERT_FILE "$ZEROBREW_PREFIX/opt/ca-certificates/share/ca-certificates/cacert.pem"
+else if test -f "$ZEROBREW_PREFIX/etc/ca-certificates/cacert.pem"
+    set -q CURL_CA_BUNDLE; or set -gx CURL_CA_BUNDLE "$ZEROBREW_PREFIX/etc/ca-certificates/cacert.pem"
+    set -q SSL_CERT_FILE; or set -gx SSL_CERT_FILE "$ZEROBREW_PREFIX/etc/ca-certificates/cacert.pem"
+else if test -f "$ZEROBREW_PREFIX/etc/openssl/cert.pem"
+    set -q CURL_CA_BUNDLE; or set -gx CURL_CA_BUNDLE "$ZEROBREW_PREFIX/etc/openssl/cert.pem"
+    set -q SSL_CERT_FILE; or set -gx SSL_CERT_FILE "$ZEROBREW_PREFIX/etc/openssl/cert.pem"
+else if test -f "$ZEROBREW_PREFIX/share/ca-certificates/cacert.pem"
+    set -q CURL_CA_BUNDLE; or set -gx CURL_CA_BUNDLE "$ZEROBREW_PREFIX/share/ca-certificates/cacert.pem"
+    set -q SSL_CERT_FILE; or set -gx SSL_CERT_FILE "$ZEROBREW_PREFIX/share/ca-certificates/cacert.pem"
+end
 end
 
 if not set -q SSL_CERT_DIR
-    if test -d "$ZEROBREW_PREFIX/etc/ca-certificates"
-        set -gx SSL_CERT_DIR "$ZEROBREW_PREFIX/etc/ca-certificates"
-    else if test -d "$ZEROBREW_PREFIX/etc/openssl/certs"
-        set -gx SSL_CERT_DIR "$ZEROBREW_PREFIX/etc/openssl/certs"
-    else if test -d "$ZEROBREW_PREFIX/share/ca-certificates"
-        set -gx SSL_CERT_DIR "$ZEROBREW_PREFIX/share/ca-certificates"
-    end
+if test -d "$ZEROBREW_PREFIX/etc/ca-certificates"
+    set -gx SSL_CERT_DIR "$ZEROBREW_PREFIX/etc/ca-certificates"
+else if test -d "$ZEROBREW_PREFIX/etc/openssl/certs"
+    set -gx SSL_CERT_DIR "$ZEROBREW_PREFIX/etc/openssl/certs"
+else if test -d "$ZEROBREW_PREFIX/share/ca-certificates"
+    set -gx SSL_CERT_DIR "$ZEROBREW_PREFIX/share/ca-certificates"
+end
 end
 
 if not contains -- "$ZEROBREW_BIN" $PATH
-    set -gx PATH "$ZEROBREW_BIN" $PATH
+set -gx PATH "$ZEROBREW_BIN" $PATH
 end
 if not contains -- "$ZEROBREW_PREFIX/bin" $PATH
-    set -gx PATH "$ZEROBREW_PREFIX/bin" $PATH
+set -gx PATH "$ZEROBREW_PREFIX/bin" $PATH
 end
 "#,
-                zerobrew_dir = zerobrew_dir,
-                zerobrew_bin = zerobrew_bin,
-                root = root.display(),
-                prefix = prefix.display()
-            ),
+            zerobrew_dir = zerobrew_dir,
+            zerobrew_bin = zerobrew_bin,
+            root = root.display(),
+            prefix = prefix.display()
+        ),
+    }
+}
+
+/// Profile lines that evaluate `zb shellenv` at shell startup, so the
+/// environment follows whatever the installed `zb` prints.
+fn shellenv_hook(
+    shell_kind: &ShellConfigKind,
+    zerobrew_bin: &str,
+    root: &Path,
+    prefix: &Path,
+) -> String {
+    let command = format!(
+        "{zerobrew_bin}/zb --root \"{}\" --prefix \"{}\" shellenv",
+        root.display(),
+        prefix.display()
+    );
+    match shell_kind {
+        ShellConfigKind::Posix => format!("\n# zerobrew\neval \"$({command})\""),
+        ShellConfigKind::Fish => format!("\n# zerobrew\n{command} | source"),
+    }
+}
+
+/// What `zb shellenv` prints: the environment for `root` and `prefix` in the
+/// syntax of `$SHELL`.
+pub fn shellenv(root: &Path, prefix: &Path) -> Result<String, InitError> {
+    let home = std::env::var("HOME").map_err(|_| InitError::Message("HOME not set".to_string()))?;
+    let zerobrew_dir = zerobrew_dir()?;
+    let zerobrew_bin = format!("{zerobrew_dir}/bin");
+    let (_, shell_kind) = shell_config_target(&home);
+    Ok(env_block(
+        &shell_kind,
+        &zerobrew_dir,
+        &zerobrew_bin,
+        root,
+        prefix,
+    ))
+}
+
+fn add_to_path(
+    prefix: &Path,
+    zerobrew_dir: &str,
+    zerobrew_bin: &str,
+    root: &Path,
+    integration: Option<ShellIntegration>,
+) -> Result<(), InitError> {
+    let home = std::env::var("HOME").map_err(|_| InitError::Message("HOME not set".to_string()))?;
+    let (config_file, shell_kind) = shell_config_target(&home);
+
+    let prefix_bin = prefix.join("bin");
+    let existing_config = std::fs::read_to_string(&config_file).unwrap_or_default();
+
+    if let Some(integration) = integration {
+        let block_body = match integration {
+            ShellIntegration::ManagedBlock => {
+                env_block(&shell_kind, zerobrew_dir, zerobrew_bin, root, prefix)
+            }
+            ShellIntegration::Shellenv => shellenv_hook(&shell_kind, zerobrew_bin, root, prefix),
         };
         let managed_block = format!("{ZB_BLOCK_START}{block_body}\n{ZB_BLOCK_END}\n");
         let updated_config = upsert_managed_block(&existing_config, &managed_block);
+        let damage = managed_block_damage(&existing_config);
+        let backup = if damage.is_empty() {
+            None
+        } else {
+            Some(backup_profile(&config_file, &existing_config)?)
+        };
 
         if let Some(parent) = std::path::Path::new(&config_file).parent() {
             std::fs::create_dir_all(parent).map_err(|e| {
@@ -374,6 +603,16 @@ end
             );
             println!("{}", managed_block);
         } else {
+            if let Some(backup) = backup {
+                let damage: Vec<String> = damage.iter().map(ToString::to_string).collect();
+                println!(
+                    "    {} Repaired the zerobrew block in {} ({}); backup at {}",
+                    style("✓").green(),
+                    config_file,
+                    damage.join(", "),
+                    backup.display()
+                );
+            }
             println!(
                 "    {} Updated zerobrew configuration in {}",
                 style("✓").green(),
@@ -386,7 +625,7 @@ end
                 prefix_bin.display()
             );
         }
-    } else if no_modify_path {
+    } else {
         println!(
             "    {} Skipped shell configuration (--no-modify-path)",
             style("→").cyan()
@@ -410,8 +649,8 @@ pub fn ensure_init(root: &Path, prefix: &Path, auto_init: bool) -> Result<(), zb
     // Check if both stdin and stdout are TTYs
     // If stdout is not a TTY, the user won't see the prompt, so don't prompt
     // If stdin is not a TTY, we can't read input, so don't prompt
-    let is_interactive = std::io::IsTerminal::is_terminal(&std::io::stdin())
-        && std::io::IsTerminal::is_terminal(&std::io::stdout());
+    // Under --strict, never prompt
+    let is_interactive = crate::utils::interactive();
 
     if is_interactive && !auto_init {
         println!(
@@ -556,7 +795,14 @@ mod tests {
             std::env::set_var("SHELL", "/bin/bash");
         }
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, false).unwrap();
+        add_to_path(
+            &prefix,
+            zerobrew_dir,
+            zerobrew_bin,
+            &root,
+            Some(ShellIntegration::ManagedBlock),
+        )
+        .unwrap();
 
         let content = fs::read_to_string(&shell_config).unwrap();
         assert!(content.contains(ZB_BLOCK_START));
@@ -600,7 +846,14 @@ mod tests {
             std::env::set_var("SHELL", "/bin/bash");
         }
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, false).unwrap();
+        add_to_path(
+            &prefix,
+            zerobrew_dir,
+            zerobrew_bin,
+            &root,
+            Some(ShellIntegration::ManagedBlock),
+        )
+        .unwrap();
 
         let content = fs::read_to_string(&shell_config).unwrap();
         assert!(content.contains("_zb_path_append()"));
@@ -628,13 +881,43 @@ mod tests {
             std::env::set_var("SHELL", "/bin/bash");
         }
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, false).unwrap();
+        add_to_path(
+            &prefix,
+            zerobrew_dir,
+            zerobrew_bin,
+            &root,
+            Some(ShellIntegration::ManagedBlock),
+        )
+        .unwrap();
 
         let content = fs::read_to_string(&shell_config).unwrap();
         assert!(content.contains("_zb_path_append \"$ZEROBREW_BIN\""));
         assert!(content.contains("_zb_path_append \"$ZEROBREW_PREFIX/bin\""));
     }
 
+    #[test]
+    fn shellenv_hook_evaluates_zb_with_the_chosen_locations() {
+        let root = Path::new("/opt/zerobrew");
+        let prefix = Path::new("/opt/zerobrew/prefix");
+        let bin = "/home/user/.zerobrew/bin";
+
+        let posix = shellenv_hook(&ShellConfigKind::Posix, bin, root, prefix);
+        assert!(posix.contains(
+            r#"eval "$(/home/user/.zerobrew/bin/zb --root "/opt/zerobrew" --prefix "/opt/zerobrew/prefix" shellenv)""#
+        ));
+        let fish = shellenv_hook(&ShellConfigKind::Fish, bin, root, prefix);
+        assert!(fish.ends_with("shellenv | source"));
+
+        let block = env_block(
+            &ShellConfigKind::Posix,
+            "/home/user/.zerobrew",
+            bin,
+            root,
+            prefix,
+        );
+        assert!(block.contains("export ZEROBREW_PREFIX=/opt/zerobrew/prefix"));
+    }
+
     #[test]
     fn add_to_path_no_modify_shell_skips_write() {
         let tmp = TempDir::new().unwrap();
@@ -655,7 +938,7 @@ mod tests {
             std::env::set_var("SHELL", "/bin/bash");
         }
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, true).unwrap();
+        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, None).unwrap();
 
         // File should not be created
         assert!(!shell_config.exists());
@@ -690,7 +973,14 @@ mod tests {
         )
         .unwrap();
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, false).unwrap();
+        add_to_path(
+            &prefix,
+            zerobrew_dir,
+            zerobrew_bin,
+            &root,
+            Some(ShellIntegration::ManagedBlock),
+        )
+        .unwrap();
 
         // Managed block should be replaced, preserving unrelated user content
         let content = fs::read_to_string(&shell_config).unwrap();
@@ -720,7 +1010,14 @@ mod tests {
             std::env::remove_var("ZDOTDIR");
         }
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, false).unwrap();
+        add_to_path(
+            &prefix,
+            zerobrew_dir,
+            zerobrew_bin,
+            &root,
+            Some(ShellIntegration::ManagedBlock),
+        )
+        .unwrap();
 
         assert!(shell_config.exists());
         let content = fs::read_to_string(&shell_config).unwrap();
@@ -754,7 +1051,14 @@ mod tests {
             std::env::remove_var("ZDOTDIR");
         }
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, false).unwrap();
+        add_to_path(
+            &prefix,
+            zerobrew_dir,
+            zerobrew_bin,
+            &root,
+            Some(ShellIntegration::ManagedBlock),
+        )
+        .unwrap();
 
         // Should write to .zshenv, not .zshrc
         assert!(zshenv.exists());
@@ -787,7 +1091,14 @@ mod tests {
             std::env::set_var("SHELL", "/bin/bash");
         }
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, false).unwrap();
+        add_to_path(
+            &prefix,
+            zerobrew_dir,
+            zerobrew_bin,
+            &root,
+            Some(ShellIntegration::ManagedBlock),
+        )
+        .unwrap();
 
         assert!(bash_profile.exists());
         let profile_content = fs::read_to_string(&bash_profile).unwrap();
@@ -815,7 +1126,14 @@ mod tests {
             std::env::set_var("SHELL", "/bin/sh");
         }
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, false).unwrap();
+        add_to_path(
+            &prefix,
+            zerobrew_dir,
+            zerobrew_bin,
+            &root,
+            Some(ShellIntegration::ManagedBlock),
+        )
+        .unwrap();
 
         assert!(profile.exists());
         let content = fs::read_to_string(&profile).unwrap();
@@ -848,7 +1166,14 @@ mod tests {
             std::env::set_var("ZDOTDIR", zdotdir.to_str().unwrap());
         }
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, false).unwrap();
+        add_to_path(
+            &prefix,
+            zerobrew_dir,
+            zerobrew_bin,
+            &root,
+            Some(ShellIntegration::ManagedBlock),
+        )
+        .unwrap();
 
         // Should write to $ZDOTDIR/.zshrc when it exists
         assert!(shell_config.exists());
@@ -874,7 +1199,14 @@ mod tests {
             std::env::set_var("SHELL", "/usr/bin/fish");
         }
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, false).unwrap();
+        add_to_path(
+            &prefix,
+            zerobrew_dir,
+            zerobrew_bin,
+            &root,
+            Some(ShellIntegration::ManagedBlock),
+        )
+        .unwrap();
 
         assert!(fish_config.exists());
         let content = fs::read_to_string(&fish_config).unwrap();
@@ -917,7 +1249,14 @@ mod tests {
             std::env::set_var("ZDOTDIR", zdotdir.to_str().unwrap());
         }
 
-        add_to_path(&prefix, zerobrew_dir, zerobrew_bin, &root, false).unwrap();
+        add_to_path(
+            &prefix,
+            zerobrew_dir,
+            zerobrew_bin,
+            &root,
+            Some(ShellIntegration::ManagedBlock),
+        )
+        .unwrap();
 
         assert!(!zdotdir_zshrc.exists());
         assert!(home_zshrc.exists());
@@ -925,6 +1264,44 @@ mod tests {
         assert!(content.contains("# zerobrew"));
     }
 
+    #[test]
+    fn damaged_managed_blocks_are_detected_and_repaired() {
+        let block =
+            format!("{ZB_BLOCK_START}\n# zerobrew\nexport ZEROBREW_DIR=/new\n{ZB_BLOCK_END}\n");
+        let old = "\n# zerobrew\nexport ZEROBREW_DIR=/old\n_zb_path_append \"$ZEROBREW_BIN\"\n";
+        let repaired = format!("before\n{block}after\n");
+
+        let intact = format!("before\n{ZB_BLOCK_START}{old}{ZB_BLOCK_END}\nafter\n");
+        assert!(managed_block_damage(&intact).is_empty());
+
+        let unterminated = format!("before\n{ZB_BLOCK_START}{old}after\n");
+        assert_eq!(
+            managed_block_damage(&unterminated),
+            [BlockDamage::UnterminatedStart]
+        );
+        assert_eq!(upsert_managed_block(&unterminated, &block), repaired);
+
+        let orphan = format!("before\n{old}{ZB_BLOCK_END}\nafter\n");
+        assert_eq!(managed_block_damage(&orphan), [BlockDamage::OrphanEnd]);
+        assert_eq!(
+            upsert_managed_block(&orphan, &block),
+            format!("before\n\n{block}after\n")
+        );
+
+        let nested = format!(
+            "before\n{ZB_BLOCK_START}{old}{ZB_BLOCK_START}{old}{ZB_BLOCK_END}\n{ZB_BLOCK_END}\nafter\n"
+        );
+        assert_eq!(managed_block_damage(&nested), [BlockDamage::Nested]);
+        assert_eq!(upsert_managed_block(&nested, &block), repaired);
+
+        let duplicated = format!(
+            "before\n{ZB_BLOCK_START}{old}{ZB_BLOCK_END}\nafter\n{ZB_BLOCK_START}{old}{ZB_BLOCK_END}\n"
+        );
+        assert_eq!(managed_block_damage(&duplicated), [BlockDamage::Duplicated]);
+        assert_eq!(upsert_managed_block(&duplicated, &block), repaired);
+        assert!(managed_block_damage(&upsert_managed_block(&duplicated, &block)).is_empty());
+    }
+
     #[test]
     fn upsert_managed_block_replacement_consumes_trailing_newline() {
         let managed_block =
@@ -1,5 +1,56 @@
-use console::style;
-use std::path::PathBuf;
+use console::{Key, Term, style};
+use std::ffi::OsStr;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Turn warnings into errors and never prompt, for the rest of the run
+/// (`--strict`).
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+pub fn is_strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+/// Whether the user can be asked things: stdin and stdout are terminals and
+/// `--strict` is off.
+pub fn interactive() -> bool {
+    !is_strict() && io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// Print `message` as a warning, or return it as an error under `--strict`.
+pub fn warn(message: impl Into<String>) -> Result<(), zb_core::Error> {
+    let message = message.into();
+    if is_strict() {
+        return Err(zb_core::Error::Strict { message });
+    }
+    eprintln!("{} {message}", style("Warning:").yellow().bold());
+    Ok(())
+}
+
+/// Ask a y/N `question`; only "y" confirms. Under `--strict` nothing is
+/// asked and the command fails instead, since `--yes` is the way to confirm
+/// in scripts.
+pub fn confirm(question: &str) -> Result<bool, zb_core::Error> {
+    if is_strict() {
+        return Err(zb_core::Error::Strict {
+            message: format!(
+                "'{}' needs confirmation; pass --yes",
+                console::strip_ansi_codes(question)
+            ),
+        });
+    }
+    print!("{question} [y/N] ");
+    io::stdout().flush().unwrap();
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    Ok(input.trim().eq_ignore_ascii_case("y"))
+}
 
 pub fn normalize_formula_name(name: &str) -> Result<String, zb_core::Error> {
     let trimmed = name.trim();
@@ -99,9 +150,181 @@ pub fn get_root_path(cli_root: Option<PathBuf>) -> PathBuf {
     }
 }
 
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// A printed line and the lines nested under it, for [`tree_lines`].
+pub struct TreeNode {
+    pub label: String,
+    pub children: Vec<TreeNode>,
+}
+
+/// `roots` and everything under them, one line per node, with children
+/// drawn as branches.
+pub fn tree_lines(roots: &[TreeNode]) -> Vec<String> {
+    fn branches(nodes: &[TreeNode], indent: &str, lines: &mut Vec<String>) {
+        for (i, node) in nodes.iter().enumerate() {
+            let (branch, next) = if i + 1 == nodes.len() {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            lines.push(format!("{indent}{}{}", style(branch).dim(), node.label));
+            branches(&node.children, &format!("{indent}{next}"), lines);
+        }
+    }
+
+    let mut lines = Vec::new();
+    for root in roots {
+        lines.push(root.label.clone());
+        branches(&root.children, "", &mut lines);
+    }
+    lines
+}
+
+/// A one-line hint when `binaries` linked into `prefix_bin` won't be what
+/// the shell runs, or `None` when they will.
+///
+/// `shell_loaded` tells whether zerobrew's shell block has been sourced in
+/// this shell (it exports `ZEROBREW_PREFIX`).
+pub fn path_activation_warning(
+    path: &OsStr,
+    prefix_bin: &Path,
+    binaries: &[String],
+    shell_loaded: bool,
+) -> Option<String> {
+    let dirs: Vec<PathBuf> = std::env::split_paths(path).collect();
+    let Some(position) = dirs.iter().position(|dir| dir == prefix_bin) else {
+        return Some(if shell_loaded {
+            format!(
+                "{} is not in PATH; add it to use these commands",
+                prefix_bin.display()
+            )
+        } else {
+            format!(
+                "{} is not in PATH yet; run `exec $SHELL` to load zerobrew's shell setup",
+                prefix_bin.display()
+            )
+        });
+    };
+
+    binaries.iter().find_map(|binary| {
+        let shadowing = dirs[..position]
+            .iter()
+            .find(|dir| dir.join(binary).is_file())?;
+        Some(format!(
+            "`{binary}` runs {} instead; move {} ahead of {} in PATH",
+            shadowing.join(binary).display(),
+            prefix_bin.display(),
+            shadowing.display()
+        ))
+    })
+}
+
+/// Lets the user deselect items of a multi-item operation; all start selected.
+///
+/// Returns `None` without prompting when `yes` is set, there is only one
+/// item, or the run isn't [`interactive`], so callers keep acting on every
+/// item. Otherwise returns which items to act on; cancelling keeps none.
+pub fn choose_items(labels: &[String], yes: bool) -> Option<Vec<bool>> {
+    if yes || labels.len() < 2 || !interactive() {
+        return None;
+    }
+
+    let term = Term::stdout();
+    let mut list = Checklist::new(labels.len());
+    println!(
+        "    {}",
+        style("↑/↓ move · space toggles · a toggles all · enter confirms · esc cancels").dim()
+    );
+    let _ = term.hide_cursor();
+    let confirmed = loop {
+        for (i, label) in labels.iter().enumerate() {
+            let pointer = if i == list.cursor { "›" } else { " " };
+            let mark = if list.selected[i] {
+                style("◉").green()
+            } else {
+                style("○").dim()
+            };
+            let _ = term.write_line(&format!("  {} {mark} {label}", style(pointer).cyan()));
+        }
+        let Ok(key) = term.read_key() else {
+            let _ = term.show_cursor();
+            return None;
+        };
+        match list.press(key) {
+            Some(confirmed) => break confirmed,
+            None => {
+                let _ = term.clear_last_lines(labels.len());
+            }
+        }
+    };
+    let _ = term.show_cursor();
+
+    Some(if confirmed {
+        list.selected
+    } else {
+        vec![false; labels.len()]
+    })
+}
+
+/// Selection state behind [`choose_items`].
+struct Checklist {
+    selected: Vec<bool>,
+    cursor: usize,
+}
+
+impl Checklist {
+    fn new(len: usize) -> Self {
+        Self {
+            selected: vec![true; len],
+            cursor: 0,
+        }
+    }
+
+    /// Applies one key press; `Some(true)` confirms, `Some(false)` cancels.
+    fn press(&mut self, key: Key) -> Option<bool> {
+        let last = self.selected.len() - 1;
+        match key {
+            Key::ArrowUp | Key::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            Key::ArrowDown | Key::Char('j') => self.cursor = (self.cursor + 1).min(last),
+            Key::Char(' ') => self.selected[self.cursor] = !self.selected[self.cursor],
+            Key::Char('a') => {
+                let all = !self.selected.iter().all(|selected| *selected);
+                self.selected.fill(all);
+            }
+            Key::Enter => return Some(true),
+            Key::Escape | Key::Char('q') => return Some(false),
+            _ => {}
+        }
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::normalize_formula_name;
+    use super::{Checklist, format_bytes, normalize_formula_name, path_activation_warning};
+    use console::Key;
+    use std::ffi::OsString;
+
+    #[test]
+    fn format_bytes_picks_unit() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GB");
+    }
 
     #[test]
     fn normalize_core_tap_formula() {
@@ -126,4 +349,52 @@ mod tests {
             "cask:docker-desktop".to_string()
         );
     }
+
+    #[test]
+    fn path_warning_covers_missing_and_shadowed_prefix() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let system = tmp.path().join("usr/bin");
+        let prefix_bin = tmp.path().join("prefix/bin");
+        std::fs::create_dir_all(&system).unwrap();
+        std::fs::write(system.join("jq"), "").unwrap();
+        let jq = ["jq".to_string()];
+
+        let path = std::env::join_paths([&system]).unwrap();
+        let warning = path_activation_warning(&path, &prefix_bin, &jq, false).unwrap();
+        assert!(warning.contains("exec $SHELL"));
+
+        let path = std::env::join_paths([&system, &prefix_bin]).unwrap();
+        let warning = path_activation_warning(&path, &prefix_bin, &jq, true).unwrap();
+        assert!(warning.starts_with("`jq` runs"));
+
+        let path = std::env::join_paths([&prefix_bin, &system]).unwrap();
+        assert!(path_activation_warning(&path, &prefix_bin, &jq, true).is_none());
+        assert!(
+            path_activation_warning(&OsString::new(), &prefix_bin, &[], true)
+                .unwrap()
+                .contains("not in PATH")
+        );
+    }
+
+    #[test]
+    fn checklist_toggles_items_under_the_cursor() {
+        let mut list = Checklist::new(3);
+        assert_eq!(list.press(Key::ArrowUp), None);
+        assert_eq!(list.press(Key::ArrowDown), None);
+        assert_eq!(list.press(Key::Char(' ')), None);
+        assert_eq!(list.selected, [true, false, true]);
+
+        list.press(Key::ArrowDown);
+        list.press(Key::ArrowDown);
+        list.press(Key::Char(' '));
+        assert_eq!(list.selected, [true, false, false]);
+
+        list.press(Key::Char('a'));
+        assert_eq!(list.selected, [true, true, true]);
+        list.press(Key::Char('a'));
+        assert_eq!(list.selected, [false, false, false]);
+
+        assert_eq!(list.press(Key::Enter), Some(true));
+        assert_eq!(list.press(Key::Escape), Some(false));
+    }
 }
@@ -23,6 +23,10 @@ pub struct BuildPlan {
     pub version: String,
     pub source_url: String,
     pub source_checksum: Option<String>,
+    /// Build from a checkout of the `head` repository rather than a
+    /// release archive; `source_branch` names the branch to check out.
+    pub head: bool,
+    pub source_branch: Option<String>,
     pub ruby_source_path: Option<String>,
     pub build_dependencies: Vec<String>,
     pub runtime_dependencies: Vec<String>,
@@ -32,19 +36,29 @@ pub struct BuildPlan {
 }
 
 impl BuildPlan {
+    /// Plan a build of the formula's stable source, or of its `head`
+    /// repository for a HEAD-only formula.
     pub fn from_formula(formula: &Formula, prefix: &Path) -> Option<Self> {
-        let source = formula.source_url()?;
+        let head = formula.is_head_only();
+        let (source_url, source_checksum) = if head {
+            (formula.head_url()?.to_string(), None)
+        } else {
+            let source = formula.source_url()?;
+            (source.url.clone(), source.checksum.clone())
+        };
         let version = formula.effective_version();
         let cellar_path = prefix.join("Cellar").join(&formula.name).join(&version);
 
         let all_build_deps = formula.all_build_dependencies();
-        let detected_system = detect_build_system(&source.url, &all_build_deps);
+        let detected_system = detect_build_system(&source_url, &all_build_deps);
 
         Some(Self {
             formula_name: formula.name.clone(),
             version,
-            source_url: source.url.clone(),
-            source_checksum: source.checksum.clone(),
+            source_url,
+            source_checksum,
+            head,
+            source_branch: formula.head_branch().filter(|_| head).map(str::to_string),
             ruby_source_path: formula.ruby_source_path.clone(),
             build_dependencies: all_build_deps,
             runtime_dependencies: formula.dependencies.clone(),
@@ -92,7 +106,8 @@ mod tests {
         Formula {
             name: name.to_string(),
             versions: Versions {
-                stable: "1.0.0".to_string(),
+                stable: Some("1.0.0".to_string()),
+                head: None,
             },
             dependencies: vec!["libfoo".to_string()],
             bottle: Bottle {
@@ -115,6 +130,14 @@ mod tests {
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            license: None,
+            deprecated: false,
+            deprecation_reason: None,
+            desc: None,
+            homepage: None,
+            service: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
         }
     }
 
@@ -150,6 +173,29 @@ mod tests {
         assert!(BuildPlan::from_formula(&f, &prefix).is_none());
     }
 
+    #[test]
+    fn head_only_formulas_build_from_the_head_repository() {
+        let mut f = test_formula("nightly", "https://example.com/src.tar.gz", &[]);
+        f.versions.stable = None;
+        f.urls = Some(FormulaUrls {
+            stable: None,
+            head: Some(serde_json::json!({
+                "url": "https://example.com/nightly.git",
+                "branch": "main"
+            })),
+        });
+        let prefix = PathBuf::from("/opt/zerobrew");
+        let plan = BuildPlan::from_formula(&f, &prefix).unwrap();
+        assert!(plan.head);
+        assert_eq!(plan.source_url, "https://example.com/nightly.git");
+        assert_eq!(plan.source_checksum, None);
+        assert_eq!(plan.source_branch.as_deref(), Some("main"));
+        assert_eq!(plan.cellar_path, prefix.join("Cellar/nightly/HEAD"));
+
+        f.urls = None;
+        assert!(BuildPlan::from_formula(&f, &prefix).is_none());
+    }
+
     #[test]
     fn cellar_path_includes_version() {
         let f = test_formula("wget", "https://example.com/src.tar.gz", &[]);
@@ -1,27 +1,368 @@
+use serde::Serialize;
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 pub struct ConflictedLink {
     pub path: PathBuf,
     pub owned_by: Option<String>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// A formula in a plan the license policy doesn't allow.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LicenseViolation {
+    pub name: String,
+    pub license: Option<String>,
+    /// How the plan came to include it: a requested formula, then each
+    /// dependency down to this one's dependent. Empty when it was requested.
+    pub required_by: Vec<String>,
+}
+
+/// Where a subsystem failure happened and what caused it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ErrorContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formula: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The underlying error (I/O, SQLite, HTTP, ...) as reported by its source.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// HTTP status the server answered with, for failures that got that far.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// The connection failed or dropped before a complete answer arrived.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum Error {
-    UnsupportedBottle { name: String },
-    ChecksumMismatch { expected: String, actual: String },
-    LinkConflict { conflicts: Vec<ConflictedLink> },
-    StoreCorruption { message: String },
-    NetworkFailure { message: String },
-    MissingFormula { name: String },
-    UnsupportedTap { name: String },
-    UnsupportedFormula { name: String, reason: String },
-    DependencyCycle { cycle: Vec<String> },
-    NotInstalled { name: String },
-    FileError { message: String },
-    InvalidArgument { message: String },
-    ExecutionError { message: String },
+    UnsupportedBottle {
+        name: String,
+    },
+    /// The formula only defines a `head` spec, so there is nothing to
+    /// install but a build of its repository.
+    HeadOnly {
+        name: String,
+    },
+    /// `name@version` was asked for, but that version is neither installed,
+    /// retained nor the one the formula currently ships.
+    VersionUnavailable {
+        name: String,
+        version: String,
+        current: String,
+    },
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    LinkConflict {
+        conflicts: Vec<ConflictedLink>,
+    },
+    StoreCorruption {
+        message: String,
+    },
+    NetworkFailure {
+        message: String,
+    },
+    MissingFormula {
+        name: String,
+    },
+    UnsupportedTap {
+        name: String,
+    },
+    UnsupportedFormula {
+        name: String,
+        reason: String,
+    },
+    DependencyCycle {
+        cycle: Vec<String>,
+    },
+    NotInstalled {
+        name: String,
+    },
+    HasDependents {
+        name: String,
+        dependents: Vec<String>,
+    },
+    FileError {
+        message: String,
+    },
+    InvalidArgument {
+        message: String,
+    },
+    LicensePolicy {
+        violations: Vec<LicenseViolation>,
+    },
+    /// `name` declares a conflict with the installed formula `installed`.
+    FormulaConflict {
+        name: String,
+        installed: String,
+        reason: Option<String>,
+    },
+    /// Files of `name`'s keg were edited or added since it was installed.
+    ModifiedKegFiles {
+        name: String,
+        files: Vec<String>,
+    },
+    /// What `zb install --locked` resolved differs from `zb.lock`.
+    LockfileDrift {
+        drift: Vec<String>,
+    },
+    /// A warning that `--strict` turned into an error.
+    Strict {
+        message: String,
+    },
+    ExecutionError {
+        message: String,
+    },
+    Timeout {
+        message: String,
+    },
+    Download {
+        message: String,
+        context: Box<ErrorContext>,
+    },
+    Unpack {
+        message: String,
+        context: Box<ErrorContext>,
+    },
+    Relocate {
+        message: String,
+        context: Box<ErrorContext>,
+    },
+    Link {
+        message: String,
+        context: Box<ErrorContext>,
+    },
+    Db {
+        message: String,
+        context: Box<ErrorContext>,
+    },
+}
+
+impl Error {
+    pub fn download(message: impl Into<String>) -> Self {
+        Error::Download {
+            message: message.into(),
+            context: Box::default(),
+        }
+    }
+
+    pub fn unpack(message: impl Into<String>) -> Self {
+        Error::Unpack {
+            message: message.into(),
+            context: Box::default(),
+        }
+    }
+
+    pub fn relocate(message: impl Into<String>) -> Self {
+        Error::Relocate {
+            message: message.into(),
+            context: Box::default(),
+        }
+    }
+
+    pub fn link(message: impl Into<String>) -> Self {
+        Error::Link {
+            message: message.into(),
+            context: Box::default(),
+        }
+    }
+
+    pub fn db(message: impl Into<String>) -> Self {
+        Error::Db {
+            message: message.into(),
+            context: Box::default(),
+        }
+    }
+
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::Download { context, .. }
+            | Error::Unpack { context, .. }
+            | Error::Relocate { context, .. }
+            | Error::Link { context, .. }
+            | Error::Db { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    fn context_mut(&mut self) -> Option<&mut ErrorContext> {
+        match self {
+            Error::Download { context, .. }
+            | Error::Unpack { context, .. }
+            | Error::Relocate { context, .. }
+            | Error::Link { context, .. }
+            | Error::Db { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Record the formula being processed. Keeps a formula set closer to the failure.
+    pub fn with_formula(mut self, formula: impl Into<String>) -> Self {
+        if let Some(context) = self.context_mut()
+            && context.formula.is_none()
+        {
+            context.formula = Some(formula.into());
+        }
+        self
+    }
+
+    pub fn with_path(mut self, path: impl AsRef<Path>) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.path = Some(path.as_ref().to_path_buf());
+        }
+        self
+    }
+
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.url = Some(url.into());
+        }
+        self
+    }
+
+    pub fn with_source(mut self, source: impl fmt::Display) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.source = Some(source.to_string());
+        }
+        self
+    }
+
+    pub fn with_status(mut self, status: u16) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.status = Some(status);
+        }
+        self
+    }
+
+    /// Mark the failure as a connection that failed or dropped mid-transfer.
+    pub fn interrupted(mut self) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.interrupted = true;
+        }
+        self
+    }
+
+    /// HTTP status behind the failure, if the server answered.
+    pub fn status(&self) -> Option<u16> {
+        self.context()?.status
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.context().is_some_and(|context| context.interrupted)
+    }
+
+    /// The install phase a subsystem error belongs to.
+    pub fn phase(&self) -> Option<&'static str> {
+        match self {
+            Error::Download { .. } => Some("download"),
+            Error::Unpack { .. } => Some("unpack"),
+            Error::Relocate { .. } => Some("relocate"),
+            Error::Link { .. } | Error::LinkConflict { .. } => Some("link"),
+            Error::Db { .. } => Some("db"),
+            _ => None,
+        }
+    }
+
+    /// A suggestion for what the user can do about the error, if there is one.
+    pub fn hint(&self) -> Option<String> {
+        let formula = self
+            .context()
+            .and_then(|c| c.formula.as_deref())
+            .unwrap_or("<formula>");
+        match self {
+            Error::Download { .. } | Error::NetworkFailure { .. } | Error::Timeout { .. } => Some(
+                "check your network connection or proxy settings and try again".to_string(),
+            ),
+            Error::Unpack { .. } | Error::ChecksumMismatch { .. } => Some(
+                "the downloaded bottle may be damaged; run `zb gc` and try again".to_string(),
+            ),
+            Error::Relocate { .. } => Some(format!(
+                "run `zb uninstall {formula}` and install it again; if it keeps failing, try `zb install -s {formula}`"
+            )),
+            Error::Link { .. } => Some(format!(
+                "check permissions on the prefix, then run `zb uninstall {formula}` and install it again"
+            )),
+            Error::LinkConflict { .. } => Some(format!(
+                "remove the conflicting files or uninstall the formula that owns them, or run `zb link --overwrite {formula}` to replace them"
+            )),
+            Error::Db { .. } => Some(
+                "make sure no other zb process is running; `zb reset` rebuilds the database from scratch"
+                    .to_string(),
+            ),
+            Error::HasDependents { .. } => Some(
+                "uninstall those first, or pass --ignore-dependencies to remove it anyway"
+                    .to_string(),
+            ),
+            Error::HeadOnly { name } => Some(format!(
+                "build it from its repository with `zb install --build-from-source {name}`"
+            )),
+            Error::VersionUnavailable { name, .. } => Some(format!(
+                "run `zbx {name}` for the current version; older ones are only kept when `retain_versions` is set"
+            )),
+            Error::LicensePolicy { violations } => Some(format!(
+                "add {} to `exempt` under [licenses] in config.toml to allow it anyway",
+                violations
+                    .iter()
+                    .map(|violation| format!("\"{}\"", violation.name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+            Error::FormulaConflict { installed, .. } => Some(format!(
+                "run `zb uninstall {installed}` first, or pass --force to install it anyway"
+            )),
+            Error::ModifiedKegFiles { name, .. } => Some(format!(
+                "pass --backup to keep copies of them, or --force to delete them: `zb uninstall --backup {name}`"
+            )),
+            Error::LockfileDrift { .. } => Some(
+                "if the change is expected, rewrite the lockfile with `zb plan --lock`".to_string(),
+            ),
+            Error::Strict { .. } => Some(
+                "address the warning, or run without --strict to continue past it".to_string(),
+            ),
+            Error::MissingFormula { .. } => {
+                Some("check the spelling, or use `tap/formula` for formulas outside homebrew/core".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Typed representation for `--json` output.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let serde_json::Value::Object(map) = &mut value {
+            map.insert("error".into(), self.to_string().into());
+            if let Some(phase) = self.phase() {
+                map.insert("phase".into(), phase.into());
+            }
+            if let Some(hint) = self.hint() {
+                map.insert("hint".into(), hint.into());
+            }
+        }
+        value
+    }
+}
+
+fn write_context(f: &mut fmt::Formatter<'_>, message: &str, context: &ErrorContext) -> fmt::Result {
+    if let Some(formula) = &context.formula {
+        write!(f, " for '{formula}'")?;
+    }
+    write!(f, ": {message}")?;
+    if let Some(path) = &context.path {
+        write!(f, " ({})", path.display())?;
+    } else if let Some(url) = &context.url {
+        write!(f, " ({url})")?;
+    }
+    if let Some(source) = &context.source {
+        write!(f, ": {source}")?;
+    }
+    Ok(())
 }
 
 impl fmt::Display for Error {
@@ -30,6 +371,18 @@ impl fmt::Display for Error {
             Error::UnsupportedBottle { name } => {
                 write!(f, "unsupported bottle for formula '{name}'")
             }
+            Error::HeadOnly { name } => write!(
+                f,
+                "formula '{name}' has no stable version, only HEAD, and no bottles"
+            ),
+            Error::VersionUnavailable {
+                name,
+                version,
+                current,
+            } => write!(
+                f,
+                "formula '{name}' {version} is not installed or retained, and {current} is the version available"
+            ),
             Error::ChecksumMismatch { expected, actual } => {
                 write!(f, "checksum mismatch (expected {expected}, got {actual})")
             }
@@ -68,9 +421,75 @@ impl fmt::Display for Error {
                 write!(f, "dependency cycle detected: {rendered}")
             }
             Error::NotInstalled { name } => write!(f, "formula '{name}' is not installed"),
+            Error::HasDependents { name, dependents } => write!(
+                f,
+                "formula '{name}' is required by {}",
+                dependents.join(", ")
+            ),
             Error::FileError { message } => write!(f, "file error: {message}"),
             Error::InvalidArgument { message } => write!(f, "invalid argument: {message}"),
+            Error::Strict { message } => write!(f, "{message} (--strict)"),
+            Error::LicensePolicy { violations } => {
+                write!(f, "license policy does not allow:")?;
+                for violation in violations {
+                    write!(f, "\n  '{}'", violation.name)?;
+                    match &violation.license {
+                        Some(license) => write!(f, " ({license})")?,
+                        None => write!(f, " (no license recorded)")?,
+                    }
+                    if !violation.required_by.is_empty() {
+                        write!(f, ", required by {}", violation.required_by.join(" → "))?;
+                    }
+                }
+                Ok(())
+            }
+            Error::FormulaConflict {
+                name,
+                installed,
+                reason,
+            } => {
+                write!(f, "formula '{name}' conflicts with installed '{installed}'")?;
+                match reason {
+                    Some(reason) => write!(f, ": {reason}"),
+                    None => Ok(()),
+                }
+            }
+            Error::ModifiedKegFiles { name, files } => {
+                write!(f, "'{name}' has files that changed since it was installed:")?;
+                for file in files {
+                    write!(f, "\n  {file}")?;
+                }
+                Ok(())
+            }
+            Error::LockfileDrift { drift } => {
+                write!(f, "resolved plan no longer matches zb.lock:")?;
+                for line in drift {
+                    write!(f, "\n  {line}")?;
+                }
+                Ok(())
+            }
             Error::ExecutionError { message } => write!(f, "{message}"),
+            Error::Timeout { message } => write!(f, "timed out: {message}"),
+            Error::Download { message, context } => {
+                write!(f, "download failed")?;
+                write_context(f, message, context)
+            }
+            Error::Unpack { message, context } => {
+                write!(f, "unpack failed")?;
+                write_context(f, message, context)
+            }
+            Error::Relocate { message, context } => {
+                write!(f, "relocation failed")?;
+                write_context(f, message, context)
+            }
+            Error::Link { message, context } => {
+                write!(f, "linking failed")?;
+                write_context(f, message, context)
+            }
+            Error::Db { message, context } => {
+                write!(f, "database error")?;
+                write_context(f, message, context)
+            }
         }
     }
 }
@@ -89,4 +508,191 @@ mod tests {
 
         assert!(err.to_string().contains("libheif"));
     }
+
+    #[test]
+    fn head_only_error_points_at_source_builds() {
+        let err = Error::HeadOnly {
+            name: "nightly-tool".to_string(),
+        };
+
+        assert!(err.to_string().contains("only HEAD"));
+        assert!(
+            err.hint()
+                .unwrap()
+                .contains("--build-from-source nightly-tool")
+        );
+    }
+
+    #[test]
+    fn version_unavailable_error_names_the_current_version() {
+        let err = Error::VersionUnavailable {
+            name: "jq".to_string(),
+            version: "1.6".to_string(),
+            current: "1.7.1".to_string(),
+        };
+
+        assert!(err.to_string().contains("'jq' 1.6"));
+        assert!(err.to_string().contains("1.7.1 is the version available"));
+        assert!(err.hint().unwrap().contains("zbx jq"));
+    }
+
+    #[test]
+    fn license_policy_errors_show_how_the_formula_was_pulled_in() {
+        let err = Error::LicensePolicy {
+            violations: vec![
+                LicenseViolation {
+                    name: "libfoo".to_string(),
+                    license: Some("AGPL-3.0-only".to_string()),
+                    required_by: vec!["app".to_string(), "libbar".to_string()],
+                },
+                LicenseViolation {
+                    name: "app".to_string(),
+                    license: None,
+                    required_by: Vec::new(),
+                },
+            ],
+        };
+
+        assert_eq!(
+            err.to_string(),
+            "license policy does not allow:\n  'libfoo' (AGPL-3.0-only), required by app → libbar\n  'app' (no license recorded)"
+        );
+        assert!(err.hint().unwrap().contains("\"libfoo\", \"app\""));
+    }
+
+    #[test]
+    fn formula_conflicts_name_the_installed_formula() {
+        let err = Error::FormulaConflict {
+            name: "gnupg".to_string(),
+            installed: "gpg2".to_string(),
+            reason: Some("both install a `gpg` binary".to_string()),
+        };
+
+        assert_eq!(
+            err.to_string(),
+            "formula 'gnupg' conflicts with installed 'gpg2': both install a `gpg` binary"
+        );
+        assert!(err.hint().unwrap().contains("zb uninstall gpg2"));
+        assert_eq!(err.to_json()["kind"], "formula_conflict");
+    }
+
+    #[test]
+    fn modified_keg_files_are_listed_with_a_way_out() {
+        let err = Error::ModifiedKegFiles {
+            name: "nginx".to_string(),
+            files: vec!["modified etc/nginx/nginx.conf".to_string()],
+        };
+
+        assert_eq!(
+            err.to_string(),
+            "'nginx' has files that changed since it was installed:\n  modified etc/nginx/nginx.conf"
+        );
+        assert!(err.hint().unwrap().contains("--backup nginx"));
+        assert_eq!(err.to_json()["kind"], "modified_keg_files");
+    }
+
+    #[test]
+    fn lockfile_drift_lists_every_difference() {
+        let err = Error::LockfileDrift {
+            drift: vec![
+                "jq: version 1.7 → 1.7.1".to_string(),
+                "oniguruma 6.9.9 is not locked".to_string(),
+            ],
+        };
+
+        assert_eq!(
+            err.to_string(),
+            "resolved plan no longer matches zb.lock:\n  jq: version 1.7 → 1.7.1\n  oniguruma 6.9.9 is not locked"
+        );
+        assert!(err.hint().unwrap().contains("zb plan --lock"));
+        assert_eq!(err.to_json()["kind"], "lockfile_drift");
+    }
+
+    #[test]
+    fn strict_errors_name_the_flag() {
+        let err = Error::Strict {
+            message: "formula 'jq' is deprecated".to_string(),
+        };
+
+        assert_eq!(err.to_string(), "formula 'jq' is deprecated (--strict)");
+        assert!(err.hint().unwrap().contains("without --strict"));
+        assert_eq!(err.to_json()["kind"], "strict");
+    }
+
+    #[test]
+    fn subsystem_errors_render_context() {
+        let err = Error::unpack("failed to read tarball")
+            .with_path("/tmp/blob.tar.gz")
+            .with_source("unexpected end of file")
+            .with_formula("jq");
+
+        assert_eq!(
+            err.to_string(),
+            "unpack failed for 'jq': failed to read tarball (/tmp/blob.tar.gz): unexpected end of file"
+        );
+        assert_eq!(err.phase(), Some("unpack"));
+        assert!(err.hint().is_some());
+    }
+
+    #[test]
+    fn with_formula_keeps_innermost_formula() {
+        let err = Error::link("failed to create symlink")
+            .with_formula("openssl@3")
+            .with_formula("curl");
+
+        assert_eq!(
+            err.context().and_then(|c| c.formula.as_deref()),
+            Some("openssl@3")
+        );
+    }
+
+    #[test]
+    fn with_formula_leaves_plain_variants_alone() {
+        let err = Error::NotInstalled {
+            name: "jq".to_string(),
+        }
+        .with_formula("jq");
+
+        assert_eq!(
+            err,
+            Error::NotInstalled {
+                name: "jq".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn network_failures_keep_their_status_and_interruption() {
+        let err = Error::download("HTTP 503 Service Unavailable").with_status(503);
+        assert_eq!(err.status(), Some(503));
+        assert!(!err.is_interrupted());
+        assert_eq!(err.to_json()["context"]["status"], 503);
+
+        let err = Error::download("failed to read chunk").interrupted();
+        assert_eq!(err.status(), None);
+        assert!(err.is_interrupted());
+
+        let err = Error::NetworkFailure {
+            message: "HTTP 503".to_string(),
+        }
+        .with_status(503);
+        assert_eq!(err.status(), None);
+    }
+
+    #[test]
+    fn json_is_tagged_by_kind() {
+        let json = Error::db("failed to open database")
+            .with_source("unable to open database file")
+            .to_json();
+
+        assert_eq!(json["kind"], "db");
+        assert_eq!(json["phase"], "db");
+        assert_eq!(json["context"]["source"], "unable to open database file");
+        assert!(
+            json["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("database error")
+        );
+    }
 }
@@ -1,3 +1,6 @@
+use std::cmp::Reverse;
+use std::sync::OnceLock;
+
 use crate::{Error, Formula};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -7,108 +10,189 @@ pub struct SelectedBottle {
     pub sha256: String,
 }
 
-pub fn select_bottle(formula: &Formula) -> Result<SelectedBottle, Error> {
-    // Prefer macOS ARM bottles in order of preference (newest first)
-    #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-    {
-        let macos_tags = [
-            "arm64_tahoe",
-            "arm64_sequoia",
-            "arm64_sonoma",
-            "arm64_ventura",
-        ];
-
-        for preferred_tag in macos_tags {
-            if let Some(file) = formula.bottle.stable.files.get(preferred_tag) {
-                return Ok(SelectedBottle {
-                    tag: preferred_tag.to_string(),
-                    url: file.url.clone(),
-                    sha256: file.sha256.clone(),
-                });
-            }
-        }
-    }
+/// macOS releases bottles are built for, newest first.
+const MACOS_RELEASES: [(&str, u32); 6] = [
+    ("tahoe", 26),
+    ("sequoia", 15),
+    ("sonoma", 14),
+    ("ventura", 13),
+    ("monterey", 12),
+    ("big_sur", 11),
+];
 
-    // Prefer macOS Intel bottles in order of preference (newest first)
-    // Homebrew uses bare OS version names (e.g. "sonoma") for Intel Mac bottles
-    #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-    {
-        let macos_tags = ["tahoe", "sequoia", "sonoma", "ventura"];
-
-        for preferred_tag in macos_tags {
-            if let Some(file) = formula.bottle.stable.files.get(preferred_tag) {
-                return Ok(SelectedBottle {
-                    tag: preferred_tag.to_string(),
-                    url: file.url.clone(),
-                    sha256: file.sha256.clone(),
-                });
-            }
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    Arm64,
+    X86_64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    /// `version` is the major release (14 for Sonoma), when it could be read.
+    MacOs {
+        version: Option<u32>,
+    },
+    Linux,
+    /// Only platform-independent (`all`) bottles apply.
+    Other,
+}
+
+/// What a bottle has to run on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Platform {
+    pub os: Os,
+    pub arch: Arch,
+}
+
+impl Platform {
+    /// The machine zerobrew is running on.
+    pub fn current() -> Self {
+        static CURRENT: OnceLock<Platform> = OnceLock::new();
+        *CURRENT.get_or_init(|| {
+            let arch = if cfg!(target_arch = "aarch64") {
+                Arch::Arm64
+            } else {
+                Arch::X86_64
+            };
+            let os = if cfg!(target_os = "macos") {
+                Os::MacOs {
+                    version: host_macos_version(),
+                }
+            } else if cfg!(target_os = "linux") {
+                Os::Linux
+            } else {
+                Os::Other
+            };
+            Platform { os, arch }
+        })
     }
 
-    // Prefer Linux x86_64 bottles
-    #[cfg(target_os = "linux")]
-    {
-        let linux_tags = ["x86_64_linux"];
-        for preferred_tag in linux_tags {
-            if let Some(file) = formula.bottle.stable.files.get(preferred_tag) {
-                return Ok(SelectedBottle {
-                    tag: preferred_tag.to_string(),
-                    url: file.url.clone(),
-                    sha256: file.sha256.clone(),
-                });
+    /// Bottle tag for this platform, e.g. `arm64_sonoma` or `x86_64_linux`.
+    /// Intel Macs use the bare release name.
+    pub fn bottle_tag(&self) -> Option<String> {
+        match (self.os, self.arch) {
+            (Os::Linux, Arch::Arm64) => Some("arm64_linux".to_string()),
+            (Os::Linux, Arch::X86_64) => Some("x86_64_linux".to_string()),
+            (Os::MacOs { version }, arch) => {
+                let (name, _) = MACOS_RELEASES
+                    .iter()
+                    .find(|(_, major)| Some(*major) == version)?;
+                Some(match arch {
+                    Arch::Arm64 => format!("arm64_{name}"),
+                    Arch::X86_64 => name.to_string(),
+                })
             }
+            (Os::Other, _) => None,
         }
     }
 
-    // Check for universal "all" bottle (platform-independent packages like ca-certificates)
-    if let Some(file) = formula.bottle.stable.files.get("all") {
-        return Ok(SelectedBottle {
-            tag: "all".to_string(),
-            url: file.url.clone(),
-            sha256: file.sha256.clone(),
-        });
+    /// Whether a bottle built for `tag` can run on this platform.
+    pub fn supports(&self, tag: &str) -> bool {
+        self.rank(tag).is_some()
     }
 
-    // Fallback: any arm64 macOS bottle (but not linux)
-    #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-    for (tag, file) in &formula.bottle.stable.files {
-        if tag.starts_with("arm64_") && !tag.contains("linux") {
-            return Ok(SelectedBottle {
-                tag: tag.clone(),
-                url: file.url.clone(),
-                sha256: file.sha256.clone(),
-            });
+    /// Where `tag` ranks for this platform (lower is better), or `None` when
+    /// its bottle can't run here.
+    ///
+    /// On macOS the newest release not newer than the host wins, then `all`.
+    /// Release names we don't know are only tried when the host release is
+    /// unknown too, since they are most likely newer than the host.
+    fn rank(&self, tag: &str) -> Option<(u8, Reverse<u32>)> {
+        if tag == "all" {
+            return Some((1, Reverse(0)));
         }
-    }
-
-    // Fallback: any Intel macOS bottle (bare OS name, not arm64_ or linux)
-    #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-    for (tag, file) in &formula.bottle.stable.files {
-        if !tag.starts_with("arm64_") && !tag.contains("linux") && tag != "all" {
-            return Ok(SelectedBottle {
-                tag: tag.clone(),
-                url: file.url.clone(),
-                sha256: file.sha256.clone(),
-            });
+        match self.os {
+            Os::Linux => {
+                let native = match self.arch {
+                    Arch::Arm64 => "arm64_linux",
+                    Arch::X86_64 => "x86_64_linux",
+                };
+                (tag == native).then_some((0, Reverse(0)))
+            }
+            Os::MacOs { version } => {
+                let release = match self.arch {
+                    Arch::Arm64 => tag.strip_prefix("arm64_")?,
+                    Arch::X86_64 if tag.starts_with("arm64_") => return None,
+                    Arch::X86_64 => tag.strip_prefix("x86_64_").unwrap_or(tag),
+                };
+                if release.contains("linux") {
+                    return None;
+                }
+                match MACOS_RELEASES.iter().find(|(name, _)| *name == release) {
+                    Some((_, major)) if version.is_none_or(|host| *major <= host) => {
+                        Some((0, Reverse(*major)))
+                    }
+                    Some(_) => None,
+                    None => version.is_none().then_some((2, Reverse(0))),
+                }
+            }
+            Os::Other => None,
         }
     }
+}
 
-    // Fallback for Linux: any linux bottle
-    #[cfg(target_os = "linux")]
-    for (tag, file) in &formula.bottle.stable.files {
-        if tag.contains("linux") {
-            return Ok(SelectedBottle {
-                tag: tag.clone(),
-                url: file.url.clone(),
-                sha256: file.sha256.clone(),
-            });
-        }
+/// Major macOS release from `SystemVersion.plist`, e.g. 14 on Sonoma.
+fn host_macos_version() -> Option<u32> {
+    let plist = std::fs::read_to_string("/System/Library/CoreServices/SystemVersion.plist").ok()?;
+    let (_, rest) = plist.split_once("<key>ProductVersion</key>")?;
+    let (_, rest) = rest.split_once("<string>")?;
+    let (version, _) = rest.split_once("</string>")?;
+    parse_macos_major(version)
+}
+
+fn parse_macos_major(version: &str) -> Option<u32> {
+    let major: u32 = version.trim().split('.').next()?.parse().ok()?;
+    MACOS_RELEASES
+        .iter()
+        .any(|(_, known)| *known == major)
+        .then_some(major)
+}
+
+/// Pick the bottle for the machine zerobrew is running on.
+pub fn select_bottle(formula: &Formula) -> Result<SelectedBottle, Error> {
+    select_bottle_for(formula, &Platform::current())
+}
+
+/// Pick the bottle published for `preferred` when there is one, falling back
+/// to the best bottle of `formula` that runs on `platform`.
+pub fn select_bottle_preferring(
+    formula: &Formula,
+    platform: &Platform,
+    preferred: Option<&str>,
+) -> Result<SelectedBottle, Error> {
+    let forced = preferred.and_then(|tag| formula.bottle.stable.files.get_key_value(tag));
+    match forced {
+        Some((tag, file)) => Ok(SelectedBottle {
+            tag: tag.clone(),
+            url: file.url.clone(),
+            sha256: file.sha256.clone(),
+        }),
+        None => select_bottle_for(formula, platform),
     }
+}
 
-    Err(Error::UnsupportedBottle {
-        name: formula.name.clone(),
-    })
+/// Pick the best bottle of `formula` that runs on `platform`.
+pub fn select_bottle_for(formula: &Formula, platform: &Platform) -> Result<SelectedBottle, Error> {
+    if formula.is_head_only() {
+        return Err(Error::HeadOnly {
+            name: formula.name.clone(),
+        });
+    }
+    formula
+        .bottle
+        .stable
+        .files
+        .iter()
+        .filter_map(|(tag, file)| Some((platform.rank(tag)?, tag, file)))
+        .min_by_key(|(rank, _, _)| *rank)
+        .map(|(_, tag, file)| SelectedBottle {
+            tag: tag.clone(),
+            url: file.url.clone(),
+            sha256: file.sha256.clone(),
+        })
+        .ok_or_else(|| Error::UnsupportedBottle {
+            name: formula.name.clone(),
+        })
 }
 
 #[cfg(test)]
@@ -150,7 +234,7 @@ mod tests {
             );
         }
 
-        #[cfg(target_os = "linux")]
+        #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
         {
             assert_eq!(selected.tag, "x86_64_linux");
             assert_eq!(
@@ -179,7 +263,8 @@ mod tests {
         let formula = Formula {
             name: "ca-certificates".to_string(),
             versions: Versions {
-                stable: "2024-01-01".to_string(),
+                stable: Some("2024-01-01".to_string()),
+                head: None,
             },
             dependencies: Vec::new(),
             bottle: Bottle {
@@ -194,6 +279,14 @@ mod tests {
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            license: None,
+            deprecated: false,
+            deprecation_reason: None,
+            desc: None,
+            homepage: None,
+            service: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
         };
 
         let selected = select_bottle(&formula).unwrap();
@@ -217,7 +310,8 @@ mod tests {
         let formula = Formula {
             name: "legacy".to_string(),
             versions: Versions {
-                stable: "0.1.0".to_string(),
+                stable: Some("0.1.0".to_string()),
+                head: None,
             },
             dependencies: Vec::new(),
             bottle: Bottle {
@@ -232,6 +326,14 @@ mod tests {
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            license: None,
+            deprecated: false,
+            deprecation_reason: None,
+            desc: None,
+            homepage: None,
+            service: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
         };
 
         let err = select_bottle(&formula).unwrap_err();
@@ -257,7 +359,8 @@ mod tests {
         let formula = Formula {
             name: "legacy".to_string(),
             versions: Versions {
-                stable: "0.1.0".to_string(),
+                stable: Some("0.1.0".to_string()),
+                head: None,
             },
             dependencies: Vec::new(),
             bottle: Bottle {
@@ -272,6 +375,14 @@ mod tests {
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            license: None,
+            deprecated: false,
+            deprecation_reason: None,
+            desc: None,
+            homepage: None,
+            service: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
         };
 
         let err = select_bottle(&formula).unwrap_err();
@@ -280,4 +391,119 @@ mod tests {
             Error::UnsupportedBottle { name } if name == "legacy"
         ));
     }
+
+    fn formula_with_tags(tags: &[&str]) -> Formula {
+        let files = tags
+            .iter()
+            .map(|tag| {
+                let file = BottleFile {
+                    url: format!("https://example.com/foo.{tag}.bottle.tar.gz"),
+                    sha256: "0".repeat(64),
+                };
+                (tag.to_string(), file)
+            })
+            .collect();
+        let mut formula: Formula =
+            serde_json::from_str(include_str!("../../fixtures/formula_foo.json")).unwrap();
+        formula.bottle.stable.files = files;
+        formula
+    }
+
+    fn macos(version: Option<u32>, arch: Arch) -> Platform {
+        Platform {
+            os: Os::MacOs { version },
+            arch,
+        }
+    }
+
+    #[test]
+    fn prefers_newest_macos_bottle_not_newer_than_host() {
+        let sonoma = macos(Some(14), Arch::Arm64);
+        let tag = |tags: &[&str]| {
+            select_bottle_for(&formula_with_tags(tags), &sonoma).map(|selected| selected.tag)
+        };
+
+        assert_eq!(
+            tag(&["arm64_sequoia", "arm64_sonoma", "arm64_ventura"]).unwrap(),
+            "arm64_sonoma"
+        );
+        assert_eq!(
+            tag(&["arm64_sequoia", "arm64_ventura", "arm64_monterey"]).unwrap(),
+            "arm64_ventura"
+        );
+        assert_eq!(tag(&["arm64_sequoia", "all"]).unwrap(), "all");
+        assert!(tag(&["arm64_sequoia", "arm64_tahoe", "sonoma"]).is_err());
+        assert!(tag(&["arm64_someday"]).is_err());
+
+        // Without a known host release every known release is a candidate.
+        let unknown = macos(None, Arch::Arm64);
+        let formula = formula_with_tags(&["arm64_someday", "arm64_sequoia", "arm64_sonoma"]);
+        assert_eq!(
+            select_bottle_for(&formula, &unknown).unwrap().tag,
+            "arm64_sequoia"
+        );
+        let formula = formula_with_tags(&["arm64_someday", "x86_64_linux"]);
+        assert_eq!(
+            select_bottle_for(&formula, &unknown).unwrap().tag,
+            "arm64_someday"
+        );
+    }
+
+    #[test]
+    fn intel_macs_use_bare_release_tags() {
+        let formula = formula_with_tags(&["arm64_sonoma", "sequoia", "ventura", "x86_64_linux"]);
+        let sonoma = macos(Some(14), Arch::X86_64);
+        assert_eq!(select_bottle_for(&formula, &sonoma).unwrap().tag, "ventura");
+        assert_eq!(sonoma.bottle_tag().as_deref(), Some("sonoma"));
+        assert_eq!(
+            macos(Some(14), Arch::Arm64).bottle_tag().as_deref(),
+            Some("arm64_sonoma")
+        );
+        assert_eq!(macos(None, Arch::Arm64).bottle_tag(), None);
+    }
+
+    #[test]
+    fn linux_only_takes_its_own_architecture() {
+        let formula = formula_with_tags(&["arm64_linux", "arm64_sonoma", "x86_64_linux"]);
+        let arm = Platform {
+            os: Os::Linux,
+            arch: Arch::Arm64,
+        };
+        let intel = Platform {
+            os: Os::Linux,
+            arch: Arch::X86_64,
+        };
+        assert_eq!(
+            select_bottle_for(&formula, &arm).unwrap().tag,
+            "arm64_linux"
+        );
+        assert_eq!(
+            select_bottle_for(&formula, &intel).unwrap().tag,
+            "x86_64_linux"
+        );
+        assert!(select_bottle_for(&formula_with_tags(&["arm64_linux"]), &intel).is_err());
+    }
+
+    #[test]
+    fn preferred_tag_wins_when_published_and_falls_back_otherwise() {
+        let beta = macos(None, Arch::Arm64);
+        let formula = formula_with_tags(&["arm64_sequoia", "arm64_ventura"]);
+        let tag = |preferred| {
+            select_bottle_preferring(&formula, &beta, preferred)
+                .unwrap()
+                .tag
+        };
+        assert_eq!(tag(Some("arm64_ventura")), "arm64_ventura");
+        assert_eq!(tag(Some("arm64_tahoe")), "arm64_sequoia");
+        assert_eq!(tag(None), "arm64_sequoia");
+        assert!(beta.supports("arm64_ventura"));
+        assert!(!beta.supports("x86_64_linux"));
+    }
+
+    #[test]
+    fn macos_versions_map_to_known_releases() {
+        assert_eq!(parse_macos_major("14.6.1"), Some(14));
+        assert_eq!(parse_macos_major("26.0"), Some(26));
+        assert_eq!(parse_macos_major("10.15"), None);
+    }
 }
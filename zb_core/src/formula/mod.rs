@@ -2,11 +2,13 @@ pub mod bottle;
 pub mod resolve;
 pub mod types;
 
-pub use bottle::{SelectedBottle, select_bottle};
+pub use bottle::{
+    Arch, Os, Platform, SelectedBottle, select_bottle, select_bottle_for, select_bottle_preferring,
+};
 pub use resolve::resolve_closure;
 pub use types::{
-    Bottle, BottleFile, BottleStable, Formula, FormulaUrls, KegOnly, RubySourceChecksum, SourceUrl,
-    UsesFromMacos, Versions,
+    Bottle, BottleFile, BottleStable, Formula, FormulaUrls, KegOnly, RubySourceChecksum, Service,
+    SourceUrl, UsesFromMacos, Versions,
 };
 
 /// Extract the formula token from an install key.
@@ -23,9 +25,26 @@ pub fn formula_token(name: &str) -> &str {
         .unwrap_or("")
 }
 
+/// Whether `version` is the `requested` one, which may leave out trailing
+/// components or the revision: `1.7` matches `1.7.1` and `1.7_1`, not `1.70`.
+pub fn version_matches(requested: &str, version: &str) -> bool {
+    version
+        .strip_prefix(requested)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '_', '-']))
+}
+
 #[cfg(test)]
 mod tests {
-    use super::formula_token;
+    use super::{formula_token, version_matches};
+
+    #[test]
+    fn versions_match_on_component_boundaries() {
+        assert!(version_matches("1.7.1", "1.7.1"));
+        assert!(version_matches("1.7", "1.7.1"));
+        assert!(version_matches("1.7", "1.7_1"));
+        assert!(!version_matches("1.7", "1.70"));
+        assert!(!version_matches("1.7.1", "1.7"));
+    }
 
     #[test]
     fn formula_token_keeps_core_formula_name() {
@@ -128,7 +128,8 @@ mod tests {
         Formula {
             name: name.to_string(),
             versions: Versions {
-                stable: "1.0.0".to_string(),
+                stable: Some("1.0.0".to_string()),
+                head: None,
             },
             dependencies: deps.iter().map(|dep| dep.to_string()).collect(),
             bottle: Bottle {
@@ -143,6 +144,14 @@ mod tests {
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            license: None,
+            deprecated: false,
+            deprecation_reason: None,
+            desc: None,
+            homepage: None,
+            service: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
         }
     }
 
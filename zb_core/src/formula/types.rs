@@ -101,21 +101,81 @@ pub struct Formula {
     pub requirements: Vec<serde_json::Value>,
     #[serde(default)]
     pub variations: Option<serde_json::Value>,
+    /// SPDX expression, e.g. `MIT` or `Apache-2.0 OR MIT`.
+    #[serde(default)]
+    pub license: Option<String>,
+    #[serde(default)]
+    pub deprecated: bool,
+    #[serde(default)]
+    pub deprecation_reason: Option<String>,
+    #[serde(default)]
+    pub desc: Option<String>,
+    #[serde(default)]
+    pub homepage: Option<String>,
+    #[serde(default)]
+    pub service: Option<Service>,
+    /// Formulas that can't be installed alongside this one, usually because
+    /// they ship the same files.
+    #[serde(default)]
+    pub conflicts_with: Vec<String>,
+    /// The reason for each entry of `conflicts_with`, when one is given.
+    #[serde(default)]
+    pub conflicts_with_reasons: Vec<Option<String>>,
 }
 
 impl Formula {
+    /// The version a keg of this formula is installed under: the stable
+    /// version with its revision, or `HEAD` for formulas without one.
     pub fn effective_version(&self) -> String {
+        let Some(stable) = &self.versions.stable else {
+            return HEAD_VERSION.to_string();
+        };
         if self.revision > 0 {
-            format!("{}_{}", self.versions.stable, self.revision)
+            format!("{}_{}", stable, self.revision)
         } else {
-            self.versions.stable.clone()
+            stable.clone()
         }
     }
 
+    /// Whether the formula only defines a `head` spec, so it has neither a
+    /// versioned release nor bottles and can only be built from its repository.
+    pub fn is_head_only(&self) -> bool {
+        self.versions.stable.is_none()
+    }
+
+    /// Repository URL of the `head` spec, when there is one.
+    pub fn head_url(&self) -> Option<&str> {
+        match self.urls.as_ref()?.head.as_ref()? {
+            serde_json::Value::String(url) => Some(url),
+            head => head.get("url")?.as_str(),
+        }
+    }
+
+    /// Branch the `head` spec checks out, when it names one.
+    pub fn head_branch(&self) -> Option<&str> {
+        self.urls.as_ref()?.head.as_ref()?.get("branch")?.as_str()
+    }
+
     pub fn is_keg_only(&self) -> bool {
         self.name.contains('@') || !matches!(self.keg_only, KegOnly::No)
     }
 
+    /// Why this formula conflicts with `other`, if it does and says why.
+    pub fn conflict_reason(&self, other: &str) -> Option<&str> {
+        let index = self.conflicts_with.iter().position(|name| name == other)?;
+        self.conflicts_with_reasons.get(index)?.as_deref()
+    }
+
+    /// Why the formula isn't linked into the prefix, or `None` when it is.
+    pub fn keg_only_reason(&self) -> Option<String> {
+        match &self.keg_only {
+            KegOnly::Reason(reason) => Some(reason.clone()),
+            _ if self.name.contains('@') => Some("versioned formula".to_string()),
+            KegOnly::Yes => Some("keg-only formula".to_string()),
+            KegOnly::No => None,
+        }
+    }
+
     pub fn source_url(&self) -> Option<&SourceUrl> {
         self.urls.as_ref().and_then(|u| u.stable.as_ref())
     }
@@ -138,17 +198,89 @@ impl Formula {
     }
 }
 
+/// A formula's `service` block: how `zb services` runs it in the background.
+/// Paths may use `$HOMEBREW_PREFIX` and `~`.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub struct Service {
+    /// The command line, or a `{"macos": [...], "linux": [...]}` map of them.
+    #[serde(default)]
+    pub run: Option<serde_json::Value>,
+    /// `immediate` (the default), `interval` or `cron`.
+    #[serde(default)]
+    pub run_type: Option<String>,
+    /// Seconds between runs of an `interval` service.
+    #[serde(default)]
+    pub interval: Option<u64>,
+    /// `{"always": true}`, `{"crashed": true}` or `{"successful_exit": false}`.
+    #[serde(default)]
+    pub keep_alive: Option<serde_json::Value>,
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    #[serde(default)]
+    pub log_path: Option<String>,
+    #[serde(default)]
+    pub error_log_path: Option<String>,
+    #[serde(default)]
+    pub environment_variables: BTreeMap<String, String>,
+}
+
+impl Service {
+    /// The command line on macOS (`macos`) or Linux, empty when the block
+    /// has none for that platform.
+    pub fn command(&self, macos: bool) -> Vec<String> {
+        let run = match &self.run {
+            Some(serde_json::Value::Object(by_os)) => {
+                by_os.get(if macos { "macos" } else { "linux" })
+            }
+            run => run.as_ref(),
+        };
+        match run {
+            Some(serde_json::Value::String(command)) => vec![command.clone()],
+            Some(serde_json::Value::Array(args)) => args
+                .iter()
+                .filter_map(|arg| arg.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether the service is restarted whenever it exits.
+    pub fn keeps_alive(&self) -> bool {
+        match &self.keep_alive {
+            Some(serde_json::Value::Bool(always)) => *always,
+            Some(keep_alive) => keep_alive.get("always") == Some(&serde_json::Value::Bool(true)),
+            None => false,
+        }
+    }
+
+    /// Whether the service is restarted only after it crashes.
+    pub fn restarts_on_crash(&self) -> bool {
+        self.keep_alive.as_ref().is_some_and(|keep_alive| {
+            keep_alive.get("crashed") == Some(&serde_json::Value::Bool(true))
+                || keep_alive.get("successful_exit") == Some(&serde_json::Value::Bool(false))
+        })
+    }
+}
+
+/// Version of kegs built from a formula's `head` spec.
+const HEAD_VERSION: &str = "HEAD";
+
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub struct Versions {
-    pub stable: String,
+    /// `None` for formulas that only define a `head` spec.
+    #[serde(default)]
+    pub stable: Option<String>,
+    #[serde(default)]
+    pub head: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub struct Bottle {
+    #[serde(default)]
     pub stable: BottleStable,
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
 pub struct BottleStable {
     pub files: BTreeMap<String, BottleFile>,
     /// Rebuild number for the bottle. When > 0, the bottle's internal paths
@@ -177,11 +309,33 @@ mod tests {
         for fixture in fixtures {
             let formula: Formula = serde_json::from_str(fixture).unwrap();
             assert!(!formula.name.is_empty());
-            assert!(!formula.versions.stable.is_empty());
+            assert!(formula.versions.stable.is_some());
             assert!(!formula.bottle.stable.files.is_empty());
         }
     }
 
+    #[test]
+    fn service_commands_are_picked_per_platform() {
+        let service: Service = serde_json::from_value(serde_json::json!({
+            "run": { "macos": ["/bin/mac-daemon"], "linux": "/bin/linux-daemon" },
+            "keep_alive": { "always": true }
+        }))
+        .unwrap();
+        assert_eq!(service.command(true), ["/bin/mac-daemon"]);
+        assert_eq!(service.command(false), ["/bin/linux-daemon"]);
+        assert!(service.keeps_alive());
+        assert!(!service.restarts_on_crash());
+
+        let service: Service = serde_json::from_value(serde_json::json!({
+            "run": ["/bin/daemon", "--foreground"],
+            "keep_alive": { "successful_exit": false }
+        }))
+        .unwrap();
+        assert_eq!(service.command(false), ["/bin/daemon", "--foreground"]);
+        assert!(!service.keeps_alive());
+        assert!(service.restarts_on_crash());
+    }
+
     #[test]
     fn effective_version_without_revision() {
         let fixture = include_str!("../../fixtures/formula_foo.json");
@@ -214,6 +368,26 @@ mod tests {
         assert_eq!(formula.effective_version(), "8.0.1");
     }
 
+    #[test]
+    fn head_only_formulas_deserialize_without_stable_or_bottles() {
+        let json = r#"{
+            "name": "nightly-tool",
+            "versions": { "stable": null, "head": "HEAD", "bottle": false },
+            "dependencies": [],
+            "bottle": {},
+            "urls": { "head": { "url": "https://example.com/nightly-tool.git", "branch": "main" } }
+        }"#;
+        let formula: Formula = serde_json::from_str(json).unwrap();
+        assert!(formula.is_head_only());
+        assert_eq!(formula.effective_version(), "HEAD");
+        assert!(formula.bottle.stable.files.is_empty());
+        assert_eq!(
+            formula.head_url(),
+            Some("https://example.com/nightly-tool.git")
+        );
+        assert_eq!(formula.head_branch(), Some("main"));
+    }
+
     #[test]
     fn revision_field_defaults_to_zero() {
         let fixture = include_str!("../../fixtures/formula_foo.json");
@@ -227,6 +401,7 @@ mod tests {
         let formula: Formula = serde_json::from_str(fixture).unwrap();
         assert_eq!(formula.keg_only, KegOnly::No);
         assert!(!formula.is_keg_only());
+        assert_eq!(formula.keg_only_reason(), None);
     }
 
     #[test]
@@ -261,6 +436,29 @@ mod tests {
             matches!(formula.keg_only, KegOnly::Reason(ref s) if s == "it conflicts with PostgreSQL")
         );
         assert!(formula.is_keg_only());
+        assert_eq!(
+            formula.keg_only_reason().as_deref(),
+            Some("it conflicts with PostgreSQL")
+        );
+    }
+
+    #[test]
+    fn conflicts_with_pairs_names_with_reasons() {
+        let json = r#"{
+            "name": "gnupg",
+            "versions": { "stable": "2.4.5" },
+            "dependencies": [],
+            "conflicts_with": ["gpg1", "gpg2"],
+            "conflicts_with_reasons": [null, "both install a `gpg` binary"],
+            "bottle": { "stable": { "files": {} } }
+        }"#;
+        let formula: Formula = serde_json::from_str(json).unwrap();
+        assert_eq!(formula.conflicts_with, ["gpg1", "gpg2"]);
+        assert_eq!(formula.conflict_reason("gpg1"), None);
+        assert_eq!(
+            formula.conflict_reason("gpg2"),
+            Some("both install a `gpg` binary")
+        );
     }
 
     #[test]
@@ -276,5 +474,9 @@ mod tests {
         let formula: Formula = serde_json::from_str(json).unwrap();
         assert_eq!(formula.keg_only, KegOnly::No);
         assert!(formula.is_keg_only());
+        assert_eq!(
+            formula.keg_only_reason().as_deref(),
+            Some("versioned formula")
+        );
     }
 }
@@ -5,7 +5,8 @@ pub mod formula;
 
 pub use build::{BuildPlan, BuildSystem, InstallMethod};
 pub use context::{ConcurrencyLimits, Context, LogLevel, LoggerHandle, Paths};
-pub use errors::{ConflictedLink, Error};
+pub use errors::{ConflictedLink, Error, ErrorContext, LicenseViolation};
 pub use formula::{
-    Formula, KegOnly, SelectedBottle, formula_token, resolve_closure, select_bottle,
+    Arch, Formula, KegOnly, Os, Platform, SelectedBottle, Service, formula_token, resolve_closure,
+    select_bottle, select_bottle_for, select_bottle_preferring, version_matches,
 };
@@ -0,0 +1,356 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use zb_core::Error;
+
+const CELLAR_PLACEHOLDER: &str = "@@HOMEBREW_CELLAR@@";
+const PREFIX_PLACEHOLDER: &str = "@@HOMEBREW_PREFIX@@";
+
+/// How a bottle may be poured, as written to the `cellar:` field of the bottle DSL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BottleCellar {
+    /// Nothing in the keg refers to the prefix.
+    AnySkipRelocation,
+    /// Every reference was replaced by a placeholder, so any prefix works.
+    Any,
+    /// Binaries still embed the build cellar; the bottle only works there.
+    Fixed(String),
+}
+
+impl BottleCellar {
+    fn dsl(&self) -> String {
+        match self {
+            Self::AnySkipRelocation => ":any_skip_relocation".to_string(),
+            Self::Any => ":any".to_string(),
+            Self::Fixed(path) => format!("\"{path}\""),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bottle {
+    pub name: String,
+    pub version: String,
+    pub tag: String,
+    pub path: PathBuf,
+    pub sha256: String,
+    pub cellar: BottleCellar,
+    /// Text files whose prefix references were turned back into placeholders.
+    pub relocated: usize,
+    /// Binary files that still contain the build prefix.
+    pub unrelocatable: Vec<PathBuf>,
+}
+
+impl Bottle {
+    pub fn file_name(&self) -> String {
+        bottle_file_name(&self.name, &self.version, &self.tag)
+    }
+
+    /// The `bottle do ... end` block for a formula file.
+    pub fn stanza(&self, root_url: Option<&str>) -> String {
+        let mut stanza = String::from("  bottle do\n");
+        if let Some(url) = root_url {
+            stanza.push_str(&format!("    root_url \"{}\"\n", url.trim_end_matches('/')));
+        }
+        stanza.push_str(&format!(
+            "    sha256 cellar: {}, {}: \"{}\"\n",
+            self.cellar.dsl(),
+            self.tag,
+            self.sha256
+        ));
+        stanza.push_str("  end\n");
+        stanza
+    }
+}
+
+pub fn bottle_file_name(name: &str, version: &str, tag: &str) -> String {
+    format!("{name}--{version}.{tag}.bottle.tar.gz")
+}
+
+/// Bottle tag for the machine zerobrew is running on, e.g. `arm64_sonoma`.
+pub fn host_bottle_tag() -> Option<String> {
+    zb_core::Platform::current().bottle_tag()
+}
+
+/// Pack an installed keg into `<out_dir>/<name>--<version>.<tag>.bottle.tar.gz`.
+///
+/// Text files have the concrete cellar and prefix paths turned back into
+/// Homebrew placeholders inside the archive; the keg on disk is left untouched.
+pub fn create_bottle(
+    keg_path: &Path,
+    name: &str,
+    version: &str,
+    tag: &str,
+    prefix: &Path,
+    cellar_dir: &Path,
+    out_dir: &Path,
+) -> Result<Bottle, Error> {
+    if !keg_path.is_dir() {
+        return Err(Error::NotInstalled {
+            name: name.to_string(),
+        });
+    }
+
+    fs::create_dir_all(out_dir).map_err(|e| Error::FileError {
+        message: format!("failed to create {}: {e}", out_dir.display()),
+    })?;
+
+    let path = out_dir.join(bottle_file_name(name, version, tag));
+    let file = fs::File::create(&path).map_err(|e| Error::FileError {
+        message: format!("failed to create {}: {e}", path.display()),
+    })?;
+
+    let prefix_str = prefix.to_string_lossy();
+    let cellar_str = cellar_dir.to_string_lossy();
+    let archive_root = Path::new(name).join(version);
+
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    builder.follow_symlinks(false);
+
+    let mut relocated = 0;
+    let mut unrelocatable = Vec::new();
+    let mut references_prefix = false;
+
+    let entries = walkdir::WalkDir::new(keg_path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok);
+    for entry in entries {
+        let Ok(relative) = entry.path().strip_prefix(keg_path) else {
+            continue;
+        };
+        let archive_path = archive_root.join(relative);
+        let file_type = entry.file_type();
+
+        let result = if file_type.is_file() {
+            let contents = fs::read(entry.path()).map_err(|e| Error::FileError {
+                message: format!("failed to read {}: {e}", entry.path().display()),
+            })?;
+            let mode = entry.metadata().map(|m| mode_of(&m)).unwrap_or(0o644);
+
+            let contents = match relocate(&contents, &prefix_str, &cellar_str) {
+                Relocation::Unchanged => contents,
+                Relocation::Text(text) => {
+                    relocated += 1;
+                    references_prefix = true;
+                    text.into_bytes()
+                }
+                Relocation::Binary => {
+                    unrelocatable.push(relative.to_path_buf());
+                    contents
+                }
+            };
+
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(mode);
+            header.set_mtime(0);
+            header.set_cksum();
+            builder.append_data(&mut header, &archive_path, contents.as_slice())
+        } else if file_type.is_symlink() || file_type.is_dir() {
+            builder.append_path_with_name(entry.path(), &archive_path)
+        } else {
+            continue;
+        };
+
+        result.map_err(|e| Error::FileError {
+            message: format!("failed to add {} to bottle: {e}", relative.display()),
+        })?;
+    }
+
+    let encoder = builder.into_inner().map_err(|e| Error::FileError {
+        message: format!("failed to finish bottle archive: {e}"),
+    })?;
+    let mut file = encoder.finish().map_err(|e| Error::FileError {
+        message: format!("failed to finish bottle archive: {e}"),
+    })?;
+    file.flush().map_err(|e| Error::FileError {
+        message: format!("failed to write bottle: {e}"),
+    })?;
+
+    let sha256 = sha256_file(&path)?;
+    let cellar = if !unrelocatable.is_empty() {
+        BottleCellar::Fixed(cellar_str.into_owned())
+    } else if references_prefix {
+        BottleCellar::Any
+    } else {
+        BottleCellar::AnySkipRelocation
+    };
+
+    Ok(Bottle {
+        name: name.to_string(),
+        version: version.to_string(),
+        tag: tag.to_string(),
+        path,
+        sha256,
+        cellar,
+        relocated,
+        unrelocatable,
+    })
+}
+
+enum Relocation {
+    Unchanged,
+    Text(String),
+    Binary,
+}
+
+fn relocate(contents: &[u8], prefix: &str, cellar: &str) -> Relocation {
+    let mentions = |needle: &str| {
+        !needle.is_empty()
+            && contents
+                .windows(needle.len())
+                .any(|window| window == needle.as_bytes())
+    };
+    if !mentions(prefix) && !mentions(cellar) {
+        return Relocation::Unchanged;
+    }
+
+    match std::str::from_utf8(contents) {
+        Ok(text) if !text.contains('\0') => {
+            // The cellar usually lives under the prefix, so it has to go first.
+            let text = text
+                .replace(cellar, CELLAR_PLACEHOLDER)
+                .replace(prefix, PREFIX_PLACEHOLDER);
+            Relocation::Text(text)
+        }
+        _ => Relocation::Binary,
+    }
+}
+
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+fn sha256_file(path: &Path) -> Result<String, Error> {
+    let mut file = fs::File::open(path).map_err(|e| Error::FileError {
+        message: format!("failed to read {}: {e}", path.display()),
+    })?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| Error::FileError {
+        message: format!("failed to hash {}: {e}", path.display()),
+    })?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn read_archive(path: &Path) -> Vec<(String, Vec<u8>)> {
+        let file = fs::File::open(path).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let path = entry.path().unwrap().to_string_lossy().into_owned();
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents).unwrap();
+                (path, contents)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn text_files_get_placeholders_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let cellar = prefix.join("Cellar");
+        let keg = cellar.join("hello/1.0");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::create_dir_all(keg.join("lib/pkgconfig")).unwrap();
+        fs::write(keg.join("bin/hello"), "#!/bin/sh\necho hi\n").unwrap();
+        fs::write(
+            keg.join("lib/pkgconfig/hello.pc"),
+            format!(
+                "prefix={}/hello/1.0\nincludedir={}/include\n",
+                cellar.display(),
+                prefix.display()
+            ),
+        )
+        .unwrap();
+
+        let out = tmp.path().join("out");
+        let bottle =
+            create_bottle(&keg, "hello", "1.0", "x86_64_linux", &prefix, &cellar, &out).unwrap();
+
+        assert_eq!(bottle.file_name(), "hello--1.0.x86_64_linux.bottle.tar.gz");
+        assert_eq!(bottle.cellar, BottleCellar::Any);
+        assert_eq!(bottle.relocated, 1);
+        assert!(bottle.unrelocatable.is_empty());
+
+        let entries = read_archive(&bottle.path);
+        let pc = entries
+            .iter()
+            .find(|(path, _)| path == "hello/1.0/lib/pkgconfig/hello.pc")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&pc.1),
+            "prefix=@@HOMEBREW_CELLAR@@/hello/1.0\nincludedir=@@HOMEBREW_PREFIX@@/include\n"
+        );
+
+        // The installed keg is not rewritten.
+        assert!(
+            fs::read_to_string(keg.join("lib/pkgconfig/hello.pc"))
+                .unwrap()
+                .contains(&cellar.display().to_string())
+        );
+        assert_eq!(bottle.sha256, sha256_file(&bottle.path).unwrap());
+    }
+
+    #[test]
+    fn binaries_with_prefix_pin_the_cellar() {
+        let tmp = tempfile::tempdir().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let cellar = prefix.join("Cellar");
+        let keg = cellar.join("tool/2.0");
+        fs::create_dir_all(keg.join("lib")).unwrap();
+        let mut binary = b"\x7fELF\0\0".to_vec();
+        binary.extend_from_slice(prefix.join("lib").to_string_lossy().as_bytes());
+        fs::write(keg.join("lib/libtool.so"), &binary).unwrap();
+
+        let bottle = create_bottle(
+            &keg,
+            "tool",
+            "2.0",
+            "x86_64_linux",
+            &prefix,
+            &cellar,
+            tmp.path(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            bottle.cellar,
+            BottleCellar::Fixed(cellar.display().to_string())
+        );
+        assert_eq!(bottle.unrelocatable, vec![PathBuf::from("lib/libtool.so")]);
+    }
+
+    #[test]
+    fn stanza_matches_homebrew_dsl() {
+        let bottle = Bottle {
+            name: "hello".into(),
+            version: "1.0".into(),
+            tag: "arm64_sonoma".into(),
+            path: PathBuf::new(),
+            sha256: "abc".into(),
+            cellar: BottleCellar::AnySkipRelocation,
+            relocated: 0,
+            unrelocatable: vec![],
+        };
+
+        assert_eq!(
+            bottle.stanza(Some("https://ghcr.io/v2/acme/tap/")),
+            "  bottle do\n    root_url \"https://ghcr.io/v2/acme/tap\"\n    sha256 cellar: :any_skip_relocation, arm64_sonoma: \"abc\"\n  end\n"
+        );
+    }
+}
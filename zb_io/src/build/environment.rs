@@ -1,26 +1,34 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use zb_core::BuildPlan;
+use zb_core::{BuildPlan, formula_token};
 
-pub fn build_env(plan: &BuildPlan, prefix: &Path) -> HashMap<String, String> {
+use super::executor::DepInfo;
+
+pub fn build_env(
+    plan: &BuildPlan,
+    prefix: &Path,
+    deps: &HashMap<String, DepInfo>,
+) -> HashMap<String, String> {
     let mut env = HashMap::new();
 
-    let bin_dir = prefix.join("bin");
-    let lib_dir = prefix.join("lib");
-    let include_dir = prefix.join("include");
-    let pkgconfig_dir = lib_dir.join("pkgconfig");
+    // Keg-only dependencies are not linked into the prefix, so their opt paths
+    // have to come first or the build picks up the system copies instead.
+    let mut roots = keg_only_opt_paths(prefix, deps);
+    roots.push(prefix.to_path_buf());
+
+    let bin_dirs = join_dirs(&roots, "bin");
+    let pkgconfig_dirs = join_dirs(&roots, "lib/pkgconfig");
+    let include_flags = prefixed_dirs(&roots, "include", "-I");
+    let lib_flags = prefixed_dirs(&roots, "lib", "-L");
 
     let system_path = std::env::var("PATH").unwrap_or_default();
-    env.insert(
-        "PATH".into(),
-        format!("{}:{system_path}", bin_dir.display()),
-    );
+    env.insert("PATH".into(), format!("{bin_dirs}:{system_path}"));
 
     let system_pkg = std::env::var("PKG_CONFIG_PATH").unwrap_or_default();
     env.insert(
         "PKG_CONFIG_PATH".into(),
-        format!("{}:{system_pkg}", pkgconfig_dir.display()),
+        format!("{pkgconfig_dirs}:{system_pkg}"),
     );
 
     let system_cflags = std::env::var("CFLAGS").unwrap_or_default();
@@ -29,23 +37,23 @@ pub fn build_env(plan: &BuildPlan, prefix: &Path) -> HashMap<String, String> {
 
     env.insert(
         "CFLAGS".into(),
-        format!("-I{} {system_cflags}", include_dir.display())
+        format!("{include_flags} {system_cflags}")
             .trim()
             .to_string(),
     );
     env.insert(
         "CPPFLAGS".into(),
-        format!("-I{} {system_cppflags}", include_dir.display())
+        format!("{include_flags} {system_cppflags}")
             .trim()
             .to_string(),
     );
     env.insert(
         "LDFLAGS".into(),
-        format!("-L{} {system_ldflags}", lib_dir.display())
-            .trim()
-            .to_string(),
+        format!("{lib_flags} {system_ldflags}").trim().to_string(),
     );
 
+    env.insert("CMAKE_PREFIX_PATH".into(), join_dirs(&roots, ""));
+
     env.insert("HOMEBREW_PREFIX".into(), prefix.display().to_string());
     env.insert(
         "HOMEBREW_CELLAR".into(),
@@ -59,14 +67,113 @@ pub fn build_env(plan: &BuildPlan, prefix: &Path) -> HashMap<String, String> {
     );
     env.insert("ZEROBREW_FORMULA_NAME".into(), plan.formula_name.clone());
     env.insert("ZEROBREW_FORMULA_VERSION".into(), plan.version.clone());
+    if plan.head {
+        env.insert("ZEROBREW_BUILD_HEAD".into(), "1".into());
+    }
 
     env.insert("MAKEFLAGS".into(), format!("-j{}", num_cpus()));
 
     env
 }
 
+/// `opt/<name>` for every keg-only dependency, sorted so the environment is stable.
+fn keg_only_opt_paths(prefix: &Path, deps: &HashMap<String, DepInfo>) -> Vec<PathBuf> {
+    let mut names: Vec<&str> = deps
+        .iter()
+        .filter(|(_, info)| info.keg_only)
+        .map(|(name, _)| formula_token(name))
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| prefix.join("opt").join(name))
+        .collect()
+}
+
+fn join_dirs(roots: &[PathBuf], subdir: &str) -> String {
+    roots
+        .iter()
+        .map(|root| {
+            if subdir.is_empty() {
+                root.display().to_string()
+            } else {
+                root.join(subdir).display().to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn prefixed_dirs(roots: &[PathBuf], subdir: &str, flag: &str) -> String {
+    roots
+        .iter()
+        .map(|root| format!("{flag}{}", root.join(subdir).display()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 fn num_cpus() -> usize {
     std::thread::available_parallelism()
         .map(|n| n.get())
         .unwrap_or(4)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zb_core::BuildSystem;
+
+    fn plan(prefix: &Path) -> BuildPlan {
+        BuildPlan {
+            formula_name: "curl".into(),
+            version: "8.0.0".into(),
+            source_url: "https://example.com/curl.tar.gz".into(),
+            source_checksum: None,
+            head: false,
+            source_branch: None,
+            ruby_source_path: None,
+            build_dependencies: vec![],
+            runtime_dependencies: vec!["openssl@3".into(), "zstd".into()],
+            detected_system: BuildSystem::Autoconf,
+            prefix: prefix.to_path_buf(),
+            cellar_path: prefix.join("Cellar/curl/8.0.0"),
+        }
+    }
+
+    fn dep(keg_only: bool) -> DepInfo {
+        DepInfo {
+            cellar_path: String::new(),
+            keg_only,
+        }
+    }
+
+    #[test]
+    fn keg_only_dependencies_get_opt_paths() {
+        let prefix = Path::new("/opt/zb");
+        let deps = HashMap::from([
+            ("openssl@3".to_string(), dep(true)),
+            ("zstd".to_string(), dep(false)),
+        ]);
+
+        let env = build_env(&plan(prefix), prefix, &deps);
+
+        assert!(
+            env["PKG_CONFIG_PATH"]
+                .starts_with("/opt/zb/opt/openssl@3/lib/pkgconfig:/opt/zb/lib/pkgconfig")
+        );
+        assert!(env["CPPFLAGS"].starts_with("-I/opt/zb/opt/openssl@3/include -I/opt/zb/include"));
+        assert!(env["LDFLAGS"].starts_with("-L/opt/zb/opt/openssl@3/lib -L/opt/zb/lib"));
+        assert_eq!(env["CMAKE_PREFIX_PATH"], "/opt/zb/opt/openssl@3:/opt/zb");
+        assert!(!env["LDFLAGS"].contains("zstd"));
+    }
+
+    #[test]
+    fn without_keg_only_dependencies_only_prefix_is_used() {
+        let prefix = Path::new("/opt/zb");
+        let env = build_env(&plan(prefix), prefix, &HashMap::new());
+
+        assert!(env["PATH"].starts_with("/opt/zb/bin:"));
+        assert_eq!(env["CMAKE_PREFIX_PATH"], "/opt/zb");
+    }
+}
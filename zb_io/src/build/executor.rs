@@ -8,19 +8,31 @@ use tokio::process::Command;
 use zb_core::{BuildPlan, Error};
 
 use super::environment::build_env;
-use super::source::download_and_extract_source;
+use super::source::{clone_head_source, download_and_extract_source};
 
 const SHIM_RUBY: &str = include_str!("shim.rb");
 
 pub struct BuildExecutor {
     prefix: PathBuf,
     work_root: PathBuf,
+    proxy: Option<reqwest::Proxy>,
 }
 
 impl BuildExecutor {
     pub fn new(prefix: PathBuf) -> Self {
         let work_root = prefix.join("tmp").join("build");
-        Self { prefix, work_root }
+        Self {
+            prefix,
+            work_root,
+            proxy: None,
+        }
+    }
+
+    /// Download sources through `proxy` instead of the proxy environment
+    /// variables.
+    pub(crate) fn with_proxy(mut self, proxy: Option<reqwest::Proxy>) -> Self {
+        self.proxy = proxy;
+        self
     }
 
     pub async fn execute(
@@ -32,12 +44,17 @@ impl BuildExecutor {
         let work_dir = self.work_root.join(&plan.formula_name);
         self.prepare_work_dir(&work_dir).await?;
 
-        let source_root = download_and_extract_source(
-            &plan.source_url,
-            plan.source_checksum.as_deref(),
-            &work_dir,
-        )
-        .await?;
+        let source_root = if plan.head {
+            clone_head_source(&plan.source_url, plan.source_branch.as_deref(), &work_dir).await?
+        } else {
+            download_and_extract_source(
+                &plan.source_url,
+                plan.source_checksum.as_deref(),
+                &work_dir,
+                self.proxy.as_ref(),
+            )
+            .await?
+        };
 
         let shim_path = work_dir.join("zerobrew_shim.rb");
         fs::write(&shim_path, SHIM_RUBY)
@@ -52,7 +69,7 @@ impl BuildExecutor {
                 message: format!("failed to create cellar directory: {e}"),
             })?;
 
-        let mut env = build_env(plan, &self.prefix);
+        let mut env = build_env(plan, &self.prefix, installed_deps);
         env.insert(
             "ZEROBREW_FORMULA_FILE".into(),
             formula_rb_path.display().to_string(),
@@ -87,6 +104,8 @@ impl BuildExecutor {
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct DepInfo {
     pub cellar_path: String,
+    /// Not linked into the prefix, so only reachable through `opt/<name>`.
+    pub keg_only: bool,
 }
 
 async fn find_ruby() -> Result<PathBuf, Error> {
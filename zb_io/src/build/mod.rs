@@ -1,5 +1,7 @@
+pub mod bottle;
 pub mod environment;
 pub mod executor;
 pub mod source;
 
+pub use bottle::{Bottle, BottleCellar, host_bottle_tag};
 pub use executor::{BuildExecutor, DepInfo};
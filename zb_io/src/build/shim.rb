@@ -23,6 +23,7 @@ FORMULA_NAME = ENV.fetch("ZEROBREW_FORMULA_NAME")
 FORMULA_VERSION = ENV.fetch("ZEROBREW_FORMULA_VERSION")
 FORMULA_FILE = ENV.fetch("ZEROBREW_FORMULA_FILE")
 INSTALLED_DEPS = JSON.parse(ENV.fetch("ZEROBREW_INSTALLED_DEPS", "{}"))
+BUILD_HEAD = ENV["ZEROBREW_BUILD_HEAD"] == "1"
 
 module OS
   def self.mac?
@@ -221,8 +222,8 @@ class StagedResource
 end
 
 class BuildOptions
-  def head?  = false
-  def stable? = true
+  def head?  = BUILD_HEAD
+  def stable? = !BUILD_HEAD
   def with?(name) = false
   def without?(name) = true
 end
@@ -5,14 +5,16 @@ use zb_core::Error;
 
 use crate::checksum::verify_sha256_bytes;
 use crate::extraction::extract_tarball;
+use crate::network::proxy;
 
 pub async fn download_and_extract_source(
     url: &str,
     expected_checksum: Option<&str>,
     work_dir: &Path,
+    proxy: Option<&reqwest::Proxy>,
 ) -> Result<PathBuf, Error> {
     let tarball_path = work_dir.join("source.tar.gz");
-    download_source(url, &tarball_path).await?;
+    download_source(url, &tarball_path, proxy).await?;
 
     verify_checksum(&tarball_path, expected_checksum, url).await?;
 
@@ -28,8 +30,44 @@ pub async fn download_and_extract_source(
     find_source_root(&src_dir).await
 }
 
-async fn download_source(url: &str, dest: &Path) -> Result<(), Error> {
-    let client = reqwest::Client::builder()
+/// Check out the tip of a formula's `head` repository (on `branch` when
+/// given) into `work_dir`, returning the checkout.
+pub async fn clone_head_source(
+    url: &str,
+    branch: Option<&str>,
+    work_dir: &Path,
+) -> Result<PathBuf, Error> {
+    let src_dir = work_dir.join("src");
+    let mut git = tokio::process::Command::new("git");
+    git.args(["clone", "--depth", "1", "--quiet"]);
+    if let Some(branch) = branch {
+        git.args(["--branch", branch]);
+    }
+    let output = git
+        .arg(url)
+        .arg(&src_dir)
+        .output()
+        .await
+        .map_err(|e| Error::ExecutionError {
+            message: format!("failed to run git, which HEAD builds need: {e}"),
+        })?;
+    if !output.status.success() {
+        return Err(Error::NetworkFailure {
+            message: format!(
+                "failed to clone {url}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    Ok(src_dir)
+}
+
+async fn download_source(
+    url: &str,
+    dest: &Path,
+    proxy: Option<&reqwest::Proxy>,
+) -> Result<(), Error> {
+    let client = proxy::apply(reqwest::Client::builder(), proxy)
         .timeout(std::time::Duration::from_secs(300))
         .build()
         .map_err(|e| Error::NetworkFailure {
@@ -0,0 +1,94 @@
+//! Launcher shims for kegs installed with lazy materialization.
+//!
+//! A lazy install only extracts the bottle into the store and drops a small
+//! shell script into `prefix/bin` for each executable. The first time one of
+//! them runs, it calls back into zerobrew to materialize and link the keg
+//! (which replaces the shims with regular symlinks) and then execs the real
+//! binary.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cellar::shim::{marker_line, sh_quote};
+
+const SHIM_MARKER: &str = "# zerobrew lazy shim";
+
+/// The command a shim runs to materialize its keg; `materialize <formula>`
+/// is appended.
+#[derive(Debug, Clone)]
+pub struct LazyLauncher {
+    program: PathBuf,
+    args: Vec<String>,
+}
+
+impl LazyLauncher {
+    pub fn new(program: impl Into<PathBuf>, args: Vec<String>) -> Self {
+        Self {
+            program: program.into(),
+            args,
+        }
+    }
+}
+
+pub(crate) fn shim_script(launcher: &LazyLauncher, formula: &str, target: &Path) -> String {
+    let mut command = vec![sh_quote(&launcher.program.to_string_lossy())];
+    command.extend(launcher.args.iter().map(|arg| sh_quote(arg)));
+    command.push("materialize".to_string());
+    command.push(sh_quote(formula));
+
+    format!(
+        "#!/bin/sh\n{SHIM_MARKER} for {formula}\n{} >&2 || exit 1\nexec {} \"$@\"\n",
+        command.join(" "),
+        sh_quote(&target.to_string_lossy()),
+    )
+}
+
+/// Whether `path` is a lazy launcher shim (and not a symlink or a file some
+/// other tool put there).
+pub(crate) fn is_shim(path: &Path) -> bool {
+    marker_line(path).is_some_and(|line| line.starts_with(SHIM_MARKER))
+}
+
+/// Executables in the `bin` directory of unpacked bottle content.
+pub(crate) fn bin_names(content: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(content.join("bin")) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|entry| !entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cellar::shim::write_shim;
+    use tempfile::TempDir;
+
+    #[test]
+    fn shim_materializes_then_execs_target() {
+        let tmp = TempDir::new().unwrap();
+        let launcher = LazyLauncher::new(
+            "/usr/local/bin/zb",
+            vec!["--root".to_string(), "/opt/it's".to_string()],
+        );
+        let script = shim_script(&launcher, "jq", Path::new("/opt/Cellar/jq/1.7/bin/jq"));
+
+        assert!(script.contains(
+            r"'/usr/local/bin/zb' '--root' '/opt/it'\''s' materialize 'jq' >&2 || exit 1"
+        ));
+        assert!(script.ends_with("exec '/opt/Cellar/jq/1.7/bin/jq' \"$@\"\n"));
+
+        let shim = tmp.path().join("jq");
+        write_shim(&shim, &script).unwrap();
+        assert!(is_shim(&shim));
+
+        let other = tmp.path().join("other");
+        fs::write(&other, "#!/bin/sh\necho hi\n").unwrap();
+        assert!(!is_shim(&other));
+    }
+}
@@ -4,7 +4,9 @@ use std::path::{Component, Path, PathBuf};
 
 use zb_core::{ConflictedLink, Error};
 
-const LINK_DIRS: &[&str] = &["bin", "lib", "libexec", "include", "share", "etc"];
+use crate::cellar::shim::{ShimEnv, exec_shim_script, exec_shim_target, write_shim};
+
+pub(crate) const LINK_DIRS: &[&str] = &["bin", "lib", "libexec", "include", "share", "etc"];
 
 pub struct Linker {
     prefix: PathBuf,
@@ -18,6 +20,113 @@ pub struct LinkedFile {
     pub target_path: PathBuf,
 }
 
+/// Keg entries left out of linking, given as patterns relative to the keg
+/// (`bin/python3*`, `share/doc`). `*` and `?` match within one path
+/// component, and a pattern matching a directory covers everything in it.
+#[derive(Debug, Clone, Copy)]
+pub struct LinkExclusions<'a> {
+    keg_path: &'a Path,
+    patterns: &'a [String],
+}
+
+impl<'a> LinkExclusions<'a> {
+    pub fn new(keg_path: &'a Path, patterns: &'a [String]) -> Self {
+        Self { keg_path, patterns }
+    }
+
+    fn none() -> LinkExclusions<'static> {
+        LinkExclusions {
+            keg_path: Path::new(""),
+            patterns: &[],
+        }
+    }
+
+    fn covers(&self, path: &Path) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(self.keg_path) else {
+            return false;
+        };
+        let components: Vec<_> = relative.components().collect();
+        self.patterns.iter().any(|pattern| {
+            let parts: Vec<&str> = pattern.trim_matches('/').split('/').collect();
+            parts.len() == components.len()
+                && parts.iter().zip(&components).all(|(part, component)| {
+                    component
+                        .as_os_str()
+                        .to_str()
+                        .is_some_and(|name| wildcard_match(part, name))
+                })
+        })
+    }
+
+    /// The keg entries these patterns keep out of the prefix, relative to
+    /// the keg. Entries inside an excluded directory aren't listed again.
+    pub fn excluded_entries(&self) -> Vec<PathBuf> {
+        fn walk(exclusions: &LinkExclusions, dir: &Path, found: &mut Vec<PathBuf>) {
+            let Ok(entries) = fs::read_dir(dir) else {
+                return;
+            };
+            let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+            paths.sort();
+            for path in paths {
+                if exclusions.covers(&path) {
+                    if let Ok(relative) = path.strip_prefix(exclusions.keg_path) {
+                        found.push(relative.to_path_buf());
+                    }
+                } else if path.is_dir() {
+                    walk(exclusions, &path, found);
+                }
+            }
+        }
+
+        let mut found = Vec::new();
+        if self.patterns.is_empty() {
+            return found;
+        }
+        for dir_name in LINK_DIRS {
+            let dir = self.keg_path.join(dir_name);
+            if self.covers(&dir) {
+                found.push(PathBuf::from(dir_name));
+            } else {
+                walk(self, &dir, &mut found);
+            }
+        }
+        found
+    }
+}
+
+/// Match `name` against a pattern where `*` is any run of characters and `?`
+/// any single one.
+pub(crate) fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 fn keg_name_from_path(path: &Path) -> Option<String> {
     let components: Vec<_> = path.components().collect();
     for (i, c) in components.iter().enumerate() {
@@ -31,6 +140,36 @@ fn keg_name_from_path(path: &Path) -> Option<String> {
     None
 }
 
+/// The `<cellar>/<name>/<version>` directory containing `path`.
+fn keg_root_of(path: &Path) -> Option<PathBuf> {
+    let components: Vec<_> = path.components().collect();
+    let cellar = components
+        .iter()
+        .position(|c| matches!(c, Component::Normal(s) if s.eq_ignore_ascii_case("cellar")))?;
+    (components.len() > cellar + 2).then(|| components[..cellar + 3].iter().collect())
+}
+
+/// Who put `path` into the prefix, as far as can be told from the file itself:
+/// a keg in `own_cellar` (its name), a Homebrew keg elsewhere, recognized by its
+/// install receipt (`Homebrew's <name>`), or `None` when nothing points at a keg.
+pub(crate) fn observed_owner(path: &Path, own_cellar: &Path) -> Option<String> {
+    let target = match exec_shim_target(path) {
+        Some(target) => target,
+        None => fs::canonicalize(path).ok()?,
+    };
+    let keg = keg_root_of(&target)?;
+    let name = keg_name_from_path(&keg)?;
+
+    let ours = fs::canonicalize(own_cellar)
+        .map(|cellar| keg.starts_with(cellar))
+        .unwrap_or(false);
+    if !ours && keg.join("INSTALL_RECEIPT.json").exists() {
+        Some(format!("Homebrew's {name}"))
+    } else {
+        Some(name)
+    }
+}
+
 fn keg_name_from_symlink(dst: &Path) -> Option<String> {
     let target = fs::read_link(dst).ok()?;
     let resolved = if target.is_relative() {
@@ -65,22 +204,106 @@ impl Linker {
     /// Pre-flight check: scan all destinations for conflicts without creating any symlinks.
     /// Returns Ok(()) if no conflicts, or Err(LinkConflict) with all conflicts collected.
     pub fn check_conflicts(&self, keg_path: &Path) -> Result<(), Error> {
+        self.check_conflicts_excluding(&LinkExclusions::new(keg_path, &[]))
+    }
+
+    /// [`Self::check_conflicts`] for the entries of a keg that aren't excluded.
+    pub fn check_conflicts_excluding(&self, exclude: &LinkExclusions) -> Result<(), Error> {
+        let conflicts = self.conflicts(exclude);
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::LinkConflict { conflicts })
+        }
+    }
+
+    /// Files in the prefix standing where the keg's non-excluded entries
+    /// would be linked.
+    pub fn conflicts(&self, exclude: &LinkExclusions) -> Vec<ConflictedLink> {
         let mut conflicts = Vec::new();
         for dir_name in LINK_DIRS {
-            let src_dir = keg_path.join(dir_name);
+            let src_dir = exclude.keg_path.join(dir_name);
             let dst_dir = self.prefix.join(dir_name);
-            if src_dir.exists() {
-                Self::collect_conflicts(&src_dir, &dst_dir, &mut conflicts);
+            if src_dir.exists() && !exclude.covers(&src_dir) {
+                Self::collect_conflicts(&src_dir, &dst_dir, exclude, &mut conflicts);
             }
         }
-        if conflicts.is_empty() {
-            Ok(())
-        } else {
-            Err(Error::LinkConflict { conflicts })
+        conflicts
+    }
+
+    /// [`Self::conflicts`] for one file or directory of a keg.
+    pub fn subset_conflicts(&self, keg_path: &Path, subset: &Path) -> Vec<ConflictedLink> {
+        let src = keg_path.join(subset);
+        let dst = self.prefix.join(subset);
+
+        let mut conflicts = Vec::new();
+        if src.is_dir() {
+            Self::collect_conflicts(&src, &dst, &LinkExclusions::none(), &mut conflicts);
+        } else if let (Some(src_dir), Some(dst_dir)) = (src.parent(), dst.parent()) {
+            Self::collect_conflicts(src_dir, dst_dir, &LinkExclusions::none(), &mut conflicts);
+            conflicts.retain(|conflict| conflict.path == dst);
         }
+        conflicts
     }
 
-    fn collect_conflicts(src: &Path, dst: &Path, conflicts: &mut Vec<ConflictedLink>) {
+    /// Delete the files and symlinks in `conflicts` so a keg can be linked
+    /// over them. Directory links above them are expanded first, so nothing
+    /// inside another keg is touched; real directories are refused.
+    pub fn remove_conflicts(&self, conflicts: &[ConflictedLink]) -> Result<(), Error> {
+        for conflict in conflicts {
+            let path = &conflict.path;
+            self.expand_dir_links_above(path)?;
+            if path.is_dir() && !path.is_symlink() {
+                return Err(Error::link("refusing to overwrite a directory").with_path(path));
+            }
+            if let Err(e) = fs::remove_file(path)
+                && e.kind() != io::ErrorKind::NotFound
+            {
+                return Err(Error::link("failed to remove conflicting file")
+                    .with_path(path)
+                    .with_source(e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace symlinked directories between the prefix and `path` with real
+    /// directories of links, the way linking merges them.
+    fn expand_dir_links_above(&self, path: &Path) -> Result<(), Error> {
+        let Some(parent) = path.parent() else {
+            return Ok(());
+        };
+        let Ok(relative) = parent.strip_prefix(&self.prefix) else {
+            return Ok(());
+        };
+        let mut dir = self.prefix.clone();
+        for component in relative.components() {
+            dir.push(component);
+            if !dir.is_symlink() {
+                continue;
+            }
+            let old_target = fs::read_link(&dir).map_err(|e| {
+                Error::link("failed to read symlink")
+                    .with_path(&dir)
+                    .with_source(e)
+            })?;
+            let old_target = if old_target.is_relative() {
+                dir.parent().unwrap_or(Path::new("")).join(old_target)
+            } else {
+                old_target
+            };
+            let _ = fs::remove_file(&dir);
+            Self::link_recursive(&old_target, &dir, &LinkExclusions::none())?;
+        }
+        Ok(())
+    }
+
+    fn collect_conflicts(
+        src: &Path,
+        dst: &Path,
+        exclude: &LinkExclusions,
+        conflicts: &mut Vec<ConflictedLink>,
+    ) {
         let entries = match fs::read_dir(src) {
             Ok(e) => e,
             Err(_) => return,
@@ -88,6 +311,9 @@ impl Linker {
         for entry in entries.flatten() {
             let src_path = entry.path();
             let dst_path = dst.join(entry.file_name());
+            if exclude.covers(&src_path) {
+                continue;
+            }
 
             // Use src_path.is_dir() which follows symlinks, so that keg entries
             // like `man -> ../gnuman` (symlinks to directories) are treated as dirs.
@@ -103,10 +329,12 @@ impl Linker {
                     } else {
                         old_target
                     };
-                    Self::collect_conflicts_merged(&src_path, &resolved, &dst_path, conflicts);
+                    Self::collect_conflicts_merged(
+                        &src_path, &resolved, &dst_path, exclude, conflicts,
+                    );
                     continue;
                 }
-                Self::collect_conflicts(&src_path, &dst_path, conflicts);
+                Self::collect_conflicts(&src_path, &dst_path, exclude, conflicts);
                 continue;
             }
 
@@ -121,6 +349,16 @@ impl Linker {
                         continue;
                     }
                 }
+                if let Some(target) = exec_shim_target(&dst_path) {
+                    if target == src_path {
+                        continue;
+                    }
+                    conflicts.push(ConflictedLink {
+                        owned_by: keg_name_from_path(&target),
+                        path: dst_path,
+                    });
+                    continue;
+                }
                 conflicts.push(ConflictedLink {
                     path: dst_path.clone(),
                     owned_by: keg_name_from_symlink(&dst_path),
@@ -141,6 +379,7 @@ impl Linker {
         src: &Path,
         old_target: &Path,
         dst: &Path,
+        exclude: &LinkExclusions,
         conflicts: &mut Vec<ConflictedLink>,
     ) {
         let new_entries = match fs::read_dir(src) {
@@ -151,12 +390,21 @@ impl Linker {
             let src_path = entry.path();
             let matching_old = old_target.join(entry.file_name());
             let dst_path = dst.join(entry.file_name());
+            if exclude.covers(&src_path) {
+                continue;
+            }
 
             if src_path.is_dir() {
                 if matching_old.exists() {
-                    Self::collect_conflicts_merged(&src_path, &matching_old, &dst_path, conflicts);
+                    Self::collect_conflicts_merged(
+                        &src_path,
+                        &matching_old,
+                        &dst_path,
+                        exclude,
+                        conflicts,
+                    );
                 } else {
-                    Self::collect_conflicts(&src_path, &dst_path, conflicts);
+                    Self::collect_conflicts(&src_path, &dst_path, exclude, conflicts);
                 }
                 continue;
             }
@@ -173,86 +421,185 @@ impl Linker {
     }
 
     pub fn link_keg(&self, keg_path: &Path) -> Result<Vec<LinkedFile>, Error> {
-        self.check_conflicts(keg_path)?;
+        self.link_keg_with(keg_path, None, &[])
+    }
+
+    /// Link a keg, writing exec shims with `shims` as their environment for
+    /// the executables in `bin` instead of symlinking them. Entries matching
+    /// `exclude` (see [`LinkExclusions`]) are skipped, and links this keg
+    /// already has for them are removed.
+    pub fn link_keg_with(
+        &self,
+        keg_path: &Path,
+        shims: Option<&ShimEnv>,
+        exclude: &[String],
+    ) -> Result<Vec<LinkedFile>, Error> {
+        let exclude = LinkExclusions::new(keg_path, exclude);
+        self.check_conflicts_excluding(&exclude)?;
         self.link_opt(keg_path)?;
         let mut linked = Vec::new();
         for dir_name in LINK_DIRS {
             let src_dir = keg_path.join(dir_name);
             let dst_dir = self.prefix.join(dir_name);
-            if src_dir.exists() {
-                linked.extend(Self::link_recursive(&src_dir, &dst_dir)?);
+            if !src_dir.exists() {
+                continue;
+            }
+            if exclude.covers(&src_dir) {
+                Self::unlink_recursive(&src_dir, &dst_dir)?;
+                continue;
             }
+            match shims {
+                Some(env) if *dir_name == "bin" => {
+                    linked.extend(Self::shim_bin(keg_path, &src_dir, &dst_dir, env, &exclude)?)
+                }
+                _ => linked.extend(Self::link_recursive(&src_dir, &dst_dir, &exclude)?),
+            }
+        }
+        Ok(linked)
+    }
+
+    fn shim_bin(
+        keg_path: &Path,
+        src: &Path,
+        dst: &Path,
+        env: &ShimEnv,
+        exclude: &LinkExclusions,
+    ) -> Result<Vec<LinkedFile>, Error> {
+        let mut linked = Vec::new();
+        fs::create_dir_all(dst).map_err(|e| {
+            Error::link("failed to create directory")
+                .with_path(dst)
+                .with_source(e)
+        })?;
+
+        for entry in fs::read_dir(src).map_err(|e| {
+            Error::link("failed to read directory")
+                .with_path(src)
+                .with_source(e)
+        })? {
+            let entry = entry.map_err(|e| {
+                Error::link("failed to read directory entry")
+                    .with_path(src)
+                    .with_source(e)
+            })?;
+            let src_path = entry.path();
+            let dst_path = dst.join(entry.file_name());
+            if exclude.covers(&src_path) {
+                Self::unlink_excluded(&src_path, &dst_path)?;
+                continue;
+            }
+
+            if src_path.is_dir() {
+                linked.extend(Self::link_recursive(&src_path, &dst_path, exclude)?);
+                continue;
+            }
+
+            // check_conflicts already ruled out anything that isn't ours; a
+            // symlink here is this keg linked without shims.
+            if dst_path.is_symlink() {
+                let _ = fs::remove_file(&dst_path);
+            }
+            write_shim(&dst_path, &exec_shim_script(keg_path, &src_path, env))?;
+            linked.push(LinkedFile {
+                link_path: dst_path,
+                target_path: src_path,
+            });
         }
         Ok(linked)
     }
 
-    fn link_recursive(src: &Path, dst: &Path) -> Result<Vec<LinkedFile>, Error> {
+    fn link_recursive(
+        src: &Path,
+        dst: &Path,
+        exclude: &LinkExclusions,
+    ) -> Result<Vec<LinkedFile>, Error> {
         let mut linked = Vec::new();
         if !dst.exists() {
-            fs::create_dir_all(dst).map_err(|e| Error::StoreCorruption {
-                message: e.to_string(),
+            fs::create_dir_all(dst).map_err(|e| {
+                Error::link("failed to create directory")
+                    .with_path(dst)
+                    .with_source(e)
             })?;
         }
 
-        for entry in fs::read_dir(src).map_err(|e| Error::StoreCorruption {
-            message: e.to_string(),
+        for entry in fs::read_dir(src).map_err(|e| {
+            Error::link("failed to read directory")
+                .with_path(src)
+                .with_source(e)
         })? {
-            let entry = entry.map_err(|e| Error::StoreCorruption {
-                message: e.to_string(),
+            let entry = entry.map_err(|e| {
+                Error::link("failed to read directory entry")
+                    .with_path(src)
+                    .with_source(e)
             })?;
             let src_path = entry.path();
             let dst_path = dst.join(entry.file_name());
+            if exclude.covers(&src_path) {
+                Self::unlink_excluded(&src_path, &dst_path)?;
+                continue;
+            }
 
             // Use src_path.is_dir() which follows symlinks, so that keg entries
             // like `man -> ../gnuman` (symlinks to directories) are expanded
             // into individual file symlinks instead of conflicting.
             if src_path.is_dir() {
                 if dst_path.symlink_metadata().is_ok() && dst_path.is_symlink() {
-                    let old_target =
-                        fs::read_link(&dst_path).map_err(|e| Error::StoreCorruption {
-                            message: e.to_string(),
-                        })?;
+                    let old_target = fs::read_link(&dst_path).map_err(|e| {
+                        Error::link("failed to read symlink")
+                            .with_path(&dst_path)
+                            .with_source(e)
+                    })?;
                     let _ = fs::remove_file(&dst_path);
-                    Self::link_recursive(&old_target, &dst_path)?;
+                    Self::link_recursive(&old_target, &dst_path, &LinkExclusions::none())?;
                 }
-                linked.extend(Self::link_recursive(&src_path, &dst_path)?);
+                linked.extend(Self::link_recursive(&src_path, &dst_path, exclude)?);
                 continue;
             }
 
-            if dst_path.symlink_metadata().is_ok() {
-                if let Ok(target) = fs::read_link(&dst_path) {
-                    let resolved = if target.is_relative() {
-                        dst_path.parent().unwrap_or(Path::new("")).join(&target)
-                    } else {
-                        target
-                    };
-                    if fs::canonicalize(&resolved).ok() == fs::canonicalize(&src_path).ok() {
-                        if resolved.exists() {
-                            linked.push(LinkedFile {
-                                link_path: dst_path,
-                                target_path: src_path,
-                            });
-                            continue;
-                        } else {
-                            let _ = fs::remove_file(&dst_path);
-                        }
-                    } else {
-                        return Err(Error::LinkConflict {
-                            conflicts: vec![ConflictedLink {
-                                path: dst_path.clone(),
-                                owned_by: keg_name_from_symlink(&dst_path),
-                            }],
+            linked.push(Self::link_file(src_path, dst_path)?);
+        }
+        Ok(linked)
+    }
+
+    /// Drop the links an earlier, unrestricted link of the keg made for an
+    /// entry that is excluded now.
+    fn unlink_excluded(src_path: &Path, dst_path: &Path) -> Result<(), Error> {
+        if src_path.is_dir() && dst_path.is_dir() && !dst_path.is_symlink() {
+            Self::unlink_recursive(src_path, dst_path)?;
+        } else {
+            Self::unlink_file(src_path, dst_path);
+        }
+        Ok(())
+    }
+
+    fn link_file(src_path: PathBuf, dst_path: PathBuf) -> Result<LinkedFile, Error> {
+        if dst_path.symlink_metadata().is_ok() {
+            if let Ok(target) = fs::read_link(&dst_path) {
+                let resolved = if target.is_relative() {
+                    dst_path.parent().unwrap_or(Path::new("")).join(&target)
+                } else {
+                    target
+                };
+                if fs::canonicalize(&resolved).ok() == fs::canonicalize(&src_path).ok() {
+                    if resolved.exists() {
+                        return Ok(LinkedFile {
+                            link_path: dst_path,
+                            target_path: src_path,
                         });
+                    } else {
+                        let _ = fs::remove_file(&dst_path);
                     }
                 } else {
                     return Err(Error::LinkConflict {
                         conflicts: vec![ConflictedLink {
-                            path: dst_path,
-                            owned_by: None,
+                            path: dst_path.clone(),
+                            owned_by: keg_name_from_symlink(&dst_path),
                         }],
                     });
                 }
-            } else if dst_path.exists() {
+            } else if exec_shim_target(&dst_path).as_deref() == Some(src_path.as_path()) {
+                let _ = fs::remove_file(&dst_path);
+            } else {
                 return Err(Error::LinkConflict {
                     conflicts: vec![ConflictedLink {
                         path: dst_path,
@@ -260,19 +607,25 @@ impl Linker {
                     }],
                 });
             }
-
-            #[cfg(unix)]
-            std::os::unix::fs::symlink(&src_path, &dst_path).map_err(|e| {
-                Error::StoreCorruption {
-                    message: e.to_string(),
-                }
-            })?;
-            linked.push(LinkedFile {
-                link_path: dst_path,
-                target_path: src_path,
+        } else if dst_path.exists() {
+            return Err(Error::LinkConflict {
+                conflicts: vec![ConflictedLink {
+                    path: dst_path,
+                    owned_by: None,
+                }],
             });
         }
-        Ok(linked)
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&src_path, &dst_path).map_err(|e| {
+            Error::link("failed to create symlink")
+                .with_path(&dst_path)
+                .with_source(e)
+        })?;
+        Ok(LinkedFile {
+            link_path: dst_path,
+            target_path: src_path,
+        })
     }
 
     pub fn unlink_keg(&self, keg_path: &Path) -> Result<Vec<PathBuf>, Error> {
@@ -293,11 +646,15 @@ impl Linker {
         if !src.exists() || !dst.exists() {
             return Ok(unlinked);
         }
-        for entry in fs::read_dir(src).map_err(|e| Error::StoreCorruption {
-            message: e.to_string(),
+        for entry in fs::read_dir(src).map_err(|e| {
+            Error::link("failed to read directory")
+                .with_path(src)
+                .with_source(e)
         })? {
-            let entry = entry.map_err(|e| Error::StoreCorruption {
-                message: e.to_string(),
+            let entry = entry.map_err(|e| {
+                Error::link("failed to read directory entry")
+                    .with_path(src)
+                    .with_source(e)
             })?;
             let src_path = entry.path();
             let dst_path = dst.join(entry.file_name());
@@ -312,19 +669,170 @@ impl Linker {
                 continue;
             }
 
-            if let Ok(target) = fs::read_link(&dst_path) {
+            if Self::unlink_file(&src_path, &dst_path) {
+                unlinked.push(dst_path);
+            }
+        }
+        Ok(unlinked)
+    }
+
+    /// Remove `dst_path` if it is this keg's link (or shim) for `src_path`.
+    fn unlink_file(src_path: &Path, dst_path: &Path) -> bool {
+        let ours = match fs::read_link(dst_path) {
+            Ok(target) => {
                 let resolved = if target.is_relative() {
                     dst_path.parent().unwrap_or(Path::new("")).join(&target)
                 } else {
                     target
                 };
-                if fs::canonicalize(&resolved).ok() == fs::canonicalize(&src_path).ok() {
-                    let _ = fs::remove_file(&dst_path);
-                    unlinked.push(dst_path);
-                }
+                fs::canonicalize(&resolved).ok() == fs::canonicalize(src_path).ok()
+            }
+            Err(_) => exec_shim_target(dst_path).as_deref() == Some(src_path),
+        };
+        ours && fs::remove_file(dst_path).is_ok()
+    }
+
+    /// Resolve a `--only` selection like `bin/foo`, `share/man` or just `man`
+    /// to a path inside the keg. Names that aren't found at the top of the keg
+    /// are looked up under `share`.
+    pub fn resolve_subset(&self, keg_path: &Path, spec: &str) -> Result<PathBuf, Error> {
+        let invalid = |reason: &str| Error::InvalidArgument {
+            message: format!("cannot link '{spec}': {reason}"),
+        };
+        let spec_path = Path::new(spec.trim_end_matches('/'));
+        if spec_path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(invalid("expected a path relative to the keg"));
+        }
+
+        let subset = if keg_path.join(spec_path).exists() {
+            spec_path.to_path_buf()
+        } else if keg_path.join("share").join(spec_path).exists() {
+            Path::new("share").join(spec_path)
+        } else {
+            return Err(invalid("no such file or directory in the keg"));
+        };
+
+        match subset.components().next() {
+            Some(Component::Normal(top)) if LINK_DIRS.iter().any(|dir| top == *dir) => Ok(subset),
+            _ => Err(invalid(&format!(
+                "only {} are linked into the prefix",
+                LINK_DIRS.join(", ")
+            ))),
+        }
+    }
+
+    /// Link one file or directory of a keg (see [`Self::resolve_subset`]),
+    /// leaving the rest of its links alone.
+    pub fn link_subset(&self, keg_path: &Path, subset: &Path) -> Result<Vec<LinkedFile>, Error> {
+        let conflicts = self.subset_conflicts(keg_path, subset);
+        if !conflicts.is_empty() {
+            return Err(Error::LinkConflict { conflicts });
+        }
+
+        let src = keg_path.join(subset);
+        let dst = self.prefix.join(subset);
+        self.link_opt(keg_path)?;
+        if src.is_dir() {
+            return Self::link_recursive(&src, &dst, &LinkExclusions::none());
+        }
+        if let Some(dst_dir) = dst.parent() {
+            fs::create_dir_all(dst_dir).map_err(|e| {
+                Error::link("failed to create directory")
+                    .with_path(dst_dir)
+                    .with_source(e)
+            })?;
+        }
+        Ok(vec![Self::link_file(src, dst)?])
+    }
+
+    /// Remove the links of one file or directory of a keg.
+    pub fn unlink_subset(&self, keg_path: &Path, subset: &Path) -> Result<Vec<PathBuf>, Error> {
+        let src = keg_path.join(subset);
+        let dst = self.prefix.join(subset);
+        if src.is_dir() && !dst.is_symlink() {
+            return Self::unlink_recursive(&src, &dst);
+        }
+        Ok(if Self::unlink_file(&src, &dst) {
+            vec![dst]
+        } else {
+            Vec::new()
+        })
+    }
+
+    /// The links [`Self::link_keg_with`] would create for a keg, without
+    /// touching the prefix. Links already in place aren't listed.
+    pub fn plan_keg(&self, keg_path: &Path, exclude: &[String]) -> Vec<LinkedFile> {
+        let exclude = LinkExclusions::new(keg_path, exclude);
+        let mut planned = Vec::new();
+        for dir_name in LINK_DIRS {
+            let src_dir = keg_path.join(dir_name);
+            if src_dir.exists() && !exclude.covers(&src_dir) {
+                Self::plan_recursive(
+                    &src_dir,
+                    &self.prefix.join(dir_name),
+                    &exclude,
+                    &mut planned,
+                );
+            }
+        }
+        planned
+    }
+
+    /// [`Self::plan_keg`] for one file or directory of a keg.
+    pub fn plan_subset(&self, keg_path: &Path, subset: &Path) -> Vec<LinkedFile> {
+        let src = keg_path.join(subset);
+        let dst = self.prefix.join(subset);
+        let mut planned = Vec::new();
+        if src.is_dir() {
+            Self::plan_recursive(&src, &dst, &LinkExclusions::none(), &mut planned);
+        } else if !Self::links_to(&dst, &src) {
+            planned.push(LinkedFile {
+                link_path: dst,
+                target_path: src,
+            });
+        }
+        planned
+    }
+
+    fn plan_recursive(
+        src: &Path,
+        dst: &Path,
+        exclude: &LinkExclusions,
+        planned: &mut Vec<LinkedFile>,
+    ) {
+        let Ok(entries) = fs::read_dir(src) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries.flatten().collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let src_path = entry.path();
+            let dst_path = dst.join(entry.file_name());
+            if exclude.covers(&src_path) {
+                continue;
+            }
+            if src_path.is_dir() {
+                Self::plan_recursive(&src_path, &dst_path, exclude, planned);
+            } else if !Self::links_to(&dst_path, &src_path) {
+                planned.push(LinkedFile {
+                    link_path: dst_path,
+                    target_path: src_path,
+                });
             }
         }
-        Ok(unlinked)
+    }
+
+    /// Whether `dst_path` already leads to `src_path`, as a symlink (possibly
+    /// through a linked directory) or an exec shim.
+    fn links_to(dst_path: &Path, src_path: &Path) -> bool {
+        if exec_shim_target(dst_path).as_deref() == Some(src_path) {
+            return true;
+        }
+        dst_path.symlink_metadata().is_ok()
+            && fs::canonicalize(dst_path).ok() == fs::canonicalize(src_path).ok()
     }
 
     fn unlink_opt(&self, keg_path: &Path) -> Result<(), Error> {
@@ -334,45 +842,78 @@ impl Linker {
             .and_then(|n| n.to_str());
         if let Some(name) = name {
             let opt_link = self.opt_dir.join(name);
-            if let Ok(target) = fs::read_link(&opt_link) {
-                let resolved = if target.is_relative() {
-                    opt_link.parent().unwrap_or(Path::new("")).join(&target)
-                } else {
-                    target
-                };
-                if fs::canonicalize(&resolved).ok() == fs::canonicalize(keg_path).ok() {
-                    let _ = fs::remove_file(&opt_link);
-                }
+            if Self::opt_link_points_to(&opt_link, keg_path) {
+                let _ = fs::remove_file(&opt_link);
             }
         }
         Ok(())
     }
 
+    /// Point `opt/<name>` at the keg, and `opt/<name>@<version>` too (see
+    /// [`Self::link_versioned_opt`]).
     pub fn link_opt(&self, keg_path: &Path) -> Result<(), Error> {
         let name = keg_path
             .parent()
             .and_then(|p| p.file_name())
             .and_then(|n| n.to_str())
-            .ok_or_else(|| Error::StoreCorruption {
-                message: "invalid keg path".into(),
-            })?;
-        let opt_link = self.opt_dir.join(name);
+            .ok_or_else(|| Error::link("invalid keg path").with_path(keg_path))?;
+        Self::point_opt_link(&self.opt_dir.join(name), keg_path)?;
+        self.link_versioned_opt(keg_path)
+    }
+
+    /// `opt/<name>@<version>` for the keg at `keg_path`. Unlike `opt/<name>`,
+    /// which follows whichever version is active, it keeps pointing at this
+    /// keg for as long as it exists, so dependents needing that exact
+    /// version can reference it.
+    pub fn versioned_opt_link(&self, keg_path: &Path) -> Option<PathBuf> {
+        let version = keg_path.file_name()?.to_str()?;
+        let name = keg_path.parent()?.file_name()?.to_str()?;
+        Some(self.opt_dir.join(format!("{name}@{version}")))
+    }
+
+    /// Create the versioned opt link of a keg (see
+    /// [`Self::versioned_opt_link`]), which retained kegs keep while unlinked.
+    pub fn link_versioned_opt(&self, keg_path: &Path) -> Result<(), Error> {
+        let opt_link = self
+            .versioned_opt_link(keg_path)
+            .ok_or_else(|| Error::link("invalid keg path").with_path(keg_path))?;
+        Self::point_opt_link(&opt_link, keg_path)
+    }
+
+    /// Remove the versioned opt link of a keg that is going away.
+    pub fn unlink_versioned_opt(&self, keg_path: &Path) {
+        if let Some(opt_link) = self.versioned_opt_link(keg_path)
+            && opt_link.symlink_metadata().is_ok()
+            && (Self::opt_link_points_to(&opt_link, keg_path) || !opt_link.exists())
+        {
+            let _ = fs::remove_file(&opt_link);
+        }
+    }
+
+    fn opt_link_points_to(opt_link: &Path, keg_path: &Path) -> bool {
+        let Ok(target) = fs::read_link(opt_link) else {
+            return false;
+        };
+        let resolved = if target.is_relative() {
+            opt_link.parent().unwrap_or(Path::new("")).join(&target)
+        } else {
+            target
+        };
+        fs::canonicalize(&resolved).ok() == fs::canonicalize(keg_path).ok()
+    }
+
+    fn point_opt_link(opt_link: &Path, keg_path: &Path) -> Result<(), Error> {
         if opt_link.symlink_metadata().is_ok() {
-            if let Ok(target) = fs::read_link(&opt_link) {
-                let resolved = if target.is_relative() {
-                    opt_link.parent().unwrap_or(Path::new("")).join(&target)
-                } else {
-                    target
-                };
-                if fs::canonicalize(&resolved).ok() == fs::canonicalize(keg_path).ok() {
-                    return Ok(());
-                }
+            if Self::opt_link_points_to(opt_link, keg_path) {
+                return Ok(());
             }
-            let _ = fs::remove_file(&opt_link);
+            let _ = fs::remove_file(opt_link);
         }
         #[cfg(unix)]
-        std::os::unix::fs::symlink(keg_path, &opt_link).map_err(|e| Error::StoreCorruption {
-            message: e.to_string(),
+        std::os::unix::fs::symlink(keg_path, opt_link).map_err(|e| {
+            Error::link("failed to create opt link")
+                .with_path(opt_link)
+                .with_source(e)
         })?;
         Ok(())
     }
@@ -385,6 +926,9 @@ impl Linker {
         if let Ok(entries) = fs::read_dir(&keg_bin) {
             for entry in entries.flatten() {
                 let dst_path = self.bin_dir.join(entry.file_name());
+                if exec_shim_target(&dst_path).as_deref() == Some(entry.path().as_path()) {
+                    return true;
+                }
                 if let Ok(target) = fs::read_link(&dst_path) {
                     let resolved = if target.is_relative() {
                         dst_path.parent().unwrap_or(Path::new("")).join(&target)
@@ -426,6 +970,159 @@ mod tests {
         assert!(tmp.path().join("bin/foo").exists());
     }
 
+    #[test]
+    fn shim_mode_writes_exec_shims_that_unlink_cleanly() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        let linker = Linker::new(tmp.path()).unwrap();
+        let shim = tmp.path().join("bin/foo");
+
+        let linked = linker
+            .link_keg_with(&keg, Some(&ShimEnv::default()), &[])
+            .unwrap();
+        assert_eq!(linked.len(), 1);
+        assert!(!shim.is_symlink());
+        assert_eq!(exec_shim_target(&shim), Some(keg.join("bin/foo")));
+        assert!(linker.is_linked(&keg));
+
+        // Relinking is idempotent, and switching back to symlinks replaces the shim.
+        linker
+            .link_keg_with(&keg, Some(&ShimEnv::default()), &[])
+            .unwrap();
+        linker.link_keg(&keg).unwrap();
+        assert!(shim.is_symlink());
+
+        linker
+            .link_keg_with(&keg, Some(&ShimEnv::default()), &[])
+            .unwrap();
+        let other = setup_keg(&tmp, "other");
+        fs::rename(other.join("bin/other"), other.join("bin/foo")).unwrap();
+        let Err(Error::LinkConflict { conflicts }) = linker.check_conflicts(&other) else {
+            panic!("expected a conflict with foo's shim");
+        };
+        assert_eq!(conflicts[0].owned_by.as_deref(), Some("foo"));
+
+        let unlinked = linker.unlink_keg(&keg).unwrap();
+        assert_eq!(unlinked, vec![shim.clone()]);
+        assert!(!shim.exists());
+    }
+
+    #[test]
+    fn excluded_entries_are_skipped_and_their_old_links_removed() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "python");
+        fs::write(keg.join("bin/python3.12"), b"py").unwrap();
+        fs::write(keg.join("bin/pip3"), b"pip").unwrap();
+        fs::create_dir_all(keg.join("share/doc/python")).unwrap();
+        fs::write(keg.join("share/doc/python/README"), b"doc").unwrap();
+        fs::create_dir_all(keg.join("share/man/man1")).unwrap();
+        fs::write(keg.join("share/man/man1/python3.1"), b"man").unwrap();
+        let linker = Linker::new(tmp.path()).unwrap();
+        linker.link_keg(&keg).unwrap();
+
+        // Another tool owns python3.12, which would otherwise conflict.
+        fs::remove_file(tmp.path().join("bin/python3.12")).unwrap();
+        fs::write(tmp.path().join("bin/python3.12"), b"system").unwrap();
+        let exclude = ["bin/python3*".to_string(), "share/doc".to_string()];
+        let linked = linker.link_keg_with(&keg, None, &exclude).unwrap();
+
+        let linked: Vec<_> = linked.iter().map(|file| file.link_path.clone()).collect();
+        assert!(linked.contains(&tmp.path().join("bin/pip3")));
+        assert!(linked.contains(&tmp.path().join("share/man/man1/python3.1")));
+        assert!(!linked.contains(&tmp.path().join("bin/python3.12")));
+        assert_eq!(
+            fs::read(tmp.path().join("bin/python3.12")).unwrap(),
+            b"system"
+        );
+        assert!(!tmp.path().join("share/doc/python/README").exists());
+
+        assert_eq!(
+            LinkExclusions::new(&keg, &exclude).excluded_entries(),
+            [PathBuf::from("bin/python3.12"), PathBuf::from("share/doc")]
+        );
+        assert!(LinkExclusions::new(&keg, &[]).excluded_entries().is_empty());
+    }
+
+    #[test]
+    fn wildcards_match_within_a_component() {
+        assert!(wildcard_match("python3*", "python3"));
+        assert!(wildcard_match("python3*", "python3.12-config"));
+        assert!(wildcard_match("*-config", "python3.12-config"));
+        assert!(wildcard_match("lib?.a", "libz.a"));
+        assert!(!wildcard_match("python3*", "python2"));
+        assert!(!wildcard_match("lib?.a", "libzz.a"));
+    }
+
+    #[test]
+    fn subsets_link_and_unlink_independently() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "coreutils");
+        fs::write(keg.join("bin/ls"), b"ls").unwrap();
+        fs::create_dir_all(keg.join("share/man/man1")).unwrap();
+        fs::write(keg.join("share/man/man1/ls.1"), b"man").unwrap();
+        let linker = Linker::new(tmp.path()).unwrap();
+        linker.link_keg(&keg).unwrap();
+
+        let ls = linker.resolve_subset(&keg, "bin/ls").unwrap();
+        let removed = linker.unlink_subset(&keg, &ls).unwrap();
+        assert_eq!(removed, vec![tmp.path().join("bin/ls")]);
+        assert!(tmp.path().join("bin/coreutils").exists());
+        assert!(tmp.path().join("share/man/man1/ls.1").exists());
+
+        // `man` is found under share.
+        let man = linker.resolve_subset(&keg, "man").unwrap();
+        assert_eq!(man, Path::new("share/man"));
+        linker.unlink_subset(&keg, &man).unwrap();
+        assert!(!tmp.path().join("share/man/man1/ls.1").exists());
+
+        let linked = linker.link_subset(&keg, &ls).unwrap();
+        assert_eq!(linked.len(), 1);
+        assert!(tmp.path().join("bin/ls").exists());
+        assert!(!tmp.path().join("share/man/man1/ls.1").exists());
+
+        // Another tool owning the path is a conflict.
+        fs::remove_file(tmp.path().join("bin/ls")).unwrap();
+        fs::write(tmp.path().join("bin/ls"), b"system ls").unwrap();
+        assert!(matches!(
+            linker.link_subset(&keg, &ls),
+            Err(Error::LinkConflict { .. })
+        ));
+
+        assert!(linker.resolve_subset(&keg, "bin/missing").is_err());
+        assert!(linker.resolve_subset(&keg, "../other").is_err());
+        fs::write(keg.join("INSTALL_RECEIPT.json"), b"{}").unwrap();
+        assert!(linker.resolve_subset(&keg, "INSTALL_RECEIPT.json").is_err());
+    }
+
+    #[test]
+    fn observed_owner_tells_our_kegs_from_homebrews() {
+        let tmp = TempDir::new().unwrap();
+        let ours = setup_keg(&tmp, "foo");
+        // Bottles ship receipts too; only the location tells the kegs apart.
+        fs::write(ours.join("INSTALL_RECEIPT.json"), b"{}").unwrap();
+        let cellar = tmp.path().join("cellar");
+        let homebrew = tmp.path().join("homebrew/Cellar/coreutils/9.5");
+        fs::create_dir_all(homebrew.join("bin")).unwrap();
+        fs::write(homebrew.join("bin/ls"), b"ls").unwrap();
+        fs::write(homebrew.join("INSTALL_RECEIPT.json"), b"{}").unwrap();
+
+        let prefix = tmp.path().join("prefix");
+        let linker = Linker::new(&prefix).unwrap();
+        linker.link_keg(&ours).unwrap();
+        std::os::unix::fs::symlink(homebrew.join("bin/ls"), prefix.join("bin/ls")).unwrap();
+        fs::write(prefix.join("bin/plain"), b"plain").unwrap();
+
+        assert_eq!(
+            observed_owner(&prefix.join("bin/foo"), &cellar).as_deref(),
+            Some("foo")
+        );
+        assert_eq!(
+            observed_owner(&prefix.join("bin/ls"), &cellar).as_deref(),
+            Some("Homebrew's coreutils")
+        );
+        assert_eq!(observed_owner(&prefix.join("bin/plain"), &cellar), None);
+    }
+
     #[test]
     fn merging_directories_works() {
         let tmp = TempDir::new().unwrap();
@@ -495,6 +1192,80 @@ mod tests {
         }
     }
 
+    #[test]
+    fn plans_only_the_links_not_yet_in_place() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path();
+        let linker = Linker::new(prefix).unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        fs::create_dir_all(keg.join("share/doc")).unwrap();
+        fs::write(keg.join("share/doc/README"), b"docs").unwrap();
+
+        let planned: Vec<PathBuf> = linker
+            .plan_keg(&keg, &[])
+            .into_iter()
+            .map(|file| file.link_path)
+            .collect();
+        assert_eq!(
+            planned,
+            [prefix.join("bin/foo"), prefix.join("share/doc/README")]
+        );
+        assert!(!prefix.join("bin/foo").exists());
+
+        linker.link_subset(&keg, Path::new("bin")).unwrap();
+        let planned = linker.plan_keg(&keg, &[]);
+        assert_eq!(planned.len(), 1);
+        assert_eq!(planned[0].target_path, keg.join("share/doc/README"));
+        assert!(linker.plan_subset(&keg, Path::new("bin/foo")).is_empty());
+        assert!(linker.plan_keg(&keg, &["share/doc".to_string()]).is_empty());
+    }
+
+    #[test]
+    fn removing_conflicts_lets_the_keg_link_over_them() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path();
+        let linker = Linker::new(prefix).unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        fs::write(prefix.join("bin/foo"), b"stray").unwrap();
+
+        // A directory linked whole from another keg is split up rather than
+        // having the file deleted inside that keg.
+        let other = prefix.join("cellar/bar/1.0.0");
+        fs::create_dir_all(other.join("share/man")).unwrap();
+        fs::write(other.join("share/man/foo.1"), b"bar's page").unwrap();
+        fs::write(other.join("share/man/bar.1"), b"bar").unwrap();
+        fs::create_dir_all(prefix.join("share")).unwrap();
+        std::os::unix::fs::symlink(other.join("share/man"), prefix.join("share/man")).unwrap();
+        fs::create_dir_all(keg.join("share/man")).unwrap();
+        fs::write(keg.join("share/man/foo.1"), b"foo's page").unwrap();
+
+        let conflicts = linker.conflicts(&LinkExclusions::new(&keg, &[]));
+        assert_eq!(conflicts.len(), 2);
+        linker.remove_conflicts(&conflicts).unwrap();
+        linker.link_keg(&keg).unwrap();
+
+        assert_eq!(fs::read(prefix.join("bin/foo")).unwrap(), b"hi");
+        assert_eq!(
+            fs::read(prefix.join("share/man/foo.1")).unwrap(),
+            b"foo's page"
+        );
+        assert!(prefix.join("share/man/bar.1").is_symlink());
+        assert!(other.join("share/man/foo.1").exists());
+    }
+
+    #[test]
+    fn removing_conflicts_refuses_directories() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path();
+        let linker = Linker::new(prefix).unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        fs::create_dir_all(prefix.join("bin/foo")).unwrap();
+
+        let conflicts = linker.conflicts(&LinkExclusions::new(&keg, &[]));
+        assert!(linker.remove_conflicts(&conflicts).is_err());
+        assert!(prefix.join("bin/foo").is_dir());
+    }
+
     #[test]
     fn check_conflicts_collects_all_conflicts() {
         let tmp = TempDir::new().unwrap();
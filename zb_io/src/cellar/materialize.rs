@@ -30,6 +30,10 @@ impl Cellar {
         Ok(Self { cellar_dir })
     }
 
+    pub fn dir(&self) -> &Path {
+        &self.cellar_dir
+    }
+
     pub fn keg_path(&self, name: &str, version: &str) -> PathBuf {
         self.cellar_dir.join(name).join(version)
     }
@@ -52,8 +56,10 @@ impl Cellar {
 
         // Create parent directory for the keg
         if let Some(parent) = keg_path.parent() {
-            fs::create_dir_all(parent).map_err(|e| Error::StoreCorruption {
-                message: format!("failed to create keg parent directory: {e}"),
+            fs::create_dir_all(parent).map_err(|e| {
+                Error::unpack("failed to create keg parent directory")
+                    .with_path(parent)
+                    .with_source(e)
             })?;
         }
 
@@ -72,15 +78,9 @@ impl Cellar {
         #[cfg(target_os = "linux")]
         {
             // Derive prefix from cellar_dir directly without hardcoded fallback
-            let prefix = self
-                .cellar_dir
-                .parent()
-                .ok_or_else(|| Error::StoreCorruption {
-                    message: format!(
-                        "Invalid cellar directory (no parent): {}",
-                        self.cellar_dir.display()
-                    ),
-                })?;
+            let prefix = self.cellar_dir.parent().ok_or_else(|| {
+                Error::relocate("invalid cellar directory (no parent)").with_path(&self.cellar_dir)
+            })?;
             patch_placeholders(&keg_path, prefix, name, version)?;
         }
 
@@ -98,8 +98,10 @@ impl Cellar {
             return Ok(());
         }
 
-        fs::remove_dir_all(&keg_path).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to remove keg: {e}"),
+        fs::remove_dir_all(&keg_path).map_err(|e| {
+            Error::unpack("failed to remove keg")
+                .with_path(&keg_path)
+                .with_source(e)
         })?;
 
         // Also try to remove the parent (name) directory if it's now empty
@@ -115,7 +117,11 @@ impl Cellar {
 /// Homebrew bottles have structure {name}/{version}/ inside the tarball.
 /// This function finds that directory, falling back to the store_entry root
 /// if the expected structure isn't found.
-fn find_bottle_content(store_entry: &Path, name: &str, version: &str) -> Result<PathBuf, Error> {
+pub(crate) fn find_bottle_content(
+    store_entry: &Path,
+    name: &str,
+    version: &str,
+) -> Result<PathBuf, Error> {
     // Try the expected Homebrew structure: {name}/{version}/
     let expected_path = store_entry.join(name).join(version);
     if expected_path.exists() && expected_path.is_dir() {
@@ -155,6 +161,19 @@ fn copy_dir_with_fallback(src: &Path, dst: &Path) -> Result<(), Error> {
     copy_dir_recursive(src, dst, true)
 }
 
+/// Copy `src` to `dst` without sharing file data with it (a clone on APFS,
+/// else a plain copy), for trees that may be modified where they end up.
+pub(crate) fn copy_dir_detached(src: &Path, dst: &Path) -> Result<(), Error> {
+    #[cfg(target_os = "macos")]
+    {
+        if try_clonefile_dir(src, dst).is_ok() {
+            return Ok(());
+        }
+    }
+
+    copy_dir_recursive(src, dst, false)
+}
+
 #[cfg(target_os = "macos")]
 fn try_clonefile_dir(src: &Path, dst: &Path) -> io::Result<()> {
     use std::ffi::CString;
@@ -181,38 +200,52 @@ fn try_clonefile_dir(src: &Path, dst: &Path) -> io::Result<()> {
 }
 
 fn copy_dir_recursive(src: &Path, dst: &Path, try_hardlink: bool) -> Result<(), Error> {
-    fs::create_dir_all(dst).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to create directory {}: {e}", dst.display()),
+    fs::create_dir_all(dst).map_err(|e| {
+        Error::unpack("failed to create directory")
+            .with_path(dst)
+            .with_source(e)
     })?;
 
-    for entry in fs::read_dir(src).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to read directory {}: {e}", src.display()),
+    for entry in fs::read_dir(src).map_err(|e| {
+        Error::unpack("failed to read directory")
+            .with_path(src)
+            .with_source(e)
     })? {
-        let entry = entry.map_err(|e| Error::StoreCorruption {
-            message: format!("failed to read directory entry: {e}"),
+        let entry = entry.map_err(|e| {
+            Error::unpack("failed to read directory entry")
+                .with_path(src)
+                .with_source(e)
         })?;
 
         let src_path = entry.path();
         let dst_path = dst.join(entry.file_name());
-        let file_type = entry.file_type().map_err(|e| Error::StoreCorruption {
-            message: format!("failed to get file type: {e}"),
+        let file_type = entry.file_type().map_err(|e| {
+            Error::unpack("failed to get file type")
+                .with_path(&src_path)
+                .with_source(e)
         })?;
 
         if file_type.is_dir() {
             copy_dir_recursive(&src_path, &dst_path, try_hardlink)?;
         } else if file_type.is_symlink() {
-            let target = fs::read_link(&src_path).map_err(|e| Error::StoreCorruption {
-                message: format!("failed to read symlink: {e}"),
+            let target = fs::read_link(&src_path).map_err(|e| {
+                Error::unpack("failed to read symlink")
+                    .with_path(&src_path)
+                    .with_source(e)
             })?;
 
             #[cfg(unix)]
-            std::os::unix::fs::symlink(&target, &dst_path).map_err(|e| Error::StoreCorruption {
-                message: format!("failed to create symlink: {e}"),
+            std::os::unix::fs::symlink(&target, &dst_path).map_err(|e| {
+                Error::unpack("failed to create symlink")
+                    .with_path(&dst_path)
+                    .with_source(e)
             })?;
 
             #[cfg(not(unix))]
-            fs::copy(&src_path, &dst_path).map_err(|e| Error::StoreCorruption {
-                message: format!("failed to copy symlink as file: {e}"),
+            fs::copy(&src_path, &dst_path).map_err(|e| {
+                Error::unpack("failed to copy symlink as file")
+                    .with_path(&src_path)
+                    .with_source(e)
             })?;
         } else {
             // Try hardlink first, then copy
@@ -221,20 +254,24 @@ fn copy_dir_recursive(src: &Path, dst: &Path, try_hardlink: bool) -> Result<(),
             }
 
             // Fall back to copy
-            fs::copy(&src_path, &dst_path).map_err(|e| Error::StoreCorruption {
-                message: format!("failed to copy file: {e}"),
+            fs::copy(&src_path, &dst_path).map_err(|e| {
+                Error::unpack("failed to copy file")
+                    .with_path(&dst_path)
+                    .with_source(e)
             })?;
 
             // Preserve permissions
             #[cfg(unix)]
             {
-                let metadata = fs::metadata(&src_path).map_err(|e| Error::StoreCorruption {
-                    message: format!("failed to read metadata: {e}"),
+                let metadata = fs::metadata(&src_path).map_err(|e| {
+                    Error::unpack("failed to read metadata")
+                        .with_path(&src_path)
+                        .with_source(e)
                 })?;
                 fs::set_permissions(&dst_path, metadata.permissions()).map_err(|e| {
-                    Error::StoreCorruption {
-                        message: format!("failed to set permissions: {e}"),
-                    }
+                    Error::unpack("failed to set permissions")
+                        .with_path(&dst_path)
+                        .with_source(e)
                 })?;
             }
         }
@@ -1,5 +1,9 @@
+pub mod lazy;
 pub mod link;
 pub mod materialize;
+pub mod shim;
 
-pub use link::{LinkedFile, Linker};
+pub use lazy::LazyLauncher;
+pub use link::{LinkExclusions, LinkedFile, Linker};
 pub use materialize::{Cellar, CopyStrategy};
+pub use shim::{ShimEnv, version_env_var};
@@ -0,0 +1,185 @@
+//! Exec shims: small shell scripts written into `prefix/bin` in place of
+//! symlinks for formulas linked in shim mode.
+//!
+//! A shim execs the keg's binary after injecting its environment, and lets
+//! `ZEROBREW_<NAME>_VERSION` pick another installed version of the formula
+//! for a single invocation.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use zb_core::Error;
+
+const EXEC_SHIM_MARKER: &str = "# zerobrew shim: ";
+
+/// Environment applied by every shim of a keg.
+#[derive(Debug, Clone, Default)]
+pub struct ShimEnv {
+    /// Directories prepended to `PATH`, e.g. `opt/<dep>/bin` of keg-only dependencies.
+    pub path: Vec<PathBuf>,
+    pub vars: BTreeMap<String, String>,
+}
+
+pub(crate) fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The variable that overrides which version of `keg_name` a shim runs,
+/// e.g. `ZEROBREW_PYTHON_3_12_VERSION` for `python@3.12`.
+pub fn version_env_var(keg_name: &str) -> String {
+    let name: String = keg_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("ZEROBREW_{name}_VERSION")
+}
+
+/// Script for the shim of `target`, an executable in `keg_path/bin`.
+pub(crate) fn exec_shim_script(keg_path: &Path, target: &Path, env: &ShimEnv) -> String {
+    let kegs_dir = keg_path.parent().unwrap_or(keg_path);
+    let keg_name = kegs_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let relative = target.strip_prefix(keg_path).unwrap_or(target);
+    let var = version_env_var(&keg_name);
+
+    let mut script = format!(
+        "#!/bin/sh\n{EXEC_SHIM_MARKER}{}\nkeg={}\nif [ -n \"${{{var}:-}}\" ]; then\n  keg={}\"${var}\"\n  [ -d \"$keg\" ] || {{ echo \"zerobrew: {keg_name} ${var} is not installed\" >&2; exit 1; }}\nfi\n",
+        target.display(),
+        sh_quote(&keg_path.to_string_lossy()),
+        sh_quote(&format!("{}/", kegs_dir.display())),
+    );
+    if !env.path.is_empty() {
+        let dirs: Vec<String> = env
+            .path
+            .iter()
+            .map(|dir| sh_quote(&dir.to_string_lossy()))
+            .collect();
+        script.push_str(&format!("PATH={}:\"$PATH\"\nexport PATH\n", dirs.join(":")));
+    }
+    for (key, value) in env.vars.iter().filter(|(key, _)| is_env_name(key)) {
+        script.push_str(&format!("{key}={}\nexport {key}\n", sh_quote(value)));
+    }
+    script.push_str(&format!(
+        "exec \"$keg\"/{} \"$@\"\n",
+        sh_quote(&relative.to_string_lossy())
+    ));
+    script
+}
+
+pub(crate) fn write_shim(path: &Path, script: &str) -> Result<(), Error> {
+    fs::write(path, script).map_err(|e| {
+        Error::link("failed to write shim")
+            .with_path(path)
+            .with_source(e)
+    })?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).map_err(|e| {
+            Error::link("failed to make shim executable")
+                .with_path(path)
+                .with_source(e)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Second line of a regular (non-symlink) file, where shims keep their marker.
+pub(crate) fn marker_line(path: &Path) -> Option<String> {
+    if path.is_symlink() {
+        return None;
+    }
+    let file = fs::File::open(path).ok()?;
+    BufReader::new(file).lines().nth(1)?.ok()
+}
+
+/// The executable an exec shim at `path` runs by default, or `None` if
+/// `path` isn't one.
+pub(crate) fn exec_shim_target(path: &Path) -> Option<PathBuf> {
+    marker_line(path)?
+        .strip_prefix(EXEC_SHIM_MARKER)
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    #[test]
+    fn version_env_var_is_shell_safe() {
+        assert_eq!(version_env_var("node"), "ZEROBREW_NODE_VERSION");
+        assert_eq!(
+            version_env_var("python@3.12"),
+            "ZEROBREW_PYTHON_3_12_VERSION"
+        );
+    }
+
+    #[test]
+    fn shim_injects_env_and_switches_versions() {
+        let tmp = TempDir::new().unwrap();
+        let kegs = tmp.path().join("Cellar/tool");
+        for version in ["1.0", "2.0"] {
+            let bin = kegs.join(version).join("bin");
+            fs::create_dir_all(&bin).unwrap();
+            write_shim(
+                &bin.join("tool"),
+                &format!("#!/bin/sh\necho {version} \"$TOOL_MODE\" \"$PATH\" \"$@\"\n"),
+            )
+            .unwrap();
+        }
+
+        let keg = kegs.join("2.0");
+        let env = ShimEnv {
+            path: vec![PathBuf::from("/opt/dep/bin")],
+            vars: BTreeMap::from([
+                ("TOOL_MODE".to_string(), "fast".to_string()),
+                ("BAD; rm -rf /".to_string(), "x".to_string()),
+            ]),
+        };
+        let shim = tmp.path().join("tool");
+        write_shim(&shim, &exec_shim_script(&keg, &keg.join("bin/tool"), &env)).unwrap();
+        assert_eq!(exec_shim_target(&shim), Some(keg.join("bin/tool")));
+
+        let run = |version: Option<&str>| {
+            let mut command = Command::new(&shim);
+            command.arg("x y").env("PATH", "/usr/bin:/bin");
+            if let Some(version) = version {
+                command.env("ZEROBREW_TOOL_VERSION", version);
+            }
+            command.output().unwrap()
+        };
+
+        let output = run(None);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "2.0 fast /opt/dep/bin:/usr/bin:/bin x y\n"
+        );
+        assert!(String::from_utf8_lossy(&run(Some("1.0")).stdout).starts_with("1.0 "));
+
+        let missing = run(Some("9.9"));
+        assert!(!missing.status.success());
+        assert!(String::from_utf8_lossy(&missing.stderr).contains("is not installed"));
+    }
+}
@@ -0,0 +1,787 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use zb_core::{Error, formula_token};
+
+/// User settings read from `<root>/config.toml`.
+///
+/// ```toml
+/// retain_versions = 1
+/// bottle_tag = "arm64_sequoia"
+/// bottle_domain = "https://artifacts.example.com/homebrew-bottles"
+/// link_exclude = ["share/doc"]
+///
+/// [formula.node]
+/// retain_versions = 3
+/// link_mode = "shim"
+/// env = { NODE_OPTIONS = "--max-old-space-size=4096" }
+///
+/// [formula.python]
+/// link_exclude = ["bin/python3*"]
+///
+/// [notify]
+/// enabled = true
+/// min_seconds = 60
+///
+/// [licenses]
+/// require_acceptance = ["BUSL-1.1", "SSPL-1.0"]
+///
+/// [security]
+/// ecosystem = "Alpine"
+/// verification_report = true
+///
+/// [shell]
+/// path_check = false
+///
+/// [cache]
+/// verify_blobs = true
+/// max_age_days = 14
+/// max_size_gb = 10
+///
+/// [analytics]
+/// enabled = false
+///
+/// [index]
+/// stale_after_days = 30
+///
+/// [retry]
+/// attempts = 6
+/// backoff_ms = 500
+///
+/// [download]
+/// stall_timeout_secs = 120
+/// stall_policy = "skip"
+/// command_timeout_secs = 300
+///
+/// [auth]
+/// github_token = "ghp_..."
+///
+/// [proxy]
+/// url = "http://proxy.corp.example:3128"
+/// username = "builder"
+/// no_proxy = ["localhost", ".corp.example"]
+///
+/// [cask]
+/// appdir = "~/Applications"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Superseded versions kept per formula unless overridden below.
+    pub retain_versions: usize,
+    pub link_mode: LinkMode,
+    /// Keg entries never linked into the prefix, for every formula (see
+    /// [`crate::cellar::LinkExclusions`]).
+    pub link_exclude: Vec<String>,
+    /// Bottle tag to install ahead of the host's own, e.g. on a beta macOS
+    /// release nothing is published for yet. `ZEROBREW_BOTTLE_TAG` overrides it.
+    pub bottle_tag: Option<String>,
+    /// Mirror serving homebrew/core bottles in place of
+    /// `https://ghcr.io/v2/homebrew/core`; bottles it lacks come from there.
+    /// `ZEROBREW_BOTTLE_DOMAIN` (or Homebrew's `HOMEBREW_BOTTLE_DOMAIN`)
+    /// overrides it.
+    pub bottle_domain: Option<String>,
+    pub notify: NotifyConfig,
+    pub licenses: LicenseConfig,
+    pub security: SecurityConfig,
+    pub shell: ShellConfig,
+    pub cache: CacheConfig,
+    pub analytics: AnalyticsConfig,
+    pub index: IndexConfig,
+    pub retry: RetryConfig,
+    pub download: DownloadConfig,
+    pub auth: AuthConfig,
+    pub proxy: ProxyConfig,
+    pub cask: CaskConfig,
+    pub formula: BTreeMap<String, FormulaConfig>,
+}
+
+/// Desktop notifications when a long install finishes (off by default).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub enabled: bool,
+    /// Operations that finish faster than this stay silent.
+    pub min_seconds: u64,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_seconds: 30,
+        }
+    }
+}
+
+/// Licenses that must be accepted before a formula under them is installed,
+/// and which licenses may be installed at all.
+///
+/// Identifiers are SPDX, compared case-insensitively; a trailing `*`
+/// matches any suffix (`"AGPL-*"`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LicenseConfig {
+    /// Defaults to common source-available licenses that aren't
+    /// OSI-approved.
+    pub require_acceptance: Vec<String>,
+    /// When set, only formulas under these licenses are installed, and
+    /// formulas without license metadata aren't.
+    pub allow: Vec<String>,
+    /// Formulas under these licenses are never installed.
+    pub deny: Vec<String>,
+    /// Formulas `allow` and `deny` don't apply to.
+    pub exempt: Vec<String>,
+}
+
+impl Default for LicenseConfig {
+    fn default() -> Self {
+        Self {
+            require_acceptance: ["BUSL-1.1", "SSPL-1.0", "Elastic-2.0"]
+                .map(String::from)
+                .to_vec(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+            exempt: Vec::new(),
+        }
+    }
+}
+
+/// Where `zb audit --security` looks up advisories.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SecurityConfig {
+    pub osv_url: String,
+    /// OSV ecosystem to scope lookups to; formulas are matched by name
+    /// across ecosystems when unset.
+    pub ecosystem: Option<String>,
+    /// Write a [`crate::VerificationReport`] into each keg installed from a
+    /// bottle.
+    pub verification_report: bool,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            osv_url: "https://api.osv.dev".to_string(),
+            ecosystem: None,
+            verification_report: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ShellConfig {
+    /// Warn after an install when the linked executables aren't what the
+    /// shell will run.
+    pub path_check: bool,
+}
+
+impl Default for ShellConfig {
+    fn default() -> Self {
+        Self { path_check: true }
+    }
+}
+
+/// Download cache settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Re-check the hash of a cached bottle before reusing it, unless its
+    /// size and mtime are unchanged since it was downloaded.
+    pub verify_blobs: bool,
+    /// Downloaded bottles older than this are pruned by `zb cleanup`.
+    pub max_age_days: u64,
+    /// `zb cleanup` also prunes the oldest downloads until the rest fit.
+    pub max_size_gb: Option<u64>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            verify_blobs: false,
+            max_age_days: 30,
+            max_size_gb: None,
+        }
+    }
+}
+
+/// Download and cache statistics kept for `zb stats`. They never leave
+/// this machine.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AnalyticsConfig {
+    pub enabled: bool,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Local formula index settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IndexConfig {
+    /// Installs resolved from an index older than this many days warn that
+    /// versions may be out of date; 0 turns the warning off.
+    pub stale_after_days: u64,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            stale_after_days: 14,
+        }
+    }
+}
+
+/// Retries of transient network failures (dropped connections and the
+/// statuses below) for API requests and bottle downloads.
+/// `ZEROBREW_RETRY_ATTEMPTS` and `ZEROBREW_RETRY_BACKOFF_MS` override it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Tries per request, the first included; 1 disables retries.
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for each one after it.
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Wait a random 50-100% of the backoff, so parallel downloads that
+    /// failed together don't retry in lockstep.
+    pub jitter: bool,
+    /// HTTP statuses worth another try.
+    pub statuses: Vec<u16>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 4,
+            backoff_ms: 100,
+            max_backoff_ms: 5_000,
+            jitter: true,
+            statuses: vec![408, 429, 500, 502, 503, 504],
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Whether a response with `status` should be retried.
+    pub fn retries_status(&self, status: u16) -> bool {
+        self.statuses.contains(&status)
+    }
+
+    /// How long to wait before retry number `retry` (counting from 1).
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(20);
+        let backoff = self
+            .backoff_ms
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff_ms);
+        if !self.jitter {
+            return Duration::from_millis(backoff);
+        }
+        // Cheap randomness is plenty to spread retries apart.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos() as u64);
+        Duration::from_millis(backoff / 2 + nanos % (backoff / 2 + 1))
+    }
+}
+
+/// Stall detection for bottle downloads and the time limit for helper tools.
+/// `ZEROBREW_DOWNLOAD_STALL_TIMEOUT`, `ZEROBREW_STALL_POLICY` and
+/// `ZEROBREW_COMMAND_TIMEOUT` override it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct DownloadConfig {
+    /// Seconds a download may go without receiving any bytes before it is
+    /// aborted.
+    pub stall_timeout_secs: u64,
+    /// What becomes of a stalled download: `retry`, `retry:<n>` or `skip`.
+    pub stall_policy: String,
+    /// Seconds a helper tool (codesign, install_name_tool, brew, ...) may
+    /// run before it is killed.
+    pub command_timeout_secs: u64,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            stall_timeout_secs: 60,
+            stall_policy: "retry".to_string(),
+            command_timeout_secs: 120,
+        }
+    }
+}
+
+/// Credentials for private taps. `ZEROBREW_GITHUB_TOKEN` (or Homebrew's
+/// `HOMEBREW_GITHUB_API_TOKEN`) and `ZEROBREW_GHCR_TOKEN` override them.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Reads private tap repositories and their formula files on GitHub.
+    pub github_token: Option<String>,
+    /// Pulls bottles from private ghcr.io packages; the GitHub token when unset.
+    pub ghcr_token: Option<String>,
+}
+
+impl AuthConfig {
+    pub fn ghcr_token(&self) -> Option<String> {
+        self.ghcr_token
+            .clone()
+            .or_else(|| self.github_token.clone())
+    }
+}
+
+// Keeps tokens out of debug output.
+impl std::fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redact = |token: &Option<String>| token.as_ref().map(|_| "<redacted>");
+        f.debug_struct("AuthConfig")
+            .field("github_token", &redact(&self.github_token))
+            .field("ghcr_token", &redact(&self.ghcr_token))
+            .finish()
+    }
+}
+
+/// Proxy for API requests and bottle downloads. Without a `url`, the
+/// standard `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` variables apply.
+/// `ZEROBREW_PROXY_PASSWORD` overrides the password.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    pub url: Option<String>,
+    /// Basic auth credentials sent to the proxy.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Hosts reached directly (`NO_PROXY` syntax); `NO_PROXY` when empty.
+    pub no_proxy: Vec<String>,
+}
+
+// Keeps the password out of debug output.
+impl std::fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("no_proxy", &self.no_proxy)
+            .finish()
+    }
+}
+
+/// Where cask artifacts go.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CaskConfig {
+    /// Directory `.app` bundles are copied into; `~` is the home directory.
+    /// Defaults to `/Applications` when writable, else `~/Applications`.
+    pub appdir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FormulaConfig {
+    pub retain_versions: Option<usize>,
+    pub link_mode: Option<LinkMode>,
+    /// Keg entries of this formula never linked, on top of the global ones.
+    pub link_exclude: Vec<String>,
+    /// Variables set by the formula's shims (only applies in shim mode).
+    pub env: BTreeMap<String, String>,
+}
+
+/// How a keg's executables are exposed in `prefix/bin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    #[default]
+    Symlink,
+    /// Exec shims that can inject environment and switch versions per
+    /// invocation (see [`crate::cellar::shim`]).
+    Shim,
+}
+
+impl Config {
+    /// Load the config file, treating a missing file as an empty config.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let mut config = Self::load_file(path)?;
+        if let Ok(tag) = std::env::var("ZEROBREW_BOTTLE_TAG")
+            && !tag.is_empty()
+        {
+            config.bottle_tag = Some(tag);
+        }
+        let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        if let Some(token) =
+            env("ZEROBREW_GITHUB_TOKEN").or_else(|| env("HOMEBREW_GITHUB_API_TOKEN"))
+        {
+            config.auth.github_token = Some(token);
+        }
+        if let Some(domain) =
+            env("ZEROBREW_BOTTLE_DOMAIN").or_else(|| env("HOMEBREW_BOTTLE_DOMAIN"))
+        {
+            config.bottle_domain = Some(domain);
+        }
+        if let Some(token) = env("ZEROBREW_GHCR_TOKEN") {
+            config.auth.ghcr_token = Some(token);
+        }
+        if let Some(password) = env("ZEROBREW_PROXY_PASSWORD") {
+            config.proxy.password = Some(password);
+        }
+        if let Some(attempts) = env("ZEROBREW_RETRY_ATTEMPTS").and_then(|v| v.parse().ok()) {
+            config.retry.attempts = attempts;
+        }
+        if let Some(backoff) = env("ZEROBREW_RETRY_BACKOFF_MS").and_then(|v| v.parse().ok()) {
+            config.retry.backoff_ms = backoff;
+        }
+        if let Some(stall) = env("ZEROBREW_DOWNLOAD_STALL_TIMEOUT").and_then(|v| v.parse().ok()) {
+            config.download.stall_timeout_secs = stall;
+        }
+        if let Some(policy) = env("ZEROBREW_STALL_POLICY") {
+            config.download.stall_policy = policy;
+        }
+        if let Some(timeout) = env("ZEROBREW_COMMAND_TIMEOUT").and_then(|v| v.parse().ok()) {
+            config.download.command_timeout_secs = timeout;
+        }
+        Ok(config)
+    }
+
+    fn load_file(path: &Path) -> Result<Self, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents).map_err(|e| match e {
+                Error::InvalidArgument { message } => Error::InvalidArgument {
+                    message: format!("{}: {message}", path.display()),
+                },
+                other => other,
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(Error::FileError {
+                message: format!("failed to read {}: {e}", path.display()),
+            }),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        toml::from_str(contents).map_err(|e| Error::InvalidArgument {
+            message: format!("invalid config: {}", e.message()),
+        })
+    }
+
+    fn formula(&self, name: &str) -> Option<&FormulaConfig> {
+        self.formula
+            .get(name)
+            .or_else(|| self.formula.get(formula_token(name)))
+    }
+
+    /// How many superseded versions of `name` to keep after an upgrade.
+    pub fn retain_versions(&self, name: &str) -> usize {
+        self.formula(name)
+            .and_then(|formula| formula.retain_versions)
+            .unwrap_or(self.retain_versions)
+    }
+
+    pub fn link_mode(&self, name: &str) -> LinkMode {
+        self.formula(name)
+            .and_then(|formula| formula.link_mode)
+            .unwrap_or(self.link_mode)
+    }
+
+    /// Link exclusion patterns for `name`: the global ones, then its own.
+    pub fn link_exclude(&self, name: &str) -> Vec<String> {
+        let mut patterns = self.link_exclude.clone();
+        if let Some(formula) = self.formula(name) {
+            patterns.extend(formula.link_exclude.iter().cloned());
+        }
+        patterns
+    }
+
+    pub fn env(&self, name: &str) -> BTreeMap<String, String> {
+        self.formula(name)
+            .map(|formula| formula.env.clone())
+            .unwrap_or_default()
+    }
+
+    /// Whether the `allow`/`deny` policy lets `name` be installed under
+    /// `license`. An `OR` expression needs one permitted choice, an `AND`
+    /// expression needs every part permitted.
+    pub fn license_permits(&self, name: &str, license: Option<&str>) -> bool {
+        let policy = &self.licenses;
+        if policy.exempt.iter().any(|exempt| {
+            exempt.eq_ignore_ascii_case(name) || exempt.eq_ignore_ascii_case(formula_token(name))
+        }) {
+            return true;
+        }
+        let Some(license) = license else {
+            return policy.allow.is_empty();
+        };
+        let permits = |id: &str| {
+            !policy
+                .deny
+                .iter()
+                .any(|pattern| license_matches(pattern, id))
+                && (policy.allow.is_empty()
+                    || policy
+                        .allow
+                        .iter()
+                        .any(|pattern| license_matches(pattern, id)))
+        };
+        let tokens = spdx_tokens(license);
+        let mut position = 0;
+        // An expression that doesn't parse is judged by its identifiers alone.
+        match spdx_or(&tokens, &mut position, &permits) {
+            Some(permitted) if position == tokens.len() => permitted,
+            _ => tokens
+                .iter()
+                .filter(|token| !matches!(**token, "AND" | "OR" | "WITH" | "(" | ")"))
+                .all(|id| permits(id)),
+        }
+    }
+
+    /// Whether any identifier in the SPDX expression `license` is one the
+    /// user has to accept.
+    pub fn license_requires_acceptance(&self, license: &str) -> bool {
+        license
+            .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .filter(|id| !id.is_empty() && !matches!(*id, "AND" | "OR" | "WITH"))
+            .any(|id| {
+                self.licenses
+                    .require_acceptance
+                    .iter()
+                    .any(|required| required.eq_ignore_ascii_case(id))
+            })
+    }
+}
+
+fn license_matches(pattern: &str, id: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => id
+            .get(..prefix.len())
+            .is_some_and(|head| head.eq_ignore_ascii_case(prefix)),
+        None => pattern.eq_ignore_ascii_case(id),
+    }
+}
+
+fn spdx_tokens(expression: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    for word in expression.split_whitespace() {
+        let mut rest = word;
+        while let Some(i) = rest.find(['(', ')']) {
+            if i > 0 {
+                tokens.push(&rest[..i]);
+            }
+            tokens.push(&rest[i..=i]);
+            rest = &rest[i + 1..];
+        }
+        if !rest.is_empty() {
+            tokens.push(rest);
+        }
+    }
+    tokens
+}
+
+/// `term (OR term)*`; `None` when the expression is malformed.
+fn spdx_or(tokens: &[&str], position: &mut usize, permits: &dyn Fn(&str) -> bool) -> Option<bool> {
+    let mut permitted = spdx_and(tokens, position, permits)?;
+    while tokens.get(*position) == Some(&"OR") {
+        *position += 1;
+        permitted |= spdx_and(tokens, position, permits)?;
+    }
+    Some(permitted)
+}
+
+/// `license (AND license)*`, where AND binds tighter than OR.
+fn spdx_and(tokens: &[&str], position: &mut usize, permits: &dyn Fn(&str) -> bool) -> Option<bool> {
+    let mut permitted = spdx_license(tokens, position, permits)?;
+    while tokens.get(*position) == Some(&"AND") {
+        *position += 1;
+        permitted &= spdx_license(tokens, position, permits)?;
+    }
+    Some(permitted)
+}
+
+/// An identifier, optionally `WITH` an exception, or a parenthesized
+/// expression.
+fn spdx_license(
+    tokens: &[&str],
+    position: &mut usize,
+    permits: &dyn Fn(&str) -> bool,
+) -> Option<bool> {
+    let token = *tokens.get(*position)?;
+    *position += 1;
+    if token == "(" {
+        let permitted = spdx_or(tokens, position, permits)?;
+        if tokens.get(*position) != Some(&")") {
+            return None;
+        }
+        *position += 1;
+        return Some(permitted);
+    }
+    if matches!(token, ")" | "AND" | "OR" | "WITH") {
+        return None;
+    }
+    if tokens.get(*position) == Some(&"WITH") {
+        *position += 2;
+    }
+    Some(permits(token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn per_formula_settings_override_defaults() {
+        let config = Config::parse(
+            r#"
+            retain_versions = 1
+            bottle_tag = "arm64_ventura"
+            link_exclude = ["share/doc"]
+
+            [formula.node]
+            retain_versions = 3
+            link_mode = "shim"
+            env = { NODE_OPTIONS = "--max-old-space-size=4096" }
+            link_exclude = ["bin/corepack"]
+
+            [formula."hashicorp/tap/terraform"]
+            retain_versions = 0
+
+            [notify]
+            enabled = true
+
+            [cache]
+            max_age_days = 14
+            max_size_gb = 5
+
+            [analytics]
+            enabled = false
+
+            [auth]
+            github_token = "ghp_secret"
+
+            [cask]
+            appdir = "~/Applications"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.retain_versions("node"), 3);
+        assert_eq!(config.retain_versions("jq"), 1);
+        assert_eq!(config.retain_versions("hashicorp/tap/terraform"), 0);
+        assert_eq!(config.link_mode("node"), LinkMode::Shim);
+        assert_eq!(config.link_mode("jq"), LinkMode::Symlink);
+        assert_eq!(
+            config.env("node").get("NODE_OPTIONS").map(String::as_str),
+            Some("--max-old-space-size=4096")
+        );
+        assert_eq!(config.link_exclude("node"), ["share/doc", "bin/corepack"]);
+        assert_eq!(config.link_exclude("jq"), ["share/doc"]);
+        assert!(config.notify.enabled);
+        assert_eq!(config.notify.min_seconds, 30);
+        assert_eq!(config.cache.max_age_days, 14);
+        assert_eq!(config.cache.max_size_gb, Some(5));
+        assert!(!config.cache.verify_blobs);
+        assert!(!config.analytics.enabled);
+        assert_eq!(config.auth.ghcr_token().as_deref(), Some("ghp_secret"));
+        assert!(!format!("{config:?}").contains("ghp_secret"));
+        assert_eq!(config.bottle_tag.as_deref(), Some("arm64_ventura"));
+        assert_eq!(
+            config.cask.appdir.as_deref(),
+            Some(Path::new("~/Applications"))
+        );
+    }
+
+    #[test]
+    fn retry_backoff_doubles_up_to_the_cap() {
+        let config = Config::parse(
+            r#"
+            [retry]
+            attempts = 6
+            backoff_ms = 200
+            max_backoff_ms = 1000
+            jitter = false
+            "#,
+        )
+        .unwrap();
+        let retry = &config.retry;
+        assert_eq!(retry.attempts, 6);
+        assert_eq!(retry.delay(1), Duration::from_millis(200));
+        assert_eq!(retry.delay(2), Duration::from_millis(400));
+        assert_eq!(retry.delay(4), Duration::from_millis(1000));
+        assert!(retry.retries_status(503));
+        assert!(!retry.retries_status(404));
+
+        let jittered = RetryConfig::default().delay(3);
+        assert!(jittered >= Duration::from_millis(200) && jittered <= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn missing_file_is_empty_config_and_bad_values_are_rejected() {
+        let tmp = TempDir::new().unwrap();
+        let config = Config::load(&tmp.path().join("config.toml")).unwrap();
+        assert_eq!(config.retain_versions("jq"), 0);
+
+        let path = tmp.path().join("bad.toml");
+        std::fs::write(&path, "[formula.jq]\nretain_versions = -1\n").unwrap();
+        let err = Config::load(&path).unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument { .. }));
+        assert!(err.to_string().contains("bad.toml"));
+    }
+
+    #[test]
+    fn license_expressions_match_configured_identifiers() {
+        let config = Config::default();
+        assert!(config.license_requires_acceptance("BUSL-1.1"));
+        assert!(config.license_requires_acceptance("(MIT OR busl-1.1)"));
+        assert!(!config.license_requires_acceptance("Apache-2.0 WITH LLVM-exception"));
+
+        let config = Config::parse("[licenses]\nrequire_acceptance = []\n").unwrap();
+        assert!(!config.license_requires_acceptance("BUSL-1.1"));
+    }
+
+    #[test]
+    fn license_policy_evaluates_spdx_expressions() {
+        let config = Config::default();
+        assert!(config.license_permits("jq", None));
+        assert!(config.license_permits("jq", Some("AGPL-3.0-only")));
+
+        let config = Config::parse(
+            r#"
+            [licenses]
+            deny = ["AGPL-*"]
+            exempt = ["hashicorp/tap/vault"]
+            "#,
+        )
+        .unwrap();
+        assert!(!config.license_permits("mongo", Some("agpl-3.0-or-later")));
+        assert!(config.license_permits("mongo", Some("AGPL-3.0-only OR MIT")));
+        assert!(config.license_permits("mongo", Some("MIT AND (AGPL-3.0-only OR GPL-2.0)")));
+        assert!(!config.license_permits("mongo", Some("MIT AND AGPL-3.0-only")));
+        assert!(config.license_permits("hashicorp/tap/vault", Some("AGPL-3.0-only")));
+        assert!(!config.license_permits("vault", Some("AGPL-3.0-only")));
+        assert!(config.license_permits("jq", None));
+
+        let config = Config::parse(
+            r#"
+            [licenses]
+            allow = ["MIT", "Apache-2.0", "BSD-*"]
+            "#,
+        )
+        .unwrap();
+        assert!(config.license_permits("a", Some("Apache-2.0 WITH LLVM-exception")));
+        assert!(config.license_permits("a", Some("(MIT OR GPL-2.0-only) AND BSD-3-Clause")));
+        assert!(!config.license_permits("a", Some("MIT AND GPL-2.0-only")));
+        assert!(!config.license_permits("a", Some("GPL-2.0-only")));
+        assert!(!config.license_permits("a", None));
+        // Unbalanced expressions fall back to requiring every identifier.
+        assert!(!config.license_permits("a", Some("(MIT OR GPL-2.0-only")));
+        assert!(config.license_permits("a", Some("(MIT OR BSD-2-Clause")));
+    }
+}
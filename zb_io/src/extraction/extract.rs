@@ -19,14 +19,13 @@ enum CompressionFormat {
 }
 
 fn detect_compression(path: &Path) -> Result<CompressionFormat, Error> {
-    let mut file = File::open(path).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to open tarball: {e}"),
-    })?;
+    let mut file =
+        File::open(path).map_err(|e| Error::unpack("failed to open tarball").with_source(e))?;
 
     let mut magic = [0u8; 6];
-    let bytes_read = file.read(&mut magic).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to read magic bytes: {e}"),
-    })?;
+    let bytes_read = file
+        .read(&mut magic)
+        .map_err(|e| Error::unpack("failed to read magic bytes").with_source(e))?;
 
     if bytes_read < 2 {
         return Ok(CompressionFormat::Unknown);
@@ -62,9 +61,8 @@ pub fn extract_tarball(tarball_path: &Path, dest_dir: &Path) -> Result<(), Error
 pub fn extract_archive(archive_path: &Path, dest_dir: &Path) -> Result<(), Error> {
     let format = detect_compression(archive_path)?;
 
-    let file = File::open(archive_path).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to open archive: {e}"),
-    })?;
+    let file = File::open(archive_path)
+        .map_err(|e| Error::unpack("failed to open archive").with_source(e))?;
     let reader = BufReader::new(file);
 
     match format {
@@ -77,9 +75,8 @@ pub fn extract_archive(archive_path: &Path, dest_dir: &Path) -> Result<(), Error
             extract_tar_archive(decoder, dest_dir)
         }
         CompressionFormat::Zstd => {
-            let decoder = ZstdDecoder::new(reader).map_err(|e| Error::StoreCorruption {
-                message: format!("failed to create zstd decoder: {e}"),
-            })?;
+            let decoder = ZstdDecoder::new(reader)
+                .map_err(|e| Error::unpack("failed to create zstd decoder").with_source(e))?;
             extract_tar_archive(decoder, dest_dir)
         }
         CompressionFormat::Zip => extract_zip_archive(archive_path, dest_dir),
@@ -97,16 +94,16 @@ fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error>
     archive.set_preserve_permissions(true);
     archive.set_unpack_xattrs(true);
 
-    for entry in archive.entries().map_err(|e| Error::StoreCorruption {
-        message: format!("failed to read archive entries: {e}"),
-    })? {
-        let mut entry = entry.map_err(|e| Error::StoreCorruption {
-            message: format!("failed to read archive entry: {e}"),
-        })?;
+    for entry in archive
+        .entries()
+        .map_err(|e| Error::unpack("failed to read archive entries").with_source(e))?
+    {
+        let mut entry =
+            entry.map_err(|e| Error::unpack("failed to read archive entry").with_source(e))?;
 
-        let entry_path = entry.path().map_err(|e| Error::StoreCorruption {
-            message: format!("failed to read entry path: {e}"),
-        })?;
+        let entry_path = entry
+            .path()
+            .map_err(|e| Error::unpack("failed to read entry path").with_source(e))?;
 
         // Store path as owned string for error message
         let path_display = entry_path.display().to_string();
@@ -114,32 +111,28 @@ fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error>
         // Security check: validate path doesn't escape destination
         validate_path(&entry_path, dest_dir)?;
 
-        entry
-            .unpack_in(dest_dir)
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to unpack entry {path_display}: {e}"),
-            })?;
+        entry.unpack_in(dest_dir).map_err(|e| {
+            Error::unpack("failed to unpack entry")
+                .with_path(&path_display)
+                .with_source(e)
+        })?;
     }
 
     Ok(())
 }
 
 fn extract_zip_archive(path: &Path, dest_dir: &Path) -> Result<(), Error> {
-    let file = File::open(path).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to open zip archive: {e}"),
-    })?;
-    let mut zip = zip::ZipArchive::new(file).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to open zip archive: {e}"),
-    })?;
+    let file =
+        File::open(path).map_err(|e| Error::unpack("failed to open zip archive").with_source(e))?;
+    let mut zip = zip::ZipArchive::new(file)
+        .map_err(|e| Error::unpack("failed to open zip archive").with_source(e))?;
 
     for i in 0..zip.len() {
-        let mut entry = zip.by_index(i).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to read zip entry: {e}"),
-        })?;
+        let mut entry = zip
+            .by_index(i)
+            .map_err(|e| Error::unpack("failed to read zip entry").with_source(e))?;
         let Some(raw_path) = entry.enclosed_name().map(|p| p.to_path_buf()) else {
-            return Err(Error::StoreCorruption {
-                message: "zip entry with invalid path".to_string(),
-            });
+            return Err(Error::unpack("zip entry with invalid path"));
         };
 
         validate_path(&raw_path, dest_dir)?;
@@ -147,32 +140,29 @@ fn extract_zip_archive(path: &Path, dest_dir: &Path) -> Result<(), Error> {
         let out_path = dest_dir.join(&raw_path);
 
         if entry.is_dir() {
-            std::fs::create_dir_all(&out_path).map_err(|e| Error::StoreCorruption {
-                message: format!("failed to create output directory: {e}"),
-            })?;
+            std::fs::create_dir_all(&out_path)
+                .map_err(|e| Error::unpack("failed to create output directory").with_source(e))?;
             continue;
         }
 
         if let Some(parent) = out_path.parent() {
-            std::fs::create_dir_all(parent).map_err(|e| Error::StoreCorruption {
-                message: format!("failed to create output parent directory: {e}"),
+            std::fs::create_dir_all(parent).map_err(|e| {
+                Error::unpack("failed to create output parent directory").with_source(e)
             })?;
         }
 
-        let mut output = File::create(&out_path).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create extracted file: {e}"),
-        })?;
-        std::io::copy(&mut entry, &mut output).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to extract zip entry: {e}"),
-        })?;
+        let mut output = File::create(&out_path)
+            .map_err(|e| Error::unpack("failed to create extracted file").with_source(e))?;
+        std::io::copy(&mut entry, &mut output)
+            .map_err(|e| Error::unpack("failed to extract zip entry").with_source(e))?;
 
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
             if let Some(mode) = entry.unix_mode() {
                 let perms = std::fs::Permissions::from_mode(mode);
-                std::fs::set_permissions(&out_path, perms).map_err(|e| Error::StoreCorruption {
-                    message: format!("failed to set zip file permissions: {e}"),
+                std::fs::set_permissions(&out_path, perms).map_err(|e| {
+                    Error::unpack("failed to set zip file permissions").with_source(e)
                 })?;
             }
         }
@@ -193,17 +183,13 @@ fn extract_zip_archive(path: &Path, dest_dir: &Path) -> Result<(), Error> {
 fn validate_path(path: &Path, dest_dir: &Path) -> Result<(), Error> {
     // Reject absolute paths
     if path.is_absolute() {
-        return Err(Error::StoreCorruption {
-            message: format!("absolute path in archive: {}", path.display()),
-        });
+        return Err(Error::unpack("absolute path in archive").with_path(path));
     }
 
     // Reject paths with .. components
     for component in path.components() {
         if let std::path::Component::ParentDir = component {
-            return Err(Error::StoreCorruption {
-                message: format!("path traversal in archive: {}", path.display()),
-            });
+            return Err(Error::unpack("path traversal in archive").with_path(path));
         }
     }
 
@@ -217,14 +203,12 @@ fn validate_path(path: &Path, dest_dir: &Path) -> Result<(), Error> {
     let normalized_dest = normalize_path(dest_dir);
 
     if !normalized.starts_with(&normalized_dest) {
-        return Err(Error::StoreCorruption {
-            message: format!(
-                "path escapes destination directory: {} (normalized: {}) not within {}",
-                path.display(),
-                normalized.display(),
-                normalized_dest.display()
-            ),
-        });
+        return Err(Error::unpack(format!(
+            "path escapes destination directory: {} (normalized: {}) not within {}",
+            path.display(),
+            normalized.display(),
+            normalized_dest.display()
+        )));
     }
 
     Ok(())
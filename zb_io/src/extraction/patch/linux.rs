@@ -345,25 +345,13 @@ fn patch_text_placeholders(keg_path: &Path, prefix_dir: &Path) -> Result<(), Err
                 .replace("@@HOMEBREW_PERL@@", "/usr/bin/perl")
                 .replace("@@HOMEBREW_JAVA@@", "/usr/bin/java");
 
-            // Write back
-            // Check readonly
+            // Atomic write, so a keg file hardlinked from the store doesn't
+            // carry this prefix back into the store entry
             let metadata = fs::metadata(path)?;
-            let original_mode = metadata.permissions().mode();
-            let is_readonly = original_mode & 0o200 == 0;
-
-            if is_readonly {
-                let mut perms = metadata.permissions();
-                perms.set_mode(original_mode | 0o200);
-                fs::set_permissions(path, perms)?;
-            }
-
-            fs::write(path, new_content)?;
-
-            if is_readonly {
-                let mut perms = metadata.permissions();
-                perms.set_mode(original_mode);
-                fs::set_permissions(path, perms)?;
-            }
+            let temp_path = path.with_extension("tmp_patch");
+            fs::write(&temp_path, new_content)?;
+            fs::set_permissions(&temp_path, metadata.permissions())?;
+            fs::rename(temp_path, path)?;
 
             Ok(())
         })();
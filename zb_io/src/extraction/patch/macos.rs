@@ -2,6 +2,8 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use zb_core::Error;
 
+use crate::watchdog::{command_timeout, output_with_timeout};
+
 const HOMEBREW_PREFIXES: &[&str] = &[
     "/opt/homebrew",
     "/usr/local/Homebrew",
@@ -71,8 +73,10 @@ fn patch_text_file_strings(path: &Path, new_prefix: &str, new_cellar: &str) -> R
         return Ok(());
     }
 
-    let metadata = fs::metadata(path).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to read metadata: {e}"),
+    let metadata = fs::metadata(path).map_err(|e| {
+        Error::relocate("failed to read metadata")
+            .with_path(path)
+            .with_source(e)
     })?;
     let original_mode = metadata.permissions().mode();
     let is_readonly = original_mode & 0o200 == 0;
@@ -80,20 +84,26 @@ fn patch_text_file_strings(path: &Path, new_prefix: &str, new_cellar: &str) -> R
     if is_readonly {
         let mut perms = metadata.permissions();
         perms.set_mode(original_mode | 0o200);
-        fs::set_permissions(path, perms).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to make writable: {e}"),
+        fs::set_permissions(path, perms).map_err(|e| {
+            Error::relocate("failed to make writable")
+                .with_path(path)
+                .with_source(e)
         })?;
     }
 
-    fs::write(path, new_content).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to write file: {e}"),
+    fs::write(path, new_content).map_err(|e| {
+        Error::relocate("failed to write file")
+            .with_path(path)
+            .with_source(e)
     })?;
 
     if is_readonly {
         let mut perms = metadata.permissions();
         perms.set_mode(original_mode);
-        fs::set_permissions(path, perms).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to restore permissions: {e}"),
+        fs::set_permissions(path, perms).map_err(|e| {
+            Error::relocate("failed to restore permissions")
+                .with_path(path)
+                .with_source(e)
         })?;
     }
 
@@ -106,8 +116,10 @@ fn patch_macho_binary_strings(path: &Path, new_prefix: &str) -> Result<(), Error
     use std::io::{Read as _, Write as _};
     use std::os::unix::fs::PermissionsExt;
 
-    let metadata = fs::metadata(path).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to read metadata: {e}"),
+    let metadata = fs::metadata(path).map_err(|e| {
+        Error::relocate("failed to read metadata")
+            .with_path(path)
+            .with_source(e)
     })?;
     let original_mode = metadata.permissions().mode();
     let is_readonly = original_mode & 0o200 == 0;
@@ -115,19 +127,24 @@ fn patch_macho_binary_strings(path: &Path, new_prefix: &str) -> Result<(), Error
     if is_readonly {
         let mut perms = metadata.permissions();
         perms.set_mode(original_mode | 0o200);
-        fs::set_permissions(path, perms).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to make writable: {e}"),
+        fs::set_permissions(path, perms).map_err(|e| {
+            Error::relocate("failed to make writable")
+                .with_path(path)
+                .with_source(e)
         })?;
     }
 
-    let mut file = fs::File::open(path).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to open file: {e}"),
+    let mut file = fs::File::open(path).map_err(|e| {
+        Error::relocate("failed to open file")
+            .with_path(path)
+            .with_source(e)
     })?;
     let mut contents = Vec::new();
-    file.read_to_end(&mut contents)
-        .map_err(|e| Error::StoreCorruption {
-            message: format!("failed to read file: {e}"),
-        })?;
+    file.read_to_end(&mut contents).map_err(|e| {
+        Error::relocate("failed to read file")
+            .with_path(path)
+            .with_source(e)
+    })?;
     drop(file);
 
     let original_contents = contents.clone();
@@ -168,30 +185,41 @@ fn patch_macho_binary_strings(path: &Path, new_prefix: &str) -> Result<(), Error
 
     if patched && contents != original_contents {
         let temp_path = path.with_extension("tmp_patch");
-        let mut temp_file = fs::File::create(&temp_path).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create temp file: {e}"),
+        let mut temp_file = fs::File::create(&temp_path).map_err(|e| {
+            Error::relocate("failed to create temp file")
+                .with_path(&temp_path)
+                .with_source(e)
+        })?;
+        temp_file.write_all(&contents).map_err(|e| {
+            Error::relocate("failed to write temp file")
+                .with_path(&temp_path)
+                .with_source(e)
         })?;
-        temp_file
-            .write_all(&contents)
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to write temp file: {e}"),
-            })?;
         drop(temp_file);
 
-        fs::rename(&temp_path, path).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to rename temp file: {e}"),
+        fs::rename(&temp_path, path).map_err(|e| {
+            Error::relocate("failed to rename temp file")
+                .with_path(path)
+                .with_source(e)
         })?;
 
         // Restore original permissions — fs::File::create uses 0644 by default,
         // which drops the execute bit from patched binaries.
-        fs::set_permissions(path, metadata.permissions()).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to restore permissions after patching: {e}"),
+        fs::set_permissions(path, metadata.permissions()).map_err(|e| {
+            Error::relocate("failed to restore permissions after patching")
+                .with_path(path)
+                .with_source(e)
         })?;
 
-        match std::process::Command::new("codesign")
-            .args(["--force", "--sign", "-", &path.to_string_lossy()])
-            .output()
-        {
+        match output_with_timeout(
+            std::process::Command::new("codesign").args([
+                "--force",
+                "--sign",
+                "-",
+                &path.to_string_lossy(),
+            ]),
+            command_timeout(),
+        ) {
             Ok(output) if !output.status.success() => {
                 eprintln!(
                     "Warning: Failed to re-sign {}: {}",
@@ -367,10 +395,10 @@ pub fn patch_homebrew_placeholders(
         let mut patched_any = false;
 
         // Get and patch library dependencies (-L)
-        if let Ok(output) = Command::new("otool")
-            .args(["-L", &path.to_string_lossy()])
-            .output()
-            && output.status.success()
+        if let Ok(output) = output_with_timeout(
+            Command::new("otool").args(["-L", &path.to_string_lossy()]),
+            command_timeout(),
+        ) && output.status.success()
         {
             let stdout = String::from_utf8_lossy(&output.stdout);
             for line in stdout.lines() {
@@ -378,9 +406,15 @@ pub fn patch_homebrew_placeholders(
                 if let Some(old_path) = line.split_whitespace().next()
                     && let Some(new_path) = patch_path(old_path)
                 {
-                    let result = Command::new("install_name_tool")
-                        .args(["-change", old_path, &new_path, &path.to_string_lossy()])
-                        .output();
+                    let result = output_with_timeout(
+                        Command::new("install_name_tool").args([
+                            "-change",
+                            old_path,
+                            &new_path,
+                            &path.to_string_lossy(),
+                        ]),
+                        command_timeout(),
+                    );
                     if result.is_ok() {
                         patched_any = true;
                     } else {
@@ -391,10 +425,10 @@ pub fn patch_homebrew_placeholders(
         }
 
         // Get and patch install name ID (-D)
-        if let Ok(output) = Command::new("otool")
-            .args(["-D", &path.to_string_lossy()])
-            .output()
-            && output.status.success()
+        if let Ok(output) = output_with_timeout(
+            Command::new("otool").args(["-D", &path.to_string_lossy()]),
+            command_timeout(),
+        ) && output.status.success()
         {
             let stdout = String::from_utf8_lossy(&output.stdout);
             for line in stdout.lines().skip(1) {
@@ -404,9 +438,14 @@ pub fn patch_homebrew_placeholders(
                     continue;
                 }
                 if let Some(new_id) = patch_path(line) {
-                    let result = Command::new("install_name_tool")
-                        .args(["-id", &new_id, &path.to_string_lossy()])
-                        .output();
+                    let result = output_with_timeout(
+                        Command::new("install_name_tool").args([
+                            "-id",
+                            &new_id,
+                            &path.to_string_lossy(),
+                        ]),
+                        command_timeout(),
+                    );
                     if result.is_ok() {
                         patched_any = true;
                     } else {
@@ -418,9 +457,10 @@ pub fn patch_homebrew_placeholders(
 
         // Re-sign if we patched anything (patching invalidates code signature)
         if patched_any {
-            let _ = Command::new("codesign")
-                .args(["--force", "--sign", "-", &path.to_string_lossy()])
-                .output();
+            let _ = output_with_timeout(
+                Command::new("codesign").args(["--force", "--sign", "-", &path.to_string_lossy()]),
+                command_timeout(),
+            );
         }
 
         // Restore original permissions
@@ -433,13 +473,9 @@ pub fn patch_homebrew_placeholders(
 
     let failures = patch_failures.load(Ordering::Relaxed);
     if failures > 0 {
-        return Err(Error::StoreCorruption {
-            message: format!(
-                "failed to patch {} Mach-O files in {}",
-                failures,
-                keg_path.display()
-            ),
-        });
+        return Err(
+            Error::relocate(format!("failed to patch {failures} Mach-O files")).with_path(keg_path),
+        );
     }
 
     Ok(())
@@ -454,14 +490,18 @@ pub fn codesign_and_strip_xattrs(keg_path: &Path) -> Result<(), Error> {
     use std::process::Command;
 
     // First, do a quick recursive xattr strip (single command, very fast)
-    let _ = Command::new("xattr")
-        .args(["-rd", "com.apple.quarantine", &keg_path.to_string_lossy()])
-        .stderr(std::process::Stdio::null())
-        .output();
-    let _ = Command::new("xattr")
-        .args(["-rd", "com.apple.provenance", &keg_path.to_string_lossy()])
-        .stderr(std::process::Stdio::null())
-        .output();
+    let _ = output_with_timeout(
+        Command::new("xattr")
+            .args(["-rd", "com.apple.quarantine", &keg_path.to_string_lossy()])
+            .stderr(std::process::Stdio::null()),
+        command_timeout(),
+    );
+    let _ = output_with_timeout(
+        Command::new("xattr")
+            .args(["-rd", "com.apple.provenance", &keg_path.to_string_lossy()])
+            .stderr(std::process::Stdio::null()),
+        command_timeout(),
+    );
 
     // Find executables in bin/ directories only (where signing matters)
     // Skip dylibs and other Mach-O files - they inherit signing from their loader
@@ -493,11 +533,14 @@ pub fn codesign_and_strip_xattrs(keg_path: &Path) -> Result<(), Error> {
         }
 
         // Verify signature - if valid, skip
-        let verify = Command::new("codesign")
-            .args(["-v", &path.to_string_lossy()])
-            .stderr(std::process::Stdio::null())
-            .stdout(std::process::Stdio::null())
-            .status();
+        let verify = output_with_timeout(
+            Command::new("codesign")
+                .args(["-v", &path.to_string_lossy()])
+                .stderr(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null()),
+            command_timeout(),
+        )
+        .map(|output| output.status);
 
         if verify.map(|s| s.success()).unwrap_or(false) {
             return; // Already signed
@@ -518,9 +561,10 @@ pub fn codesign_and_strip_xattrs(keg_path: &Path) -> Result<(), Error> {
         }
 
         // Sign the binary
-        let _ = Command::new("codesign")
-            .args(["--force", "--sign", "-", &path.to_string_lossy()])
-            .output();
+        let _ = output_with_timeout(
+            Command::new("codesign").args(["--force", "--sign", "-", &path.to_string_lossy()]),
+            command_timeout(),
+        );
 
         // Restore permissions
         if is_readonly {
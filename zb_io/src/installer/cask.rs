@@ -1,12 +1,25 @@
 use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use zb_core::Error;
 
+use crate::extraction::extract_archive;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CaskBinary {
     pub source: String,
     pub target: String,
 }
 
+/// An `.app` bundle in the cask's artifact, copied into the app directory
+/// as `target`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaskApp {
+    pub source: String,
+    pub target: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ResolvedCask {
     pub install_name: String,
@@ -15,6 +28,16 @@ pub struct ResolvedCask {
     pub url: String,
     pub sha256: String,
     pub binaries: Vec<CaskBinary>,
+    pub apps: Vec<CaskApp>,
+    /// Installer packages in the artifact, run with macOS's `installer`.
+    pub pkgs: Vec<String>,
+    /// Receipt ids the cask's `uninstall pkgutil:` stanza names, forgotten
+    /// (and their files removed) on uninstall.
+    pub pkgutil_ids: Vec<String>,
+    /// Preferences, caches and support files the cask's `zap` stanza names,
+    /// as written there (`~/Library/Caches/org.example*`), removed by
+    /// `zb uninstall --zap`.
+    pub zap: Vec<String>,
 }
 
 pub fn resolve_cask(token: &str, cask: &Value) -> Result<ResolvedCask, Error> {
@@ -38,11 +61,20 @@ pub fn resolve_cask(token: &str, cask: &Value) -> Result<ResolvedCask, Error> {
     }
 
     let binaries = parse_binary_artifacts(cask)?;
-    if binaries.is_empty() {
+    let apps = parse_app_artifacts(cask)?;
+    let pkgs = artifact_entries(cask, "pkg")
+        .filter_map(|entry| entry_source(entry).map(str::to_string))
+        .collect::<Vec<_>>();
+    if binaries.is_empty() && apps.is_empty() && pkgs.is_empty() {
         return Err(Error::InvalidArgument {
-            message: format!("cask '{token}' does not expose supported binary artifacts"),
+            message: format!(
+                "cask '{token}' does not expose supported artifacts (app, pkg or binary)"
+            ),
         });
     }
+    for pkg in &pkgs {
+        relative_artifact_path(token, pkg)?;
+    }
 
     Ok(ResolvedCask {
         install_name: format!("cask:{token}"),
@@ -51,9 +83,399 @@ pub fn resolve_cask(token: &str, cask: &Value) -> Result<ResolvedCask, Error> {
         url,
         sha256,
         binaries,
+        apps,
+        pkgs,
+        pkgutil_ids: parse_pkgutil_ids(cask),
+        zap: parse_zap_paths(cask),
     })
 }
 
+/// Entries of every `kind` stanza among the cask's artifacts.
+fn artifact_entries<'a>(cask: &'a Value, kind: &'a str) -> impl Iterator<Item = &'a Value> {
+    cask.get("artifacts")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(move |artifact| artifact.get(kind).and_then(Value::as_array))
+        .flatten()
+}
+
+/// The path an artifact entry names: `"Foo.app"` or `["Foo.app", {...}]`.
+fn entry_source(entry: &Value) -> Option<&str> {
+    entry
+        .as_str()
+        .or_else(|| entry.as_array()?.first()?.as_str())
+}
+
+fn parse_app_artifacts(cask: &Value) -> Result<Vec<CaskApp>, Error> {
+    let mut apps = Vec::new();
+    for entry in artifact_entries(cask, "app") {
+        let source = entry_source(entry).ok_or_else(|| Error::InvalidArgument {
+            message: "unsupported cask app artifact shape".to_string(),
+        })?;
+        let target = entry
+            .as_array()
+            .and_then(|array| array.get(1))
+            .and_then(|options| options.get("target"))
+            .and_then(Value::as_str)
+            .map(ToString::to_string)
+            .map_or_else(|| basename(source), Ok)?;
+        if target.contains('/') || !target.ends_with(".app") {
+            return Err(Error::InvalidArgument {
+                message: format!("unsupported cask app target '{target}'"),
+            });
+        }
+        apps.push(CaskApp {
+            source: source.to_string(),
+            target,
+        });
+    }
+    Ok(apps)
+}
+
+fn parse_pkgutil_ids(cask: &Value) -> Vec<String> {
+    let mut ids = Vec::new();
+    for entry in artifact_entries(cask, "uninstall") {
+        match entry.get("pkgutil") {
+            Some(Value::String(id)) => ids.push(id.clone()),
+            Some(Value::Array(list)) => {
+                ids.extend(list.iter().filter_map(Value::as_str).map(str::to_string));
+            }
+            _ => {}
+        }
+    }
+    ids
+}
+
+/// The `trash`, `delete` and `rmdir` paths of the cask's `zap` stanzas.
+/// Only absolute and `~/` paths are kept, and none that climb with `..` or
+/// name a whole top-level directory.
+fn parse_zap_paths(cask: &Value) -> Vec<String> {
+    let mut paths = Vec::new();
+    for entry in artifact_entries(cask, "zap") {
+        for key in ["trash", "delete", "rmdir"] {
+            match entry.get(key) {
+                Some(Value::String(path)) => paths.push(path.clone()),
+                Some(Value::Array(list)) => {
+                    paths.extend(list.iter().filter_map(Value::as_str).map(str::to_string));
+                }
+                _ => {}
+            }
+        }
+    }
+    paths.retain(|path| {
+        let relative = path
+            .strip_prefix("~/")
+            .or_else(|| path.strip_prefix('/'))
+            .map(Path::new);
+        relative.is_some_and(|relative| {
+            let depth = relative.components().count();
+            (depth > 1 || (path.starts_with("~/") && depth == 1))
+                && relative
+                    .components()
+                    .all(|component| matches!(component, std::path::Component::Normal(_)))
+        })
+    });
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// A zap path with `~` expanded to `home`, or `None` without a home.
+pub fn expand_zap_path(path: &str, home: Option<&Path>) -> Option<PathBuf> {
+    match path.strip_prefix("~/") {
+        Some(rest) => Some(home?.join(rest)),
+        None => Some(PathBuf::from(path)),
+    }
+}
+
+/// Remove what a zap path names, expanding `*` and `?` in its last
+/// component. Returns the paths removed.
+pub fn zap(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let pattern = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let matches = match path.parent() {
+        Some(parent) if pattern.contains(['*', '?']) => match fs::read_dir(parent) {
+            Ok(entries) => entries
+                .flatten()
+                .filter(|entry| {
+                    crate::cellar::link::wildcard_match(
+                        &pattern,
+                        &entry.file_name().to_string_lossy(),
+                    )
+                })
+                .map(|entry| entry.path())
+                .collect(),
+            Err(_) => Vec::new(),
+        },
+        _ if path.symlink_metadata().is_ok() => vec![path.to_path_buf()],
+        _ => Vec::new(),
+    };
+    for path in &matches {
+        remove_app(path)?;
+    }
+    Ok(matches)
+}
+
+/// `path` checked to stay inside the unpacked artifact.
+pub(crate) fn relative_artifact_path<'a>(token: &str, path: &'a str) -> Result<&'a Path, Error> {
+    let relative = Path::new(path);
+    if relative.is_absolute()
+        || relative
+            .components()
+            .any(|component| matches!(component, std::path::Component::ParentDir))
+    {
+        return Err(Error::InvalidArgument {
+            message: format!("cask '{token}' artifact '{path}' must be a relative path inside it"),
+        });
+    }
+    Ok(relative)
+}
+
+/// How a cask's download is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaskArchive {
+    /// A disk image, mounted to copy its contents.
+    Dmg,
+    /// A flat installer package, used as is.
+    Pkg,
+    /// A zip or tar archive.
+    Archive,
+}
+
+impl CaskArchive {
+    /// Tell the kind apart by the download's magic bytes, then by the URL's
+    /// extension: disk images only carry their signature in a trailer.
+    pub fn detect(url: &str, path: &Path) -> Self {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let Ok(mut file) = fs::File::open(path) else {
+            return CaskArchive::Archive;
+        };
+        let mut magic = [0u8; 4];
+        if file.read_exact(&mut magic).is_ok() {
+            if &magic == b"xar!" {
+                return CaskArchive::Pkg;
+            }
+            if magic == [0x50, 0x4b, 0x03, 0x04] {
+                return CaskArchive::Archive;
+            }
+        }
+        let mut trailer = [0u8; 4];
+        if file.seek(SeekFrom::End(-512)).is_ok()
+            && file.read_exact(&mut trailer).is_ok()
+            && &trailer == b"koly"
+        {
+            return CaskArchive::Dmg;
+        }
+
+        let path = url
+            .split(['?', '#'])
+            .next()
+            .unwrap_or(url)
+            .to_ascii_lowercase();
+        if path.ends_with(".dmg") {
+            CaskArchive::Dmg
+        } else if path.ends_with(".pkg") {
+            CaskArchive::Pkg
+        } else {
+            CaskArchive::Archive
+        }
+    }
+}
+
+/// Unpack the download at `blob` for `cask` into `dest`: a disk image's
+/// contents, a package under its file name, or an extracted archive.
+pub fn unpack_cask(cask: &ResolvedCask, blob: &Path, dest: &Path) -> Result<(), Error> {
+    match CaskArchive::detect(&cask.url, blob) {
+        CaskArchive::Archive => extract_archive(blob, dest),
+        CaskArchive::Pkg => {
+            let name = cask
+                .pkgs
+                .first()
+                .cloned()
+                .unwrap_or_else(|| format!("{}.pkg", cask.token));
+            let target = dest.join(relative_artifact_path(&cask.token, &name)?);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(|e| {
+                    Error::unpack("failed to create directory")
+                        .with_path(parent)
+                        .with_source(e)
+                })?;
+            }
+            fs::copy(blob, &target).map_err(|e| {
+                Error::unpack("failed to copy package")
+                    .with_path(&target)
+                    .with_source(e)
+            })?;
+            Ok(())
+        }
+        CaskArchive::Dmg => copy_disk_image(&cask.token, blob, dest),
+    }
+}
+
+/// Mount the disk image read-only, copy its contents to `dest` and detach it.
+fn copy_disk_image(token: &str, image: &Path, dest: &Path) -> Result<(), Error> {
+    if !cfg!(target_os = "macos") {
+        return Err(Error::UnsupportedFormula {
+            name: format!("cask:{token}"),
+            reason: "it ships a disk image, which can only be mounted on macOS".to_string(),
+        });
+    }
+
+    let mountpoint = dest.with_extension("mount");
+    fs::create_dir_all(&mountpoint).map_err(|e| {
+        Error::unpack("failed to create mount point")
+            .with_path(&mountpoint)
+            .with_source(e)
+    })?;
+    run(
+        Command::new("hdiutil")
+            .args(["attach", "-nobrowse", "-readonly", "-noautoopen", "-quiet"])
+            .arg("-mountpoint")
+            .arg(&mountpoint)
+            .arg(image),
+        "mount disk image",
+    )
+    .map_err(|e| Error::unpack(e).with_path(image))?;
+
+    let copied = run(
+        Command::new("ditto").arg(&mountpoint).arg(dest),
+        "copy disk image contents",
+    );
+    let detached = run(
+        Command::new("hdiutil")
+            .args(["detach", "-force", "-quiet"])
+            .arg(&mountpoint),
+        "detach disk image",
+    );
+    let _ = fs::remove_dir(&mountpoint);
+    copied
+        .and(detached)
+        .map_err(|e| Error::unpack(e).with_path(image))
+}
+
+/// Copy the app bundle at `source` to `target`, refusing to replace a
+/// bundle zerobrew didn't put there.
+pub fn install_app(token: &str, source: &Path, target: &Path, replace: bool) -> Result<(), Error> {
+    if !source.is_dir() {
+        return Err(Error::InvalidArgument {
+            message: format!(
+                "cask '{token}' app '{}' not found in its download",
+                source.display()
+            ),
+        });
+    }
+    if target.exists() || target.is_symlink() {
+        if !replace {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "{} already exists; remove it first to install cask '{token}'",
+                    target.display()
+                ),
+            });
+        }
+        remove_app(target)?;
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| Error::FileError {
+            message: format!("failed to create {}: {e}", parent.display()),
+        })?;
+    }
+    crate::cellar::materialize::copy_dir_detached(source, target)
+}
+
+pub fn remove_app(path: &Path) -> Result<(), Error> {
+    let removed = if path.is_symlink() || path.is_file() {
+        fs::remove_file(path)
+    } else {
+        fs::remove_dir_all(path)
+    };
+    match removed {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::FileError {
+            message: format!("failed to remove {}: {e}", path.display()),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Run macOS's `installer` on the package at `pkg`, as root.
+pub fn install_pkg(token: &str, pkg: &Path) -> Result<(), Error> {
+    if !cfg!(target_os = "macos") {
+        return Err(Error::UnsupportedFormula {
+            name: format!("cask:{token}"),
+            reason: "it ships an installer package, which only runs on macOS".to_string(),
+        });
+    }
+    if !pkg.is_file() {
+        return Err(Error::InvalidArgument {
+            message: format!(
+                "cask '{token}' package '{}' not found in its download",
+                pkg.display()
+            ),
+        });
+    }
+    let mut installer = privileged("installer");
+    installer.arg("-pkg").arg(pkg).args(["-target", "/"]);
+    run(&mut installer, &format!("install {}", pkg.display()))
+        .map_err(|message| Error::ExecutionError { message })
+}
+
+/// Remove the files the package receipt `id` lists, then the receipt.
+pub fn forget_pkg(id: &str) -> Result<(), Error> {
+    let output = Command::new("pkgutil")
+        .args(["--only-files", "--files", id])
+        .output()
+        .map_err(|e| Error::ExecutionError {
+            message: format!("failed to run pkgutil: {e}"),
+        })?;
+    if !output.status.success() {
+        // Already forgotten, e.g. removed by the app's own uninstaller.
+        return Ok(());
+    }
+    let files: Vec<PathBuf> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| Path::new("/").join(line))
+        .collect();
+    for chunk in files.chunks(256) {
+        let mut rm = privileged("rm");
+        rm.arg("-f").arg("--").args(chunk);
+        run(&mut rm, &format!("remove files of {id}"))
+            .map_err(|message| Error::ExecutionError { message })?;
+    }
+    let mut forget = privileged("pkgutil");
+    forget.args(["--forget", id]);
+    run(&mut forget, &format!("forget {id}")).map_err(|message| Error::ExecutionError { message })
+}
+
+/// `program`, through `sudo` unless already running as root.
+fn privileged(program: &str) -> Command {
+    // SAFETY: geteuid has no preconditions.
+    if unsafe { libc::geteuid() } == 0 {
+        Command::new(program)
+    } else {
+        let mut sudo = Command::new("sudo");
+        sudo.arg(program);
+        sudo
+    }
+}
+
+fn run(command: &mut Command, action: &str) -> Result<(), String> {
+    let output = command
+        .output()
+        .map_err(|e| format!("failed to {action}: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "failed to {action}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
 fn required_string(value: &Value, field: &str) -> Result<String, Error> {
     value
         .get(field)
@@ -220,6 +642,118 @@ mod tests {
         assert_eq!(resolved.binaries[1].target, "tool-two");
     }
 
+    #[test]
+    fn resolve_cask_parses_apps_pkgs_and_receipts() {
+        let cask = serde_json::json!({
+            "token": "test",
+            "version": "1.0.0",
+            "url": "https://example.com/Test.dmg",
+            "sha256": "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "artifacts": [
+                { "app": ["Test.app", ["Helper.app", { "target": "Test Helper.app" }]] },
+                { "pkg": ["Driver.pkg"] },
+                { "uninstall": [{ "pkgutil": ["com.example.driver"], "quit": "com.example.test" }] }
+            ]
+        });
+
+        let resolved = resolve_cask("test", &cask).unwrap();
+        assert!(resolved.binaries.is_empty());
+        assert_eq!(resolved.apps[0].target, "Test.app");
+        assert_eq!(resolved.apps[1].source, "Helper.app");
+        assert_eq!(resolved.apps[1].target, "Test Helper.app");
+        assert_eq!(resolved.pkgs, ["Driver.pkg"]);
+        assert_eq!(resolved.pkgutil_ids, ["com.example.driver"]);
+        assert!(resolved.zap.is_empty());
+
+        let escaping = serde_json::json!({
+            "version": "1.0.0",
+            "url": "https://example.com/test.pkg",
+            "sha256": "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "artifacts": [{ "pkg": ["../evil.pkg"] }]
+        });
+        assert!(resolve_cask("test", &escaping).is_err());
+    }
+
+    #[test]
+    fn resolve_cask_keeps_only_contained_zap_paths() {
+        let cask = serde_json::json!({
+            "version": "1.0.0",
+            "url": "https://example.com/Test.dmg",
+            "sha256": "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "artifacts": [
+                { "app": ["Test.app"] },
+                { "zap": [{
+                    "trash": [
+                        "~/Library/Caches/com.example.test*",
+                        "~/.test",
+                        "~",
+                        "/Library",
+                        "~/Library/../Documents",
+                        "Relative/Path"
+                    ],
+                    "delete": "/Library/Application Support/Test",
+                    "rmdir": ["~/Library/Application Support/Example"]
+                }] }
+            ]
+        });
+
+        let resolved = resolve_cask("test", &cask).unwrap();
+        assert_eq!(
+            resolved.zap,
+            [
+                "/Library/Application Support/Test",
+                "~/.test",
+                "~/Library/Application Support/Example",
+                "~/Library/Caches/com.example.test*",
+            ]
+        );
+        assert_eq!(
+            expand_zap_path("~/.test", Some(Path::new("/Users/me"))),
+            Some(PathBuf::from("/Users/me/.test"))
+        );
+        assert_eq!(expand_zap_path("~/.test", None), None);
+    }
+
+    #[test]
+    fn cask_archives_are_told_apart_by_content_then_url() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let file = |name: &str, bytes: &[u8]| {
+            let path = tmp.path().join(name);
+            fs::write(&path, bytes).unwrap();
+            path
+        };
+
+        let pkg = file("download", b"xar!\0\x1c\0\x01");
+        assert_eq!(
+            CaskArchive::detect("https://example.com/get", &pkg),
+            CaskArchive::Pkg
+        );
+
+        let mut image = vec![0u8; 1024];
+        image[512..516].copy_from_slice(b"koly");
+        let dmg = file("image", &image);
+        assert_eq!(
+            CaskArchive::detect("https://example.com/get", &dmg),
+            CaskArchive::Dmg
+        );
+
+        let zip = file("archive", &[0x50, 0x4b, 0x03, 0x04, 0, 0]);
+        assert_eq!(
+            CaskArchive::detect("https://example.com/Test.dmg", &zip),
+            CaskArchive::Archive
+        );
+
+        let unknown = file("unknown", b"??");
+        assert_eq!(
+            CaskArchive::detect("https://example.com/Test.DMG?download=1", &unknown),
+            CaskArchive::Dmg
+        );
+        assert_eq!(
+            CaskArchive::detect("https://example.com/test.tar.gz", &unknown),
+            CaskArchive::Archive
+        );
+    }
+
     #[test]
     fn resolve_cask_missing_required_field_is_invalid_argument() {
         let cask = serde_json::json!({
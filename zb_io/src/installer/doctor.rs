@@ -0,0 +1,242 @@
+use std::collections::BTreeSet;
+use std::ffi::{CString, OsStr};
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::cellar::link::LINK_DIRS;
+
+/// `bin` directories of the usual Homebrew installations.
+const HOMEBREW_BINS: &[&str] = &[
+    "/opt/homebrew/bin",
+    "/usr/local/bin",
+    "/home/linuxbrew/.linuxbrew/bin",
+];
+
+/// Something wrong with the installation, the paths it concerns and how to
+/// fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub problem: String,
+    pub paths: Vec<PathBuf>,
+    pub remedy: String,
+}
+
+/// Look over the prefix for problems `zb` won't repair by itself.
+/// `installed` holds the keg names (tokens) of installed formulas.
+pub(crate) fn diagnose(
+    root: &Path,
+    prefix: &Path,
+    cellar: &Path,
+    installed: &BTreeSet<String>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let broken = broken_links(prefix);
+    if !broken.is_empty() {
+        diagnostics.push(Diagnostic {
+            problem: format!("{} broken symlinks in the prefix", broken.len()),
+            remedy: "relink the formulas they belonged to with `zb link`, or delete them"
+                .to_string(),
+            paths: broken,
+        });
+    }
+
+    let orphaned = orphaned_opt_links(&prefix.join("opt"), cellar, installed);
+    if !orphaned.is_empty() {
+        diagnostics.push(Diagnostic {
+            problem: format!(
+                "{} opt links don't belong to an installed formula",
+                orphaned.len()
+            ),
+            remedy: "delete them; installing a formula recreates its opt link".to_string(),
+            paths: orphaned,
+        });
+    }
+
+    let mut dirs = vec![
+        root.to_path_buf(),
+        root.join("store"),
+        root.join("db"),
+        root.join("cache"),
+        cellar.to_path_buf(),
+        prefix.to_path_buf(),
+        prefix.join("opt"),
+    ];
+    dirs.extend(LINK_DIRS.iter().map(|dir| prefix.join(dir)));
+    let unwritable = unwritable_dirs(&dirs);
+    if !unwritable.is_empty() {
+        diagnostics.push(Diagnostic {
+            problem: format!(
+                "{} directories zb writes to aren't writable",
+                unwritable.len()
+            ),
+            remedy: "take ownership of them, e.g. `sudo chown -R $(whoami) <dir>`".to_string(),
+            paths: unwritable,
+        });
+    }
+
+    let homebrew_bins: Vec<PathBuf> = HOMEBREW_BINS
+        .iter()
+        .map(PathBuf::from)
+        .filter(|bin| bin.join("brew").is_file())
+        .collect();
+    if let Some(path) = std::env::var_os("PATH") {
+        diagnostics.extend(check_path_order(&path, &prefix.join("bin"), &homebrew_bins));
+    }
+
+    diagnostics
+}
+
+/// Symlinks under the prefix's link directories whose target is gone.
+fn broken_links(prefix: &Path) -> Vec<PathBuf> {
+    LINK_DIRS
+        .iter()
+        .flat_map(|dir| walkdir::WalkDir::new(prefix.join(dir)).into_iter())
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path_is_symlink() && fs::metadata(entry.path()).is_err())
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// Entries of `opt_dir` that point nowhere, or into `cellar` for a formula
+/// that isn't installed. Links into another cellar (a Homebrew sharing the
+/// prefix) are left alone.
+fn orphaned_opt_links(opt_dir: &Path, cellar: &Path, installed: &BTreeSet<String>) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(opt_dir) else {
+        return Vec::new();
+    };
+    let cellar = fs::canonicalize(cellar).unwrap_or_else(|_| cellar.to_path_buf());
+    let mut orphaned: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| match fs::canonicalize(entry.path()) {
+            Ok(target) => {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                target.starts_with(&cellar) && !installed.contains(opt_link_formula(&name, &target))
+            }
+            Err(_) => true,
+        })
+        .map(|entry| entry.path())
+        .collect();
+    orphaned.sort();
+    orphaned
+}
+
+/// The formula an opt link named `name` belongs to: `jq@1.7.1` pointing at
+/// the `1.7.1` keg is the versioned link of `jq`, while `python@3.12` is a
+/// formula of its own.
+fn opt_link_formula<'a>(name: &'a str, target: &Path) -> &'a str {
+    name.rsplit_once('@')
+        .filter(|(_, version)| target.file_name().is_some_and(|keg| keg == *version))
+        .map_or(name, |(formula, _)| formula)
+}
+
+/// Existing directories in `dirs` the current user can't write to.
+fn unwritable_dirs(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    dirs.iter()
+        .filter(|dir| dir.is_dir() && !writable(dir))
+        .cloned()
+        .collect()
+}
+
+pub(super) fn writable(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: access only reads the NUL-terminated path we pass.
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// `prefix_bin` has to be in `path` ahead of any Homebrew `bin` directory,
+/// or Homebrew's copies of commands win.
+fn check_path_order(
+    path: &OsStr,
+    prefix_bin: &Path,
+    homebrew_bins: &[PathBuf],
+) -> Option<Diagnostic> {
+    let dirs: Vec<PathBuf> = std::env::split_paths(path).collect();
+    let Some(position) = dirs.iter().position(|dir| dir == prefix_bin) else {
+        return Some(Diagnostic {
+            problem: format!("{} is not in PATH", prefix_bin.display()),
+            paths: Vec::new(),
+            remedy: "run `zb init` to set up your shell, then `exec $SHELL`".to_string(),
+        });
+    };
+
+    let ahead: Vec<PathBuf> = dirs[..position]
+        .iter()
+        .filter(|dir| *dir != prefix_bin && homebrew_bins.contains(dir))
+        .cloned()
+        .collect();
+    (!ahead.is_empty()).then(|| Diagnostic {
+        problem: format!(
+            "Homebrew comes before {} in PATH, so its commands shadow zerobrew's",
+            prefix_bin.display()
+        ),
+        remedy: format!(
+            "move {} ahead of them in PATH (or drop Homebrew's shellenv line from your shell profile)",
+            prefix_bin.display()
+        ),
+        paths: ahead,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn finds_broken_links_and_orphaned_opt_links() {
+        let tmp = tempfile::tempdir().unwrap();
+        let prefix = tmp.path();
+        let keg = prefix.join("cellar/jq/1.7.1");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::write(keg.join("bin/jq"), "jq").unwrap();
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        fs::create_dir_all(prefix.join("opt")).unwrap();
+        symlink(keg.join("bin/jq"), prefix.join("bin/jq")).unwrap();
+        symlink(
+            prefix.join("cellar/gone/1.0/bin/gone"),
+            prefix.join("bin/gone"),
+        )
+        .unwrap();
+        symlink(&keg, prefix.join("opt/jq")).unwrap();
+        symlink(&keg, prefix.join("opt/jq-old")).unwrap();
+        symlink(&keg, prefix.join("opt/jq@1.7.1")).unwrap();
+        symlink(&keg, prefix.join("opt/jq@1.6")).unwrap();
+        symlink(prefix.join("cellar/gone/1.0"), prefix.join("opt/gone")).unwrap();
+        let homebrew = prefix.join("Cellar/wget/1.24");
+        fs::create_dir_all(&homebrew).unwrap();
+        symlink(&homebrew, prefix.join("opt/wget")).unwrap();
+
+        assert_eq!(broken_links(prefix), [prefix.join("bin/gone")]);
+        let installed = BTreeSet::from(["jq".to_string(), "gone".to_string()]);
+        assert_eq!(
+            orphaned_opt_links(&prefix.join("opt"), &prefix.join("cellar"), &installed),
+            [
+                prefix.join("opt/gone"),
+                prefix.join("opt/jq-old"),
+                prefix.join("opt/jq@1.6")
+            ]
+        );
+    }
+
+    #[test]
+    fn path_order_flags_homebrew_ahead_of_the_prefix() {
+        let prefix_bin = Path::new("/opt/zerobrew/prefix/bin");
+        let homebrew = [PathBuf::from("/opt/homebrew/bin")];
+
+        let path = std::env::join_paths(["/opt/homebrew/bin", "/opt/zerobrew/prefix/bin"]).unwrap();
+        let diagnostic = check_path_order(&path, prefix_bin, &homebrew).unwrap();
+        assert_eq!(diagnostic.paths, homebrew);
+
+        let path = std::env::join_paths(["/opt/zerobrew/prefix/bin", "/opt/homebrew/bin"]).unwrap();
+        assert!(check_path_order(&path, prefix_bin, &homebrew).is_none());
+
+        let path = std::env::join_paths(["/usr/bin"]).unwrap();
+        let diagnostic = check_path_order(&path, prefix_bin, &homebrew).unwrap();
+        assert!(diagnostic.problem.contains("not in PATH"));
+    }
+}
@@ -1,5 +1,17 @@
+use std::fs;
+use std::path::Path;
 use std::process::Command;
 
+use crate::watchdog::{command_timeout, output_with_timeout};
+
+/// Cellars of the usual Homebrew installations, scanned when `brew` itself
+/// can't be run.
+const HOMEBREW_CELLARS: &[&str] = &[
+    "/opt/homebrew/Cellar",
+    "/usr/local/Cellar",
+    "/home/linuxbrew/.linuxbrew/Cellar",
+];
+
 /// Represents a Homebrew package that can be migrated
 #[derive(Debug, Clone)]
 pub struct HomebrewPackage {
@@ -56,6 +68,53 @@ pub fn parse_casks_from_plain_text(output: &str) -> Vec<HomebrewPackage> {
         .collect()
 }
 
+/// Formulas installed in a Homebrew `cellar`, sorted by name. The tap comes
+/// from each keg's install receipt, defaulting to homebrew/core.
+pub fn scan_cellar(cellar: &Path) -> Vec<HomebrewPackage> {
+    let Ok(entries) = fs::read_dir(cellar) else {
+        return Vec::new();
+    };
+    let mut packages: Vec<HomebrewPackage> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let mut kegs: Vec<_> = fs::read_dir(entry.path())
+                .ok()?
+                .flatten()
+                .map(|keg| keg.path())
+                .filter(|keg| keg.is_dir())
+                .collect();
+            kegs.sort();
+            let keg = kegs.pop()?;
+            let tap = fs::read(keg.join("INSTALL_RECEIPT.json"))
+                .ok()
+                .and_then(|receipt| serde_json::from_slice::<serde_json::Value>(&receipt).ok())
+                .and_then(|receipt| Some(receipt.pointer("/source/tap")?.as_str()?.to_string()))
+                .unwrap_or_else(|| "homebrew/core".to_string());
+            Some(HomebrewPackage {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                tap,
+                is_cask: false,
+            })
+        })
+        .collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages
+}
+
+/// Casks installed in a Homebrew `caskroom`, sorted by name.
+fn scan_caskroom(caskroom: &Path) -> Vec<HomebrewPackage> {
+    let Ok(entries) = fs::read_dir(caskroom) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    parse_casks_from_plain_text(&names.join("\n"))
+}
+
 /// Categorize Homebrew packages for migration
 ///
 /// Returns a struct with separate lists for:
@@ -88,12 +147,35 @@ pub fn categorize_packages(packages: &[HomebrewPackage]) -> HomebrewMigrationPac
 ///
 /// Only formulas from `homebrew/core` can be migrated to zerobrew.
 /// Formulas from other taps and all casks are collected separately.
+/// Without a runnable `brew`, the usual Homebrew cellars are scanned instead.
 pub fn get_homebrew_packages() -> Result<HomebrewMigrationPackages, String> {
     // Get installed formulas
-    let formulas_output = Command::new("brew")
-        .args(["info", "--json=v1", "--installed"])
-        .output()
-        .map_err(|e| format!("Failed to run 'brew info': {}", e))?;
+    let formulas_output = match output_with_timeout(
+        Command::new("brew").args(["info", "--json=v1", "--installed"]),
+        command_timeout(),
+    ) {
+        Ok(output) => output,
+        Err(e) => {
+            let cellars: Vec<&Path> = HOMEBREW_CELLARS
+                .iter()
+                .map(Path::new)
+                .filter(|cellar| cellar.is_dir())
+                .collect();
+            if cellars.is_empty() {
+                return Err(format!("Failed to run 'brew info': {}", e));
+            }
+            let packages: Vec<HomebrewPackage> = cellars
+                .iter()
+                .flat_map(|cellar| {
+                    let caskroom = cellar.with_file_name("Caskroom");
+                    scan_cellar(cellar)
+                        .into_iter()
+                        .chain(scan_caskroom(&caskroom))
+                })
+                .collect();
+            return Ok(categorize_packages(&packages));
+        }
+    };
 
     if !formulas_output.status.success() {
         return Err(format!(
@@ -108,10 +190,11 @@ pub fn get_homebrew_packages() -> Result<HomebrewMigrationPackages, String> {
     let formulas = parse_formulas_from_json(&formulas_json);
 
     // Get installed casks (plain text output, no JSON support)
-    let casks_output = Command::new("brew")
-        .args(["list", "--cask"])
-        .output()
-        .map_err(|e| format!("Failed to run 'brew list --cask': {}", e))?;
+    let casks_output = output_with_timeout(
+        Command::new("brew").args(["list", "--cask"]),
+        command_timeout(),
+    )
+    .map_err(|e| format!("Failed to run 'brew list --cask': {}", e))?;
 
     if !casks_output.status.success() {
         return Err(format!(
@@ -194,6 +277,34 @@ mod tests {
         assert!(packages.is_empty());
     }
 
+    #[test]
+    fn scan_cellar_reads_taps_from_install_receipts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cellar = tmp.path().join("Cellar");
+        fs::create_dir_all(cellar.join("jq/1.7.1")).unwrap();
+        fs::create_dir_all(cellar.join("terraform/1.5.7")).unwrap();
+        fs::write(
+            cellar.join("terraform/1.5.7/INSTALL_RECEIPT.json"),
+            r#"{"source": {"tap": "hashicorp/tap"}}"#,
+        )
+        .unwrap();
+        fs::create_dir_all(cellar.join("empty")).unwrap();
+        fs::create_dir_all(tmp.path().join("Caskroom/firefox/128.0")).unwrap();
+
+        let packages = scan_cellar(&cellar);
+        let found: Vec<(&str, &str)> = packages
+            .iter()
+            .map(|p| (p.name.as_str(), p.tap.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [("jq", "homebrew/core"), ("terraform", "hashicorp/tap")]
+        );
+        let casks = scan_caskroom(&tmp.path().join("Caskroom"));
+        assert_eq!(casks[0].name, "firefox");
+        assert!(casks[0].is_cask);
+    }
+
     #[test]
     fn test_parse_casks_handles_multiple_lines() {
         let brew_output = "visual-studio-code\nfirefox\ndocker\niterm2\n";
@@ -1,36 +1,89 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use crate::cellar::link::Linker;
-use crate::cellar::materialize::Cellar;
-use crate::installer::cask::resolve_cask;
-use crate::network::api::ApiClient;
+use crate::cellar::lazy::{LazyLauncher, bin_names, is_shim, shim_script};
+use crate::cellar::link::{LinkExclusions, LinkedFile, Linker, observed_owner};
+use crate::cellar::materialize::{Cellar, find_bottle_content};
+use crate::cellar::shim::{ShimEnv, exec_shim_target, write_shim};
+use crate::config::{Config, LinkMode};
+use crate::installer::cask::{
+    ResolvedCask, expand_zap_path, forget_pkg, install_app, install_pkg, relative_artifact_path,
+    remove_app, resolve_cask, unpack_cask, zap,
+};
+use crate::installer::doctor::{self, Diagnostic};
+use crate::installer::lockfile::Lockfile;
+use crate::installer::outdated::{
+    KegMetadata, OutdatedDiff, OutdatedFormula, PlannedUpgrade, UpgradeNotice, diff_dependencies,
+    metadata_changes, receipt_dependencies, release_notes_url,
+};
+use crate::installer::plan::{
+    DryRun, DryRunItem, EntryStatus, PlanAction, PlanDiff, PlanEntry, PlanVerification, plan_hash,
+};
+use crate::installer::preflight::{self, PreflightWarning};
+use crate::installer::smoke::{self, CheckStatus, SmokeCheck, SmokeReport, link_points_at};
+use crate::installer::source::{
+    FormulaSource, FormulaSourceOrigin, core_ruby_source_path, find_local_formula,
+    homebrew_taps_dirs, read_local,
+};
+use crate::installer::verification::{BlobVerification, VerificationReport};
+use crate::network::advisory::{Advisory, AdvisoryClient};
+use crate::network::api::{ApiClient, formula_rb_url};
 use crate::network::download::{
     DownloadProgressCallback, DownloadRequest, DownloadResult, ParallelDownloader,
 };
-use crate::progress::{InstallProgress, ProgressCallback};
+use crate::network::index::{
+    FormulaIndex, IndexPin, IndexUpdate, SearchHit, pin_index, refresh_in_background, unix_now,
+    update_index,
+};
+use crate::network::peer::{self, PeerPull};
+use crate::network::tap_formula::{TapFormulaRef, parse_tap_formula_ref, parse_tap_formula_ruby};
+use crate::plugins::{HookPhase, PluginHooks};
+use crate::progress::{InstallProgress, ProgressCallback, item_id};
+use crate::services::{ServiceKind, ServiceManager, render_unit};
+use crate::storage::audit::{OrphanAction, StoreAudit, audit};
 use crate::storage::blob::BlobCache;
-use crate::storage::db::Database;
+use crate::storage::db::{
+    Database, DownloadStats, InstalledKeg, KegFileState, LicenseAcceptance, TapInfo, UpgradeRecord,
+};
 use crate::storage::store::Store;
+use crate::tap::{Taps, default_remote, normalize_tap_name};
+use crate::watchdog::WatchdogConfig;
 
 use zb_core::{
-    BuildPlan, Error, Formula, InstallMethod, SelectedBottle, formula_token, resolve_closure,
-    select_bottle,
+    BuildPlan, ConflictedLink, Error, Formula, InstallMethod, LicenseViolation, Platform,
+    SelectedBottle, formula_token, resolve_closure, select_bottle_preferring, version_matches,
 };
 
 /// Maximum number of retries for corrupted downloads
 const MAX_CORRUPTION_RETRIES: usize = 3;
 
+/// Downloads a plan needs before the formula index is refreshed alongside.
+const BACKGROUND_REFRESH_MIN_DOWNLOADS: usize = 8;
+
+/// Index age after which a long install refreshes it in the background.
+const BACKGROUND_REFRESH_AGE_SECS: i64 = 60 * 60;
+
 pub struct Installer {
     api_client: ApiClient,
-    downloader: ParallelDownloader,
-    store: Store,
+    downloader: Deferred<ParallelDownloader>,
+    store: Deferred<Store>,
     cellar: Cellar,
-    linker: Linker,
+    linker: Deferred<Linker>,
     db: Database,
     prefix: std::path::PathBuf,
+    hooks: Deferred<PluginHooks>,
+    index: Deferred<Option<FormulaIndex>>,
+    config: Config,
+    lazy: Option<LazyLauncher>,
+    auto_accept_licenses: bool,
+    ignore_dependencies: bool,
+    zap: bool,
+    force: bool,
+    strict: bool,
+    offline: bool,
+    taps: Option<Taps>,
 }
 
 #[derive(Debug)]
@@ -40,15 +93,239 @@ pub struct PlannedInstall {
     pub method: InstallMethod,
 }
 
+impl PlannedInstall {
+    /// Identifies this item in [`InstallProgress`] events.
+    pub fn progress_id(&self) -> String {
+        let sha256 = match &self.method {
+            InstallMethod::Bottle(bottle) => bottle.sha256.as_str(),
+            InstallMethod::Source(plan) => plan.source_checksum.as_deref().unwrap_or("source"),
+        };
+        item_id(
+            &self.install_name,
+            &self.formula.effective_version(),
+            sha256,
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct InstallPlan {
     pub items: Vec<PlannedInstall>,
+    /// Unix time the formula metadata was fetched: when the local index was
+    /// last updated for plans resolved from it, otherwise when the API
+    /// answered.
+    pub metadata_fetched_at: Option<i64>,
 }
 
 pub struct ExecuteResult {
     pub installed: usize,
 }
 
+/// Upper bound on threads deleting store entries during GC.
+const GC_WORKERS: usize = 8;
+
+/// Rough ratio of a bottle's unpacked size to its compressed size, for
+/// estimating disk use before anything is unpacked.
+const UNPACKED_SIZE_RATIO: u64 = 3;
+
+/// Upper bound on bottles unpacked into the store at once.
+const UNPACK_WORKERS: usize = 4;
+
+#[derive(Debug, Clone, Default)]
+pub struct GcOptions {
+    /// Only report what would be removed.
+    pub dry_run: bool,
+    /// Skip entries that became unreferenced less than this long ago.
+    pub min_age: Option<std::time::Duration>,
+}
+
+/// What [`Installer::cleanup`] removes besides unreferenced store entries.
+#[derive(Debug, Clone, Default)]
+pub struct CleanupOptions {
+    /// Only report what would be removed.
+    pub dry_run: bool,
+    /// Remove kegs of versions that are neither installed nor retained.
+    pub superseded_kegs: bool,
+    /// Remove superseded kegs and keep only this many versions of each
+    /// formula, the installed one included; older retained versions are
+    /// dropped.
+    pub keep_latest_kegs: Option<usize>,
+    /// Skip store entries that became unreferenced less than this long ago.
+    pub min_age: Option<std::time::Duration>,
+    /// Cached downloads older than this are pruned.
+    pub cache_max_age: Option<std::time::Duration>,
+    /// Oldest cached downloads are pruned until the cache fits in this.
+    pub cache_max_bytes: Option<u64>,
+}
+
+/// Something `zb cleanup` removed (or would remove) and the bytes it took.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CleanedItem {
+    pub label: String,
+    pub bytes: u64,
+}
+
+/// What `zb cleanup` removed, by kind.
+#[derive(Debug, Clone, Default)]
+pub struct CleanupReport {
+    /// Superseded keg versions, as `name@version`.
+    pub kegs: Vec<CleanedItem>,
+    pub store_entries: Vec<CleanedItem>,
+    /// Cached downloads, by sha256.
+    pub blobs: Vec<CleanedItem>,
+}
+
+impl CleanupReport {
+    /// Total bytes reclaimed.
+    pub fn bytes(&self) -> u64 {
+        self.kegs
+            .iter()
+            .chain(&self.store_entries)
+            .chain(&self.blobs)
+            .map(|item| item.bytes)
+            .sum()
+    }
+}
+
+/// A store entry `zb cleanup --compact` archived (or would archive).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactedEntry {
+    pub store_key: String,
+    pub unpacked_bytes: u64,
+    /// `None` on a dry run.
+    pub archived_bytes: Option<u64>,
+}
+
+/// A planned formula whose license has to be accepted before it's installed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LicenseRequirement {
+    pub name: String,
+    pub version: String,
+    pub license: String,
+}
+
+/// An installed formula with known vulnerabilities.
+#[derive(Debug, Clone)]
+pub struct SecurityFinding {
+    pub name: String,
+    pub version: String,
+    pub advisories: Vec<Advisory>,
+}
+
+/// Outcome of [`Installer::security_audit`].
+#[derive(Debug, Clone, Default)]
+pub struct SecurityReport {
+    pub findings: Vec<SecurityFinding>,
+    /// Formulas checked against the advisory snapshot.
+    pub checked: usize,
+    /// Formulas the snapshot has no entry for at their installed version.
+    pub unchecked: Vec<String>,
+    /// When the oldest advisory data used was fetched.
+    pub fetched_at: Option<i64>,
+    /// Whether the report came from the stored snapshot rather than a
+    /// fresh lookup.
+    pub offline: bool,
+}
+
+/// Outcome of [`Installer::environment_report`].
+#[derive(Debug, Clone, Default)]
+pub struct EnvironmentReport {
+    /// Bottle tag of this machine, when known.
+    pub platform: Option<String>,
+    pub prefix: PathBuf,
+    pub formulas: Vec<ReportedFormula>,
+    /// Whether upstream versions could be looked up; when not, no formula
+    /// has a `current_version`.
+    pub outdated_known: bool,
+    /// Recent upgrade notes, newest first.
+    pub history: Vec<UpgradeRecord>,
+}
+
+/// One installed formula in an [`EnvironmentReport`].
+#[derive(Debug, Clone, Default)]
+pub struct ReportedFormula {
+    pub name: String,
+    pub version: String,
+    pub installed_at: i64,
+    /// License recorded at install time.
+    pub license: Option<String>,
+    /// Bytes in the keg, or `None` when it isn't materialized.
+    pub size: Option<u64>,
+    /// URL that served the bottle, when recorded.
+    pub source: Option<String>,
+    pub note: Option<String>,
+    /// Newer upstream version, if the formula is outdated.
+    pub current_version: Option<String>,
+}
+
+/// Outcome of [`Installer::relocate`].
+#[derive(Debug, Clone, Default)]
+pub struct RelocationReport {
+    /// Kegs re-materialized from the store for the new prefix.
+    pub rematerialized: Vec<String>,
+    /// Formulas whose links were recreated.
+    pub relinked: Vec<String>,
+    /// Kegs that can't be re-patched (source builds, missing store entries)
+    /// and still reference the old prefix until reinstalled.
+    pub needs_reinstall: Vec<String>,
+}
+
+/// Outcome of [`Installer::audit_links`].
+#[derive(Debug, Clone, Default)]
+pub struct LinkAudit {
+    /// Number of recorded links looked at.
+    pub checked: usize,
+    /// Recorded links that are gone or no longer lead to their keg.
+    pub broken: Vec<PathBuf>,
+    /// Number of prefix paths deliberately left unlinked by `link_exclude`.
+    pub excluded: usize,
+    /// Excluded paths that still lead into the formula's keg, because the
+    /// keg was linked before the pattern was added.
+    pub excluded_but_linked: Vec<(String, PathBuf)>,
+}
+
+impl LinkAudit {
+    pub fn is_clean(&self) -> bool {
+        self.broken.is_empty() && self.excluded_but_linked.is_empty()
+    }
+}
+
+/// Where [`Installer::reinstall`] took the fresh copy of a keg from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReinstallSource {
+    /// The store entry the keg was materialized from before.
+    Store,
+    /// The cached bottle, unpacked into the store again.
+    CachedBottle,
+    /// The bottle, downloaded again because no local copy was left.
+    Download,
+}
+
+/// A file of an installed keg that differs from what was installed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedKegFile {
+    /// Relative to the keg.
+    pub path: String,
+    /// Not part of the install at all, rather than edited.
+    pub added: bool,
+}
+
+impl std::fmt::Display for ChangedKegFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let change = if self.added { "added" } else { "modified" };
+        write!(f, "{change} {}", self.path)
+    }
+}
+
+/// What [`Installer::link`] would do, from [`Installer::preview_link`].
+#[derive(Debug, Clone, Default)]
+pub struct LinkPreview {
+    /// Links that would be created; those already in place aren't listed.
+    pub links: Vec<LinkedFile>,
+    /// Files in the way, which block the link unless it overwrites them.
+    pub conflicts: Vec<ConflictedLink>,
+}
+
 impl Installer {
     pub fn new(
         api_client: ApiClient,
@@ -61,13 +338,351 @@ impl Installer {
     ) -> Self {
         Self {
             api_client,
-            downloader: ParallelDownloader::new(blob_cache),
-            store,
+            downloader: Deferred::ready(ParallelDownloader::new(blob_cache)),
+            store: Deferred::ready(store),
             cellar,
-            linker,
+            linker: Deferred::ready(linker),
             db,
             prefix,
+            hooks: Deferred::ready(PluginHooks::default()),
+            index: Deferred::ready(None),
+            config: Config::default(),
+            lazy: None,
+            auto_accept_licenses: false,
+            ignore_dependencies: false,
+            zap: false,
+            force: false,
+            strict: false,
+            offline: false,
+            taps: None,
+        }
+    }
+
+    /// Attach the local formula index maintained by [`Installer::update_index`].
+    pub fn set_formula_index(&mut self, index: FormulaIndex) {
+        self.index.set(Some(index));
+    }
+
+    pub fn formula_index(&self) -> Result<Option<&FormulaIndex>, Error> {
+        Ok(self.index.get()?.as_ref())
+    }
+
+    /// Refresh the local formula index, fetching only formulas that changed
+    /// since the last update when possible.
+    pub async fn update_index(&mut self) -> Result<IndexUpdate, Error> {
+        let tag = self.bottle_tag();
+        let index = self
+            .index
+            .get_mut()?
+            .as_mut()
+            .ok_or_else(|| Error::ExecutionError {
+                message: "no formula index is configured".to_string(),
+            })?;
+        if let Some(pin) = index.pin() {
+            return Err(Error::ExecutionError {
+                message: format!(
+                    "the formula index is pinned to {}; run `zb update --unpin-index` first",
+                    pin.requested
+                ),
+            });
+        }
+        update_index(&self.api_client, index, tag.as_deref()).await
+    }
+
+    /// Replace the formula index with the API snapshot at `pin` (a
+    /// `YYYY-MM-DD` date or commit SHA) and resolve core formulas from it
+    /// until [`Installer::unpin_index`].
+    pub async fn pin_index(&mut self, pin: &str) -> Result<(IndexPin, IndexUpdate), Error> {
+        let index = self
+            .index
+            .get_mut()?
+            .as_mut()
+            .ok_or_else(|| Error::ExecutionError {
+                message: "no formula index is configured".to_string(),
+            })?;
+        pin_index(&self.api_client, index, pin).await
+    }
+
+    /// Drop the index pin; returns the pin that was dropped. The next update
+    /// brings the index back to the current API.
+    pub fn unpin_index(&mut self) -> Result<Option<IndexPin>, Error> {
+        let Some(index) = self.formula_index()? else {
+            return Ok(None);
+        };
+        let pin = index.pin();
+        index.set_pin(None)?;
+        Ok(pin)
+    }
+
+    pub fn index_pin(&self) -> Option<IndexPin> {
+        self.formula_index().ok()??.pin()
+    }
+
+    /// Store tap checkouts in `taps` (see [`Installer::tap`]).
+    pub fn set_taps(&mut self, taps: Taps) {
+        self.taps = Some(taps);
+    }
+
+    /// Clone the tap `name` (`owner/repo`) from `remote`, or from its GitHub
+    /// repository, and index its formulas so they resolve by short name.
+    pub async fn tap(&mut self, name: &str, remote: Option<&str>) -> Result<TapInfo, Error> {
+        let taps = self.taps()?;
+        let tap = normalize_tap_name(name)?;
+        if self.db.list_taps()?.iter().any(|info| info.name == tap) && taps.is_tapped(&tap) {
+            return Err(Error::InvalidArgument {
+                message: format!("{tap} is already tapped; `zb update` refreshes it"),
+            });
+        }
+
+        let remote = remote.map_or_else(|| default_remote(&tap), str::to_string);
+        taps.clone(&tap, &remote).await?;
+        let formulas = taps.formula_names(&tap)?;
+        self.db.record_tap(&tap, &remote, &formulas)?;
+        self.tap_info(&tap)
+    }
+
+    /// Remove the tap `name`. Installed formulas that came from it keep
+    /// working but can't be upgraded, so they block the untap unless `force`.
+    pub fn untap(&mut self, name: &str, force: bool) -> Result<TapInfo, Error> {
+        let taps = self.taps()?;
+        let tap = normalize_tap_name(name)?;
+        let info = self.tap_info(&tap)?;
+
+        let formulas = self.db.tap_formulas(&tap)?;
+        let prefix = format!("{tap}/");
+        let mut installed: Vec<String> = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .map(|keg| keg.name)
+            .filter(|keg| keg.to_ascii_lowercase().starts_with(&prefix) || formulas.contains(keg))
+            .collect();
+        installed.sort();
+        if !installed.is_empty() && !force {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "{tap} provides installed formulas ({}); uninstall them first or pass --force",
+                    installed.join(", ")
+                ),
+            });
+        }
+
+        taps.remove(&tap)?;
+        self.db.remove_tap(&tap)?;
+        Ok(info)
+    }
+
+    /// Pull every tap and re-index its formulas.
+    pub async fn update_taps(&mut self) -> Result<Vec<TapInfo>, Error> {
+        let Some(taps) = &self.taps else {
+            return Ok(Vec::new());
+        };
+        let mut updated = Vec::new();
+        for info in self.db.list_taps()? {
+            if taps.is_tapped(&info.name) {
+                taps.update(&info.name).await?;
+            } else {
+                // The checkout went missing; clone it again.
+                taps.clone(&info.name, &info.remote).await?;
+            }
+            let formulas = taps.formula_names(&info.name)?;
+            self.db.record_tap(&info.name, &info.remote, &formulas)?;
+            updated.push(self.tap_info(&info.name)?);
+        }
+        Ok(updated)
+    }
+
+    pub fn list_taps(&self) -> Result<Vec<TapInfo>, Error> {
+        self.db.list_taps()
+    }
+
+    fn tap_info(&self, tap: &str) -> Result<TapInfo, Error> {
+        self.db
+            .list_taps()?
+            .into_iter()
+            .find(|info| info.name == tap)
+            .ok_or_else(|| Error::InvalidArgument {
+                message: format!("{tap} is not tapped"),
+            })
+    }
+
+    fn taps(&self) -> Result<&Taps, Error> {
+        self.taps.as_ref().ok_or_else(|| Error::ExecutionError {
+            message: "no tap directory is configured".to_string(),
+        })
+    }
+
+    /// Metadata for `name`: from the local index when offline or pinned to a
+    /// snapshot, live from the API otherwise. Tap formulas come from the local checkout
+    /// when tapped and are fetched live otherwise; short names missing from
+    /// homebrew/core fall back to the tapped formulas.
+    async fn get_formula(&self, name: &str) -> Result<Formula, Error> {
+        if let Some(spec) = parse_tap_formula_ref(name) {
+            if let Ok(tap) = normalize_tap_name(&format!("{}/{}", spec.owner, spec.repo))
+                && let Some(formula) = self.local_tap_formula(&tap, &spec)?
+            {
+                return Ok(formula);
+            }
+            return self.api_client.get_formula(name).await;
+        }
+
+        let result = match self
+            .formula_index()?
+            .filter(|index| self.offline || index.pin().is_some())
+        {
+            Some(index) => {
+                self.indexed_formula(index, name)?
+                    .ok_or_else(|| Error::MissingFormula {
+                        name: name.to_string(),
+                    })
+            }
+            None => self.api_client.get_formula(name).await,
+        };
+        if !matches!(result, Err(Error::MissingFormula { .. })) {
+            return result;
+        }
+
+        let taps = self.db.taps_with_formula(name)?;
+        match taps.as_slice() {
+            [] => result,
+            [tap] => {
+                let (owner, repo) = tap.split_once('/').unwrap_or((tap, ""));
+                let spec = TapFormulaRef {
+                    owner: owner.to_string(),
+                    repo: repo.to_string(),
+                    formula: name.to_string(),
+                };
+                self.local_tap_formula(tap, &spec)?.map_or(result, Ok)
+            }
+            _ => Err(Error::InvalidArgument {
+                message: format!(
+                    "'{name}' is in several taps; use one of: {}",
+                    taps.iter()
+                        .map(|tap| format!("{tap}/{name}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }),
+        }
+    }
+
+    /// `spec` parsed from the checkout of `tap`, if it's tapped and has it.
+    fn local_tap_formula(&self, tap: &str, spec: &TapFormulaRef) -> Result<Option<Formula>, Error> {
+        let Some(path) = self
+            .taps
+            .as_ref()
+            .and_then(|taps| taps.formula_file(tap, &spec.formula))
+        else {
+            return Ok(None);
+        };
+        let contents = fs::read_to_string(&path).map_err(|e| Error::FileError {
+            message: format!("failed to read {}: {e}", path.display()),
+        })?;
+        let mut formula = parse_tap_formula_ruby(spec, &contents)?;
+        formula.ruby_source_path = Some(path.to_string_lossy().into_owned());
+        Ok(Some(formula))
+    }
+
+    /// Search the local formula index (see [`FormulaIndex::search`]).
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, Error> {
+        let index = self
+            .formula_index()?
+            .filter(|index| !index.is_empty())
+            .ok_or_else(|| Error::ExecutionError {
+                message: "the formula index is empty; run `zb update` first".to_string(),
+            })?;
+        index.search(query, limit)
+    }
+
+    /// Bottle tag installs prefer: the configured override, else the host's.
+    pub fn bottle_tag(&self) -> Option<String> {
+        self.config
+            .bottle_tag
+            .clone()
+            .or_else(crate::build::host_bottle_tag)
+    }
+
+    /// The bottle of `formula` to install, honouring a configured tag when
+    /// the formula publishes it.
+    pub fn select_bottle(&self, formula: &Formula) -> Result<SelectedBottle, Error> {
+        select_bottle_preferring(
+            formula,
+            &Platform::current(),
+            self.config.bottle_tag.as_deref(),
+        )
+    }
+
+    /// Replace the settings loaded from `config.toml`.
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Host conditions likely to break an install into this prefix.
+    pub fn preflight(&self) -> Vec<PreflightWarning> {
+        preflight::preflight(&self.prefix)
+    }
+
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// Install leaf bottles lazily: record the store entry and write launcher
+    /// shims that run `launcher` to materialize the keg on first use.
+    pub fn set_lazy_launcher(&mut self, launcher: Option<LazyLauncher>) {
+        self.lazy = launcher;
+    }
+
+    /// Accept licenses that need acceptance without asking, recording them
+    /// as accepted through `--accept-licenses`.
+    pub fn set_accept_licenses(&mut self, accept: bool) {
+        self.auto_accept_licenses = accept;
+    }
+
+    pub fn accepts_licenses(&self) -> bool {
+        self.auto_accept_licenses
+    }
+
+    /// Fail instead of warning when planning or installing runs into
+    /// something it would otherwise work around (`--strict`).
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Resolve core formulas from the local index only, without asking the
+    /// API (`--offline`).
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// Whole days since the metadata behind `plan` was fetched, when it came
+    /// from the local index and is older than `[index] stale_after_days`.
+    /// Offline installs chose the local index, so they are never stale.
+    pub fn stale_metadata_days(&self, plan: &InstallPlan) -> Option<u64> {
+        let stale_after = self.config.index.stale_after_days;
+        if self.offline || stale_after == 0 {
+            return None;
         }
+        let age_secs = unix_now().saturating_sub(plan.metadata_fetched_at?);
+        let days = u64::try_from(age_secs).ok()? / (24 * 60 * 60);
+        (days >= stale_after).then_some(days)
+    }
+
+    /// Print `message` as a warning, or return it as an error in strict mode.
+    fn warn(&self, message: String) -> Result<(), Error> {
+        if self.strict {
+            return Err(Error::Strict { message });
+        }
+        eprintln!("warning: {message}");
+        Ok(())
+    }
+
+    /// Replace the plugins invoked at install phases (see [`PluginHooks`]).
+    pub fn set_plugin_hooks(&mut self, hooks: PluginHooks) {
+        self.hooks.set(hooks);
     }
 
     pub async fn plan(&self, names: &[String]) -> Result<InstallPlan, Error> {
@@ -79,16 +694,58 @@ impl Installer {
         names: &[String],
         build_from_source: bool,
     ) -> Result<InstallPlan, Error> {
-        let formulas = self.fetch_all_formulas(names).await?;
+        self.hooks.get()?.run(
+            HookPhase::PrePlan,
+            &self.prefix,
+            serde_json::json!({ "formulas": names }),
+        )?;
+        if let Some(tag) = &self.config.bottle_tag
+            && !Platform::current().supports(tag)
+        {
+            self.warn(format!(
+                "bottle tag '{tag}' doesn't match this machine ({}); its bottles may not run",
+                host_platform()
+            ))?;
+        }
+
+        if self.offline && self.formula_index()?.is_none_or(FormulaIndex::is_empty) {
+            return Err(Error::ExecutionError {
+                message: "the formula index is empty; run `zb update` before installing offline"
+                    .to_string(),
+            });
+        }
+        let (formulas, metadata_fetched_at) = self.fetch_all_formulas(names).await?;
         let ordered = resolve_closure(names, &formulas)?;
+        self.check_license_policy(names, &ordered, &formulas)?;
+        if !self.force {
+            self.check_conflicts(&ordered, &formulas)?;
+        }
 
         let mut items = Vec::with_capacity(ordered.len());
         for install_name in ordered {
             let formula = formulas.get(&install_name).cloned().unwrap();
-            let method = if build_from_source {
+            let method = if formula.is_head_only() {
+                // No release to take a bottle or archive of: only a build of
+                // the head repository, and only when asked for.
+                if !build_from_source {
+                    return Err(Error::HeadOnly {
+                        name: formula.name.clone(),
+                    });
+                }
+                match BuildPlan::from_formula(&formula, &self.prefix) {
+                    Some(plan) => InstallMethod::Source(plan),
+                    None => {
+                        return Err(Error::UnsupportedFormula {
+                            name: formula.name.clone(),
+                            reason: "it has no stable version and no head repository to build"
+                                .to_string(),
+                        });
+                    }
+                }
+            } else if build_from_source {
                 match BuildPlan::from_formula(&formula, &self.prefix) {
                     Some(plan) => InstallMethod::Source(plan),
-                    None => match select_bottle(&formula) {
+                    None => match self.select_bottle(&formula) {
                         Ok(bottle) => InstallMethod::Bottle(bottle),
                         Err(_) => {
                             return Err(Error::UnsupportedBottle {
@@ -98,7 +755,7 @@ impl Installer {
                     },
                 }
             } else {
-                match select_bottle(&formula) {
+                match self.select_bottle(&formula) {
                     Ok(bottle) => InstallMethod::Bottle(bottle),
                     Err(_) => match BuildPlan::from_formula(&formula, &self.prefix) {
                         Some(plan) => InstallMethod::Source(plan),
@@ -110,6 +767,21 @@ impl Installer {
                     },
                 }
             };
+            if formula.deprecated {
+                self.warn(match &formula.deprecation_reason {
+                    Some(reason) => format!("{} is deprecated ({reason})", formula.name),
+                    None => format!("{} is deprecated", formula.name),
+                })?;
+            }
+            if let InstallMethod::Source(plan) = &method
+                && !plan.head
+                && plan.source_checksum.is_none()
+            {
+                self.warn(format!(
+                    "{} has no source checksum; its archive won't be verified",
+                    formula.name
+                ))?;
+            }
             items.push(PlannedInstall {
                 install_name,
                 formula,
@@ -117,84 +789,463 @@ impl Installer {
             });
         }
 
-        Ok(InstallPlan { items })
+        Ok(InstallPlan {
+            items,
+            metadata_fetched_at,
+        })
     }
 
-    /// Try to extract a download, with automatic retry on corruption
-    async fn extract_with_retry(
+    /// Fail when a formula the plan would newly install declares a conflict
+    /// with an installed one, before anything is downloaded; linking it
+    /// would otherwise fail on the files they share.
+    fn check_conflicts(
         &self,
-        download: &DownloadResult,
-        formula: &Formula,
-        bottle: &SelectedBottle,
-        progress: Option<DownloadProgressCallback>,
-    ) -> Result<std::path::PathBuf, Error> {
-        let mut blob_path = download.blob_path.clone();
-        let mut last_error = None;
+        ordered: &[String],
+        formulas: &BTreeMap<String, Formula>,
+    ) -> Result<(), Error> {
+        for name in ordered {
+            if self.db.get_installed(name).is_some() {
+                continue;
+            }
+            let formula = &formulas[name];
+            if let Some(installed) = formula
+                .conflicts_with
+                .iter()
+                .find(|other| *other != name && self.db.get_installed(other).is_some())
+            {
+                return Err(Error::FormulaConflict {
+                    name: name.clone(),
+                    installed: installed.clone(),
+                    reason: formula.conflict_reason(installed).map(str::to_string),
+                });
+            }
+        }
+        Ok(())
+    }
 
-        for attempt in 0..MAX_CORRUPTION_RETRIES {
-            match self.store.ensure_entry(&bottle.sha256, &blob_path) {
-                Ok(entry) => return Ok(entry),
-                Err(Error::StoreCorruption { message }) => {
-                    // Remove the corrupted blob
-                    self.downloader.remove_blob(&bottle.sha256);
+    /// Fail with every formula in the closure the `[licenses]` policy
+    /// doesn't allow, each with the chain of dependents that pulled it in.
+    fn check_license_policy(
+        &self,
+        roots: &[String],
+        ordered: &[String],
+        formulas: &BTreeMap<String, Formula>,
+    ) -> Result<(), Error> {
+        let violating: Vec<&String> = ordered
+            .iter()
+            .filter(|name| {
+                let license = formulas.get(*name).and_then(|f| f.license.as_deref());
+                !self.config.license_permits(name, license)
+            })
+            .collect();
+        if violating.is_empty() {
+            return Ok(());
+        }
 
-                    if attempt + 1 < MAX_CORRUPTION_RETRIES {
-                        // Log retry attempt
-                        eprintln!(
-                            "    Corrupted download detected for {}, retrying ({}/{})...",
-                            formula.name,
-                            attempt + 2,
-                            MAX_CORRUPTION_RETRIES
-                        );
+        // Breadth-first from the roots, so each chain is a shortest one.
+        let mut parents: BTreeMap<&str, &str> = BTreeMap::new();
+        let mut queue: std::collections::VecDeque<&str> =
+            roots.iter().map(String::as_str).collect();
+        while let Some(name) = queue.pop_front() {
+            for dependency in formulas.get(name).into_iter().flat_map(|f| &f.dependencies) {
+                if formulas.contains_key(dependency)
+                    && !roots.contains(dependency)
+                    && !parents.contains_key(dependency.as_str())
+                {
+                    parents.insert(dependency, name);
+                    queue.push_back(dependency);
+                }
+            }
+        }
 
-                        // Re-download
-                        let request = DownloadRequest {
-                            url: bottle.url.clone(),
-                            sha256: bottle.sha256.clone(),
-                            name: formula.name.clone(),
-                        };
+        let violations = violating
+            .into_iter()
+            .map(|name| {
+                let mut required_by = Vec::new();
+                let mut current = name.as_str();
+                while let Some(parent) = parents.get(current) {
+                    required_by.push(parent.to_string());
+                    current = parent;
+                }
+                required_by.reverse();
+                LicenseViolation {
+                    name: name.clone(),
+                    license: formulas.get(name).and_then(|f| f.license.clone()),
+                    required_by,
+                }
+            })
+            .collect();
+        Err(Error::LicensePolicy { violations })
+    }
 
-                        match self
-                            .downloader
-                            .download_single(request, progress.clone())
-                            .await
-                        {
-                            Ok(new_path) => {
-                                blob_path = new_path;
-                                // Continue to next iteration to retry extraction
-                            }
-                            Err(e) => {
-                                last_error = Some(e);
-                                break;
-                            }
-                        }
-                    } else {
-                        last_error = Some(Error::StoreCorruption {
-                            message: format!(
-                                "{message}\n\nFailed after {MAX_CORRUPTION_RETRIES} attempts. The download may be corrupted at the source."
-                            ),
-                        });
-                    }
+    /// What executing `plan` would change: formulas to install, upgrade or
+    /// reinstall, and installed dependencies nothing would need afterwards.
+    /// Formulas nothing depends on now are never removed, since they were
+    /// asked for.
+    pub fn diff_plan(&self, plan: &InstallPlan) -> Result<PlanDiff, Error> {
+        let mut diff = PlanDiff::default();
+        for entry in plan.entries_in_order() {
+            match self.db.get_installed(&entry.name) {
+                None => diff.actions.push(PlanAction::Install {
+                    name: entry.name,
+                    version: entry.version,
+                }),
+                Some(installed) if installed.version != entry.version => {
+                    diff.actions.push(PlanAction::Upgrade {
+                        name: entry.name,
+                        from: installed.version,
+                        to: entry.version,
+                    })
                 }
-                Err(e) => {
-                    last_error = Some(e);
-                    break;
+                Some(installed) if installed.store_key != entry.store_key => {
+                    diff.actions.push(PlanAction::Reinstall {
+                        name: entry.name,
+                        version: entry.version,
+                    })
                 }
+                Some(_) => diff.unchanged.push(entry.name),
             }
         }
 
-        Err(last_error.unwrap_or_else(|| Error::StoreCorruption {
-            message: "extraction failed with unknown error".to_string(),
-        }))
-    }
+        // Dependencies after the plan: planned formulas bring their new
+        // ones, everything else keeps what it was installed with.
+        let installed = self.db.list_installed()?;
+        let mut remaining: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for keg in &installed {
+            remaining.insert(keg.name.clone(), self.db.dependencies(&keg.name)?);
+        }
+        for item in &plan.items {
+            remaining.insert(item.install_name.clone(), item.formula.dependencies.clone());
+        }
+        let leaves = self.leaves()?;
+        let candidates: Vec<(String, String)> = installed
+            .into_iter()
+            .filter(|keg| {
+                !leaves.contains(&keg.name)
+                    && !plan.items.iter().any(|item| item.install_name == keg.name)
+            })
+            .map(|keg| (keg.name, keg.version))
+            .collect();
+
+        // Removing one orphan can orphan its own dependencies, so repeat
+        // until nothing changes; dependents come out before dependencies.
+        let mut removed = true;
+        while removed {
+            removed = false;
+            for (name, version) in &candidates {
+                if !remaining.contains_key(name) {
+                    continue;
+                }
+                let needed = remaining.iter().any(|(other, dependencies)| {
+                    other != name
+                        && dependencies
+                            .iter()
+                            .any(|dep| dep == name || dep == formula_token(name))
+                });
+                if !needed {
+                    remaining.remove(name);
+                    diff.actions.push(PlanAction::Autoremove {
+                        name: name.clone(),
+                        version: version.clone(),
+                    });
+                    removed = true;
+                }
+            }
+        }
+        Ok(diff)
+    }
+
+    /// Stable hash identifying `plan` on this platform; see [`InstallPlan::hash`].
+    pub fn plan_hash(&self, plan: &InstallPlan) -> String {
+        plan.hash(&host_platform())
+    }
+
+    /// A lockfile pinning exactly what `plan`, made for `roots`, resolved to.
+    pub fn lockfile(
+        &self,
+        plan: &InstallPlan,
+        roots: &[String],
+        build_from_source: bool,
+    ) -> Lockfile {
+        Lockfile::new(plan, &host_platform(), roots, build_from_source)
+    }
+
+    /// Fail unless `plan` resolved to exactly what `lockfile` pins, on the
+    /// platform it was locked for.
+    pub fn check_lockfile(&self, lockfile: &Lockfile, plan: &InstallPlan) -> Result<(), Error> {
+        let drift = lockfile.drift(plan, &host_platform());
+        if drift.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::LockfileDrift { drift })
+        }
+    }
+
+    /// What executing `plan` would change, fetch and take up on disk,
+    /// without touching the store or prefix. Sizes of blobs that aren't
+    /// cached are asked of the server.
+    pub async fn dry_run(&self, plan: &InstallPlan) -> Result<DryRun, Error> {
+        let diff = self.diff_plan(plan)?;
+        let downloader = self.downloader.get()?;
+        let mut run = DryRun::default();
+        let mut fetch = Vec::new();
+        for (item, entry) in plan.items.iter().zip(plan.entries_in_order()) {
+            let (url, cached) = match &item.method {
+                InstallMethod::Bottle(bottle) => (
+                    bottle.url.as_str(),
+                    self.store.get()?.has_entry(&bottle.sha256)
+                        || downloader.cached_blob_size(&bottle.sha256).is_some(),
+                ),
+                InstallMethod::Source(build) => (build.source_url.as_str(), false),
+            };
+            let action = diff
+                .actions
+                .iter()
+                .find(|action| action.name() == entry.name)
+                .cloned();
+            if action.is_some() && !cached {
+                fetch.push((run.items.len(), url));
+            }
+            run.items.push(DryRunItem {
+                name: entry.name,
+                version: entry.version,
+                method: entry.method,
+                action,
+                cached,
+                download_bytes: None,
+            });
+        }
+
+        let sizes =
+            futures::future::join_all(fetch.iter().map(|(_, url)| downloader.remote_size(url)))
+                .await;
+        for ((idx, _), size) in fetch.iter().zip(sizes) {
+            run.items[*idx].download_bytes = size;
+            if let Some(bytes) = size {
+                run.download_bytes += bytes;
+                // The blob stays in the download cache next to what it unpacks to.
+                run.disk_estimate += bytes + bytes * UNPACKED_SIZE_RATIO;
+            }
+        }
+        Ok(run)
+    }
+
+    /// Compare a previously executed plan, looked up by hash or unique hash
+    /// prefix, with what is installed now.
+    pub fn verify_plan(&self, hash: &str) -> Result<PlanVerification, Error> {
+        let prefix = hash.trim().to_ascii_lowercase();
+        if prefix.len() < 8 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::InvalidArgument {
+                message: format!("'{hash}' is not a plan hash (need at least 8 hex digits)"),
+            });
+        }
+
+        let mut plans = self.db.find_plans(&prefix)?;
+        let plan = match plans.len() {
+            0 => {
+                return Err(Error::InvalidArgument {
+                    message: format!("no recorded install plan matches '{hash}'"),
+                });
+            }
+            1 => plans.remove(0),
+            n => {
+                return Err(Error::InvalidArgument {
+                    message: format!("'{hash}' matches {n} recorded plans; use more digits"),
+                });
+            }
+        };
+
+        let entries: Vec<PlanEntry> = serde_json::from_str(&plan.entries)
+            .map_err(|e| Error::db("recorded plan is corrupt").with_source(e))?;
+        let entries = entries
+            .into_iter()
+            .map(|entry| {
+                let status = match self.db.get_installed(&entry.name) {
+                    None => EntryStatus::NotInstalled,
+                    Some(keg)
+                        if keg.version == entry.version && keg.store_key == entry.store_key =>
+                    {
+                        EntryStatus::Matches
+                    }
+                    Some(keg) => EntryStatus::Differs {
+                        installed_version: keg.version,
+                        installed_store_key: keg.store_key,
+                    },
+                };
+                (entry, status)
+            })
+            .collect();
+
+        Ok(PlanVerification {
+            hash: plan.hash,
+            platform: plan.platform,
+            current_platform: host_platform(),
+            recorded_at: plan.recorded_at,
+            entries,
+        })
+    }
+
+    /// Try to extract a download, with automatic retry on corruption
+    async fn extract_with_retry(
+        &self,
+        download: &DownloadResult,
+        item: &PlannedInstall,
+        bottle: &SelectedBottle,
+        progress: Option<DownloadProgressCallback>,
+    ) -> Result<std::path::PathBuf, Error> {
+        let formula = &item.formula;
+        let mut blob_path = download.blob_path.clone();
+        let mut last_error = None;
+
+        for attempt in 0..MAX_CORRUPTION_RETRIES {
+            match self.store.get()?.ensure_entry(&bottle.sha256, &blob_path) {
+                Ok(entry) => return Ok(entry),
+                Err(e @ (Error::StoreCorruption { .. } | Error::Unpack { .. })) => {
+                    // Remove the corrupted blob
+                    self.downloader.get()?.remove_blob(&bottle.sha256);
+
+                    if attempt + 1 < MAX_CORRUPTION_RETRIES {
+                        // Log retry attempt
+                        eprintln!(
+                            "    Corrupted download detected for {}, retrying ({}/{})...",
+                            formula.name,
+                            attempt + 2,
+                            MAX_CORRUPTION_RETRIES
+                        );
+
+                        // Re-download
+                        let request = DownloadRequest {
+                            url: bottle.url.clone(),
+                            sha256: bottle.sha256.clone(),
+                            name: formula.name.clone(),
+                            id: item.progress_id(),
+                        };
+
+                        match self
+                            .downloader
+                            .get()?
+                            .download_single(request, progress.clone())
+                            .await
+                        {
+                            Ok(new_path) => {
+                                blob_path = new_path;
+                                // Continue to next iteration to retry extraction
+                            }
+                            Err(e) => {
+                                last_error = Some(e);
+                                break;
+                            }
+                        }
+                    } else {
+                        last_error = Some(
+                            Error::unpack(format!(
+                                "failed after {MAX_CORRUPTION_RETRIES} attempts; the download may be corrupted at the source"
+                            ))
+                            .with_path(&blob_path)
+                            .with_source(e),
+                        );
+                    }
+                }
+                Err(e) => {
+                    last_error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| Error::unpack("extraction failed with unknown error"))
+            .with_formula(&formula.name))
+    }
 
-    /// Recursively fetch a formula and all its dependencies in parallel batches
+    /// Recursively fetch a formula and all its dependencies in parallel
+    /// batches, along with the Unix time that metadata was fetched (see
+    /// [`InstallPlan::metadata_fetched_at`]).
     async fn fetch_all_formulas(
         &self,
         names: &[String],
+    ) -> Result<(BTreeMap<String, Formula>, Option<i64>), Error> {
+        // Closures are only cached when metadata comes from the index, the
+        // same rule `get_formula` applies; online runs always resolve live.
+        let closure_index = self
+            .formula_index()?
+            .filter(|index| self.offline || index.pin().is_some());
+        let fetched_at = match closure_index {
+            Some(index) => index.updated_at(),
+            None => Some(unix_now()),
+        };
+        if let Some(index) = closure_index
+            && let Some(formulas) = self.cached_formulas(index, names)?
+        {
+            return Ok((formulas, fetched_at));
+        }
+
+        let formulas = self.fetch_formula_closure(names).await?;
+        if let Some(index) = closure_index {
+            // Best effort: a failed write only costs a re-fetch next time.
+            for root in names {
+                if let Some(closure) = closure_of(root, &formulas) {
+                    let _ = index.cache_closure(&self.closure_key(root), &closure);
+                }
+            }
+        }
+        Ok((formulas, fetched_at))
+    }
+
+    /// Key a cached closure of `root` is stored under. Formulas without a
+    /// bottle for the preferred tag drop out of a closure, so the tag is
+    /// part of the key.
+    fn closure_key(&self, root: &str) -> String {
+        format!("{root}@{}", self.bottle_tag().unwrap_or_default())
+    }
+
+    /// Formulas for `names` from closures cached against the current index,
+    /// or `None` when any root or member isn't available locally.
+    fn cached_formulas(
+        &self,
+        index: &FormulaIndex,
+        names: &[String],
+    ) -> Result<Option<BTreeMap<String, Formula>>, Error> {
+        let mut formulas = BTreeMap::new();
+        for root in names {
+            let Some(closure) = index.cached_closure(&self.closure_key(root)) else {
+                return Ok(None);
+            };
+            for name in closure {
+                if let std::collections::btree_map::Entry::Vacant(entry) = formulas.entry(name) {
+                    let Some(formula) = self.indexed_formula(index, entry.key())? else {
+                        return Ok(None);
+                    };
+                    entry.insert(formula);
+                }
+            }
+        }
+        Ok(Some(formulas))
+    }
+
+    /// `name` parsed from the index. JSON that no longer parses as a
+    /// formula (the API's schema drifted from the one zb knows) is reported
+    /// and treated as missing.
+    fn indexed_formula(&self, index: &FormulaIndex, name: &str) -> Result<Option<Formula>, Error> {
+        let Some(json) = index.formula_json(name) else {
+            return Ok(None);
+        };
+        match serde_json::from_str(&json) {
+            Ok(formula) => Ok(Some(formula)),
+            Err(e) => {
+                self.warn(format!(
+                    "indexed formula {name} doesn't match the expected schema: {e}"
+                ))?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn fetch_formula_closure(
+        &self,
+        names: &[String],
     ) -> Result<BTreeMap<String, Formula>, Error> {
         use std::collections::HashSet;
-        use zb_core::select_bottle;
 
         let mut formulas = BTreeMap::new();
         let mut fetched: HashSet<String> = HashSet::new();
@@ -217,10 +1268,7 @@ impl Installer {
             }
 
             // Fetch all in parallel
-            let futures: Vec<_> = batch
-                .iter()
-                .map(|n| self.api_client.get_formula(n))
-                .collect();
+            let futures: Vec<_> = batch.iter().map(|n| self.get_formula(n)).collect();
 
             let results = futures::future::join_all(futures).await;
 
@@ -231,11 +1279,15 @@ impl Installer {
                     Err(e) => return Err(e),
                 };
 
-                if select_bottle(&formula).is_err() && !formula.has_source_url() {
-                    eprintln!(
-                        "    Skipping {} (no bottle or source available for this platform)",
+                // HEAD-only formulas stay in, so planning can say what they need.
+                if self.select_bottle(&formula).is_err()
+                    && !formula.has_source_url()
+                    && !formula.is_head_only()
+                {
+                    self.warn(format!(
+                        "skipping {} (no bottle or source available for this platform)",
                         formula.name
-                    );
+                    ))?;
                     continue;
                 }
 
@@ -270,6 +1322,38 @@ impl Installer {
             }
         };
 
+        let unaccepted = self.licenses_to_accept(&plan);
+        if !unaccepted.is_empty() {
+            if !self.auto_accept_licenses {
+                let listed: Vec<String> = unaccepted
+                    .iter()
+                    .map(|requirement| format!("{} ({})", requirement.name, requirement.license))
+                    .collect();
+                return Err(Error::InvalidArgument {
+                    message: format!(
+                        "license acceptance required for {}; rerun with --accept-licenses",
+                        listed.join(", ")
+                    ),
+                });
+            }
+            self.accept_licenses(&unaccepted, "--accept-licenses")?;
+        }
+
+        let plan_entries = plan.entries();
+        let plan_hash = plan_hash(&plan_entries, &host_platform());
+        let notices = self.upgrade_notices(&plan);
+        let metadata: Vec<(String, String, KegMetadata)> = plan
+            .items
+            .iter()
+            .map(|item| {
+                (
+                    item.install_name.clone(),
+                    item.formula.effective_version(),
+                    KegMetadata::of(&item.formula),
+                )
+            })
+            .collect();
+
         let (bottle_items, source_items): (Vec<_>, Vec<_>) = plan
             .items
             .into_iter()
@@ -279,23 +1363,22 @@ impl Installer {
             return Ok(ExecuteResult { installed: 0 });
         }
 
+        // Keeps GC from deleting a store entry between extraction and the
+        // reference being recorded.
+        let _store_lock = self.store.get()?.lock_store(false)?;
+
         let mut installed = 0usize;
         let mut error: Option<Error> = None;
+        let mut index_refresh = None;
 
         if !bottle_items.is_empty() {
-            let requests: Vec<DownloadRequest> = bottle_items
-                .iter()
-                .map(|item| {
-                    let InstallMethod::Bottle(ref bottle) = item.method else {
-                        unreachable!()
-                    };
-                    DownloadRequest {
-                        url: bottle.url.clone(),
-                        sha256: bottle.sha256.clone(),
-                        name: item.formula.name.clone(),
-                    }
-                })
-                .collect();
+            let (requests, groups) = dedupe_bottle_downloads(&bottle_items);
+            index_refresh = self.spawn_index_refresh(&requests);
+            let lazy_names = if link {
+                self.lazy_candidates(&bottle_items)
+            } else {
+                BTreeSet::new()
+            };
 
             let download_progress: Option<DownloadProgressCallback> = progress.clone().map(|cb| {
                 Arc::new(move |event: InstallProgress| {
@@ -305,176 +1388,165 @@ impl Installer {
 
             let mut rx = self
                 .downloader
+                .get()?
                 .download_streaming(requests, download_progress.clone());
 
-            while let Some(result) = rx.recv().await {
-                match result {
-                    Ok(download) => {
-                        let idx = download.index;
-                        let item = &bottle_items[idx];
-                        let InstallMethod::Bottle(ref bottle) = item.method else {
-                            unreachable!()
-                        };
-                        let processed_name = item.install_name.clone();
-                        let materialized_name = item.formula.name.clone();
-                        let processed_version = item.formula.effective_version();
-                        let processed_store_key = bottle.sha256.clone();
-
-                        report(InstallProgress::UnpackStarted {
-                            name: materialized_name.clone(),
-                        });
-
-                        let store_entry = match self
-                            .extract_with_retry(
+            // Bottles unpack on blocking workers as their downloads land, so
+            // network and disk work overlap. Kegs are finished here, each
+            // once the dependencies it has in the plan are.
+            let dependencies = bottle_dependencies(&bottle_items);
+            let unpack_slots = Arc::new(tokio::sync::Semaphore::new(UNPACK_WORKERS));
+            let (unpacked_tx, mut unpacked_rx) = tokio::sync::mpsc::unbounded_channel();
+            let mut unpacking = 0usize;
+            let mut downloads_done = false;
+            let mut waiting: Vec<(usize, PathBuf, DownloadResult)> = Vec::new();
+            let mut finished: BTreeSet<usize> = BTreeSet::new();
+            // Finished without a keg; dependents of these are not installed.
+            let mut failed: BTreeSet<usize> = BTreeSet::new();
+
+            loop {
+                tokio::select! {
+                    result = rx.recv(), if !downloads_done => match result {
+                        None => downloads_done = true,
+                        Some(Err(e)) => error = Some(e),
+                        Some(Ok(download)) => {
+                            self.record_download_stats(&download);
+                            let members = self.accept_download(
                                 &download,
-                                &item.formula,
-                                bottle,
-                                download_progress.clone(),
-                            )
-                            .await
-                        {
-                            Ok(entry) => entry,
-                            Err(e) => {
-                                error = Some(e);
-                                continue;
-                            }
-                        };
-
-                        let keg_path = match self.cellar.materialize(
-                            &materialized_name,
-                            &processed_version,
-                            &store_entry,
-                        ) {
-                            Ok(path) => path,
-                            Err(e) => {
-                                error = Some(e);
-                                continue;
-                            }
-                        };
-
-                        report(InstallProgress::UnpackCompleted {
-                            name: materialized_name.clone(),
-                        });
-
-                        let tx = match self.db.transaction() {
-                            Ok(tx) => tx,
-                            Err(e) => {
-                                Self::cleanup_materialized(
-                                    &self.cellar,
-                                    &materialized_name,
-                                    &processed_version,
-                                );
-                                error = Some(e);
-                                continue;
-                            }
-                        };
-
-                        if let Err(e) = tx.record_install(
-                            &processed_name,
-                            &processed_version,
-                            &processed_store_key,
-                        ) {
-                            drop(tx);
-                            Self::cleanup_materialized(
-                                &self.cellar,
-                                &materialized_name,
-                                &processed_version,
-                            );
-                            error = Some(e);
-                            continue;
-                        }
-
-                        if let Err(e) = tx.commit() {
-                            Self::cleanup_materialized(
-                                &self.cellar,
-                                &materialized_name,
-                                &processed_version,
+                                &groups[download.index],
+                                &bottle_items,
+                                &report,
+                                &mut error,
                             );
-                            error = Some(e);
-                            continue;
-                        }
-
-                        if let Err(e) = self.linker.link_opt(&keg_path) {
-                            eprintln!(
-                                "warning: failed to create opt link for {}: {}",
-                                processed_name, e
-                            );
-                        }
-
-                        let should_link = link && !item.formula.is_keg_only();
+                            let rejected = groups[download.index]
+                                .iter()
+                                .filter(|idx| !members.contains(idx));
+                            finished.extend(rejected.clone());
+                            failed.extend(rejected);
+                            let Some(&first) = members.first() else {
+                                continue;
+                            };
+                            let store_key = download.sha256.clone();
 
-                        let linked_files = if should_link {
-                            report(InstallProgress::LinkStarted {
-                                name: materialized_name.clone(),
-                            });
-                            match self.linker.link_keg(&keg_path) {
-                                Ok(files) => {
-                                    report(InstallProgress::LinkCompleted {
-                                        name: materialized_name.clone(),
-                                    });
-                                    files
-                                }
-                                Err(e) => {
-                                    let _ = self.linker.unlink_keg(&keg_path);
-                                    error = Some(e);
-                                    installed += 1;
-                                    report(InstallProgress::InstallCompleted {
-                                        name: materialized_name.clone(),
-                                    });
-                                    continue;
+                            if self.store.get()?.has_entry(&store_key) {
+                                let bytes = blob_size(&download.blob_path);
+                                if self.config.analytics.enabled
+                                    && let Err(e) = self.db.record_cache_hit("store", bytes)
+                                {
+                                    eprintln!("warning: failed to record cache stats: {e}");
                                 }
-                            }
-                        } else {
-                            if link && item.formula.is_keg_only() {
-                                let reason = match &item.formula.keg_only {
-                                    zb_core::KegOnly::Reason(s) => s.clone(),
-                                    _ if item.formula.name.contains('@') => {
-                                        "versioned formula".to_string()
+                                match self.store.get()?.unpacked_entry(&store_key) {
+                                    Ok(entry) => waiting.extend(
+                                        members
+                                            .iter()
+                                            .map(|&idx| (idx, entry.clone(), download.clone())),
+                                    ),
+                                    Err(e) => {
+                                        error = Some(e.with_formula(&bottle_items[first].install_name));
+                                        finished.extend(&members);
+                                        failed.extend(members);
                                     }
-                                    _ => "keg-only formula".to_string(),
-                                };
-                                report(InstallProgress::LinkSkipped {
-                                    name: materialized_name.clone(),
-                                    reason,
+                                }
+                            } else {
+                                unpacking += 1;
+                                let store = self.store.get()?.clone();
+                                let slots = unpack_slots.clone();
+                                let unpacked_tx = unpacked_tx.clone();
+                                tokio::spawn(async move {
+                                    let _slot = slots.acquire_owned().await;
+                                    let blob_path = download.blob_path.clone();
+                                    let result = tokio::task::spawn_blocking(move || {
+                                        store.ensure_entry(&store_key, &blob_path)
+                                    })
+                                    .await
+                                    .unwrap_or_else(|e| {
+                                        Err(Error::unpack(format!("unpack worker failed: {e}")))
+                                    });
+                                    let _ = unpacked_tx.send((download, members, result));
                                 });
                             }
-                            Vec::new()
+                        }
+                    },
+                    Some((download, members, result)) = unpacked_rx.recv(), if unpacking > 0 => {
+                        unpacking -= 1;
+                        let first = &bottle_items[members[0]];
+                        let InstallMethod::Bottle(ref bottle) = first.method else {
+                            unreachable!()
                         };
-
-                        if !linked_files.is_empty()
-                            && let Ok(tx) = self.db.transaction()
-                        {
-                            let mut ok = true;
-                            for linked in &linked_files {
-                                if tx
-                                    .record_linked_file(
-                                        &processed_name,
-                                        &processed_version,
-                                        &linked.link_path.to_string_lossy(),
-                                        &linked.target_path.to_string_lossy(),
-                                    )
-                                    .is_err()
-                                {
-                                    ok = false;
-                                    break;
-                                }
+                        let result = match result {
+                            // Corrupt blobs are fetched again and retried here.
+                            Err(Error::StoreCorruption { .. } | Error::Unpack { .. }) => {
+                                self.extract_with_retry(
+                                    &download,
+                                    first,
+                                    bottle,
+                                    download_progress.clone(),
+                                )
+                                .await
                             }
-                            if ok {
-                                let _ = tx.commit();
+                            result => result,
+                        };
+                        let recorded = result.and_then(|entry| match &download.source {
+                            Some(source) => self
+                                .db
+                                .record_store_source(&bottle.sha256, source)
+                                .map(|()| entry),
+                            None => Ok(entry),
+                        });
+                        match recorded {
+                            Ok(entry) => waiting.extend(
+                                members
+                                    .iter()
+                                    .map(|&idx| (idx, entry.clone(), download.clone())),
+                            ),
+                            Err(e) => {
+                                error = Some(e.with_formula(&first.install_name));
+                                finished.extend(&members);
+                                failed.extend(members);
                             }
                         }
+                    },
+                    else => break,
+                }
 
-                        report(InstallProgress::InstallCompleted {
-                            name: materialized_name.clone(),
-                        });
-
+                while let Some(pos) = waiting.iter().position(|(idx, ..)| {
+                    dependencies[*idx].iter().all(|dep| finished.contains(dep))
+                }) {
+                    let (idx, store_entry, download) = waiting.remove(pos);
+                    finished.insert(idx);
+                    if let Some(&dep) = dependencies[idx].iter().find(|dep| failed.contains(dep)) {
+                        error = Some(failed_dependency(
+                            &bottle_items[idx],
+                            &bottle_items[dep],
+                            error.take(),
+                        ));
+                        failed.insert(idx);
+                    } else if self.finish_bottle_install(
+                        &bottle_items[idx],
+                        &store_entry,
+                        &download,
+                        link,
+                        &lazy_names,
+                        &report,
+                        &mut error,
+                    ) {
                         installed += 1;
-                    }
-                    Err(e) => {
-                        error = Some(e);
+                    } else {
+                        failed.insert(idx);
                     }
                 }
             }
+
+            // Whatever still waits has a dependency that never arrived.
+            for (idx, ..) in waiting {
+                if let Some(&dep) = dependencies[idx].iter().find(|dep| !finished.contains(dep)) {
+                    error = Some(failed_dependency(
+                        &bottle_items[idx],
+                        &bottle_items[dep],
+                        error.take(),
+                    ));
+                }
+            }
         }
 
         for item in &source_items {
@@ -483,6 +1555,7 @@ impl Installer {
             };
 
             report(InstallProgress::UnpackStarted {
+                id: item.progress_id(),
                 name: item.formula.name.clone(),
             });
 
@@ -492,54 +1565,583 @@ impl Installer {
             {
                 Ok(()) => installed += 1,
                 Err(e) => {
-                    error = Some(e);
+                    error = Some(e.with_formula(&item.install_name));
                     continue;
                 }
             }
         }
 
+        self.record_metadata(&metadata, &notices);
+        // Whatever the refresh hasn't committed by now is left for next time.
+        drop(index_refresh);
+
         if let Some(e) = error {
             return Err(e);
         }
 
-        Ok(ExecuteResult { installed })
-    }
-
-    fn cleanup_failed_install(
-        linker: &Linker,
-        cellar: &Cellar,
-        name: &str,
-        version: &str,
-        keg_path: &Path,
-        unlink: bool,
-    ) {
-        if unlink && let Err(e) = linker.unlink_keg(keg_path) {
-            eprintln!(
-                "warning: failed to clean up links for {}@{} after install error: {}",
-                name, version, e
-            );
+        // The plan only becomes a receipt once every entry is in place.
+        let recorded = serde_json::to_string(&plan_entries)
+            .map_err(|e| Error::db("failed to serialize plan").with_source(e))
+            .and_then(|entries| self.db.record_plan(&plan_hash, &host_platform(), &entries));
+        if let Err(e) = recorded {
+            eprintln!("warning: failed to record install plan: {e}");
         }
 
-        if let Err(e) = cellar.remove_keg(name, version) {
-            eprintln!(
-                "warning: failed to remove keg for {}@{} after install error: {}",
-                name, version, e
-            );
-        }
+        Ok(ExecuteResult { installed })
     }
 
-    async fn install_from_source(
+    /// Materialize, record and link one downloaded and unpacked bottle.
+    /// Returns whether the formula counts as installed; failures go to
+    /// `error`.
+    #[allow(clippy::too_many_arguments)]
+    fn finish_bottle_install(
         &mut self,
         item: &PlannedInstall,
-        build_plan: &BuildPlan,
+        store_entry: &Path,
+        download: &DownloadResult,
         link: bool,
+        lazy_names: &BTreeSet<String>,
         report: &impl Fn(InstallProgress),
-    ) -> Result<(), Error> {
-        let install_name = &item.install_name;
-        let formula_name = &item.formula.name;
-        let version = item.formula.effective_version();
-
-        let ruby_source_path =
+        error: &mut Option<Error>,
+    ) -> bool {
+        let InstallMethod::Bottle(ref bottle) = item.method else {
+            unreachable!()
+        };
+        let processed_name = item.install_name.clone();
+        let materialized_name = item.formula.name.clone();
+        let progress_id = item.progress_id();
+        let processed_version = item.formula.effective_version();
+        let processed_store_key = bottle.sha256.clone();
+
+        if lazy_names.contains(&processed_name) {
+            match self.install_lazily(
+                &processed_name,
+                &materialized_name,
+                &processed_version,
+                &processed_store_key,
+                store_entry,
+            ) {
+                Ok(true) => {
+                    report(InstallProgress::UnpackCompleted {
+                        id: progress_id.clone(),
+                        name: materialized_name.clone(),
+                    });
+                    report(InstallProgress::InstallCompleted {
+                        id: progress_id.clone(),
+                        name: materialized_name.clone(),
+                    });
+                    return true;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    *error = Some(e.with_formula(&processed_name));
+                    return false;
+                }
+            }
+        }
+
+        let keg_path =
+            match self
+                .cellar
+                .materialize(&materialized_name, &processed_version, store_entry)
+            {
+                Ok(path) => path,
+                Err(e) => {
+                    *error = Some(e.with_formula(&processed_name));
+                    return false;
+                }
+            };
+
+        report(InstallProgress::UnpackCompleted {
+            id: progress_id.clone(),
+            name: materialized_name.clone(),
+        });
+
+        if let Err(e) = self.record_install_with_retention(
+            &processed_name,
+            &processed_version,
+            &processed_store_key,
+        ) {
+            Self::cleanup_materialized(&self.cellar, &materialized_name, &processed_version);
+            *error = Some(e.with_formula(&processed_name));
+            return false;
+        }
+
+        self.discard_lazy_shims(&processed_name);
+
+        // Under --strict the keg stays installed, but the
+        // install as a whole fails.
+        if let Err(e) = self
+            .linker
+            .get()
+            .and_then(|linker| linker.link_opt(&keg_path))
+            && let Err(e) = self.warn(format!(
+                "failed to create opt link for {processed_name}: {e}"
+            ))
+        {
+            error.get_or_insert(e.with_formula(&processed_name));
+        }
+
+        let should_link = link && !item.formula.is_keg_only();
+
+        let linked_files = if should_link {
+            report(InstallProgress::LinkStarted {
+                id: progress_id.clone(),
+                name: materialized_name.clone(),
+            });
+            match self.link_keg_for(&processed_name, &keg_path) {
+                Ok(files) => {
+                    report(InstallProgress::LinkCompleted {
+                        id: progress_id.clone(),
+                        name: materialized_name.clone(),
+                    });
+                    files
+                }
+                Err(e) => {
+                    let _ = self
+                        .linker
+                        .get()
+                        .and_then(|linker| linker.unlink_keg(&keg_path));
+                    *error = Some(e.with_formula(&processed_name));
+                    report(InstallProgress::InstallCompleted {
+                        id: progress_id.clone(),
+                        name: materialized_name.clone(),
+                    });
+                    return true;
+                }
+            }
+        } else {
+            if link && let Some(reason) = item.formula.keg_only_reason() {
+                report(InstallProgress::LinkSkipped {
+                    id: progress_id.clone(),
+                    name: materialized_name.clone(),
+                    reason,
+                });
+            }
+            Vec::new()
+        };
+
+        if !linked_files.is_empty()
+            && let Ok(tx) = self.db.transaction()
+        {
+            let mut ok = true;
+            for linked in &linked_files {
+                if tx
+                    .record_linked_file(
+                        &processed_name,
+                        &processed_version,
+                        &linked.link_path.to_string_lossy(),
+                        &linked.target_path.to_string_lossy(),
+                    )
+                    .is_err()
+                {
+                    ok = false;
+                    break;
+                }
+            }
+            if ok {
+                let _ = tx.commit();
+            }
+        }
+
+        if self.config.security.verification_report
+            && let Err(e) = VerificationReport::new(
+                &processed_name,
+                &processed_version,
+                vec![BlobVerification::new(
+                    &bottle.url,
+                    &processed_store_key,
+                    &download.blob_path,
+                    download.source.as_deref(),
+                )],
+            )
+            .write(&keg_path)
+            && let Err(e) = self.warn(format!(
+                "failed to write verification report for {processed_name}: {e}"
+            ))
+        {
+            error.get_or_insert(e.with_formula(&processed_name));
+        }
+
+        self.run_post_link_hook(
+            &processed_name,
+            &processed_version,
+            &keg_path,
+            &linked_files,
+        );
+
+        report(InstallProgress::InstallCompleted {
+            id: progress_id.clone(),
+            name: materialized_name.clone(),
+        });
+
+        true
+    }
+
+    /// Report a landed download for each formula sharing it and run their
+    /// post-download hooks. Returns the formulas that may go on to unpack.
+    fn accept_download(
+        &self,
+        download: &DownloadResult,
+        group: &[usize],
+        items: &[PlannedInstall],
+        report: &impl Fn(InstallProgress),
+        error: &mut Option<Error>,
+    ) -> Vec<usize> {
+        let mut accepted = Vec::with_capacity(group.len());
+        for &idx in group {
+            let item = &items[idx];
+            let progress_id = item.progress_id();
+            if idx != group[0] {
+                report(InstallProgress::DownloadStarted {
+                    id: progress_id.clone(),
+                    name: item.formula.name.clone(),
+                    total_bytes: None,
+                });
+                report(InstallProgress::DownloadCompleted {
+                    id: progress_id.clone(),
+                    name: item.formula.name.clone(),
+                    total_bytes: 0,
+                });
+            }
+
+            if let Err(e) = self.hooks.get().and_then(|hooks| {
+                hooks.run(
+                    HookPhase::PostDownload,
+                    &self.prefix,
+                    serde_json::json!({
+                        "formula": item.install_name,
+                        "version": item.formula.effective_version(),
+                        "sha256": download.sha256,
+                        "blob": download.blob_path,
+                    }),
+                )
+            }) {
+                *error = Some(e);
+                continue;
+            }
+
+            report(InstallProgress::UnpackStarted {
+                id: progress_id,
+                name: item.formula.name.clone(),
+            });
+            accepted.push(idx);
+        }
+        accepted
+    }
+
+    /// Notable metadata changes for plan items that replace an installed
+    /// version, e.g. a license change or new runtime dependencies.
+    pub fn upgrade_notices(&self, plan: &InstallPlan) -> Vec<UpgradeNotice> {
+        plan.items
+            .iter()
+            .filter_map(|item| {
+                let installed = self.db.get_installed(&item.install_name)?;
+                let to_version = item.formula.effective_version();
+                if installed.version == to_version {
+                    return None;
+                }
+
+                let previous = self
+                    .db
+                    .keg_metadata(&item.install_name)
+                    .filter(|(version, _)| *version == installed.version)
+                    .and_then(|(_, json)| serde_json::from_str::<KegMetadata>(&json).ok());
+                let receipt_deps = receipt_dependencies(
+                    &self
+                        .cellar
+                        .keg_path(formula_token(&item.install_name), &installed.version),
+                );
+                let changes = metadata_changes(previous.as_ref(), receipt_deps, &item.formula);
+
+                (!changes.is_empty()).then(|| UpgradeNotice {
+                    name: item.install_name.clone(),
+                    from_version: installed.version,
+                    to_version,
+                    changes,
+                })
+            })
+            .collect()
+    }
+
+    /// Store the metadata of every item that made it in, plus the changes
+    /// its upgrade brought, for the next upgrade and for later audit.
+    fn record_metadata(
+        &self,
+        metadata: &[(String, String, KegMetadata)],
+        notices: &[UpgradeNotice],
+    ) {
+        for (name, version, keg_metadata) in metadata {
+            if self
+                .db
+                .get_installed(name)
+                .is_none_or(|keg| keg.version != *version)
+            {
+                continue;
+            }
+
+            // Measured now, so listing sizes doesn't walk every keg later.
+            self.record_keg_size(name, version);
+            self.record_keg_manifest(name, version);
+            let mut recorded = serde_json::to_string(keg_metadata)
+                .map_err(|e| Error::db("failed to serialize keg metadata").with_source(e))
+                .and_then(|json| self.db.set_keg_metadata(name, version, &json))
+                .and_then(|()| self.db.set_dependencies(name, &keg_metadata.dependencies));
+            for notice in notices.iter().filter(|notice| notice.name == *name) {
+                for change in &notice.changes {
+                    recorded = recorded.and_then(|()| {
+                        self.db.record_upgrade_change(
+                            name,
+                            &notice.from_version,
+                            &notice.to_version,
+                            &change.to_string(),
+                        )
+                    });
+                }
+            }
+            if let Err(e) = recorded {
+                eprintln!("warning: failed to record metadata for {name}: {e}");
+            }
+        }
+    }
+
+    /// Refresh a stale formula index on its own connection while a plan with
+    /// many downloads runs, so the next command sees current metadata. The
+    /// refresh is cancelled when the returned handle is dropped.
+    fn spawn_index_refresh(&self, requests: &[DownloadRequest]) -> Option<AbortOnDrop> {
+        let index = self.formula_index().ok()??;
+        let downloader = self.downloader.get().ok()?;
+        let downloads = requests
+            .iter()
+            .filter(|request| downloader.cached_blob_size(&request.sha256).is_none())
+            .count();
+        if downloads < BACKGROUND_REFRESH_MIN_DOWNLOADS
+            || index.pin().is_some()
+            || !index.older_than(BACKGROUND_REFRESH_AGE_SECS)
+        {
+            return None;
+        }
+        let path = index.path()?;
+        let tag = self.bottle_tag()?;
+        let api = self.api_client.uncached();
+        Some(AbortOnDrop(tokio::spawn(async move {
+            // Best effort: a failed refresh leaves the index as it was.
+            if let Ok(mut index) = FormulaIndex::open(&path) {
+                let _ = refresh_in_background(&api, &mut index, &tag).await;
+            }
+        })))
+    }
+
+    /// Count a finished download (or blob cache hit) towards `zb stats`.
+    fn record_download_stats(&self, download: &DownloadResult) {
+        if !self.config.analytics.enabled {
+            return;
+        }
+        let bytes = blob_size(&download.blob_path);
+        let recorded = match &download.source {
+            Some(url) => {
+                let host = reqwest::Url::parse(url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_string))
+                    .unwrap_or_else(|| "unknown".to_string());
+                self.db
+                    .record_download(&host, bytes, download.elapsed.as_millis() as u64)
+            }
+            None => self.db.record_cache_hit("blob", bytes),
+        };
+        if let Err(e) = recorded {
+            eprintln!("warning: failed to record download stats: {e}");
+        }
+    }
+
+    /// Download and cache statistics for `zb stats`.
+    pub fn download_stats(&self) -> Result<DownloadStats, Error> {
+        self.db.download_stats()
+    }
+
+    pub fn reset_download_stats(&self) -> Result<(), Error> {
+        self.db.reset_download_stats()
+    }
+
+    /// Metadata changes recorded on past upgrades of `name`, oldest first.
+    pub fn upgrade_history(&self, name: &str) -> Result<Vec<UpgradeRecord>, Error> {
+        self.db.upgrade_history(name)
+    }
+
+    /// Formulas in `plan` under a license the config requires accepting,
+    /// minus those whose license was already accepted on an earlier install.
+    pub fn licenses_to_accept(&self, plan: &InstallPlan) -> Vec<LicenseRequirement> {
+        plan.items
+            .iter()
+            .filter_map(|item| {
+                let license = item.formula.license.as_ref()?;
+                (self.config.license_requires_acceptance(license)
+                    && !self.db.has_accepted_license(&item.install_name, license))
+                .then(|| LicenseRequirement {
+                    name: item.install_name.clone(),
+                    version: item.formula.effective_version(),
+                    license: license.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Record that the user accepted each requirement, and how.
+    pub fn accept_licenses(
+        &mut self,
+        requirements: &[LicenseRequirement],
+        accepted_via: &str,
+    ) -> Result<(), Error> {
+        for requirement in requirements {
+            self.db.record_license_acceptance(
+                &requirement.name,
+                &requirement.version,
+                &requirement.license,
+                accepted_via,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Record why `name` is installed, shown by `zb list --notes` and `zb info`.
+    pub fn set_install_note(&self, name: &str, note: &str) -> Result<(), Error> {
+        if self.db.get_installed(name).is_none() {
+            return Err(Error::NotInstalled {
+                name: name.to_string(),
+            });
+        }
+        self.db.set_install_note(name, note)
+    }
+
+    pub fn install_note(&self, name: &str) -> Option<String> {
+        self.db.install_note(name)
+    }
+
+    pub fn license_acceptances(&self, name: &str) -> Result<Vec<LicenseAcceptance>, Error> {
+        self.db.license_acceptances(name)
+    }
+
+    /// Everything `zb report` shows about the installed environment.
+    ///
+    /// Checking for newer versions needs the API; when it can't be reached
+    /// the report is still produced, just without outdated status.
+    pub async fn environment_report(
+        &self,
+        history_limit: usize,
+    ) -> Result<EnvironmentReport, Error> {
+        let installed = self.db.list_installed()?;
+        let (outdated, outdated_known) = match self.outdated().await {
+            Ok(outdated) => (outdated, true),
+            Err(_) => (Vec::new(), false),
+        };
+
+        let formulas = installed
+            .into_iter()
+            .map(|keg| {
+                let license = self
+                    .db
+                    .keg_metadata(&keg.name)
+                    .filter(|(version, _)| *version == keg.version)
+                    .and_then(|(_, json)| serde_json::from_str::<KegMetadata>(&json).ok())
+                    .and_then(|metadata| metadata.license);
+                ReportedFormula {
+                    license,
+                    size: self.keg_size(&keg.name, &keg.version),
+                    source: self.db.store_source(&keg.store_key),
+                    note: self.db.install_note(&keg.name),
+                    current_version: outdated
+                        .iter()
+                        .find(|item| item.name == keg.name)
+                        .map(|item| item.current_version.clone()),
+                    name: keg.name,
+                    version: keg.version,
+                    installed_at: keg.installed_at,
+                }
+            })
+            .collect();
+
+        Ok(EnvironmentReport {
+            platform: crate::build::host_bottle_tag(),
+            prefix: self.prefix.clone(),
+            formulas,
+            outdated_known,
+            history: self.db.recent_upgrade_history(history_limit)?,
+        })
+    }
+
+    /// Current metadata for `name` from the API (or the pinned snapshot), or
+    /// from its tap.
+    pub async fn fetch_formula(&self, name: &str) -> Result<Formula, Error> {
+        self.get_formula(name).await
+    }
+
+    /// Formulas in the runtime dependency closure of `names`, plus, with
+    /// `include_build`, the build dependencies of `names` and their closure.
+    pub async fn dependency_formulas(
+        &self,
+        names: &[String],
+        include_build: bool,
+    ) -> Result<BTreeMap<String, Formula>, Error> {
+        let (mut formulas, _) = self.fetch_all_formulas(names).await?;
+        if include_build {
+            let build: Vec<String> = names
+                .iter()
+                .filter_map(|name| formulas.get(name))
+                .flat_map(Formula::all_build_dependencies)
+                .filter(|dep| !formulas.contains_key(dep))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            if !build.is_empty() {
+                formulas.extend(self.fetch_all_formulas(&build).await?.0);
+            }
+        }
+        Ok(formulas)
+    }
+
+    /// URL that served the installed bottle of `name`, when it was downloaded
+    /// after sources started being recorded.
+    pub fn bottle_source(&self, name: &str) -> Option<String> {
+        let keg = self.db.get_installed(name)?;
+        self.db.store_source(&keg.store_key)
+    }
+
+    fn cleanup_failed_install(
+        linker: &Linker,
+        cellar: &Cellar,
+        name: &str,
+        version: &str,
+        keg_path: &Path,
+        unlink: bool,
+    ) {
+        if unlink && let Err(e) = linker.unlink_keg(keg_path) {
+            eprintln!(
+                "warning: failed to clean up links for {}@{} after install error: {}",
+                name, version, e
+            );
+        }
+
+        if let Err(e) = cellar.remove_keg(name, version) {
+            eprintln!(
+                "warning: failed to remove keg for {}@{} after install error: {}",
+                name, version, e
+            );
+        }
+    }
+
+    async fn install_from_source(
+        &mut self,
+        item: &PlannedInstall,
+        build_plan: &BuildPlan,
+        link: bool,
+        report: &impl Fn(InstallProgress),
+    ) -> Result<(), Error> {
+        let install_name = &item.install_name;
+        let formula_name = &item.formula.name;
+        let version = item.formula.effective_version();
+        let progress_id = item.progress_id();
+
+        let ruby_source_path =
             item.formula
                 .ruby_source_path
                 .as_deref()
@@ -560,12 +2162,17 @@ impl Installer {
             .await?;
 
         let mut installed_deps = std::collections::HashMap::new();
-        for dep_name in &build_plan.runtime_dependencies {
+        for dep_name in build_plan
+            .runtime_dependencies
+            .iter()
+            .chain(&build_plan.build_dependencies)
+        {
             if let Some(keg) = self.db.get_installed(dep_name) {
                 installed_deps.insert(
                     dep_name.clone(),
                     crate::build::DepInfo {
                         cellar_path: dependency_cellar_path(&self.cellar, &keg.name, &keg.version),
+                        keg_only: !self.db.is_linked(&keg.name),
                     },
                 );
             }
@@ -575,7 +2182,8 @@ impl Installer {
         let previous_keg_backup =
             Self::backup_existing_source_keg(&keg_path, formula_name, &version)?;
 
-        let executor = crate::build::BuildExecutor::new(self.prefix.clone());
+        let executor = crate::build::BuildExecutor::new(self.prefix.clone())
+            .with_proxy(self.api_client.proxy().cloned());
         if let Err(build_err) = executor
             .execute(build_plan, &formula_rb, &installed_deps)
             .await
@@ -596,39 +2204,35 @@ impl Installer {
         }
 
         report(InstallProgress::UnpackCompleted {
+            id: progress_id.clone(),
             name: formula_name.clone(),
         });
 
         let store_key = format!("source:{formula_name}:{version}");
 
-        let tx = self.db.transaction().inspect_err(|_| {
-            Self::cleanup_materialized(&self.cellar, formula_name, &version);
-        })?;
-
-        if let Err(e) = tx.record_install(install_name, &version, &store_key) {
-            drop(tx);
+        if let Err(e) = self.record_install_with_retention(install_name, &version, &store_key) {
             Self::cleanup_materialized(&self.cellar, formula_name, &version);
             return Err(e);
         }
 
-        if let Err(e) = tx.commit() {
-            Self::cleanup_materialized(&self.cellar, formula_name, &version);
-            return Err(e);
-        }
+        self.discard_lazy_shims(install_name);
 
-        if let Err(e) = self.linker.link_opt(&keg_path) {
-            eprintln!("warning: failed to create opt link for {install_name}: {e}");
+        if let Err(e) = self.linker.get()?.link_opt(&keg_path) {
+            self.warn(format!("failed to create opt link for {install_name}: {e}"))?;
         }
 
         let should_link = link && !item.formula.is_keg_only();
+        let mut linked_files = Vec::new();
 
         if should_link {
             report(InstallProgress::LinkStarted {
+                id: progress_id.clone(),
                 name: formula_name.clone(),
             });
-            match self.linker.link_keg(&keg_path) {
+            match self.link_keg_for(install_name, &keg_path) {
                 Ok(files) => {
                     report(InstallProgress::LinkCompleted {
+                        id: progress_id.clone(),
                         name: formula_name.clone(),
                     });
                     if !files.is_empty()
@@ -653,33 +2257,60 @@ impl Installer {
                             let _ = tx.commit();
                         }
                     }
+                    linked_files = files;
                 }
                 Err(e) => {
-                    let _ = self.linker.unlink_keg(&keg_path);
+                    let _ = self.linker.get()?.unlink_keg(&keg_path);
                     report(InstallProgress::InstallCompleted {
+                        id: progress_id.clone(),
                         name: formula_name.clone(),
                     });
                     return Err(e);
                 }
             }
-        } else if link && item.formula.is_keg_only() {
-            let reason = match &item.formula.keg_only {
-                zb_core::KegOnly::Reason(s) => s.clone(),
-                _ if item.formula.name.contains('@') => "versioned formula".to_string(),
-                _ => "keg-only formula".to_string(),
-            };
+        } else if link && let Some(reason) = item.formula.keg_only_reason() {
             report(InstallProgress::LinkSkipped {
+                id: progress_id.clone(),
                 name: formula_name.clone(),
                 reason,
             });
         }
 
+        self.run_post_link_hook(install_name, &version, &keg_path, &linked_files);
+
         report(InstallProgress::InstallCompleted {
+            id: progress_id.clone(),
             name: formula_name.clone(),
         });
         Ok(())
     }
 
+    /// Post-link plugins are advisory: failures are reported as warnings only.
+    fn run_post_link_hook(
+        &self,
+        name: &str,
+        version: &str,
+        keg_path: &Path,
+        linked_files: &[LinkedFile],
+    ) {
+        let linked: Vec<&Path> = linked_files
+            .iter()
+            .map(|file| file.link_path.as_path())
+            .collect();
+        let _ = self.hooks.get().and_then(|hooks| {
+            hooks.run(
+                HookPhase::PostLink,
+                &self.prefix,
+                serde_json::json!({
+                    "formula": name,
+                    "version": version,
+                    "keg": keg_path,
+                    "linked": linked,
+                }),
+            )
+        });
+    }
+
     fn backup_existing_source_keg(
         keg_path: &Path,
         formula_name: &str,
@@ -691,19 +2322,21 @@ impl Installer {
 
         let backup_path = Self::source_keg_backup_path(keg_path);
         if backup_path.exists() {
-            fs::remove_dir_all(&backup_path).map_err(|e| Error::StoreCorruption {
-                message: format!(
-                    "failed to remove stale source-build backup for '{}@{}': {}",
-                    formula_name, version, e
-                ),
+            fs::remove_dir_all(&backup_path).map_err(|e| {
+                Error::link(format!(
+                    "failed to remove stale source-build backup for {version}"
+                ))
+                .with_formula(formula_name)
+                .with_path(&backup_path)
+                .with_source(e)
             })?;
         }
 
-        fs::rename(keg_path, &backup_path).map_err(|e| Error::StoreCorruption {
-            message: format!(
-                "failed to backup existing keg for '{}@{}': {}",
-                formula_name, version, e
-            ),
+        fs::rename(keg_path, &backup_path).map_err(|e| {
+            Error::link(format!("failed to backup existing keg for {version}"))
+                .with_formula(formula_name)
+                .with_path(keg_path)
+                .with_source(e)
         })?;
 
         Ok(Some(backup_path))
@@ -716,19 +2349,21 @@ impl Installer {
         version: &str,
     ) -> Result<(), Error> {
         if keg_path.exists() {
-            fs::remove_dir_all(keg_path).map_err(|e| Error::StoreCorruption {
-                message: format!(
-                    "failed to remove failed source-build output for '{}@{}': {}",
-                    formula_name, version, e
-                ),
+            fs::remove_dir_all(keg_path).map_err(|e| {
+                Error::link(format!(
+                    "failed to remove failed source-build output for {version}"
+                ))
+                .with_formula(formula_name)
+                .with_path(keg_path)
+                .with_source(e)
             })?;
         }
 
-        fs::rename(backup_path, keg_path).map_err(|e| Error::StoreCorruption {
-            message: format!(
-                "failed to restore previous keg for '{}@{}': {}",
-                formula_name, version, e
-            ),
+        fs::rename(backup_path, keg_path).map_err(|e| {
+            Error::link(format!("failed to restore previous keg for {version}"))
+                .with_formula(formula_name)
+                .with_path(keg_path)
+                .with_source(e)
         })
     }
 
@@ -741,11 +2376,13 @@ impl Installer {
             return Ok(());
         }
 
-        fs::remove_dir_all(backup_path).map_err(|e| Error::StoreCorruption {
-            message: format!(
-                "failed to remove source-build backup for '{}@{}': {}",
-                formula_name, version, e
-            ),
+        fs::remove_dir_all(backup_path).map_err(|e| {
+            Error::link(format!(
+                "failed to remove source-build backup for {version}"
+            ))
+            .with_formula(formula_name)
+            .with_path(backup_path)
+            .with_source(e)
         })
     }
 
@@ -763,522 +2400,4547 @@ impl Installer {
     }
 
     /// Remove a materialized keg that was never registered in the database.
-    fn cleanup_materialized(cellar: &Cellar, name: &str, version: &str) {
-        if let Err(e) = cellar.remove_keg(name, version) {
-            eprintln!(
-                "warning: failed to remove keg for {}@{} after install error: {}",
-                name, version, e
-            );
-        }
-    }
-
-    /// Convenience method to plan and execute in one call
-    pub async fn install(&mut self, names: &[String], link: bool) -> Result<ExecuteResult, Error> {
-        let (casks, formulas): (Vec<_>, Vec<_>) = names
-            .iter()
-            .cloned()
-            .partition(|name| name.starts_with("cask:"));
+    /// Record `name` as installed at `version`. The version it replaces is
+    /// unlinked and, when the formula's `retain_versions` policy asks for it,
+    /// retained; retained versions beyond the limit are removed.
+    fn record_install_with_retention(
+        &mut self,
+        name: &str,
+        version: &str,
+        store_key: &str,
+    ) -> Result<(), Error> {
+        let keep = self.config.retain_versions(name);
+        let previous = self.db.get_installed(name);
+        let mut retained: Vec<String> = self
+            .db
+            .retained_kegs(name)?
+            .into_iter()
+            .map(|keg| keg.version)
+            .filter(|retained| retained != version)
+            .collect();
 
-        let mut installed = 0usize;
+        let superseded = previous.filter(|previous| previous.version != version);
 
-        if !formulas.is_empty() {
-            let plan = self.plan(&formulas).await?;
-            installed += self.execute(plan, link).await?.installed;
+        let tx = self.db.transaction()?;
+        tx.record_install(name, version, store_key)?;
+        // Reinstalling a retained version makes it the current one again.
+        tx.release_retained(name, version)?;
+        if keep > 0
+            && let Some(previous) = &superseded
+        {
+            tx.retain_keg(name, &previous.version, &previous.store_key)?;
+            retained.retain(|retained| *retained != previous.version);
+            retained.insert(0, previous.version.clone());
         }
-
-        if !casks.is_empty() {
-            installed += self.install_casks(&casks, link).await?.installed;
+        let pruned = retained.split_off(keep.min(retained.len()));
+        for old_version in &pruned {
+            tx.release_retained(name, old_version)?;
         }
+        tx.commit()?;
 
-        Ok(ExecuteResult { installed })
+        // The superseded keg stays on disk (retained or left for cleanup) but
+        // must not keep its links, or linking the new keg would conflict.
+        if let Some(previous) = superseded {
+            let keg_path = self.cellar.keg_path(formula_token(name), &previous.version);
+            if let Err(e) = self.linker.get()?.unlink_keg(&keg_path) {
+                eprintln!("warning: failed to unlink {name}@{}: {e}", previous.version);
+            }
+        }
+
+        for old_version in pruned {
+            self.remove_superseded_keg(name, &old_version);
+        }
+        Ok(())
     }
 
-    pub async fn install_casks(
+    /// Fresh, linkable bottle installs that nothing else in the plan depends
+    /// on. Only these can be installed lazily: a dependency has to be in the
+    /// Cellar for its dependents' load paths to resolve.
+    fn lazy_candidates(&self, items: &[PlannedInstall]) -> BTreeSet<String> {
+        if self.lazy.is_none() {
+            return BTreeSet::new();
+        }
+        let dependencies: BTreeSet<&str> = items
+            .iter()
+            .flat_map(|item| item.formula.dependencies.iter().map(String::as_str))
+            .collect();
+        items
+            .iter()
+            .filter(|item| {
+                !dependencies.contains(item.install_name.as_str())
+                    && !dependencies.contains(item.formula.name.as_str())
+                    && !item.formula.is_keg_only()
+                    && self.db.get_installed(&item.install_name).is_none()
+            })
+            .map(|item| item.install_name.clone())
+            .collect()
+    }
+
+    /// Record a bottle install without materializing the keg, writing a
+    /// launcher shim for each executable. Returns `false` when the bottle has
+    /// no executables or a shim would replace someone else's file; the keg
+    /// is then materialized as usual.
+    fn install_lazily(
         &mut self,
-        names: &[String],
-        link: bool,
-    ) -> Result<ExecuteResult, Error> {
-        let mut installed = 0usize;
-        for name in names {
-            let token = name
-                .strip_prefix("cask:")
-                .expect("install_casks expects cask: prefixed names");
-            self.install_single_cask(token, link).await?;
-            installed += 1;
+        name: &str,
+        keg_name: &str,
+        version: &str,
+        store_key: &str,
+        store_entry: &Path,
+    ) -> Result<bool, Error> {
+        let Some(launcher) = self.lazy.clone() else {
+            return Ok(false);
+        };
+        let content = find_bottle_content(store_entry, keg_name, version)?;
+        let bins = bin_names(&content);
+        let bin_dir = self.prefix.join("bin");
+        let taken = |bin: &String| {
+            let path = bin_dir.join(bin);
+            path.symlink_metadata().is_ok() && !is_shim(&path)
+        };
+        if bins.is_empty() || bins.iter().any(taken) {
+            return Ok(false);
         }
-        Ok(ExecuteResult { installed })
+
+        fs::create_dir_all(&bin_dir).map_err(|e| {
+            Error::link("failed to create directory")
+                .with_path(&bin_dir)
+                .with_source(e)
+        })?;
+
+        let keg_bin = self.cellar.keg_path(keg_name, version).join("bin");
+        let shims: Vec<PathBuf> = bins.iter().map(|bin| bin_dir.join(bin)).collect();
+        let result = (|| {
+            for (bin, shim) in bins.iter().zip(&shims) {
+                write_shim(shim, &shim_script(&launcher, name, &keg_bin.join(bin)))?;
+            }
+            let tx = self.db.transaction()?;
+            tx.record_install(name, version, store_key)?;
+            for shim in &shims {
+                tx.record_lazy_shim(name, version, &shim.to_string_lossy())?;
+            }
+            tx.commit()
+        })();
+
+        if let Err(e) = result {
+            for shim in &shims {
+                if is_shim(shim) {
+                    let _ = fs::remove_file(shim);
+                }
+            }
+            return Err(e);
+        }
+        Ok(true)
     }
 
-    /// Uninstall a formula
-    pub fn uninstall(&mut self, name: &str) -> Result<(), Error> {
-        // Check if installed
+    /// Materialize and link a keg that was installed lazily, replacing its
+    /// launcher shims. Returns `false` if the keg was already materialized.
+    pub fn materialize_lazy(&mut self, name: &str) -> Result<bool, Error> {
         let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
             name: name.to_string(),
         })?;
-        let keg_name = formula_token(&installed.name);
-
-        // Unlink executables
-        let keg_path = self.cellar.keg_path(keg_name, &installed.version);
-        self.linker.unlink_keg(&keg_path)?;
+        if self.db.lazy_shims(name)?.is_empty() {
+            return Ok(false);
+        }
 
-        // Remove from database (decrements store ref)
-        {
-            let tx = self.db.transaction()?;
-            tx.record_uninstall(name)?;
-            tx.commit()?;
+        // Several shims of the same keg may start at once; the first one to
+        // get the lock does the work.
+        let _lock = self.store.get()?.lock_entry(&installed.store_key)?;
+        if self.db.lazy_shims(name)?.is_empty() {
+            return Ok(false);
         }
 
-        // Remove cellar entry
+        let keg_name = formula_token(&installed.name);
+        // Anything already there was left by an interrupted materialization.
         self.cellar.remove_keg(keg_name, &installed.version)?;
+        let keg_path = self.cellar.materialize(
+            keg_name,
+            &installed.version,
+            &self.store.get()?.unpacked_entry(&installed.store_key)?,
+        )?;
+
+        self.discard_lazy_shims(name);
+        let linked_files = self.link_keg_for(name, &keg_path)?;
+        let tx = self.db.transaction()?;
+        for linked in &linked_files {
+            tx.record_linked_file(
+                name,
+                &installed.version,
+                &linked.link_path.to_string_lossy(),
+                &linked.target_path.to_string_lossy(),
+            )?;
+        }
+        tx.commit()?;
 
-        Ok(())
+        self.run_post_link_hook(name, &installed.version, &keg_path, &linked_files);
+        Ok(true)
     }
 
-    /// Garbage collect unreferenced store entries
-    pub fn gc(&mut self) -> Result<Vec<String>, Error> {
-        let unreferenced = self.db.get_unreferenced_store_keys()?;
-        let mut removed = Vec::new();
+    /// Replace `name`'s keg with a fresh copy, e.g. after files in it were
+    /// damaged or deleted. The keg is unlinked, removed and materialized again
+    /// from its store entry, or from the cached bottle when the entry is gone;
+    /// the bottle is only downloaded when neither is left. Links are restored
+    /// if the keg had any.
+    pub async fn reinstall(&mut self, name: &str) -> Result<ReinstallSource, Error> {
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        let invalid = |message: String| Err(Error::InvalidArgument { message });
+        if installed.name.starts_with("cask:") {
+            return invalid(format!(
+                "{name} is a cask; run `zb install {name}` again to restage it"
+            ));
+        }
+        if self.is_lazy(name) {
+            return invalid(format!(
+                "{name} is installed lazily and not materialized yet, so there is nothing to repair"
+            ));
+        }
+        if installed.store_key.starts_with("source:") {
+            return invalid(format!(
+                "{name} was built from source; rebuild it with `zb install --build-from-source {name}`"
+            ));
+        }
+
+        let linked = self.db.is_linked(name);
+        let keg_name = formula_token(&installed.name);
+        let store_lock = self.store.get()?.lock_store(false)?;
+        let (source, entry) = if self.store.get()?.has_entry(&installed.store_key) {
+            let entry = self.store.get()?.unpacked_entry(&installed.store_key)?;
+            (ReinstallSource::Store, Some(entry))
+        } else if let Some(blob) = self.downloader.get()?.cached_blob(&installed.store_key) {
+            let entry = self
+                .store
+                .get()?
+                .ensure_entry(&installed.store_key, &blob)?;
+            (ReinstallSource::CachedBottle, Some(entry))
+        } else {
+            (ReinstallSource::Download, None)
+        };
+
+        // Resolve the download before touching the keg, so a formula that
+        // moved on to another version is left as it is.
+        let plan = match entry {
+            Some(_) => None,
+            None => {
+                let formula = self.get_formula(name).await?;
+                if formula.effective_version() != installed.version {
+                    return invalid(format!(
+                        "{name} {} is no longer available to download; `zb upgrade {name}` installs {}",
+                        installed.version,
+                        formula.effective_version()
+                    ));
+                }
+                let bottle = self.select_bottle(&formula)?;
+                Some(InstallPlan {
+                    items: vec![PlannedInstall {
+                        install_name: name.to_string(),
+                        formula,
+                        method: InstallMethod::Bottle(bottle),
+                    }],
+                    metadata_fetched_at: None,
+                })
+            }
+        };
 
-        for store_key in unreferenced {
-            self.store.remove_entry(&store_key)?;
-            self.db.delete_store_ref(&store_key)?;
-            removed.push(store_key);
+        let keg_path = self.cellar.keg_path(keg_name, &installed.version);
+        self.linker.get()?.unlink_keg(&keg_path)?;
+        self.cellar.remove_keg(keg_name, &installed.version)?;
+        let recorded = self.db.linked_files(name)?;
+        let tx = self.db.transaction()?;
+        for (link, _) in &recorded {
+            tx.remove_linked_file(name, link)?;
         }
+        tx.commit()?;
 
-        Ok(removed)
-    }
+        let Some(entry) = entry else {
+            drop(store_lock);
+            self.execute(plan.expect("planned a download"), linked)
+                .await?;
+            return Ok(source);
+        };
 
-    /// Check if a formula is installed
-    pub fn is_installed(&self, name: &str) -> bool {
-        self.db.get_installed(name).is_some()
+        let keg_path = self
+            .cellar
+            .materialize(keg_name, &installed.version, &entry)?;
+        self.linker.get()?.link_opt(&keg_path)?;
+        if linked {
+            let linked_files = self.link_keg_for(name, &keg_path)?;
+            let tx = self.db.transaction()?;
+            for file in &linked_files {
+                tx.record_linked_file(
+                    name,
+                    &installed.version,
+                    &file.link_path.to_string_lossy(),
+                    &file.target_path.to_string_lossy(),
+                )?;
+            }
+            tx.commit()?;
+            self.run_post_link_hook(name, &installed.version, &keg_path, &linked_files);
+        }
+        Ok(source)
     }
 
-    /// Get info about an installed formula
-    pub fn get_installed(&self, name: &str) -> Option<crate::storage::db::InstalledKeg> {
-        self.db.get_installed(name)
+    /// Whether `name` has files linked into the prefix.
+    pub fn is_linked(&self, name: &str) -> bool {
+        self.db.is_linked(name)
     }
 
-    /// List all installed formulas
-    pub fn list_installed(&self) -> Result<Vec<crate::storage::db::InstalledKeg>, Error> {
-        self.db.list_installed()
+    /// Disk space taken by the `version` keg of `name`, when it is in the Cellar.
+    ///
+    /// Sizes are recorded at install time; a keg without one is measured
+    /// once and recorded then, unless it hasn't been materialized yet.
+    pub fn keg_size(&self, name: &str, version: &str) -> Option<u64> {
+        let keg_path = self.cellar.keg_path(formula_token(name), version);
+        if !keg_path.is_dir() {
+            return None;
+        }
+        if let Some(bytes) = self.db.keg_size(name, version) {
+            return Some(bytes);
+        }
+        Some(self.record_keg_size(name, version))
     }
 
-    /// Get the path to a keg in the cellar
-    pub fn keg_path(&self, name: &str, version: &str) -> std::path::PathBuf {
-        self.cellar.keg_path(name, version)
+    /// Remember the state of every file in the `version` keg of `name`, so
+    /// an uninstall can tell what was changed since. Best effort.
+    fn record_keg_manifest(&self, name: &str, version: &str) {
+        if self.is_lazy(name) {
+            return;
+        }
+        let keg_path = self.cellar.keg_path(formula_token(name), version);
+        let _ = self
+            .db
+            .set_keg_manifest(name, version, &keg_file_states(&keg_path));
     }
-    async fn install_single_cask(&mut self, token: &str, link: bool) -> Result<(), Error> {
-        let cask_json = self.api_client.get_cask(token).await?;
-        let cask = resolve_cask(token, &cask_json)?;
-
-        let blob_path = self
-            .downloader
-            .download_single(
-                DownloadRequest {
-                    url: cask.url.clone(),
-                    sha256: cask.sha256.clone(),
-                    name: cask.install_name.clone(),
-                },
-                None,
-            )
-            .await?;
 
-        let extracted = self.store.ensure_entry(&cask.sha256, &blob_path)?;
-        let keg_path = self.cellar.keg_path(&cask.install_name, &cask.version);
-        let mut cleanup = FailedInstallGuard::new(
-            &self.linker,
-            &self.cellar,
-            &cask.install_name,
-            &cask.version,
-            &keg_path,
-            link,
-        );
+    /// Measure the `version` keg of `name` and remember its size. Best
+    /// effort: a failed write only costs another walk next time.
+    fn record_keg_size(&self, name: &str, version: &str) -> u64 {
+        let bytes = directory_size(&self.cellar.keg_path(formula_token(name), version));
+        if !self.is_lazy(name) {
+            let _ = self.db.set_keg_size(name, version, bytes);
+        }
+        bytes
+    }
 
-        stage_cask_binaries(&extracted, &keg_path, &cask)?;
+    /// Whether `name` was installed lazily and hasn't been used yet.
+    pub fn is_lazy(&self, name: &str) -> bool {
+        self.db
+            .lazy_shims(name)
+            .is_ok_and(|shims| !shims.is_empty())
+    }
 
-        let linked_files = if link {
-            self.linker.link_keg(&keg_path)?
-        } else {
-            Vec::new()
+    /// Remove the launcher shims of a lazily installed keg, e.g. because it
+    /// was just materialized or uninstalled.
+    fn discard_lazy_shims(&mut self, name: &str) {
+        let shims = match self.db.lazy_shims(name) {
+            Ok(shims) if !shims.is_empty() => shims,
+            _ => return,
         };
-
-        let tx = self.db.transaction()?;
-        tx.record_install(&cask.install_name, &cask.version, &cask.sha256)?;
-        for linked in &linked_files {
-            tx.record_linked_file(
-                &cask.install_name,
-                &cask.version,
-                &linked.link_path.to_string_lossy(),
-                &linked.target_path.to_string_lossy(),
-            )?;
+        for shim in &shims {
+            let path = Path::new(shim);
+            if is_shim(path) {
+                let _ = fs::remove_file(path);
+            }
+        }
+        let cleared = self.db.transaction().and_then(|tx| {
+            tx.clear_lazy_shims(name)?;
+            tx.commit()
+        });
+        if let Err(e) = cleared {
+            eprintln!("warning: failed to forget launcher shims for {name}: {e}");
         }
-        tx.commit()?;
-
-        cleanup.disarm();
-        Ok(())
     }
-}
-
-fn dependency_cellar_path(cellar: &Cellar, installed_name: &str, version: &str) -> String {
-    cellar
-        .keg_path(formula_token(installed_name), version)
-        .display()
-        .to_string()
-}
 
-struct FailedInstallGuard<'a> {
-    linker: &'a Linker,
-    cellar: &'a Cellar,
-    name: &'a str,
-    version: &'a str,
-    keg_path: &'a Path,
-    unlink: bool,
-    armed: bool,
-}
+    /// Link a keg the way the config asks for: plain symlinks, or exec shims
+    /// carrying the formula's env and the `bin` dirs of its keg-only
+    /// dependencies. Entries matching its `link_exclude` patterns are left
+    /// out and recorded, so they aren't mistaken for missing links later.
+    fn link_keg_for(&self, name: &str, keg_path: &Path) -> Result<Vec<LinkedFile>, Error> {
+        let exclude = self.config.link_exclude(name);
+        let linked = match self.config.link_mode(name) {
+            LinkMode::Symlink => self.linker.get()?.link_keg_with(keg_path, None, &exclude),
+            LinkMode::Shim => {
+                let env = ShimEnv {
+                    path: self.keg_only_dependency_bins(keg_path),
+                    vars: self.config.env(name),
+                };
+                self.linker
+                    .get()?
+                    .link_keg_with(keg_path, Some(&env), &exclude)
+            }
+        }
+        .map_err(|e| self.observe_conflicts(e))?;
 
-impl<'a> FailedInstallGuard<'a> {
-    fn new(
-        linker: &'a Linker,
-        cellar: &'a Cellar,
-        name: &'a str,
-        version: &'a str,
-        keg_path: &'a Path,
-        unlink: bool,
-    ) -> Self {
-        Self {
-            linker,
-            cellar,
-            name,
-            version,
-            keg_path,
-            unlink,
-            armed: true,
+        let excluded: Vec<PathBuf> = LinkExclusions::new(keg_path, &exclude)
+            .excluded_entries()
+            .into_iter()
+            .map(|entry| self.prefix.join(entry))
+            .collect();
+        if let Err(e) = self.db.record_link_exclusions(name, &excluded) {
+            eprintln!("warning: failed to record link exclusions for {name}: {e}");
         }
+        Ok(linked)
     }
 
-    fn disarm(&mut self) {
-        self.armed = false;
+    /// Prefix paths left unlinked for `name` by `link_exclude` patterns.
+    pub fn link_exclusions(&self, name: &str) -> Result<Vec<PathBuf>, Error> {
+        Ok(self
+            .db
+            .link_exclusions(name)?
+            .into_iter()
+            .map(PathBuf::from)
+            .collect())
     }
-}
 
-impl Drop for FailedInstallGuard<'_> {
-    fn drop(&mut self) {
-        if self.armed {
-            Installer::cleanup_failed_install(
-                self.linker,
-                self.cellar,
-                self.name,
-                self.version,
-                self.keg_path,
-                self.unlink,
-            );
+    /// Name the owners of files that blocked linking (our kegs or Homebrew's)
+    /// and remember them, so the prefix's ownership can be looked up later.
+    fn observe_conflicts(&self, err: Error) -> Error {
+        let Error::LinkConflict { mut conflicts } = err else {
+            return err;
+        };
+        for conflict in &mut conflicts {
+            if let Some(owner) = observed_owner(&conflict.path, self.cellar.dir()) {
+                conflict.owned_by = Some(owner);
+            }
+        }
+        if let Err(e) = self.db.record_prefix_owners(&conflicts) {
+            eprintln!("warning: failed to record owners of conflicting files: {e}");
         }
+        Error::LinkConflict { conflicts }
     }
-}
 
-fn stage_cask_binaries(
-    extracted_root: &Path,
-    keg_path: &Path,
-    cask: &crate::installer::cask::ResolvedCask,
-) -> Result<(), Error> {
-    let bin_dir = keg_path.join("bin");
-    fs::create_dir_all(&bin_dir).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to create cask bin dir: {e}"),
-    })?;
+    /// Paths in the prefix currently linked to `name`'s keg.
+    pub fn linked_files(&self, name: &str) -> Result<Vec<PathBuf>, Error> {
+        Ok(self
+            .db
+            .linked_files(name)?
+            .into_iter()
+            .map(|(link, _)| PathBuf::from(link))
+            .collect())
+    }
 
-    for binary in &cask.binaries {
-        let source = resolve_cask_source_path(extracted_root, cask, &binary.source)?;
-        if !source.exists() {
-            return Err(Error::InvalidArgument {
-                message: format!(
-                    "cask '{}' binary source '{}' not found in archive",
-                    cask.token, binary.source
-                ),
-            });
+    /// Shell exports that make the unlinked keg of `name` usable through its
+    /// opt link: `bin` and `sbin` on `PATH`, and compiler and pkg-config
+    /// flags for the libraries and headers it has.
+    pub fn keg_only_exports(&self, name: &str) -> Vec<String> {
+        let opt = self.prefix.join("opt").join(formula_token(name));
+        let has = |dir: &str| opt.join(dir).is_dir();
+        let mut exports = Vec::new();
+        for dir in ["bin", "sbin"].into_iter().filter(|dir| has(dir)) {
+            exports.push(format!("export PATH=\"{}:$PATH\"", opt.join(dir).display()));
         }
-
-        let target = bin_dir.join(&binary.target);
-        if target.exists() {
-            fs::remove_file(&target).map_err(|e| Error::StoreCorruption {
-                message: format!("failed to replace existing cask binary: {e}"),
-            })?;
+        if has("lib") {
+            exports.push(format!(
+                "export LDFLAGS=\"-L{}\"",
+                opt.join("lib").display()
+            ));
         }
+        if has("include") {
+            exports.push(format!(
+                "export CPPFLAGS=\"-I{}\"",
+                opt.join("include").display()
+            ));
+        }
+        if has("lib/pkgconfig") {
+            exports.push(format!(
+                "export PKG_CONFIG_PATH=\"{}\"",
+                opt.join("lib/pkgconfig").display()
+            ));
+        }
+        exports
+    }
 
-        fs::copy(&source, &target).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to stage cask binary '{}': {e}", binary.target),
-        })?;
+    /// Who owns `path` in the prefix: the formula that linked it, or the
+    /// owner observed when it last got in the way of a link.
+    pub fn prefix_owner(&self, path: &Path) -> Option<String> {
+        self.db.prefix_owner(path)
+    }
 
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = fs::metadata(&target)
-                .map_err(|e| Error::StoreCorruption {
-                    message: format!("failed to read staged cask binary metadata: {e}"),
-                })?
-                .permissions();
-            if perms.mode() & 0o111 == 0 {
-                perms.set_mode(0o755);
-                fs::set_permissions(&target, perms).map_err(|e| Error::StoreCorruption {
-                    message: format!("failed to make staged cask binary executable: {e}"),
-                })?;
+    fn keg_only_dependency_bins(&self, keg_path: &Path) -> Vec<PathBuf> {
+        receipt_dependencies(keg_path)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|dep| !self.db.is_linked(dep))
+            .map(|dep| {
+                self.prefix
+                    .join("opt")
+                    .join(formula_token(&dep))
+                    .join("bin")
+            })
+            .filter(|bin| bin.is_dir())
+            .collect()
+    }
+
+    fn remove_superseded_keg(&self, name: &str, version: &str) {
+        let keg_name = formula_token(name);
+        let keg_path = self.cellar.keg_path(keg_name, version);
+        match self.linker.get() {
+            Ok(linker) => {
+                if let Err(e) = linker.unlink_keg(&keg_path) {
+                    eprintln!("warning: failed to unlink {name}@{version}: {e}");
+                }
+                linker.unlink_versioned_opt(&keg_path);
             }
+            Err(e) => eprintln!("warning: failed to unlink {name}@{version}: {e}"),
+        }
+        if let Err(e) = self.cellar.remove_keg(keg_name, version) {
+            eprintln!("warning: failed to remove keg for {name}@{version}: {e}");
         }
     }
 
-    Ok(())
-}
-
-fn resolve_cask_source_path(
-    extracted_root: &Path,
-    cask: &crate::installer::cask::ResolvedCask,
-    source: &str,
-) -> Result<std::path::PathBuf, Error> {
-    if source.starts_with("$APPDIR") {
-        return Err(Error::InvalidArgument {
-            message: format!(
-                "cask '{}' uses APPDIR artifacts which are not supported yet",
-                cask.token
-            ),
-        });
+    fn cleanup_materialized(cellar: &Cellar, name: &str, version: &str) {
+        if let Err(e) = cellar.remove_keg(name, version) {
+            eprintln!(
+                "warning: failed to remove keg for {}@{} after install error: {}",
+                name, version, e
+            );
+        }
     }
 
-    let mut normalized = source.to_string();
-    let caskroom_prefix = format!("$HOMEBREW_PREFIX/Caskroom/{}/{}/", cask.token, cask.version);
-    if let Some(stripped) = normalized.strip_prefix(&caskroom_prefix) {
-        normalized = stripped.to_string();
+    /// Convenience method to plan and execute in one call
+    pub async fn install(&mut self, names: &[String], link: bool) -> Result<ExecuteResult, Error> {
+        let (casks, formulas): (Vec<_>, Vec<_>) = names
+            .iter()
+            .cloned()
+            .partition(|name| name.starts_with("cask:"));
+
+        let mut installed = 0usize;
+
+        if !formulas.is_empty() {
+            let plan = self.plan(&formulas).await?;
+            installed += self.execute(plan, link).await?.installed;
+        }
+
+        if !casks.is_empty() {
+            installed += self.install_casks(&casks, link).await?.installed;
+        }
+
+        Ok(ExecuteResult { installed })
     }
 
-    let source_path = Path::new(&normalized);
-    if source_path.is_absolute() {
-        return Err(Error::InvalidArgument {
-            message: format!(
-                "cask '{}' binary source '{}' must be a relative path",
-                cask.token, source
-            ),
-        });
+    pub async fn install_casks(
+        &mut self,
+        names: &[String],
+        link: bool,
+    ) -> Result<ExecuteResult, Error> {
+        let mut installed = 0usize;
+        for name in names {
+            let token = name
+                .strip_prefix("cask:")
+                .expect("install_casks expects cask: prefixed names");
+            self.install_single_cask(token, link).await?;
+            installed += 1;
+        }
+        Ok(ExecuteResult { installed })
     }
 
-    for component in source_path.components() {
-        if matches!(component, std::path::Component::ParentDir) {
-            return Err(Error::InvalidArgument {
-                message: format!(
-                    "cask '{}' binary source '{}' cannot contain '..'",
-                    cask.token, source
-                ),
+    /// Let [`Installer::uninstall`] remove formulas other installed
+    /// formulas depend on.
+    pub fn set_ignore_dependencies(&mut self, ignore: bool) {
+        self.ignore_dependencies = ignore;
+    }
+
+    /// Let [`Installer::plan`] include formulas that conflict with installed
+    /// ones, and [`Installer::uninstall`] delete files changed since the
+    /// install.
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    /// Let [`Installer::uninstall`] also remove the preferences, caches and
+    /// support files a cask's `zap` stanza named when it was installed.
+    pub fn set_zap(&mut self, zap: bool) {
+        self.zap = zap;
+    }
+
+    /// `names` reordered so every formula comes after those in the list that
+    /// depend on it, letting them be uninstalled one by one.
+    pub fn uninstall_order(&self, names: &[String]) -> Vec<String> {
+        let mut pending: Vec<String> = names.to_vec();
+        let mut ordered = Vec::with_capacity(names.len());
+        while !pending.is_empty() {
+            let next = pending.iter().position(|name| {
+                let dependents = self
+                    .db
+                    .dependents(&[name, formula_token(name)])
+                    .unwrap_or_default();
+                !dependents
+                    .iter()
+                    .any(|dependent| dependent != name && pending.contains(dependent))
             });
+            // A dependency cycle leaves no free formula; keep the given order.
+            ordered.push(pending.remove(next.unwrap_or(0)));
         }
+        ordered
     }
 
-    Ok(extracted_root.join(source_path))
-}
+    /// Installed formulas `name` was recorded as depending on, sorted.
+    pub fn installed_dependencies(&self, name: &str) -> Result<Vec<String>, Error> {
+        let recorded = self.db.dependencies(name)?;
+        let mut installed: Vec<String> = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .map(|keg| keg.name)
+            .filter(|installed| {
+                installed != name
+                    && recorded
+                        .iter()
+                        .any(|dep| dep == installed || dep == formula_token(installed))
+            })
+            .collect();
+        installed.sort();
+        Ok(installed)
+    }
 
-/// Create an Installer with standard paths
-pub fn create_installer(
-    root: &Path,
-    prefix: &Path,
-    concurrency: usize,
-) -> Result<Installer, Error> {
-    use std::fs;
+    /// Installed formulas no other installed formula depends on.
+    pub fn leaves(&self) -> Result<Vec<String>, Error> {
+        let mut leaves = Vec::new();
+        for keg in self.db.list_installed()? {
+            let dependents = self.db.dependents(&[&keg.name, formula_token(&keg.name)])?;
+            if dependents.iter().all(|dependent| *dependent == keg.name) {
+                leaves.push(keg.name);
+            }
+        }
+        Ok(leaves)
+    }
 
-    // First ensure the root directory exists
-    if !root.exists() {
-        fs::create_dir_all(root).map_err(|e| {
-            if e.kind() == std::io::ErrorKind::PermissionDenied {
-                Error::StoreCorruption {
-                    message: format!(
-                        "cannot create root directory '{}': permission denied.\n\n\
-                        Create it with:\n  sudo mkdir -p {} && sudo chown $USER {}",
-                        root.display(),
-                        root.display(),
-                        root.display()
-                    ),
-                }
-            } else {
-                Error::StoreCorruption {
-                    message: format!("failed to create root directory '{}': {e}", root.display()),
+    /// Uninstall the active version of `name`. When versions of it are
+    /// retained, the newest one takes over as the active version, linked
+    /// if the uninstalled one was; otherwise `name` is gone entirely.
+    pub fn uninstall(&mut self, name: &str) -> Result<(), Error> {
+        self.uninstall_with(name, true)
+    }
+
+    /// Uninstall `name` and every retained version of it.
+    pub fn uninstall_all_versions(&mut self, name: &str) -> Result<(), Error> {
+        self.uninstall_with(name, false)
+    }
+
+    fn uninstall_with(&mut self, name: &str, promote: bool) -> Result<(), Error> {
+        // Check if installed
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        let keg_name = formula_token(&installed.name);
+
+        let mut retained = self.db.retained_kegs(name)?;
+        let promoted = if promote && !name.starts_with("cask:") && !retained.is_empty() {
+            Some(retained.remove(0))
+        } else {
+            None
+        };
+
+        // Dependents keep working on a promoted version.
+        if !self.ignore_dependencies && promoted.is_none() {
+            let dependents: Vec<String> = self
+                .db
+                .dependents(&[name, keg_name])?
+                .into_iter()
+                .filter(|dependent| dependent != name)
+                .collect();
+            if !dependents.is_empty() {
+                return Err(Error::HasDependents {
+                    name: name.to_string(),
+                    dependents,
+                });
+            }
+        }
+
+        if !self.force {
+            let changed = self.changed_keg_files(name)?;
+            if !changed.is_empty() {
+                return Err(Error::ModifiedKegFiles {
+                    name: name.to_string(),
+                    files: changed.iter().map(ToString::to_string).collect(),
+                });
+            }
+        }
+
+        // Unlink executables
+        let keg_path = self.cellar.keg_path(keg_name, &installed.version);
+        let was_linked = self.db.is_linked(name);
+        self.linker.get()?.unlink_keg(&keg_path)?;
+        self.discard_lazy_shims(name);
+
+        // Apps and package payloads a cask put outside its keg
+        for (kind, path) in self.db.cask_artifacts(name)? {
+            match kind.as_str() {
+                "app" => remove_app(Path::new(&path))?,
+                "pkgutil" => forget_pkg(&path)?,
+                "zap" if self.zap => {
+                    if let Err(e) = zap(Path::new(&path)) {
+                        self.warn(format!("{e}; {name} left it behind"))?;
+                    }
                 }
+                _ => {}
+            }
+        }
+
+        // Remove from database (decrements store ref)
+        if promoted.is_some() {
+            retained.clear();
+        }
+        {
+            let tx = self.db.transaction()?;
+            tx.record_uninstall(name)?;
+            for keg in &retained {
+                tx.release_retained(name, &keg.version)?;
+            }
+            if let Some(keg) = &promoted {
+                tx.record_install(name, &keg.version, &keg.store_key)?;
+                tx.release_retained(name, &keg.version)?;
             }
+            tx.commit()?;
+        }
+
+        // Remove cellar entry
+        self.linker.get()?.unlink_versioned_opt(&keg_path);
+        self.cellar.remove_keg(keg_name, &installed.version)?;
+        for keg in retained {
+            self.remove_superseded_keg(name, &keg.version);
+        }
+
+        if let Some(keg) = promoted {
+            self.promote_keg(name, &keg, was_linked)?;
+        }
+        Ok(())
+    }
+
+    /// Files of the installed keg of `name` edited or added since it was
+    /// installed. Empty when no record of its files was kept, e.g. for kegs
+    /// installed lazily or by older versions of zb.
+    pub fn changed_keg_files(&self, name: &str) -> Result<Vec<ChangedKegFile>, Error> {
+        let Some(installed) = self.db.get_installed(name) else {
+            return Ok(Vec::new());
+        };
+        let recorded = self.db.keg_manifest(name, &installed.version)?;
+        if recorded.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keg_path = self
+            .cellar
+            .keg_path(formula_token(name), &installed.version);
+        let recorded: BTreeMap<String, KegFileState> = recorded
+            .into_iter()
+            .map(|file| (file.path.clone(), file))
+            .collect();
+        Ok(keg_file_states(&keg_path)
+            .into_iter()
+            .filter_map(|file| match recorded.get(&file.path) {
+                Some(original) if *original == file => None,
+                original => Some(ChangedKegFile {
+                    added: original.is_none(),
+                    path: file.path,
+                }),
+            })
+            .collect())
+    }
+
+    /// Copy `files` of the installed keg of `name` under `dir`, keeping
+    /// their paths, and return the directory they went to.
+    pub fn backup_keg_files(
+        &self,
+        name: &str,
+        files: &[ChangedKegFile],
+        dir: &Path,
+    ) -> Result<PathBuf, Error> {
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
         })?;
+        let keg_path = self
+            .cellar
+            .keg_path(formula_token(name), &installed.version);
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let backup = dir.join(format!(
+            "{}-{}-{secs}",
+            formula_token(name),
+            installed.version
+        ));
+        for file in files {
+            let target = backup.join(&file.path);
+            let copied = target
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::copy(keg_path.join(&file.path), &target));
+            if let Err(e) = copied {
+                return Err(Error::FileError {
+                    message: format!("failed to back up {}: {e}", target.display()),
+                });
+            }
+        }
+        Ok(backup)
     }
 
-    // Ensure all subdirectories exist
-    fs::create_dir_all(root.join("db")).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to create db directory: {e}"),
-    })?;
+    /// Make the formerly retained `keg` of `name` the active version: point
+    /// `opt/<name>` at it, materializing it again if its directory is gone,
+    /// and link it when `link` is set.
+    fn promote_keg(&mut self, name: &str, keg: &InstalledKeg, link: bool) -> Result<(), Error> {
+        let keg_name = formula_token(name);
+        let keg_path = self.cellar.keg_path(keg_name, &keg.version);
+        if !keg_path.is_dir() {
+            let _lock = self.store.get()?.lock_store(false)?;
+            let entry = self.store.get()?.unpacked_entry(&keg.store_key)?;
+            self.cellar.materialize(keg_name, &keg.version, &entry)?;
+        }
+        self.linker.get()?.link_opt(&keg_path)?;
+        if !link {
+            return Ok(());
+        }
 
-    let api_client = ApiClient::new();
-    let blob_cache = BlobCache::new(&root.join("cache")).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to create blob cache: {e}"),
-    })?;
-    let store = Store::new(root).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to create store: {e}"),
-    })?;
-    // Use prefix/Cellar so bottles' hardcoded rpaths work
-    let cellar = Cellar::new_at(prefix.join("Cellar")).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to create cellar: {e}"),
-    })?;
-    let linker = Linker::new(prefix).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to create linker: {e}"),
-    })?;
-    let db = Database::open(&root.join("db/zb.sqlite3"))?;
+        let linked = self.link_keg_for(name, &keg_path)?;
+        let tx = self.db.transaction()?;
+        for file in &linked {
+            tx.record_linked_file(
+                name,
+                &keg.version,
+                &file.link_path.to_string_lossy(),
+                &file.target_path.to_string_lossy(),
+            )?;
+        }
+        tx.commit()
+    }
 
-    use crate::network::download::ParallelDownloader;
-    let parallel_downloader = ParallelDownloader::with_concurrency(blob_cache, concurrency);
+    /// Link an installed keg into the prefix, or only the parts of it named
+    /// in `only` (see [`Linker::resolve_subset`]). With `overwrite`, files in
+    /// the way are deleted first instead of failing the link. Returns the
+    /// links in place.
+    pub fn link(
+        &mut self,
+        name: &str,
+        only: &[String],
+        overwrite: bool,
+    ) -> Result<Vec<LinkedFile>, Error> {
+        let (version, keg_path) = self.linkable_keg(name)?;
+
+        if overwrite {
+            let conflicts = self.link_conflicts(name, &keg_path, only)?;
+            self.linker.get()?.remove_conflicts(&conflicts)?;
+            let tx = self.db.transaction()?;
+            for conflict in &conflicts {
+                tx.forget_prefix_path(&conflict.path.to_string_lossy())?;
+            }
+            tx.commit()?;
+        }
 
-    Ok(Installer {
-        api_client,
-        downloader: parallel_downloader,
-        store,
-        cellar,
-        linker,
-        db,
-        prefix: prefix.to_path_buf(),
-    })
-}
+        let linked = if only.is_empty() {
+            self.link_keg_for(name, &keg_path)?
+        } else {
+            if self.config.link_mode(name) == LinkMode::Shim {
+                return Err(Error::InvalidArgument {
+                    message: format!("{name} is linked with shims, which cover the whole keg"),
+                });
+            }
+            let mut linked = Vec::new();
+            for subset in self.resolve_subsets(&keg_path, only)? {
+                linked.extend(
+                    self.linker
+                        .get()?
+                        .link_subset(&keg_path, &subset)
+                        .map_err(|e| self.observe_conflicts(e))?,
+                );
+            }
+            linked
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use tempfile::TempDir;
-    use wiremock::matchers::{method, path};
-    use wiremock::{Mock, MockServer, ResponseTemplate};
+        let tx = self.db.transaction()?;
+        for file in &linked {
+            tx.record_linked_file(
+                name,
+                &version,
+                &file.link_path.to_string_lossy(),
+                &file.target_path.to_string_lossy(),
+            )?;
+        }
+        tx.commit()?;
+        Ok(linked)
+    }
 
-    fn create_bottle_tarball(formula_name: &str) -> Vec<u8> {
-        use flate2::Compression;
-        use flate2::write::GzEncoder;
-        use std::io::Write;
-        use tar::Builder;
+    /// Remove a keg's links from the prefix without uninstalling it, or only
+    /// the links of the parts named in `only`. Returns the removed links.
+    pub fn unlink(&mut self, name: &str, only: &[String]) -> Result<Vec<PathBuf>, Error> {
+        let (_, keg_path) = self.linkable_keg(name)?;
+
+        let (unlinked, forget) = if only.is_empty() {
+            let unlinked = self.linker.get()?.unlink_keg(&keg_path)?;
+            let recorded = self.db.linked_files(name)?;
+            (
+                unlinked,
+                recorded.into_iter().map(|(link, _)| link).collect(),
+            )
+        } else {
+            let mut unlinked = Vec::new();
+            for subset in self.resolve_subsets(&keg_path, only)? {
+                unlinked.extend(self.linker.get()?.unlink_subset(&keg_path, &subset)?);
+            }
+            let forget: Vec<String> = unlinked
+                .iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect();
+            (unlinked, forget)
+        };
 
-        let mut builder = Builder::new(Vec::new());
+        if only.is_empty() {
+            self.db.record_link_exclusions(name, &[])?;
+        }
+        let tx = self.db.transaction()?;
+        for link in &forget {
+            tx.remove_linked_file(name, link)?;
+        }
+        tx.commit()?;
+        Ok(unlinked)
+    }
 
-        // Create bin directory with executable
-        let mut header = tar::Header::new_gnu();
-        header
-            .set_path(format!("{}/1.0.0/bin/{}", formula_name, formula_name))
-            .unwrap();
-        header.set_size(20);
-        header.set_mode(0o755);
-        header.set_cksum();
+    /// The links [`Self::link`] would create for `name` and the files in
+    /// their way, without changing the prefix.
+    pub fn preview_link(&self, name: &str, only: &[String]) -> Result<LinkPreview, Error> {
+        let (_, keg_path) = self.linkable_keg(name)?;
+        let linker = self.linker.get()?;
+        let links = if only.is_empty() {
+            linker.plan_keg(&keg_path, &self.config.link_exclude(name))
+        } else {
+            self.resolve_subsets(&keg_path, only)?
+                .iter()
+                .flat_map(|subset| linker.plan_subset(&keg_path, subset))
+                .collect()
+        };
+        let conflicts = self.link_conflicts(name, &keg_path, only)?;
+        Ok(LinkPreview { links, conflicts })
+    }
+
+    /// The recorded links [`Self::unlink`] would remove for `name`.
+    pub fn preview_unlink(&self, name: &str, only: &[String]) -> Result<Vec<PathBuf>, Error> {
+        let (_, keg_path) = self.linkable_keg(name)?;
+        let subsets: Vec<PathBuf> = self
+            .resolve_subsets(&keg_path, only)?
+            .into_iter()
+            .map(|subset| self.prefix.join(subset))
+            .collect();
+        Ok(self
+            .linked_files(name)?
+            .into_iter()
+            .filter(|link| subsets.is_empty() || subsets.iter().any(|dir| link.starts_with(dir)))
+            .collect())
+    }
+
+    fn link_conflicts(
+        &self,
+        name: &str,
+        keg_path: &Path,
+        only: &[String],
+    ) -> Result<Vec<ConflictedLink>, Error> {
+        let linker = self.linker.get()?;
+        if only.is_empty() {
+            let exclude = self.config.link_exclude(name);
+            return Ok(linker.conflicts(&LinkExclusions::new(keg_path, &exclude)));
+        }
+        Ok(self
+            .resolve_subsets(keg_path, only)?
+            .iter()
+            .flat_map(|subset| linker.subset_conflicts(keg_path, subset))
+            .collect())
+    }
+
+    fn linkable_keg(&self, name: &str) -> Result<(String, PathBuf), Error> {
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        if self.is_lazy(name) {
+            return Err(Error::InvalidArgument {
+                message: format!("{name} is installed lazily; run it once to materialize it"),
+            });
+        }
+        let keg_path = self
+            .cellar
+            .keg_path(formula_token(&installed.name), &installed.version);
+        Ok((installed.version, keg_path))
+    }
+
+    fn resolve_subsets(&self, keg_path: &Path, only: &[String]) -> Result<Vec<PathBuf>, Error> {
+        only.iter()
+            .map(|spec| self.linker.get()?.resolve_subset(keg_path, spec))
+            .collect()
+    }
+
+    /// Repair the installation after its prefix moved from `from` to the
+    /// current prefix.
+    ///
+    /// Kegs are materialized again from the store, which re-patches install
+    /// names, RPATHs and text placeholders for the new location. Recorded
+    /// paths are rewritten and links pointing at the old prefix recreated.
+    pub fn relocate(&mut self, from: &Path) -> Result<RelocationReport, Error> {
+        if from == self.prefix {
+            return Err(Error::InvalidArgument {
+                message: format!("the prefix is already at {}", from.display()),
+            });
+        }
+        let _store_lock = self.store.get()?.lock_store(false)?;
+        self.db.relocate_paths(from, &self.prefix)?;
+
+        let mut report = RelocationReport::default();
+        let installed = self.db.list_installed()?;
+        let retained = self.db.list_retained()?;
+        for (keg, label) in installed.iter().map(|keg| (keg, keg.name.clone())).chain(
+            retained
+                .iter()
+                .map(|keg| (keg, format!("{}@{}", keg.name, keg.version))),
+        ) {
+            // Casks only stage binaries, which carry no prefix paths; lazy
+            // kegs aren't materialized yet.
+            if keg.name.starts_with("cask:") || self.is_lazy(&keg.name) {
+                continue;
+            }
+            if keg.store_key.starts_with("source:") || !self.store.get()?.has_entry(&keg.store_key)
+            {
+                report.needs_reinstall.push(label);
+                continue;
+            }
+            let keg_name = formula_token(&keg.name);
+            self.cellar.remove_keg(keg_name, &keg.version)?;
+            let keg_path = self.cellar.materialize(
+                keg_name,
+                &keg.version,
+                &self.store.get()?.unpacked_entry(&keg.store_key)?,
+            )?;
+            relocate_keg_text(&keg_path, from, &self.prefix)?;
+            report.rematerialized.push(label);
+        }
+
+        for keg in &installed {
+            for shim in self.db.lazy_shims(&keg.name)? {
+                relocate_text_file(Path::new(&shim), from, &self.prefix)?;
+            }
+            if self.is_lazy(&keg.name) {
+                continue;
+            }
+
+            let keg_path = self.cellar.keg_path(formula_token(&keg.name), &keg.version);
+            self.linker.get()?.link_opt(&keg_path)?;
+            if !self.db.is_linked(&keg.name) {
+                continue;
+            }
+
+            for (link, _) in self.db.linked_files(&keg.name)? {
+                let link = Path::new(&link);
+                let target = fs::read_link(link).ok().or_else(|| exec_shim_target(link));
+                if target.is_some_and(|target| target.starts_with(from)) {
+                    let _ = fs::remove_file(link);
+                }
+            }
+            let linked = self.link_keg_for(&keg.name, &keg_path)?;
+            let tx = self.db.transaction()?;
+            for file in &linked {
+                tx.record_linked_file(
+                    &keg.name,
+                    &keg.version,
+                    &file.link_path.to_string_lossy(),
+                    &file.target_path.to_string_lossy(),
+                )?;
+            }
+            tx.commit()?;
+            report.relinked.push(keg.name.clone());
+        }
+
+        Ok(report)
+    }
+
+    /// Checksums and sizes of the cached downloads.
+    pub fn cached_blobs(&self) -> Result<Vec<(String, u64)>, Error> {
+        self.downloader
+            .get()?
+            .blob_cache()
+            .blobs()
+            .map_err(|e| Error::FileError {
+                message: format!("failed to list the download cache: {e}"),
+            })
+    }
+
+    /// Serve the download cache read-only to other machines on `listener`,
+    /// until the process is stopped.
+    pub async fn serve_cache(&self, listener: tokio::net::TcpListener) -> Result<(), Error> {
+        peer::serve_blobs(self.downloader.get()?.blob_cache().clone(), listener).await
+    }
+
+    /// Copy the downloads `peer` (a machine running `zb cache serve`) has
+    /// cached and this machine hasn't, keeping only those that match their
+    /// checksum.
+    pub async fn pull_cache(&self, peer: &str) -> Result<PeerPull, Error> {
+        peer::pull_blobs(self.downloader.get()?.blob_cache(), peer).await
+    }
+
+    /// Garbage collect unreferenced store entries
+    pub fn gc(&mut self) -> Result<Vec<String>, Error> {
+        self.gc_with_options(&GcOptions::default())
+    }
+
+    /// Remove unreferenced store entries and, as `options` asks, old kegs
+    /// and cached downloads that are too old or don't fit the cache size cap
+    /// (see [`CleanupOptions`]), reporting the space each freed (or would
+    /// free on a dry run).
+    pub fn cleanup(&mut self, options: &CleanupOptions) -> Result<CleanupReport, Error> {
+        let mut report = CleanupReport::default();
+        let mut released = Vec::new();
+
+        let mut kegs = Vec::new();
+        if options.superseded_kegs || options.keep_latest_kegs.is_some() {
+            kegs = self.superseded_kegs()?;
+        }
+        if let Some(keep) = options.keep_latest_kegs {
+            let mut kept: BTreeMap<String, usize> = BTreeMap::new();
+            let dropped: Vec<InstalledKeg> = self
+                .db
+                .list_retained()?
+                .into_iter()
+                .filter(|keg| !keg.name.starts_with("cask:"))
+                .filter(|keg| {
+                    let count = kept.entry(keg.name.clone()).or_default();
+                    *count += 1;
+                    // The installed version counts as the first one kept.
+                    *count >= keep
+                })
+                .collect();
+            if !options.dry_run && !dropped.is_empty() {
+                let tx = self.db.transaction()?;
+                for keg in &dropped {
+                    tx.release_retained(&keg.name, &keg.version)?;
+                }
+                tx.commit()?;
+            }
+            for keg in dropped {
+                kegs.push((keg.name, keg.version));
+                released.push(keg.store_key);
+            }
+        }
+        for (name, version) in kegs {
+            let keg_path = self.cellar.keg_path(formula_token(&name), &version);
+            let bytes = directory_size(&keg_path);
+            if !options.dry_run {
+                self.remove_superseded_keg(&name, &version);
+            }
+            report.kegs.push(CleanedItem {
+                label: format!("{name}@{version}"),
+                bytes,
+            });
+        }
+
+        let mut unreferenced = self.gc_with_options(&GcOptions {
+            dry_run: true,
+            min_age: options.min_age,
+        })?;
+        if options.dry_run && options.min_age.is_none() {
+            // Entries only the dropped versions hold would be freed as well.
+            for key in released {
+                if self.db.get_store_refcount(&key) == 1 && !unreferenced.contains(&key) {
+                    unreferenced.push(key);
+                }
+            }
+        }
+        let store = self.store.get()?;
+        let sizes: BTreeMap<String, u64> = unreferenced
+            .into_iter()
+            .map(|key| {
+                let bytes = store.disk_usage(&key);
+                (key, bytes)
+            })
+            .collect();
+        let removed = if options.dry_run {
+            sizes.keys().cloned().collect()
+        } else {
+            self.gc_with_options(&GcOptions {
+                dry_run: false,
+                min_age: options.min_age,
+            })?
+        };
+        report.store_entries = removed
+            .into_iter()
+            .map(|key| CleanedItem {
+                bytes: sizes.get(&key).copied().unwrap_or(0),
+                label: key,
+            })
+            .collect();
+
+        if options.cache_max_age.is_some() || options.cache_max_bytes.is_some() {
+            report.blobs = self
+                .downloader
+                .get()?
+                .prune_blobs(
+                    options.cache_max_age.unwrap_or(std::time::Duration::MAX),
+                    options.cache_max_bytes,
+                    options.dry_run,
+                )?
+                .into_iter()
+                .map(|(sha256, bytes)| CleanedItem {
+                    label: sha256,
+                    bytes,
+                })
+                .collect();
+        }
+
+        Ok(report)
+    }
+
+    /// Keg directories left in the Cellar for installed formulas that are
+    /// neither the installed nor a retained version, as `(name, version)`.
+    fn superseded_kegs(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut known: BTreeMap<String, (String, BTreeSet<String>)> = BTreeMap::new();
+        let mut kegs = self.db.list_installed()?;
+        kegs.extend(self.db.list_retained()?);
+        for keg in kegs {
+            if keg.name.starts_with("cask:") {
+                continue;
+            }
+            known
+                .entry(formula_token(&keg.name).to_string())
+                .or_insert_with(|| (keg.name.clone(), BTreeSet::new()))
+                .1
+                .insert(keg.version);
+        }
+
+        let mut superseded = Vec::new();
+        for (token, (name, versions)) in known {
+            let Ok(entries) = fs::read_dir(self.cellar.dir().join(&token)) else {
+                continue;
+            };
+            let mut stale: Vec<String> = entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|version| !versions.contains(version))
+                .collect();
+            stale.sort();
+            superseded.extend(stale.into_iter().map(|version| (name.clone(), version)));
+        }
+        Ok(superseded)
+    }
+
+    /// Compress store entries no keg was materialized from for `unused_for`
+    /// into single-file archives. They are unpacked again on next use.
+    pub fn compact_store(
+        &mut self,
+        unused_for: std::time::Duration,
+        dry_run: bool,
+    ) -> Result<Vec<CompactedEntry>, Error> {
+        let _lock = self.store.get()?.lock_store(true)?;
+        let now = std::time::SystemTime::now();
+
+        let mut compacted = Vec::new();
+        for store_key in self.store.get()?.entry_keys()? {
+            let Some(last_used) = self.store.get()?.last_used(&store_key) else {
+                continue;
+            };
+            if now.duration_since(last_used).unwrap_or_default() < unused_for {
+                continue;
+            }
+            if dry_run {
+                compacted.push(CompactedEntry {
+                    unpacked_bytes: directory_size(&self.store.get()?.entry_path(&store_key)),
+                    store_key,
+                    archived_bytes: None,
+                });
+                continue;
+            }
+            let (unpacked_bytes, archived_bytes) = self.store.get()?.archive_entry(&store_key)?;
+            compacted.push(CompactedEntry {
+                store_key,
+                unpacked_bytes,
+                archived_bytes: Some(archived_bytes),
+            });
+        }
+        Ok(compacted)
+    }
+
+    /// Remove unreferenced store entries, returning their keys (or, for a
+    /// dry run, the keys that would be removed).
+    ///
+    /// Runs under the exclusive store lock, so no install can pick up an
+    /// entry while it is being deleted.
+    pub fn gc_with_options(&mut self, options: &GcOptions) -> Result<Vec<String>, Error> {
+        let _lock = self.store.get()?.lock_store(true)?;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let candidates: Vec<String> = self
+            .db
+            .get_unreferenced_store_keys()?
+            .into_iter()
+            .filter(
+                |store_key| match (options.min_age, self.db.store_released_at(store_key)) {
+                    (Some(min_age), Some(released_at)) => {
+                        now.saturating_sub(released_at) >= min_age.as_secs() as i64
+                    }
+                    // Without a release time its age is unknown, so it's kept.
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                },
+            )
+            .collect();
+        if options.dry_run || candidates.is_empty() {
+            return Ok(candidates);
+        }
+
+        let workers = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .clamp(1, GC_WORKERS);
+        let chunk_size = candidates.len().div_ceil(workers);
+        let store = self.store.get()?;
+        let mut panicked = false;
+        let results: Vec<(String, Result<(), Error>)> = std::thread::scope(|scope| {
+            let handles: Vec<_> = candidates
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|store_key| (store_key.clone(), store.remove_entry(store_key)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().map_err(|_| panicked = true).ok())
+                .flatten()
+                .collect()
+        });
+
+        let mut removed = Vec::new();
+        let mut first_error = panicked.then(|| Error::ExecutionError {
+            message: "a garbage collection worker panicked".to_string(),
+        });
+        for (store_key, result) in results {
+            match result {
+                Ok(()) => {
+                    self.db.delete_store_ref(&store_key)?;
+                    removed.push(store_key);
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(removed),
+        }
+    }
+
+    /// Compare store refcounts with the installed kegs, correcting them when
+    /// `repair` is set, and deal with store entries the database doesn't
+    /// know about.
+    pub fn audit_store(
+        &mut self,
+        repair: bool,
+        orphans: OrphanAction,
+    ) -> Result<StoreAudit, Error> {
+        let mut kegs = self.db.list_installed()?;
+        kegs.extend(self.db.list_retained()?);
+        let references: Vec<String> = kegs.iter().map(|keg| keg.store_key.clone()).collect();
+        let bottle_keys: BTreeSet<String> = kegs
+            .iter()
+            .filter(|keg| !keg.name.starts_with("cask:") && !keg.store_key.starts_with("source:"))
+            .map(|keg| keg.store_key.clone())
+            .collect();
+
+        let mut result = audit(
+            &self.db.store_refcounts()?,
+            &references,
+            &bottle_keys,
+            &self.store.get()?.entry_keys()?,
+        );
+
+        if repair {
+            for fix in &result.refcount_fixes {
+                self.db.set_store_refcount(&fix.store_key, fix.actual)?;
+            }
+        }
+        result.repaired = repair;
+
+        for store_key in &result.orphans {
+            match orphans {
+                OrphanAction::Report => {}
+                OrphanAction::Adopt => self.db.set_store_refcount(store_key, 0)?,
+                OrphanAction::Delete => self.store.get()?.remove_entry(store_key)?,
+            }
+        }
+        result.orphan_action = orphans;
+
+        Ok(result)
+    }
+
+    /// Problems with the prefix and `root` that need a hand to fix: broken
+    /// links, stale opt links, unwritable directories and a PATH that lets
+    /// Homebrew's commands win.
+    pub fn diagnose(&self, root: &Path) -> Result<Vec<Diagnostic>, Error> {
+        let installed: BTreeSet<String> = self
+            .db
+            .list_installed()?
+            .iter()
+            .map(|keg| formula_token(&keg.name).to_string())
+            .collect();
+        Ok(doctor::diagnose(
+            root,
+            &self.prefix,
+            self.cellar.dir(),
+            &installed,
+        ))
+    }
+
+    /// Check every recorded link of the installed kegs against the prefix,
+    /// and that paths left out by `link_exclude` don't lead into the keg.
+    pub fn audit_links(&self) -> Result<LinkAudit, Error> {
+        let mut result = LinkAudit::default();
+        for keg in self.db.list_installed()? {
+            let links = self.db.linked_files(&keg.name)?;
+            result.checked += links.len();
+            result.broken.extend(
+                links
+                    .into_iter()
+                    .filter(|(link, target)| !link_points_at(Path::new(link), Path::new(target)))
+                    .map(|(link, _)| PathBuf::from(link)),
+            );
+
+            // Patterns added since the keg was last linked count too.
+            let keg_path = self.cellar.keg_path(formula_token(&keg.name), &keg.version);
+            let patterns = self.config.link_exclude(&keg.name);
+            let mut excluded: BTreeSet<PathBuf> = LinkExclusions::new(&keg_path, &patterns)
+                .excluded_entries()
+                .into_iter()
+                .map(|entry| self.prefix.join(entry))
+                .collect();
+            excluded.extend(self.link_exclusions(&keg.name)?);
+            for path in excluded {
+                result.excluded += 1;
+                if links_into(&path, &keg_path) {
+                    result.excluded_but_linked.push((keg.name.clone(), path));
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Installed formulas whose current stable version differs from the installed one.
+    ///
+    /// Casks and formulas that can no longer be fetched are skipped.
+    pub async fn outdated(&self) -> Result<Vec<OutdatedFormula>, Error> {
+        let installed: Vec<_> = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .filter(|keg| !keg.name.starts_with("cask:"))
+            .collect();
+
+        let futures: Vec<_> = installed
+            .iter()
+            .map(|keg| self.get_formula(&keg.name))
+            .collect();
+        let results = futures::future::join_all(futures).await;
+
+        let mut outdated = Vec::new();
+        for (keg, result) in installed.into_iter().zip(results) {
+            let Ok(formula) = result else {
+                continue;
+            };
+            let current_version = formula.effective_version();
+            if current_version != keg.version {
+                outdated.push(OutdatedFormula {
+                    name: keg.name,
+                    installed_version: keg.version,
+                    current_version,
+                    formula,
+                });
+            }
+        }
+
+        Ok(outdated)
+    }
+
+    /// Check installed formulas against OSV advisories.
+    ///
+    /// A fresh lookup replaces the locally stored snapshot; with `offline`,
+    /// or when OSV can't be reached, the last snapshot is used instead.
+    pub async fn security_audit(&mut self, offline: bool) -> Result<SecurityReport, Error> {
+        let installed: Vec<InstalledKeg> = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .filter(|keg| !keg.name.starts_with("cask:"))
+            .collect();
+
+        let mut lookup_error = None;
+        if !offline {
+            let packages: Vec<(String, String)> = installed
+                .iter()
+                .map(|keg| (keg.name.clone(), keg.version.clone()))
+                .collect();
+            let client = AdvisoryClient::new(
+                self.config.security.osv_url.clone(),
+                self.config.security.ecosystem.clone(),
+            )
+            .with_proxy(self.api_client.proxy());
+            match client.query(&packages).await {
+                Ok(results) => {
+                    let mut entries = Vec::with_capacity(packages.len());
+                    for ((name, version), advisories) in packages.into_iter().zip(results) {
+                        let json = serde_json::to_string(&advisories).map_err(|e| {
+                            Error::db("failed to serialize advisories").with_source(e)
+                        })?;
+                        entries.push((name, version, json));
+                    }
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|d| d.as_secs() as i64)
+                        .unwrap_or(0);
+                    self.db.replace_advisories(&entries, now)?;
+                }
+                Err(e @ Error::NetworkFailure { .. }) => lookup_error = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut report = SecurityReport {
+            offline: offline || lookup_error.is_some(),
+            ..SecurityReport::default()
+        };
+        for keg in installed {
+            let Some((version, json, fetched_at)) = self
+                .db
+                .advisories(&keg.name)
+                .filter(|(version, _, _)| *version == keg.version)
+            else {
+                report.unchecked.push(keg.name);
+                continue;
+            };
+            let advisories: Vec<Advisory> = serde_json::from_str(&json)
+                .map_err(|e| Error::db("invalid advisory snapshot").with_source(e))?;
+            report.checked += 1;
+            report.fetched_at = Some(report.fetched_at.map_or(fetched_at, |t| t.min(fetched_at)));
+            if !advisories.is_empty() {
+                report.findings.push(SecurityFinding {
+                    name: keg.name,
+                    version,
+                    advisories,
+                });
+            }
+        }
+
+        // Nothing to fall back on: surface why the lookup failed.
+        if let Some(e) = lookup_error
+            && report.checked == 0
+            && !report.unchecked.is_empty()
+        {
+            return Err(e);
+        }
+        Ok(report)
+    }
+
+    /// Plan upgrading `names`, or every outdated formula when empty.
+    ///
+    /// The plan is in dependency order and holds only formulas that change:
+    /// outdated ones and dependencies that aren't installed yet. Named
+    /// formulas that are already current are left out.
+    pub async fn plan_upgrade(&self, names: &[String]) -> Result<InstallPlan, Error> {
+        let targets: Vec<String> = if names.is_empty() {
+            self.outdated()
+                .await?
+                .into_iter()
+                .map(|outdated| outdated.name)
+                .filter(|name| !self.db.is_pinned(name))
+                .collect()
+        } else {
+            for name in names {
+                if self.db.get_installed(name).is_none() {
+                    return Err(Error::NotInstalled { name: name.clone() });
+                }
+                if self.db.is_pinned(name) {
+                    return Err(Error::InvalidArgument {
+                        message: format!("'{name}' is pinned; run `zb unpin {name}` to upgrade it"),
+                    });
+                }
+            }
+            names.to_vec()
+        };
+        if targets.is_empty() {
+            return Ok(InstallPlan {
+                items: Vec::new(),
+                metadata_fetched_at: None,
+            });
+        }
+
+        // Pinned dependencies stay at their installed version too.
+        let mut plan = self.plan(&targets).await?;
+        plan.items.retain(|item| {
+            self.db.get_installed(&item.install_name).is_none_or(|keg| {
+                keg.version != item.formula.effective_version()
+                    && !self.db.is_pinned(&item.install_name)
+            })
+        });
+        Ok(plan)
+    }
+
+    /// Installed formulas `plan` replaces, with the versions on each side.
+    pub fn planned_upgrades(&self, plan: &InstallPlan) -> Vec<PlannedUpgrade> {
+        plan.items
+            .iter()
+            .filter_map(|item| {
+                let keg = self.db.get_installed(&item.install_name)?;
+                let to_version = item.formula.effective_version();
+                (keg.version != to_version).then(|| PlannedUpgrade {
+                    name: item.install_name.clone(),
+                    from_version: keg.version,
+                    to_version,
+                })
+            })
+            .collect()
+    }
+
+    /// Remove the kegs left behind by completed `upgrades`, except versions
+    /// kept for rollback. Returns the removed `name@version`s.
+    pub fn remove_superseded(&mut self, upgrades: &[PlannedUpgrade]) -> Result<Vec<String>, Error> {
+        let mut removed = Vec::new();
+        for upgrade in upgrades {
+            let upgraded = self
+                .db
+                .get_installed(&upgrade.name)
+                .is_some_and(|keg| keg.version == upgrade.to_version);
+            let retained = self
+                .db
+                .retained_kegs(&upgrade.name)?
+                .iter()
+                .any(|keg| keg.version == upgrade.from_version);
+            if upgraded && !retained {
+                self.remove_superseded_keg(&upgrade.name, &upgrade.from_version);
+                removed.push(format!("{}@{}", upgrade.name, upgrade.from_version));
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Describe what upgrading an outdated formula would change.
+    pub async fn outdated_diff(&self, outdated: &OutdatedFormula) -> OutdatedDiff {
+        let keg_path = self
+            .cellar
+            .keg_path(formula_token(&outdated.name), &outdated.installed_version);
+        let (added_dependencies, removed_dependencies, dependencies_known) =
+            match receipt_dependencies(&keg_path) {
+                Some(installed) => {
+                    let (added, removed) =
+                        diff_dependencies(&installed, &outdated.formula.dependencies);
+                    (added, removed, true)
+                }
+                None => (Vec::new(), Vec::new(), false),
+            };
+
+        // Sizes are left out when the blob cache can't be opened.
+        let downloader = self.downloader.get().ok();
+        let installed_bottle_size = self
+            .db
+            .get_installed(&outdated.name)
+            .and_then(|keg| downloader?.cached_blob_size(&keg.store_key));
+        let current_bottle_size = match (downloader, self.select_bottle(&outdated.formula)) {
+            (Some(downloader), Ok(bottle)) => downloader.remote_size(&bottle.url).await,
+            _ => None,
+        };
+
+        OutdatedDiff {
+            added_dependencies,
+            removed_dependencies,
+            dependencies_known,
+            installed_bottle_size,
+            current_bottle_size,
+            release_notes_url: release_notes_url(&outdated.formula),
+        }
+    }
+
+    /// Print adaptive download concurrency decisions to stderr.
+    /// The Ruby source of a formula, preferring a local Homebrew tap checkout
+    /// over fetching it from upstream.
+    pub async fn formula_source(&self, name: &str) -> Result<FormulaSource, Error> {
+        if let Some(path) = find_local_formula(&homebrew_taps_dirs(), name) {
+            return read_local(&path);
+        }
+
+        let formula = self.get_formula(name).await?;
+        let ruby_source_path = formula
+            .ruby_source_path
+            .clone()
+            .unwrap_or_else(|| core_ruby_source_path(&formula.name));
+        // Formulas from our own tap checkouts are already on disk.
+        if Path::new(&ruby_source_path).is_absolute() {
+            return read_local(Path::new(&ruby_source_path));
+        }
+        let checksum = formula
+            .ruby_source_checksum
+            .as_ref()
+            .map(|checksum| checksum.sha256.as_str());
+
+        let cache_dir = self.prefix.join("tmp").join("rb_cache");
+        let path = self
+            .api_client
+            .fetch_formula_rb(&ruby_source_path, &cache_dir, checksum)
+            .await?;
+        let contents = fs::read_to_string(&path).map_err(|e| Error::FileError {
+            message: format!("failed to read {}: {e}", path.display()),
+        })?;
+
+        Ok(FormulaSource {
+            origin: FormulaSourceOrigin::Remote(formula_rb_url(&ruby_source_path)),
+            contents,
+        })
+    }
+
+    /// Smoke-test an installed formula: run its primary binary, check its links
+    /// and signatures, and for tap formulas run the simple statements of its
+    /// `test do` block.
+    pub async fn smoke_test(&self, name: &str) -> Result<SmokeReport, Error> {
+        let keg = self
+            .db
+            .get_installed(name)
+            .ok_or_else(|| Error::NotInstalled {
+                name: name.to_string(),
+            })?;
+        let token = formula_token(&keg.name);
+        let keg_path = self.keg_path(token, &keg.version);
+
+        let mut checks = Vec::new();
+        checks.push(match smoke::primary_binary(&keg_path, token) {
+            Some(binary) => smoke::check_binary(&binary),
+            None => SmokeCheck::new(
+                "binary",
+                "",
+                CheckStatus::Skipped("no executables in bin/".to_string()),
+            ),
+        });
+        checks.push(smoke::check_links(&self.db.linked_files(&keg.name)?));
+        checks.extend(smoke::check_signatures(&keg_path));
+        if parse_tap_formula_ref(&keg.name).is_some() {
+            checks.push(
+                self.run_test_block(&keg.name, &keg.version, &keg_path)
+                    .await,
+            );
+        }
+
+        Ok(SmokeReport {
+            formula: keg.name,
+            version: keg.version,
+            checks,
+        })
+    }
+
+    async fn run_test_block(&self, name: &str, version: &str, keg_path: &Path) -> SmokeCheck {
+        let skipped =
+            |reason: String| SmokeCheck::new("test block", "", CheckStatus::Skipped(reason));
+
+        let source = match self.formula_source(name).await {
+            Ok(source) => source,
+            Err(e) => return skipped(format!("could not fetch formula source: {e}")),
+        };
+        let Some(body) = smoke::test_block(&source.contents) else {
+            return skipped("formula has no test block".to_string());
+        };
+        let steps = match smoke::parse_test_steps(&body) {
+            Ok(steps) if steps.is_empty() => return skipped("test block is empty".to_string()),
+            Ok(steps) => steps,
+            Err(line) => return skipped(format!("unsupported statement: {line}")),
+        };
+
+        let token = formula_token(name);
+        let testpath = self
+            .prefix
+            .join("tmp")
+            .join(format!("zb-test-{token}-{}", std::process::id()));
+        if let Err(e) = fs::create_dir_all(&testpath) {
+            return SmokeCheck::new(
+                "test block",
+                "",
+                CheckStatus::Failed(format!("failed to create {}: {e}", testpath.display())),
+            );
+        }
+
+        let status = smoke::run_test_steps(
+            &steps,
+            &smoke::TestContext {
+                name: token,
+                keg: keg_path,
+                prefix: &self.prefix,
+                testpath: &testpath,
+                version,
+            },
+        );
+        let _ = fs::remove_dir_all(&testpath);
+
+        SmokeCheck::new("test block", format!("{} step(s)", steps.len()), status)
+    }
+
+    pub fn set_debug_http(&mut self, enabled: bool) {
+        self.downloader
+            .configure(move |downloader| downloader.set_debug_http(enabled));
+    }
+
+    /// Check if a formula is installed
+    pub fn is_installed(&self, name: &str) -> bool {
+        self.db.get_installed(name).is_some()
+    }
+
+    /// Get info about an installed formula
+    pub fn get_installed(&self, name: &str) -> Option<crate::storage::db::InstalledKeg> {
+        self.db.get_installed(name)
+    }
+
+    /// List all installed formulas
+    pub fn list_installed(&self) -> Result<Vec<crate::storage::db::InstalledKeg>, Error> {
+        self.db.list_installed()
+    }
+
+    /// Write the unit file for `name`'s `service` block into its keg, where
+    /// [`ServiceManager::start`](crate::ServiceManager::start) picks it up,
+    /// creating the log and working directories it names. A lazily
+    /// installed keg is materialized first, since the service runs from it.
+    pub async fn write_service_definition(
+        &mut self,
+        name: &str,
+        kind: ServiceKind,
+    ) -> Result<PathBuf, Error> {
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        let formula = self.get_formula(name).await?;
+        let service = formula.service.ok_or_else(|| Error::UnsupportedFormula {
+            name: name.to_string(),
+            reason: "it doesn't define a service".to_string(),
+        })?;
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let unit = render_unit(kind, name, &service, &self.prefix, home.as_deref())?;
+        if self.is_lazy(name) {
+            self.materialize_lazy(name)?;
+        }
+
+        let resolve = |path: &Option<String>| {
+            path.as_deref().map(|path| {
+                PathBuf::from(path.replace("$HOMEBREW_PREFIX", &self.prefix.to_string_lossy()))
+            })
+        };
+        let log_dirs = [&service.log_path, &service.error_log_path]
+            .into_iter()
+            .filter_map(|path| resolve(path)?.parent().map(Path::to_path_buf));
+        for dir in log_dirs.chain(resolve(&service.working_dir)) {
+            if dir.is_absolute() {
+                fs::create_dir_all(&dir).map_err(|e| Error::FileError {
+                    message: format!("failed to create {}: {e}", dir.display()),
+                })?;
+            }
+        }
+
+        let path = self
+            .cellar
+            .keg_path(formula_token(name), &installed.version)
+            .join(ServiceManager::definition_file_name(kind, name));
+        fs::write(&path, unit).map_err(|e| Error::FileError {
+            message: format!("failed to write {}: {e}", path.display()),
+        })?;
+        // zb's own file, so it doesn't count as a change to the keg.
+        let keg_path = self
+            .cellar
+            .keg_path(formula_token(name), &installed.version);
+        if let Some(state) = keg_file_state(&keg_path, &path) {
+            let _ = self.db.record_keg_file(name, &installed.version, &state);
+        }
+        Ok(path)
+    }
+
+    /// Superseded versions of `name` kept by its retention policy, newest first.
+    pub fn retained_versions(
+        &self,
+        name: &str,
+    ) -> Result<Vec<crate::storage::db::InstalledKeg>, Error> {
+        self.db.retained_kegs(name)
+    }
+
+    /// The keg of the installed or a retained version of `name` matching
+    /// `version` (see [`version_matches`]), as `(version, keg path)`. A
+    /// retained keg whose directory was removed is materialized again from
+    /// the store; `None` when no such version is at hand.
+    pub fn version_keg(
+        &mut self,
+        name: &str,
+        version: &str,
+    ) -> Result<Option<(String, PathBuf)>, Error> {
+        let mut kegs: Vec<InstalledKeg> = self.db.get_installed(name).into_iter().collect();
+        kegs.extend(self.db.retained_kegs(name)?);
+        let Some(keg) = kegs.iter().find(|keg| keg.version == version).or_else(|| {
+            kegs.iter()
+                .find(|keg| version_matches(version, &keg.version))
+        }) else {
+            return Ok(None);
+        };
+
+        let keg_name = formula_token(name);
+        let keg_path = self.cellar.keg_path(keg_name, &keg.version);
+        if !keg_path.is_dir() {
+            if !self.store.get()?.has_entry(&keg.store_key) {
+                return Ok(None);
+            }
+            let _lock = self.store.get()?.lock_store(false)?;
+            let entry = self.store.get()?.unpacked_entry(&keg.store_key)?;
+            self.cellar.materialize(keg_name, &keg.version, &entry)?;
+        }
+        Ok(Some((keg.version.clone(), keg_path)))
+    }
+
+    /// Create or replace the named set of formulas.
+    pub fn save_set(&mut self, name: &str, formulas: &[String]) -> Result<(), Error> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "invalid set name '{name}' (use letters, digits, '-', '_' and '.')"
+                ),
+            });
+        }
+        if formulas.is_empty() {
+            return Err(Error::InvalidArgument {
+                message: format!("set '{name}' needs at least one formula"),
+            });
+        }
+        self.db.save_set(name, formulas)
+    }
+
+    /// Members of the named set.
+    pub fn formula_set(&self, name: &str) -> Result<Vec<String>, Error> {
+        self.db
+            .get_set(name)?
+            .ok_or_else(|| Error::InvalidArgument {
+                message: format!("no set named '{name}'"),
+            })
+    }
+
+    pub fn formula_sets(&self) -> Result<Vec<(String, Vec<String>)>, Error> {
+        self.db.list_sets()
+    }
+
+    pub fn delete_set(&mut self, name: &str) -> Result<(), Error> {
+        if self.db.delete_set(name)? {
+            Ok(())
+        } else {
+            Err(Error::InvalidArgument {
+                message: format!("no set named '{name}'"),
+            })
+        }
+    }
+
+    /// Keep `name` at its installed version on upgrades. Returns whether it
+    /// wasn't pinned yet.
+    pub fn pin(&self, name: &str) -> Result<bool, Error> {
+        if self.db.get_installed(name).is_none() {
+            return Err(Error::NotInstalled {
+                name: name.to_string(),
+            });
+        }
+        self.db.pin(name)
+    }
+
+    /// Returns whether `name` was pinned.
+    pub fn unpin(&self, name: &str) -> Result<bool, Error> {
+        self.db.unpin(name)
+    }
+
+    pub fn is_pinned(&self, name: &str) -> bool {
+        self.db.is_pinned(name)
+    }
+
+    pub fn pinned_formulas(&self) -> Result<Vec<String>, Error> {
+        self.db.pinned()
+    }
+
+    /// Add `name` to the install queue; returns whether it wasn't queued yet.
+    pub fn queue_install(&self, name: &str) -> Result<bool, Error> {
+        self.db.queue_install(name)
+    }
+
+    pub fn queued_installs(&self) -> Result<Vec<String>, Error> {
+        self.db.queued_installs()
+    }
+
+    pub fn dequeue_installs(&self, names: &[String]) -> Result<usize, Error> {
+        self.db.dequeue_installs(names)
+    }
+
+    /// Get the path to a keg in the cellar
+    pub fn keg_path(&self, name: &str, version: &str) -> std::path::PathBuf {
+        self.cellar.keg_path(name, version)
+    }
+
+    /// Pack an installed formula into a relocatable bottle in `out_dir`.
+    pub fn bottle(
+        &self,
+        name: &str,
+        tag: &str,
+        out_dir: &Path,
+    ) -> Result<crate::build::Bottle, Error> {
+        let installed = self
+            .db
+            .get_installed(name)
+            .ok_or_else(|| Error::NotInstalled {
+                name: name.to_string(),
+            })?;
+        let token = formula_token(&installed.name);
+        let keg_path = self.cellar.keg_path(token, &installed.version);
+
+        crate::build::bottle::create_bottle(
+            &keg_path,
+            token,
+            &installed.version,
+            tag,
+            &self.prefix,
+            self.cellar.dir(),
+            out_dir,
+        )
+    }
+    async fn install_single_cask(&mut self, token: &str, link: bool) -> Result<(), Error> {
+        let cask_json = self.api_client.get_cask(token).await?;
+        let cask = resolve_cask(token, &cask_json)?;
+
+        let blob_path = self
+            .downloader
+            .get()?
+            .download_single(
+                DownloadRequest {
+                    url: cask.url.clone(),
+                    sha256: cask.sha256.clone(),
+                    name: cask.install_name.clone(),
+                    id: item_id(&cask.install_name, &cask.version, &cask.sha256),
+                },
+                None,
+            )
+            .await?;
+
+        let extracted = self
+            .store
+            .get()?
+            .ensure_entry_with(&cask.sha256, |dest| unpack_cask(&cask, &blob_path, dest))?;
+        let keg_path = self.cellar.keg_path(&cask.install_name, &cask.version);
+        let mut cleanup = FailedInstallGuard::new(
+            self.linker.get()?,
+            &self.cellar,
+            &cask.install_name,
+            &cask.version,
+            &keg_path,
+            link,
+        );
+
+        let appdir = self.appdir();
+        let apps = self.place_cask_apps(&cask, &extracted, &appdir)?;
+        let staged = cask
+            .pkgs
+            .iter()
+            .try_for_each(|pkg| {
+                install_pkg(
+                    &cask.token,
+                    &extracted.join(relative_artifact_path(&cask.token, pkg)?),
+                )
+            })
+            .and_then(|()| stage_cask_binaries(&extracted, &keg_path, &cask, &appdir))
+            .and_then(|()| {
+                if link {
+                    self.linker.get()?.link_keg(&keg_path)
+                } else {
+                    Ok(Vec::new())
+                }
+            });
+        let linked_files = match staged {
+            Ok(linked_files) => linked_files,
+            Err(e) => {
+                for app in &apps {
+                    let _ = remove_app(app);
+                }
+                return Err(e);
+            }
+        };
+
+        let tx = self.db.transaction()?;
+        tx.record_install(&cask.install_name, &cask.version, &cask.sha256)?;
+        for linked in &linked_files {
+            tx.record_linked_file(
+                &cask.install_name,
+                &cask.version,
+                &linked.link_path.to_string_lossy(),
+                &linked.target_path.to_string_lossy(),
+            )?;
+        }
+        for app in &apps {
+            tx.record_cask_artifact(&cask.install_name, "app", &app.to_string_lossy())?;
+        }
+        if !cask.pkgs.is_empty() {
+            for id in &cask.pkgutil_ids {
+                tx.record_cask_artifact(&cask.install_name, "pkgutil", id)?;
+            }
+        }
+        // `~` is expanded now, so zapping later removes this user's files.
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        for path in &cask.zap {
+            if let Some(path) = expand_zap_path(path, home.as_deref()) {
+                tx.record_cask_artifact(&cask.install_name, "zap", &path.to_string_lossy())?;
+            }
+        }
+        tx.commit()?;
+
+        cleanup.disarm();
+        Ok(())
+    }
+
+    /// Where cask apps go: the configured `appdir` (`~` is the home
+    /// directory), else `/Applications` when writable, else `~/Applications`.
+    fn appdir(&self) -> PathBuf {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        match (&self.config.cask.appdir, home) {
+            (Some(appdir), Some(home)) => match appdir.strip_prefix("~") {
+                Ok(rest) => home.join(rest),
+                Err(_) => appdir.clone(),
+            },
+            (Some(appdir), None) => appdir.clone(),
+            (None, Some(home)) if !super::doctor::writable(Path::new("/Applications")) => {
+                home.join("Applications")
+            }
+            (None, _) => PathBuf::from("/Applications"),
+        }
+    }
+
+    /// Copy the cask's app bundles into `appdir`, returning where they went.
+    /// Bundles an earlier install of the cask placed are replaced; anything
+    /// else in the way fails the install, after removing the bundles
+    /// already copied.
+    fn place_cask_apps(
+        &self,
+        cask: &ResolvedCask,
+        extracted: &Path,
+        appdir: &Path,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let previous = self.db.cask_artifacts(&cask.install_name)?;
+        let mut placed: Vec<PathBuf> = Vec::new();
+        for app in &cask.apps {
+            let target = appdir.join(&app.target);
+            let ours = previous
+                .iter()
+                .any(|(kind, path)| kind == "app" && Path::new(path) == target);
+            let result = relative_artifact_path(&cask.token, &app.source).and_then(|source| {
+                install_app(&cask.token, &extracted.join(source), &target, ours)
+            });
+            if let Err(e) = result {
+                for app in &placed {
+                    let _ = remove_app(app);
+                }
+                return Err(e);
+            }
+            placed.push(target);
+        }
+        Ok(placed)
+    }
+}
+
+fn blob_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Total size of the regular files under `dir`; symlinks aren't followed.
+/// Every file and symlink under `keg_path`, as it is now.
+fn keg_file_states(keg_path: &Path) -> Vec<KegFileState> {
+    walkdir::WalkDir::new(keg_path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| !entry.file_type().is_dir())
+        .filter_map(|entry| keg_file_state(keg_path, entry.path()))
+        .collect()
+}
+
+/// The state of `path` in the keg at `keg_path`; symlinks aren't followed.
+fn keg_file_state(keg_path: &Path, path: &Path) -> Option<KegFileState> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as i64);
+    Some(KegFileState {
+        path: path
+            .strip_prefix(keg_path)
+            .ok()?
+            .to_string_lossy()
+            .into_owned(),
+        size: metadata.len(),
+        modified,
+    })
+}
+
+fn directory_size(dir: &Path) -> u64 {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Whether `path`, or a link anywhere under it, leads into `keg_path`.
+fn links_into(path: &Path, keg_path: &Path) -> bool {
+    let Ok(keg_path) = fs::canonicalize(keg_path) else {
+        return false;
+    };
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path_is_symlink() || entry.file_type().is_file())
+        .any(|entry| {
+            let target = match exec_shim_target(entry.path()) {
+                Some(target) => Some(target),
+                None if entry.path_is_symlink() => fs::canonicalize(entry.path()).ok(),
+                None => None,
+            };
+            target.is_some_and(|target| target.starts_with(&keg_path))
+        })
+}
+
+/// Rewrite `from` to `to` in a text file. Binary and non-UTF-8 files are
+/// left alone.
+///
+/// The file is replaced rather than written in place, so keg files
+/// hardlinked from the store don't carry the new prefix into the store.
+fn relocate_text_file(path: &Path, from: &Path, to: &Path) -> Result<bool, Error> {
+    let bytes = fs::read(path).map_err(|e| Error::FileError {
+        message: format!("failed to read {}: {e}", path.display()),
+    })?;
+    if bytes.contains(&0) {
+        return Ok(false);
+    }
+    let Ok(contents) = String::from_utf8(bytes) else {
+        return Ok(false);
+    };
+    let relocated = contents.replace(&*from.to_string_lossy(), &to.to_string_lossy());
+    if relocated == contents {
+        return Ok(false);
+    }
+
+    let write = || -> std::io::Result<()> {
+        let permissions = fs::metadata(path)?.permissions();
+        let temp_path = path.with_extension("tmp_relocate");
+        fs::write(&temp_path, relocated)?;
+        fs::set_permissions(&temp_path, permissions)?;
+        fs::rename(temp_path, path)
+    };
+    write().map_err(|e| Error::FileError {
+        message: format!("failed to write {}: {e}", path.display()),
+    })?;
+    Ok(true)
+}
+
+/// Rewrite text files in a keg that still name the `from` prefix, such as
+/// scripts and `.pc` files patched for it at install time.
+fn relocate_keg_text(keg_path: &Path, from: &Path, to: &Path) -> Result<(), Error> {
+    for entry in walkdir::WalkDir::new(keg_path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+    {
+        relocate_text_file(entry.path(), from, to)?;
+    }
+    Ok(())
+}
+
+/// `root` and every formula it reaches through dependencies in `formulas`.
+fn closure_of(root: &str, formulas: &BTreeMap<String, Formula>) -> Option<Vec<String>> {
+    let mut closure = BTreeSet::new();
+    let mut pending = vec![root];
+    while let Some(name) = pending.pop() {
+        let formula = formulas.get(name)?;
+        if closure.insert(name.to_string()) {
+            pending.extend(
+                formula
+                    .dependencies
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|dep| formulas.contains_key(*dep)),
+            );
+        }
+    }
+    Some(closure.into_iter().collect())
+}
+
+/// A subsystem of the [`Installer`] opened the first time it is used, so
+/// each command only builds what it needs.
+struct Deferred<T> {
+    value: std::cell::OnceCell<T>,
+    open: Option<Box<dyn Fn() -> Result<T, Error> + Send>>,
+}
+
+impl<T> Deferred<T> {
+    fn ready(value: T) -> Self {
+        Self {
+            value: std::cell::OnceCell::from(value),
+            open: None,
+        }
+    }
+
+    fn new(open: impl Fn() -> Result<T, Error> + Send + 'static) -> Self {
+        Self {
+            value: std::cell::OnceCell::new(),
+            open: Some(Box::new(open)),
+        }
+    }
+
+    /// The subsystem, opened now if this is its first use. A failed open is
+    /// tried again on the next use.
+    fn get(&self) -> Result<&T, Error> {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        let open = self
+            .open
+            .as_ref()
+            .expect("a deferred subsystem holds a value or a way to open one");
+        let value = open()?;
+        Ok(self.value.get_or_init(|| value))
+    }
+
+    fn get_mut(&mut self) -> Result<&mut T, Error> {
+        self.get()?;
+        Ok(self.value.get_mut().expect("opened just above"))
+    }
+
+    /// Apply `configure` to the subsystem now if it is open, or once it is.
+    fn configure(&mut self, configure: impl Fn(&T) + Send + 'static)
+    where
+        T: 'static,
+    {
+        if let Some(value) = self.value.get() {
+            configure(value);
+        } else if let Some(open) = self.open.take() {
+            self.open = Some(Box::new(move || {
+                let value = open()?;
+                configure(&value);
+                Ok(value)
+            }));
+        }
+    }
+
+    fn set(&mut self, value: T) {
+        self.value = std::cell::OnceCell::from(value);
+    }
+}
+
+/// Aborts a background task when dropped.
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Why `item` was left out: `dependency` didn't make it into the cellar,
+/// with the failure that stopped it when there was one.
+fn failed_dependency(
+    item: &PlannedInstall,
+    dependency: &PlannedInstall,
+    cause: Option<Error>,
+) -> Error {
+    let mut message = format!(
+        "{} was not installed because its dependency {} failed",
+        item.install_name, dependency.install_name
+    );
+    if let Some(cause) = cause {
+        message.push_str(&format!(": {cause}"));
+    }
+    Error::ExecutionError { message }
+}
+
+/// For each bottle item, the other bottle items it depends on.
+fn bottle_dependencies(items: &[PlannedInstall]) -> Vec<Vec<usize>> {
+    items
+        .iter()
+        .map(|item| {
+            items
+                .iter()
+                .enumerate()
+                .filter(|(_, dep)| {
+                    item.formula
+                        .dependencies
+                        .iter()
+                        .any(|name| *name == dep.install_name || *name == dep.formula.name)
+                })
+                .map(|(idx, _)| idx)
+                .collect()
+        })
+        .collect()
+}
+
+/// Build one download request per distinct bottle blob in the plan.
+///
+/// Aliased formulas and identical rebuilds can resolve to the same bottle; they
+/// share a single download. The returned groups list, for each request, the
+/// indices of the plan items that use it (first item is the one it is named after).
+fn dedupe_bottle_downloads(items: &[PlannedInstall]) -> (Vec<DownloadRequest>, Vec<Vec<usize>>) {
+    let mut requests: Vec<DownloadRequest> = Vec::new();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut by_sha: BTreeMap<&str, usize> = BTreeMap::new();
+
+    for (idx, item) in items.iter().enumerate() {
+        let InstallMethod::Bottle(ref bottle) = item.method else {
+            unreachable!()
+        };
+        match by_sha.get(bottle.sha256.as_str()) {
+            Some(&request_idx) => groups[request_idx].push(idx),
+            None => {
+                by_sha.insert(&bottle.sha256, requests.len());
+                requests.push(DownloadRequest {
+                    url: bottle.url.clone(),
+                    sha256: bottle.sha256.clone(),
+                    name: item.formula.name.clone(),
+                    id: item.progress_id(),
+                });
+                groups.push(vec![idx]);
+            }
+        }
+    }
+
+    (requests, groups)
+}
+
+fn dependency_cellar_path(cellar: &Cellar, installed_name: &str, version: &str) -> String {
+    cellar
+        .keg_path(formula_token(installed_name), version)
+        .display()
+        .to_string()
+}
+
+struct FailedInstallGuard<'a> {
+    linker: &'a Linker,
+    cellar: &'a Cellar,
+    name: &'a str,
+    version: &'a str,
+    keg_path: &'a Path,
+    unlink: bool,
+    armed: bool,
+}
+
+impl<'a> FailedInstallGuard<'a> {
+    fn new(
+        linker: &'a Linker,
+        cellar: &'a Cellar,
+        name: &'a str,
+        version: &'a str,
+        keg_path: &'a Path,
+        unlink: bool,
+    ) -> Self {
+        Self {
+            linker,
+            cellar,
+            name,
+            version,
+            keg_path,
+            unlink,
+            armed: true,
+        }
+    }
+
+    fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for FailedInstallGuard<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            Installer::cleanup_failed_install(
+                self.linker,
+                self.cellar,
+                self.name,
+                self.version,
+                self.keg_path,
+                self.unlink,
+            );
+        }
+    }
+}
+
+fn stage_cask_binaries(
+    extracted_root: &Path,
+    keg_path: &Path,
+    cask: &ResolvedCask,
+    appdir: &Path,
+) -> Result<(), Error> {
+    if cask.binaries.is_empty() {
+        return Ok(());
+    }
+    let bin_dir = keg_path.join("bin");
+    fs::create_dir_all(&bin_dir).map_err(|e| {
+        Error::link("failed to create cask bin dir")
+            .with_formula(&cask.token)
+            .with_path(&bin_dir)
+            .with_source(e)
+    })?;
+
+    for binary in &cask.binaries {
+        // Executables inside an app bundle load the rest of it, so they're
+        // linked where the app was installed rather than copied.
+        if let Some(in_app) = binary.source.strip_prefix("$APPDIR/") {
+            let source = appdir.join(relative_artifact_path(&cask.token, in_app)?);
+            if !source.exists() {
+                return Err(Error::InvalidArgument {
+                    message: format!(
+                        "cask '{}' binary '{}' not found in {}",
+                        cask.token,
+                        in_app,
+                        appdir.display()
+                    ),
+                });
+            }
+            let target = bin_dir.join(&binary.target);
+            let _ = fs::remove_file(&target);
+            std::os::unix::fs::symlink(&source, &target).map_err(|e| {
+                Error::link(format!("failed to link cask binary '{}'", binary.target))
+                    .with_formula(&cask.token)
+                    .with_path(&target)
+                    .with_source(e)
+            })?;
+            continue;
+        }
+
+        let source = resolve_cask_source_path(extracted_root, cask, &binary.source)?;
+        if !source.exists() {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "cask '{}' binary source '{}' not found in archive",
+                    cask.token, binary.source
+                ),
+            });
+        }
+
+        let target = bin_dir.join(&binary.target);
+        if target.exists() {
+            fs::remove_file(&target).map_err(|e| {
+                Error::link("failed to replace existing cask binary")
+                    .with_formula(&cask.token)
+                    .with_path(&target)
+                    .with_source(e)
+            })?;
+        }
+
+        fs::copy(&source, &target).map_err(|e| {
+            Error::link(format!("failed to stage cask binary '{}'", binary.target))
+                .with_formula(&cask.token)
+                .with_path(&target)
+                .with_source(e)
+        })?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = fs::metadata(&target)
+                .map_err(|e| {
+                    Error::link("failed to read staged cask binary metadata")
+                        .with_path(&target)
+                        .with_source(e)
+                })?
+                .permissions();
+            if perms.mode() & 0o111 == 0 {
+                perms.set_mode(0o755);
+                fs::set_permissions(&target, perms).map_err(|e| {
+                    Error::link("failed to make staged cask binary executable")
+                        .with_path(&target)
+                        .with_source(e)
+                })?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve_cask_source_path(
+    extracted_root: &Path,
+    cask: &ResolvedCask,
+    source: &str,
+) -> Result<std::path::PathBuf, Error> {
+    let mut normalized = source.to_string();
+    let caskroom_prefix = format!("$HOMEBREW_PREFIX/Caskroom/{}/{}/", cask.token, cask.version);
+    if let Some(stripped) = normalized.strip_prefix(&caskroom_prefix) {
+        normalized = stripped.to_string();
+    }
+
+    let source_path = Path::new(&normalized);
+    if source_path.is_absolute() {
+        return Err(Error::InvalidArgument {
+            message: format!(
+                "cask '{}' binary source '{}' must be a relative path",
+                cask.token, source
+            ),
+        });
+    }
+
+    for component in source_path.components() {
+        if matches!(component, std::path::Component::ParentDir) {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "cask '{}' binary source '{}' cannot contain '..'",
+                    cask.token, source
+                ),
+            });
+        }
+    }
+
+    Ok(extracted_root.join(source_path))
+}
+
+/// Platform component of plan hashes.
+fn host_platform() -> String {
+    crate::build::host_bottle_tag().unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Create an Installer with standard paths
+pub fn create_installer(
+    root: &Path,
+    prefix: &Path,
+    concurrency: usize,
+) -> Result<Installer, Error> {
+    use std::fs;
+
+    // First ensure the root directory exists
+    if !root.exists() {
+        fs::create_dir_all(root).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                Error::FileError {
+                    message: format!(
+                        "cannot create root directory '{}': permission denied.\n\n\
+                        Create it with:\n  sudo mkdir -p {} && sudo chown $USER {}",
+                        root.display(),
+                        root.display(),
+                        root.display()
+                    ),
+                }
+            } else {
+                Error::FileError {
+                    message: format!("failed to create root directory '{}': {e}", root.display()),
+                }
+            }
+        })?;
+    }
+
+    // Ensure all subdirectories exist
+    fs::create_dir_all(root.join("db")).map_err(|e| {
+        Error::db("failed to create db directory")
+            .with_path(root.join("db"))
+            .with_source(e)
+    })?;
+
+    let config = Config::load(&root.join("config.toml"))?;
+    let proxy = crate::network::proxy::from_config(&config.proxy)?;
+    let api_client = ApiClient::new()
+        .with_github_token(config.auth.github_token.clone())
+        .with_retry(config.retry.clone())
+        .with_proxy(proxy.clone());
+    // Use prefix/Cellar so bottles' hardcoded rpaths work
+    let cellar = Cellar::new_at(prefix.join("Cellar")).map_err(|e| {
+        Error::link("failed to create cellar")
+            .with_path(prefix.join("Cellar"))
+            .with_source(e)
+    })?;
+    let db = Database::open(&root.join("db/zb.sqlite3"))?;
+    let taps = Taps::new(root.join("taps")).with_github_token(config.auth.github_token.clone());
+
+    // Everything else is opened the first time a command uses it, so query
+    // commands only pay for the database and the Cellar.
+    let watchdog = WatchdogConfig::from_config(&config.download);
+    crate::watchdog::set_command_timeout(&watchdog);
+
+    let downloader = {
+        let cache_dir = root.join("cache");
+        let verify_blobs = config.cache.verify_blobs;
+        let registry_token = config.auth.ghcr_token();
+        let retry = config.retry.clone();
+        let bottle_domain = config.bottle_domain.clone();
+        Deferred::new(move || {
+            let blob_cache = BlobCache::new(&cache_dir)
+                .map_err(|e| {
+                    Error::download("failed to create blob cache")
+                        .with_path(&cache_dir)
+                        .with_source(e)
+                })?
+                .with_verification(verify_blobs);
+            let downloader = ParallelDownloader::with_concurrency(blob_cache, concurrency);
+            downloader.set_registry_token(registry_token.clone());
+            downloader.set_retry(retry.clone());
+            downloader.set_bottle_domain(bottle_domain.clone());
+            downloader.set_watchdog(watchdog);
+            downloader.set_proxy(proxy.clone());
+            Ok(downloader)
+        })
+    };
+    let store = {
+        let root = root.to_path_buf();
+        Deferred::new(move || {
+            Store::new(&root).map_err(|e| Error::unpack("failed to create store").with_source(e))
+        })
+    };
+    let linker = {
+        let prefix = prefix.to_path_buf();
+        Deferred::new(move || {
+            Linker::new(&prefix).map_err(|e| {
+                Error::link("failed to create linker")
+                    .with_path(&prefix)
+                    .with_source(e)
+            })
+        })
+    };
+    let index = {
+        let path = root.join("db/index.sqlite3");
+        Deferred::new(move || FormulaIndex::open(&path).map(Some))
+    };
+    let hooks = {
+        let plugins = root.join("plugins");
+        Deferred::new(move || Ok(PluginHooks::discover(&plugins)))
+    };
+
+    Ok(Installer {
+        api_client,
+        downloader,
+        store,
+        cellar,
+        linker,
+        db,
+        prefix: prefix.to_path_buf(),
+        hooks,
+        index,
+        config,
+        lazy: None,
+        auto_accept_licenses: false,
+        ignore_dependencies: false,
+        zap: false,
+        force: false,
+        strict: false,
+        offline: false,
+        taps: Some(taps),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::outdated::MetadataChange;
+    use std::fs;
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn create_bottle_tarball(formula_name: &str) -> Vec<u8> {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+        use tar::Builder;
+
+        let mut builder = Builder::new(Vec::new());
+
+        // Create bin directory with executable
+        let mut header = tar::Header::new_gnu();
+        header
+            .set_path(format!("{}/1.0.0/bin/{}", formula_name, formula_name))
+            .unwrap();
+        header.set_size(20);
+        header.set_mode(0o755);
+        header.set_cksum();
+
+        let content = format!("#!/bin/sh\necho {}", formula_name);
+        builder.append(&header, content.as_bytes()).unwrap();
+
+        let tar_data = builder.into_inner().unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&tar_data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn sha256_hex(data: &[u8]) -> String {
+        use sha2::{Digest, Sha256};
+        let mut hasher = Sha256::new();
+        hasher.update(data);
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn get_test_bottle_tag() -> &'static str {
+        if cfg!(target_os = "linux") {
+            "x86_64_linux"
+        } else if cfg!(target_arch = "x86_64") {
+            "sonoma"
+        } else {
+            "arm64_sonoma"
+        }
+    }
+
+    #[test]
+    fn dependency_cellar_path_uses_formula_token_for_tap_name() {
+        let tmp = TempDir::new().unwrap();
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let path = dependency_cellar_path(&cellar, "hashicorp/tap/terraform", "1.10.0");
+
+        assert!(path.ends_with("cellar/terraform/1.10.0"));
+    }
+
+    #[test]
+    fn dependency_cellar_path_keeps_core_formula_name() {
+        let tmp = TempDir::new().unwrap();
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let path = dependency_cellar_path(&cellar, "openssl@3", "3.3.2");
+
+        assert!(path.ends_with("cellar/openssl@3/3.3.2"));
+    }
+
+    #[test]
+    fn dependency_cellar_path_uses_name_from_db_record() {
+        let tmp = TempDir::new().unwrap();
+        let cellar = Cellar::new(tmp.path()).unwrap();
+
+        let db_path = tmp.path().join("zb.sqlite3");
+        let mut db = Database::open(&db_path).unwrap();
+        let tx = db.transaction().unwrap();
+        tx.record_install("hashicorp/tap/terraform", "1.10.0", "store-key")
+            .unwrap();
+        tx.commit().unwrap();
+
+        let keg = db.get_installed("hashicorp/tap/terraform").unwrap();
+        let path = dependency_cellar_path(&cellar, &keg.name, &keg.version);
+
+        assert!(path.ends_with("cellar/terraform/1.10.0"));
+    }
+
+    #[test]
+    fn source_keg_backup_can_restore_previous_installation() {
+        let tmp = TempDir::new().unwrap();
+        let keg_path = tmp.path().join("cellar").join("example").join("1.0.0");
+        fs::create_dir_all(&keg_path).unwrap();
+        fs::write(keg_path.join("old.txt"), "old").unwrap();
+
+        let backup = Installer::backup_existing_source_keg(&keg_path, "example", "1.0.0").unwrap();
+        let backup = backup.expect("backup path should exist");
+
+        assert!(!keg_path.exists());
+        assert!(backup.exists());
+
+        fs::create_dir_all(&keg_path).unwrap();
+        fs::write(keg_path.join("new.txt"), "new").unwrap();
+
+        Installer::restore_source_keg_from_backup(&keg_path, &backup, "example", "1.0.0").unwrap();
+
+        assert!(keg_path.join("old.txt").exists());
+        assert!(!keg_path.join("new.txt").exists());
+        assert!(!backup.exists());
+    }
+
+    #[test]
+    fn backup_existing_source_keg_returns_none_when_keg_is_missing() {
+        let tmp = TempDir::new().unwrap();
+        let missing_keg = tmp.path().join("cellar").join("example").join("1.0.0");
+
+        let backup =
+            Installer::backup_existing_source_keg(&missing_keg, "example", "1.0.0").unwrap();
+
+        assert!(backup.is_none());
+    }
+
+    #[tokio::test]
+    async fn install_completes_successfully() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        // Create bottle
+        let bottle = create_bottle_tarball("testpkg");
+        let bottle_sha = sha256_hex(&bottle);
+
+        // Create formula JSON
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{
+                "name": "testpkg",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{}": {{
+                                "url": "{}/bottles/testpkg-1.0.0.{}.bottle.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            tag,
+            mock_server.uri(),
+            tag,
+            bottle_sha
+        );
+
+        // Mount formula API mock
+        Mock::given(method("GET"))
+            .and(path("/testpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+
+        // Mount bottle download mock
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/bottles/testpkg-1.0.0.{}.bottle.tar.gz",
+                tag
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&mock_server)
+            .await;
+
+        // Create installer with mocked API
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let api_client = ApiClient::with_base_url(mock_server.uri());
+        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
+        let store = Store::new(&root).unwrap();
+        let cellar = Cellar::new(&root).unwrap();
+        let linker = Linker::new(&prefix).unwrap();
+        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+
+        let mut installer = Installer::new(
+            api_client,
+            blob_cache,
+            store,
+            cellar,
+            linker,
+            db,
+            prefix.clone(),
+        );
+
+        // Install
+        installer
+            .install(&["testpkg".to_string()], true)
+            .await
+            .unwrap();
+
+        // Verify keg exists
+        assert!(root.join("cellar/testpkg/1.0.0").exists());
+
+        // Verify link exists
+        assert!(prefix.join("bin/testpkg").exists());
+
+        // Verify database records
+        let installed = installer.db.get_installed("testpkg");
+        assert!(installed.is_some());
+        assert_eq!(installed.unwrap().version, "1.0.0");
+
+        // The executed plan is kept as a receipt that can be verified later
+        let plans = installer.db.find_plans("").unwrap();
+        assert_eq!(plans.len(), 1);
+        let verification = installer.verify_plan(&plans[0].hash[..12]).unwrap();
+        assert!(verification.matches());
+        assert_eq!(verification.entries[0].0.name, "testpkg");
+
+        installer.uninstall("testpkg").unwrap();
+        let verification = installer.verify_plan(&plans[0].hash).unwrap();
+        assert!(!verification.matches());
+        assert_eq!(verification.entries[0].1, EntryStatus::NotInstalled);
+        assert!(installer.verify_plan("deadbeef00").is_err());
+    }
+
+    #[tokio::test]
+    async fn plugins_can_reject_downloads_and_observe_links() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("testpkg");
+        let bottle_sha = sha256_hex(&bottle);
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{
+                "name": "testpkg",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{tag}": {{
+                                "url": "{}/bottles/testpkg-1.0.0.{tag}.bottle.tar.gz",
+                                "sha256": "{bottle_sha}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            mock_server.uri(),
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/testpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/testpkg-1.0.0.{tag}.bottle.tar.gz")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(mock_server.uri()),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+        );
+
+        let plugins = root.join("plugins");
+        fs::create_dir_all(&plugins).unwrap();
+        let plugin = plugins.join("policy");
+        let log = tmp.path().join("hooks.log");
+        fs::write(
+            &plugin,
+            format!(
+                "#!/bin/sh\n\
+                 [ \"$1\" = post-download ] && {{ echo 'blocked by policy' >&2; exit 1; }}\n\
+                 echo \"$1\" >> {}\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&plugin, fs::Permissions::from_mode(0o755)).unwrap();
+        installer.set_plugin_hooks(PluginHooks::discover(&plugins));
+
+        let Err(err) = installer.install(&["testpkg".to_string()], true).await else {
+            panic!("post-download plugin should have rejected the install");
+        };
+        assert!(err.to_string().contains("blocked by policy"));
+        assert!(!installer.is_installed("testpkg"));
+        assert_eq!(fs::read_to_string(&log).unwrap(), "pre-plan\n");
+
+        fs::write(
+            &plugin,
+            format!("#!/bin/sh\necho \"$1\" >> {}\n", log.display()),
+        )
+        .unwrap();
+        fs::remove_file(&log).unwrap();
+
+        installer
+            .install(&["testpkg".to_string()], true)
+            .await
+            .unwrap();
+        assert!(installer.is_installed("testpkg"));
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            "pre-plan\npost-download\npost-link\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn outdated_reports_version_and_dependency_changes() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        for (name, version, deps) in [
+            ("oldpkg", "2.0.0", r#"["newdep"]"#),
+            ("currentpkg", "1.0.0", "[]"),
+        ] {
+            let json = format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "{version}" }},
+                    "dependencies": {deps},
+                    "urls": {{ "stable": {{ "url": "https://github.com/example/{name}/archive/v{version}.tar.gz" }} }},
+                    "bottle": {{
+                        "stable": {{
+                            "files": {{
+                                "{tag}": {{
+                                    "url": "{}/bottles/{name}.tar.gz",
+                                    "sha256": "{}"
+                                }}
+                            }}
+                        }}
+                    }}
+                }}"#,
+                mock_server.uri(),
+                "0".repeat(64)
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(json))
+                .mount(&mock_server)
+                .await;
+        }
+
+        Mock::given(method("HEAD"))
+            .and(path("/bottles/oldpkg.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).append_header("Content-Length", "4096"))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("oldpkg", "1.0.0", "oldkey").unwrap();
+            tx.record_install("currentpkg", "1.0.0", "currentkey")
+                .unwrap();
+            tx.record_install("cask:someapp", "3.0", "caskkey").unwrap();
+            tx.commit().unwrap();
+        }
+
+        let cellar = Cellar::new(&root).unwrap();
+        let keg = cellar.keg_path("oldpkg", "1.0.0");
+        fs::create_dir_all(&keg).unwrap();
+        fs::write(
+            keg.join("INSTALL_RECEIPT.json"),
+            r#"{"runtime_dependencies": [{"full_name": "olddep", "declared_directly": true}]}"#,
+        )
+        .unwrap();
+
+        let installer = Installer::new(
+            ApiClient::with_base_url(mock_server.uri()),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            cellar,
+            Linker::new(&prefix).unwrap(),
+            db,
+            prefix.clone(),
+        );
+
+        let outdated = installer.outdated().await.unwrap();
+        assert_eq!(outdated.len(), 1);
+        assert_eq!(outdated[0].name, "oldpkg");
+        assert_eq!(outdated[0].installed_version, "1.0.0");
+        assert_eq!(outdated[0].current_version, "2.0.0");
+
+        let diff = installer.outdated_diff(&outdated[0]).await;
+        assert!(diff.dependencies_known);
+        assert_eq!(diff.added_dependencies, vec!["newdep"]);
+        assert_eq!(diff.removed_dependencies, vec!["olddep"]);
+        assert_eq!(diff.installed_bottle_size, None);
+        assert_eq!(diff.current_bottle_size, Some(4096));
+        assert_eq!(
+            diff.release_notes_url.as_deref(),
+            Some("https://github.com/example/oldpkg/releases")
+        );
+    }
+
+    #[tokio::test]
+    async fn uninstall_cleans_everything() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        // Create bottle
+        let bottle = create_bottle_tarball("uninstallme");
+        let bottle_sha = sha256_hex(&bottle);
+
+        // Create formula JSON
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{
+                "name": "uninstallme",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{}": {{
+                                "url": "{}/bottles/uninstallme-1.0.0.{}.bottle.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            tag,
+            mock_server.uri(),
+            tag,
+            bottle_sha
+        );
+
+        // Mount mocks
+        Mock::given(method("GET"))
+            .and(path("/uninstallme.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/bottles/uninstallme-1.0.0.{}.bottle.tar.gz",
+                tag
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&mock_server)
+            .await;
+
+        // Create installer
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let api_client = ApiClient::with_base_url(mock_server.uri());
+        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
+        let store = Store::new(&root).unwrap();
+        let cellar = Cellar::new(&root).unwrap();
+        let linker = Linker::new(&prefix).unwrap();
+        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+
+        let mut installer = Installer::new(
+            api_client,
+            blob_cache,
+            store,
+            cellar,
+            linker,
+            db,
+            prefix.clone(),
+        );
+
+        // Install
+        installer
+            .install(&["uninstallme".to_string()], true)
+            .await
+            .unwrap();
+
+        // Verify installed
+        assert!(installer.is_installed("uninstallme"));
+        assert!(root.join("cellar/uninstallme/1.0.0").exists());
+        assert!(prefix.join("bin/uninstallme").exists());
+
+        // Uninstall
+        installer.uninstall("uninstallme").unwrap();
+
+        // Verify everything cleaned up
+        assert!(!installer.is_installed("uninstallme"));
+        assert!(!root.join("cellar/uninstallme/1.0.0").exists());
+        assert!(!prefix.join("bin/uninstallme").exists());
+    }
+
+    #[tokio::test]
+    async fn lazy_install_writes_shims_and_materializes_on_demand() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+        let bottle = create_bottle_tarball("lazypkg");
+        let bottle_sha = sha256_hex(&bottle);
+
+        let formula_json = format!(
+            r#"{{
+                "name": "lazypkg",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{tag}": {{
+                                "url": "{}/bottles/lazypkg.tar.gz",
+                                "sha256": "{bottle_sha}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            mock_server.uri()
+        );
+        Mock::given(method("GET"))
+            .and(path("/lazypkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/lazypkg.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(mock_server.uri()),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+        );
+        installer.set_lazy_launcher(Some(LazyLauncher::new("/usr/local/bin/zb", vec![])));
+
+        installer
+            .install(&["lazypkg".to_string()], true)
+            .await
+            .unwrap();
+
+        let shim = prefix.join("bin/lazypkg");
+        assert!(is_shim(&shim));
+        assert!(!root.join("cellar/lazypkg/1.0.0").exists());
+        assert!(installer.is_lazy("lazypkg"));
+        assert_eq!(installer.db.get_store_refcount(&bottle_sha), 1);
+
+        assert!(installer.materialize_lazy("lazypkg").unwrap());
+        assert!(root.join("cellar/lazypkg/1.0.0/bin/lazypkg").exists());
+        assert!(shim.is_symlink());
+        assert!(!installer.is_lazy("lazypkg"));
+        assert!(installer.db.is_linked("lazypkg"));
+        assert!(!installer.materialize_lazy("lazypkg").unwrap());
+    }
+
+    #[tokio::test]
+    async fn upgrades_retain_configured_number_of_previous_kegs() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+        let bottle = create_bottle_tarball("retainpkg");
+        let bottle_sha = sha256_hex(&bottle);
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(mock_server.uri()),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+        );
+        installer.set_config(Config::parse("[formula.retainpkg]\nretain_versions = 1\n").unwrap());
+
+        for version in ["1.0.0", "2.0.0", "3.0.0"] {
+            mock_server.reset().await;
+            let formula_json = format!(
+                r#"{{
+                    "name": "retainpkg",
+                    "versions": {{ "stable": "{version}" }},
+                    "dependencies": [],
+                    "bottle": {{
+                        "stable": {{
+                            "files": {{
+                                "{tag}": {{
+                                    "url": "{}/bottles/retainpkg-{version}.tar.gz",
+                                    "sha256": "{bottle_sha}"
+                                }}
+                            }}
+                        }}
+                    }}
+                }}"#,
+                mock_server.uri()
+            );
+            Mock::given(method("GET"))
+                .and(path("/retainpkg.json"))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/retainpkg-{version}.tar.gz")))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+                .mount(&mock_server)
+                .await;
+
+            installer
+                .install(&["retainpkg".to_string()], true)
+                .await
+                .unwrap();
+        }
+
+        assert!(!root.join("cellar/retainpkg/1.0.0").exists());
+        assert!(root.join("cellar/retainpkg/2.0.0").exists());
+        assert!(root.join("cellar/retainpkg/3.0.0").exists());
+        let retained: Vec<String> = installer
+            .retained_versions("retainpkg")
+            .unwrap()
+            .into_iter()
+            .map(|keg| keg.version)
+            .collect();
+        assert_eq!(retained, ["2.0.0"]);
+        // One reference from the installed keg, one from the retained keg.
+        assert_eq!(installer.db.get_store_refcount(&bottle_sha), 2);
+        let opt = prefix.join("opt");
+        assert_eq!(
+            fs::read_link(opt.join("retainpkg")).unwrap(),
+            root.join("cellar/retainpkg/3.0.0")
+        );
+        assert_eq!(
+            fs::read_link(opt.join("retainpkg@2.0.0")).unwrap(),
+            root.join("cellar/retainpkg/2.0.0")
+        );
+        assert!(opt.join("retainpkg@3.0.0").exists());
+        assert!(opt.join("retainpkg@1.0.0").symlink_metadata().is_err());
+
+        // Uninstalling the active version promotes the retained one.
+        installer.uninstall("retainpkg").unwrap();
+        assert_eq!(
+            installer.get_installed("retainpkg").unwrap().version,
+            "2.0.0"
+        );
+        assert!(installer.retained_versions("retainpkg").unwrap().is_empty());
+        assert!(!root.join("cellar/retainpkg/3.0.0").exists());
+        assert_eq!(
+            fs::read_link(opt.join("retainpkg")).unwrap(),
+            root.join("cellar/retainpkg/2.0.0")
+        );
+        assert!(opt.join("retainpkg@3.0.0").symlink_metadata().is_err());
+        assert!(prefix.join("bin/retainpkg").exists());
+        assert_eq!(installer.db.get_store_refcount(&bottle_sha), 1);
+
+        installer.uninstall("retainpkg").unwrap();
+        assert!(!root.join("cellar/retainpkg").exists());
+        assert!(opt.join("retainpkg").symlink_metadata().is_err());
+        assert!(opt.join("retainpkg@2.0.0").symlink_metadata().is_err());
+        assert!(installer.retained_versions("retainpkg").unwrap().is_empty());
+        assert_eq!(installer.db.get_store_refcount(&bottle_sha), 0);
+    }
+
+    #[tokio::test]
+    async fn upgrade_replaces_outdated_kegs_and_installs_new_dependencies() {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        let bottle = |name: &str, version: &str| {
+            let script = format!("#!/bin/sh\necho {name} {version}\n");
+            let mut builder = tar::Builder::new(Vec::new());
+            let mut header = tar::Header::new_gnu();
+            header
+                .set_path(format!("{name}/{version}/bin/{name}"))
+                .unwrap();
+            header.set_size(script.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append(&header, script.as_bytes()).unwrap();
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+            encoder.finish().unwrap()
+        };
+        let serve = |name: &'static str, version: &'static str, deps: &'static [&'static str]| {
+            let bottle = bottle(name, version);
+            let formula = serde_json::json!({
+                "name": name,
+                "versions": { "stable": version },
+                "dependencies": deps,
+                "bottle": { "stable": { "files": { tag: {
+                    "url": format!("{}/bottles/{name}-{version}.tar.gz", mock_server.uri()),
+                    "sha256": sha256_hex(&bottle),
+                } } } },
+            });
+            (
+                Mock::given(method("GET"))
+                    .and(path(format!("/{name}.json")))
+                    .respond_with(ResponseTemplate::new(200).set_body_json(formula)),
+                Mock::given(method("GET"))
+                    .and(path(format!("/bottles/{name}-{version}.tar.gz")))
+                    .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle)),
+            )
+        };
+
+        for (name, version, deps) in [("app", "1.0.0", &["lib"][..]), ("lib", "1.0.0", &[])] {
+            let (formula, bottle) = serve(name, version, deps);
+            formula.mount(&mock_server).await;
+            bottle.mount(&mock_server).await;
+        }
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(mock_server.uri()),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+        );
+        installer.install(&["app".to_string()], true).await.unwrap();
+        assert!(installer.plan_upgrade(&[]).await.unwrap().items.is_empty());
+
+        mock_server.reset().await;
+        for (name, version, deps) in [
+            ("app", "2.0.0", &["lib", "newdep"][..]),
+            ("lib", "1.0.0", &[]),
+            ("newdep", "1.0.0", &[]),
+        ] {
+            let (formula, bottle) = serve(name, version, deps);
+            formula.mount(&mock_server).await;
+            bottle.mount(&mock_server).await;
+        }
+
+        let plan = installer.plan_upgrade(&[]).await.unwrap();
+        let mut planned: Vec<&str> = plan
+            .items
+            .iter()
+            .map(|item| item.install_name.as_str())
+            .collect();
+        planned.sort_unstable();
+        assert_eq!(planned, ["app", "newdep"]);
+        let upgrades = installer.planned_upgrades(&plan);
+        assert_eq!(
+            upgrades,
+            vec![PlannedUpgrade {
+                name: "app".to_string(),
+                from_version: "1.0.0".to_string(),
+                to_version: "2.0.0".to_string(),
+            }]
+        );
+
+        installer.execute(plan, true).await.unwrap();
+        assert_eq!(
+            installer.remove_superseded(&upgrades).unwrap(),
+            ["app@1.0.0"]
+        );
+        assert!(!root.join("cellar/app/1.0.0").exists());
+        assert!(root.join("cellar/app/2.0.0").exists());
+        assert!(installer.is_installed("newdep"));
+        assert_eq!(
+            fs::read_to_string(prefix.join("bin/app")).unwrap(),
+            "#!/bin/sh\necho app 2.0.0\n"
+        );
+
+        assert!(
+            installer
+                .plan_upgrade(&["app".to_string()])
+                .await
+                .unwrap()
+                .items
+                .is_empty()
+        );
+        assert!(matches!(
+            installer.plan_upgrade(&["missing".to_string()]).await,
+            Err(Error::NotInstalled { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn upgrade_reports_and_records_metadata_changes() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+        let bottle = create_bottle_tarball("metapkg");
+        let bottle_sha = sha256_hex(&bottle);
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(mock_server.uri()),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+        );
+
+        for (version, license, deprecated) in [("1.0.0", "MIT", false), ("2.0.0", "BUSL-1.1", true)]
+        {
+            mock_server.reset().await;
+            let formula_json = format!(
+                r#"{{
+                    "name": "metapkg",
+                    "versions": {{ "stable": "{version}" }},
+                    "license": "{license}",
+                    "deprecated": {deprecated},
+                    "dependencies": [],
+                    "bottle": {{
+                        "stable": {{
+                            "files": {{
+                                "{tag}": {{
+                                    "url": "{}/bottles/metapkg-{version}.tar.gz",
+                                    "sha256": "{bottle_sha}"
+                                }}
+                            }}
+                        }}
+                    }}
+                }}"#,
+                mock_server.uri()
+            );
+            Mock::given(method("GET"))
+                .and(path("/metapkg.json"))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/metapkg-{version}.tar.gz")))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+                .mount(&mock_server)
+                .await;
+
+            let plan = installer.plan(&["metapkg".to_string()]).await.unwrap();
+            let notices = installer.upgrade_notices(&plan);
+            if version == "1.0.0" {
+                assert!(notices.is_empty());
+            } else {
+                assert_eq!(notices.len(), 1);
+                assert_eq!(notices[0].from_version, "1.0.0");
+                assert_eq!(
+                    notices[0].changes,
+                    vec![
+                        MetadataChange::License {
+                            from: Some("MIT".to_string()),
+                            to: Some("BUSL-1.1".to_string()),
+                        },
+                        MetadataChange::Deprecated { reason: None },
+                    ]
+                );
+
+                // BUSL-1.1 needs acceptance, so the plan is refused until
+                // the user accepts it.
+                assert_eq!(
+                    installer.licenses_to_accept(&plan),
+                    vec![LicenseRequirement {
+                        name: "metapkg".to_string(),
+                        version: "2.0.0".to_string(),
+                        license: "BUSL-1.1".to_string(),
+                    }]
+                );
+                assert!(matches!(
+                    installer.execute(plan, true).await,
+                    Err(Error::InvalidArgument { .. })
+                ));
+                installer.set_accept_licenses(true);
+            }
+            let plan = installer.plan(&["metapkg".to_string()]).await.unwrap();
+            installer.execute(plan, true).await.unwrap();
+        }
+
+        let acceptances = installer.license_acceptances("metapkg").unwrap();
+        assert_eq!(acceptances.len(), 1);
+        assert_eq!(acceptances[0].license, "BUSL-1.1");
+        assert_eq!(acceptances[0].accepted_via, "--accept-licenses");
+        let plan = installer.plan(&["metapkg".to_string()]).await.unwrap();
+        assert!(installer.licenses_to_accept(&plan).is_empty());
+
+        let history: Vec<String> = installer
+            .upgrade_history("metapkg")
+            .unwrap()
+            .into_iter()
+            .map(|record| {
+                format!(
+                    "{} → {}: {}",
+                    record.from_version, record.to_version, record.change
+                )
+            })
+            .collect();
+        assert_eq!(
+            history,
+            [
+                "1.0.0 → 2.0.0: license changed: MIT → BUSL-1.1",
+                "1.0.0 → 2.0.0: deprecated",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn gc_removes_unreferenced_store_entries() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        // Create bottle
+        let bottle = create_bottle_tarball("gctest");
+        let bottle_sha = sha256_hex(&bottle);
+
+        // Create formula JSON
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{
+                "name": "gctest",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{}": {{
+                                "url": "{}/bottles/gctest-1.0.0.{}.bottle.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            tag,
+            mock_server.uri(),
+            tag,
+            bottle_sha
+        );
+
+        // Mount mocks
+        Mock::given(method("GET"))
+            .and(path("/gctest.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/gctest-1.0.0.{}.bottle.tar.gz", tag)))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&mock_server)
+            .await;
+
+        // Create installer
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let api_client = ApiClient::with_base_url(mock_server.uri());
+        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
+        let store = Store::new(&root).unwrap();
+        let cellar = Cellar::new(&root).unwrap();
+        let linker = Linker::new(&prefix).unwrap();
+        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+
+        let mut installer = Installer::new(
+            api_client,
+            blob_cache,
+            store,
+            cellar,
+            linker,
+            db,
+            prefix.clone(),
+        );
+
+        // Install and uninstall
+        installer
+            .install(&["gctest".to_string()], true)
+            .await
+            .unwrap();
+
+        // Store entry should exist before GC
+        assert!(root.join("store").join(&bottle_sha).exists());
+
+        installer.uninstall("gctest").unwrap();
+
+        // Store entry should still exist (refcount decremented but not GC'd)
+        assert!(root.join("store").join(&bottle_sha).exists());
+
+        // A just-released entry is left alone by a min-age GC, and a dry
+        // run only reports.
+        let fresh = GcOptions {
+            min_age: Some(std::time::Duration::from_secs(3600)),
+            ..GcOptions::default()
+        };
+        assert!(installer.gc_with_options(&fresh).unwrap().is_empty());
+        // Nor is one whose release time was never recorded.
+        installer.db.set_store_refcount("unknown-age", 0).unwrap();
+        let old = GcOptions {
+            min_age: Some(std::time::Duration::ZERO),
+            dry_run: true,
+        };
+        assert_eq!(
+            installer.gc_with_options(&old).unwrap(),
+            vec![bottle_sha.clone()]
+        );
+        installer.db.delete_store_ref("unknown-age").unwrap();
+        let dry_run = GcOptions {
+            dry_run: true,
+            ..GcOptions::default()
+        };
+        assert_eq!(installer.gc_with_options(&dry_run).unwrap().len(), 1);
+        assert!(root.join("store").join(&bottle_sha).exists());
+
+        // Run GC
+        let removed = installer.gc().unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0], bottle_sha);
+
+        // Store entry should now be gone
+        assert!(!root.join("store").join(&bottle_sha).exists());
+        assert!(
+            installer
+                .db
+                .get_unreferenced_store_keys()
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn audit_store_repairs_refcounts_and_adopts_orphans() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let store = Store::new(&root).unwrap();
+        for key in ["usedkey", "orphankey"] {
+            fs::create_dir_all(store.entry_path(key)).unwrap();
+        }
+
+        let mut db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("used", "1.0.0", "usedkey").unwrap();
+            tx.commit().unwrap();
+        }
+        db.set_store_refcount("usedkey", -1).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::new(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            store,
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            db,
+            prefix,
+        );
+
+        // Without repair the mismatch is only reported.
+        let audit = installer.audit_store(false, OrphanAction::Report).unwrap();
+        assert_eq!(audit.refcount_fixes.len(), 1);
+        assert!(!audit.repaired);
+        assert_eq!(installer.db.get_store_refcount("usedkey"), -1);
+
+        let audit = installer.audit_store(true, OrphanAction::Adopt).unwrap();
+        assert_eq!(audit.refcount_fixes.len(), 1);
+        assert!(audit.repaired);
+        assert_eq!(audit.refcount_fixes[0].actual, 1);
+        assert_eq!(audit.orphans, ["orphankey"]);
+        assert_eq!(installer.db.get_store_refcount("usedkey"), 1);
+
+        // Adopted orphans are unreferenced, so gc reclaims them.
+        assert_eq!(installer.gc().unwrap(), ["orphankey"]);
+        assert!(!root.join("store/orphankey").exists());
+        assert!(
+            installer
+                .audit_store(false, OrphanAction::Report)
+                .unwrap()
+                .is_clean()
+        );
+    }
+
+    #[tokio::test]
+    async fn keg_only_formulas_get_an_opt_link_but_no_prefix_links() {
+        let mock_server = MockServer::start().await;
+        let tag = get_test_bottle_tag();
+        let bottle = create_bottle_tarball("libpq");
+        let formula_json = format!(
+            r#"{{
+                "name": "libpq",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "keg_only": "it conflicts with PostgreSQL",
+                "bottle": {{ "stable": {{ "files": {{
+                    "{tag}": {{ "url": "{}/bottles/libpq.tar.gz", "sha256": "{}" }}
+                }} }} }}
+            }}"#,
+            mock_server.uri(),
+            sha256_hex(&bottle)
+        );
+        Mock::given(method("GET"))
+            .and(path("/libpq.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/libpq.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&mock_server)
+            .await;
+
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let skipped = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let events = skipped.clone();
+        let callback: Arc<ProgressCallback> = Arc::new(Box::new(move |event| {
+            if let InstallProgress::LinkSkipped { name, reason, .. } = event {
+                events.lock().unwrap().push((name, reason));
+            }
+        }));
+        let plan = test.installer.plan(&["libpq".to_string()]).await.unwrap();
+        test.installer
+            .execute_with_progress(plan, true, Some(callback))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *skipped.lock().unwrap(),
+            [(
+                "libpq".to_string(),
+                "it conflicts with PostgreSQL".to_string()
+            )]
+        );
+        assert!(!test.prefix.join("bin/libpq").exists());
+        assert!(test.prefix.join("opt/libpq/bin/libpq").exists());
+        assert_eq!(
+            test.installer.keg_only_exports("libpq"),
+            [format!(
+                "export PATH=\"{}:$PATH\"",
+                test.prefix.join("opt/libpq/bin").display()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_runs_size_downloads_without_installing() {
+        let mock_server = MockServer::start().await;
+        let tag = get_test_bottle_tag();
+        let bottle = create_bottle_tarball("app");
+        let formula_json = format!(
+            r#"{{
+                "name": "app",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{ "stable": {{ "files": {{
+                    "{tag}": {{ "url": "{}/bottles/app.tar.gz", "sha256": "{}" }}
+                }} }} }}
+            }}"#,
+            mock_server.uri(),
+            sha256_hex(&bottle)
+        );
+        Mock::given(method("GET"))
+            .and(path("/app.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/bottles/app.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let plan = test.installer.plan(&["app".to_string()]).await.unwrap();
+        let run = test.installer.dry_run(&plan).await.unwrap();
+
+        let item = &run.items[0];
+        assert!(matches!(item.action, Some(PlanAction::Install { .. })));
+        assert!(!item.cached);
+        assert_eq!(item.download_bytes, Some(bottle.len() as u64));
+        assert_eq!(run.download_bytes, bottle.len() as u64);
+        assert!(run.disk_estimate > run.download_bytes);
+        assert_eq!(run.unknown_sizes(), 0);
+        assert!(!test.prefix.join("bin/app").exists());
+        assert!(test.installer.get_installed("app").is_none());
+    }
+
+    #[tokio::test]
+    async fn kegs_are_finished_after_their_dependencies() {
+        let mock_server = MockServer::start().await;
+        let tag = get_test_bottle_tag();
+        for (name, dependencies, delay) in [("app", r#"["libdep"]"#, 0), ("libdep", "[]", 300)] {
+            let bottle = create_bottle_tarball(name);
+            let formula_json = format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "dependencies": {dependencies},
+                    "bottle": {{ "stable": {{ "files": {{
+                        "{tag}": {{ "url": "{}/bottles/{name}.tar.gz", "sha256": "{}" }}
+                    }} }} }}
+                }}"#,
+                mock_server.uri(),
+                sha256_hex(&bottle)
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
+            // The dependency lands last, but still has to be finished first.
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/{name}.tar.gz")))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_bytes(bottle)
+                        .set_delay(std::time::Duration::from_millis(delay)),
+                )
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let completed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let events = completed.clone();
+        let callback: Arc<ProgressCallback> = Arc::new(Box::new(move |event| {
+            if let InstallProgress::InstallCompleted { name, .. } = event {
+                events.lock().unwrap().push(name);
+            }
+        }));
+        let plan = test.installer.plan(&["app".to_string()]).await.unwrap();
+        let result = test
+            .installer
+            .execute_with_progress(plan, true, Some(callback))
+            .await
+            .unwrap();
+
+        assert_eq!(result.installed, 2);
+        assert_eq!(*completed.lock().unwrap(), ["libdep", "app"]);
+        assert!(test.prefix.join("bin/app").exists());
+        assert!(test.prefix.join("bin/libdep").exists());
+    }
+
+    #[tokio::test]
+    async fn foreign_bottle_tags_are_a_planning_warning() {
+        let mut test = crate::test_support::TestPrefix::new("http://127.0.0.1:9").unwrap();
+        test.installer.set_config(Config {
+            bottle_tag: Some("not_a_real_platform".to_string()),
+            ..Config::default()
+        });
+        test.installer.set_strict(true);
+
+        assert!(test.installer.list_installed().unwrap().is_empty());
+        let err = test.installer.plan(&["jq".to_string()]).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::Strict { message } if message.contains("not_a_real_platform")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn query_commands_only_open_the_database_and_cellar() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("prefix");
+        let mut installer = create_installer(&root, &prefix, 4).unwrap();
+        installer.set_debug_http(true);
+
+        assert!(installer.list_installed().unwrap().is_empty());
+        assert!(installer.leaves().unwrap().is_empty());
+        for untouched in ["db/index.sqlite3", "store", "cache"] {
+            assert!(!root.join(untouched).exists(), "{untouched} was opened");
+        }
+        assert!(!prefix.join("bin").exists());
+
+        assert!(installer.formula_index().unwrap().is_some());
+        assert!(root.join("db/index.sqlite3").exists());
+    }
+
+    #[tokio::test]
+    async fn dependents_of_a_failed_download_are_not_installed() {
+        let mock_server = MockServer::start().await;
+        let tag = get_test_bottle_tag();
+        for (name, dependencies) in [("app", r#"["libdep"]"#), ("libdep", "[]")] {
+            let bottle = create_bottle_tarball(name);
+            let formula_json = format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "dependencies": {dependencies},
+                    "bottle": {{ "stable": {{ "files": {{
+                        "{tag}": {{ "url": "{}/bottles/{name}.tar.gz", "sha256": "{}" }}
+                    }} }} }}
+                }}"#,
+                mock_server.uri(),
+                sha256_hex(&bottle)
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
+            let response = if name == "libdep" {
+                ResponseTemplate::new(404)
+            } else {
+                ResponseTemplate::new(200).set_body_bytes(bottle)
+            };
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/{name}.tar.gz")))
+                .respond_with(response)
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let plan = test.installer.plan(&["app".to_string()]).await.unwrap();
+        let Err(err) = test.installer.execute(plan, true).await else {
+            panic!("install should fail");
+        };
+
+        let message = err.to_string();
+        assert!(
+            message.starts_with("app was not installed because its dependency libdep failed"),
+            "{message}"
+        );
+        assert!(message.contains("404"), "{message}");
+
+        assert!(test.installer.get_installed("app").is_none());
+        assert!(test.installer.get_installed("libdep").is_none());
+        assert!(!test.prefix.join("bin/app").exists());
+    }
+
+    #[tokio::test]
+    async fn lockfiles_report_drift_from_the_resolved_plan() {
+        let mock_server = MockServer::start().await;
+        let tag = get_test_bottle_tag();
+        for name in ["app", "libdep"] {
+            let dependencies = if name == "app" { r#"["libdep"]"# } else { "[]" };
+            let formula_json = format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "dependencies": {dependencies},
+                    "bottle": {{ "stable": {{ "files": {{
+                        "{tag}": {{ "url": "{}/bottles/{name}.tar.gz", "sha256": "{}" }}
+                    }} }} }}
+                }}"#,
+                mock_server.uri(),
+                &name.repeat(64)[..64]
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let roots = vec!["app".to_string()];
+        let plan = test.installer.plan(&roots).await.unwrap();
+        let lockfile = test.installer.lockfile(&plan, &roots, false);
+        assert_eq!(lockfile.roots, roots);
+        assert_eq!(lockfile.formulas.len(), 2);
+        test.installer.check_lockfile(&lockfile, &plan).unwrap();
+
+        let mut drifted = lockfile.clone();
+        drifted.formulas[0].version = "0.9.0".to_string();
+        drifted.formulas[1].sha256 = "f".repeat(64);
+        drifted
+            .formulas
+            .push(crate::installer::lockfile::LockedFormula {
+                name: "gone".to_string(),
+                ..drifted.formulas[1].clone()
+            });
+        let err = test.installer.check_lockfile(&drifted, &plan).unwrap_err();
+        let Error::LockfileDrift { drift } = err else {
+            panic!("expected lockfile drift, got {err}");
+        };
+        assert_eq!(
+            drift,
+            [
+                "libdep: version 0.9.0 → 1.0.0",
+                "app: same version, different artifact",
+                "gone is no longer part of the plan",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn uninstall_protects_files_changed_since_the_install() {
+        let mock_server = MockServer::start().await;
+        let tag = get_test_bottle_tag();
+        let bottle = create_bottle_tarball("edited");
+        let formula_json = format!(
+            r#"{{
+                "name": "edited",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{ "stable": {{ "files": {{
+                    "{tag}": {{ "url": "{}/bottles/edited.tar.gz", "sha256": "{}" }}
+                }} }} }}
+            }}"#,
+            mock_server.uri(),
+            sha256_hex(&bottle)
+        );
+        Mock::given(method("GET"))
+            .and(path("/edited.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/edited.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&mock_server)
+            .await;
+
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let plan = test.installer.plan(&["edited".to_string()]).await.unwrap();
+        test.installer.execute(plan, true).await.unwrap();
+        assert!(
+            test.installer
+                .changed_keg_files("edited")
+                .unwrap()
+                .is_empty()
+        );
+
+        let keg = test.installer.keg_path("edited", "1.0.0");
+        fs::create_dir_all(keg.join("etc")).unwrap();
+        fs::write(keg.join("etc/edited.conf"), "user = me\n").unwrap();
+
+        let changed = test.installer.changed_keg_files("edited").unwrap();
+        assert_eq!(
+            changed,
+            [ChangedKegFile {
+                path: "etc/edited.conf".to_string(),
+                added: true,
+            }]
+        );
+        let err = test.installer.uninstall("edited").unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::ModifiedKegFiles { files, .. } if files == &["added etc/edited.conf"]
+        ));
+        assert!(keg.exists());
+
+        let backups = test.root.join("backups");
+        let backup = test
+            .installer
+            .backup_keg_files("edited", &changed, &backups)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(backup.join("etc/edited.conf")).unwrap(),
+            "user = me\n"
+        );
+
+        test.installer.set_force(true);
+        test.installer.uninstall("edited").unwrap();
+        assert!(!keg.exists());
+    }
+
+    #[tokio::test]
+    async fn keg_sizes_are_recorded_at_install_time() {
+        let mock_server = MockServer::start().await;
+        let tag = get_test_bottle_tag();
+        let bottle = create_bottle_tarball("sizer");
+        let formula_json = format!(
+            r#"{{
+                "name": "sizer",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{ "stable": {{ "files": {{
+                    "{tag}": {{ "url": "{}/bottles/sizer.tar.gz", "sha256": "{}" }}
+                }} }} }}
+            }}"#,
+            mock_server.uri(),
+            sha256_hex(&bottle)
+        );
+        Mock::given(method("GET"))
+            .and(path("/sizer.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/sizer.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&mock_server)
+            .await;
 
-        let content = format!("#!/bin/sh\necho {}", formula_name);
-        builder.append(&header, content.as_bytes()).unwrap();
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let plan = test.installer.plan(&["sizer".to_string()]).await.unwrap();
+        test.installer.execute(plan, true).await.unwrap();
 
-        let tar_data = builder.into_inner().unwrap();
+        let keg = test.installer.keg_path("sizer", "1.0.0");
+        let recorded = test.installer.db.keg_size("sizer", "1.0.0").unwrap();
+        assert_eq!(recorded, directory_size(&keg));
 
-        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(&tar_data).unwrap();
-        encoder.finish().unwrap()
-    }
+        // Later reads come from the database rather than a walk of the keg.
+        fs::write(keg.join("bin/extra"), vec![0u8; 4096]).unwrap();
+        assert_eq!(test.installer.keg_size("sizer", "1.0.0"), Some(recorded));
+        fs::remove_file(keg.join("bin/extra")).unwrap();
 
-    fn sha256_hex(data: &[u8]) -> String {
-        use sha2::{Digest, Sha256};
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        format!("{:x}", hasher.finalize())
+        test.installer.uninstall("sizer").unwrap();
+        assert_eq!(test.installer.db.keg_size("sizer", "1.0.0"), None);
     }
 
-    fn get_test_bottle_tag() -> &'static str {
-        if cfg!(target_os = "linux") {
-            "x86_64_linux"
-        } else if cfg!(target_arch = "x86_64") {
-            "sonoma"
-        } else {
-            "arm64_sonoma"
+    #[tokio::test]
+    async fn service_definitions_are_written_into_the_keg() {
+        let mock_server = MockServer::start().await;
+        for (name, service) in [
+            (
+                "redis",
+                r#""service": {
+                    "run": ["$HOMEBREW_PREFIX/opt/redis/bin/redis-server", "$HOMEBREW_PREFIX/etc/redis.conf"],
+                    "keep_alive": { "always": true },
+                    "log_path": "$HOMEBREW_PREFIX/var/log/redis.log"
+                },"#,
+            ),
+            ("jq", ""),
+        ] {
+            let formula_json = format!(
+                r#"{{ "name": "{name}", "versions": {{ "stable": "1.0.0" }}, "dependencies": [], {service} "bottle": {{}} }}"#
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
         }
-    }
 
-    #[test]
-    fn dependency_cellar_path_uses_formula_token_for_tap_name() {
-        let tmp = TempDir::new().unwrap();
-        let cellar = Cellar::new(tmp.path()).unwrap();
-        let path = dependency_cellar_path(&cellar, "hashicorp/tap/terraform", "1.10.0");
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let installer = &mut test.installer;
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("redis", "1.0.0", "rediskey").unwrap();
+            tx.record_install("jq", "1.0.0", "jqkey").unwrap();
+            tx.commit().unwrap();
+        }
+        for name in ["redis", "jq"] {
+            fs::create_dir_all(installer.cellar.keg_path(name, "1.0.0")).unwrap();
+        }
 
-        assert!(path.ends_with("cellar/terraform/1.10.0"));
+        let definition = installer
+            .write_service_definition("redis", ServiceKind::Systemd)
+            .await
+            .unwrap();
+        assert_eq!(
+            definition,
+            installer
+                .cellar
+                .keg_path("redis", "1.0.0")
+                .join("zerobrew.redis.service")
+        );
+        let prefix = test.prefix.display();
+        let unit = fs::read_to_string(&definition).unwrap();
+        assert!(unit.contains(&format!(
+            "ExecStart={prefix}/opt/redis/bin/redis-server {prefix}/etc/redis.conf\n"
+        )));
+        assert!(unit.contains("Restart=always\n"));
+        assert!(test.prefix.join("var/log").is_dir());
+
+        let installer = &mut test.installer;
+        assert!(matches!(
+            installer
+                .write_service_definition("jq", ServiceKind::Launchd)
+                .await,
+            Err(Error::UnsupportedFormula { .. })
+        ));
+        assert!(matches!(
+            installer
+                .write_service_definition("postgresql@16", ServiceKind::Launchd)
+                .await,
+            Err(Error::NotInstalled { .. })
+        ));
     }
 
     #[test]
-    fn dependency_cellar_path_keeps_core_formula_name() {
-        let tmp = TempDir::new().unwrap();
-        let cellar = Cellar::new(tmp.path()).unwrap();
-        let path = dependency_cellar_path(&cellar, "openssl@3", "3.3.2");
+    fn cleanup_removes_superseded_kegs_but_keeps_retained_ones() {
+        let mut test = crate::test_support::TestPrefix::new("http://127.0.0.1:9").unwrap();
+        let installer = &mut test.installer;
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("jq", "1.7.1", "jqkey").unwrap();
+            tx.retain_keg("jq", "1.7", "oldkey").unwrap();
+            tx.commit().unwrap();
+        }
+        for version in ["1.6", "1.7", "1.7.1"] {
+            let keg = installer.cellar.keg_path("jq", version);
+            fs::create_dir_all(keg.join("bin")).unwrap();
+            fs::write(keg.join("bin/jq"), "binary").unwrap();
+        }
+        // Not installed at all, so not ours to judge.
+        fs::create_dir_all(installer.cellar.keg_path("stray", "1.0")).unwrap();
+
+        let options = CleanupOptions {
+            dry_run: true,
+            superseded_kegs: true,
+            cache_max_age: Some(std::time::Duration::from_secs(86_400)),
+            ..CleanupOptions::default()
+        };
+        let report = installer.cleanup(&options).unwrap();
+        assert_eq!(
+            report.kegs,
+            [CleanedItem {
+                label: "jq@1.6".to_string(),
+                bytes: 6,
+            }]
+        );
+        assert_eq!(report.bytes(), 6);
+        assert!(installer.cellar.keg_path("jq", "1.6").exists());
 
-        assert!(path.ends_with("cellar/openssl@3/3.3.2"));
+        installer
+            .cleanup(&CleanupOptions {
+                dry_run: false,
+                ..options
+            })
+            .unwrap();
+        assert!(!installer.cellar.keg_path("jq", "1.6").exists());
+        assert!(installer.cellar.keg_path("jq", "1.7").exists());
+        assert!(installer.cellar.keg_path("jq", "1.7.1").exists());
+        assert!(installer.cellar.keg_path("stray", "1.0").exists());
     }
 
     #[test]
-    fn dependency_cellar_path_uses_name_from_db_record() {
-        let tmp = TempDir::new().unwrap();
-        let cellar = Cellar::new(tmp.path()).unwrap();
+    fn cleanup_keeps_the_latest_kegs() {
+        let mut test = crate::test_support::TestPrefix::new("http://127.0.0.1:9").unwrap();
+        let installer = &mut test.installer;
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("jq", "1.7.1", "jqkey").unwrap();
+            tx.retain_keg("jq", "1.6", "oldkey").unwrap();
+            tx.retain_keg("jq", "1.7", "prevkey").unwrap();
+            tx.commit().unwrap();
+        }
+        for version in ["1.5", "1.6", "1.7", "1.7.1"] {
+            let keg = installer.cellar.keg_path("jq", version);
+            fs::create_dir_all(keg.join("bin")).unwrap();
+            fs::write(keg.join("bin/jq"), "binary").unwrap();
+        }
+        fs::create_dir_all(installer.store.get().unwrap().entry_path("oldkey")).unwrap();
 
-        let db_path = tmp.path().join("zb.sqlite3");
-        let mut db = Database::open(&db_path).unwrap();
-        let tx = db.transaction().unwrap();
-        tx.record_install("hashicorp/tap/terraform", "1.10.0", "store-key")
+        let options = CleanupOptions {
+            dry_run: true,
+            keep_latest_kegs: Some(2),
+            ..CleanupOptions::default()
+        };
+        let report = installer.cleanup(&options).unwrap();
+        let labels =
+            |items: &[CleanedItem]| items.iter().map(|i| i.label.clone()).collect::<Vec<_>>();
+        assert_eq!(labels(&report.kegs), ["jq@1.5", "jq@1.6"]);
+        assert_eq!(labels(&report.store_entries), ["oldkey"]);
+        assert!(report.blobs.is_empty());
+        assert!(installer.cellar.keg_path("jq", "1.6").exists());
+
+        let report = installer
+            .cleanup(&CleanupOptions {
+                dry_run: false,
+                ..options
+            })
             .unwrap();
-        tx.commit().unwrap();
-
-        let keg = db.get_installed("hashicorp/tap/terraform").unwrap();
-        let path = dependency_cellar_path(&cellar, &keg.name, &keg.version);
-
-        assert!(path.ends_with("cellar/terraform/1.10.0"));
+        assert_eq!(labels(&report.store_entries), ["oldkey"]);
+        assert!(!installer.cellar.keg_path("jq", "1.5").exists());
+        assert!(!installer.cellar.keg_path("jq", "1.6").exists());
+        assert!(installer.cellar.keg_path("jq", "1.7").exists());
+        assert!(!installer.store.get().unwrap().entry_path("oldkey").exists());
+        let retained: Vec<String> = installer
+            .retained_versions("jq")
+            .unwrap()
+            .into_iter()
+            .map(|keg| keg.version)
+            .collect();
+        assert_eq!(retained, ["1.7"]);
     }
 
     #[test]
-    fn source_keg_backup_can_restore_previous_installation() {
-        let tmp = TempDir::new().unwrap();
-        let keg_path = tmp.path().join("cellar").join("example").join("1.0.0");
-        fs::create_dir_all(&keg_path).unwrap();
-        fs::write(keg_path.join("old.txt"), "old").unwrap();
-
-        let backup = Installer::backup_existing_source_keg(&keg_path, "example", "1.0.0").unwrap();
-        let backup = backup.expect("backup path should exist");
-
-        assert!(!keg_path.exists());
-        assert!(backup.exists());
-
-        fs::create_dir_all(&keg_path).unwrap();
-        fs::write(keg_path.join("new.txt"), "new").unwrap();
+    fn link_exclusions_are_recorded_and_audited() {
+        let mut test = crate::test_support::TestPrefix::new("http://127.0.0.1:9").unwrap();
+        let prefix = test.prefix.clone();
+        let installer = &mut test.installer;
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("python", "3.12.1", "pykey").unwrap();
+            tx.commit().unwrap();
+        }
+        let keg = installer.cellar.keg_path("python", "3.12.1");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::write(keg.join("bin/python3"), "binary").unwrap();
+        fs::write(keg.join("bin/pip3"), "binary").unwrap();
+
+        installer.link("python", &[], false).unwrap();
+        assert!(installer.audit_links().unwrap().is_clean());
+
+        // Excluding python3 after the fact leaves a stale link until relinked.
+        installer.set_config(
+            Config::parse(
+                "[formula.python]
+link_exclude = [\"bin/python3\"]\n",
+            )
+            .unwrap(),
+        );
+        let python3 = prefix.join("bin/python3");
+        let audit = installer.audit_links().unwrap();
+        assert_eq!(
+            audit.excluded_but_linked,
+            [("python".to_string(), python3.clone())]
+        );
 
-        Installer::restore_source_keg_from_backup(&keg_path, &backup, "example", "1.0.0").unwrap();
+        let linked = installer.link("python", &[], false).unwrap();
+        assert_eq!(linked.len(), 1);
+        assert!(!python3.exists());
+        assert_eq!(installer.link_exclusions("python").unwrap(), [python3]);
+        assert_eq!(
+            installer.linked_files("python").unwrap(),
+            [prefix.join("bin/pip3")]
+        );
+        let audit = installer.audit_links().unwrap();
+        assert!(audit.is_clean());
+        assert_eq!((audit.checked, audit.excluded), (1, 1));
 
-        assert!(keg_path.join("old.txt").exists());
-        assert!(!keg_path.join("new.txt").exists());
-        assert!(!backup.exists());
+        installer.unlink("python", &[]).unwrap();
+        assert!(installer.link_exclusions("python").unwrap().is_empty());
     }
 
     #[test]
-    fn backup_existing_source_keg_returns_none_when_keg_is_missing() {
-        let tmp = TempDir::new().unwrap();
-        let missing_keg = tmp.path().join("cellar").join("example").join("1.0.0");
-
-        let backup =
-            Installer::backup_existing_source_keg(&missing_keg, "example", "1.0.0").unwrap();
+    fn link_previews_and_overwrites_conflicting_files() {
+        let mut test = crate::test_support::TestPrefix::new("http://127.0.0.1:9").unwrap();
+        let prefix = test.prefix.clone();
+        let installer = &mut test.installer;
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("jq", "1.7.1", "jqkey").unwrap();
+            tx.commit().unwrap();
+        }
+        let keg = installer.cellar.keg_path("jq", "1.7.1");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::write(keg.join("bin/jq"), "binary").unwrap();
+        fs::write(keg.join("bin/jq-docs"), "binary").unwrap();
+        let stray = prefix.join("bin/jq");
+        fs::write(&stray, "someone else's jq").unwrap();
+
+        let preview = installer.preview_link("jq", &[]).unwrap();
+        assert_eq!(preview.links.len(), 2);
+        assert_eq!(preview.conflicts.len(), 1);
+        assert_eq!(preview.conflicts[0].path, stray);
+        assert!(!prefix.join("bin/jq-docs").exists());
 
-        assert!(backup.is_none());
+        assert!(matches!(
+            installer.link("jq", &[], false),
+            Err(Error::LinkConflict { .. })
+        ));
+        let linked = installer.link("jq", &[], true).unwrap();
+        assert_eq!(linked.len(), 2);
+        assert_eq!(fs::read(&stray).unwrap(), b"binary");
+        assert!(installer.preview_link("jq", &[]).unwrap().links.is_empty());
+
+        let only = ["bin/jq".to_string()];
+        assert_eq!(
+            installer.preview_unlink("jq", &only).unwrap(),
+            std::slice::from_ref(&stray)
+        );
+        assert_eq!(installer.preview_unlink("jq", &[]).unwrap().len(), 2);
+        assert!(stray.is_symlink());
     }
 
     #[tokio::test]
-    async fn install_completes_successfully() {
+    async fn gc_does_not_remove_referenced_store_entries() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
 
         // Create bottle
-        let bottle = create_bottle_tarball("testpkg");
+        let bottle = create_bottle_tarball("keepme");
         let bottle_sha = sha256_hex(&bottle);
 
         // Create formula JSON
         let tag = get_test_bottle_tag();
         let formula_json = format!(
             r#"{{
-                "name": "testpkg",
+                "name": "keepme",
                 "versions": {{ "stable": "1.0.0" }},
                 "dependencies": [],
                 "bottle": {{
                     "stable": {{
                         "files": {{
                             "{}": {{
-                                "url": "{}/bottles/testpkg-1.0.0.{}.bottle.tar.gz",
+                                "url": "{}/bottles/keepme-1.0.0.{}.bottle.tar.gz",
                                 "sha256": "{}"
                             }}
                         }}
@@ -1291,24 +6953,20 @@ mod tests {
             bottle_sha
         );
 
-        // Mount formula API mock
+        // Mount mocks
         Mock::given(method("GET"))
-            .and(path("/testpkg.json"))
+            .and(path("/keepme.json"))
             .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
             .mount(&mock_server)
             .await;
 
-        // Mount bottle download mock
         Mock::given(method("GET"))
-            .and(path(format!(
-                "/bottles/testpkg-1.0.0.{}.bottle.tar.gz",
-                tag
-            )))
+            .and(path(format!("/bottles/keepme-1.0.0.{}.bottle.tar.gz", tag)))
             .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
             .mount(&mock_server)
             .await;
 
-        // Create installer with mocked API
+        // Create installer
         let root = tmp.path().join("zerobrew");
         let prefix = tmp.path().join("homebrew");
         fs::create_dir_all(root.join("db")).unwrap();
@@ -1330,45 +6988,46 @@ mod tests {
             prefix.clone(),
         );
 
-        // Install
+        // Install but don't uninstall
         installer
-            .install(&["testpkg".to_string()], true)
+            .install(&["keepme".to_string()], true)
             .await
             .unwrap();
 
-        // Verify keg exists
-        assert!(root.join("cellar/testpkg/1.0.0").exists());
+        // Store entry should exist
+        assert!(root.join("store").join(&bottle_sha).exists());
 
-        // Verify link exists
-        assert!(prefix.join("bin/testpkg").exists());
+        // Run GC - should not remove anything
+        let removed = installer.gc().unwrap();
+        assert!(removed.is_empty());
 
-        // Verify database records
-        let installed = installer.db.get_installed("testpkg");
-        assert!(installed.is_some());
-        assert_eq!(installed.unwrap().version, "1.0.0");
+        // Store entry should still exist
+        assert!(root.join("store").join(&bottle_sha).exists());
     }
 
     #[tokio::test]
-    async fn uninstall_cleans_everything() {
+    async fn install_with_dependencies() {
         let mock_server = MockServer::start().await;
-        let tmp = TempDir::new().unwrap();
 
-        // Create bottle
-        let bottle = create_bottle_tarball("uninstallme");
-        let bottle_sha = sha256_hex(&bottle);
+        // Create bottles
+        let dep_bottle = create_bottle_tarball("deplib");
+        let dep_sha = sha256_hex(&dep_bottle);
 
-        // Create formula JSON
+        let main_bottle = create_bottle_tarball("mainpkg");
+        let main_sha = sha256_hex(&main_bottle);
+
+        // Create formula JSONs
         let tag = get_test_bottle_tag();
-        let formula_json = format!(
+        let dep_json = format!(
             r#"{{
-                "name": "uninstallme",
+                "name": "deplib",
                 "versions": {{ "stable": "1.0.0" }},
                 "dependencies": [],
                 "bottle": {{
                     "stable": {{
                         "files": {{
                             "{}": {{
-                                "url": "{}/bottles/uninstallme-1.0.0.{}.bottle.tar.gz",
+                                "url": "{}/bottles/deplib-1.0.0.{}.bottle.tar.gz",
                                 "sha256": "{}"
                             }}
                         }}
@@ -1378,88 +7037,19 @@ mod tests {
             tag,
             mock_server.uri(),
             tag,
-            bottle_sha
-        );
-
-        // Mount mocks
-        Mock::given(method("GET"))
-            .and(path("/uninstallme.json"))
-            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
-            .mount(&mock_server)
-            .await;
-
-        Mock::given(method("GET"))
-            .and(path(format!(
-                "/bottles/uninstallme-1.0.0.{}.bottle.tar.gz",
-                tag
-            )))
-            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
-            .mount(&mock_server)
-            .await;
-
-        // Create installer
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client = ApiClient::with_base_url(mock_server.uri());
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-        );
-
-        // Install
-        installer
-            .install(&["uninstallme".to_string()], true)
-            .await
-            .unwrap();
-
-        // Verify installed
-        assert!(installer.is_installed("uninstallme"));
-        assert!(root.join("cellar/uninstallme/1.0.0").exists());
-        assert!(prefix.join("bin/uninstallme").exists());
-
-        // Uninstall
-        installer.uninstall("uninstallme").unwrap();
-
-        // Verify everything cleaned up
-        assert!(!installer.is_installed("uninstallme"));
-        assert!(!root.join("cellar/uninstallme/1.0.0").exists());
-        assert!(!prefix.join("bin/uninstallme").exists());
-    }
-
-    #[tokio::test]
-    async fn gc_removes_unreferenced_store_entries() {
-        let mock_server = MockServer::start().await;
-        let tmp = TempDir::new().unwrap();
-
-        // Create bottle
-        let bottle = create_bottle_tarball("gctest");
-        let bottle_sha = sha256_hex(&bottle);
+            dep_sha
+        );
 
-        // Create formula JSON
-        let tag = get_test_bottle_tag();
-        let formula_json = format!(
+        let main_json = format!(
             r#"{{
-                "name": "gctest",
-                "versions": {{ "stable": "1.0.0" }},
-                "dependencies": [],
+                "name": "mainpkg",
+                "versions": {{ "stable": "2.0.0" }},
+                "dependencies": ["deplib"],
                 "bottle": {{
                     "stable": {{
                         "files": {{
                             "{}": {{
-                                "url": "{}/bottles/gctest-1.0.0.{}.bottle.tar.gz",
+                                "url": "{}/bottles/mainpkg-2.0.0.{}.bottle.tar.gz",
                                 "sha256": "{}"
                             }}
                         }}
@@ -1469,121 +7059,202 @@ mod tests {
             tag,
             mock_server.uri(),
             tag,
-            bottle_sha
+            main_sha
         );
 
         // Mount mocks
         Mock::given(method("GET"))
-            .and(path("/gctest.json"))
-            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .and(path("/deplib.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&dep_json))
             .mount(&mock_server)
             .await;
 
         Mock::given(method("GET"))
-            .and(path(format!("/bottles/gctest-1.0.0.{}.bottle.tar.gz", tag)))
-            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .and(path("/mainpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&main_json))
             .mount(&mock_server)
             .await;
 
-        // Create installer
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/deplib-1.0.0.{}.bottle.tar.gz", tag)))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(dep_bottle))
+            .mount(&mock_server)
+            .await;
 
-        let api_client = ApiClient::with_base_url(mock_server.uri());
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/bottles/mainpkg-2.0.0.{}.bottle.tar.gz",
+                tag
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(main_bottle))
+            .mount(&mock_server)
+            .await;
 
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-        );
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let installer = &mut test.installer;
 
-        // Install and uninstall
+        // Install main package (should also install dependency)
         installer
-            .install(&["gctest".to_string()], true)
+            .install(&["mainpkg".to_string()], true)
             .await
             .unwrap();
 
-        // Store entry should exist before GC
-        assert!(root.join("store").join(&bottle_sha).exists());
+        // Both packages should be installed
+        assert!(installer.db.get_installed("mainpkg").is_some());
+        assert!(installer.db.get_installed("deplib").is_some());
 
-        installer.uninstall("gctest").unwrap();
+        let stats = installer.download_stats().unwrap();
+        assert_eq!(stats.hosts.len(), 1);
+        assert_eq!(stats.hosts[0].host, "127.0.0.1");
+        assert_eq!(stats.hosts[0].downloads, 2);
+        assert_eq!(stats.cache_hits, 0);
 
-        // Store entry should still exist (refcount decremented but not GC'd)
-        assert!(root.join("store").join(&bottle_sha).exists());
+        assert_eq!(installer.leaves().unwrap(), ["mainpkg"]);
+        assert_eq!(
+            installer.installed_dependencies("mainpkg").unwrap(),
+            ["deplib"]
+        );
 
-        // Run GC
-        let removed = installer.gc().unwrap();
-        assert_eq!(removed.len(), 1);
-        assert_eq!(removed[0], bottle_sha);
+        // The dependency can't be removed from under mainpkg
+        let err = installer.uninstall("deplib").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::HasDependents { ref dependents, .. } if dependents == &["mainpkg"]
+        ));
+        assert!(installer.db.get_installed("deplib").is_some());
 
-        // Store entry should now be gone
-        assert!(!root.join("store").join(&bottle_sha).exists());
-        assert!(
-            installer
-                .db
-                .get_unreferenced_store_keys()
-                .unwrap()
-                .is_empty()
-        );
+        let order = installer.uninstall_order(&["deplib".to_string(), "mainpkg".to_string()]);
+        assert_eq!(order, ["mainpkg", "deplib"]);
+        for name in &order {
+            installer.uninstall(name).unwrap();
+        }
+        assert!(installer.db.get_installed("deplib").is_none());
     }
 
     #[tokio::test]
-    async fn gc_does_not_remove_referenced_store_entries() {
+    async fn reinstall_restores_a_damaged_keg_without_downloading() {
         let mock_server = MockServer::start().await;
-        let tmp = TempDir::new().unwrap();
-
-        // Create bottle
-        let bottle = create_bottle_tarball("keepme");
+        let bottle = create_bottle_tarball("fixpkg");
         let bottle_sha = sha256_hex(&bottle);
-
-        // Create formula JSON
         let tag = get_test_bottle_tag();
         let formula_json = format!(
             r#"{{
-                "name": "keepme",
+                "name": "fixpkg",
                 "versions": {{ "stable": "1.0.0" }},
                 "dependencies": [],
                 "bottle": {{
                     "stable": {{
                         "files": {{
-                            "{}": {{
-                                "url": "{}/bottles/keepme-1.0.0.{}.bottle.tar.gz",
-                                "sha256": "{}"
+                            "{tag}": {{
+                                "url": "{}/bottles/fixpkg.tar.gz",
+                                "sha256": "{bottle_sha}"
                             }}
                         }}
                     }}
                 }}
             }}"#,
-            tag,
-            mock_server.uri(),
-            tag,
-            bottle_sha
+            mock_server.uri()
         );
-
-        // Mount mocks
         Mock::given(method("GET"))
-            .and(path("/keepme.json"))
-            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .and(path("/fixpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/fixpkg.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .expect(1)
             .mount(&mock_server)
             .await;
 
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let installer = &mut test.installer;
+        installer
+            .install(&["fixpkg".to_string()], true)
+            .await
+            .unwrap();
+
+        let keg_bin = installer
+            .cellar
+            .keg_path("fixpkg", "1.0.0")
+            .join("bin/fixpkg");
+        let linked_bin = test.prefix.join("bin/fixpkg");
+        fs::remove_file(&keg_bin).unwrap();
+        assert!(!linked_bin.exists());
+
+        let source = installer.reinstall("fixpkg").await.unwrap();
+        assert_eq!(source, ReinstallSource::Store);
+        assert!(keg_bin.exists());
+        assert!(linked_bin.exists());
+        assert!(installer.is_linked("fixpkg"));
+
+        // Without the store entry the cached bottle is unpacked again.
+        installer
+            .store
+            .get()
+            .unwrap()
+            .remove_entry(&bottle_sha)
+            .unwrap();
+        fs::remove_file(&keg_bin).unwrap();
+        let source = installer.reinstall("fixpkg").await.unwrap();
+        assert_eq!(source, ReinstallSource::CachedBottle);
+        assert!(linked_bin.exists());
+
+        assert!(matches!(
+            installer.reinstall("missing").await,
+            Err(Error::NotInstalled { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn formulas_sharing_a_bottle_download_it_once() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("sharedpkg");
+        let bottle_sha = sha256_hex(&bottle);
+        let tag = get_test_bottle_tag();
+
+        for (name, deps) in [("sharedpkg", "[]"), ("aliaspkg", r#"["sharedpkg"]"#)] {
+            let json = format!(
+                r#"{{
+                    "name": "{}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "dependencies": {},
+                    "bottle": {{
+                        "stable": {{
+                            "files": {{
+                                "{}": {{
+                                    "url": "{}/bottles/sharedpkg-1.0.0.{}.bottle.tar.gz",
+                                    "sha256": "{}"
+                                }}
+                            }}
+                        }}
+                    }}
+                }}"#,
+                name,
+                deps,
+                tag,
+                mock_server.uri(),
+                tag,
+                bottle_sha
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(json))
+                .mount(&mock_server)
+                .await;
+        }
+
         Mock::given(method("GET"))
-            .and(path(format!("/bottles/keepme-1.0.0.{}.bottle.tar.gz", tag)))
-            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .and(path(format!(
+                "/bottles/sharedpkg-1.0.0.{}.bottle.tar.gz",
+                tag
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
             .mount(&mock_server)
             .await;
 
-        // Create installer
         let root = tmp.path().join("zerobrew");
         let prefix = tmp.path().join("homebrew");
         fs::create_dir_all(root.join("db")).unwrap();
@@ -1605,140 +7276,395 @@ mod tests {
             prefix.clone(),
         );
 
-        // Install but don't uninstall
+        let plan = installer.plan(&["aliaspkg".to_string()]).await.unwrap();
+        assert_eq!(plan.items.len(), 2);
+
+        let (requests, groups) = dedupe_bottle_downloads(&plan.items);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(groups, vec![vec![0, 1]]);
+
+        let result = installer.execute(plan, false).await.unwrap();
+        assert_eq!(result.installed, 2);
+
+        let shared = installer.db.get_installed("sharedpkg").unwrap();
+        let alias = installer.db.get_installed("aliaspkg").unwrap();
+        assert_eq!(shared.store_key, alias.store_key);
+        assert_eq!(installer.db.get_store_refcount(&bottle_sha), 2);
+    }
+
+    #[tokio::test]
+    async fn pinned_index_resolves_core_formulas_offline() {
+        let mut test = crate::test_support::TestPrefix::new("http://127.0.0.1:9").unwrap();
+        let installer = &mut test.installer;
+        let jq = serde_json::json!({
+            "name": "jq",
+            "versions": { "stable": "1.6" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": {} } },
+        });
+        let mut index = FormulaIndex::in_memory().unwrap();
+        index
+            .apply(&[(jq, None)], &[], true, "snapshot:abc")
+            .unwrap();
+        installer.set_formula_index(index);
+        assert!(installer.fetch_formula("jq").await.is_err());
+
+        let pin = IndexPin {
+            requested: "2024-06-01".to_string(),
+            sha: "abc".to_string(),
+        };
         installer
-            .install(&["keepme".to_string()], true)
-            .await
+            .formula_index()
+            .unwrap()
+            .unwrap()
+            .set_pin(Some(&pin))
+            .unwrap();
+        assert_eq!(
+            installer
+                .fetch_formula("jq")
+                .await
+                .unwrap()
+                .effective_version(),
+            "1.6"
+        );
+        assert!(matches!(
+            installer.fetch_formula("wget").await,
+            Err(Error::MissingFormula { .. })
+        ));
+        assert!(installer.update_index().await.is_err());
+
+        assert_eq!(installer.unpin_index().unwrap(), Some(pin));
+        assert!(installer.fetch_formula("jq").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn plans_from_an_old_index_report_its_age() {
+        let mut test = crate::test_support::TestPrefix::new("http://127.0.0.1:9").unwrap();
+        let installer = &mut test.installer;
+        let jq = serde_json::json!({
+            "name": "jq",
+            "versions": { "stable": "1.6" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { get_test_bottle_tag(): {
+                "url": "http://127.0.0.1:9/bottles/jq.tar.gz",
+                "sha256": format!("{:0>64}", 1),
+            } } } },
+        });
+        let names = ["jq".to_string()];
+
+        installer.set_offline(true);
+        assert!(matches!(
+            installer.plan(&names).await,
+            Err(Error::ExecutionError { .. })
+        ));
+
+        let mut index = FormulaIndex::in_memory().unwrap();
+        index.apply(&[(jq, None)], &[], true, "v1").unwrap();
+        index.set_updated_at(unix_now() - 30 * 24 * 60 * 60);
+        installer.set_formula_index(index);
+
+        // Offline plans come from the index and don't count as stale.
+        let plan = installer.plan(&names).await.unwrap();
+        assert!(plan.metadata_fetched_at.is_some());
+        assert_eq!(installer.stale_metadata_days(&plan), None);
+
+        // Online, a pinned index still answers, and its age is reported.
+        installer.set_offline(false);
+        assert!(installer.plan(&names).await.is_err());
+        let pin = IndexPin {
+            requested: "2024-06-01".to_string(),
+            sha: "abc".to_string(),
+        };
+        installer
+            .formula_index()
+            .unwrap()
+            .unwrap()
+            .set_pin(Some(&pin))
             .unwrap();
+        let plan = installer.plan(&names).await.unwrap();
+        assert_eq!(installer.stale_metadata_days(&plan), Some(30));
 
-        // Store entry should exist
-        assert!(root.join("store").join(&bottle_sha).exists());
+        installer.set_config(Config {
+            index: crate::config::IndexConfig {
+                stale_after_days: 0,
+            },
+            ..Config::default()
+        });
+        assert_eq!(installer.stale_metadata_days(&plan), None);
+    }
 
-        // Run GC - should not remove anything
-        let removed = installer.gc().unwrap();
-        assert!(removed.is_empty());
+    #[tokio::test]
+    async fn repeated_plans_reuse_cached_closure_until_index_changes() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
 
-        // Store entry should still exist
-        assert!(root.join("store").join(&bottle_sha).exists());
+        let formula_json = |name: &str, deps: &[&str]| {
+            serde_json::json!({
+                "name": name,
+                "versions": { "stable": "1.0.0" },
+                "dependencies": deps,
+                "bottle": { "stable": { "files": { tag: {
+                    "url": format!("{}/bottles/{name}.tar.gz", mock_server.uri()),
+                    "sha256": format!("{:0>64}", name.len()),
+                } } } },
+            })
+        };
+        let app = formula_json("app", &["lib"]);
+        let lib = formula_json("lib", &[]);
+
+        for (name, json) in [("app", &app), ("lib", &lib)] {
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json))
+                .expect(2)
+                .mount(&mock_server)
+                .await;
+        }
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(mock_server.uri()),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+        );
+        let mut index = FormulaIndex::in_memory().unwrap();
+        index
+            .apply(&[(app.clone(), None), (lib.clone(), None)], &[], true, "v1")
+            .unwrap();
+        installer.set_formula_index(index);
+
+        let names = |plan: &InstallPlan| -> Vec<String> {
+            plan.items
+                .iter()
+                .map(|item| item.install_name.clone())
+                .collect()
+        };
+        let key = installer.closure_key("app");
+        let cached = |installer: &Installer| {
+            installer
+                .formula_index()
+                .unwrap()
+                .unwrap()
+                .cached_closure(&key)
+        };
+
+        // Online plans resolve live and leave the cache alone.
+        let live = installer.plan(&["app".to_string()]).await.unwrap();
+        assert_eq!(cached(&installer), None);
+        assert!(live.metadata_fetched_at.is_some());
+        assert_eq!(installer.stale_metadata_days(&live), None);
+
+        installer.set_offline(true);
+        let first = installer.plan(&["app".to_string()]).await.unwrap();
+        assert_eq!(
+            cached(&installer),
+            Some(vec!["app".to_string(), "lib".to_string()])
+        );
+
+        // Served from the cache.
+        let second = installer.plan(&["app".to_string()]).await.unwrap();
+        assert_eq!(names(&live), names(&first));
+        assert_eq!(names(&first), names(&second));
+        assert_eq!(first.hash("t"), second.hash("t"));
+
+        // An index update invalidates the closure.
+        installer
+            .index
+            .get_mut()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .apply(&[(lib, None)], &[], false, "v2")
+            .unwrap();
+        assert_eq!(cached(&installer), None);
+        installer.plan(&["app".to_string()]).await.unwrap();
+        assert!(cached(&installer).is_some());
+
+        installer.set_offline(false);
+        installer.plan(&["app".to_string()]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn relocate_repatches_kegs_and_rewrites_links_after_prefix_move() {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        let script = "#!/bin/sh\necho @@HOMEBREW_PREFIX@@/share\n";
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_path("relo/1.0.0/bin/relo").unwrap();
+        header.set_size(script.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder.append(&header, script.as_bytes()).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        let bottle = encoder.finish().unwrap();
+
+        let formula = serde_json::json!({
+            "name": "relo",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}/bottles/relo.tar.gz", mock_server.uri()),
+                "sha256": sha256_hex(&bottle),
+            } } } },
+        });
+        Mock::given(method("GET"))
+            .and(path("/relo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&formula))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/relo.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+        let installer_at = |prefix: &Path| {
+            Installer::new(
+                ApiClient::with_base_url(mock_server.uri()),
+                BlobCache::new(&root.join("cache")).unwrap(),
+                Store::new(&root).unwrap(),
+                Cellar::new_at(prefix.join("Cellar")).unwrap(),
+                Linker::new(prefix).unwrap(),
+                Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+                prefix.to_path_buf(),
+            )
+        };
+
+        let old_prefix = tmp.path().join("old");
+        let mut installer = installer_at(&old_prefix);
+        installer
+            .install(&["relo".to_string()], true)
+            .await
+            .unwrap();
+        drop(installer);
+
+        let new_prefix = tmp.path().join("new");
+        fs::rename(&old_prefix, &new_prefix).unwrap();
+        let mut installer = installer_at(&new_prefix);
+        assert!(installer.relocate(&new_prefix).is_err());
+
+        let report = installer.relocate(&old_prefix).unwrap();
+        assert_eq!(report.rematerialized, vec!["relo".to_string()]);
+        assert_eq!(report.relinked, vec!["relo".to_string()]);
+        assert!(report.needs_reinstall.is_empty());
+
+        let patched = fs::read_to_string(new_prefix.join("bin/relo")).unwrap();
+        assert!(patched.contains(&format!("{}/share", new_prefix.display())));
+        assert!(!patched.contains(&old_prefix.display().to_string()));
+
+        let linked = installer.db.linked_files("relo").unwrap();
+        assert!(!linked.is_empty());
+        for (link, target) in linked {
+            assert!(Path::new(&link).starts_with(&new_prefix), "{link}");
+            assert!(Path::new(&target).starts_with(&new_prefix), "{target}");
+        }
     }
 
     #[tokio::test]
-    async fn install_with_dependencies() {
+    async fn security_audit_reports_advisories_and_falls_back_to_snapshot() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-
-        // Create bottles
-        let dep_bottle = create_bottle_tarball("deplib");
-        let dep_sha = sha256_hex(&dep_bottle);
-
-        let main_bottle = create_bottle_tarball("mainpkg");
-        let main_sha = sha256_hex(&main_bottle);
-
-        // Create formula JSONs
         let tag = get_test_bottle_tag();
-        let dep_json = format!(
-            r#"{{
-                "name": "deplib",
-                "versions": {{ "stable": "1.0.0" }},
-                "dependencies": [],
-                "bottle": {{
-                    "stable": {{
-                        "files": {{
-                            "{}": {{
-                                "url": "{}/bottles/deplib-1.0.0.{}.bottle.tar.gz",
-                                "sha256": "{}"
-                            }}
-                        }}
-                    }}
-                }}
-            }}"#,
-            tag,
-            mock_server.uri(),
-            tag,
-            dep_sha
-        );
 
-        let main_json = format!(
-            r#"{{
-                "name": "mainpkg",
-                "versions": {{ "stable": "2.0.0" }},
-                "dependencies": ["deplib"],
-                "bottle": {{
-                    "stable": {{
-                        "files": {{
-                            "{}": {{
-                                "url": "{}/bottles/mainpkg-2.0.0.{}.bottle.tar.gz",
-                                "sha256": "{}"
-                            }}
-                        }}
-                    }}
-                }}
-            }}"#,
-            tag,
-            mock_server.uri(),
-            tag,
-            main_sha
-        );
-
-        // Mount mocks
+        let bottle = create_bottle_tarball("vulnpkg");
+        let formula = serde_json::json!({
+            "name": "vulnpkg",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}/bottles/vulnpkg.tar.gz", mock_server.uri()),
+                "sha256": sha256_hex(&bottle),
+            } } } },
+        });
         Mock::given(method("GET"))
-            .and(path("/deplib.json"))
-            .respond_with(ResponseTemplate::new(200).set_body_string(&dep_json))
+            .and(path("/vulnpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&formula))
             .mount(&mock_server)
             .await;
-
         Mock::given(method("GET"))
-            .and(path("/mainpkg.json"))
-            .respond_with(ResponseTemplate::new(200).set_body_string(&main_json))
+            .and(path("/bottles/vulnpkg.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
             .mount(&mock_server)
             .await;
-
-        Mock::given(method("GET"))
-            .and(path(format!("/bottles/deplib-1.0.0.{}.bottle.tar.gz", tag)))
-            .respond_with(ResponseTemplate::new(200).set_body_bytes(dep_bottle))
+        Mock::given(method("POST"))
+            .and(path("/v1/querybatch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "results": [{ "vulns": [{ "id": "OSV-2024-1" }] }],
+            })))
+            .up_to_n_times(1)
             .mount(&mock_server)
             .await;
-
         Mock::given(method("GET"))
-            .and(path(format!(
-                "/bottles/mainpkg-2.0.0.{}.bottle.tar.gz",
-                tag
-            )))
-            .respond_with(ResponseTemplate::new(200).set_body_bytes(main_bottle))
+            .and(path("/v1/vulns/OSV-2024-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "OSV-2024-1",
+                "summary": "heap overflow",
+                "affected": [{ "ranges": [{ "events": [
+                    { "introduced": "0" }, { "fixed": "1.0.1" },
+                ] }] }],
+            })))
             .mount(&mock_server)
             .await;
 
-        // Create installer
         let root = tmp.path().join("zerobrew");
         let prefix = tmp.path().join("homebrew");
         fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client = ApiClient::with_base_url(mock_server.uri());
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
         let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
+            ApiClient::with_base_url(mock_server.uri()),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
             prefix.clone(),
         );
-
-        // Install main package (should also install dependency)
+        installer.set_config(
+            Config::parse(&format!(
+                "[security]\nosv_url = \"{}\"\n",
+                mock_server.uri()
+            ))
+            .unwrap(),
+        );
         installer
-            .install(&["mainpkg".to_string()], true)
+            .install(&["vulnpkg".to_string()], true)
             .await
             .unwrap();
 
-        // Both packages should be installed
-        assert!(installer.db.get_installed("mainpkg").is_some());
-        assert!(installer.db.get_installed("deplib").is_some());
+        let report = installer.security_audit(false).await.unwrap();
+        assert!(!report.offline);
+        assert_eq!(report.checked, 1);
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].name, "vulnpkg");
+        assert_eq!(report.findings[0].advisories[0].id, "OSV-2024-1");
+        assert_eq!(report.findings[0].advisories[0].fixed, vec!["1.0.1"]);
+
+        // The batch endpoint now fails, so the stored snapshot is used.
+        let report = installer.security_audit(false).await.unwrap();
+        assert!(report.offline);
+        assert_eq!(report.findings[0].advisories[0].id, "OSV-2024-1");
+
+        let report = installer.security_audit(true).await.unwrap();
+        assert!(report.offline);
+        assert_eq!(report.checked, 1);
+        assert!(report.unchecked.is_empty());
     }
 
     #[tokio::test]
@@ -2665,6 +8591,412 @@ end
         }
     }
 
+    #[tokio::test]
+    async fn head_only_formulas_need_a_source_build() {
+        let mock_server = MockServer::start().await;
+        let formula_json = r#"{
+            "name": "nightly",
+            "versions": { "stable": null, "head": "HEAD" },
+            "dependencies": [],
+            "urls": { "head": { "url": "https://example.com/nightly.git", "branch": "main" } },
+            "ruby_source_path": "Formula/n/nightly.rb",
+            "bottle": {}
+        }"#;
+        Mock::given(method("GET"))
+            .and(path("/nightly.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+
+        let test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let names = ["nightly".to_string()];
+        assert!(matches!(
+            test.installer.plan(&names).await,
+            Err(Error::HeadOnly { name }) if name == "nightly"
+        ));
+
+        let plan = test
+            .installer
+            .plan_with_options(&names, true)
+            .await
+            .unwrap();
+        let zb_core::InstallMethod::Source(ref bp) = plan.items[0].method else {
+            panic!("expected a source build");
+        };
+        assert!(bp.head);
+        assert_eq!(bp.version, "HEAD");
+        assert_eq!(bp.source_url, "https://example.com/nightly.git");
+        assert_eq!(bp.source_branch.as_deref(), Some("main"));
+    }
+
+    #[tokio::test]
+    async fn cask_apps_are_copied_to_the_appdir_and_removed_on_uninstall() {
+        use std::io::Write;
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (path, contents) in [
+            ("Demo.app/Contents/Info.plist", "<plist/>"),
+            ("Demo.app/Contents/MacOS/demo", "#!/bin/sh\necho demo\n"),
+        ] {
+            zip.start_file(path, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        let archive = zip.finish().unwrap().into_inner();
+
+        let mock_server = MockServer::start().await;
+        let cask_json = serde_json::json!({
+            "token": "demo",
+            "version": "1.0",
+            "url": format!("{}/demo-1.0.zip", mock_server.uri()),
+            "sha256": sha256_hex(&archive),
+            "artifacts": [
+                { "app": ["Demo.app"] },
+                { "binary": ["$APPDIR/Demo.app/Contents/MacOS/demo"] }
+            ]
+        });
+        Mock::given(method("GET"))
+            .and(path("/cask/demo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(cask_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/demo-1.0.zip"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(archive))
+            .mount(&mock_server)
+            .await;
+
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let appdir = test.path().join("Applications");
+        let mut config = Config::default();
+        config.cask.appdir = Some(appdir.clone());
+        test.installer.set_config(config);
+
+        // Someone else's bundle in the way stops the install.
+        fs::create_dir_all(appdir.join("Demo.app")).unwrap();
+        let names = ["cask:demo".to_string()];
+        let Err(err) = test.installer.install_casks(&names, true).await else {
+            panic!("expected the existing bundle to stop the install");
+        };
+        assert!(err.to_string().contains("already exists"));
+        assert!(test.installer.db.get_installed("cask:demo").is_none());
+        fs::remove_dir(appdir.join("Demo.app")).unwrap();
+
+        test.installer.install_casks(&names, true).await.unwrap();
+        let app = appdir.join("Demo.app");
+        assert!(app.join("Contents/Info.plist").is_file());
+        assert_eq!(
+            fs::canonicalize(test.prefix.join("bin/demo")).unwrap(),
+            fs::canonicalize(app.join("Contents/MacOS/demo")).unwrap()
+        );
+        assert_eq!(
+            test.installer.db.cask_artifacts("cask:demo").unwrap(),
+            [("app".to_string(), app.to_string_lossy().into_owned())]
+        );
+
+        // Reinstalling replaces the bundle it placed before.
+        test.installer.install_casks(&names, true).await.unwrap();
+
+        test.installer.uninstall("cask:demo").unwrap();
+        assert!(!app.exists());
+        assert!(!test.prefix.join("bin/demo").is_symlink());
+        assert!(
+            test.installer
+                .db
+                .cask_artifacts("cask:demo")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn uninstall_zaps_the_recorded_cask_paths_only_when_asked() {
+        use std::io::Write;
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file(
+            "Demo.app/Contents/Info.plist",
+            zip::write::SimpleFileOptions::default(),
+        )
+        .unwrap();
+        zip.write_all(b"<plist/>").unwrap();
+        let archive = zip.finish().unwrap().into_inner();
+
+        let mock_server = MockServer::start().await;
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let library = test.path().join("Library");
+        let cask_json = serde_json::json!({
+            "token": "demo",
+            "version": "1.0",
+            "url": format!("{}/demo-1.0.zip", mock_server.uri()),
+            "sha256": sha256_hex(&archive),
+            "artifacts": [
+                { "app": ["Demo.app"] },
+                { "zap": [{
+                    "trash": [format!("{}/Caches/com.example.demo*", library.display())],
+                    "delete": format!("{}/Preferences/com.example.demo.plist", library.display())
+                }] }
+            ]
+        });
+        Mock::given(method("GET"))
+            .and(path("/cask/demo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(cask_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/demo-1.0.zip"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(archive))
+            .mount(&mock_server)
+            .await;
+
+        let mut config = Config::default();
+        config.cask.appdir = Some(test.path().join("Applications"));
+        test.installer.set_config(config);
+        let leftovers = [
+            library.join("Caches/com.example.demo"),
+            library.join("Caches/com.example.demo.ShipIt/update.zip"),
+            library.join("Preferences/com.example.demo.plist"),
+        ];
+        let unrelated = library.join("Caches/com.example.other");
+        for path in leftovers.iter().chain([&unrelated]) {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let names = ["cask:demo".to_string()];
+        test.installer.install_casks(&names, true).await.unwrap();
+        test.installer.uninstall("cask:demo").unwrap();
+        assert!(leftovers.iter().all(|path| path.exists()));
+
+        test.installer.install_casks(&names, true).await.unwrap();
+        test.installer.set_zap(true);
+        test.installer.uninstall("cask:demo").unwrap();
+        assert!(!library.join("Caches/com.example.demo").exists());
+        assert!(!library.join("Caches/com.example.demo.ShipIt").exists());
+        assert!(!library.join("Preferences/com.example.demo.plist").exists());
+        assert!(unrelated.exists());
+    }
+
+    #[tokio::test]
+    async fn plan_diffs_upgrades_installs_and_orphaned_dependencies() {
+        let mock_server = MockServer::start().await;
+        for (name, version, dependencies) in [
+            ("app", "2.0", r#"["libnew", "libshared"]"#),
+            ("libnew", "1.0", "[]"),
+            ("libshared", "1.0", "[]"),
+        ] {
+            let formula_json = format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "{version}" }},
+                    "dependencies": {dependencies},
+                    "urls": {{
+                        "stable": {{ "url": "https://example.com/{name}.tar.gz", "checksum": "abc123" }}
+                    }},
+                    "bottle": {{}}
+                }}"#
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let installer = &mut test.installer;
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("app", "1.0", "appkey").unwrap();
+            tx.record_install("libshared", "1.0", "source:libshared:1.0")
+                .unwrap();
+            tx.record_install("libold", "1.0", "oldkey").unwrap();
+            tx.record_install("liboldest", "0.9", "oldestkey").unwrap();
+            tx.record_install("jq", "1.7.1", "jqkey").unwrap();
+            tx.commit().unwrap();
+        }
+        for (name, dependencies) in [
+            ("app", vec!["libold", "libshared"]),
+            ("libold", vec!["liboldest"]),
+        ] {
+            let dependencies: Vec<String> = dependencies.into_iter().map(String::from).collect();
+            installer.db.set_dependencies(name, &dependencies).unwrap();
+        }
+
+        let plan = installer.plan(&["app".to_string()]).await.unwrap();
+        let diff = installer.diff_plan(&plan).unwrap();
+        let install = |name: &str, version: &str| PlanAction::Install {
+            name: name.to_string(),
+            version: version.to_string(),
+        };
+        let autoremove = |name: &str, version: &str| PlanAction::Autoremove {
+            name: name.to_string(),
+            version: version.to_string(),
+        };
+        assert_eq!(
+            diff.actions,
+            [
+                install("libnew", "1.0"),
+                PlanAction::Upgrade {
+                    name: "app".to_string(),
+                    from: "1.0".to_string(),
+                    to: "2.0".to_string(),
+                },
+                autoremove("libold", "1.0"),
+                autoremove("liboldest", "0.9"),
+            ]
+        );
+        assert_eq!(diff.unchanged, ["libshared"]);
+    }
+
+    #[tokio::test]
+    async fn license_policy_reports_the_dependency_chain() {
+        let mock_server = MockServer::start().await;
+        for (name, license, dependencies) in [
+            ("app", "MIT", r#"["libbar"]"#),
+            ("libbar", "MIT", r#"["libfoo"]"#),
+            ("libfoo", "AGPL-3.0-only", "[]"),
+        ] {
+            let formula_json = format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "license": "{license}",
+                    "dependencies": {dependencies},
+                    "urls": {{
+                        "stable": {{ "url": "https://example.com/{name}.tar.gz", "checksum": "abc123" }}
+                    }},
+                    "ruby_source_path": "Formula/{name}.rb",
+                    "bottle": {{}}
+                }}"#
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let names = ["app".to_string()];
+        test.installer
+            .set_config(Config::parse("[licenses]\ndeny = [\"AGPL-*\"]\n").unwrap());
+        let Err(Error::LicensePolicy { violations }) = test.installer.plan(&names).await else {
+            panic!("expected the policy to reject libfoo");
+        };
+        assert_eq!(
+            violations,
+            [LicenseViolation {
+                name: "libfoo".to_string(),
+                license: Some("AGPL-3.0-only".to_string()),
+                required_by: vec!["app".to_string(), "libbar".to_string()],
+            }]
+        );
+
+        test.installer.set_config(
+            Config::parse("[licenses]\ndeny = [\"AGPL-*\"]\nexempt = [\"libfoo\"]\n").unwrap(),
+        );
+        assert_eq!(test.installer.plan(&names).await.unwrap().items.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn plans_refuse_formulas_that_conflict_with_installed_ones() {
+        let mock_server = MockServer::start().await;
+        for (name, conflicts) in [
+            (
+                "gnupg",
+                r#""conflicts_with": ["gpg2"], "conflicts_with_reasons": ["both install gpg"],"#,
+            ),
+            ("gpg2", ""),
+        ] {
+            let formula_json = format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "dependencies": [],
+                    {conflicts}
+                    "urls": {{
+                        "stable": {{ "url": "https://example.com/{name}.tar.gz", "checksum": "abc123" }}
+                    }},
+                    "ruby_source_path": "Formula/{name}.rb",
+                    "bottle": {{}}
+                }}"#
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let names = ["gnupg".to_string()];
+        assert_eq!(test.installer.plan(&names).await.unwrap().items.len(), 1);
+
+        {
+            let tx = test.installer.db.transaction().unwrap();
+            tx.record_install("gpg2", "1.0.0", "gpgkey").unwrap();
+            tx.commit().unwrap();
+        }
+        let err = test.installer.plan(&names).await.unwrap_err();
+        assert_eq!(
+            err,
+            Error::FormulaConflict {
+                name: "gnupg".to_string(),
+                installed: "gpg2".to_string(),
+                reason: Some("both install gpg".to_string()),
+            }
+        );
+
+        test.installer.set_force(true);
+        assert_eq!(test.installer.plan(&names).await.unwrap().items.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn strict_plans_fail_on_deprecated_and_unverifiable_formulas() {
+        let mock_server = MockServer::start().await;
+        for (name, deprecated, checksum) in [
+            ("oldtool", true, r#", "checksum": "abc123""#),
+            ("loose", false, ""),
+        ] {
+            let formula_json = format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "dependencies": [],
+                    "deprecated": {deprecated},
+                    "deprecation_reason": "unmaintained",
+                    "urls": {{
+                        "stable": {{ "url": "https://example.com/{name}-1.0.0.tar.gz"{checksum} }}
+                    }},
+                    "ruby_source_path": "Formula/{name}.rb",
+                    "bottle": {{}}
+                }}"#
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        let oldtool = ["oldtool".to_string()];
+        let loose = ["loose".to_string()];
+        assert!(test.installer.plan(&oldtool).await.is_ok());
+        assert!(test.installer.plan(&loose).await.is_ok());
+
+        test.installer.set_strict(true);
+        assert!(matches!(
+            test.installer.plan(&oldtool).await,
+            Err(Error::Strict { message }) if message == "oldtool is deprecated (unmaintained)"
+        ));
+        assert!(matches!(
+            test.installer.plan(&loose).await,
+            Err(Error::Strict { message }) if message.contains("no source checksum")
+        ));
+    }
+
     #[tokio::test]
     async fn plan_prefers_bottle_over_source() {
         let mock_server = MockServer::start().await;
@@ -2779,4 +9111,98 @@ end
             zb_core::Error::MissingFormula { .. }
         ));
     }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=zb", "-c", "user.email=zb@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    fn commit_tap_formula(repo: &Path, name: &str, version: &str) {
+        let class = name[..1].to_uppercase() + &name[1..];
+        fs::create_dir_all(repo.join("Formula")).unwrap();
+        fs::write(
+            repo.join(format!("Formula/{name}.rb")),
+            format!(
+                r#"class {class} < Formula
+  desc "Test formula"
+  homepage "https://example.com"
+  url "https://example.com/{name}-{version}.tar.gz"
+  version "{version}"
+  sha256 "{}"
+end
+"#,
+                "0".repeat(64)
+            ),
+        )
+        .unwrap();
+        git(repo, &["add", "-A"]);
+        git(repo, &["commit", "-q", "-m", name]);
+    }
+
+    #[tokio::test]
+    async fn tapped_formulas_resolve_by_short_name() {
+        let mock_server = MockServer::start().await;
+        let mut test = crate::test_support::TestPrefix::new(mock_server.uri()).unwrap();
+        test.installer.set_taps(Taps::new(test.root.join("taps")));
+
+        let remote = test.path().join("remote");
+        fs::create_dir_all(&remote).unwrap();
+        git(&remote, &["init", "-q"]);
+        commit_tap_formula(&remote, "hello", "2.1");
+        let remote_url = remote.to_str().unwrap();
+
+        let info = test
+            .installer
+            .tap("acme/homebrew-tools", Some(remote_url))
+            .await
+            .unwrap();
+        assert_eq!(info.name, "acme/tools");
+        assert_eq!(info.formulas, 1);
+        assert!(
+            test.root
+                .join("taps/acme/homebrew-tools/Formula/hello.rb")
+                .is_file()
+        );
+        assert!(
+            test.installer
+                .tap("acme/tools", Some(remote_url))
+                .await
+                .is_err()
+        );
+
+        let formula = test.installer.get_formula("hello").await.unwrap();
+        assert_eq!(formula.versions.stable.as_deref(), Some("2.1"));
+        let formula = test
+            .installer
+            .get_formula("acme/tools/hello")
+            .await
+            .unwrap();
+        assert_eq!(formula.versions.stable.as_deref(), Some("2.1"));
+        assert!(matches!(
+            test.installer.get_formula("goodbye").await,
+            Err(Error::MissingFormula { .. })
+        ));
+
+        commit_tap_formula(&remote, "goodbye", "0.3");
+        let updated = test.installer.update_taps().await.unwrap();
+        assert_eq!(updated[0].formulas, 2);
+        let formula = test.installer.get_formula("goodbye").await.unwrap();
+        assert_eq!(formula.versions.stable.as_deref(), Some("0.3"));
+
+        let removed = test.installer.untap("acme/tools", false).unwrap();
+        assert_eq!(removed.name, "acme/tools");
+        assert!(test.installer.list_taps().unwrap().is_empty());
+        assert!(!test.root.join("taps/acme").exists());
+        assert!(matches!(
+            test.installer.get_formula("hello").await,
+            Err(Error::MissingFormula { .. })
+        ));
+    }
 }
@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use zb_core::{Error, InstallMethod};
+
+use crate::installer::install::InstallPlan;
+
+/// Where `zb plan --lock` writes the lockfile and `zb install --locked`
+/// reads it, relative to the working directory.
+pub const LOCKFILE_NAME: &str = "zb.lock";
+
+/// Bumped whenever the format changes incompatibly.
+const LOCKFILE_VERSION: u32 = 1;
+
+/// The exact set of formulas a plan resolved to, for installing the same
+/// bits again elsewhere.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub version: u32,
+    /// Bottle tag of the host the plan was resolved on.
+    pub platform: String,
+    /// Formulas the plan was made for.
+    pub roots: Vec<String>,
+    #[serde(default)]
+    pub build_from_source: bool,
+    #[serde(default, rename = "formula")]
+    pub formulas: Vec<LockedFormula>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedFormula {
+    pub name: String,
+    pub version: String,
+    /// Bottle tag, or `source` for source builds.
+    pub bottle_tag: String,
+    /// SHA-256 of the bottle or source archive; empty when the formula
+    /// gives none for its source.
+    pub sha256: String,
+    pub url: String,
+}
+
+impl LockedFormula {
+    fn from_plan(plan: &InstallPlan) -> Vec<Self> {
+        plan.items
+            .iter()
+            .map(|item| {
+                let (bottle_tag, sha256, url) = match &item.method {
+                    InstallMethod::Bottle(bottle) => (
+                        bottle.tag.clone(),
+                        bottle.sha256.clone(),
+                        bottle.url.clone(),
+                    ),
+                    InstallMethod::Source(build) => (
+                        "source".to_string(),
+                        build.source_checksum.clone().unwrap_or_default(),
+                        build.source_url.clone(),
+                    ),
+                };
+                Self {
+                    name: item.install_name.clone(),
+                    version: item.formula.effective_version(),
+                    bottle_tag,
+                    sha256,
+                    url,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Lockfile {
+    pub(crate) fn new(
+        plan: &InstallPlan,
+        platform: &str,
+        roots: &[String],
+        build_from_source: bool,
+    ) -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            platform: platform.to_string(),
+            roots: roots.to_vec(),
+            build_from_source,
+            formulas: LockedFormula::from_plan(plan),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path).map_err(|e| Error::FileError {
+            message: format!("failed to read {}: {e}", path.display()),
+        })?;
+        let lockfile: Self = toml::from_str(&contents).map_err(|e| Error::InvalidArgument {
+            message: format!("invalid {}: {e}", path.display()),
+        })?;
+        if lockfile.version != LOCKFILE_VERSION {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "{} is lockfile version {}, but this zb reads version {LOCKFILE_VERSION}",
+                    path.display(),
+                    lockfile.version
+                ),
+            });
+        }
+        Ok(lockfile)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let contents = toml::to_string_pretty(self).map_err(|e| Error::FileError {
+            message: format!("failed to serialize lockfile: {e}"),
+        })?;
+        let contents = format!("# Generated by `zb plan --lock`. Do not edit.\n{contents}");
+        fs::write(path, contents).map_err(|e| Error::FileError {
+            message: format!("failed to write {}: {e}", path.display()),
+        })
+    }
+
+    /// How `plan`, resolved on `platform`, differs from the locked set, one
+    /// line per difference.
+    pub(crate) fn drift(&self, plan: &InstallPlan, platform: &str) -> Vec<String> {
+        let mut drift = Vec::new();
+        if self.platform != platform {
+            drift.push(format!(
+                "locked for {}, running on {platform}",
+                self.platform
+            ));
+        }
+
+        let resolved = LockedFormula::from_plan(plan);
+        for locked in &self.formulas {
+            let Some(now) = resolved.iter().find(|entry| entry.name == locked.name) else {
+                drift.push(format!("{} is no longer part of the plan", locked.name));
+                continue;
+            };
+            if now.version != locked.version {
+                drift.push(format!(
+                    "{}: version {} → {}",
+                    locked.name, locked.version, now.version
+                ));
+            } else if now.bottle_tag != locked.bottle_tag {
+                drift.push(format!(
+                    "{}: {} → {}",
+                    locked.name, locked.bottle_tag, now.bottle_tag
+                ));
+            } else if now.sha256 != locked.sha256 || now.url != locked.url {
+                drift.push(format!("{}: same version, different artifact", locked.name));
+            }
+        }
+        for now in &resolved {
+            if !self.formulas.iter().any(|locked| locked.name == now.name) {
+                drift.push(format!("{} {} is not locked", now.name, now.version));
+            }
+        }
+        drift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locked(name: &str, version: &str, sha256: &str) -> LockedFormula {
+        LockedFormula {
+            name: name.to_string(),
+            version: version.to_string(),
+            bottle_tag: "arm64_sequoia".to_string(),
+            sha256: sha256.to_string(),
+            url: format!("https://ghcr.io/v2/homebrew/core/{name}/blobs/sha256:{sha256}"),
+        }
+    }
+
+    #[test]
+    fn lockfiles_round_trip_through_toml() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(LOCKFILE_NAME);
+        let lockfile = Lockfile {
+            version: LOCKFILE_VERSION,
+            platform: "arm64_sequoia".to_string(),
+            roots: vec!["jq".to_string()],
+            build_from_source: false,
+            formulas: vec![
+                locked("oniguruma", "6.9.9", "aa"),
+                locked("jq", "1.7.1", "bb"),
+            ],
+        };
+
+        lockfile.write(&path).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("[[formula]]"));
+        assert_eq!(Lockfile::read(&path).unwrap(), lockfile);
+
+        fs::write(&path, contents.replace("version = 1", "version = 99")).unwrap();
+        assert!(matches!(
+            Lockfile::read(&path),
+            Err(Error::InvalidArgument { .. })
+        ));
+    }
+}
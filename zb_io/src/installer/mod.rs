@@ -1,9 +1,36 @@
 mod cask;
+pub mod doctor;
 pub mod homebrew;
 pub mod install;
+pub mod lockfile;
+pub mod outdated;
+pub mod plan;
+pub mod preflight;
+pub mod smoke;
+pub mod source;
+pub mod verification;
 
+pub use doctor::Diagnostic;
 pub use homebrew::{
     HomebrewMigrationPackages, HomebrewPackage, categorize_packages, get_homebrew_packages,
     parse_casks_from_plain_text, parse_formulas_from_json,
 };
-pub use install::{ExecuteResult, InstallPlan, Installer, create_installer};
+pub use install::{
+    ChangedKegFile, CleanedItem, CleanupOptions, CleanupReport, CompactedEntry, EnvironmentReport,
+    ExecuteResult, GcOptions, InstallPlan, Installer, LicenseRequirement, LinkAudit, LinkPreview,
+    ReinstallSource, RelocationReport, ReportedFormula, SecurityFinding, SecurityReport,
+    create_installer,
+};
+pub use lockfile::{LOCKFILE_NAME, LockedFormula, Lockfile};
+pub use outdated::{
+    KegMetadata, MetadataChange, OutdatedDiff, OutdatedFormula, PlannedUpgrade, UpgradeNotice,
+};
+pub use plan::{
+    DryRun, DryRunItem, EntryStatus, PlanAction, PlanDiff, PlanEntry, PlanVerification, plan_hash,
+};
+pub use preflight::PreflightWarning;
+pub use smoke::{CheckStatus, SmokeCheck, SmokeReport};
+pub use source::{FormulaSource, FormulaSourceOrigin};
+pub use verification::{
+    AttestationStatus, BlobCheck, BlobVerification, VERIFICATION_REPORT_FILE, VerificationReport,
+};
@@ -0,0 +1,302 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use zb_core::Formula;
+
+/// An installed formula whose current stable version differs from the installed one.
+#[derive(Debug, Clone)]
+pub struct OutdatedFormula {
+    pub name: String,
+    pub installed_version: String,
+    pub current_version: String,
+    pub formula: Formula,
+}
+
+/// An installed formula that a plan replaces with another version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedUpgrade {
+    pub name: String,
+    pub from_version: String,
+    pub to_version: String,
+}
+
+/// What changes when an outdated formula is upgraded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutdatedDiff {
+    pub added_dependencies: Vec<String>,
+    pub removed_dependencies: Vec<String>,
+    /// False when the installed keg has no receipt to compare against.
+    pub dependencies_known: bool,
+    pub installed_bottle_size: Option<u64>,
+    pub current_bottle_size: Option<u64>,
+    pub release_notes_url: Option<String>,
+}
+
+/// Formula metadata recorded at install time, so the next upgrade can tell
+/// what changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KegMetadata {
+    pub license: Option<String>,
+    pub keg_only: bool,
+    pub deprecated: bool,
+    pub dependencies: Vec<String>,
+}
+
+impl KegMetadata {
+    pub fn of(formula: &Formula) -> Self {
+        Self {
+            license: formula.license.clone(),
+            keg_only: formula.is_keg_only(),
+            deprecated: formula.deprecated,
+            dependencies: formula.dependencies.clone(),
+        }
+    }
+}
+
+/// A notable change between the installed and the planned version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataChange {
+    License {
+        from: Option<String>,
+        to: Option<String>,
+    },
+    NewDependencies(Vec<String>),
+    Deprecated {
+        reason: Option<String>,
+    },
+    /// Will no longer be linked into the prefix.
+    BecameKegOnly,
+}
+
+impl fmt::Display for MetadataChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let license = |license: &Option<String>| license.clone().unwrap_or("none".to_string());
+        match self {
+            MetadataChange::License { from, to } => {
+                write!(f, "license changed: {} → {}", license(from), license(to))
+            }
+            MetadataChange::NewDependencies(deps) => {
+                write!(f, "new dependencies: {}", deps.join(", "))
+            }
+            MetadataChange::Deprecated {
+                reason: Some(reason),
+            } => {
+                write!(f, "deprecated ({reason})")
+            }
+            MetadataChange::Deprecated { reason: None } => write!(f, "deprecated"),
+            MetadataChange::BecameKegOnly => write!(f, "now keg-only (will not be linked)"),
+        }
+    }
+}
+
+/// Metadata changes an upgrade of `name` brings, shown before it runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpgradeNotice {
+    pub name: String,
+    pub from_version: String,
+    pub to_version: String,
+    pub changes: Vec<MetadataChange>,
+}
+
+/// Compare what was installed against the planned `formula`.
+///
+/// Kegs installed before metadata was recorded only have their receipt's
+/// dependencies to go on, so license and keg-only changes can't be detected
+/// for them.
+pub(crate) fn metadata_changes(
+    previous: Option<&KegMetadata>,
+    receipt_deps: Option<BTreeSet<String>>,
+    formula: &Formula,
+) -> Vec<MetadataChange> {
+    let mut changes = Vec::new();
+
+    if let Some(previous) = previous
+        && previous.license != formula.license
+    {
+        changes.push(MetadataChange::License {
+            from: previous.license.clone(),
+            to: formula.license.clone(),
+        });
+    }
+
+    let installed_deps = previous
+        .map(|previous| previous.dependencies.iter().cloned().collect())
+        .or(receipt_deps);
+    if let Some(installed_deps) = installed_deps {
+        let (added, _) = diff_dependencies(&installed_deps, &formula.dependencies);
+        if !added.is_empty() {
+            changes.push(MetadataChange::NewDependencies(added));
+        }
+    }
+
+    if formula.deprecated && !previous.is_some_and(|previous| previous.deprecated) {
+        changes.push(MetadataChange::Deprecated {
+            reason: formula.deprecation_reason.clone(),
+        });
+    }
+
+    if formula.is_keg_only() && previous.is_some_and(|previous| !previous.keg_only) {
+        changes.push(MetadataChange::BecameKegOnly);
+    }
+
+    changes
+}
+
+/// Direct runtime dependencies recorded in a keg's `INSTALL_RECEIPT.json`.
+///
+/// Older receipts don't mark which dependencies were declared directly; in that case
+/// every runtime dependency is returned.
+pub(crate) fn receipt_dependencies(keg_path: &Path) -> Option<BTreeSet<String>> {
+    let contents = std::fs::read_to_string(keg_path.join("INSTALL_RECEIPT.json")).ok()?;
+    let receipt: serde_json::Value = serde_json::from_str(&contents).ok()?;
+    let deps = receipt.get("runtime_dependencies")?.as_array()?;
+
+    let has_declared = deps.iter().any(|d| d.get("declared_directly").is_some());
+    Some(
+        deps.iter()
+            .filter(|d| {
+                !has_declared
+                    || d.get("declared_directly")
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(false)
+            })
+            .filter_map(|d| d.get("full_name").and_then(|n| n.as_str()))
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+/// Split the difference between two dependency sets into (added, removed).
+pub(crate) fn diff_dependencies(
+    installed: &BTreeSet<String>,
+    current: &[String],
+) -> (Vec<String>, Vec<String>) {
+    let current: BTreeSet<String> = current.iter().cloned().collect();
+    let added = current.difference(installed).cloned().collect();
+    let removed = installed.difference(&current).cloned().collect();
+    (added, removed)
+}
+
+/// Release page for formulas whose stable source lives on GitHub.
+pub(crate) fn release_notes_url(formula: &Formula) -> Option<String> {
+    let url = &formula.source_url()?.url;
+    let rest = url.strip_prefix("https://github.com/")?;
+    let mut parts = rest.split('/');
+    let owner = parts.next().filter(|s| !s.is_empty())?;
+    let repo = parts.next().filter(|s| !s.is_empty())?;
+    let repo = repo.strip_suffix(".git").unwrap_or(repo);
+    Some(format!("https://github.com/{owner}/{repo}/releases"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formula_with_source(url: &str) -> Formula {
+        let mut formula: Formula =
+            serde_json::from_str(include_str!("../../../zb_core/fixtures/formula_foo.json"))
+                .unwrap();
+        formula.urls = Some(zb_core::formula::FormulaUrls {
+            stable: Some(zb_core::formula::SourceUrl {
+                url: url.to_string(),
+                checksum: None,
+                tag: None,
+                revision: None,
+            }),
+            head: None,
+        });
+        formula
+    }
+
+    #[test]
+    fn receipt_dependencies_prefers_declared_directly() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            tmp.path().join("INSTALL_RECEIPT.json"),
+            r#"{"runtime_dependencies": [
+                {"full_name": "oniguruma", "version": "6.9", "declared_directly": true},
+                {"full_name": "zlib", "version": "1.3", "declared_directly": false}
+            ]}"#,
+        )
+        .unwrap();
+
+        let deps = receipt_dependencies(tmp.path()).unwrap();
+        assert_eq!(deps.into_iter().collect::<Vec<_>>(), vec!["oniguruma"]);
+    }
+
+    #[test]
+    fn receipt_dependencies_missing_receipt() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(receipt_dependencies(tmp.path()).is_none());
+    }
+
+    #[test]
+    fn diff_dependencies_reports_added_and_removed() {
+        let installed: BTreeSet<String> = ["a", "b"].iter().map(|s| s.to_string()).collect();
+        let (added, removed) = diff_dependencies(&installed, &["b".into(), "c".into()]);
+        assert_eq!(added, vec!["c"]);
+        assert_eq!(removed, vec!["a"]);
+    }
+
+    #[test]
+    fn metadata_changes_report_license_deps_deprecation_and_keg_only() {
+        let mut formula = formula_with_source("https://example.com/foo.tar.gz");
+        let previous = KegMetadata {
+            license: Some("MIT".to_string()),
+            dependencies: vec!["zlib".to_string()],
+            ..KegMetadata::of(&formula)
+        };
+        assert!(metadata_changes(Some(&KegMetadata::of(&formula)), None, &formula).is_empty());
+
+        formula.license = Some("BUSL-1.1".to_string());
+        formula.dependencies = vec!["zlib".to_string(), "openssl@3".to_string()];
+        formula.deprecated = true;
+        formula.deprecation_reason = Some("unmaintained".to_string());
+        formula.keg_only = zb_core::KegOnly::Reason("conflicts with system".to_string());
+
+        let changes = metadata_changes(Some(&previous), None, &formula);
+        assert_eq!(
+            changes,
+            vec![
+                MetadataChange::License {
+                    from: Some("MIT".to_string()),
+                    to: Some("BUSL-1.1".to_string()),
+                },
+                MetadataChange::NewDependencies(vec!["openssl@3".to_string()]),
+                MetadataChange::Deprecated {
+                    reason: Some("unmaintained".to_string()),
+                },
+                MetadataChange::BecameKegOnly,
+            ]
+        );
+        assert_eq!(changes[0].to_string(), "license changed: MIT → BUSL-1.1");
+
+        // Without recorded metadata only the receipt's dependencies are known.
+        let receipt: BTreeSet<String> = ["zlib".to_string()].into();
+        let changes = metadata_changes(None, Some(receipt), &formula);
+        assert_eq!(
+            changes,
+            vec![
+                MetadataChange::NewDependencies(vec!["openssl@3".to_string()]),
+                MetadataChange::Deprecated {
+                    reason: Some("unmaintained".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn release_notes_url_for_github_sources() {
+        let formula =
+            formula_with_source("https://github.com/jqlang/jq/releases/download/jq-1.7/jq.tar.gz");
+        assert_eq!(
+            release_notes_url(&formula).as_deref(),
+            Some("https://github.com/jqlang/jq/releases")
+        );
+
+        let formula = formula_with_source("https://ftp.gnu.org/gnu/wget/wget-1.24.tar.gz");
+        assert_eq!(release_notes_url(&formula), None);
+    }
+}
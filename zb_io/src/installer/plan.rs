@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use zb_core::InstallMethod;
+
+use crate::installer::install::InstallPlan;
+
+/// Bumped whenever the hashed representation changes, so old hashes never
+/// collide with new ones.
+const PLAN_HASH_VERSION: &str = "zerobrew-plan-v1";
+
+/// One resolved formula of a plan, reduced to what determines the installed bits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub name: String,
+    pub version: String,
+    /// Bottle tag the entry was planned with, or `source` for source builds.
+    pub method: String,
+    pub store_key: String,
+}
+
+impl InstallPlan {
+    /// Entries sorted by name, independent of resolution order.
+    pub fn entries(&self) -> Vec<PlanEntry> {
+        let mut entries = self.entries_in_order();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries
+    }
+
+    /// Entries in install order, dependencies first.
+    pub fn entries_in_order(&self) -> Vec<PlanEntry> {
+        self.items
+            .iter()
+            .map(|item| {
+                let version = item.formula.effective_version();
+                let (method, store_key) = match &item.method {
+                    InstallMethod::Bottle(bottle) => (bottle.tag.clone(), bottle.sha256.clone()),
+                    InstallMethod::Source(_) => (
+                        "source".to_string(),
+                        format!("source:{}:{version}", item.formula.name),
+                    ),
+                };
+                PlanEntry {
+                    name: item.install_name.clone(),
+                    version,
+                    method,
+                    store_key,
+                }
+            })
+            .collect()
+    }
+
+    /// Stable hash of the plan's entries and the host platform tag.
+    pub fn hash(&self, platform: &str) -> String {
+        plan_hash(&self.entries(), platform)
+    }
+}
+
+pub fn plan_hash(entries: &[PlanEntry], platform: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(PLAN_HASH_VERSION.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(platform.as_bytes());
+    hasher.update(b"\n");
+    for entry in entries {
+        for field in [&entry.name, &entry.version, &entry.method, &entry.store_key] {
+            hasher.update(field.as_bytes());
+            hasher.update(b"\t");
+        }
+        hasher.update(b"\n");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryStatus {
+    Matches,
+    /// Installed, but at another version or from another bottle/build.
+    Differs {
+        installed_version: String,
+        installed_store_key: String,
+    },
+    NotInstalled,
+}
+
+/// Comparison of a recorded plan against the current installation.
+#[derive(Debug, Clone)]
+pub struct PlanVerification {
+    pub hash: String,
+    pub platform: String,
+    pub current_platform: String,
+    pub recorded_at: i64,
+    pub entries: Vec<(PlanEntry, EntryStatus)>,
+}
+
+impl PlanVerification {
+    pub fn matches(&self) -> bool {
+        self.platform == self.current_platform
+            && self
+                .entries
+                .iter()
+                .all(|(_, status)| *status == EntryStatus::Matches)
+    }
+}
+
+/// A change applying a plan makes to the installation, as `zb plan` shows it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlanAction {
+    Install {
+        name: String,
+        version: String,
+    },
+    Upgrade {
+        name: String,
+        from: String,
+        to: String,
+    },
+    /// Same version from another bottle or build.
+    Reinstall {
+        name: String,
+        version: String,
+    },
+    /// A dependency nothing needs once the plan is applied.
+    Autoremove {
+        name: String,
+        version: String,
+    },
+}
+
+impl PlanAction {
+    pub fn name(&self) -> &str {
+        match self {
+            PlanAction::Install { name, .. }
+            | PlanAction::Upgrade { name, .. }
+            | PlanAction::Reinstall { name, .. }
+            | PlanAction::Autoremove { name, .. } => name,
+        }
+    }
+}
+
+/// What applying a plan would change, from [`Installer::diff_plan`].
+///
+/// [`Installer::diff_plan`]: crate::Installer::diff_plan
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PlanDiff {
+    /// Installs, upgrades and reinstalls in plan order, then removals.
+    pub actions: Vec<PlanAction>,
+    /// Formulas in the plan that are installed exactly as planned.
+    pub unchanged: Vec<String>,
+}
+
+impl PlanDiff {
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+/// What installing a plan would download and take up, as `zb install
+/// --dry-run` shows it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DryRun {
+    /// Plan items in install order.
+    pub items: Vec<DryRunItem>,
+    /// Bytes still to download, over the items whose size is known.
+    pub download_bytes: u64,
+    /// Rough disk space the new downloads and store entries will take.
+    pub disk_estimate: u64,
+}
+
+impl DryRun {
+    /// Items to be fetched whose size the server didn't report.
+    pub fn unknown_sizes(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| !item.cached && item.action.is_some() && item.download_bytes.is_none())
+            .count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DryRunItem {
+    pub name: String,
+    pub version: String,
+    /// Bottle tag, or `source` for source builds.
+    pub method: String,
+    /// `None` when it is already installed exactly as planned.
+    pub action: Option<PlanAction>,
+    /// Already downloaded or unpacked, so nothing is fetched.
+    pub cached: bool,
+    pub download_bytes: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, version: &str) -> PlanEntry {
+        PlanEntry {
+            name: name.to_string(),
+            version: version.to_string(),
+            method: "x86_64_linux".to_string(),
+            store_key: format!("{name}-{version}"),
+        }
+    }
+
+    #[test]
+    fn hash_depends_on_every_field_and_platform() {
+        let entries = vec![entry("jq", "1.7.1"), entry("oniguruma", "6.9.9")];
+        let hash = plan_hash(&entries, "x86_64_linux");
+
+        assert_eq!(hash, plan_hash(&entries.clone(), "x86_64_linux"));
+        assert_eq!(hash.len(), 64);
+        assert_ne!(hash, plan_hash(&entries, "arm64_sonoma"));
+        assert_ne!(
+            hash,
+            plan_hash(
+                &[entry("jq", "1.7.1"), entry("oniguruma", "6.9.10")],
+                "x86_64_linux"
+            )
+        );
+
+        let mut source = entries.clone();
+        source[0].method = "source".to_string();
+        assert_ne!(hash, plan_hash(&source, "x86_64_linux"));
+
+        // Fields are delimited, so shifting text between them changes the hash.
+        let mut joined = entry("ab", "c");
+        let mut split = entry("a", "bc");
+        joined.store_key = "key".to_string();
+        split.store_key = "key".to_string();
+        assert_ne!(plan_hash(&[joined], "t"), plan_hash(&[split], "t"));
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs;
+use std::path::Path;
+
+/// Soft open-file limit below which parallel downloads, racing mirror
+/// connections and extraction can run out of descriptors.
+const MIN_OPEN_FILES: libc::rlim_t = 1024;
+
+/// Directories System Integrity Protection keeps read-only on macOS.
+const SIP_PROTECTED: &[&str] = &["/System", "/bin", "/sbin", "/usr"];
+
+/// A host condition known to break installs, and how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightWarning {
+    pub problem: String,
+    pub remedy: String,
+}
+
+impl PreflightWarning {
+    fn new(problem: impl Into<String>, remedy: impl Into<String>) -> Self {
+        Self {
+            problem: problem.into(),
+            remedy: remedy.into(),
+        }
+    }
+}
+
+/// Check the host for conditions that would otherwise surface as obscure
+/// failures deep inside extraction or linking. Raises the open-file limit
+/// when the hard limit allows it.
+pub fn preflight(prefix: &Path) -> Vec<PreflightWarning> {
+    let macos = cfg!(target_os = "macos");
+    let mut warnings = Vec::new();
+    warnings.extend(check_protected_prefix(prefix, macos));
+    warnings.extend(check_umask(current_umask()));
+    if let Some(case_sensitive) = probe_case_sensitivity(prefix) {
+        warnings.extend(check_case_sensitivity(prefix, case_sensitive, macos));
+    }
+    warnings.extend(check_open_files());
+    warnings
+}
+
+fn check_protected_prefix(prefix: &Path, macos: bool) -> Option<PreflightWarning> {
+    let protected = macos
+        && !prefix.starts_with("/usr/local")
+        && SIP_PROTECTED.iter().any(|dir| prefix.starts_with(dir));
+    protected.then(|| {
+        PreflightWarning::new(
+            format!(
+                "{} is protected by System Integrity Protection; writing kegs there only works with SIP disabled",
+                prefix.display()
+            ),
+            "use a prefix outside /System, /bin, /sbin and /usr (e.g. --prefix /opt/zerobrew/prefix)",
+        )
+    })
+}
+
+fn check_umask(umask: u32) -> Option<PreflightWarning> {
+    let problem = if umask & 0o700 != 0 {
+        format!(
+            "umask {umask:03o} takes permissions from the owner, so extracted files can't be read or run"
+        )
+    } else if umask & 0o005 != 0 {
+        format!("umask {umask:03o} makes installed files unreadable to other users and services")
+    } else {
+        return None;
+    };
+    Some(PreflightWarning::new(
+        problem,
+        "run `umask 022` in this shell before installing",
+    ))
+}
+
+fn check_case_sensitivity(
+    prefix: &Path,
+    case_sensitive: bool,
+    macos: bool,
+) -> Option<PreflightWarning> {
+    match (macos, case_sensitive) {
+        (true, true) => Some(PreflightWarning::new(
+            format!(
+                "{} is on a case-sensitive volume; bottles are built on case-insensitive ones and some can't find their own files",
+                prefix.display()
+            ),
+            "put the prefix on a case-insensitive APFS volume",
+        )),
+        (false, false) => Some(PreflightWarning::new(
+            format!(
+                "{} is on a case-insensitive filesystem; files in a bottle that differ only in case overwrite each other",
+                prefix.display()
+            ),
+            "put the prefix on a case-sensitive filesystem",
+        )),
+        _ => None,
+    }
+}
+
+/// Whether the filesystem holding `dir` tells names apart by case, or `None`
+/// when it can't be probed (e.g. the prefix doesn't exist yet).
+fn probe_case_sensitivity(dir: &Path) -> Option<bool> {
+    let probe = dir.join(format!(".zb-CaseProbe-{}", std::process::id()));
+    fs::write(&probe, b"").ok()?;
+    let folded = dir.join(format!(".zb-caseprobe-{}", std::process::id()));
+    let sensitive = !folded.exists();
+    let _ = fs::remove_file(&probe);
+    Some(sensitive)
+}
+
+fn current_umask() -> u32 {
+    // There is no way to read the umask without setting it; put it straight back.
+    // SAFETY: umask only swaps the process file mode creation mask.
+    unsafe {
+        let mask = libc::umask(0o022);
+        libc::umask(mask);
+        mask as u32
+    }
+}
+
+fn check_open_files() -> Option<PreflightWarning> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: getrlimit only writes into the struct we pass.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return None;
+    }
+    let soft = limit.rlim_cur;
+    if soft >= MIN_OPEN_FILES {
+        return None;
+    }
+
+    let hard = limit.rlim_max;
+    if hard >= MIN_OPEN_FILES {
+        limit.rlim_cur = MIN_OPEN_FILES;
+        // SAFETY: setrlimit only reads the struct we pass.
+        if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } == 0 {
+            return None;
+        }
+    }
+    Some(PreflightWarning::new(
+        format!(
+            "only {soft} open files are allowed (hard limit {hard}); parallel downloads can fail with \"Too many open files\""
+        ),
+        format!("raise the limit with `ulimit -n {MIN_OPEN_FILES}`"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sip_protected_prefixes_only_warn_on_macos() {
+        assert!(check_protected_prefix(Path::new("/usr/zerobrew"), true).is_some());
+        assert!(check_protected_prefix(Path::new("/usr/local"), true).is_none());
+        assert!(check_protected_prefix(Path::new("/opt/zerobrew/prefix"), true).is_none());
+        assert!(check_protected_prefix(Path::new("/usr/zerobrew"), false).is_none());
+    }
+
+    #[test]
+    fn umask_warnings_distinguish_owner_and_other_bits() {
+        assert!(check_umask(0o022).is_none());
+        assert!(check_umask(0o002).is_none());
+        assert!(check_umask(0o027).unwrap().problem.contains("other users"));
+        assert!(check_umask(0o277).unwrap().problem.contains("owner"));
+    }
+
+    #[test]
+    fn case_sensitivity_warns_against_the_platform_norm() {
+        let prefix = Path::new("/opt/zerobrew/prefix");
+        assert!(check_case_sensitivity(prefix, true, true).is_some());
+        assert!(check_case_sensitivity(prefix, false, true).is_none());
+        assert!(check_case_sensitivity(prefix, false, false).is_some());
+        assert!(check_case_sensitivity(prefix, true, false).is_none());
+    }
+
+    #[test]
+    fn case_probe_cleans_up_after_itself() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(probe_case_sensitivity(tmp.path()).is_some());
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 0);
+        assert!(probe_case_sensitivity(&tmp.path().join("missing")).is_none());
+    }
+}
@@ -0,0 +1,616 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use regex::Regex;
+
+use crate::cellar::shim::exec_shim_target;
+use crate::watchdog::{command_timeout, output_with_timeout};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckStatus {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+/// One smoke-test check run against an installed keg.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmokeCheck {
+    pub name: String,
+    pub detail: String,
+    pub status: CheckStatus,
+}
+
+impl SmokeCheck {
+    pub(crate) fn new(name: &str, detail: impl Into<String>, status: CheckStatus) -> Self {
+        Self {
+            name: name.to_string(),
+            detail: detail.into(),
+            status,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SmokeReport {
+    pub formula: String,
+    pub version: String,
+    pub checks: Vec<SmokeCheck>,
+}
+
+impl SmokeReport {
+    pub fn passed(&self) -> bool {
+        !self
+            .checks
+            .iter()
+            .any(|check| matches!(check.status, CheckStatus::Failed(_)))
+    }
+}
+
+/// The executable a formula is most likely to be invoked as: `bin/<name>`,
+/// falling back to the only (or first) file in `bin/`.
+pub(crate) fn primary_binary(keg: &Path, name: &str) -> Option<PathBuf> {
+    let bin = keg.join("bin");
+    let named = bin.join(name);
+    if named.is_file() {
+        return Some(named);
+    }
+
+    let mut entries: Vec<PathBuf> = fs::read_dir(&bin)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    entries.sort();
+    entries.into_iter().next()
+}
+
+/// Run the binary with `--version`, then `--help`; either exiting cleanly passes.
+pub(crate) fn check_binary(binary: &Path) -> SmokeCheck {
+    let name = binary
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut last_error = String::new();
+    for flag in ["--version", "--help"] {
+        match output_with_timeout(Command::new(binary).arg(flag), command_timeout()) {
+            Ok(output) if output.status.success() => {
+                return SmokeCheck::new("binary", format!("{name} {flag}"), CheckStatus::Passed);
+            }
+            Ok(output) => last_error = format!("{name} {flag} exited with {}", output.status),
+            Err(e) => last_error = e.to_string(),
+        }
+    }
+    SmokeCheck::new("binary", name, CheckStatus::Failed(last_error))
+}
+
+/// Whether the symlink or exec shim at `link` still leads to `target`, and
+/// `target` exists.
+pub(crate) fn link_points_at(link: &Path, target: &Path) -> bool {
+    let points_at_target = fs::read_link(link)
+        .ok()
+        .or_else(|| exec_shim_target(link))
+        .as_deref()
+        == Some(target);
+    points_at_target && link.exists()
+}
+
+/// Every recorded symlink must still exist and point at its keg target.
+pub(crate) fn check_links(links: &[(String, String)]) -> SmokeCheck {
+    if links.is_empty() {
+        return SmokeCheck::new(
+            "links",
+            "",
+            CheckStatus::Skipped("keg is not linked".to_string()),
+        );
+    }
+
+    let broken: Vec<&str> = links
+        .iter()
+        .filter(|(link, target)| !link_points_at(Path::new(link), Path::new(target)))
+        .map(|(link, _)| link.as_str())
+        .collect();
+
+    let detail = format!("{} linked file(s)", links.len());
+    if broken.is_empty() {
+        SmokeCheck::new("links", detail, CheckStatus::Passed)
+    } else {
+        SmokeCheck::new(
+            "links",
+            detail,
+            CheckStatus::Failed(format!("missing or retargeted: {}", broken.join(", "))),
+        )
+    }
+}
+
+/// `codesign --verify` every Mach-O executable in `bin/`. Only meaningful on macOS.
+pub(crate) fn check_signatures(keg: &Path) -> Option<SmokeCheck> {
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+
+    let binaries: Vec<PathBuf> = fs::read_dir(keg.join("bin"))
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_macho(path))
+        .collect();
+    if binaries.is_empty() {
+        return None;
+    }
+
+    let invalid: Vec<String> = binaries
+        .iter()
+        .filter(|path| {
+            !output_with_timeout(
+                Command::new("codesign").arg("--verify").arg(path),
+                command_timeout(),
+            )
+            .map(|output| output.status.success())
+            .unwrap_or(false)
+        })
+        .map(|path| path.display().to_string())
+        .collect();
+
+    let detail = format!("{} binary file(s)", binaries.len());
+    Some(if invalid.is_empty() {
+        SmokeCheck::new("signatures", detail, CheckStatus::Passed)
+    } else {
+        SmokeCheck::new(
+            "signatures",
+            detail,
+            CheckStatus::Failed(format!("invalid signature: {}", invalid.join(", "))),
+        )
+    })
+}
+
+fn is_macho(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut magic = [0u8; 4];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && matches!(
+            u32::from_be_bytes(magic),
+            0xfeedface | 0xfeedfacf | 0xcafebabe | 0xcefaedfe | 0xcffaedfe
+        )
+}
+
+/// The subset of `test do` statements zb can run without a Ruby interpreter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TestStep {
+    /// `system "cmd", "arg", ...`; a single string argument is run through `sh -c`.
+    System(Vec<Arg>),
+    /// `assert_match <expected>, shell_output("cmd"[, status])`
+    AssertMatch {
+        expected: Expected,
+        command: Arg,
+        status: i32,
+    },
+    /// `assert_equal <expected>, shell_output("cmd"[, status]).strip`
+    AssertEqual {
+        expected: Expected,
+        command: Arg,
+        status: i32,
+    },
+}
+
+/// A Ruby argument: a string literal (possibly interpolating keg paths) or
+/// `bin/"name"`-style path joins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Arg {
+    Str(String),
+    Path(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Expected {
+    Str(String),
+    Regex(String),
+    Version,
+}
+
+/// Paths and values substituted into test steps.
+pub(crate) struct TestContext<'a> {
+    pub name: &'a str,
+    pub keg: &'a Path,
+    pub prefix: &'a Path,
+    pub testpath: &'a Path,
+    pub version: &'a str,
+}
+
+impl TestContext<'_> {
+    fn var(&self, var: &str) -> Option<String> {
+        let opt = self.prefix.join("opt").join(self.name);
+        let path = match var {
+            "prefix" => self.keg.to_path_buf(),
+            "bin" | "sbin" | "lib" | "libexec" | "include" | "share" => self.keg.join(var),
+            "pkgshare" => self.keg.join("share").join(self.name),
+            "etc" => self.prefix.join("etc"),
+            "opt_prefix" => opt,
+            "opt_bin" | "opt_sbin" | "opt_lib" | "opt_libexec" | "opt_include" => {
+                opt.join(&var["opt_".len()..])
+            }
+            "testpath" => self.testpath.to_path_buf(),
+            "HOMEBREW_PREFIX" => self.prefix.to_path_buf(),
+            "version" => return Some(self.version.to_string()),
+            _ => return None,
+        };
+        Some(path.display().to_string())
+    }
+
+    fn resolve(&self, arg: &Arg) -> Option<String> {
+        match arg {
+            Arg::Path(var, name) => Some(format!("{}/{name}", self.var(var)?)),
+            Arg::Str(s) => {
+                let mut out = String::new();
+                let mut rest = s.as_str();
+                while let Some(start) = rest.find("#{") {
+                    out.push_str(&rest[..start]);
+                    let end = rest[start..].find('}')? + start;
+                    out.push_str(&self.var(&rest[start + 2..end])?);
+                    rest = &rest[end + 1..];
+                }
+                out.push_str(rest);
+                Some(out)
+            }
+        }
+    }
+}
+
+/// Extract the body of the formula's `test do ... end` block.
+pub(crate) fn test_block(source: &str) -> Option<Vec<&str>> {
+    let mut lines = source.lines();
+    let indent = lines.by_ref().find_map(|line| {
+        (line.trim() == "test do").then(|| line.len() - line.trim_start().len())
+    })?;
+
+    let mut body = Vec::new();
+    for line in lines {
+        let line_indent = line.len() - line.trim_start().len();
+        if line.trim() == "end" && line_indent == indent {
+            return Some(body);
+        }
+        body.push(line);
+    }
+    None
+}
+
+/// Parse a `test do` body into runnable steps, or return the first statement
+/// that falls outside the supported subset.
+pub(crate) fn parse_test_steps(body: &[&str]) -> Result<Vec<TestStep>, String> {
+    let mut steps = Vec::new();
+    for line in body {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        steps.push(parse_step(line).ok_or_else(|| line.to_string())?);
+    }
+    Ok(steps)
+}
+
+fn parse_step(line: &str) -> Option<TestStep> {
+    if let Some(args) = line.strip_prefix("system ") {
+        let args = split_args(args)?
+            .iter()
+            .map(|arg| parse_arg(arg))
+            .collect::<Option<Vec<_>>>()?;
+        return (!args.is_empty()).then_some(TestStep::System(args));
+    }
+
+    let (assert_equal, rest) = if let Some(rest) = line.strip_prefix("assert_match ") {
+        (false, rest)
+    } else {
+        (true, line.strip_prefix("assert_equal ")?)
+    };
+
+    let args = split_args(rest)?;
+    let [expected, actual] = args.as_slice() else {
+        return None;
+    };
+    let expected = parse_expected(expected)?;
+
+    let mut actual = actual.trim();
+    if assert_equal {
+        actual = actual
+            .strip_suffix(".strip")
+            .or_else(|| actual.strip_suffix(".chomp"))?;
+    }
+    let inner = actual.strip_prefix("shell_output(")?.strip_suffix(')')?;
+    let inner = split_args(inner)?;
+    let (command, status) = match inner.as_slice() {
+        [command] => (parse_arg(command)?, 0),
+        [command, status] => (parse_arg(command)?, status.trim().parse().ok()?),
+        _ => return None,
+    };
+
+    Some(if assert_equal {
+        TestStep::AssertEqual {
+            expected,
+            command,
+            status,
+        }
+    } else {
+        TestStep::AssertMatch {
+            expected,
+            command,
+            status,
+        }
+    })
+}
+
+fn parse_expected(expr: &str) -> Option<Expected> {
+    let expr = expr.trim();
+    if expr == "version" || expr == "version.to_s" {
+        return Some(Expected::Version);
+    }
+    if let Some(pattern) = expr.strip_prefix('/').and_then(|e| e.strip_suffix('/')) {
+        return Some(Expected::Regex(pattern.to_string()));
+    }
+    match parse_arg(expr)? {
+        Arg::Str(s) if !s.contains("#{") => Some(Expected::Str(s)),
+        _ => None,
+    }
+}
+
+fn parse_arg(expr: &str) -> Option<Arg> {
+    let expr = expr.trim();
+    if let Some((var, name)) = expr.split_once('/')
+        && !var.is_empty()
+        && var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        let Arg::Str(name) = parse_arg(name)? else {
+            return None;
+        };
+        return Some(Arg::Path(var.to_string(), name));
+    }
+
+    let inner = expr.strip_prefix('"')?.strip_suffix('"')?;
+    let mut value = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next()?),
+            // An unescaped quote means this was more than one string literal.
+            '"' => return None,
+            _ => value.push(c),
+        }
+    }
+    Some(Arg::Str(value))
+}
+
+/// Split on top-level commas, keeping quoted strings and parentheses intact.
+fn split_args(input: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                current.push(c);
+                current.push(chars.next()?);
+                continue;
+            }
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => depth = depth.checked_sub(1)?,
+            ',' if !in_string && depth == 0 => {
+                args.push(std::mem::take(&mut current).trim().to_string());
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+
+    if in_string || depth != 0 {
+        return None;
+    }
+    if !current.trim().is_empty() {
+        args.push(current.trim().to_string());
+    }
+    Some(args)
+}
+
+/// Run parsed steps in `ctx.testpath`, stopping at the first failure.
+pub(crate) fn run_test_steps(steps: &[TestStep], ctx: &TestContext<'_>) -> CheckStatus {
+    for step in steps {
+        if let Err(reason) = run_step(step, ctx) {
+            return CheckStatus::Failed(reason);
+        }
+    }
+    CheckStatus::Passed
+}
+
+fn run_step(step: &TestStep, ctx: &TestContext<'_>) -> Result<(), String> {
+    let resolve = |arg: &Arg| {
+        ctx.resolve(arg)
+            .ok_or_else(|| format!("cannot resolve {arg:?}"))
+    };
+
+    match step {
+        TestStep::System(args) => {
+            let args = args.iter().map(resolve).collect::<Result<Vec<_>, _>>()?;
+            let mut command = if args.len() == 1 {
+                let mut cmd = Command::new("sh");
+                cmd.args(["-c", &args[0]]);
+                cmd
+            } else {
+                let mut cmd = Command::new(&args[0]);
+                cmd.args(&args[1..]);
+                cmd
+            };
+            let output = output_with_timeout(command.current_dir(ctx.testpath), command_timeout())
+                .map_err(|e| e.to_string())?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(format!(
+                    "`{}` exited with {}",
+                    args.join(" "),
+                    output.status
+                ))
+            }
+        }
+        TestStep::AssertMatch {
+            expected,
+            command,
+            status,
+        }
+        | TestStep::AssertEqual {
+            expected,
+            command,
+            status,
+        } => {
+            let command = resolve(command)?;
+            let output = output_with_timeout(
+                Command::new("sh")
+                    .args(["-c", &command])
+                    .current_dir(ctx.testpath),
+                command_timeout(),
+            )
+            .map_err(|e| e.to_string())?;
+            if output.status.code() != Some(*status) {
+                return Err(format!(
+                    "`{command}` exited with {} (expected {status})",
+                    output.status
+                ));
+            }
+
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let matched = match (step, expected) {
+                (TestStep::AssertEqual { .. }, Expected::Str(s)) => stdout.trim() == s,
+                (TestStep::AssertEqual { .. }, Expected::Version) => stdout.trim() == ctx.version,
+                (_, Expected::Str(s)) => stdout.contains(s.as_str()),
+                (_, Expected::Version) => stdout.contains(ctx.version),
+                (_, Expected::Regex(pattern)) => Regex::new(pattern)
+                    .map_err(|e| format!("invalid pattern /{pattern}/: {e}"))?
+                    .is_match(&stdout),
+            };
+            if matched {
+                Ok(())
+            } else {
+                Err(format!(
+                    "output of `{command}` did not match {expected:?}: {}",
+                    stdout.trim()
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    const FORMULA: &str = r##"class Hello < Formula
+  desc "Says hello"
+
+  def install
+    bin.install "hello"
+  end
+
+  test do
+    # smoke
+    system bin/"hello", "--version"
+    assert_match version.to_s, shell_output("#{bin}/hello --version")
+    assert_equal "hello world", shell_output("#{bin}/hello world").strip
+    assert_match(/usage/i, shell_output("#{bin}/hello --bogus 2>&1", 2)) if false
+  end
+end
+"##;
+
+    #[test]
+    fn parses_supported_test_statements() {
+        let body = test_block(FORMULA).unwrap();
+        assert_eq!(body.len(), 5);
+
+        let err = parse_test_steps(&body).unwrap_err();
+        assert!(err.starts_with("assert_match(/usage/i"));
+
+        let steps = parse_test_steps(&body[..4]).unwrap();
+        assert_eq!(
+            steps,
+            vec![
+                TestStep::System(vec![
+                    Arg::Path("bin".into(), "hello".into()),
+                    Arg::Str("--version".into()),
+                ]),
+                TestStep::AssertMatch {
+                    expected: Expected::Version,
+                    command: Arg::Str("#{bin}/hello --version".into()),
+                    status: 0,
+                },
+                TestStep::AssertEqual {
+                    expected: Expected::Str("hello world".into()),
+                    command: Arg::Str("#{bin}/hello world".into()),
+                    status: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn runs_steps_against_the_keg() {
+        let tmp = tempfile::tempdir().unwrap();
+        let keg = tmp.path().join("Cellar/hello/1.2.3");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        let hello = keg.join("bin/hello");
+        fs::write(
+            &hello,
+            "#!/bin/sh\n[ \"$1\" = --version ] && echo 'hello 1.2.3' || echo \"hello $1\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&hello, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let ctx = TestContext {
+            name: "hello",
+            keg: &keg,
+            prefix: tmp.path(),
+            testpath: tmp.path(),
+            version: "1.2.3",
+        };
+        let body = test_block(FORMULA).unwrap();
+        let steps = parse_test_steps(&body[..4]).unwrap();
+        assert_eq!(run_test_steps(&steps, &ctx), CheckStatus::Passed);
+
+        let failing =
+            parse_test_steps(&[r##"assert_equal "bye", shell_output("#{bin}/hello x").chomp"##])
+                .unwrap();
+        assert!(matches!(
+            run_test_steps(&failing, &ctx),
+            CheckStatus::Failed(_)
+        ));
+
+        assert_eq!(check_binary(&hello).status, CheckStatus::Passed);
+        assert_eq!(primary_binary(&keg, "other"), Some(hello));
+    }
+
+    #[test]
+    fn check_links_reports_broken_symlinks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path().join("target");
+        fs::write(&target, "").unwrap();
+        let good = tmp.path().join("good");
+        std::os::unix::fs::symlink(&target, &good).unwrap();
+        let missing = tmp.path().join("missing");
+
+        let links = vec![
+            (good.display().to_string(), target.display().to_string()),
+            (missing.display().to_string(), target.display().to_string()),
+        ];
+        let check = check_links(&links);
+        assert!(matches!(check.status, CheckStatus::Failed(ref msg) if msg.contains("missing")));
+
+        assert_eq!(check_links(&links[..1]).status, CheckStatus::Passed);
+    }
+}
@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+
+use crate::network::tap_formula::{TapFormulaRef, parse_tap_formula_ref};
+
+/// Where a formula's Ruby source was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormulaSourceOrigin {
+    /// A file in a local Homebrew tap checkout.
+    LocalTap(PathBuf),
+    /// Fetched from the formula's upstream repository.
+    Remote(String),
+}
+
+impl std::fmt::Display for FormulaSourceOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormulaSourceOrigin::LocalTap(path) => write!(f, "{}", path.display()),
+            FormulaSourceOrigin::Remote(url) => f.write_str(url),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FormulaSource {
+    pub origin: FormulaSourceOrigin,
+    pub contents: String,
+}
+
+/// `Library/Taps` directories of Homebrew installations on this machine.
+pub(crate) fn homebrew_taps_dirs() -> Vec<PathBuf> {
+    let mut repositories: Vec<PathBuf> = std::env::var_os("HOMEBREW_REPOSITORY")
+        .map(PathBuf::from)
+        .into_iter()
+        .collect();
+    repositories.extend(
+        [
+            "/opt/homebrew",
+            "/usr/local/Homebrew",
+            "/home/linuxbrew/.linuxbrew/Homebrew",
+        ]
+        .map(PathBuf::from),
+    );
+
+    let mut dirs: Vec<PathBuf> = repositories
+        .into_iter()
+        .map(|repo| repo.join("Library/Taps"))
+        .filter(|dir| dir.is_dir())
+        .collect();
+    dirs.dedup();
+    dirs
+}
+
+/// Find `name` (a core formula or `owner/repo/formula`) in a local tap checkout.
+pub(crate) fn find_local_formula(taps_dirs: &[PathBuf], name: &str) -> Option<PathBuf> {
+    let spec = parse_tap_formula_ref(name).unwrap_or_else(|| TapFormulaRef {
+        owner: "homebrew".to_string(),
+        repo: "core".to_string(),
+        formula: name.to_string(),
+    });
+
+    taps_dirs.iter().find_map(|taps| {
+        let owner = taps.join(&spec.owner);
+        spec.candidate_repos().iter().find_map(|repo| {
+            spec.candidate_paths()
+                .iter()
+                .map(|path| owner.join(repo).join(path))
+                .find(|path| path.is_file())
+        })
+    })
+}
+
+/// Core formulas live at `Formula/<first letter>/<name>.rb` in homebrew-core.
+pub(crate) fn core_ruby_source_path(name: &str) -> String {
+    let first_char = name.chars().next().unwrap_or('x');
+    format!("Formula/{first_char}/{name}.rb")
+}
+
+pub(crate) fn read_local(path: &Path) -> Result<FormulaSource, zb_core::Error> {
+    let contents = std::fs::read_to_string(path).map_err(|e| zb_core::Error::FileError {
+        message: format!("failed to read {}: {e}", path.display()),
+    })?;
+    Ok(FormulaSource {
+        origin: FormulaSourceOrigin::LocalTap(path.to_path_buf()),
+        contents,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn finds_core_and_tap_formulas_in_local_checkouts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let taps = tmp.path().join("Library/Taps");
+        let core = taps.join("homebrew/homebrew-core/Formula/j/jq.rb");
+        let tap = taps.join("hashicorp/homebrew-tap/Formula/terraform.rb");
+        for path in [&core, &tap] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "class Formula; end\n").unwrap();
+        }
+
+        let dirs = [taps];
+        assert_eq!(find_local_formula(&dirs, "jq"), Some(core));
+        assert_eq!(
+            find_local_formula(&dirs, "hashicorp/tap/terraform"),
+            Some(tap)
+        );
+        assert_eq!(find_local_formula(&dirs, "wget"), None);
+    }
+}
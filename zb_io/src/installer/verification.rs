@@ -0,0 +1,143 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use zb_core::Error;
+
+/// Written into the keg next to `INSTALL_RECEIPT.json` when
+/// `[security] verification_report` is set.
+pub const VERIFICATION_REPORT_FILE: &str = "VERIFICATION_REPORT.json";
+
+/// How the downloads a keg was installed from were checked, for archiving as
+/// evidence of supply-chain checks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerificationReport {
+    pub formula: String,
+    pub version: String,
+    /// Seconds since the Unix epoch.
+    pub generated_at: u64,
+    pub blobs: Vec<BlobVerification>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlobVerification {
+    /// URL the formula points the blob at.
+    pub url: String,
+    pub sha256: String,
+    pub size: u64,
+    /// Host that served the blob (a mirror, possibly), or `None` when it came
+    /// from the download cache.
+    pub source_host: Option<String>,
+    pub verification: BlobCheck,
+    pub attestation: AttestationStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlobCheck {
+    /// Hashed while downloading; the SHA-256 matched the formula's.
+    Sha256Matched,
+    /// Taken from the download cache, which only keeps blobs whose SHA-256
+    /// matched when they were fetched.
+    CachedSha256Matched,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttestationStatus {
+    /// Bottles carry no signature or build attestation zerobrew verifies.
+    NotChecked,
+}
+
+impl BlobVerification {
+    /// The record for the blob at `blob_path`, fetched from `source`, or
+    /// from the cache when that is `None`.
+    pub(crate) fn new(url: &str, sha256: &str, blob_path: &Path, source: Option<&str>) -> Self {
+        let source_host = source.map(|source| {
+            reqwest::Url::parse(source)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_else(|| "unknown".to_string())
+        });
+        Self {
+            url: url.to_string(),
+            sha256: sha256.to_string(),
+            size: fs::metadata(blob_path).map(|m| m.len()).unwrap_or(0),
+            verification: match source {
+                Some(_) => BlobCheck::Sha256Matched,
+                None => BlobCheck::CachedSha256Matched,
+            },
+            source_host,
+            attestation: AttestationStatus::NotChecked,
+        }
+    }
+}
+
+impl VerificationReport {
+    pub(crate) fn new(formula: &str, version: &str, blobs: Vec<BlobVerification>) -> Self {
+        let generated_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            formula: formula.to_string(),
+            version: version.to_string(),
+            generated_at,
+            blobs,
+        }
+    }
+
+    /// The report written into `keg_path`, if any.
+    pub fn read(keg_path: &Path) -> Option<Self> {
+        let contents = fs::read(keg_path.join(VERIFICATION_REPORT_FILE)).ok()?;
+        serde_json::from_slice(&contents).ok()
+    }
+
+    /// Write the report into `keg_path`, returning where it went.
+    pub fn write(&self, keg_path: &Path) -> Result<PathBuf, Error> {
+        let path = keg_path.join(VERIFICATION_REPORT_FILE);
+        let json = serde_json::to_vec_pretty(self).map_err(|e| Error::FileError {
+            message: format!("failed to serialize verification report: {e}"),
+        })?;
+        fs::write(&path, json).map_err(|e| Error::FileError {
+            message: format!("failed to write {}: {e}", path.display()),
+        })?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_round_trip_through_the_keg() {
+        let tmp = tempfile::tempdir().unwrap();
+        let blob = tmp.path().join("blob");
+        fs::write(&blob, b"bottle").unwrap();
+
+        let downloaded = BlobVerification::new(
+            "https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:aa",
+            "aa",
+            &blob,
+            Some("https://mirror.example.com/jq.tar.gz"),
+        );
+        assert_eq!(downloaded.size, 6);
+        assert_eq!(
+            downloaded.source_host.as_deref(),
+            Some("mirror.example.com")
+        );
+        assert_eq!(downloaded.verification, BlobCheck::Sha256Matched);
+
+        let cached = BlobVerification::new("https://x/jq.tar.gz", "aa", &blob, None);
+        assert_eq!(cached.source_host, None);
+        assert_eq!(cached.verification, BlobCheck::CachedSha256Matched);
+
+        let report = VerificationReport::new("jq", "1.7.1", vec![downloaded]);
+        let path = report.write(tmp.path()).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+        assert_eq!(json["blobs"][0]["verification"], "sha256_matched");
+        assert_eq!(json["blobs"][0]["attestation"], "not_checked");
+        assert_eq!(VerificationReport::read(tmp.path()), Some(report));
+    }
+}
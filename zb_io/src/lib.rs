@@ -1,23 +1,55 @@
 pub mod build;
 pub mod cellar;
 pub(crate) mod checksum;
+pub mod config;
 pub mod extraction;
 pub mod installer;
 pub mod network;
+pub mod notify;
+pub mod plugins;
+pub mod processes;
 pub mod progress;
+pub mod services;
+pub mod snapshot;
 pub mod ssl;
 pub mod storage;
+pub mod tap;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod watchdog;
 
-pub use build::{BuildExecutor, DepInfo};
-pub use cellar::{Cellar, LinkedFile, Linker};
+pub use build::{Bottle, BottleCellar, BuildExecutor, DepInfo, host_bottle_tag};
+pub use cellar::{Cellar, LazyLauncher, LinkExclusions, LinkedFile, Linker, ShimEnv};
+pub use config::{
+    AnalyticsConfig, AuthConfig, CacheConfig, CaskConfig, Config, DownloadConfig, FormulaConfig,
+    IndexConfig, LicenseConfig, LinkMode, NotifyConfig, RetryConfig, SecurityConfig,
+};
 pub use extraction::extract_tarball;
 pub use installer::{
-    ExecuteResult, HomebrewMigrationPackages, HomebrewPackage, InstallPlan, Installer,
-    create_installer, get_homebrew_packages,
+    ChangedKegFile, CheckStatus, CleanedItem, CleanupOptions, CleanupReport, CompactedEntry,
+    Diagnostic, DryRun, DryRunItem, EntryStatus, EnvironmentReport, ExecuteResult, FormulaSource,
+    FormulaSourceOrigin, GcOptions, HomebrewMigrationPackages, HomebrewPackage, InstallPlan,
+    Installer, KegMetadata, LOCKFILE_NAME, LicenseRequirement, LinkAudit, LinkPreview,
+    LockedFormula, Lockfile, MetadataChange, OutdatedDiff, OutdatedFormula, PlanAction, PlanDiff,
+    PlanEntry, PlanVerification, PlannedUpgrade, PreflightWarning, ReinstallSource,
+    RelocationReport, ReportedFormula, SmokeCheck, SmokeReport, UpgradeNotice, VerificationReport,
+    create_installer, get_homebrew_packages, plan_hash,
 };
 pub use network::{
-    ApiCache, ApiClient, DownloadProgressCallback, DownloadRequest, Downloader, ParallelDownloader,
+    Advisory, AdvisoryClient, ApiCache, ApiClient, DEFAULT_PEER_PORT, DownloadProgressCallback,
+    DownloadRequest, Downloader, FormulaIndex, IndexPin, IndexUpdate, IndexedFormula,
+    ParallelDownloader, PeerPull, SearchHit,
 };
+pub use notify::{Notifier, NotifierKind};
+pub use plugins::{HookPhase, PluginHooks};
+pub use processes::{OpenProcess, processes_using};
 pub use progress::{InstallProgress, ProgressCallback};
+pub use services::{ServiceKind, ServiceManager, ServiceUnit};
+pub use snapshot::{RestoreSummary, SnapshotInfo, SnapshotStore};
 pub use ssl::{find_ca_bundle_from_prefix, find_ca_dir};
-pub use storage::{BlobCache, Database, InstalledKeg, Store};
+pub use storage::{
+    BlobCache, Database, DownloadStats, HostStats, InstalledKeg, KegFileState, LicenseAcceptance,
+    OrphanAction, RefcountFix, Store, StoreAudit, TapInfo, UpgradeRecord,
+};
+pub use tap::Taps;
+pub use watchdog::{StallPolicy, WatchdogConfig};
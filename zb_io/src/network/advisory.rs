@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use zb_core::{Error, formula_token};
+
+use super::proxy;
+
+/// Vulnerability detail requests in flight at once.
+const VULN_FETCH_CONCURRENCY: usize = 8;
+
+/// A known vulnerability affecting an installed formula version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Advisory {
+    pub id: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// Versions the advisory lists as fixed, oldest range first.
+    #[serde(default)]
+    pub fixed: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    #[serde(default)]
+    results: Vec<BatchResult>,
+}
+
+#[derive(Deserialize)]
+struct BatchResult {
+    #[serde(default)]
+    vulns: Vec<VulnRef>,
+}
+
+#[derive(Deserialize)]
+struct VulnRef {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Vuln {
+    id: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    summary: Option<String>,
+    #[serde(default)]
+    affected: Vec<Affected>,
+}
+
+#[derive(Deserialize)]
+struct Affected {
+    #[serde(default)]
+    package: Option<AffectedPackage>,
+    #[serde(default)]
+    ranges: Vec<Range>,
+}
+
+#[derive(Deserialize)]
+struct AffectedPackage {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Range {
+    #[serde(default)]
+    events: Vec<BTreeMap<String, String>>,
+}
+
+/// Client for the [OSV](https://osv.dev) vulnerability database.
+pub struct AdvisoryClient {
+    base_url: String,
+    ecosystem: Option<String>,
+    client: reqwest::Client,
+}
+
+impl AdvisoryClient {
+    /// `ecosystem` scopes lookups to one OSV ecosystem; without it formulas
+    /// are matched by name alone.
+    pub fn new(base_url: impl Into<String>, ecosystem: Option<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            ecosystem,
+            client: build_client(None),
+        }
+    }
+
+    /// Route queries through `proxy` instead of the proxy environment
+    /// variables (see [`ProxyConfig`](crate::ProxyConfig)).
+    pub(crate) fn with_proxy(mut self, proxy: Option<&reqwest::Proxy>) -> Self {
+        self.client = build_client(proxy);
+        self
+    }
+
+    /// Advisories for each `(formula, version)` pair, in the same order.
+    pub async fn query(&self, packages: &[(String, String)]) -> Result<Vec<Vec<Advisory>>, Error> {
+        if packages.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let queries: Vec<_> = packages
+            .iter()
+            .map(|(name, version)| {
+                let mut package = json!({ "name": formula_token(name) });
+                if let Some(ecosystem) = &self.ecosystem {
+                    package["ecosystem"] = json!(ecosystem);
+                }
+                json!({ "package": package, "version": upstream_version(version) })
+            })
+            .collect();
+        let response = self
+            .client
+            .post(format!("{}/v1/querybatch", self.base_url))
+            .json(&json!({ "queries": queries }))
+            .send()
+            .await
+            .map_err(|e| Error::NetworkFailure {
+                message: format!("failed to query OSV: {e}"),
+            })?;
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!("OSV query returned HTTP {}", response.status()),
+            });
+        }
+        let batch: BatchResponse = response.json().await.map_err(|e| Error::NetworkFailure {
+            message: format!("failed to parse OSV response: {e}"),
+        })?;
+
+        // The batch endpoint only returns ids; details are fetched once per
+        // distinct advisory.
+        let mut ids: Vec<&str> = batch
+            .results
+            .iter()
+            .flat_map(|result| result.vulns.iter().map(|vuln| vuln.id.as_str()))
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        let details: BTreeMap<String, Vuln> = stream::iter(ids)
+            .map(|id| self.fetch_vuln(id))
+            .buffer_unordered(VULN_FETCH_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .map(|vuln| vuln.map(|vuln| (vuln.id.clone(), vuln)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(packages
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| {
+                let Some(result) = batch.results.get(i) else {
+                    return Vec::new();
+                };
+                result
+                    .vulns
+                    .iter()
+                    .filter_map(|vuln| details.get(&vuln.id))
+                    .map(|vuln| advisory_for(vuln, formula_token(name)))
+                    .collect()
+            })
+            .collect())
+    }
+
+    async fn fetch_vuln(&self, id: &str) -> Result<Vuln, Error> {
+        let response = self
+            .client
+            .get(format!("{}/v1/vulns/{id}", self.base_url))
+            .send()
+            .await
+            .map_err(|e| Error::NetworkFailure {
+                message: format!("failed to fetch advisory {id}: {e}"),
+            })?;
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!("advisory {id} fetch returned HTTP {}", response.status()),
+            });
+        }
+        response.json().await.map_err(|e| Error::NetworkFailure {
+            message: format!("failed to parse advisory {id}: {e}"),
+        })
+    }
+}
+
+/// Fixed versions come from the affected entries naming this formula, or
+/// from every entry when none does.
+fn advisory_for(vuln: &Vuln, name: &str) -> Advisory {
+    let matching: Vec<&Affected> = vuln
+        .affected
+        .iter()
+        .filter(|affected| {
+            affected
+                .package
+                .as_ref()
+                .is_some_and(|package| package.name.eq_ignore_ascii_case(name))
+        })
+        .collect();
+    let affected = if matching.is_empty() {
+        vuln.affected.iter().collect()
+    } else {
+        matching
+    };
+
+    let mut fixed = Vec::new();
+    for event in affected
+        .iter()
+        .flat_map(|affected| &affected.ranges)
+        .flat_map(|range| &range.events)
+    {
+        if let Some(version) = event.get("fixed")
+            && !fixed.contains(version)
+        {
+            fixed.push(version.clone());
+        }
+    }
+
+    Advisory {
+        id: vuln.id.clone(),
+        aliases: vuln.aliases.clone(),
+        summary: vuln.summary.clone(),
+        fixed,
+    }
+}
+
+/// The upstream version, without Homebrew's `_<revision>` suffix.
+fn upstream_version(version: &str) -> &str {
+    match version.rsplit_once('_') {
+        Some((upstream, revision)) if revision.chars().all(|c| c.is_ascii_digit()) => upstream,
+        _ => version,
+    }
+}
+
+fn build_client(proxy: Option<&reqwest::Proxy>) -> reqwest::Client {
+    proxy::apply(reqwest::Client::builder(), proxy)
+        .user_agent("zerobrew/0.1")
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn batch_results_are_resolved_to_advisories_with_fixed_versions() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/querybatch"))
+            .and(body_partial_json(json!({ "queries": [
+                { "package": { "name": "curl" }, "version": "8.4.0" },
+                { "package": { "name": "jq" }, "version": "1.7.1" },
+            ] })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "results": [
+                { "vulns": [{ "id": "CURL-CVE-2023-46218" }] },
+                {},
+            ] })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/vulns/CURL-CVE-2023-46218"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "CURL-CVE-2023-46218",
+                "aliases": ["CVE-2023-46218"],
+                "summary": "cookie mixed case PSL bypass",
+                "affected": [
+                    { "package": { "name": "other" }, "ranges": [
+                        { "events": [{ "introduced": "0" }, { "fixed": "9.9" }] },
+                    ] },
+                    { "package": { "name": "curl" }, "ranges": [
+                        { "events": [{ "introduced": "7.46.0" }, { "fixed": "8.5.0" }] },
+                    ] },
+                ],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = AdvisoryClient::new(server.uri(), None);
+        let results = client
+            .query(&[
+                ("curl".to_string(), "8.4.0_1".to_string()),
+                ("jq".to_string(), "1.7.1".to_string()),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0],
+            vec![Advisory {
+                id: "CURL-CVE-2023-46218".to_string(),
+                aliases: vec!["CVE-2023-46218".to_string()],
+                summary: Some("cookie mixed case PSL bypass".to_string()),
+                fixed: vec!["8.5.0".to_string()],
+            }]
+        );
+        assert!(results[1].is_empty());
+    }
+
+    #[tokio::test]
+    async fn queries_go_through_the_configured_proxy() {
+        let proxy_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/querybatch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "results": [{}],
+            })))
+            .expect(1)
+            .mount(&proxy_server)
+            .await;
+        let proxy = reqwest::Proxy::all(proxy_server.uri()).unwrap();
+
+        // The host doesn't resolve; only the proxy can answer for it.
+        let client = AdvisoryClient::new("http://osv.invalid", None).with_proxy(Some(&proxy));
+        let results = client
+            .query(&[("jq".to_string(), "1.7.1".to_string())])
+            .await
+            .unwrap();
+        assert_eq!(results, vec![Vec::new()]);
+    }
+
+    #[test]
+    fn revision_suffix_is_dropped_from_queried_version() {
+        assert_eq!(upstream_version("3.3.2_1"), "3.3.2");
+        assert_eq!(upstream_version("2024_beta"), "2024_beta");
+        assert_eq!(upstream_version("1.0"), "1.0");
+    }
+}
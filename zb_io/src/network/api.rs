@@ -1,17 +1,28 @@
 use crate::checksum::verify_sha256_bytes;
+use crate::config::RetryConfig;
 use crate::network::cache::{ApiCache, CacheEntry};
+use crate::network::index::parse_manifest;
 use crate::network::tap_formula::{parse_tap_formula_ref, parse_tap_formula_ruby};
+use crate::network::{proxy, retry};
 use futures_util::stream::{self, StreamExt};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
 use zb_core::{Error, Formula};
 
 const HOMEBREW_CORE_RAW_BASE: &str =
     "https://raw.githubusercontent.com/Homebrew/homebrew-core/main";
 
+/// Repository the generated API is published from; its history keeps every
+/// published `formula.json`.
+const INDEX_ARCHIVE_REPO: &str = "Homebrew/formulae.brew.sh";
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum RubySourceLocator<'a> {
     CoreRelativePath(&'a str),
     AbsoluteUrl(&'a str),
     TapEncodedUrl(&'a str),
+    /// A formula file in a local tap checkout.
+    LocalPath(&'a str),
 }
 
 impl<'a> RubySourceLocator<'a> {
@@ -26,6 +37,10 @@ impl<'a> RubySourceLocator<'a> {
             return Self::AbsoluteUrl(input);
         }
 
+        if input.starts_with('/') {
+            return Self::LocalPath(input);
+        }
+
         Self::CoreRelativePath(input)
     }
 
@@ -34,6 +49,7 @@ impl<'a> RubySourceLocator<'a> {
             Self::CoreRelativePath(_) => original,
             Self::AbsoluteUrl(url) => url,
             Self::TapEncodedUrl(url) => url,
+            Self::LocalPath(path) => path,
         }
     }
 
@@ -41,6 +57,7 @@ impl<'a> RubySourceLocator<'a> {
         match self {
             Self::CoreRelativePath(path) => format!("{HOMEBREW_CORE_RAW_BASE}/{path}"),
             Self::AbsoluteUrl(url) | Self::TapEncodedUrl(url) => url.to_string(),
+            Self::LocalPath(path) => format!("file://{path}"),
         }
     }
 
@@ -49,11 +66,32 @@ impl<'a> RubySourceLocator<'a> {
     }
 }
 
+/// Whether `s` looks like a `YYYY-MM-DD` date.
+fn is_date(s: &str) -> bool {
+    s.len() == 10
+        && s.char_indices().all(|(i, c)| match i {
+            4 | 7 => c == '-',
+            _ => c.is_ascii_digit(),
+        })
+}
+
+/// The URL a formula's `ruby_source_path` is fetched from.
+pub fn formula_rb_url(ruby_source_path: &str) -> String {
+    RubySourceLocator::parse(ruby_source_path).to_url()
+}
+
 pub struct ApiClient {
     base_url: String,
     cask_base_url: String,
     tap_raw_base_url: String,
-    client: reqwest::Client,
+    github_api_base_url: String,
+    // Built on first request: loading TLS roots dominates the startup of
+    // commands that never go online.
+    client: OnceLock<reqwest::Client>,
+    /// Sent to GitHub so formulas in private taps can be read.
+    github_token: Option<String>,
+    retry: RetryConfig,
+    proxy: Option<reqwest::Proxy>,
     cache: Option<ApiCache>,
 }
 
@@ -63,39 +101,114 @@ impl ApiClient {
     }
 
     pub fn with_base_url(base_url: String) -> Self {
-        // Use HTTP/2 with connection pooling for better multiplexing of parallel requests
-        let client = reqwest::Client::builder()
-            .user_agent("zerobrew/0.1")
-            .pool_max_idle_per_host(20)
-            .build()
-            .unwrap_or_else(|_| reqwest::Client::new());
-
         Self {
             base_url,
             cask_base_url: "https://formulae.brew.sh/api/cask".to_string(),
             tap_raw_base_url: "https://raw.githubusercontent.com".to_string(),
-            client,
+            github_api_base_url: "https://api.github.com".to_string(),
+            client: OnceLock::new(),
+            github_token: None,
+            retry: RetryConfig::default(),
+            proxy: None,
             cache: None,
         }
     }
 
+    fn client(&self) -> &reqwest::Client {
+        self.client.get_or_init(|| {
+            // Use HTTP/2 with connection pooling for better multiplexing of parallel requests
+            proxy::apply(reqwest::Client::builder(), self.proxy.as_ref())
+                .user_agent("zerobrew/0.1")
+                .pool_max_idle_per_host(20)
+                .build()
+                .unwrap_or_else(|_| reqwest::Client::new())
+        })
+    }
+
     #[cfg(test)]
     pub fn with_tap_raw_base_url(mut self, tap_raw_base_url: String) -> Self {
         self.tap_raw_base_url = tap_raw_base_url;
         self
     }
 
+    #[cfg(test)]
+    pub fn with_github_api_base_url(mut self, github_api_base_url: String) -> Self {
+        self.github_api_base_url = github_api_base_url;
+        self
+    }
+
     #[cfg(test)]
     pub fn with_cask_base_url(mut self, cask_base_url: String) -> Self {
         self.cask_base_url = cask_base_url;
         self
     }
 
+    pub fn with_github_token(mut self, token: Option<String>) -> Self {
+        self.github_token = token;
+        self
+    }
+
+    /// How failed requests are retried (see [`RetryConfig`]).
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Route requests through `proxy` instead of the proxy environment
+    /// variables (see [`ProxyConfig`](crate::ProxyConfig)).
+    pub(crate) fn with_proxy(mut self, proxy: Option<reqwest::Proxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// The proxy set with [`with_proxy`](Self::with_proxy), for other
+    /// clients that should go the same way.
+    pub(crate) fn proxy(&self) -> Option<&reqwest::Proxy> {
+        self.proxy.as_ref()
+    }
+
+    /// Send `request`, retrying transient failures.
+    async fn send(&self, request: reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+        retry::send(&self.retry, request).await
+    }
+
+    /// A GET request for `url`, carrying the GitHub token when there is one
+    /// and `url` is on GitHub.
+    fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        let request = self.client().get(url);
+        let on_github = [&self.tap_raw_base_url, &self.github_api_base_url]
+            .iter()
+            .any(|base| {
+                url.strip_prefix(base.trim_end_matches('/'))
+                    .is_some_and(|rest| rest.starts_with('/'))
+            });
+        match &self.github_token {
+            Some(token) if on_github => request.header("Authorization", format!("token {token}")),
+            _ => request,
+        }
+    }
+
     pub fn with_cache(mut self, cache: ApiCache) -> Self {
         self.cache = Some(cache);
         self
     }
 
+    /// A client for the same endpoints without the response cache, which
+    /// can be moved to another task.
+    pub(crate) fn uncached(&self) -> Self {
+        Self {
+            base_url: self.base_url.clone(),
+            cask_base_url: self.cask_base_url.clone(),
+            tap_raw_base_url: self.tap_raw_base_url.clone(),
+            github_api_base_url: self.github_api_base_url.clone(),
+            client: self.client.clone(),
+            github_token: self.github_token.clone(),
+            retry: self.retry.clone(),
+            proxy: self.proxy.clone(),
+            cache: None,
+        }
+    }
+
     pub async fn fetch_formula_rb(
         &self,
         ruby_source_path: &str,
@@ -103,6 +216,14 @@ impl ApiClient {
         expected_sha256: Option<&str>,
     ) -> Result<std::path::PathBuf, Error> {
         let locator = RubySourceLocator::parse(ruby_source_path);
+        if let RubySourceLocator::LocalPath(path) = locator {
+            let body = std::fs::read(path).map_err(|e| Error::FileError {
+                message: format!("failed to read {path}: {e}"),
+            })?;
+            verify_sha256_bytes(&body, expected_sha256)
+                .map_err(|e| Self::map_formula_rb_checksum_error(e, path, "tap checkout"))?;
+            return Ok(std::path::PathBuf::from(path));
+        }
         let source_id = locator.source_id(ruby_source_path);
         let url = locator.to_url();
 
@@ -133,9 +254,7 @@ impl ApiClient {
         }
 
         let response = self
-            .client
-            .get(url)
-            .send()
+            .send(self.get(url))
             .await
             .map_err(|e| Error::NetworkFailure {
                 message: format!("failed to fetch formula rb: {e}"),
@@ -191,11 +310,57 @@ impl ApiClient {
             return self.get_tap_formula(&spec).await;
         }
 
+        match self.get_core_formula(name).await {
+            Err(Error::MissingFormula { .. }) => match self.canonical_formula_name(name).await {
+                Some(canonical) => self.get_core_formula(&canonical).await,
+                None => Err(Error::MissingFormula {
+                    name: name.to_string(),
+                }),
+            },
+            result => result,
+        }
+    }
+
+    /// The current name of the core formula `name` is an alias for, or was
+    /// renamed from, following successive renames. Best effort: `None` when
+    /// it is neither or the lists can't be fetched.
+    async fn canonical_formula_name(&self, name: &str) -> Option<String> {
+        if let Some(target) = self.fetch_name_map("formula_aliases").await.remove(name) {
+            return Some(target);
+        }
+
+        let renames = self.fetch_name_map("formula_renames").await;
+        let mut current = renames.get(name)?;
+        // Bounded, so a cycle in the list can't spin forever.
+        for _ in 0..renames.len() {
+            match renames.get(current) {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        Some(current.clone())
+    }
+
+    /// `{api_root}/{list}.json`, an object mapping old names to current ones,
+    /// or an empty map when it isn't published.
+    async fn fetch_name_map(&self, list: &str) -> BTreeMap<String, String> {
+        let api_root = self.base_url.trim_end_matches("/formula");
+        let url = format!("{api_root}/{list}.json");
+        let Ok(response) = self.client().get(&url).send().await else {
+            return BTreeMap::new();
+        };
+        if !response.status().is_success() {
+            return BTreeMap::new();
+        }
+        response.json().await.unwrap_or_default()
+    }
+
+    async fn get_core_formula(&self, name: &str) -> Result<Formula, Error> {
         let url = format!("{}/{}.json", self.base_url, name);
 
         let cached_entry = self.cache.as_ref().and_then(|c| c.get(&url));
 
-        let mut request = self.client.get(&url);
+        let mut request = self.client().get(&url);
 
         if let Some(ref entry) = cached_entry {
             if let Some(ref etag) = entry.etag {
@@ -206,9 +371,12 @@ impl ApiClient {
             }
         }
 
-        let response = request.send().await.map_err(|e| Error::NetworkFailure {
-            message: e.to_string(),
-        })?;
+        let response = self
+            .send(request)
+            .await
+            .map_err(|e| Error::NetworkFailure {
+                message: e.to_string(),
+            })?;
 
         if response.status() == reqwest::StatusCode::NOT_MODIFIED
             && let Some(entry) = cached_entry
@@ -264,17 +432,227 @@ impl ApiClient {
         Ok(formula)
     }
 
-    pub async fn get_cask(&self, token: &str) -> Result<serde_json::Value, Error> {
-        let url = format!("{}/{}.json", self.cask_base_url, token);
+    /// The compact per-formula manifest for `tag`, as `(raw body, digests)`.
+    ///
+    /// Returns `None` when the API doesn't publish one, in which case callers
+    /// fall back to the full index.
+    pub async fn fetch_index_manifest(
+        &self,
+        tag: &str,
+    ) -> Result<Option<(String, BTreeMap<String, String>)>, Error> {
+        let api_root = self.base_url.trim_end_matches("/formula");
+        let url = format!("{api_root}/internal/formula.{tag}.jws.json");
         let response = self
-            .client
+            .client()
             .get(&url)
             .send()
             .await
             .map_err(|e| Error::NetworkFailure {
-                message: e.to_string(),
+                message: format!("failed to fetch formula manifest: {e}"),
             })?;
 
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!("formula manifest fetch returned HTTP {}", response.status()),
+            });
+        }
+
+        let body = response.text().await.map_err(|e| Error::NetworkFailure {
+            message: format!("failed to read formula manifest: {e}"),
+        })?;
+        let digests = parse_manifest(&body)?;
+        Ok(Some((body, digests)))
+    }
+
+    /// The full formula index (a JSON array of every formula).
+    pub async fn fetch_full_index(&self) -> Result<String, Error> {
+        let url = format!("{}.json", self.base_url);
+        let response =
+            self.send(self.client().get(&url))
+                .await
+                .map_err(|e| Error::NetworkFailure {
+                    message: format!("failed to fetch formula index: {e}"),
+                })?;
+
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!("formula index fetch returned HTTP {}", response.status()),
+            });
+        }
+
+        response.text().await.map_err(|e| Error::NetworkFailure {
+            message: format!("failed to read formula index: {e}"),
+        })
+    }
+
+    /// Full commit SHA of the archived formula index for `pin`: a commit SHA
+    /// (abbreviated or not) or a `YYYY-MM-DD` date, meaning the last index
+    /// published on or before that day.
+    pub async fn resolve_index_snapshot(&self, pin: &str) -> Result<String, Error> {
+        let api = self.github_api_base_url.trim_end_matches('/');
+        let url = if is_date(pin) {
+            format!("{api}/repos/{INDEX_ARCHIVE_REPO}/commits?until={pin}T23:59:59Z&per_page=1")
+        } else if (7..=40).contains(&pin.len()) && pin.chars().all(|c| c.is_ascii_hexdigit()) {
+            format!("{api}/repos/{INDEX_ARCHIVE_REPO}/commits/{pin}")
+        } else {
+            return Err(Error::InvalidArgument {
+                message: format!("'{pin}' is neither a YYYY-MM-DD date nor a commit SHA"),
+            });
+        };
+
+        let response = self
+            .get(&url)
+            .header("Accept", "application/vnd.github+json")
+            .send()
+            .await
+            .map_err(|e| Error::NetworkFailure {
+                message: format!("failed to look up index snapshot {pin}: {e}"),
+            })?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND
+            || response.status() == reqwest::StatusCode::UNPROCESSABLE_ENTITY
+        {
+            return Err(Error::InvalidArgument {
+                message: format!("no index snapshot matches '{pin}'"),
+            });
+        }
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!("index snapshot lookup returned HTTP {}", response.status()),
+            });
+        }
+
+        let json: serde_json::Value = response.json().await.map_err(|e| Error::NetworkFailure {
+            message: format!("failed to parse index snapshot lookup: {e}"),
+        })?;
+        // A date query lists commits, newest first; a SHA names one.
+        let commit = json
+            .as_array()
+            .map_or(Some(&json), |commits| commits.first());
+        commit
+            .and_then(|commit| commit.get("sha")?.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| Error::InvalidArgument {
+                message: format!("no index snapshot was published by {pin}"),
+            })
+    }
+
+    /// The full formula index as archived at commit `sha`.
+    pub async fn fetch_index_snapshot(&self, sha: &str) -> Result<String, Error> {
+        let url = format!(
+            "{}/{INDEX_ARCHIVE_REPO}/{sha}/api/formula.json",
+            self.tap_raw_base_url.trim_end_matches('/')
+        );
+        let response = self
+            .send(self.get(&url))
+            .await
+            .map_err(|e| Error::NetworkFailure {
+                message: format!("failed to fetch index snapshot: {e}"),
+            })?;
+
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!("index snapshot fetch returned HTTP {}", response.status()),
+            });
+        }
+
+        response.text().await.map_err(|e| Error::NetworkFailure {
+            message: format!("failed to read index snapshot: {e}"),
+        })
+    }
+
+    /// 90-day install counts per formula from the public analytics, with
+    /// the number of bytes read. Returns `None` when they aren't published.
+    pub async fn fetch_install_counts(
+        &self,
+    ) -> Result<Option<(BTreeMap<String, u64>, u64)>, Error> {
+        let api_root = self.base_url.trim_end_matches("/formula");
+        let url = format!("{api_root}/analytics/install/90d.json");
+        let response = self
+            .client()
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| Error::NetworkFailure {
+                message: format!("failed to fetch install analytics: {e}"),
+            })?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!(
+                    "install analytics fetch returned HTTP {}",
+                    response.status()
+                ),
+            });
+        }
+
+        let body = response.text().await.map_err(|e| Error::NetworkFailure {
+            message: format!("failed to read install analytics: {e}"),
+        })?;
+        let json: serde_json::Value =
+            serde_json::from_str(&body).map_err(|e| Error::NetworkFailure {
+                message: format!("failed to parse install analytics: {e}"),
+            })?;
+
+        // Counts are formatted with thousands separators, e.g. "258,301".
+        let counts = json
+            .get("items")
+            .and_then(|items| items.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|item| {
+                let name = item.get("formula")?.as_str()?;
+                let count = item.get("count")?.as_str()?.replace(',', "").parse().ok()?;
+                Some((name.to_string(), count))
+            })
+            .collect();
+        Ok(Some((counts, body.len() as u64)))
+    }
+
+    /// A single formula's JSON, uninterpreted, with the number of bytes read.
+    pub async fn fetch_formula_json(&self, name: &str) -> Result<(serde_json::Value, u64), Error> {
+        let url = format!("{}/{}.json", self.base_url, name);
+        let response =
+            self.send(self.client().get(&url))
+                .await
+                .map_err(|e| Error::NetworkFailure {
+                    message: e.to_string(),
+                })?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(Error::MissingFormula {
+                name: name.to_string(),
+            });
+        }
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!("HTTP {}", response.status()),
+            });
+        }
+
+        let body = response.text().await.map_err(|e| Error::NetworkFailure {
+            message: format!("failed to read response body: {e}"),
+        })?;
+        let json = serde_json::from_str(&body).map_err(|e| Error::NetworkFailure {
+            message: format!("failed to parse formula JSON: {e}"),
+        })?;
+        Ok((json, body.len() as u64))
+    }
+
+    pub async fn get_cask(&self, token: &str) -> Result<serde_json::Value, Error> {
+        let url = format!("{}/{}.json", self.cask_base_url, token);
+        let response =
+            self.send(self.client().get(&url))
+                .await
+                .map_err(|e| Error::NetworkFailure {
+                    message: e.to_string(),
+                })?;
+
         if response.status() == reqwest::StatusCode::NOT_FOUND {
             return Err(Error::MissingFormula {
                 name: format!("cask:{token}"),
@@ -299,22 +677,8 @@ impl ApiClient {
         &self,
         spec: &crate::network::tap_formula::TapFormulaRef,
     ) -> Result<Formula, Error> {
-        let candidate_repos = if spec.repo.starts_with("homebrew-") {
-            vec![
-                spec.repo.clone(),
-                spec.repo.trim_start_matches("homebrew-").to_string(),
-            ]
-        } else {
-            vec![format!("homebrew-{}", spec.repo), spec.repo.clone()]
-        };
-        let first_char = spec.formula.chars().next().unwrap_or('x');
-        let candidate_paths = [
-            format!("Formula/{}.rb", spec.formula),
-            format!("Formula/{first_char}/{}.rb", spec.formula),
-            format!("HomebrewFormula/{}.rb", spec.formula),
-            format!("HomebrewFormula/{first_char}/{}.rb", spec.formula),
-            format!("{}.rb", spec.formula),
-        ];
+        let candidate_repos = spec.candidate_repos();
+        let candidate_paths = spec.candidate_paths();
         let branches = ["main", "master"];
 
         let mut last_status: Option<reqwest::StatusCode> = None;
@@ -330,11 +694,10 @@ impl ApiClient {
                     repo,
                     branch,
                 );
-                let client = self.client.clone();
                 let mut responses = stream::iter(candidate_paths.iter().map(|candidate_path| {
-                    let client = client.clone();
                     let url = format!("{base_prefix}{candidate_path}");
-                    async move { (url.clone(), client.get(&url).send().await) }
+                    let request = self.get(&url);
+                    async move { (url, self.send(request).await) }
                 }))
                 .buffered(2);
 
@@ -427,6 +790,10 @@ mod tests {
             RubySourceLocator::parse(&encoded),
             RubySourceLocator::TapEncodedUrl("https://example.com/tap/foo.rb")
         );
+        assert_eq!(
+            RubySourceLocator::parse("/opt/zerobrew/taps/acme/homebrew-tools/Formula/foo.rb"),
+            RubySourceLocator::LocalPath("/opt/zerobrew/taps/acme/homebrew-tools/Formula/foo.rb")
+        );
     }
 
     #[test]
@@ -464,7 +831,7 @@ mod tests {
         let formula = client.get_formula("foo").await.unwrap();
 
         assert_eq!(formula.name, "foo");
-        assert_eq!(formula.versions.stable, "1.2.3");
+        assert_eq!(formula.versions.stable.as_deref(), Some("1.2.3"));
     }
 
     #[tokio::test]
@@ -486,6 +853,38 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn resolves_aliases_and_renamed_formulas() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../../zb_core/fixtures/formula_foo.json");
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula_aliases.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"foo@latest":"foo"}"#))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula_renames.json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"oldest-foo":"old-foo","old-foo":"foo"}"#),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(mock_server.uri());
+        assert_eq!(client.get_formula("foo@latest").await.unwrap().name, "foo");
+        assert_eq!(client.get_formula("oldest-foo").await.unwrap().name, "foo");
+
+        let err = client.get_formula("bar").await.unwrap_err();
+        assert!(matches!(err, Error::MissingFormula { name } if name == "bar"));
+    }
+
     #[tokio::test]
     async fn first_request_stores_etag() {
         let mock_server = MockServer::start().await;
@@ -589,7 +988,7 @@ mod tests {
         // Should return cached formula
         let formula = client.get_formula("foo").await.unwrap();
         assert_eq!(formula.name, "foo");
-        assert_eq!(formula.versions.stable, "1.2.3");
+        assert_eq!(formula.versions.stable.as_deref(), Some("1.2.3"));
     }
 
     #[tokio::test]
@@ -617,7 +1016,7 @@ end
         let formula = client.get_formula("hashicorp/tap/terraform").await.unwrap();
 
         assert_eq!(formula.name, "terraform");
-        assert_eq!(formula.versions.stable, "1.10.0");
+        assert_eq!(formula.versions.stable.as_deref(), Some("1.10.0"));
         assert!(formula.dependencies.contains(&"go".to_string()));
         assert!(formula.bottle.stable.files.contains_key("arm64_sonoma"));
         let expected_path = format!(
@@ -631,6 +1030,47 @@ end
         );
     }
 
+    #[tokio::test]
+    async fn private_tap_sources_are_fetched_with_the_github_token() {
+        let mock_server = MockServer::start().await;
+        let rb = r#"
+class Deploy < Formula
+  version "0.4.0"
+  url "https://github.com/acme/deploy/archive/v0.4.0.tar.gz"
+  sha256 "ff39f6ef2b4ca2d7d766f2802520b023986a5d6dbcd59fba685a9e5bacf41993"
+end
+"#;
+
+        Mock::given(method("GET"))
+            .and(path("/acme/homebrew-private/main/Formula/deploy.rb"))
+            .and(header("authorization", "token secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(rb))
+            .mount(&mock_server)
+            .await;
+
+        let client =
+            ApiClient::with_base_url(mock_server.uri()).with_tap_raw_base_url(mock_server.uri());
+        assert!(matches!(
+            client.get_formula("acme/private/deploy").await,
+            Err(Error::MissingFormula { .. })
+        ));
+
+        let client = client.with_github_token(Some("secret".to_string()));
+        let formula = client.get_formula("acme/private/deploy").await.unwrap();
+        assert_eq!(formula.versions.stable.as_deref(), Some("0.4.0"));
+
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let rb_path = client
+            .fetch_formula_rb(
+                formula.ruby_source_path.as_deref().unwrap(),
+                cache_dir.path(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(rb_path).unwrap(), rb);
+    }
+
     #[tokio::test]
     async fn supports_source_only_tap_formula_without_bottle_block() {
         let mock_server = MockServer::start().await;
@@ -700,7 +1140,7 @@ end
         let formula = client.get_formula("hashicorp/tap/terraform").await.unwrap();
 
         assert_eq!(formula.name, "terraform");
-        assert_eq!(formula.versions.stable, "1.10.0");
+        assert_eq!(formula.versions.stable.as_deref(), Some("1.10.0"));
     }
 
     #[tokio::test]
@@ -727,7 +1167,7 @@ end
         let formula = client.get_formula("hashicorp/tap/terraform").await.unwrap();
 
         assert_eq!(formula.name, "terraform");
-        assert_eq!(formula.versions.stable, "1.10.0");
+        assert_eq!(formula.versions.stable.as_deref(), Some("1.10.0"));
     }
 
     #[tokio::test]
@@ -756,7 +1196,7 @@ end
         let formula = client.get_formula("hashicorp/tap/terraform").await.unwrap();
 
         assert_eq!(formula.name, "terraform");
-        assert_eq!(formula.versions.stable, "1.10.0");
+        assert_eq!(formula.versions.stable.as_deref(), Some("1.10.0"));
     }
 
     #[tokio::test]
@@ -785,7 +1225,7 @@ end
         let formula = client.get_formula("hashicorp/tap/terraform").await.unwrap();
 
         assert_eq!(formula.name, "terraform");
-        assert_eq!(formula.versions.stable, "1.10.0");
+        assert_eq!(formula.versions.stable.as_deref(), Some("1.10.0"));
     }
 
     #[tokio::test]
@@ -812,7 +1252,7 @@ end
         let formula = client.get_formula("hashicorp/tap/terraform").await.unwrap();
 
         assert_eq!(formula.name, "terraform");
-        assert_eq!(formula.versions.stable, "1.10.0");
+        assert_eq!(formula.versions.stable.as_deref(), Some("1.10.0"));
     }
 
     #[tokio::test]
@@ -1,8 +1,10 @@
 use rusqlite::{Connection, params};
 use std::path::Path;
+use std::sync::Mutex;
 
+/// Behind a mutex so an [`crate::ApiClient`] can be shared between tasks.
 pub struct ApiCache {
-    conn: Connection,
+    conn: Mutex<Connection>,
 }
 
 #[derive(Debug, Clone)]
@@ -16,13 +18,17 @@ impl ApiCache {
     pub fn open(path: &Path) -> Result<Self, rusqlite::Error> {
         let conn = Connection::open(path)?;
         Self::init_schema(&conn)?;
-        Ok(Self { conn })
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
     }
 
     pub fn in_memory() -> Result<Self, rusqlite::Error> {
         let conn = Connection::open_in_memory()?;
         Self::init_schema(&conn)?;
-        Ok(Self { conn })
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
     }
 
     fn init_schema(conn: &Connection) -> Result<(), rusqlite::Error> {
@@ -41,6 +47,8 @@ impl ApiCache {
 
     pub fn get(&self, url: &str) -> Option<CacheEntry> {
         self.conn
+            .lock()
+            .ok()?
             .query_row(
                 "SELECT etag, last_modified, body FROM api_cache WHERE url = ?1",
                 params![url],
@@ -61,7 +69,8 @@ impl ApiCache {
             .map(|d| d.as_secs() as i64)
             .unwrap_or(0);
 
-        self.conn.execute(
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute(
             "INSERT OR REPLACE INTO api_cache (url, etag, last_modified, body, cached_at)
              VALUES (?1, ?2, ?3, ?4, ?5)",
             params![url, entry.etag, entry.last_modified, entry.body, now],
@@ -0,0 +1,245 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use tokio::sync::Notify;
+
+/// Concurrency a fresh limiter starts with before it has seen any traffic.
+const INITIAL_CONCURRENCY: usize = 4;
+
+/// A round whose throughput falls below this fraction of the previous round's
+/// is taken as a sign that more connections no longer help.
+const PLATEAU_TOLERANCE: f64 = 0.9;
+
+/// AIMD-style limit on the number of downloads in flight.
+///
+/// The limit grows by one after every round of `limit` successful downloads as long
+/// as throughput keeps up, and is halved whenever a download fails or stalls. The
+/// configured concurrency acts as a ceiling rather than a fixed value.
+pub struct AdaptiveLimiter {
+    max: usize,
+    state: Mutex<State>,
+    notify: Notify,
+    trace: AtomicBool,
+}
+
+struct State {
+    limit: usize,
+    inflight: usize,
+    round_start: Instant,
+    round_bytes: u64,
+    round_successes: usize,
+    last_throughput: Option<f64>,
+}
+
+pub struct AdaptivePermit {
+    limiter: Arc<AdaptiveLimiter>,
+}
+
+impl Drop for AdaptivePermit {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        state.inflight -= 1;
+        drop(state);
+        self.limiter.notify.notify_waiters();
+    }
+}
+
+impl AdaptiveLimiter {
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            max,
+            state: Mutex::new(State {
+                limit: INITIAL_CONCURRENCY.min(max),
+                inflight: 0,
+                round_start: Instant::now(),
+                round_bytes: 0,
+                round_successes: 0,
+                last_throughput: None,
+            }),
+            notify: Notify::new(),
+            trace: AtomicBool::new(false),
+        }
+    }
+
+    /// Print every limit decision to stderr.
+    pub fn set_trace(&self, enabled: bool) {
+        self.trace.store(enabled, Ordering::Relaxed);
+        if enabled {
+            eprintln!(
+                "[debug-http] adaptive concurrency: starting at {}, ceiling {}",
+                self.limit(),
+                self.max
+            );
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    pub async fn acquire(self: &Arc<Self>) -> AdaptivePermit {
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.inflight < state.limit {
+                    state.inflight += 1;
+                    return AdaptivePermit {
+                        limiter: self.clone(),
+                    };
+                }
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Additive increase: after a full round of successes, allow one more download
+    /// unless throughput dropped compared to the previous round.
+    pub fn record_success(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.round_bytes += bytes;
+        state.round_successes += 1;
+        if state.round_successes < state.limit {
+            return;
+        }
+
+        let elapsed = state.round_start.elapsed().as_secs_f64().max(f64::EPSILON);
+        let throughput = state.round_bytes as f64 / elapsed;
+        let previous = state.last_throughput.replace(throughput);
+        let old = state.limit;
+
+        let reason = match previous {
+            Some(prev) if throughput < prev * PLATEAU_TOLERANCE => "throughput dropped, holding",
+            _ if old >= self.max => "at ceiling",
+            _ => {
+                state.limit += 1;
+                "round complete"
+            }
+        };
+        let new = state.limit;
+        Self::reset_round(&mut state);
+        drop(state);
+
+        self.trace_decision(
+            old,
+            new,
+            &format!("{reason} ({})", format_throughput(throughput)),
+        );
+        if new > old {
+            self.notify.notify_waiters();
+        }
+    }
+
+    /// Multiplicative decrease: halve the limit after a failed or stalled download.
+    pub fn record_failure(&self, reason: &str) {
+        let mut state = self.state.lock().unwrap();
+        let old = state.limit;
+        state.limit = (old / 2).max(1);
+        let new = state.limit;
+        Self::reset_round(&mut state);
+        drop(state);
+
+        self.trace_decision(old, new, reason);
+    }
+
+    fn reset_round(state: &mut State) {
+        state.round_start = Instant::now();
+        state.round_bytes = 0;
+        state.round_successes = 0;
+    }
+
+    fn trace_decision(&self, old: usize, new: usize, reason: &str) {
+        if self.trace.load(Ordering::Relaxed) {
+            eprintln!("[debug-http] concurrency {old} -> {new}: {reason}");
+        }
+    }
+}
+
+fn format_throughput(bytes_per_sec: f64) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    if bytes_per_sec >= MIB {
+        format!("{:.1} MiB/s", bytes_per_sec / MIB)
+    } else {
+        format!("{:.0} KiB/s", bytes_per_sec / 1024.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn starts_below_ceiling() {
+        assert_eq!(AdaptiveLimiter::new(20).limit(), INITIAL_CONCURRENCY);
+        assert_eq!(AdaptiveLimiter::new(2).limit(), 2);
+        assert_eq!(AdaptiveLimiter::new(0).limit(), 1);
+    }
+
+    #[test]
+    fn grows_after_a_round_and_stops_at_ceiling() {
+        let limiter = AdaptiveLimiter::new(5);
+        for _ in 0..4 {
+            limiter.record_success(1024);
+        }
+        assert_eq!(limiter.limit(), 5);
+
+        for _ in 0..5 {
+            limiter.record_success(1024 * 1024);
+        }
+        assert_eq!(limiter.limit(), 5);
+    }
+
+    #[test]
+    fn halves_on_failure_but_never_below_one() {
+        let limiter = AdaptiveLimiter::new(20);
+        limiter.record_failure("test");
+        assert_eq!(limiter.limit(), 2);
+        limiter.record_failure("test");
+        limiter.record_failure("test");
+        assert_eq!(limiter.limit(), 1);
+    }
+
+    #[test]
+    fn holds_when_throughput_drops() {
+        let limiter = AdaptiveLimiter::new(20);
+        for _ in 0..4 {
+            limiter.record_success(100 * 1024 * 1024);
+        }
+        assert_eq!(limiter.limit(), 5);
+
+        std::thread::sleep(Duration::from_millis(20));
+        for _ in 0..5 {
+            limiter.record_success(1);
+        }
+        assert_eq!(limiter.limit(), 5);
+    }
+
+    #[tokio::test]
+    async fn acquire_waits_for_a_free_slot() {
+        let limiter = Arc::new(AdaptiveLimiter::new(1));
+        let first = limiter.acquire().await;
+
+        let waiter = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move {
+                let _permit = limiter.acquire().await;
+            })
+        };
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        drop(first);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
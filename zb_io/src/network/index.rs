@@ -0,0 +1,1149 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use futures_util::stream::{self, StreamExt};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
+use sha2::{Digest, Sha256};
+use zb_core::{Error, Formula};
+
+use crate::network::api::ApiClient;
+
+/// Above this many changed formulas a single full download is cheaper than
+/// fetching them one by one.
+const FULL_REFRESH_THRESHOLD: usize = 500;
+
+/// Concurrent per-formula fetches during a delta update.
+const DELTA_CONCURRENCY: usize = 16;
+
+/// Concurrent per-formula fetches during a background refresh, which shares
+/// the connection with an install's downloads.
+const BACKGROUND_CONCURRENCY: usize = 2;
+
+/// Install counts only steer search ranking, so they are refreshed weekly.
+const POPULARITY_MAX_AGE_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Full-text matches considered before ranking.
+const FTS_CANDIDATES: usize = 200;
+
+/// Bytes of the index file SQLite may memory-map for read-only lookups.
+const MMAP_BYTES: i64 = 256 * 1024 * 1024;
+
+/// Dependency closures remembered between invocations; the least recently
+/// used are dropped first.
+const GRAPH_CACHE_ENTRIES: usize = 256;
+
+/// Local copy of the formula index, refreshed by `zb update`.
+///
+/// Each row keeps the formula JSON plus the digest of its entry in the
+/// upstream manifest; comparing digests is what lets an update fetch only the
+/// formulas that changed.
+pub struct FormulaIndex {
+    conn: Connection,
+}
+
+/// One formula as recorded in the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedFormula {
+    pub name: String,
+    pub version: String,
+    pub desc: Option<String>,
+}
+
+/// A formula matched by [`FormulaIndex::search`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    pub name: String,
+    pub version: String,
+    pub desc: Option<String>,
+    /// Installs over the last 90 days, or 0 when unknown.
+    pub installs: u64,
+    /// Matched only by edit distance, i.e. the query looks like a typo.
+    pub fuzzy: bool,
+}
+
+/// Outcome of [`update_index`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexUpdate {
+    /// Whether the whole index was downloaded rather than just the changes.
+    pub full: bool,
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+    pub bytes_downloaded: u64,
+    pub total: usize,
+}
+
+/// The API snapshot an index is pinned to by [`pin_index`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexPin {
+    /// What the pin was asked for: a date or a (possibly abbreviated) SHA.
+    pub requested: String,
+    /// Full commit SHA of the snapshot.
+    pub sha: String,
+}
+
+impl IndexUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl FormulaIndex {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let conn = Connection::open(path)
+            .map_err(|e| Error::db("failed to open formula index").with_source(e))?;
+        Self::init_schema(&conn)?;
+        Ok(Self { conn })
+    }
+
+    /// Open an existing index for lookups only.
+    ///
+    /// The file is memory-mapped and the schema is not touched, so this is
+    /// cheap enough to do on every shell completion request.
+    pub fn open_read_only(path: &Path) -> Result<Self, Error> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .map_err(|e| Error::db("failed to open formula index").with_source(e))?;
+        conn.pragma_update(None, "mmap_size", MMAP_BYTES)
+            .map_err(|e| Error::db("failed to memory-map formula index").with_source(e))?;
+        Ok(Self { conn })
+    }
+
+    pub fn in_memory() -> Result<Self, Error> {
+        let conn = Connection::open_in_memory()
+            .map_err(|e| Error::db("failed to open in-memory formula index").with_source(e))?;
+        Self::init_schema(&conn)?;
+        Ok(Self { conn })
+    }
+
+    fn init_schema(conn: &Connection) -> Result<(), Error> {
+        conn.execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS formulas (
+                name TEXT PRIMARY KEY,
+                version TEXT NOT NULL,
+                desc TEXT,
+                digest TEXT,
+                json TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS index_meta (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS formula_popularity (
+                name TEXT PRIMARY KEY,
+                installs INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS graph_cache (
+                root TEXT PRIMARY KEY,
+                index_version TEXT NOT NULL,
+                closure TEXT NOT NULL,
+                used_at INTEGER NOT NULL
+            );
+
+            CREATE VIRTUAL TABLE IF NOT EXISTS formula_search USING fts5(name, desc);
+            ",
+        )
+        .map_err(|e| Error::db("failed to initialize formula index schema").with_source(e))?;
+
+        // Indexes written before search existed have formulas but no search rows.
+        conn.execute(
+            "INSERT INTO formula_search (name, desc)
+             SELECT name, desc FROM formulas
+             WHERE NOT EXISTS (SELECT 1 FROM formula_search)",
+            [],
+        )
+        .map_err(|e| Error::db("failed to build formula search index").with_source(e))?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM formulas", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|count| count as usize)
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, name: &str) -> Option<IndexedFormula> {
+        self.conn
+            .query_row(
+                "SELECT name, version, desc FROM formulas WHERE name = ?1",
+                params![name],
+                |row| {
+                    Ok(IndexedFormula {
+                        name: row.get(0)?,
+                        version: row.get(1)?,
+                        desc: row.get(2)?,
+                    })
+                },
+            )
+            .ok()
+    }
+
+    /// Raw formula JSON as served by the API.
+    pub fn formula_json(&self, name: &str) -> Option<String> {
+        self.conn
+            .query_row(
+                "SELECT json FROM formulas WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .ok()
+    }
+
+    /// A formula as the installer sees it, parsed from the indexed JSON.
+    pub fn formula(&self, name: &str) -> Option<Formula> {
+        serde_json::from_str(&self.formula_json(name)?).ok()
+    }
+
+    /// Dependency closure of `root` (including itself) recorded against the
+    /// current index version.
+    pub fn cached_closure(&self, root: &str) -> Option<Vec<String>> {
+        let version = self.version()?;
+        let closure: String = self
+            .conn
+            .query_row(
+                "SELECT closure FROM graph_cache WHERE root = ?1 AND index_version = ?2",
+                params![root, version],
+                |row| row.get(0),
+            )
+            .ok()?;
+        let _ = self.conn.execute(
+            "UPDATE graph_cache SET used_at = ?2 WHERE root = ?1",
+            params![root, unix_now()],
+        );
+        serde_json::from_str(&closure).ok()
+    }
+
+    /// Remember the dependency closure of `root` until the index changes.
+    /// Does nothing before the first index update.
+    pub fn cache_closure(&self, root: &str, closure: &[String]) -> Result<(), Error> {
+        let Some(version) = self.version() else {
+            return Ok(());
+        };
+        let closure = serde_json::to_string(closure)
+            .map_err(|e| Error::db("failed to encode dependency closure").with_source(e))?;
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO graph_cache (root, index_version, closure, used_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![root, version, closure, unix_now()],
+            )
+            .and_then(|_| {
+                self.conn.execute(
+                    "DELETE FROM graph_cache WHERE root NOT IN
+                     (SELECT root FROM graph_cache ORDER BY used_at DESC, root LIMIT ?1)",
+                    params![GRAPH_CACHE_ENTRIES as i64],
+                )
+            })
+            .map_err(|e| Error::db("failed to cache dependency closure").with_source(e))?;
+        Ok(())
+    }
+
+    /// Unix time of the last successful update, if any.
+    pub fn updated_at(&self) -> Option<i64> {
+        self.meta("updated_at")?.parse().ok()
+    }
+
+    /// Whether the last update was more than `secs` ago (or never happened).
+    pub fn older_than(&self, secs: i64) -> bool {
+        self.updated_at().is_none_or(|at| unix_now() - at > secs)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_updated_at(&self, at: i64) {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO index_meta (key, value) VALUES ('updated_at', ?1)",
+                params![at.to_string()],
+            )
+            .unwrap();
+    }
+
+    /// The file backing the index, or `None` for an in-memory one.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.conn
+            .path()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    }
+
+    /// Identifies the index contents; changes whenever an update changes anything.
+    pub fn version(&self) -> Option<String> {
+        self.meta("version")
+    }
+
+    /// The snapshot the index is pinned to, if any. A pinned index is left
+    /// alone by [`update_index`] and is the only source of core formulas.
+    pub fn pin(&self) -> Option<IndexPin> {
+        Some(IndexPin {
+            requested: self.meta("pin_requested")?,
+            sha: self.meta("pin_sha")?,
+        })
+    }
+
+    /// Record `pin`, or with `None` drop it so updates track the API again.
+    pub fn set_pin(&self, pin: Option<&IndexPin>) -> Result<(), Error> {
+        let result = match pin {
+            Some(pin) => self.conn.execute(
+                "INSERT OR REPLACE INTO index_meta (key, value)
+                 VALUES ('pin_requested', ?1), ('pin_sha', ?2)",
+                params![pin.requested, pin.sha],
+            ),
+            None => self.conn.execute(
+                "DELETE FROM index_meta WHERE key IN ('pin_requested', 'pin_sha')",
+                [],
+            ),
+        };
+        result
+            .map(|_| ())
+            .map_err(|e| Error::db("failed to record index pin").with_source(e))
+    }
+
+    fn meta(&self, key: &str) -> Option<String> {
+        self.conn
+            .query_row(
+                "SELECT value FROM index_meta WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+            .ok()
+            .flatten()
+    }
+
+    /// Formulas matching `query`, best first.
+    ///
+    /// Exact and substring name matches rank first, then full-text matches
+    /// on names and descriptions, then names within a small edit distance of
+    /// the query (so `ripgerp` still finds `ripgrep`). Ties go to the more
+    /// popular formula.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, Error> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Err(Error::InvalidArgument {
+                message: "search query is empty".to_string(),
+            });
+        }
+
+        let text_matches = self.full_text_matches(&query)?;
+        let max_distance = match query.chars().count() {
+            0..=3 => 0,
+            4..=6 => 1,
+            _ => 2,
+        };
+
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT f.name, f.version, f.desc, COALESCE(p.installs, 0)
+                 FROM formulas f LEFT JOIN formula_popularity p ON p.name = f.name",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(SearchHit {
+                    name: row.get(0)?,
+                    version: row.get(1)?,
+                    desc: row.get(2)?,
+                    installs: row.get::<_, i64>(3)?.max(0) as u64,
+                    fuzzy: false,
+                })
+            })
+            .map_err(|e| Error::db("failed to query formulas").with_source(e))?;
+
+        let mut ranked = Vec::new();
+        for row in rows {
+            let mut hit = row.map_err(|e| Error::db("failed to collect results").with_source(e))?;
+            let name = hit.name.to_lowercase();
+            // Versioned formulas (`python@3.12`) also match on their base name.
+            let base = name.split('@').next().unwrap_or(&name);
+
+            let rank = if name == query || base == query {
+                (0, 0)
+            } else if name.starts_with(&query) {
+                (1, 0)
+            } else if name.contains(&query) {
+                (2, 0)
+            } else if let Some(position) = text_matches.get(&hit.name) {
+                (3, *position)
+            } else {
+                match edit_distance(&query, base).min(edit_distance(&query, &name)) {
+                    distance if distance <= max_distance => {
+                        hit.fuzzy = true;
+                        (4, distance)
+                    }
+                    _ => continue,
+                }
+            };
+            ranked.push((rank, hit));
+        }
+
+        // Full-text matches keep their relevance order and fuzzy matches go by
+        // distance; popularity breaks the remaining ties.
+        ranked.sort_by(|(a, a_hit), (b, b_hit)| {
+            a.cmp(b)
+                .then_with(|| b_hit.installs.cmp(&a_hit.installs))
+                .then_with(|| a_hit.name.cmp(&b_hit.name))
+        });
+        Ok(ranked.into_iter().take(limit).map(|(_, hit)| hit).collect())
+    }
+
+    /// Up to `limit` formula names starting with `prefix`, most installed
+    /// first. Only the primary key range for the prefix is read.
+    pub fn complete(&self, prefix: &str, limit: usize) -> Result<Vec<String>, Error> {
+        // Names compare bytewise, so every name with the prefix sorts below
+        // the prefix followed by the largest code point.
+        let upper = format!("{prefix}{}", char::MAX);
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT f.name FROM formulas f
+                 LEFT JOIN formula_popularity p ON p.name = f.name
+                 WHERE f.name >= ?1 AND f.name < ?2
+                 ORDER BY COALESCE(p.installs, 0) DESC, f.name
+                 LIMIT ?3",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+        stmt.query_map(
+            params![prefix, upper, limit.min(i64::MAX as usize) as i64],
+            |row| row.get(0),
+        )
+        .map_err(|e| Error::db("failed to query formula names").with_source(e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Names matching every word of `query` as a prefix, in relevance order.
+    fn full_text_matches(&self, query: &str) -> Result<BTreeMap<String, usize>, Error> {
+        let terms: Vec<String> = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|term| !term.is_empty())
+            .map(|term| format!("\"{term}\"*"))
+            .collect();
+        if terms.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name FROM formula_search WHERE formula_search MATCH ?1
+                 ORDER BY bm25(formula_search, 10.0, 1.0) LIMIT ?2",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+        let names = stmt
+            .query_map(params![terms.join(" "), FTS_CANDIDATES as i64], |row| {
+                row.get::<_, String>(0)
+            })
+            .map_err(|e| Error::db("failed to search formula index").with_source(e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))?;
+
+        Ok(names
+            .into_iter()
+            .enumerate()
+            .map(|(position, name)| (name, position))
+            .collect())
+    }
+
+    /// Unix time install counts were last recorded, if ever.
+    pub fn popularity_updated_at(&self) -> Option<i64> {
+        self.meta("popularity_at")?.parse().ok()
+    }
+
+    /// Replace the recorded install counts.
+    fn record_popularity(&mut self, counts: &BTreeMap<String, u64>) -> Result<(), Error> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| Error::db("failed to start index transaction").with_source(e))?;
+
+        tx.execute("DELETE FROM formula_popularity", [])
+            .map_err(|e| Error::db("failed to clear install counts").with_source(e))?;
+        for (name, installs) in counts {
+            tx.execute(
+                "INSERT INTO formula_popularity (name, installs) VALUES (?1, ?2)",
+                params![name, (*installs).min(i64::MAX as u64) as i64],
+            )
+            .map_err(|e| Error::db("failed to record install count").with_source(e))?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO index_meta (key, value) VALUES ('popularity_at', ?1)",
+            params![unix_now().to_string()],
+        )
+        .map_err(|e| Error::db("failed to record index metadata").with_source(e))?;
+
+        tx.commit()
+            .map_err(|e| Error::db("failed to commit formula index").with_source(e))
+    }
+
+    fn digests(&self) -> Result<BTreeMap<String, Option<String>>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, digest FROM formulas")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| Error::db("failed to query formula digests").with_source(e))?
+            .collect::<Result<BTreeMap<_, _>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Apply one update atomically: upsert `formulas`, delete `removed`, and
+    /// optionally drop every row not in `formulas` (for full refreshes).
+    pub(crate) fn apply(
+        &mut self,
+        formulas: &[(serde_json::Value, Option<String>)],
+        removed: &[String],
+        replace_all: bool,
+        version: &str,
+    ) -> Result<(), Error> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| Error::db("failed to start index transaction").with_source(e))?;
+
+        if replace_all {
+            tx.execute_batch("DELETE FROM formulas; DELETE FROM formula_search;")
+                .map_err(|e| Error::db("failed to clear formula index").with_source(e))?;
+        }
+
+        for (json, digest) in formulas {
+            let Some(name) = json.get("name").and_then(|v| v.as_str()) else {
+                continue;
+            };
+            // HEAD-only formulas have no stable version.
+            let version = json
+                .pointer("/versions/stable")
+                .and_then(|v| v.as_str())
+                .unwrap_or("HEAD");
+            let desc = json.get("desc").and_then(|v| v.as_str());
+            tx.execute(
+                "INSERT INTO formulas (name, version, desc, digest, json)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(name) DO UPDATE SET
+                     version = excluded.version,
+                     desc = excluded.desc,
+                     digest = excluded.digest,
+                     json = excluded.json",
+                params![name, version, desc, digest, json.to_string()],
+            )
+            .map_err(|e| Error::db("failed to record formula").with_source(e))?;
+            tx.execute("DELETE FROM formula_search WHERE name = ?1", params![name])
+                .and_then(|_| {
+                    tx.execute(
+                        "INSERT INTO formula_search (name, desc) VALUES (?1, ?2)",
+                        params![name, desc],
+                    )
+                })
+                .map_err(|e| Error::db("failed to index formula for search").with_source(e))?;
+        }
+
+        for name in removed {
+            tx.execute("DELETE FROM formulas WHERE name = ?1", params![name])
+                .and_then(|_| {
+                    tx.execute("DELETE FROM formula_search WHERE name = ?1", params![name])
+                })
+                .map_err(|e| Error::db("failed to remove formula").with_source(e))?;
+        }
+
+        // Closures resolved against another index version may be stale.
+        tx.execute(
+            "DELETE FROM graph_cache WHERE index_version != ?1",
+            params![version],
+        )
+        .map_err(|e| Error::db("failed to invalidate dependency cache").with_source(e))?;
+
+        for (key, value) in [
+            ("updated_at", unix_now().to_string()),
+            ("version", version.into()),
+        ] {
+            tx.execute(
+                "INSERT OR REPLACE INTO index_meta (key, value) VALUES (?1, ?2)",
+                params![key, value],
+            )
+            .map_err(|e| Error::db("failed to record index metadata").with_source(e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| Error::db("failed to commit formula index").with_source(e))
+    }
+}
+
+/// Per-formula digests from the upstream manifest.
+///
+/// The manifest maps each formula to a compact summary (version, revision,
+/// bottle checksum, ...). Its exact shape doesn't matter: any change to an
+/// entry changes its digest, which is all the delta logic needs.
+pub(crate) fn parse_manifest(body: &str) -> Result<BTreeMap<String, String>, Error> {
+    let invalid = |e: &dyn std::fmt::Display| Error::NetworkFailure {
+        message: format!("failed to parse formula manifest: {e}"),
+    };
+
+    let mut value: serde_json::Value = serde_json::from_str(body).map_err(|e| invalid(&e))?;
+    // Signed (JWS) manifests carry the real document as a JSON string.
+    if let Some(payload) = value.get("payload").and_then(|p| p.as_str()) {
+        value = serde_json::from_str(payload).map_err(|e| invalid(&e))?;
+    }
+
+    let formulae = value
+        .get("formulae")
+        .and_then(|f| f.as_object())
+        .ok_or_else(|| invalid(&"missing 'formulae' object"))?;
+
+    Ok(formulae
+        .iter()
+        .map(|(name, entry)| (name.clone(), digest(entry.to_string().as_bytes())))
+        .collect())
+}
+
+pub(crate) fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Edit distance counting adjacent transpositions as one edit
+/// (optimal string alignment).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > 2 {
+        return usize::MAX;
+    }
+
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+fn digest(bytes: &[u8]) -> String {
+    let hash = Sha256::digest(bytes);
+    hash.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Bring `index` up to date, downloading only changed formulas when a
+/// manifest is available for `tag` and the index already has content.
+///
+/// Install counts for search ranking are refreshed along the way when they
+/// are more than a week old; failing to fetch them doesn't fail the update.
+pub async fn update_index(
+    api: &ApiClient,
+    index: &mut FormulaIndex,
+    tag: Option<&str>,
+) -> Result<IndexUpdate, Error> {
+    refresh(api, index, tag, DELTA_CONCURRENCY, true).await
+}
+
+/// Catch `index` up while an install downloads alongside: changed formulas
+/// are fetched a couple at a time, and an update that needs the whole index
+/// is left to `zb update`. Stale install counts are refreshed too.
+pub(crate) async fn refresh_in_background(
+    api: &ApiClient,
+    index: &mut FormulaIndex,
+    tag: &str,
+) -> Result<IndexUpdate, Error> {
+    refresh(api, index, Some(tag), BACKGROUND_CONCURRENCY, false).await
+}
+
+async fn refresh(
+    api: &ApiClient,
+    index: &mut FormulaIndex,
+    tag: Option<&str>,
+    concurrency: usize,
+    allow_full: bool,
+) -> Result<IndexUpdate, Error> {
+    let mut update = update_formulas(api, index, tag, concurrency, allow_full).await?;
+
+    let stale = index
+        .popularity_updated_at()
+        .is_none_or(|at| unix_now() - at > POPULARITY_MAX_AGE_SECS);
+    if stale && let Ok(Some((counts, bytes))) = api.fetch_install_counts().await {
+        update.bytes_downloaded += bytes;
+        index.record_popularity(&counts)?;
+    }
+
+    Ok(update)
+}
+
+async fn update_formulas(
+    api: &ApiClient,
+    index: &mut FormulaIndex,
+    tag: Option<&str>,
+    concurrency: usize,
+    allow_full: bool,
+) -> Result<IndexUpdate, Error> {
+    let manifest = match tag {
+        Some(tag) => api.fetch_index_manifest(tag).await?,
+        None => None,
+    };
+
+    let mut update = IndexUpdate::default();
+    let stored = index.digests()?;
+
+    let Some((manifest_body, manifest)) = manifest else {
+        if !allow_full {
+            update.total = index.len();
+            return Ok(update);
+        }
+        return full_refresh(api, index, &stored, None, update).await;
+    };
+    update.bytes_downloaded += manifest_body.len() as u64;
+    let version = digest(manifest_body.as_bytes());
+
+    if index.version().as_deref() == Some(version.as_str()) {
+        // Nothing changed, but the index is now known to be current.
+        index.apply(&[], &[], false, &version)?;
+        update.total = index.len();
+        return Ok(update);
+    }
+
+    let outdated: Vec<String> = manifest
+        .iter()
+        .filter(|(name, digest)| stored.get(*name).and_then(|d| d.as_ref()) != Some(digest))
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    if stored.is_empty() || outdated.len() > FULL_REFRESH_THRESHOLD {
+        if !allow_full {
+            update.total = index.len();
+            return Ok(update);
+        }
+        return full_refresh(api, index, &stored, Some((&manifest, version)), update).await;
+    }
+
+    // Owned names keep the future `Send` for background refreshes.
+    let results: Vec<_> = stream::iter(outdated.into_iter().map(|name| async move {
+        let result = api.fetch_formula_json(&name).await;
+        (name, result)
+    }))
+    .buffer_unordered(concurrency)
+    .collect()
+    .await;
+
+    let mut formulas = Vec::with_capacity(results.len());
+    for (name, result) in results {
+        let (json, bytes) = result?;
+        update.bytes_downloaded += bytes;
+        let digest = manifest.get(&name).cloned();
+        if stored.contains_key(&name) {
+            update.changed.push(name);
+        } else {
+            update.added.push(name);
+        }
+        formulas.push((json, digest));
+    }
+    update.removed = stored
+        .keys()
+        .filter(|name| !manifest.contains_key(*name))
+        .cloned()
+        .collect();
+
+    index.apply(&formulas, &update.removed, false, &version)?;
+    update.added.sort();
+    update.changed.sort();
+    update.total = index.len();
+    Ok(update)
+}
+
+/// Replace `index` with the API as it was at `pin` (a `YYYY-MM-DD` date or
+/// a commit SHA of the published index) and pin it there.
+pub async fn pin_index(
+    api: &ApiClient,
+    index: &mut FormulaIndex,
+    pin: &str,
+) -> Result<(IndexPin, IndexUpdate), Error> {
+    let sha = api.resolve_index_snapshot(pin).await?;
+    let body = api.fetch_index_snapshot(&sha).await?;
+    let stored = index.digests()?;
+    let version = format!("snapshot:{sha}");
+    let update = apply_full_index(
+        index,
+        &stored,
+        body,
+        None,
+        Some(version),
+        IndexUpdate::default(),
+    )?;
+    let pin = IndexPin {
+        requested: pin.to_string(),
+        sha,
+    };
+    index.set_pin(Some(&pin))?;
+    Ok((pin, update))
+}
+
+async fn full_refresh(
+    api: &ApiClient,
+    index: &mut FormulaIndex,
+    stored: &BTreeMap<String, Option<String>>,
+    manifest: Option<(&BTreeMap<String, String>, String)>,
+    update: IndexUpdate,
+) -> Result<IndexUpdate, Error> {
+    let body = api.fetch_full_index().await?;
+    let (digests, version) = match manifest {
+        Some((digests, version)) => (Some(digests), Some(version)),
+        None => (None, None),
+    };
+    apply_full_index(index, stored, body, digests, version, update)
+}
+
+/// Replace the whole index with the formula array in `body`. `version`
+/// defaults to the digest of `body`.
+fn apply_full_index(
+    index: &mut FormulaIndex,
+    stored: &BTreeMap<String, Option<String>>,
+    body: String,
+    digests: Option<&BTreeMap<String, String>>,
+    version: Option<String>,
+    mut update: IndexUpdate,
+) -> Result<IndexUpdate, Error> {
+    update.full = true;
+    update.bytes_downloaded += body.len() as u64;
+
+    let formulas: Vec<serde_json::Value> =
+        serde_json::from_str(&body).map_err(|e| Error::NetworkFailure {
+            message: format!("failed to parse formula index: {e}"),
+        })?;
+    let version = version.unwrap_or_else(|| digest(body.as_bytes()));
+    drop(body);
+
+    let mut seen = BTreeSet::new();
+    let formulas: Vec<(serde_json::Value, Option<String>)> = formulas
+        .into_iter()
+        .filter_map(|json| {
+            let name = json.get("name")?.as_str()?.to_string();
+            let digest = digests.and_then(|digests| digests.get(&name).cloned());
+            if !stored.contains_key(&name) {
+                update.added.push(name.clone());
+            } else if digest.is_none() || stored.get(&name) != Some(&digest) {
+                update.changed.push(name.clone());
+            }
+            seen.insert(name);
+            Some((json, digest))
+        })
+        .collect();
+    update.removed = stored
+        .keys()
+        .filter(|name| !seen.contains(*name))
+        .cloned()
+        .collect();
+
+    index.apply(&formulas, &[], true, &version)?;
+    update.added.sort();
+    update.changed.sort();
+    update.total = index.len();
+    Ok(update)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn formula(name: &str, version: &str) -> serde_json::Value {
+        serde_json::json!({
+            "name": name,
+            "desc": format!("{name} tool"),
+            "versions": { "stable": version },
+            "dependencies": [],
+        })
+    }
+
+    fn manifest(entries: &[(&str, &str)]) -> String {
+        let formulae: serde_json::Map<String, serde_json::Value> = entries
+            .iter()
+            .map(|(name, version)| (name.to_string(), serde_json::json!([version, 0, 0])))
+            .collect();
+        let payload = serde_json::json!({ "formulae": formulae }).to_string();
+        serde_json::json!({ "payload": payload }).to_string()
+    }
+
+    #[test]
+    fn manifest_digests_change_with_entries() {
+        let a = parse_manifest(&manifest(&[("jq", "1.7"), ("wget", "1.24")])).unwrap();
+        let b = parse_manifest(&manifest(&[("jq", "1.8"), ("wget", "1.24")])).unwrap();
+        assert_ne!(a["jq"], b["jq"]);
+        assert_eq!(a["wget"], b["wget"]);
+
+        assert!(parse_manifest("{}").is_err());
+    }
+
+    #[test]
+    fn search_ranks_names_then_text_then_typos_by_popularity() {
+        let mut index = FormulaIndex::in_memory().unwrap();
+        let with_desc = |name: &str, desc: &str| {
+            let mut json = formula(name, "1.0");
+            json["desc"] = desc.into();
+            (json, None)
+        };
+        let formulas = vec![
+            with_desc("ripgrep", "Search tool like grep and The Silver Searcher"),
+            with_desc(
+                "ripgrep-all",
+                "Wrapper around ripgrep for PDFs and archives",
+            ),
+            with_desc("grep", "GNU grep, egrep and fgrep"),
+            with_desc("the_silver_searcher", "Code-search similar to ack"),
+            with_desc(
+                "python@3.12",
+                "Interpreted, interactive, object-oriented language",
+            ),
+        ];
+        index.apply(&formulas, &[], true, "v1").unwrap();
+        index
+            .record_popularity(&BTreeMap::from([
+                ("ripgrep".to_string(), 90_000),
+                ("the_silver_searcher".to_string(), 10_000),
+            ]))
+            .unwrap();
+
+        let names = |index: &FormulaIndex, query: &str| -> Vec<String> {
+            index
+                .search(query, 10)
+                .unwrap()
+                .into_iter()
+                .map(|hit| hit.name)
+                .collect()
+        };
+
+        assert_eq!(names(&index, "ripgrep"), ["ripgrep", "ripgrep-all"]);
+        assert_eq!(names(&index, "python"), ["python@3.12"]);
+        // Description matches come after name matches.
+        assert_eq!(
+            names(&index, "grep")[..3],
+            ["grep", "ripgrep", "ripgrep-all"]
+        );
+        assert_eq!(names(&index, "silver")[0], "the_silver_searcher");
+
+        let typo = index.search("ripgerp", 10).unwrap();
+        assert_eq!(typo[0].name, "ripgrep");
+        assert!(typo[0].fuzzy);
+        assert_eq!(typo[0].installs, 90_000);
+
+        assert!(index.search("zzzzzz", 10).unwrap().is_empty());
+        assert!(index.search("  ", 10).is_err());
+
+        // Removed formulas leave the search index too.
+        index
+            .apply(&[], &["ripgrep".to_string()], false, "v2")
+            .unwrap();
+        assert_eq!(names(&index, "ripgrep"), ["ripgrep-all"]);
+    }
+
+    #[test]
+    fn completion_matches_prefix_by_popularity() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("index.sqlite3");
+        let mut index = FormulaIndex::open(&path).unwrap();
+        let formulas: Vec<_> = ["git", "git-lfs", "gitleaks", "gh", "jq"]
+            .into_iter()
+            .map(|name| (formula(name, "1.0"), None))
+            .collect();
+        index.apply(&formulas, &[], true, "v1").unwrap();
+        index
+            .record_popularity(&BTreeMap::from([
+                ("git".to_string(), 500_000),
+                ("gitleaks".to_string(), 20_000),
+            ]))
+            .unwrap();
+        drop(index);
+
+        let index = FormulaIndex::open_read_only(&path).unwrap();
+        assert_eq!(
+            index.complete("git", 10).unwrap(),
+            ["git", "gitleaks", "git-lfs"]
+        );
+        assert_eq!(index.complete("git", 2).unwrap(), ["git", "gitleaks"]);
+        assert_eq!(index.complete("", 1).unwrap(), ["git"]);
+        assert!(index.complete("zz", 10).unwrap().is_empty());
+        assert!(FormulaIndex::open_read_only(&path.with_file_name("missing")).is_err());
+    }
+
+    #[tokio::test]
+    async fn second_update_fetches_only_changed_formulas() {
+        let server = MockServer::start().await;
+        let api = ApiClient::with_base_url(format!("{}/api/formula", server.uri()));
+        let tag = "x86_64_linux";
+        let manifest_path = format!("/api/internal/formula.{tag}.jws.json");
+
+        Mock::given(method("GET"))
+            .and(path(manifest_path.clone()))
+            .respond_with(ResponseTemplate::new(200).set_body_string(manifest(&[
+                ("jq", "1.7"),
+                ("wget", "1.24"),
+                ("old", "1"),
+            ])))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![
+                formula("jq", "1.7"),
+                formula("wget", "1.24"),
+                formula("old", "1"),
+            ]))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/analytics/install/90d.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "items": [{ "number": 1, "formula": "wget", "count": "1,234" }]
+            })))
+            // Counts are fresh after the first update.
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut index = FormulaIndex::in_memory().unwrap();
+        let first = update_index(&api, &mut index, Some(tag)).await.unwrap();
+        assert!(first.full);
+        assert_eq!(first.added, ["jq", "old", "wget"]);
+        assert_eq!(index.get("jq").unwrap().version, "1.7");
+        assert_eq!(index.search("wget", 1).unwrap()[0].installs, 1234);
+
+        Mock::given(method("GET"))
+            .and(path(manifest_path))
+            .respond_with(ResponseTemplate::new(200).set_body_string(manifest(&[
+                ("jq", "1.8"),
+                ("wget", "1.24"),
+                ("new", "2"),
+            ])))
+            .mount(&server)
+            .await;
+        for (name, version) in [("jq", "1.8"), ("new", "2")] {
+            Mock::given(method("GET"))
+                .and(path(format!("/api/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(formula(name, version)))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let second = update_index(&api, &mut index, Some(tag)).await.unwrap();
+        assert!(!second.full);
+        assert_eq!(second.changed, ["jq"]);
+        assert_eq!(second.added, ["new"]);
+        assert_eq!(second.removed, ["old"]);
+        assert_eq!(second.total, 3);
+        assert_eq!(index.get("jq").unwrap().version, "1.8");
+        assert!(index.get("old").is_none());
+
+        let third = update_index(&api, &mut index, Some(tag)).await.unwrap();
+        assert!(third.is_empty());
+    }
+
+    #[tokio::test]
+    async fn background_refresh_only_applies_deltas() {
+        let server = MockServer::start().await;
+        let api = ApiClient::with_base_url(format!("{}/api/formula", server.uri()));
+        let tag = "x86_64_linux";
+
+        Mock::given(method("GET"))
+            .and(path(format!("/api/internal/formula.{tag}.jws.json")))
+            .respond_with(ResponseTemplate::new(200).set_body_string(manifest(&[("jq", "1.8")])))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![formula("jq", "1.8")]))
+            .expect(0)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/formula/jq.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(formula("jq", "1.8")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // An empty index needs a full download, which is left to `zb update`.
+        let mut index = FormulaIndex::in_memory().unwrap();
+        let update = refresh_in_background(&api, &mut index, tag).await.unwrap();
+        assert!(update.is_empty());
+        assert!(index.is_empty());
+
+        index
+            .apply(&[(formula("jq", "1.7"), None)], &[], true, "v1")
+            .unwrap();
+        let update = refresh_in_background(&api, &mut index, tag).await.unwrap();
+        assert_eq!(update.changed, ["jq"]);
+        assert_eq!(index.get("jq").unwrap().version, "1.8");
+    }
+
+    #[tokio::test]
+    async fn pinning_loads_the_snapshot_published_by_a_date() {
+        let server = MockServer::start().await;
+        let api = ApiClient::with_base_url(format!("{}/api/formula", server.uri()))
+            .with_github_api_base_url(server.uri())
+            .with_tap_raw_base_url(server.uri());
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+
+        Mock::given(method("GET"))
+            .and(path("/repos/Homebrew/formulae.brew.sh/commits"))
+            .and(query_param("until", "2025-01-15T23:59:59Z"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "sha": sha }
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/Homebrew/formulae.brew.sh/{sha}/api/formula.json"
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![formula("jq", "1.7")]))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut index = FormulaIndex::in_memory().unwrap();
+        index
+            .apply(&[(formula("wget", "1.25"), None)], &[], true, "v1")
+            .unwrap();
+        let (pin, update) = pin_index(&api, &mut index, "2025-01-15").await.unwrap();
+        assert_eq!(pin.sha, sha);
+        assert_eq!(update.added, ["jq"]);
+        assert_eq!(update.removed, ["wget"]);
+        assert_eq!(index.pin(), Some(pin));
+        assert_eq!(index.get("jq").unwrap().version, "1.7");
+        assert_eq!(index.version().unwrap(), format!("snapshot:{sha}"));
+
+        index.set_pin(None).unwrap();
+        assert!(index.pin().is_none());
+        assert!(pin_index(&api, &mut index, "last-tuesday").await.is_err());
+    }
+}
@@ -1,10 +1,20 @@
+pub mod advisory;
 pub mod api;
 pub mod cache;
 pub mod download;
+pub mod index;
+pub mod peer;
+pub(crate) mod proxy;
+pub(crate) mod retry;
 pub mod tap_formula;
 
+pub use advisory::{Advisory, AdvisoryClient};
 pub use api::ApiClient;
 pub use cache::{ApiCache, CacheEntry};
 pub use download::{
     DownloadProgressCallback, DownloadRequest, DownloadResult, Downloader, ParallelDownloader,
 };
+pub use index::{
+    FormulaIndex, IndexPin, IndexUpdate, IndexedFormula, SearchHit, pin_index, update_index,
+};
+pub use peer::{DEFAULT_PEER_PORT, PeerPull};
@@ -0,0 +1,330 @@
+//! Sharing the download cache with other machines on the network.
+//!
+//! `zb cache serve` answers two read-only requests over plain HTTP:
+//! `GET /blobs` lists `<sha256> <size>` lines, and `GET /blobs/<sha256>`
+//! returns one blob. `zb cache pull` fetches the blobs it doesn't have and
+//! only admits those whose content hashes to the advertised checksum, so a
+//! peer can't hand out anything the cache wouldn't have downloaded itself.
+
+use std::io::Write;
+use std::time::Duration;
+
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use zb_core::Error;
+
+use crate::storage::BlobCache;
+
+/// Port `zb cache serve` listens on and `zb cache pull` connects to by default.
+pub const DEFAULT_PEER_PORT: u16 = 7480;
+
+/// Blobs fetched from a peer at once.
+const PULL_CONCURRENCY: usize = 4;
+
+/// Longest request head the server reads before giving up on a client.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// What [`pull_blobs`] did with the blobs a peer offered.
+#[derive(Debug, Default)]
+pub struct PeerPull {
+    /// Blobs fetched and admitted, with their sizes.
+    pub fetched: Vec<(String, u64)>,
+    /// Blobs the local cache already had.
+    pub already_cached: usize,
+    /// Blobs that failed to download or didn't match their checksum.
+    pub rejected: Vec<(String, String)>,
+}
+
+impl PeerPull {
+    pub fn bytes(&self) -> u64 {
+        self.fetched.iter().map(|(_, len)| len).sum()
+    }
+}
+
+/// Base URL for `peer`, given as `host`, `host:port` or a full `http://` URL.
+pub fn peer_url(peer: &str) -> String {
+    let peer = peer.trim_end_matches('/');
+    if peer.starts_with("http://") || peer.starts_with("https://") {
+        return peer.to_string();
+    }
+    // A bare IPv6 address has colons but no port.
+    let has_port = match peer.rsplit_once(':') {
+        Some((host, port)) => {
+            (!host.contains(':') || host.ends_with(']')) && port.parse::<u16>().is_ok()
+        }
+        None => false,
+    };
+    if has_port {
+        format!("http://{peer}")
+    } else if peer.contains(':') && !peer.starts_with('[') {
+        format!("http://[{peer}]:{DEFAULT_PEER_PORT}")
+    } else {
+        format!("http://{peer}:{DEFAULT_PEER_PORT}")
+    }
+}
+
+/// Serve `cache` read-only on `listener` until the process is stopped.
+pub async fn serve_blobs(cache: BlobCache, listener: TcpListener) -> Result<(), Error> {
+    loop {
+        let (stream, _) = listener.accept().await.map_err(|e| Error::NetworkFailure {
+            message: format!("failed to accept a connection: {e}"),
+        })?;
+        let cache = cache.clone();
+        tokio::spawn(async move {
+            // A client hanging up mid-transfer is its problem, not the server's.
+            let _ = handle_connection(&cache, stream).await;
+        });
+    }
+}
+
+async fn handle_connection(cache: &BlobCache, mut stream: TcpStream) -> std::io::Result<()> {
+    let Some(head) = read_request_head(&mut stream).await? else {
+        return respond(&mut stream, "400 Bad Request", b"bad request\n").await;
+    };
+    let mut parts = head.lines().next().unwrap_or("").split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    if method != "GET" && method != "HEAD" {
+        return respond(&mut stream, "405 Method Not Allowed", b"read-only\n").await;
+    }
+    let send_body = method == "GET";
+
+    if path == "/blobs" {
+        let listing: String = cache
+            .blobs()?
+            .into_iter()
+            .map(|(sha256, len)| format!("{sha256} {len}\n"))
+            .collect();
+        let body = if send_body { listing.as_bytes() } else { b"" };
+        return respond_with_length(&mut stream, listing.len() as u64, body).await;
+    }
+
+    let blob = path
+        .strip_prefix("/blobs/")
+        .filter(|sha256| is_sha256(sha256))
+        .and_then(|sha256| cache.cached_blob(sha256));
+    let Some(blob) = blob else {
+        return respond(&mut stream, "404 Not Found", b"not found\n").await;
+    };
+    let mut file = tokio::fs::File::open(&blob).await?;
+    let len = file.metadata().await?.len();
+    respond_with_length(&mut stream, len, b"").await?;
+    if send_body {
+        tokio::io::copy(&mut file, &mut stream).await?;
+    }
+    stream.shutdown().await
+}
+
+/// The request line and headers, or `None` when the client sent too much or
+/// hung up first.
+async fn read_request_head(stream: &mut TcpStream) -> std::io::Result<Option<String>> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || head.len() + read > MAX_REQUEST_HEAD {
+            return Ok(None);
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+    Ok(Some(String::from_utf8_lossy(&head).into_owned()))
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &[u8]) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await
+}
+
+/// A `200 OK` head for `len` bytes, followed by `body` (empty when the caller
+/// streams the body itself).
+async fn respond_with_length(stream: &mut TcpStream, len: u64, body: &[u8]) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {len}\r\nConnection: close\r\n\r\n"
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await
+}
+
+fn is_sha256(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Fetch the blobs `peer` serves that `cache` doesn't have yet.
+pub async fn pull_blobs(cache: &BlobCache, peer: &str) -> Result<PeerPull, Error> {
+    let base = peer_url(peer);
+    let client = reqwest::Client::builder()
+        .user_agent("zerobrew/0.1")
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| Error::NetworkFailure {
+            message: format!("failed to build HTTP client: {e}"),
+        })?;
+
+    let listing = client
+        .get(format!("{base}/blobs"))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| Error::NetworkFailure {
+            message: format!("failed to list the cache at {base}: {e}"),
+        })?
+        .text()
+        .await
+        .map_err(|e| Error::NetworkFailure {
+            message: format!("failed to list the cache at {base}: {e}"),
+        })?;
+
+    let mut pull = PeerPull::default();
+    let mut wanted = Vec::new();
+    for line in listing.lines() {
+        let Some(sha256) = line.split_whitespace().next().filter(|s| is_sha256(s)) else {
+            continue;
+        };
+        let sha256 = sha256.to_ascii_lowercase();
+        if cache.has_blob(&sha256) {
+            pull.already_cached += 1;
+        } else {
+            wanted.push(sha256);
+        }
+    }
+
+    let mut results = futures::stream::iter(wanted)
+        .map(|sha256| {
+            let url = format!("{base}/blobs/{sha256}");
+            let client = &client;
+            async move {
+                let result = pull_blob(client, cache, &url, &sha256).await;
+                (sha256, result)
+            }
+        })
+        .buffer_unordered(PULL_CONCURRENCY);
+    while let Some((sha256, result)) = results.next().await {
+        match result {
+            Ok(len) => pull.fetched.push((sha256, len)),
+            Err(e) => pull.rejected.push((sha256, e.to_string())),
+        }
+    }
+    pull.fetched.sort();
+    pull.rejected.sort();
+    Ok(pull)
+}
+
+/// Download one blob into the cache, committing it only when its content
+/// hashes to `sha256`.
+async fn pull_blob(
+    client: &reqwest::Client,
+    cache: &BlobCache,
+    url: &str,
+    sha256: &str,
+) -> Result<u64, Error> {
+    let network = |e: reqwest::Error| Error::NetworkFailure {
+        message: e.to_string(),
+    };
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(network)?;
+
+    let mut writer = cache.start_write(sha256).map_err(|e| Error::FileError {
+        message: format!("failed to create blob writer: {e}"),
+    })?;
+    let mut hasher = Sha256::new();
+    let mut len = 0u64;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(network)?;
+        len += chunk.len() as u64;
+        hasher.update(&chunk);
+        writer.write_all(&chunk).map_err(|e| Error::FileError {
+            message: format!("failed to write blob: {e}"),
+        })?;
+    }
+
+    let actual = format!("{:x}", hasher.finalize());
+    if actual != sha256 {
+        return Err(Error::ChecksumMismatch {
+            expected: sha256.to_string(),
+            actual,
+        });
+    }
+    writer.commit()?;
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn cache_with(dir: &TempDir, blobs: &[&[u8]]) -> (BlobCache, Vec<String>) {
+        let cache = BlobCache::new(dir.path()).unwrap();
+        let mut shas = Vec::new();
+        for blob in blobs {
+            let sha256 = format!("{:x}", Sha256::digest(blob));
+            let mut writer = cache.start_write(&sha256).unwrap();
+            writer.write_all(blob).unwrap();
+            writer.commit().unwrap();
+            shas.push(sha256);
+        }
+        (cache, shas)
+    }
+
+    #[test]
+    fn peer_urls_default_the_scheme_and_port() {
+        assert_eq!(peer_url("build-mac"), "http://build-mac:7480");
+        assert_eq!(peer_url("10.0.0.5:9000"), "http://10.0.0.5:9000");
+        assert_eq!(peer_url("http://10.0.0.5/"), "http://10.0.0.5");
+        assert_eq!(peer_url("fe80::1"), "http://[fe80::1]:7480");
+        assert_eq!(peer_url("[fe80::1]:9000"), "http://[fe80::1]:9000");
+    }
+
+    #[tokio::test]
+    async fn pull_fetches_missing_blobs_from_a_peer() {
+        let serving = TempDir::new().unwrap();
+        let (served, shas) = cache_with(&serving, &[b"bottle one", b"bottle two"]);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer = listener.local_addr().unwrap().to_string();
+        tokio::spawn(serve_blobs(served, listener));
+
+        let pulling = TempDir::new().unwrap();
+        let (cache, _) = cache_with(&pulling, &[b"bottle one"]);
+        let pull = pull_blobs(&cache, &peer).await.unwrap();
+        assert_eq!(pull.already_cached, 1);
+        assert_eq!(pull.fetched, [(shas[1].clone(), 10)]);
+        assert!(pull.rejected.is_empty());
+        assert_eq!(
+            std::fs::read(cache.blob_path(&shas[1])).unwrap(),
+            b"bottle two"
+        );
+
+        let pull = pull_blobs(&cache, &peer).await.unwrap();
+        assert_eq!(pull.already_cached, 2);
+        assert!(pull.fetched.is_empty());
+    }
+
+    #[tokio::test]
+    async fn pull_rejects_blobs_that_do_not_match_their_checksum() {
+        let serving = TempDir::new().unwrap();
+        let (served, shas) = cache_with(&serving, &[b"genuine bottle"]);
+        std::fs::write(served.blob_path(&shas[0]), b"tampered bottle").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer = listener.local_addr().unwrap().to_string();
+        tokio::spawn(serve_blobs(served, listener));
+
+        let pulling = TempDir::new().unwrap();
+        let cache = BlobCache::new(pulling.path()).unwrap();
+        let pull = pull_blobs(&cache, &peer).await.unwrap();
+        assert!(pull.fetched.is_empty());
+        assert_eq!(pull.rejected.len(), 1);
+        assert!(pull.rejected[0].1.contains("checksum mismatch"));
+        assert!(!cache.has_blob(&shas[0]));
+    }
+}
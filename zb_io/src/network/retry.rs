@@ -0,0 +1,135 @@
+use crate::config::RetryConfig;
+use zb_core::Error;
+
+/// Send `request`, trying again after a backoff while [`is_transient`]
+/// holds for its failure. The last attempt's outcome is returned as is.
+pub(crate) async fn send(
+    policy: &RetryConfig,
+    request: reqwest::RequestBuilder,
+) -> reqwest::Result<reqwest::Response> {
+    let mut retry = 1;
+    loop {
+        // Requests with streaming bodies can't be cloned, so they get one try.
+        let Some(attempt) = request.try_clone().filter(|_| retry < policy.attempts) else {
+            return request.send().await;
+        };
+        match attempt.send().await {
+            Ok(response) if !is_transient(policy, &status_error(response.status())) => {
+                return Ok(response);
+            }
+            Err(e) if !is_transient(policy, &transport_error("request failed", &e)) => {
+                return Err(e);
+            }
+            _ => {}
+        }
+        tokio::time::sleep(policy.delay(retry)).await;
+        retry += 1;
+    }
+}
+
+/// Whether a failure is worth another try: the server answered with one of
+/// the policy's retryable statuses, or the connection failed or dropped.
+pub(crate) fn is_transient(policy: &RetryConfig, error: &Error) -> bool {
+    match error.status() {
+        Some(status) => policy.retries_status(status),
+        None => error.is_interrupted(),
+    }
+}
+
+/// A response that wasn't a success, carrying its status.
+pub(crate) fn status_error(status: reqwest::StatusCode) -> Error {
+    Error::download(format!("HTTP {status}")).with_status(status.as_u16())
+}
+
+/// A request that got no complete response. Anything but a malformed
+/// request or a redirect loop counts as interrupted.
+pub(crate) fn transport_error(message: &str, e: &reqwest::Error) -> Error {
+    let error = Error::download(message).with_source(e);
+    match e.status() {
+        Some(status) => error.with_status(status.as_u16()),
+        None if e.is_builder() || e.is_redirect() => error,
+        None => error.interrupted(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn policy(attempts: u32) -> RetryConfig {
+        RetryConfig {
+            attempts,
+            backoff_ms: 1,
+            jitter: false,
+            ..RetryConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn retryable_statuses_are_retried_until_attempts_run_out() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/down"))
+            .respond_with(ResponseTemplate::new(502))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/missing"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let get = |p: &str| client.get(format!("{}{p}", mock_server.uri()));
+
+        let response = send(&policy(3), get("/flaky")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let response = send(&policy(2), get("/down")).await.unwrap();
+        assert_eq!(response.status(), 502);
+        let response = send(&policy(3), get("/missing")).await.unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    #[test]
+    fn transient_failures_follow_the_policy_not_the_message() {
+        let policy = policy(3);
+        let unavailable = status_error(reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert!(is_transient(&policy, &unavailable));
+        assert!(!is_transient(
+            &policy,
+            &status_error(reqwest::StatusCode::NOT_FOUND)
+        ));
+
+        // The wording doesn't matter, only the recorded status or interruption.
+        assert!(!is_transient(
+            &policy,
+            &Error::download("HTTP 503 error sending request")
+        ));
+        assert!(is_transient(
+            &policy,
+            &Error::download("connection reset").interrupted()
+        ));
+        assert!(!is_transient(
+            &policy,
+            &Error::NetworkFailure {
+                message: "HTTP 503".to_string()
+            }
+        ));
+    }
+}
@@ -25,12 +25,26 @@ static URL_VERSION_RE: LazyLock<Regex> = LazyLock::new(|| {
 static REVISION_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"(?m)^\s*revision\s+(\d+)\s*$"#).expect("REVISION_RE must compile")
 });
+static DESC_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?m)^\s*desc\s+["']([^"']+)["']"#).expect("DESC_RE must compile")
+});
+static HOMEPAGE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?m)^\s*homepage\s+["']([^"']+)["']"#).expect("HOMEPAGE_RE must compile")
+});
 static DEPENDS_ON_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"(?m)^\s*depends_on\s+["']([^"']+)["'](.*)$"#).expect("DEPENDS_ON_RE must compile")
 });
 static SOURCE_URL_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"(?m)^\s*url\s+["']([^"']+)["']"#).expect("SOURCE_URL_RE must compile")
 });
+static HEAD_URL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^\s*(?:head|url)\s+["']([^"']+)["']"#).expect("HEAD_URL_RE must compile")
+});
+static HEAD_START_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^\s*head\s+do\b"#).expect("HEAD_START_RE must compile"));
+static BRANCH_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\bbranch:\s*["']([^"']+)["']"#).expect("BRANCH_RE must compile")
+});
 static SOURCE_SHA_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"(?m)^\s*sha256\s+["']([0-9a-f]{64})["']\s*$"#)
         .expect("SOURCE_SHA_RE must compile")
@@ -70,6 +84,33 @@ static ELSIF_HW_CPU_RE: LazyLock<Regex> = LazyLock::new(|| {
 static ELSE_LINE_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r#"^\s*else\s*(?:#.*)?$"#).expect("ELSE_LINE_RE must compile"));
 
+impl TapFormulaRef {
+    /// Repository names to try, since `owner/foo` usually lives in `homebrew-foo`.
+    pub fn candidate_repos(&self) -> Vec<String> {
+        if self.repo.starts_with("homebrew-") {
+            vec![
+                self.repo.clone(),
+                self.repo.trim_start_matches("homebrew-").to_string(),
+            ]
+        } else {
+            vec![format!("homebrew-{}", self.repo), self.repo.clone()]
+        }
+    }
+
+    /// Locations of the formula file within a tap, in lookup order.
+    pub fn candidate_paths(&self) -> Vec<String> {
+        let formula = &self.formula;
+        let first_char = formula.chars().next().unwrap_or('x');
+        vec![
+            format!("Formula/{formula}.rb"),
+            format!("Formula/{first_char}/{formula}.rb"),
+            format!("HomebrewFormula/{formula}.rb"),
+            format!("HomebrewFormula/{first_char}/{formula}.rb"),
+            format!("{formula}.rb"),
+        ]
+    }
+}
+
 pub fn parse_tap_formula_ref(input: &str) -> Option<TapFormulaRef> {
     let mut parts = input.split('/');
     let owner = parts.next()?;
@@ -339,12 +380,20 @@ fn resolve_version_interpolation(source: &str) -> String {
 
 pub fn parse_tap_formula_ruby(spec: &TapFormulaRef, source: &str) -> Result<Formula, Error> {
     let source = preprocess_tap_source(source);
-    let stable = parse_version(&source).unwrap_or_else(|| "0".to_string());
     let revision = parse_revision(&source).unwrap_or(0);
     let dependencies = parse_runtime_dependencies(&source);
     let build_dependencies = parse_build_dependencies(&source);
     let parsed_source_url = parse_source_url(&source);
-    let bottle = parse_bottle(spec, &source, &stable, revision);
+    let head = parse_head_url(&source);
+    // Without a stable `url`, a formula with a `head` spec is HEAD-only.
+    let stable = match (parse_version(&source), &parsed_source_url, &head) {
+        (Some(version), _, _) => Some(version),
+        (None, ParsedSourceUrl::NotPresent, Some(_)) => None,
+        (None, _, _) => Some("0".to_string()),
+    };
+    let bottle = stable
+        .as_deref()
+        .and_then(|stable| parse_bottle(spec, &source, stable, revision));
 
     let source_url = match parsed_source_url {
         ParsedSourceUrl::PresentWithChecksum(source_url) => Some(source_url),
@@ -360,30 +409,39 @@ pub fn parse_tap_formula_ruby(spec: &TapFormulaRef, source: &str) -> Result<Form
         ParsedSourceUrl::NotPresent => None,
     };
 
-    if bottle.is_none() && source_url.is_none() {
+    if bottle.is_none() && source_url.is_none() && head.is_none() {
         return Err(Error::UnsupportedFormula {
             name: spec.formula.clone(),
             reason: "tap formula does not provide bottle data or source url".to_string(),
         });
     }
 
+    let urls = (source_url.is_some() || head.is_some()).then_some(FormulaUrls {
+        stable: source_url,
+        head,
+    });
     Ok(Formula {
         name: spec.formula.clone(),
-        versions: Versions { stable },
+        versions: Versions { stable, head: None },
         dependencies,
         bottle: bottle.unwrap_or_else(empty_bottle),
         revision,
         keg_only: KegOnly::default(),
         build_dependencies,
-        urls: source_url.map(|stable| FormulaUrls {
-            stable: Some(stable),
-            head: None,
-        }),
+        urls,
         ruby_source_path: None,
         ruby_source_checksum: None,
         uses_from_macos: Vec::new(),
         requirements: Vec::new(),
         variations: None,
+        license: None,
+        deprecated: false,
+        deprecation_reason: None,
+        desc: first_capture(&DESC_RE, &source),
+        homepage: first_capture(&HOMEPAGE_RE, &source),
+        service: None,
+        conflicts_with: Vec::new(),
+        conflicts_with_reasons: Vec::new(),
     })
 }
 
@@ -413,6 +471,12 @@ fn normalize_inferred_version(raw: &str) -> String {
     v
 }
 
+fn first_capture(re: &Regex, source: &str) -> Option<String> {
+    re.captures(source)
+        .and_then(|c| c.get(1))
+        .map(|m| m.as_str().to_string())
+}
+
 fn parse_revision(source: &str) -> Option<u32> {
     REVISION_RE
         .captures(source)
@@ -519,6 +583,32 @@ fn parse_source_url(source: &str) -> ParsedSourceUrl {
     }
 }
 
+/// The `head` spec, either `head "url", branch: "main"` or a `head do`
+/// block with a `url` line, as the API's `urls.head` object.
+fn parse_head_url(source: &str) -> Option<serde_json::Value> {
+    let body = extract_formula_class_body(source).unwrap_or(source);
+    let mut depth = 0usize;
+    let mut in_head = false;
+
+    for line in body.lines() {
+        let trimmed = line.trim();
+        if depth == 0 {
+            in_head = HEAD_START_RE.is_match(trimmed);
+        }
+        let spec = (depth == 0 && trimmed.starts_with("head") && !in_head)
+            || (depth == 1 && in_head && trimmed.starts_with("url"));
+        if spec && let Some(cap) = HEAD_URL_RE.captures(trimmed) {
+            let mut head = serde_json::json!({ "url": &cap[1] });
+            if let Some(branch) = BRANCH_RE.captures(trimmed) {
+                head["branch"] = branch[1].into();
+            }
+            return Some(head);
+        }
+        update_depth(&mut depth, trimmed);
+    }
+    None
+}
+
 fn update_depth(depth: &mut usize, trimmed: &str) {
     if END_RE.is_match(trimmed) {
         *depth = depth.saturating_sub(1);
@@ -738,7 +828,7 @@ end
 
         let formula = parse_tap_formula_ruby(&spec, source).unwrap();
         assert_eq!(formula.name, "terraform");
-        assert_eq!(formula.versions.stable, "1.10.0");
+        assert_eq!(formula.versions.stable.as_deref(), Some("1.10.0"));
         assert_eq!(formula.revision, 1);
         assert_eq!(formula.bottle.stable.rebuild, 2);
         assert_eq!(formula.dependencies, vec!["openssl@3".to_string()]);
@@ -812,7 +902,7 @@ end
             formula: "jaso".to_string(),
         };
         let formula = parse_tap_formula_ruby(&spec, source).unwrap();
-        assert_eq!(formula.versions.stable, "1.0.1");
+        assert_eq!(formula.versions.stable.as_deref(), Some("1.0.1"));
         assert_eq!(
             formula.bottle.stable.files["x86_64_linux"].url,
             "https://github.com/simnalamburt/homebrew-x/releases/download/jaso-1.0.1/jaso-1.0.1.x86_64_linux.bottle.tar.gz"
@@ -1031,6 +1121,44 @@ end
         );
     }
 
+    #[test]
+    fn parses_head_only_formulas() {
+        let spec = TapFormulaRef {
+            owner: "someone".to_string(),
+            repo: "tap".to_string(),
+            formula: "nightly".to_string(),
+        };
+
+        let source = r#"
+class Nightly < Formula
+  desc "Builds from main"
+  head "https://github.com/someone/nightly.git", branch: "main"
+
+  depends_on "go" => :build
+end
+"#;
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        assert!(formula.is_head_only());
+        assert_eq!(
+            formula.head_url(),
+            Some("https://github.com/someone/nightly.git")
+        );
+        assert_eq!(formula.head_branch(), Some("main"));
+        assert!(formula.source_url().is_none());
+
+        let source = r#"
+class Nightly < Formula
+  head do
+    url "https://github.com/someone/nightly.git", branch: "trunk"
+    depends_on "autoconf" => :build
+  end
+end
+"#;
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        assert!(formula.is_head_only());
+        assert_eq!(formula.head_branch(), Some("trunk"));
+    }
+
     #[test]
     fn returns_unsupported_formula_when_neither_bottle_nor_source_is_available() {
         let source = r#"
@@ -1074,7 +1202,7 @@ end
         };
 
         let formula = parse_tap_formula_ruby(&spec, source).unwrap();
-        assert_eq!(formula.versions.stable, "0.2.2");
+        assert_eq!(formula.versions.stable.as_deref(), Some("0.2.2"));
 
         let stable = formula
             .urls
@@ -1224,7 +1352,15 @@ end
 
         let formula = parse_tap_formula_ruby(&spec, source).unwrap();
         assert_eq!(formula.name, "sag");
-        assert_eq!(formula.versions.stable, "0.2.2");
+        assert_eq!(formula.versions.stable.as_deref(), Some("0.2.2"));
+        assert_eq!(
+            formula.desc.as_deref(),
+            Some("Command-line ElevenLabs TTS with mac-style flags")
+        );
+        assert_eq!(
+            formula.homepage.as_deref(),
+            Some("https://github.com/steipete/sag")
+        );
 
         let stable = formula
             .urls
@@ -1290,7 +1426,7 @@ end
         };
 
         let formula = parse_tap_formula_ruby(&spec, source).unwrap();
-        assert_eq!(formula.versions.stable, "29.3.0");
+        assert_eq!(formula.versions.stable.as_deref(), Some("29.3.0"));
         let stable = formula
             .urls
             .as_ref()
@@ -0,0 +1,133 @@
+use std::process::Command;
+use std::time::Duration;
+
+use zb_core::Error;
+
+use crate::config::NotifyConfig;
+use crate::watchdog::{command_timeout, output_with_timeout};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifierKind {
+    /// Notification Center, through `osascript`.
+    Osascript,
+    /// freedesktop notifications, through `notify-send`.
+    NotifySend,
+}
+
+/// Posts a desktop notification when a long-running operation finishes.
+///
+/// Operations shorter than the configured threshold stay silent, so only
+/// installs worth switching away from produce a notification.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    kind: NotifierKind,
+    min_duration: Duration,
+}
+
+impl Notifier {
+    pub fn new(kind: NotifierKind, min_duration: Duration) -> Self {
+        Self { kind, min_duration }
+    }
+
+    /// Notifier for this platform, or `None` when notifications are disabled.
+    pub fn from_config(config: &NotifyConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let kind = if cfg!(target_os = "macos") {
+            NotifierKind::Osascript
+        } else {
+            NotifierKind::NotifySend
+        };
+        Some(Self::new(kind, Duration::from_secs(config.min_seconds)))
+    }
+
+    /// Report that `operation` finished after `elapsed`. Returns whether a
+    /// notification was posted.
+    pub fn finished(
+        &self,
+        operation: &str,
+        elapsed: Duration,
+        succeeded: bool,
+    ) -> Result<bool, Error> {
+        if elapsed < self.min_duration {
+            return Ok(false);
+        }
+
+        let secs = elapsed.as_secs();
+        let took = if secs >= 60 {
+            format!("{}m {}s", secs / 60, secs % 60)
+        } else {
+            format!("{secs}s")
+        };
+        let message = if succeeded {
+            format!("{operation} finished in {took}")
+        } else {
+            format!("{operation} failed after {took}")
+        };
+
+        let output = output_with_timeout(&mut self.command(&message), command_timeout())?;
+        if !output.status.success() {
+            return Err(Error::ExecutionError {
+                message: format!(
+                    "failed to post notification: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            });
+        }
+        Ok(true)
+    }
+
+    fn command(&self, message: &str) -> Command {
+        match self.kind {
+            NotifierKind::Osascript => {
+                let mut cmd = Command::new("osascript");
+                cmd.arg("-e").arg(format!(
+                    "display notification {} with title \"zerobrew\"",
+                    applescript_string(message)
+                ));
+                cmd
+            }
+            NotifierKind::NotifySend => {
+                let mut cmd = Command::new("notify-send");
+                cmd.args(["--app-name=zerobrew", "zerobrew", message]);
+                cmd
+            }
+        }
+    }
+}
+
+fn applescript_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn osascript_message_is_escaped() {
+        let notifier = Notifier::new(NotifierKind::Osascript, Duration::ZERO);
+        let cmd = notifier.command(r#"install "jq\" finished"#);
+        let args: Vec<_> = cmd.get_args().collect();
+
+        assert_eq!(cmd.get_program(), "osascript");
+        assert_eq!(
+            args[1],
+            r#"display notification "install \"jq\\\" finished" with title "zerobrew""#
+        );
+    }
+
+    #[test]
+    fn short_operations_stay_silent() {
+        let notifier = Notifier::new(NotifierKind::NotifySend, Duration::from_secs(30));
+        assert!(
+            !notifier
+                .finished("install jq", Duration::from_secs(5), true)
+                .unwrap()
+        );
+
+        let disabled = NotifyConfig::default();
+        assert!(Notifier::from_config(&disabled).is_none());
+    }
+}
@@ -0,0 +1,205 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde_json::{Value, json};
+use zb_core::Error;
+
+use crate::watchdog::{command_timeout, output_with_input};
+
+/// Points in the install pipeline where plugins are invoked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPhase {
+    /// Before the requested formulas are resolved. A failing plugin aborts the install.
+    PrePlan,
+    /// After a bottle is downloaded and before it is unpacked. A failing plugin
+    /// skips that formula.
+    PostDownload,
+    /// After a keg is materialized and linked. Failures only produce a warning.
+    PostLink,
+}
+
+impl HookPhase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HookPhase::PrePlan => "pre-plan",
+            HookPhase::PostDownload => "post-download",
+            HookPhase::PostLink => "post-link",
+        }
+    }
+
+    /// Whether a non-zero exit from a plugin stops the operation.
+    pub fn is_blocking(self) -> bool {
+        !matches!(self, HookPhase::PostLink)
+    }
+}
+
+/// Executables in `<root>/plugins`, run in name order at each [`HookPhase`].
+///
+/// Each plugin is invoked as `<plugin> <phase>` with a JSON object on stdin
+/// that always carries `phase` and `prefix`, plus phase-specific fields:
+///
+/// - `pre-plan`: `formulas` (the names that were requested)
+/// - `post-download`: `formula`, `version`, `sha256`, `blob`
+/// - `post-link`: `formula`, `version`, `keg`, `linked` (symlinks created)
+#[derive(Debug, Clone, Default)]
+pub struct PluginHooks {
+    plugins: Vec<PathBuf>,
+}
+
+impl PluginHooks {
+    /// Collect the executables in `dir`; a missing directory means no plugins.
+    pub fn discover(dir: &Path) -> Self {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Self::default();
+        };
+
+        let mut plugins: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                fs::metadata(path)
+                    .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+                    .unwrap_or(false)
+            })
+            .collect();
+        plugins.sort();
+        Self { plugins }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    pub fn plugins(&self) -> &[PathBuf] {
+        &self.plugins
+    }
+
+    /// Run every plugin for `phase`. `payload` must be a JSON object; `phase`
+    /// and `prefix` are added to it.
+    ///
+    /// For blocking phases the first failing plugin's error is returned; for
+    /// the others failures are printed as warnings and `Ok` is returned.
+    pub fn run(&self, phase: HookPhase, prefix: &Path, mut payload: Value) -> Result<(), Error> {
+        if self.plugins.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(object) = payload.as_object_mut() {
+            object.insert("phase".into(), json!(phase.as_str()));
+            object.insert("prefix".into(), json!(prefix));
+        }
+        let input = payload.to_string();
+
+        for plugin in &self.plugins {
+            if let Err(e) = run_plugin(plugin, phase, input.as_bytes()) {
+                if phase.is_blocking() {
+                    return Err(e);
+                }
+                eprintln!("warning: {e}");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn run_plugin(plugin: &Path, phase: HookPhase, input: &[u8]) -> Result<(), Error> {
+    let name = plugin
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| plugin.display().to_string());
+
+    let output = output_with_input(
+        Command::new(plugin).arg(phase.as_str()),
+        input,
+        command_timeout(),
+    )?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = match stderr.trim() {
+        "" => output.status.to_string(),
+        reason => reason.to_string(),
+    };
+    Err(Error::ExecutionError {
+        message: format!("plugin '{name}' failed at {}: {reason}", phase.as_str()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_plugin(dir: &Path, name: &str, script: &str) {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn discover_skips_non_executables_and_sorts() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_plugin(tmp.path(), "20-notify", "exit 0");
+        write_plugin(tmp.path(), "10-policy", "exit 0");
+        fs::write(tmp.path().join("README"), "not a plugin").unwrap();
+
+        let hooks = PluginHooks::discover(tmp.path());
+        let names: Vec<_> = hooks
+            .plugins()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["10-policy", "20-notify"]);
+
+        assert!(PluginHooks::discover(&tmp.path().join("missing")).is_empty());
+    }
+
+    #[test]
+    fn plugins_receive_phase_argument_and_json_payload() {
+        let tmp = tempfile::tempdir().unwrap();
+        let plugins = tmp.path().join("plugins");
+        fs::create_dir(&plugins).unwrap();
+        let log = tmp.path().join("log");
+        write_plugin(
+            &plugins,
+            "record",
+            &format!("echo \"$1\" > {0}; cat >> {0}", log.display()),
+        );
+
+        PluginHooks::discover(&plugins)
+            .run(
+                HookPhase::PrePlan,
+                Path::new("/opt/zb"),
+                json!({ "formulas": ["jq"] }),
+            )
+            .unwrap();
+
+        let log = fs::read_to_string(log).unwrap();
+        let (phase, payload) = log.split_once('\n').unwrap();
+        assert_eq!(phase, "pre-plan");
+        let payload: Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(payload["phase"], "pre-plan");
+        assert_eq!(payload["prefix"], "/opt/zb");
+        assert_eq!(payload["formulas"][0], "jq");
+    }
+
+    #[test]
+    fn failures_block_only_blocking_phases() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_plugin(tmp.path(), "deny", "echo 'jq is not allowed' >&2; exit 1");
+        let hooks = PluginHooks::discover(tmp.path());
+
+        let err = hooks
+            .run(HookPhase::PrePlan, Path::new("/opt/zb"), json!({}))
+            .unwrap_err();
+        assert!(err.to_string().contains("plugin 'deny' failed at pre-plan"));
+        assert!(err.to_string().contains("jq is not allowed"));
+
+        hooks
+            .run(HookPhase::PostLink, Path::new("/opt/zb"), json!({}))
+            .unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::watchdog::{command_timeout, output_with_timeout};
+
+/// A running process with files open under one of the directories asked about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenProcess {
+    pub pid: u32,
+    pub command: String,
+    pub paths: Vec<PathBuf>,
+}
+
+/// Processes holding files open under `dirs`, mapped libraries and
+/// executables included, as reported by `lsof`.
+///
+/// Returns `None` when `lsof` is missing or fails to run. Processes owned by
+/// other users only show up when running with enough privileges.
+pub fn processes_using(dirs: &[PathBuf]) -> Option<Vec<OpenProcess>> {
+    if dirs.is_empty() {
+        return Some(Vec::new());
+    }
+    // Kegs are usually reached through symlinks; lsof reports resolved paths.
+    let dirs: Vec<PathBuf> = dirs
+        .iter()
+        .map(|dir| dir.canonicalize().unwrap_or_else(|_| dir.clone()))
+        .collect();
+
+    // lsof exits non-zero whenever some process couldn't be inspected, so
+    // only an empty listing counts as a failure.
+    let output = output_with_timeout(
+        Command::new("lsof").args(["-n", "-w", "-F", "pcn"]),
+        command_timeout(),
+    )
+    .ok()?;
+    if output.stdout.is_empty() && !output.status.success() {
+        return None;
+    }
+    Some(parse_lsof(&String::from_utf8_lossy(&output.stdout), &dirs))
+}
+
+/// Parse `lsof -F pcn` output, keeping the files under `dirs`.
+fn parse_lsof(output: &str, dirs: &[PathBuf]) -> Vec<OpenProcess> {
+    let mut processes: Vec<OpenProcess> = Vec::new();
+    let mut current: Option<OpenProcess> = None;
+
+    for line in output.lines() {
+        let Some(field) = line.chars().next() else {
+            continue;
+        };
+        let value = &line[field.len_utf8()..];
+        match field {
+            'p' => {
+                processes.extend(current.take().filter(|p| !p.paths.is_empty()));
+                current = value.parse().ok().map(|pid| OpenProcess {
+                    pid,
+                    command: String::new(),
+                    paths: Vec::new(),
+                });
+            }
+            'c' => {
+                if let Some(process) = &mut current {
+                    process.command = value.to_string();
+                }
+            }
+            'n' => {
+                let path = Path::new(value);
+                if let Some(process) = &mut current
+                    && dirs.iter().any(|dir| path.starts_with(dir))
+                    && !process.paths.iter().any(|p| p == path)
+                {
+                    process.paths.push(path.to_path_buf());
+                }
+            }
+            _ => {}
+        }
+    }
+    processes.extend(current.filter(|p| !p.paths.is_empty()));
+    processes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lsof_keeps_processes_with_files_under_dirs() {
+        let output = "\
+p101
+cpostgres
+fcwd
+n/var/lib/postgres
+ftxt
+n/opt/zerobrew/Cellar/postgresql@16/16.2/bin/postgres
+fmem
+n/opt/zerobrew/Cellar/openssl@3/3.2.1/lib/libssl.3.dylib
+fmem
+n/opt/zerobrew/Cellar/openssl@3/3.2.1/lib/libssl.3.dylib
+p202
+cbash
+ftxt
+n/bin/bash
+p303
+credis-server
+fmem
+n/opt/zerobrew/Cellar/openssl@3/3.2.1/lib/libcrypto.3.dylib
+";
+        let dirs = [PathBuf::from("/opt/zerobrew/Cellar/openssl@3/3.2.1")];
+        let processes = parse_lsof(output, &dirs);
+
+        assert_eq!(processes.len(), 2);
+        assert_eq!(processes[0].pid, 101);
+        assert_eq!(processes[0].command, "postgres");
+        assert_eq!(
+            processes[0].paths,
+            [PathBuf::from(
+                "/opt/zerobrew/Cellar/openssl@3/3.2.1/lib/libssl.3.dylib"
+            )]
+        );
+        assert_eq!(processes[1].command, "redis-server");
+    }
+
+    #[test]
+    fn parse_lsof_does_not_match_sibling_kegs_by_prefix() {
+        let output = "p1\ncfoo\nn/opt/zerobrew/Cellar/jq/1.7.1-extra/bin/jq\n";
+        let dirs = [PathBuf::from("/opt/zerobrew/Cellar/jq/1.7.1")];
+        assert!(parse_lsof(output, &dirs).is_empty());
+    }
+}
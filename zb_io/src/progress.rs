@@ -1,32 +1,105 @@
 /// Progress events during installation
+///
+/// `id` identifies the plan item an event belongs to (see [`item_id`]) and
+/// stays unique when two items share a name; `name` is what to display.
 #[derive(Debug, Clone)]
 pub enum InstallProgress {
     /// Starting to download a package (with total size if known)
     DownloadStarted {
+        id: String,
         name: String,
         total_bytes: Option<u64>,
     },
     /// Download progress update
     DownloadProgress {
+        id: String,
         name: String,
         downloaded: u64,
         total_bytes: Option<u64>,
     },
     /// Download completed for a package
-    DownloadCompleted { name: String, total_bytes: u64 },
+    DownloadCompleted {
+        id: String,
+        name: String,
+        total_bytes: u64,
+    },
     /// Starting to unpack/materialize a package
-    UnpackStarted { name: String },
+    UnpackStarted { id: String, name: String },
     /// Unpacking completed for a package
-    UnpackCompleted { name: String },
+    UnpackCompleted { id: String, name: String },
     /// Starting to link a package
-    LinkStarted { name: String },
+    LinkStarted { id: String, name: String },
     /// Linking completed for a package
-    LinkCompleted { name: String },
+    LinkCompleted { id: String, name: String },
     /// Linking skipped (keg-only or conflict)
-    LinkSkipped { name: String, reason: String },
+    LinkSkipped {
+        id: String,
+        name: String,
+        reason: String,
+    },
     /// Installation completed for a package (final state)
-    InstallCompleted { name: String },
+    InstallCompleted { id: String, name: String },
+}
+
+impl InstallProgress {
+    /// The plan item this event belongs to.
+    pub fn id(&self) -> &str {
+        match self {
+            Self::DownloadStarted { id, .. }
+            | Self::DownloadProgress { id, .. }
+            | Self::DownloadCompleted { id, .. }
+            | Self::UnpackStarted { id, .. }
+            | Self::UnpackCompleted { id, .. }
+            | Self::LinkStarted { id, .. }
+            | Self::LinkCompleted { id, .. }
+            | Self::LinkSkipped { id, .. }
+            | Self::InstallCompleted { id, .. } => id,
+        }
+    }
+
+    /// The same event, attributed to the item `id`.
+    pub(crate) fn with_id(mut self, item: &str) -> Self {
+        match &mut self {
+            Self::DownloadStarted { id, .. }
+            | Self::DownloadProgress { id, .. }
+            | Self::DownloadCompleted { id, .. }
+            | Self::UnpackStarted { id, .. }
+            | Self::UnpackCompleted { id, .. }
+            | Self::LinkStarted { id, .. }
+            | Self::LinkCompleted { id, .. }
+            | Self::LinkSkipped { id, .. }
+            | Self::InstallCompleted { id, .. } => *id = item.to_string(),
+        }
+        self
+    }
+}
+
+/// Progress id of the plan item installing `version` of `name` from the
+/// artifact with checksum `sha256`.
+pub fn item_id(name: &str, version: &str, sha256: &str) -> String {
+    let sha = sha256.get(..12).unwrap_or(sha256);
+    format!("{name}@{version}#{sha}")
 }
 
 /// Callback type for progress reporting
 pub type ProgressCallback = Box<dyn Fn(InstallProgress) + Send + Sync>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn item_ids_tell_apart_same_name_at_other_versions() {
+        let old = item_id("python", "3.12.1", &"ab".repeat(32));
+        let new = item_id("python", "3.13.0", &"ab".repeat(32));
+        assert_ne!(old, new);
+        assert_eq!(old, "python@3.12.1#abababababab");
+        assert_eq!(item_id("jq", "1.7.1", "source"), "jq@1.7.1#source");
+
+        let event = InstallProgress::UnpackStarted {
+            id: "jq".to_string(),
+            name: "jq".to_string(),
+        };
+        assert_eq!(event.with_id(&old).id(), old);
+    }
+}
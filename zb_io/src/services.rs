@@ -0,0 +1,513 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use zb_core::{Error, Service, formula_token};
+
+use crate::watchdog::{command_timeout, output_with_timeout};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceKind {
+    Launchd,
+    Systemd,
+}
+
+/// A per-user service unit registered for a formula.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceUnit {
+    pub formula: String,
+    pub label: String,
+    pub path: PathBuf,
+    pub kind: ServiceKind,
+}
+
+/// Locates and controls the launchd agents / systemd user units zerobrew manages.
+///
+/// Units are named `zerobrew.<formula>` and live in the user's LaunchAgents
+/// directory on macOS and in `~/.config/systemd/user` elsewhere.
+pub struct ServiceManager {
+    unit_dir: PathBuf,
+    kind: ServiceKind,
+}
+
+impl ServiceManager {
+    pub fn new(unit_dir: PathBuf, kind: ServiceKind) -> Self {
+        Self { unit_dir, kind }
+    }
+
+    /// Service manager for the current user, or `None` when `HOME` is unset.
+    pub fn for_current_user() -> Option<Self> {
+        let home = PathBuf::from(std::env::var_os("HOME")?);
+        Some(if cfg!(target_os = "macos") {
+            Self::new(home.join("Library/LaunchAgents"), ServiceKind::Launchd)
+        } else {
+            Self::new(home.join(".config/systemd/user"), ServiceKind::Systemd)
+        })
+    }
+
+    pub fn kind(&self) -> ServiceKind {
+        self.kind
+    }
+
+    pub fn label(formula: &str) -> String {
+        format!("zerobrew.{}", formula_token(formula))
+    }
+
+    /// File name of the unit for `formula`: `zerobrew.<formula>.plist` or
+    /// `zerobrew.<formula>.service`.
+    pub fn definition_file_name(kind: ServiceKind, formula: &str) -> String {
+        let label = Self::label(formula);
+        match kind {
+            ServiceKind::Launchd => format!("{label}.plist"),
+            ServiceKind::Systemd => format!("{label}.service"),
+        }
+    }
+
+    fn unit_path(&self, label: &str) -> PathBuf {
+        match self.kind {
+            ServiceKind::Launchd => self.unit_dir.join(format!("{label}.plist")),
+            ServiceKind::Systemd => self.unit_dir.join(format!("{label}.service")),
+        }
+    }
+
+    /// Every unit zerobrew registered, sorted by formula.
+    pub fn list(&self) -> Vec<ServiceUnit> {
+        let extension = match self.kind {
+            ServiceKind::Launchd => ".plist",
+            ServiceKind::Systemd => ".service",
+        };
+        let mut units: Vec<ServiceUnit> = fs::read_dir(&self.unit_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                let label = file_name.strip_suffix(extension)?;
+                let formula = label.strip_prefix("zerobrew.")?;
+                Some(ServiceUnit {
+                    formula: formula.to_string(),
+                    label: label.to_string(),
+                    path: entry.path(),
+                    kind: self.kind,
+                })
+            })
+            .collect();
+        units.sort_by(|a, b| a.formula.cmp(&b.formula));
+        units
+    }
+
+    /// Register the unit file `definition` for `formula` and start it, at
+    /// login from now on too.
+    pub fn start(&self, formula: &str, definition: &Path) -> Result<ServiceUnit, Error> {
+        let label = Self::label(formula);
+        let unit = ServiceUnit {
+            formula: formula.to_string(),
+            path: self.unit_path(&label),
+            label,
+            kind: self.kind,
+        };
+        fs::create_dir_all(&self.unit_dir).map_err(|e| Error::FileError {
+            message: format!("failed to create {}: {e}", self.unit_dir.display()),
+        })?;
+        fs::copy(definition, &unit.path).map_err(|e| Error::FileError {
+            message: format!("failed to write {}: {e}", unit.path.display()),
+        })?;
+
+        match unit.kind {
+            ServiceKind::Launchd => run(Command::new("launchctl")
+                .args(["load", "-w"])
+                .arg(&unit.path))?,
+            ServiceKind::Systemd => {
+                run(Command::new("systemctl").args(["--user", "daemon-reload"]))?;
+                run(Command::new("systemctl").args([
+                    "--user",
+                    "enable",
+                    "--now",
+                    &unit_file_name(&unit),
+                ]))?;
+            }
+        }
+        Ok(unit)
+    }
+
+    /// The unit registered for `formula`, if one exists on disk.
+    pub fn find(&self, formula: &str) -> Option<ServiceUnit> {
+        let label = Self::label(formula);
+        let path = self.unit_path(&label);
+        path.exists().then(|| ServiceUnit {
+            formula: formula.to_string(),
+            label,
+            path,
+            kind: self.kind,
+        })
+    }
+
+    pub fn is_running(&self, unit: &ServiceUnit) -> bool {
+        let mut command = match unit.kind {
+            ServiceKind::Launchd => {
+                let mut cmd = Command::new("launchctl");
+                cmd.args(["list", &unit.label]);
+                cmd
+            }
+            ServiceKind::Systemd => {
+                let mut cmd = Command::new("systemctl");
+                cmd.args(["--user", "is-active", "--quiet", &unit_file_name(unit)]);
+                cmd
+            }
+        };
+        output_with_timeout(&mut command, command_timeout())
+            .map(|output| output.status.success())
+            .unwrap_or(false)
+    }
+
+    /// Stop the service and unregister it from launchd/systemd.
+    pub fn stop(&self, unit: &ServiceUnit) -> Result<(), Error> {
+        match unit.kind {
+            ServiceKind::Launchd => run(Command::new("launchctl")
+                .args(["unload", "-w"])
+                .arg(&unit.path)),
+            ServiceKind::Systemd => run(Command::new("systemctl").args([
+                "--user",
+                "disable",
+                "--now",
+                &unit_file_name(unit),
+            ])),
+        }
+    }
+
+    /// Delete the unit file so nothing points at a keg that is about to go away.
+    pub fn remove(&self, unit: &ServiceUnit) -> Result<(), Error> {
+        match fs::remove_file(&unit.path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(Error::FileError {
+                    message: format!("failed to remove {}: {e}", unit.path.display()),
+                });
+            }
+        }
+
+        if unit.kind == ServiceKind::Systemd {
+            // Best effort: a stale unit cache only matters until the next reload.
+            let _ = run(Command::new("systemctl").args(["--user", "daemon-reload"]));
+        }
+        Ok(())
+    }
+}
+
+/// The unit file running `formula`'s `service` block, with `$HOMEBREW_PREFIX`
+/// resolved to `prefix` and `~` to `home`.
+pub fn render_unit(
+    kind: ServiceKind,
+    formula: &str,
+    service: &Service,
+    prefix: &Path,
+    home: Option<&Path>,
+) -> Result<String, Error> {
+    let unsupported = |reason: &str| Error::UnsupportedFormula {
+        name: formula.to_string(),
+        reason: reason.to_string(),
+    };
+    let resolve = |value: &str| {
+        let value = value.replace("$HOMEBREW_PREFIX", &prefix.to_string_lossy());
+        match (value.strip_prefix("~/"), home) {
+            (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+            _ => value,
+        }
+    };
+
+    let command: Vec<String> = service
+        .command(kind == ServiceKind::Launchd)
+        .iter()
+        .map(|arg| resolve(arg))
+        .collect();
+    if command.is_empty() {
+        return Err(unsupported("its service has no command for this platform"));
+    }
+    let interval = match service.run_type.as_deref() {
+        None | Some("immediate") => None,
+        Some("interval") => Some(
+            service
+                .interval
+                .ok_or_else(|| unsupported("its interval service has no interval"))?,
+        ),
+        Some(other) => return Err(unsupported(&format!("{other} services aren't supported"))),
+    };
+    let working_dir = service.working_dir.as_deref().map(resolve);
+    let log_path = service.log_path.as_deref().map(resolve);
+    let error_log_path = service.error_log_path.as_deref().map(resolve);
+    let environment: Vec<(String, String)> = service
+        .environment_variables
+        .iter()
+        .map(|(key, value)| (key.clone(), resolve(value)))
+        .collect();
+    let label = ServiceManager::label(formula);
+
+    let mut unit = String::new();
+    match kind {
+        ServiceKind::Launchd => {
+            let string = |value: &str| format!("<string>{}</string>", xml_escape(value));
+            unit.push_str(concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+                "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+                "<plist version=\"1.0\">\n<dict>\n",
+            ));
+            unit.push_str(&format!("\t<key>Label</key>\n\t{}\n", string(&label)));
+            unit.push_str("\t<key>ProgramArguments</key>\n\t<array>\n");
+            for arg in &command {
+                unit.push_str(&format!("\t\t{}\n", string(arg)));
+            }
+            unit.push_str("\t</array>\n\t<key>RunAtLoad</key>\n\t<true/>\n");
+            if service.keeps_alive() {
+                unit.push_str("\t<key>KeepAlive</key>\n\t<true/>\n");
+            } else if service.restarts_on_crash() {
+                unit.push_str(
+                    "\t<key>KeepAlive</key>\n\t<dict>\n\t\t<key>SuccessfulExit</key>\n\t\t<false/>\n\t</dict>\n",
+                );
+            }
+            if let Some(interval) = interval {
+                unit.push_str(&format!(
+                    "\t<key>StartInterval</key>\n\t<integer>{interval}</integer>\n"
+                ));
+            }
+            for (key, value) in [
+                ("WorkingDirectory", &working_dir),
+                ("StandardOutPath", &log_path),
+                ("StandardErrorPath", &error_log_path),
+            ] {
+                if let Some(value) = value {
+                    unit.push_str(&format!("\t<key>{key}</key>\n\t{}\n", string(value)));
+                }
+            }
+            if !environment.is_empty() {
+                unit.push_str("\t<key>EnvironmentVariables</key>\n\t<dict>\n");
+                for (key, value) in &environment {
+                    unit.push_str(&format!(
+                        "\t\t<key>{}</key>\n\t\t{}\n",
+                        xml_escape(key),
+                        string(value)
+                    ));
+                }
+                unit.push_str("\t</dict>\n");
+            }
+            unit.push_str("</dict>\n</plist>\n");
+        }
+        ServiceKind::Systemd => {
+            let exec: Vec<String> = command.iter().map(|arg| systemd_quote(arg)).collect();
+            unit.push_str(&format!(
+                "[Unit]\nDescription=zerobrew: {formula}\n\n[Service]\nType=simple\nExecStart={}\n",
+                exec.join(" ")
+            ));
+            // systemd has timers for this, but restarting after the interval
+            // runs the command just as often without a second unit.
+            if let Some(interval) = interval {
+                unit.push_str(&format!("Restart=always\nRestartSec={interval}\n"));
+            } else if service.keeps_alive() {
+                unit.push_str("Restart=always\n");
+            } else if service.restarts_on_crash() {
+                unit.push_str("Restart=on-failure\n");
+            }
+            if let Some(dir) = &working_dir {
+                unit.push_str(&format!("WorkingDirectory={}\n", systemd_quote(dir)));
+            }
+            if let Some(path) = &log_path {
+                unit.push_str(&format!("StandardOutput=append:{path}\n"));
+            }
+            if let Some(path) = &error_log_path {
+                unit.push_str(&format!("StandardError=append:{path}\n"));
+            }
+            for (key, value) in &environment {
+                unit.push_str(&format!(
+                    "Environment={}\n",
+                    systemd_quote(&format!("{key}={value}"))
+                ));
+            }
+            unit.push_str("\n[Install]\nWantedBy=default.target\n");
+        }
+    }
+    Ok(unit)
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `value` double-quoted when systemd would otherwise split or expand it.
+fn systemd_quote(value: &str) -> String {
+    if !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | '$' | '%' | ';'))
+    {
+        return value.to_string();
+    }
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "$$")
+        .replace('%', "%%");
+    format!("\"{escaped}\"")
+}
+
+fn unit_file_name(unit: &ServiceUnit) -> String {
+    unit.path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| format!("{}.service", unit.label))
+}
+
+fn run(command: &mut Command) -> Result<(), Error> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = output_with_timeout(command, command_timeout())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::ExecutionError {
+            message: format!(
+                "{program} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_units_by_formula_token() {
+        let tmp = tempfile::tempdir().unwrap();
+        let manager = ServiceManager::new(tmp.path().to_path_buf(), ServiceKind::Systemd);
+        assert!(manager.find("postgresql@16").is_none());
+
+        fs::write(tmp.path().join("zerobrew.postgresql@16.service"), "").unwrap();
+        let unit = manager.find("postgresql@16").unwrap();
+        assert_eq!(unit.label, "zerobrew.postgresql@16");
+        assert_eq!(unit.kind, ServiceKind::Systemd);
+
+        let manager = ServiceManager::new(tmp.path().to_path_buf(), ServiceKind::Launchd);
+        fs::write(tmp.path().join("zerobrew.redis.plist"), "").unwrap();
+        assert_eq!(
+            manager.find("acme/tap/redis").unwrap().path,
+            tmp.path().join("zerobrew.redis.plist")
+        );
+    }
+
+    #[test]
+    fn lists_only_zerobrew_units() {
+        let tmp = tempfile::tempdir().unwrap();
+        let manager = ServiceManager::new(tmp.path().to_path_buf(), ServiceKind::Launchd);
+        assert!(manager.list().is_empty());
+
+        for file in [
+            "zerobrew.redis.plist",
+            "zerobrew.postgresql@16.plist",
+            "homebrew.mxcl.redis.plist",
+            "zerobrew.redis.service",
+        ] {
+            fs::write(tmp.path().join(file), "").unwrap();
+        }
+        let formulas: Vec<String> = manager.list().into_iter().map(|u| u.formula).collect();
+        assert_eq!(formulas, ["postgresql@16", "redis"]);
+    }
+
+    fn redis_service() -> Service {
+        serde_json::from_value(serde_json::json!({
+            "run": {
+                "macos": ["$HOMEBREW_PREFIX/opt/redis/bin/redis-server", "--dir", "~/redis data"],
+                "linux": ["$HOMEBREW_PREFIX/opt/redis/bin/redis-server", "--dir", "~/redis data"]
+            },
+            "keep_alive": { "crashed": true },
+            "working_dir": "$HOMEBREW_PREFIX/var",
+            "log_path": "$HOMEBREW_PREFIX/var/log/redis.log",
+            "environment_variables": { "LANG": "en_US.UTF-8" }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn renders_launchd_plists() {
+        let plist = render_unit(
+            ServiceKind::Launchd,
+            "redis",
+            &redis_service(),
+            Path::new("/opt/zerobrew"),
+            Some(Path::new("/Users/me")),
+        )
+        .unwrap();
+        assert!(plist.contains("<key>Label</key>\n\t<string>zerobrew.redis</string>"));
+        assert!(plist.contains(concat!(
+            "\t\t<string>/opt/zerobrew/opt/redis/bin/redis-server</string>\n",
+            "\t\t<string>--dir</string>\n",
+            "\t\t<string>/Users/me/redis data</string>\n"
+        )));
+        assert!(plist.contains("<key>SuccessfulExit</key>\n\t\t<false/>"));
+        assert!(plist.contains(
+            "<key>StandardOutPath</key>\n\t<string>/opt/zerobrew/var/log/redis.log</string>"
+        ));
+        assert!(plist.contains("<key>LANG</key>\n\t\t<string>en_US.UTF-8</string>"));
+        assert!(!plist.contains("StartInterval"));
+    }
+
+    #[test]
+    fn renders_systemd_units() {
+        let unit = render_unit(
+            ServiceKind::Systemd,
+            "redis",
+            &redis_service(),
+            Path::new("/opt/zerobrew"),
+            Some(Path::new("/home/me")),
+        )
+        .unwrap();
+        assert!(unit.contains(
+            "ExecStart=/opt/zerobrew/opt/redis/bin/redis-server --dir \"/home/me/redis data\"\n"
+        ));
+        assert!(unit.contains("Restart=on-failure\n"));
+        assert!(unit.contains("WorkingDirectory=/opt/zerobrew/var\n"));
+        assert!(unit.contains("StandardOutput=append:/opt/zerobrew/var/log/redis.log\n"));
+        assert!(unit.contains("Environment=LANG=en_US.UTF-8\n"));
+
+        let mut periodic = redis_service();
+        periodic.run_type = Some("interval".to_string());
+        periodic.interval = Some(3600);
+        let unit = render_unit(
+            ServiceKind::Systemd,
+            "redis",
+            &periodic,
+            Path::new("/opt/zerobrew"),
+            None,
+        )
+        .unwrap();
+        assert!(unit.contains("Restart=always\nRestartSec=3600\n"));
+
+        periodic.run_type = Some("cron".to_string());
+        assert!(
+            render_unit(
+                ServiceKind::Systemd,
+                "redis",
+                &periodic,
+                Path::new("/"),
+                None
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn remove_deletes_unit_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let manager = ServiceManager::new(tmp.path().to_path_buf(), ServiceKind::Launchd);
+        fs::write(tmp.path().join("zerobrew.redis.plist"), "").unwrap();
+
+        let unit = manager.find("redis").unwrap();
+        manager.remove(&unit).unwrap();
+        assert!(manager.find("redis").is_none());
+        manager.remove(&unit).unwrap();
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use zb_core::Error;
+
+use crate::cellar::link::LINK_DIRS;
+
+const MANIFEST_FILE: &str = "snapshot.json";
+const DATABASE_FILE: &str = "zb.sqlite3";
+
+/// Number of snapshots kept around; older ones are pruned when a new one is taken.
+const MAX_SNAPSHOTS: usize = 5;
+
+/// Metadata-only snapshots of the prefix symlinks and the install database.
+///
+/// Snapshots don't copy kegs or store entries, so they are cheap enough to take
+/// before every risky operation. Restoring one puts the prefix links and database
+/// back the way they were; kegs that were removed in the meantime have to be
+/// reinstalled.
+pub struct SnapshotStore {
+    dir: PathBuf,
+    db_path: PathBuf,
+    prefix: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub label: String,
+    pub created_at: i64,
+    pub links: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestoreSummary {
+    /// Links recreated or repointed to their snapshot target.
+    pub restored: usize,
+    /// Links that did not exist when the snapshot was taken and were removed.
+    pub removed: usize,
+    /// Paths left untouched because a regular file or directory now occupies them.
+    pub skipped: Vec<PathBuf>,
+    /// Restored links whose target no longer exists.
+    pub dangling: Vec<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    label: String,
+    created_at: i64,
+    links: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl SnapshotStore {
+    pub fn new(root: &Path, prefix: &Path) -> Self {
+        Self {
+            dir: root.join("snapshots"),
+            db_path: root.join("db/zb.sqlite3"),
+            prefix: prefix.to_path_buf(),
+        }
+    }
+
+    /// Record the current prefix links and database under a new snapshot id.
+    pub fn create(&self, label: &str) -> Result<SnapshotInfo, Error> {
+        let created_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        // Several snapshots taken within the same second get an increasing suffix.
+        let next = self
+            .list()?
+            .iter()
+            .filter(|s| s.created_at == created_at)
+            .map(|s| sequence(&s.id) + 1)
+            .max()
+            .unwrap_or(0);
+        let id = if next == 0 {
+            created_at.to_string()
+        } else {
+            format!("{created_at}-{next}")
+        };
+
+        let snapshot_dir = self.dir.join(&id);
+        fs::create_dir_all(&snapshot_dir).map_err(|e| Error::FileError {
+            message: format!(
+                "failed to create snapshot directory {}: {e}",
+                snapshot_dir.display()
+            ),
+        })?;
+
+        let result = self.write_snapshot(&snapshot_dir, label, created_at);
+        if result.is_err() {
+            let _ = fs::remove_dir_all(&snapshot_dir);
+        }
+        let links = result?;
+
+        self.prune(MAX_SNAPSHOTS)?;
+
+        Ok(SnapshotInfo {
+            id,
+            label: label.to_string(),
+            created_at,
+            links,
+        })
+    }
+
+    fn write_snapshot(&self, dir: &Path, label: &str, created_at: i64) -> Result<usize, Error> {
+        if self.db_path.exists() {
+            fs::copy(&self.db_path, dir.join(DATABASE_FILE)).map_err(|e| Error::FileError {
+                message: format!("failed to copy database into snapshot: {e}"),
+            })?;
+        }
+
+        let manifest = Manifest {
+            label: label.to_string(),
+            created_at,
+            links: self.current_links(),
+        };
+        let json = serde_json::to_vec_pretty(&manifest).map_err(|e| Error::FileError {
+            message: format!("failed to serialize snapshot manifest: {e}"),
+        })?;
+        fs::write(dir.join(MANIFEST_FILE), json).map_err(|e| Error::FileError {
+            message: format!("failed to write snapshot manifest: {e}"),
+        })?;
+
+        Ok(manifest.links.len())
+    }
+
+    /// All snapshots, newest first.
+    pub fn list(&self) -> Result<Vec<SnapshotInfo>, Error> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(Error::FileError {
+                    message: format!("failed to read {}: {e}", self.dir.display()),
+                });
+            }
+        };
+
+        let mut snapshots = Vec::new();
+        for entry in entries.flatten() {
+            let id = entry.file_name().to_string_lossy().into_owned();
+            if let Ok(manifest) = self.read_manifest(&id) {
+                snapshots.push(SnapshotInfo {
+                    id,
+                    label: manifest.label,
+                    created_at: manifest.created_at,
+                    links: manifest.links.len(),
+                });
+            }
+        }
+        snapshots.sort_by_key(|s| std::cmp::Reverse((s.created_at, sequence(&s.id))));
+        Ok(snapshots)
+    }
+
+    pub fn latest(&self) -> Result<Option<SnapshotInfo>, Error> {
+        Ok(self.list()?.into_iter().next())
+    }
+
+    /// Put the prefix links and database back to the state recorded in `id`.
+    ///
+    /// Only symlinks are touched; regular files that now occupy a recorded path are
+    /// reported in `skipped` rather than overwritten.
+    pub fn restore(&self, id: &str) -> Result<RestoreSummary, Error> {
+        let manifest = self.read_manifest(id)?;
+        let mut summary = RestoreSummary::default();
+
+        for (relative, target) in self.current_links() {
+            if manifest.links.get(&relative) != Some(&target) {
+                let path = self.prefix.join(&relative);
+                if fs::remove_file(&path).is_ok() && !manifest.links.contains_key(&relative) {
+                    summary.removed += 1;
+                }
+            }
+        }
+
+        for (relative, target) in &manifest.links {
+            let path = self.prefix.join(relative);
+            match fs::symlink_metadata(&path) {
+                Ok(meta) if meta.file_type().is_symlink() => continue,
+                Ok(_) => {
+                    summary.skipped.push(path);
+                    continue;
+                }
+                Err(_) => {}
+            }
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| Error::FileError {
+                    message: format!("failed to create {}: {e}", parent.display()),
+                })?;
+            }
+            create_symlink(target, &path).map_err(|e| Error::FileError {
+                message: format!("failed to restore link {}: {e}", path.display()),
+            })?;
+            summary.restored += 1;
+
+            if !path.exists() {
+                summary.dangling.push(path);
+            }
+        }
+
+        let snapshot_db = self.dir.join(id).join(DATABASE_FILE);
+        if snapshot_db.exists() {
+            fs::copy(&snapshot_db, &self.db_path).map_err(|e| Error::FileError {
+                message: format!("failed to restore database from snapshot: {e}"),
+            })?;
+        }
+
+        Ok(summary)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), Error> {
+        let path = self.snapshot_dir(id)?;
+        fs::remove_dir_all(&path).map_err(|e| Error::FileError {
+            message: format!("failed to remove snapshot {id}: {e}"),
+        })
+    }
+
+    fn prune(&self, keep: usize) -> Result<(), Error> {
+        for snapshot in self.list()?.into_iter().skip(keep) {
+            self.remove(&snapshot.id)?;
+        }
+        Ok(())
+    }
+
+    fn snapshot_dir(&self, id: &str) -> Result<PathBuf, Error> {
+        let path = self.dir.join(id);
+        if id.is_empty() || id.contains(['/', '\\']) || !path.is_dir() {
+            return Err(Error::InvalidArgument {
+                message: format!("no snapshot with id '{id}'"),
+            });
+        }
+        Ok(path)
+    }
+
+    fn read_manifest(&self, id: &str) -> Result<Manifest, Error> {
+        let path = self.snapshot_dir(id)?.join(MANIFEST_FILE);
+        let contents = fs::read(&path).map_err(|e| Error::FileError {
+            message: format!("failed to read {}: {e}", path.display()),
+        })?;
+        serde_json::from_slice(&contents).map_err(|e| Error::FileError {
+            message: format!("invalid snapshot manifest {}: {e}", path.display()),
+        })
+    }
+
+    /// Every symlink under the linked prefix directories, keyed by path relative to the prefix.
+    fn current_links(&self) -> BTreeMap<PathBuf, PathBuf> {
+        let mut links = BTreeMap::new();
+        for dir in LINK_DIRS.iter().copied().chain(["opt"]) {
+            let root = self.prefix.join(dir);
+            for entry in walkdir::WalkDir::new(&root)
+                .follow_links(false)
+                .into_iter()
+                .flatten()
+            {
+                if !entry.path_is_symlink() {
+                    continue;
+                }
+                let Ok(target) = fs::read_link(entry.path()) else {
+                    continue;
+                };
+                if let Ok(relative) = entry.path().strip_prefix(&self.prefix) {
+                    links.insert(relative.to_path_buf(), target);
+                }
+            }
+        }
+        links
+    }
+}
+
+fn sequence(id: &str) -> u32 {
+    id.split_once('-')
+        .and_then(|(_, n)| n.parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "symlinks are not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, PathBuf, PathBuf) {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("root");
+        let prefix = tmp.path().join("prefix");
+        fs::create_dir_all(root.join("db")).unwrap();
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        fs::create_dir_all(prefix.join("Cellar/foo/1.0/bin")).unwrap();
+        fs::create_dir_all(prefix.join("Cellar/foo/2.0/bin")).unwrap();
+        fs::write(prefix.join("Cellar/foo/1.0/bin/foo"), "v1").unwrap();
+        fs::write(prefix.join("Cellar/foo/2.0/bin/foo"), "v2").unwrap();
+        (tmp, root, prefix)
+    }
+
+    #[test]
+    fn restore_reverts_links_and_database() {
+        let (_tmp, root, prefix) = setup();
+        fs::write(root.join("db/zb.sqlite3"), "before").unwrap();
+        symlink("../Cellar/foo/1.0/bin/foo", prefix.join("bin/foo")).unwrap();
+
+        let store = SnapshotStore::new(&root, &prefix);
+        let snapshot = store.create("upgrade").unwrap();
+        assert_eq!(snapshot.links, 1);
+
+        // Simulate an upgrade that repoints foo and links a new bar.
+        fs::remove_file(prefix.join("bin/foo")).unwrap();
+        symlink("../Cellar/foo/2.0/bin/foo", prefix.join("bin/foo")).unwrap();
+        symlink("../Cellar/foo/2.0/bin/foo", prefix.join("bin/bar")).unwrap();
+        fs::write(root.join("db/zb.sqlite3"), "after").unwrap();
+
+        let summary = store.restore(&snapshot.id).unwrap();
+        assert_eq!(summary.restored, 1);
+        assert_eq!(summary.removed, 1);
+        assert!(summary.dangling.is_empty());
+
+        assert_eq!(
+            fs::read_link(prefix.join("bin/foo")).unwrap(),
+            PathBuf::from("../Cellar/foo/1.0/bin/foo")
+        );
+        assert!(!prefix.join("bin/bar").exists());
+        assert_eq!(
+            fs::read_to_string(root.join("db/zb.sqlite3")).unwrap(),
+            "before"
+        );
+    }
+
+    #[test]
+    fn restore_does_not_overwrite_regular_files() {
+        let (_tmp, root, prefix) = setup();
+        symlink("../Cellar/foo/1.0/bin/foo", prefix.join("bin/foo")).unwrap();
+
+        let store = SnapshotStore::new(&root, &prefix);
+        let snapshot = store.create("test").unwrap();
+
+        fs::remove_file(prefix.join("bin/foo")).unwrap();
+        fs::write(prefix.join("bin/foo"), "user file").unwrap();
+
+        let summary = store.restore(&snapshot.id).unwrap();
+        assert_eq!(summary.skipped, vec![prefix.join("bin/foo")]);
+        assert_eq!(
+            fs::read_to_string(prefix.join("bin/foo")).unwrap(),
+            "user file"
+        );
+    }
+
+    #[test]
+    fn restore_reports_links_to_removed_kegs() {
+        let (_tmp, root, prefix) = setup();
+        symlink("../Cellar/foo/1.0/bin/foo", prefix.join("bin/foo")).unwrap();
+
+        let store = SnapshotStore::new(&root, &prefix);
+        let snapshot = store.create("test").unwrap();
+
+        fs::remove_file(prefix.join("bin/foo")).unwrap();
+        fs::remove_dir_all(prefix.join("Cellar/foo/1.0")).unwrap();
+
+        let summary = store.restore(&snapshot.id).unwrap();
+        assert_eq!(summary.dangling, vec![prefix.join("bin/foo")]);
+    }
+
+    #[test]
+    fn create_prunes_old_snapshots() {
+        let (_tmp, root, prefix) = setup();
+        let store = SnapshotStore::new(&root, &prefix);
+
+        for _ in 0..MAX_SNAPSHOTS + 2 {
+            store.create("test").unwrap();
+        }
+
+        let snapshots = store.list().unwrap();
+        assert_eq!(snapshots.len(), MAX_SNAPSHOTS);
+        let newest = store.create("newest").unwrap();
+        assert_eq!(store.latest().unwrap(), Some(newest));
+    }
+
+    #[test]
+    fn unknown_snapshot_is_rejected() {
+        let (_tmp, root, prefix) = setup();
+        let store = SnapshotStore::new(&root, &prefix);
+
+        assert!(matches!(
+            store.restore("missing"),
+            Err(Error::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            store.restore("../db"),
+            Err(Error::InvalidArgument { .. })
+        ));
+        assert!(store.latest().unwrap().is_none());
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What to do with store entries that exist on disk but have no database record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrphanAction {
+    /// Only list them.
+    #[default]
+    Report,
+    /// Record them with a zero refcount so the next `zb gc` can reclaim them.
+    Adopt,
+    /// Remove them from disk immediately.
+    Delete,
+}
+
+/// A `store_refs` row whose count disagrees with the installed kegs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefcountFix {
+    pub store_key: String,
+    /// `None` when installed kegs reference a key that has no row at all.
+    pub recorded: Option<i64>,
+    pub actual: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StoreAudit {
+    /// Number of store keys looked at (on disk or in the database).
+    pub checked: usize,
+    pub refcount_fixes: Vec<RefcountFix>,
+    /// Whether `refcount_fixes` were written to the database.
+    pub repaired: bool,
+    /// Entries on disk unknown to the database, handled per [`OrphanAction`].
+    pub orphans: Vec<String>,
+    pub orphan_action: OrphanAction,
+    /// Bottle entries referenced by installed kegs but missing from disk.
+    /// They can't be fixed here; reinstalling the formula restores them.
+    pub missing_entries: Vec<String>,
+}
+
+impl StoreAudit {
+    pub fn is_clean(&self) -> bool {
+        self.refcount_fixes.is_empty() && self.orphans.is_empty() && self.missing_entries.is_empty()
+    }
+}
+
+/// Compare recorded refcounts with the ones implied by installed kegs and
+/// the entries actually on disk.
+///
+/// `references` has one store key per installed keg; `on_disk` lists the
+/// store directory. Keys without a store entry by design (source builds,
+/// casks) should be left out of `bottle_keys` so they aren't reported missing.
+pub(crate) fn audit(
+    recorded: &[(String, i64)],
+    references: &[String],
+    bottle_keys: &BTreeSet<String>,
+    on_disk: &[String],
+) -> StoreAudit {
+    let recorded: BTreeMap<&str, i64> = recorded.iter().map(|(k, c)| (k.as_str(), *c)).collect();
+    let mut actual: BTreeMap<&str, i64> = BTreeMap::new();
+    for key in references {
+        *actual.entry(key.as_str()).or_default() += 1;
+    }
+    let on_disk: BTreeSet<&str> = on_disk.iter().map(String::as_str).collect();
+
+    let mut keys: BTreeSet<&str> = recorded.keys().copied().collect();
+    keys.extend(actual.keys().copied());
+    keys.extend(on_disk.iter().copied());
+
+    let mut result = StoreAudit {
+        checked: keys.len(),
+        ..StoreAudit::default()
+    };
+
+    for key in keys {
+        let count = actual.get(key).copied().unwrap_or(0);
+        match recorded.get(key) {
+            Some(&recorded) if recorded != count => result.refcount_fixes.push(RefcountFix {
+                store_key: key.to_string(),
+                recorded: Some(recorded),
+                actual: count,
+            }),
+            None if count > 0 => result.refcount_fixes.push(RefcountFix {
+                store_key: key.to_string(),
+                recorded: None,
+                actual: count,
+            }),
+            None if on_disk.contains(key) => result.orphans.push(key.to_string()),
+            _ => {}
+        }
+
+        if count > 0 && bottle_keys.contains(key) && !on_disk.contains(key) {
+            result.missing_entries.push(key.to_string());
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn recomputes_counts_and_finds_orphans() {
+        let recorded = vec![
+            ("shared".to_string(), 1),
+            ("negative".to_string(), -2),
+            ("ok".to_string(), 1),
+            ("stale".to_string(), 3),
+        ];
+        let references = strings(&["shared", "shared", "ok", "negative", "unrecorded"]);
+        let bottle_keys: BTreeSet<String> = strings(&["shared", "ok", "negative", "unrecorded"])
+            .into_iter()
+            .collect();
+        let on_disk = strings(&["shared", "ok", "negative", "stale", "orphan"]);
+
+        let result = audit(&recorded, &references, &bottle_keys, &on_disk);
+
+        assert_eq!(result.checked, 6);
+        assert_eq!(
+            result.refcount_fixes,
+            vec![
+                RefcountFix {
+                    store_key: "negative".into(),
+                    recorded: Some(-2),
+                    actual: 1
+                },
+                RefcountFix {
+                    store_key: "shared".into(),
+                    recorded: Some(1),
+                    actual: 2
+                },
+                RefcountFix {
+                    store_key: "stale".into(),
+                    recorded: Some(3),
+                    actual: 0
+                },
+                RefcountFix {
+                    store_key: "unrecorded".into(),
+                    recorded: None,
+                    actual: 1
+                },
+            ]
+        );
+        assert_eq!(result.orphans, ["orphan"]);
+        assert_eq!(result.missing_entries, ["unrecorded"]);
+        assert!(!result.is_clean());
+    }
+
+    #[test]
+    fn consistent_store_is_clean() {
+        let recorded = vec![("a".to_string(), 1), ("source:x:1".to_string(), 1)];
+        let references = strings(&["a", "source:x:1"]);
+        let bottle_keys: BTreeSet<String> = strings(&["a"]).into_iter().collect();
+
+        let result = audit(&recorded, &references, &bottle_keys, &strings(&["a"]));
+        assert!(result.is_clean());
+    }
+}
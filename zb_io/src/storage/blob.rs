@@ -1,13 +1,16 @@
 use std::fs;
-use std::io::{self, Seek, SeekFrom, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use sha2::{Digest, Sha256};
 use zb_core::Error;
 
 #[derive(Clone)]
 pub struct BlobCache {
     blobs_dir: PathBuf,
     tmp_dir: PathBuf,
+    verify: bool,
 }
 
 impl BlobCache {
@@ -18,19 +21,72 @@ impl BlobCache {
         fs::create_dir_all(&blobs_dir)?;
         fs::create_dir_all(&tmp_dir)?;
 
-        Ok(Self { blobs_dir, tmp_dir })
+        Ok(Self {
+            blobs_dir,
+            tmp_dir,
+            verify: false,
+        })
+    }
+
+    /// Re-check the hash of cached blobs before reusing them (see
+    /// [`Self::cached_blob`]).
+    pub fn with_verification(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
     }
 
     pub fn blob_path(&self, sha256: &str) -> PathBuf {
         self.blobs_dir.join(format!("{sha256}.tar.gz"))
     }
 
+    /// Size and mtime the blob had when it was admitted to the cache.
+    fn admission_path(&self, sha256: &str) -> PathBuf {
+        self.blobs_dir.join(format!("{sha256}.admitted"))
+    }
+
     pub fn has_blob(&self, sha256: &str) -> bool {
         self.blob_path(sha256).exists()
     }
 
+    /// Path of a cached blob that is safe to reuse.
+    ///
+    /// With verification on, a blob whose size or mtime changed since it was
+    /// admitted is hashed again; one that no longer matches `sha256` (disk
+    /// corruption, a stray edit) is removed so the caller downloads it anew.
+    pub fn cached_blob(&self, sha256: &str) -> Option<PathBuf> {
+        let path = self.blob_path(sha256);
+        let metadata = fs::metadata(&path).ok()?;
+        if !self.verify {
+            return Some(path);
+        }
+
+        let admitted = fs::read_to_string(self.admission_path(sha256)).ok();
+        if admitted.is_some() && admitted == admission_record(&metadata) {
+            return Some(path);
+        }
+
+        match file_sha256(&path) {
+            Ok(actual) if actual == sha256 => {
+                self.record_admission(sha256, &path);
+                Some(path)
+            }
+            _ => {
+                eprintln!("    Cached download {sha256} is corrupted, fetching it again");
+                let _ = self.remove_blob(sha256);
+                None
+            }
+        }
+    }
+
+    fn record_admission(&self, sha256: &str, path: &Path) {
+        if let Some(record) = fs::metadata(path).ok().as_ref().and_then(admission_record) {
+            let _ = fs::write(self.admission_path(sha256), record);
+        }
+    }
+
     /// Remove a blob from the cache (used when extraction fails due to corruption)
     pub fn remove_blob(&self, sha256: &str) -> io::Result<bool> {
+        let _ = fs::remove_file(self.admission_path(sha256));
         let path = self.blob_path(sha256);
         if path.exists() {
             fs::remove_file(&path)?;
@@ -40,6 +96,63 @@ impl BlobCache {
         }
     }
 
+    /// Blobs downloaded more than `max_age` ago, plus the oldest of the rest
+    /// while they add up to more than `max_bytes`, with their sizes. They
+    /// are removed from the cache unless `dry_run` is set.
+    pub fn prune(
+        &self,
+        max_age: Duration,
+        max_bytes: Option<u64>,
+        dry_run: bool,
+    ) -> io::Result<Vec<(String, u64)>> {
+        let cutoff = SystemTime::now().checked_sub(max_age).unwrap_or(UNIX_EPOCH);
+        let mut blobs = self.entries()?;
+        // Oldest first, so the size cap evicts least recently downloaded.
+        blobs.sort();
+
+        let mut kept: u64 = blobs.iter().map(|(_, _, len)| len).sum();
+        let mut pruned = Vec::new();
+        for (modified, sha256, len) in blobs {
+            let over_size = max_bytes.is_some_and(|max| kept > max);
+            if modified > cutoff && !over_size {
+                continue;
+            }
+            if !dry_run {
+                self.remove_blob(&sha256)?;
+            }
+            kept -= len;
+            pruned.push((sha256, len));
+        }
+        pruned.sort();
+        Ok(pruned)
+    }
+
+    /// Checksums and sizes of the cached blobs, sorted by checksum.
+    pub fn blobs(&self) -> io::Result<Vec<(String, u64)>> {
+        let mut blobs: Vec<_> = self
+            .entries()?
+            .into_iter()
+            .map(|(_, sha256, len)| (sha256, len))
+            .collect();
+        blobs.sort();
+        Ok(blobs)
+    }
+
+    /// `(mtime, sha256, size)` of each cached blob.
+    fn entries(&self) -> io::Result<Vec<(SystemTime, String, u64)>> {
+        let mut blobs = Vec::new();
+        for entry in fs::read_dir(&self.blobs_dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(sha256) = file_name.to_str().and_then(|n| n.strip_suffix(".tar.gz")) else {
+                continue;
+            };
+            let metadata = entry.metadata()?;
+            blobs.push((metadata.modified()?, sha256.to_string(), metadata.len()));
+        }
+        Ok(blobs)
+    }
+
     pub fn start_write(&self, sha256: &str) -> io::Result<BlobWriter> {
         let final_path = self.blob_path(sha256);
         // Use unique temp filename to avoid corruption from concurrent racing downloads
@@ -55,16 +168,77 @@ impl BlobCache {
             file,
             tmp_path,
             final_path,
+            admission_path: self.admission_path(sha256),
             committed: false,
+            resumable: false,
+            resumed_from: 0,
         })
     }
+
+    /// Like [`Self::start_write`], but into `<sha256>.tar.gz.part`, which
+    /// survives an interrupted download so the next attempt can pick up
+    /// where it stopped (see [`BlobWriter::resumed_from`]).
+    ///
+    /// The file is locked while written; if another process holds it, this
+    /// falls back to a fresh private temp file.
+    pub fn resume_write(&self, sha256: &str) -> io::Result<BlobWriter> {
+        let tmp_path = self.partial_path(sha256);
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&tmp_path)?;
+        if file.try_lock().is_err() {
+            return self.start_write(sha256);
+        }
+        let resumed_from = file.seek(SeekFrom::End(0))?;
+
+        Ok(BlobWriter {
+            file,
+            tmp_path,
+            final_path: self.blob_path(sha256),
+            admission_path: self.admission_path(sha256),
+            committed: false,
+            resumable: true,
+            resumed_from,
+        })
+    }
+
+    fn partial_path(&self, sha256: &str) -> PathBuf {
+        self.tmp_dir.join(format!("{sha256}.tar.gz.part"))
+    }
+}
+
+/// `<size> <mtime in ns>`, or `None` when the filesystem has no mtime.
+fn admission_record(metadata: &fs::Metadata) -> Option<String> {
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("{} {}", metadata.len(), mtime.as_nanos()))
+}
+
+fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
 pub struct BlobWriter {
     file: fs::File,
     tmp_path: PathBuf,
     final_path: PathBuf,
+    admission_path: PathBuf,
     committed: bool,
+    /// Keep the temp file when dropped uncommitted.
+    resumable: bool,
+    resumed_from: u64,
 }
 
 impl BlobWriter {
@@ -72,6 +246,35 @@ impl BlobWriter {
         self.file.seek(pos)
     }
 
+    /// Bytes an earlier, interrupted download already left in the file;
+    /// writes append after them.
+    pub fn resumed_from(&self) -> u64 {
+        self.resumed_from
+    }
+
+    /// Hasher fed with the bytes already in the file, to continue hashing
+    /// a resumed download.
+    pub fn prefix_hasher(&self) -> io::Result<Sha256> {
+        let mut file = fs::File::open(&self.tmp_path)?.take(self.resumed_from);
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher)?;
+        Ok(hasher)
+    }
+
+    /// Throw away what an earlier attempt left and start from zero.
+    pub fn restart(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.resumed_from = 0;
+        Ok(())
+    }
+
+    /// Drop the written bytes instead of keeping them for a resume, e.g.
+    /// because they failed verification.
+    pub fn discard(mut self) {
+        self.resumable = false;
+    }
+
     pub fn commit(mut self) -> Result<PathBuf, Error> {
         self.file.flush().map_err(|e| Error::NetworkFailure {
             message: format!("failed to flush blob: {e}"),
@@ -100,6 +303,16 @@ impl BlobWriter {
             }
         }
 
+        // The hash was checked while streaming, so record what the verified
+        // blob looks like on disk.
+        if let Some(record) = fs::metadata(&self.final_path)
+            .ok()
+            .as_ref()
+            .and_then(admission_record)
+        {
+            let _ = fs::write(&self.admission_path, record);
+        }
+
         self.committed = true;
         Ok(self.final_path.clone())
     }
@@ -117,7 +330,7 @@ impl Write for BlobWriter {
 
 impl Drop for BlobWriter {
     fn drop(&mut self) {
-        if !self.committed && self.tmp_path.exists() {
+        if !self.committed && !self.resumable && self.tmp_path.exists() {
             let _ = fs::remove_file(&self.tmp_path);
         }
     }
@@ -168,6 +381,66 @@ mod tests {
         assert!(!has_temp_files, "temp files for {sha} should be cleaned up");
     }
 
+    #[test]
+    fn resumable_writes_keep_partial_data_until_committed() {
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap();
+
+        {
+            let mut writer = cache.resume_write("abc123").unwrap();
+            assert_eq!(writer.resumed_from(), 0);
+            writer.write_all(b"partial").unwrap();
+        }
+
+        let mut writer = cache.resume_write("abc123").unwrap();
+        assert_eq!(writer.resumed_from(), 7);
+        let mut expected = Sha256::new();
+        expected.update(b"partial");
+        assert_eq!(
+            writer.prefix_hasher().unwrap().finalize(),
+            expected.finalize()
+        );
+        writer.write_all(b" data").unwrap();
+        let path = writer.commit().unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"partial data");
+
+        let writer = cache.resume_write("def456").unwrap();
+        writer.discard();
+        assert_eq!(cache.resume_write("def456").unwrap().resumed_from(), 0);
+    }
+
+    #[test]
+    fn verification_rehashes_blobs_changed_since_admission() {
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap().with_verification(true);
+        let sha = format!("{:x}", Sha256::digest(b"bottle"));
+
+        let mut writer = cache.start_write(&sha).unwrap();
+        writer.write_all(b"bottle").unwrap();
+        let path = writer.commit().unwrap();
+        assert_eq!(cache.cached_blob(&sha), Some(path.clone()));
+
+        // Same size and mtime as admitted: trusted without hashing.
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::write(&path, b"bott1e").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        assert!(cache.cached_blob(&sha).is_some());
+
+        // Anything else is hashed, and a mismatch evicts the blob.
+        fs::write(&path, b"bottle, truncated").unwrap();
+        assert_eq!(cache.cached_blob(&sha), None);
+        assert!(!cache.has_blob(&sha));
+
+        let unverified = BlobCache::new(tmp.path()).unwrap();
+        fs::write(&path, b"garbage").unwrap();
+        assert_eq!(unverified.cached_blob(&sha), Some(path));
+    }
+
     #[test]
     fn blob_path_uses_sha256() {
         let tmp = TempDir::new().unwrap();
@@ -202,4 +475,54 @@ mod tests {
         let removed = cache.remove_blob("nonexistent").unwrap();
         assert!(!removed);
     }
+
+    #[test]
+    fn prune_removes_only_blobs_older_than_max_age() {
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap();
+        for sha in ["old", "new"] {
+            let mut writer = cache.start_write(sha).unwrap();
+            writer.write_all(b"bottle").unwrap();
+            writer.commit().unwrap();
+        }
+        fs::File::options()
+            .write(true)
+            .open(cache.blob_path("old"))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(3 * 86_400))
+            .unwrap();
+
+        let max_age = Duration::from_secs(86_400);
+        assert_eq!(
+            cache.prune(max_age, None, true).unwrap(),
+            [("old".to_string(), 6)]
+        );
+        assert!(cache.has_blob("old"));
+
+        cache.prune(max_age, None, false).unwrap();
+        assert!(!cache.has_blob("old"));
+        assert!(cache.has_blob("new"));
+    }
+
+    #[test]
+    fn prune_evicts_oldest_blobs_over_the_size_cap() {
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap();
+        for (sha, age_days) in [("a", 3), ("b", 2), ("c", 1)] {
+            let mut writer = cache.start_write(sha).unwrap();
+            writer.write_all(b"bottle").unwrap();
+            writer.commit().unwrap();
+            fs::File::options()
+                .write(true)
+                .open(cache.blob_path(sha))
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(age_days * 86_400))
+                .unwrap();
+        }
+
+        let max_age = Duration::from_secs(30 * 86_400);
+        let pruned = cache.prune(max_age, Some(12), false).unwrap();
+        assert_eq!(pruned, [("a".to_string(), 6)]);
+        assert!(cache.has_blob("b") && cache.has_blob("c"));
+    }
 }
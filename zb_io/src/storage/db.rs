@@ -1,13 +1,87 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Transaction, params};
 
-use zb_core::Error;
+use zb_core::{ConflictedLink, Error};
 
 pub struct Database {
     conn: Connection,
 }
 
+/// An executed install plan, as recorded by [`Database::record_plan`].
+#[derive(Debug, Clone)]
+pub struct RecordedPlan {
+    pub hash: String,
+    pub platform: String,
+    /// JSON array of the plan's entries.
+    pub entries: String,
+    pub recorded_at: i64,
+}
+
+/// A metadata change recorded when a formula was upgraded.
+#[derive(Debug, Clone)]
+pub struct UpgradeRecord {
+    pub name: String,
+    pub from_version: String,
+    pub to_version: String,
+    pub change: String,
+    pub recorded_at: i64,
+}
+
+/// Bottles downloaded from one host since statistics were last reset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostStats {
+    pub host: String,
+    pub downloads: u64,
+    pub bytes: u64,
+    /// Total time spent transferring, for average throughput.
+    pub millis: u64,
+}
+
+/// What the blob cache and store saved since statistics were last reset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadStats {
+    pub hosts: Vec<HostStats>,
+    /// Bottles served from the blob cache instead of being downloaded.
+    pub cache_hits: u64,
+    pub cache_bytes: u64,
+    /// Bottles whose store entry already existed, so nothing was extracted.
+    pub store_hits: u64,
+    pub store_bytes: u64,
+}
+
+/// A recorded acceptance of a formula's license.
+#[derive(Debug, Clone)]
+pub struct LicenseAcceptance {
+    pub name: String,
+    pub version: String,
+    pub license: String,
+    /// How it was accepted: `--accept-licenses` or `prompt`.
+    pub accepted_via: String,
+    pub accepted_at: i64,
+}
+
+/// A tapped formula repository (see [`crate::tap::Taps`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapInfo {
+    /// `owner/repo`, without the `homebrew-` prefix of the repository.
+    pub name: String,
+    pub remote: String,
+    /// Formulas found in it when it was last cloned or updated.
+    pub formulas: usize,
+    pub tapped_at: i64,
+}
+
+/// A file of a keg as it was when installed, to tell later edits apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KegFileState {
+    /// Relative to the keg.
+    pub path: String,
+    pub size: u64,
+    /// Modification time, in nanoseconds since the Unix epoch.
+    pub modified: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct InstalledKeg {
     pub name: String,
@@ -18,19 +92,28 @@ pub struct InstalledKeg {
 
 impl Database {
     pub fn open(path: &Path) -> Result<Self, Error> {
-        let conn = Connection::open(path).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to open database: {e}"),
-        })?;
+        let conn = Connection::open(path)
+            .map_err(|e| Error::db("failed to open database").with_source(e))?;
 
         Self::init_schema(&conn)?;
 
         Ok(Self { conn })
     }
 
+    /// Open an existing database for lookups only, leaving the schema
+    /// alone; used to answer shell completion requests.
+    pub fn open_read_only(path: &Path) -> Result<Self, Error> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .map_err(|e| Error::db("failed to open database").with_source(e))?;
+        Ok(Self { conn })
+    }
+
     pub fn in_memory() -> Result<Self, Error> {
-        let conn = Connection::open_in_memory().map_err(|e| Error::StoreCorruption {
-            message: format!("failed to open in-memory database: {e}"),
-        })?;
+        let conn = Connection::open_in_memory()
+            .map_err(|e| Error::db("failed to open in-memory database").with_source(e))?;
 
         Self::init_schema(&conn)?;
 
@@ -52,6 +135,16 @@ impl Database {
                 refcount INTEGER NOT NULL DEFAULT 1
             );
 
+            CREATE TABLE IF NOT EXISTS store_released (
+                store_key TEXT PRIMARY KEY,
+                released_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS store_sources (
+                store_key TEXT PRIMARY KEY,
+                url TEXT NOT NULL
+            );
+
             CREATE TABLE IF NOT EXISTS keg_files (
                 name TEXT NOT NULL,
                 version TEXT NOT NULL,
@@ -59,11 +152,150 @@ impl Database {
                 target_path TEXT NOT NULL,
                 PRIMARY KEY (name, linked_path)
             );
+
+            CREATE TABLE IF NOT EXISTS link_exclusions (
+                name TEXT NOT NULL,
+                path TEXT NOT NULL,
+                PRIMARY KEY (name, path)
+            );
+
+            CREATE TABLE IF NOT EXISTS prefix_owners (
+                path TEXT PRIMARY KEY,
+                owner TEXT NOT NULL,
+                observed_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS lazy_shims (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                shim_path TEXT NOT NULL,
+                PRIMARY KEY (name, shim_path)
+            );
+
+            CREATE TABLE IF NOT EXISTS plans (
+                hash TEXT PRIMARY KEY,
+                platform TEXT NOT NULL,
+                entries TEXT NOT NULL,
+                recorded_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS formula_sets (
+                name TEXT NOT NULL,
+                formula TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                PRIMARY KEY (name, formula)
+            );
+
+            CREATE TABLE IF NOT EXISTS keg_metadata (
+                name TEXT PRIMARY KEY,
+                version TEXT NOT NULL,
+                metadata TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS keg_sizes (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                bytes INTEGER NOT NULL,
+                PRIMARY KEY (name, version)
+            );
+
+            CREATE TABLE IF NOT EXISTS keg_manifest (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                path TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                modified INTEGER NOT NULL,
+                PRIMARY KEY (name, version, path)
+            );
+
+            CREATE TABLE IF NOT EXISTS download_stats (
+                host TEXT PRIMARY KEY,
+                downloads INTEGER NOT NULL,
+                bytes INTEGER NOT NULL,
+                millis INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS cache_stats (
+                kind TEXT PRIMARY KEY,
+                hits INTEGER NOT NULL,
+                bytes INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS pinned (
+                name TEXT PRIMARY KEY,
+                pinned_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS install_queue (
+                name TEXT PRIMARY KEY,
+                queued_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS keg_dependencies (
+                name TEXT NOT NULL,
+                dependency TEXT NOT NULL,
+                PRIMARY KEY (name, dependency)
+            );
+
+            CREATE TABLE IF NOT EXISTS upgrade_history (
+                name TEXT NOT NULL,
+                from_version TEXT NOT NULL,
+                to_version TEXT NOT NULL,
+                change TEXT NOT NULL,
+                recorded_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS install_notes (
+                name TEXT PRIMARY KEY,
+                note TEXT NOT NULL,
+                noted_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS advisories (
+                name TEXT PRIMARY KEY,
+                version TEXT NOT NULL,
+                advisories TEXT NOT NULL,
+                fetched_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS license_acceptances (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                license TEXT NOT NULL,
+                accepted_via TEXT NOT NULL,
+                accepted_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS retained_kegs (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                store_key TEXT NOT NULL,
+                retained_at INTEGER NOT NULL,
+                PRIMARY KEY (name, version)
+            );
+
+            CREATE TABLE IF NOT EXISTS taps (
+                name TEXT PRIMARY KEY,
+                remote TEXT NOT NULL,
+                tapped_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS tap_formulas (
+                tap TEXT NOT NULL,
+                name TEXT NOT NULL,
+                PRIMARY KEY (tap, name)
+            );
+            CREATE INDEX IF NOT EXISTS tap_formulas_by_name ON tap_formulas (name);
+
+            CREATE TABLE IF NOT EXISTS cask_artifacts (
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                path TEXT NOT NULL,
+                PRIMARY KEY (name, kind, path)
+            );
             ",
         )
-        .map_err(|e| Error::StoreCorruption {
-            message: format!("failed to initialize schema: {e}"),
-        })?;
+        .map_err(|e| Error::db("failed to initialize schema").with_source(e))?;
 
         Ok(())
     }
@@ -72,9 +304,7 @@ impl Database {
         let tx = self
             .conn
             .transaction()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to start transaction: {e}"),
-            })?;
+            .map_err(|e| Error::db("failed to start transaction").with_source(e))?;
 
         Ok(InstallTransaction { tx })
     }
@@ -102,9 +332,7 @@ impl Database {
             .prepare(
                 "SELECT name, version, store_key, installed_at FROM installed_kegs ORDER BY name",
             )
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to prepare statement: {e}"),
-            })?;
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
 
         let kegs = stmt
             .query_map([], |row| {
@@ -115,57 +343,1088 @@ impl Database {
                     installed_at: row.get(3)?,
                 })
             })
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to query installed kegs: {e}"),
-            })?
+            .map_err(|e| Error::db("failed to query installed kegs").with_source(e))?
             .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to collect results: {e}"),
-            })?;
+            .map_err(|e| Error::db("failed to collect results").with_source(e))?;
 
         Ok(kegs)
     }
 
-    pub fn get_store_refcount(&self, store_key: &str) -> i64 {
+    /// Whether any files from the keg are linked into the prefix.
+    ///
+    /// Keg-only formulas (and kegs installed with `--no-link`) are only reachable
+    /// through their `opt` path.
+    pub fn is_linked(&self, name: &str) -> bool {
         self.conn
             .query_row(
-                "SELECT refcount FROM store_refs WHERE store_key = ?1",
-                params![store_key],
+                "SELECT 1 FROM keg_files WHERE name = ?1 LIMIT 1",
+                params![name],
+                |_| Ok(()),
+            )
+            .is_ok()
+    }
+
+    /// `(linked_path, target_path)` for every symlink recorded for the keg.
+    pub fn linked_files(&self, name: &str) -> Result<Vec<(String, String)>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT linked_path, target_path FROM keg_files WHERE name = ?1 ORDER BY linked_path",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map(params![name], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| Error::db("failed to query linked files").with_source(e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Replace the prefix paths the keg's last link left out because of
+    /// `link_exclude` patterns, forgetting any links recorded under them.
+    pub fn record_link_exclusions(&self, name: &str, paths: &[PathBuf]) -> Result<(), Error> {
+        self.conn
+            .execute("DELETE FROM link_exclusions WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to clear link exclusions").with_source(e))?;
+        for path in paths {
+            let path = path.to_string_lossy();
+            self.conn
+                .execute(
+                    "INSERT OR REPLACE INTO link_exclusions (name, path) VALUES (?1, ?2)",
+                    params![name, path],
+                )
+                .map_err(|e| Error::db("failed to record link exclusion").with_source(e))?;
+            self.conn
+                .execute(
+                    "DELETE FROM keg_files
+                     WHERE name = ?1 AND (linked_path = ?2 OR substr(linked_path, 1, length(?2) + 1) = ?2 || '/')",
+                    params![name, path],
+                )
+                .map_err(|e| Error::db("failed to remove keg files records").with_source(e))?;
+        }
+        Ok(())
+    }
+
+    /// Prefix paths the keg's last link left out, sorted.
+    pub fn link_exclusions(&self, name: &str) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path FROM link_exclusions WHERE name = ?1 ORDER BY path")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map(params![name], |row| row.get(0))
+            .map_err(|e| Error::db("failed to query link exclusions").with_source(e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Remember who owned files found in the way while linking; `None` is
+    /// stored as `unknown`.
+    pub fn record_prefix_owners(&self, conflicts: &[ConflictedLink]) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        for conflict in conflicts {
+            self.conn
+                .execute(
+                    "INSERT OR REPLACE INTO prefix_owners (path, owner, observed_at)
+                     VALUES (?1, ?2, ?3)",
+                    params![
+                        conflict.path.to_string_lossy(),
+                        conflict.owned_by.as_deref().unwrap_or("unknown"),
+                        now
+                    ],
+                )
+                .map_err(|e| Error::db("failed to record prefix owner").with_source(e))?;
+        }
+        Ok(())
+    }
+
+    /// Owner of a path in the prefix: the installed formula that linked it,
+    /// otherwise whatever was observed there during an earlier link attempt.
+    pub fn prefix_owner(&self, path: &Path) -> Option<String> {
+        let path = path.to_string_lossy();
+        self.conn
+            .query_row(
+                "SELECT name FROM keg_files WHERE linked_path = ?1
+                 UNION ALL
+                 SELECT owner FROM prefix_owners WHERE path = ?1
+                 LIMIT 1",
+                params![path],
                 |row| row.get(0),
             )
-            .unwrap_or(0)
+            .ok()
+    }
+
+    /// Rewrite every recorded path under `from` to the same path under `to`,
+    /// after the prefix has been moved.
+    pub fn relocate_paths(&mut self, from: &Path, to: &Path) -> Result<usize, Error> {
+        let from = from.to_string_lossy();
+        let to = to.to_string_lossy();
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| Error::db("failed to start transaction").with_source(e))?;
+
+        let mut rewritten = 0;
+        for (table, column) in [
+            ("keg_files", "linked_path"),
+            ("keg_files", "target_path"),
+            ("lazy_shims", "shim_path"),
+            ("link_exclusions", "path"),
+            ("prefix_owners", "path"),
+        ] {
+            rewritten += tx
+                .execute(
+                    &format!(
+                        "UPDATE OR REPLACE {table}
+                         SET {column} = ?2 || substr({column}, length(?1) + 1)
+                         WHERE substr({column}, 1, length(?1) + 1) = ?1 || '/'"
+                    ),
+                    params![from, to],
+                )
+                .map_err(|e| Error::db("failed to relocate recorded paths").with_source(e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| Error::db("failed to commit transaction").with_source(e))?;
+        Ok(rewritten)
+    }
+
+    /// Superseded versions of `name` kept for rollback, newest first.
+    pub fn retained_kegs(&self, name: &str) -> Result<Vec<InstalledKeg>, Error> {
+        self.query_retained(
+            "SELECT name, version, store_key, retained_at FROM retained_kegs
+             WHERE name = ?1 ORDER BY retained_at DESC, rowid DESC",
+            params![name],
+        )
+    }
+
+    /// Every retained keg, grouped by formula and newest first.
+    pub fn list_retained(&self) -> Result<Vec<InstalledKeg>, Error> {
+        self.query_retained(
+            "SELECT name, version, store_key, retained_at FROM retained_kegs
+             ORDER BY name, retained_at DESC, rowid DESC",
+            [],
+        )
+    }
+
+    fn query_retained(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<InstalledKeg>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(sql)
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map(params, |row| {
+            Ok(InstalledKeg {
+                name: row.get(0)?,
+                version: row.get(1)?,
+                store_key: row.get(2)?,
+                installed_at: row.get(3)?,
+            })
+        })
+        .map_err(|e| Error::db("failed to query retained kegs").with_source(e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Launcher shims of a keg that hasn't been materialized yet. Empty once
+    /// the keg is in the Cellar.
+    pub fn lazy_shims(&self, name: &str) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT shim_path FROM lazy_shims WHERE name = ?1 ORDER BY shim_path")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map(params![name], |row| row.get(0))
+            .map_err(|e| Error::db("failed to query lazy shims").with_source(e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    pub fn record_plan(&self, hash: &str, platform: &str, entries: &str) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        self.conn
+            .execute(
+                "INSERT INTO plans (hash, platform, entries, recorded_at) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(hash) DO UPDATE SET recorded_at = excluded.recorded_at",
+                params![hash, platform, entries, now],
+            )
+            .map_err(|e| Error::db("failed to record plan").with_source(e))?;
+
+        Ok(())
+    }
+
+    /// Recorded plans whose hash starts with `prefix`.
+    pub fn find_plans(&self, prefix: &str) -> Result<Vec<RecordedPlan>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT hash, platform, entries, recorded_at FROM plans
+                 WHERE substr(hash, 1, length(?1)) = ?1 ORDER BY hash",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map(params![prefix], |row| {
+            Ok(RecordedPlan {
+                hash: row.get(0)?,
+                platform: row.get(1)?,
+                entries: row.get(2)?,
+                recorded_at: row.get(3)?,
+            })
+        })
+        .map_err(|e| Error::db("failed to query plans").with_source(e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Remember the formula metadata (JSON) `name` was installed with.
+    pub fn set_keg_metadata(&self, name: &str, version: &str, metadata: &str) -> Result<(), Error> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO keg_metadata (name, version, metadata) VALUES (?1, ?2, ?3)",
+                params![name, version, metadata],
+            )
+            .map_err(|e| Error::db("failed to record keg metadata").with_source(e))?;
+
+        Ok(())
+    }
+
+    /// `(version, metadata JSON)` recorded for the installed `name`.
+    pub fn keg_metadata(&self, name: &str) -> Option<(String, String)> {
+        self.conn
+            .query_row(
+                "SELECT version, metadata FROM keg_metadata WHERE name = ?1",
+                params![name],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .ok()
+    }
+
+    /// Remember the disk space the `version` keg of `name` takes.
+    pub fn set_keg_size(&self, name: &str, version: &str, bytes: u64) -> Result<(), Error> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO keg_sizes (name, version, bytes) VALUES (?1, ?2, ?3)",
+                params![name, version, bytes as i64],
+            )
+            .map_err(|e| Error::db("failed to record keg size").with_source(e))?;
+
+        Ok(())
+    }
+
+    /// The recorded disk space of the `version` keg of `name`.
+    pub fn keg_size(&self, name: &str, version: &str) -> Option<u64> {
+        self.conn
+            .query_row(
+                "SELECT bytes FROM keg_sizes WHERE name = ?1 AND version = ?2",
+                params![name, version],
+                |row| row.get::<_, i64>(0),
+            )
+            .ok()
+            .map(|bytes| bytes as u64)
+    }
+
+    /// Replace the recorded files of the `version` keg of `name`.
+    pub fn set_keg_manifest(
+        &self,
+        name: &str,
+        version: &str,
+        files: &[KegFileState],
+    ) -> Result<(), Error> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| Error::db("failed to start transaction").with_source(e))?;
+        tx.execute(
+            "DELETE FROM keg_manifest WHERE name = ?1 AND version = ?2",
+            params![name, version],
+        )
+        .map_err(|e| Error::db("failed to clear keg manifest").with_source(e))?;
+        for file in files {
+            Self::insert_keg_file(&tx, name, version, file)?;
+        }
+        tx.commit()
+            .map_err(|e| Error::db("failed to commit transaction").with_source(e))
+    }
+
+    /// Add or update one file in the recorded files of a keg, e.g. one zb
+    /// wrote into it after the install.
+    pub fn record_keg_file(
+        &self,
+        name: &str,
+        version: &str,
+        file: &KegFileState,
+    ) -> Result<(), Error> {
+        Self::insert_keg_file(&self.conn, name, version, file)
+    }
+
+    fn insert_keg_file(
+        conn: &Connection,
+        name: &str,
+        version: &str,
+        file: &KegFileState,
+    ) -> Result<(), Error> {
+        conn.execute(
+            "INSERT OR REPLACE INTO keg_manifest (name, version, path, size, modified)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![name, version, file.path, file.size as i64, file.modified],
+        )
+        .map_err(|e| Error::db("failed to record keg file").with_source(e))?;
+        Ok(())
+    }
+
+    /// The recorded files of the `version` keg of `name`, empty when none
+    /// were recorded.
+    pub fn keg_manifest(&self, name: &str, version: &str) -> Result<Vec<KegFileState>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT path, size, modified FROM keg_manifest
+                 WHERE name = ?1 AND version = ?2 ORDER BY path",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+        stmt.query_map(params![name, version], |row| {
+            Ok(KegFileState {
+                path: row.get(0)?,
+                size: row.get::<_, i64>(1)? as u64,
+                modified: row.get(2)?,
+            })
+        })
+        .map_err(|e| Error::db("failed to query keg manifest").with_source(e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Replace the recorded runtime dependencies of `name`.
+    pub fn set_dependencies(&self, name: &str, dependencies: &[String]) -> Result<(), Error> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| Error::db("failed to start transaction").with_source(e))?;
+        tx.execute(
+            "DELETE FROM keg_dependencies WHERE name = ?1",
+            params![name],
+        )
+        .map_err(|e| Error::db("failed to clear dependencies").with_source(e))?;
+        for dependency in dependencies {
+            tx.execute(
+                "INSERT OR IGNORE INTO keg_dependencies (name, dependency) VALUES (?1, ?2)",
+                params![name, dependency],
+            )
+            .map_err(|e| Error::db("failed to record dependency").with_source(e))?;
+        }
+        tx.commit()
+            .map_err(|e| Error::db("failed to commit transaction").with_source(e))
+    }
+
+    /// The recorded runtime dependencies of `name`, sorted.
+    pub fn dependencies(&self, name: &str) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT dependency FROM keg_dependencies WHERE name = ?1 ORDER BY dependency")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+        let rows = stmt
+            .query_map(params![name], |row| row.get::<_, String>(0))
+            .map_err(|e| Error::db("failed to query dependencies").with_source(e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Installed formulas that depend on any of `names`, sorted.
+    pub fn dependents(&self, names: &[&str]) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT DISTINCT d.name FROM keg_dependencies d
+                 JOIN installed_kegs k ON k.name = d.name
+                 WHERE d.dependency = ?1 ORDER BY d.name",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        let mut dependents = Vec::new();
+        for name in names {
+            let rows = stmt
+                .query_map(params![name], |row| row.get::<_, String>(0))
+                .map_err(|e| Error::db("failed to query dependents").with_source(e))?;
+            for row in rows {
+                let dependent =
+                    row.map_err(|e| Error::db("failed to collect results").with_source(e))?;
+                if !dependents.contains(&dependent) {
+                    dependents.push(dependent);
+                }
+            }
+        }
+        dependents.sort();
+        Ok(dependents)
+    }
+
+    pub fn record_upgrade_change(
+        &self,
+        name: &str,
+        from_version: &str,
+        to_version: &str,
+        change: &str,
+    ) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        self.conn
+            .execute(
+                "INSERT INTO upgrade_history (name, from_version, to_version, change, recorded_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![name, from_version, to_version, change, now],
+            )
+            .map_err(|e| Error::db("failed to record upgrade change").with_source(e))?;
+
+        Ok(())
+    }
+
+    /// Metadata changes seen on past upgrades of `name`, oldest first.
+    pub fn upgrade_history(&self, name: &str) -> Result<Vec<UpgradeRecord>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name, from_version, to_version, change, recorded_at FROM upgrade_history
+                 WHERE name = ?1 ORDER BY recorded_at, rowid",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map(params![name], |row| {
+            Ok(UpgradeRecord {
+                name: row.get(0)?,
+                from_version: row.get(1)?,
+                to_version: row.get(2)?,
+                change: row.get(3)?,
+                recorded_at: row.get(4)?,
+            })
+        })
+        .map_err(|e| Error::db("failed to query upgrade history").with_source(e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// The `limit` most recently recorded upgrade changes across all
+    /// formulas, newest first.
+    pub fn recent_upgrade_history(&self, limit: usize) -> Result<Vec<UpgradeRecord>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name, from_version, to_version, change, recorded_at FROM upgrade_history
+                 ORDER BY recorded_at DESC, rowid DESC LIMIT ?1",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map(params![limit.min(i64::MAX as usize) as i64], |row| {
+            Ok(UpgradeRecord {
+                name: row.get(0)?,
+                from_version: row.get(1)?,
+                to_version: row.get(2)?,
+                change: row.get(3)?,
+                recorded_at: row.get(4)?,
+            })
+        })
+        .map_err(|e| Error::db("failed to query upgrade history").with_source(e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Attach a free-form note explaining why `name` is installed,
+    /// replacing any earlier one.
+    pub fn set_install_note(&self, name: &str, note: &str) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO install_notes (name, note, noted_at) VALUES (?1, ?2, ?3)",
+                params![name, note, now],
+            )
+            .map_err(|e| Error::db("failed to record install note").with_source(e))?;
+        Ok(())
+    }
+
+    pub fn install_note(&self, name: &str) -> Option<String> {
+        self.conn
+            .query_row(
+                "SELECT note FROM install_notes WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .ok()
+    }
+
+    /// Replace the advisory snapshot with `entries` of
+    /// `(name, version, advisories JSON)`, all fetched at `fetched_at`.
+    pub fn replace_advisories(
+        &mut self,
+        entries: &[(String, String, String)],
+        fetched_at: i64,
+    ) -> Result<(), Error> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| Error::db("failed to start transaction").with_source(e))?;
+        tx.execute("DELETE FROM advisories", [])
+            .map_err(|e| Error::db("failed to clear advisories").with_source(e))?;
+        for (name, version, advisories) in entries {
+            tx.execute(
+                "INSERT INTO advisories (name, version, advisories, fetched_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![name, version, advisories, fetched_at],
+            )
+            .map_err(|e| Error::db("failed to record advisories").with_source(e))?;
+        }
+        tx.commit()
+            .map_err(|e| Error::db("failed to commit transaction").with_source(e))
+    }
+
+    /// The version checked, its advisories JSON and when they were fetched.
+    pub fn advisories(&self, name: &str) -> Option<(String, String, i64)> {
+        self.conn
+            .query_row(
+                "SELECT version, advisories, fetched_at FROM advisories WHERE name = ?1",
+                params![name],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .ok()
+    }
+
+    pub fn record_license_acceptance(
+        &self,
+        name: &str,
+        version: &str,
+        license: &str,
+        accepted_via: &str,
+    ) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        self.conn
+            .execute(
+                "INSERT INTO license_acceptances (name, version, license, accepted_via, accepted_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![name, version, license, accepted_via, now],
+            )
+            .map_err(|e| Error::db("failed to record license acceptance").with_source(e))?;
+
+        Ok(())
+    }
+
+    /// Whether `license` was accepted for any version of `name`.
+    pub fn has_accepted_license(&self, name: &str, license: &str) -> bool {
+        self.conn
+            .query_row(
+                "SELECT 1 FROM license_acceptances WHERE name = ?1 AND license = ?2 LIMIT 1",
+                params![name, license],
+                |_| Ok(()),
+            )
+            .is_ok()
+    }
+
+    /// License acceptances recorded for `name`, oldest first.
+    pub fn license_acceptances(&self, name: &str) -> Result<Vec<LicenseAcceptance>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name, version, license, accepted_via, accepted_at FROM license_acceptances
+                 WHERE name = ?1 ORDER BY accepted_at, rowid",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map(params![name], |row| {
+            Ok(LicenseAcceptance {
+                name: row.get(0)?,
+                version: row.get(1)?,
+                license: row.get(2)?,
+                accepted_via: row.get(3)?,
+                accepted_at: row.get(4)?,
+            })
+        })
+        .map_err(|e| Error::db("failed to query license acceptances").with_source(e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Replace the members of the set `name`, keeping their order.
+    pub fn save_set(&mut self, name: &str, formulas: &[String]) -> Result<(), Error> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| Error::db("failed to start transaction").with_source(e))?;
+
+        tx.execute("DELETE FROM formula_sets WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to clear set").with_source(e))?;
+        for (position, formula) in formulas.iter().enumerate() {
+            tx.execute(
+                "INSERT OR IGNORE INTO formula_sets (name, formula, position) VALUES (?1, ?2, ?3)",
+                params![name, formula, position as i64],
+            )
+            .map_err(|e| Error::db("failed to record set member").with_source(e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| Error::db("failed to commit transaction").with_source(e))
+    }
+
+    /// Members of the set `name`, or `None` if there is no such set.
+    pub fn get_set(&self, name: &str) -> Result<Option<Vec<String>>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT formula FROM formula_sets WHERE name = ?1 ORDER BY position")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        let formulas = stmt
+            .query_map(params![name], |row| row.get(0))
+            .map_err(|e| Error::db("failed to query set").with_source(e))?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))?;
+
+        Ok((!formulas.is_empty()).then_some(formulas))
+    }
+
+    /// Every set with its members, sorted by set name.
+    pub fn list_sets(&self) -> Result<Vec<(String, Vec<String>)>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, formula FROM formula_sets ORDER BY name, position")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|e| Error::db("failed to query sets").with_source(e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))?;
+
+        let mut sets: Vec<(String, Vec<String>)> = Vec::new();
+        for (name, formula) in rows {
+            match sets.last_mut() {
+                Some((last, members)) if *last == name => members.push(formula),
+                _ => sets.push((name, vec![formula])),
+            }
+        }
+        Ok(sets)
+    }
+
+    /// Returns whether the set existed.
+    pub fn delete_set(&self, name: &str) -> Result<bool, Error> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM formula_sets WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to delete set").with_source(e))?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Record the tap `name` and replace the formulas indexed for it.
+    pub fn record_tap(
+        &mut self,
+        name: &str,
+        remote: &str,
+        formulas: &[String],
+    ) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| Error::db("failed to start transaction").with_source(e))?;
+
+        tx.execute(
+            "INSERT INTO taps (name, remote, tapped_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(name) DO UPDATE SET remote = excluded.remote",
+            params![name, remote, now],
+        )
+        .map_err(|e| Error::db("failed to record tap").with_source(e))?;
+        tx.execute("DELETE FROM tap_formulas WHERE tap = ?1", params![name])
+            .map_err(|e| Error::db("failed to clear tap formulas").with_source(e))?;
+        for formula in formulas {
+            tx.execute(
+                "INSERT OR IGNORE INTO tap_formulas (tap, name) VALUES (?1, ?2)",
+                params![name, formula],
+            )
+            .map_err(|e| Error::db("failed to record tap formula").with_source(e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| Error::db("failed to commit transaction").with_source(e))
+    }
+
+    /// Forget the tap `name` and its formulas. Returns whether it was tapped.
+    pub fn remove_tap(&self, name: &str) -> Result<bool, Error> {
+        self.conn
+            .execute("DELETE FROM tap_formulas WHERE tap = ?1", params![name])
+            .map_err(|e| Error::db("failed to remove tap formulas").with_source(e))?;
+        let deleted = self
+            .conn
+            .execute("DELETE FROM taps WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to remove tap").with_source(e))?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Every tap, sorted by name.
+    pub fn list_taps(&self) -> Result<Vec<TapInfo>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT t.name, t.remote, t.tapped_at, COUNT(f.name) FROM taps t
+                 LEFT JOIN tap_formulas f ON f.tap = t.name
+                 GROUP BY t.name ORDER BY t.name",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map([], |row| {
+            Ok(TapInfo {
+                name: row.get(0)?,
+                remote: row.get(1)?,
+                tapped_at: row.get(2)?,
+                formulas: row.get::<_, i64>(3)? as usize,
+            })
+        })
+        .map_err(|e| Error::db("failed to query taps").with_source(e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Formulas indexed for the tap `name`, sorted.
+    pub fn tap_formulas(&self, name: &str) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM tap_formulas WHERE tap = ?1 ORDER BY name")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map(params![name], |row| row.get(0))
+            .map_err(|e| Error::db("failed to query tap formulas").with_source(e))?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Taps that have a formula called `formula`, sorted.
+    pub fn taps_with_formula(&self, formula: &str) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tap FROM tap_formulas WHERE name = ?1 ORDER BY tap")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map(params![formula], |row| row.get(0))
+            .map_err(|e| Error::db("failed to query tap formulas").with_source(e))?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// What installing the cask `name` put outside its keg, as
+    /// `(kind, path)` pairs in the order they were recorded.
+    pub fn cask_artifacts(&self, name: &str) -> Result<Vec<(String, String)>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT kind, path FROM cask_artifacts WHERE name = ?1 ORDER BY rowid")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map(params![name], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| Error::db("failed to query cask artifacts").with_source(e))?
+            .collect::<Result<Vec<(String, String)>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Count a bottle of `bytes` downloaded from `host` in `millis`.
+    pub fn record_download(&self, host: &str, bytes: u64, millis: u64) -> Result<(), Error> {
+        self.conn
+            .execute(
+                "INSERT INTO download_stats (host, downloads, bytes, millis) VALUES (?1, 1, ?2, ?3)
+                 ON CONFLICT(host) DO UPDATE SET
+                     downloads = downloads + 1,
+                     bytes = bytes + excluded.bytes,
+                     millis = millis + excluded.millis",
+                params![host, bytes as i64, millis as i64],
+            )
+            .map_err(|e| Error::db("failed to record download").with_source(e))?;
+
+        Ok(())
+    }
+
+    /// Count a bottle of `bytes` reused from the blob cache (`kind` "blob") or
+    /// the store (`kind` "store").
+    pub fn record_cache_hit(&self, kind: &str, bytes: u64) -> Result<(), Error> {
+        self.conn
+            .execute(
+                "INSERT INTO cache_stats (kind, hits, bytes) VALUES (?1, 1, ?2)
+                 ON CONFLICT(kind) DO UPDATE SET
+                     hits = hits + 1,
+                     bytes = bytes + excluded.bytes",
+                params![kind, bytes as i64],
+            )
+            .map_err(|e| Error::db("failed to record cache hit").with_source(e))?;
+
+        Ok(())
+    }
+
+    /// Download and cache statistics, hosts sorted by bytes downloaded.
+    pub fn download_stats(&self) -> Result<DownloadStats, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT host, downloads, bytes, millis FROM download_stats
+                 ORDER BY bytes DESC, host",
+            )
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        let hosts = stmt
+            .query_map([], |row| {
+                Ok(HostStats {
+                    host: row.get(0)?,
+                    downloads: row.get::<_, i64>(1)? as u64,
+                    bytes: row.get::<_, i64>(2)? as u64,
+                    millis: row.get::<_, i64>(3)? as u64,
+                })
+            })
+            .map_err(|e| Error::db("failed to query download stats").with_source(e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))?;
+
+        let hits = |kind: &str| {
+            self.conn
+                .query_row(
+                    "SELECT hits, bytes FROM cache_stats WHERE kind = ?1",
+                    params![kind],
+                    |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+                )
+                .unwrap_or((0, 0))
+        };
+        let (cache_hits, cache_bytes) = hits("blob");
+        let (store_hits, store_bytes) = hits("store");
+
+        Ok(DownloadStats {
+            hosts,
+            cache_hits,
+            cache_bytes,
+            store_hits,
+            store_bytes,
+        })
+    }
+
+    pub fn reset_download_stats(&self) -> Result<(), Error> {
+        self.conn
+            .execute_batch("DELETE FROM download_stats; DELETE FROM cache_stats;")
+            .map_err(|e| Error::db("failed to reset download stats").with_source(e))
+    }
+
+    /// Pin `name` at its installed version. Returns whether it wasn't pinned yet.
+    pub fn pin(&self, name: &str) -> Result<bool, Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO pinned (name, pinned_at) VALUES (?1, ?2)",
+                params![name, now],
+            )
+            .map_err(|e| Error::db("failed to pin formula").with_source(e))?;
+
+        Ok(inserted > 0)
+    }
+
+    /// Returns whether `name` was pinned.
+    pub fn unpin(&self, name: &str) -> Result<bool, Error> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM pinned WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to unpin formula").with_source(e))?;
+
+        Ok(deleted > 0)
+    }
+
+    pub fn is_pinned(&self, name: &str) -> bool {
+        self.conn
+            .query_row(
+                "SELECT 1 FROM pinned WHERE name = ?1",
+                params![name],
+                |_| Ok(()),
+            )
+            .is_ok()
+    }
+
+    /// Pinned formula names, sorted.
+    pub fn pinned(&self) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM pinned ORDER BY name")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map([], |row| row.get(0))
+            .map_err(|e| Error::db("failed to query pinned formulas").with_source(e))?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Queue `name` for a later `zb queue run`. Returns whether it was not
+    /// queued already.
+    pub fn queue_install(&self, name: &str) -> Result<bool, Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO install_queue (name, queued_at) VALUES (?1, ?2)",
+                params![name, now],
+            )
+            .map_err(|e| Error::db("failed to queue install").with_source(e))?;
+
+        Ok(inserted > 0)
+    }
+
+    /// Queued formula names, in the order they were added.
+    pub fn queued_installs(&self) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM install_queue ORDER BY queued_at, rowid")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map([], |row| row.get(0))
+            .map_err(|e| Error::db("failed to query install queue").with_source(e))?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Drop `names` from the install queue, returning how many were queued.
+    pub fn dequeue_installs(&self, names: &[String]) -> Result<usize, Error> {
+        let mut removed = 0;
+        for name in names {
+            removed += self
+                .conn
+                .execute("DELETE FROM install_queue WHERE name = ?1", params![name])
+                .map_err(|e| Error::db("failed to dequeue install").with_source(e))?;
+        }
+        Ok(removed)
+    }
+
+    pub fn get_store_refcount(&self, store_key: &str) -> i64 {
+        self.conn
+            .query_row(
+                "SELECT refcount FROM store_refs WHERE store_key = ?1",
+                params![store_key],
+                |row| row.get(0),
+            )
+            .unwrap_or(0)
+    }
+
+    /// Every `store_refs` row as `(store_key, refcount)`.
+    pub fn store_refcounts(&self) -> Result<Vec<(String, i64)>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT store_key, refcount FROM store_refs ORDER BY store_key")
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
+
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| Error::db("failed to query store refs").with_source(e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::db("failed to collect results").with_source(e))
+    }
+
+    /// Overwrite (or create) the refcount for a store entry.
+    pub fn set_store_refcount(&self, store_key: &str, refcount: i64) -> Result<(), Error> {
+        self.conn
+            .execute(
+                "INSERT INTO store_refs (store_key, refcount) VALUES (?1, ?2)
+                 ON CONFLICT(store_key) DO UPDATE SET refcount = excluded.refcount",
+                params![store_key, refcount],
+            )
+            .map_err(|e| Error::db("failed to set store refcount").with_source(e))?;
+
+        Ok(())
     }
 
     pub fn get_unreferenced_store_keys(&self) -> Result<Vec<String>, Error> {
         let mut stmt = self
             .conn
             .prepare("SELECT store_key FROM store_refs WHERE refcount <= 0")
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to prepare statement: {e}"),
-            })?;
+            .map_err(|e| Error::db("failed to prepare statement").with_source(e))?;
 
         let keys = stmt
             .query_map([], |row| row.get(0))
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to query unreferenced keys: {e}"),
-            })?
+            .map_err(|e| Error::db("failed to query unreferenced keys").with_source(e))?
             .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to collect results: {e}"),
-            })?;
+            .map_err(|e| Error::db("failed to collect results").with_source(e))?;
 
         Ok(keys)
     }
 
+    /// Unix time `store_key` lost its last reference, if that was recorded.
+    pub fn store_released_at(&self, store_key: &str) -> Option<i64> {
+        self.conn
+            .query_row(
+                "SELECT released_at FROM store_released WHERE store_key = ?1",
+                params![store_key],
+                |row| row.get(0),
+            )
+            .ok()
+    }
+
+    /// Remember which URL served the bottle for a store entry.
+    pub fn record_store_source(&self, store_key: &str, url: &str) -> Result<(), Error> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO store_sources (store_key, url) VALUES (?1, ?2)",
+                params![store_key, url],
+            )
+            .map_err(|e| Error::db("failed to record bottle source").with_source(e))?;
+        Ok(())
+    }
+
+    pub fn store_source(&self, store_key: &str) -> Option<String> {
+        self.conn
+            .query_row(
+                "SELECT url FROM store_sources WHERE store_key = ?1",
+                params![store_key],
+                |row| row.get(0),
+            )
+            .ok()
+    }
+
     pub fn delete_store_ref(&self, store_key: &str) -> Result<(), Error> {
         self.conn
             .execute(
                 "DELETE FROM store_refs WHERE store_key = ?1",
                 params![store_key],
             )
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to delete store ref: {e}"),
-            })?;
+            .and_then(|_| {
+                self.conn.execute(
+                    "DELETE FROM store_released WHERE store_key = ?1",
+                    params![store_key],
+                )
+            })
+            .and_then(|_| {
+                self.conn.execute(
+                    "DELETE FROM store_sources WHERE store_key = ?1",
+                    params![store_key],
+                )
+            })
+            .map_err(|e| Error::db("failed to delete store ref").with_source(e))?;
         Ok(())
     }
 }
@@ -189,9 +1448,7 @@ impl<'a> InstallTransaction<'a> {
                 |row| row.get(0),
             )
             .optional()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to query previous store key: {e}"),
-            })?;
+            .map_err(|e| Error::db("failed to query previous store key").with_source(e))?;
 
         self.tx
             .execute(
@@ -203,22 +1460,13 @@ impl<'a> InstallTransaction<'a> {
                      installed_at = excluded.installed_at",
                 params![name, version, store_key, now],
             )
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to record install: {e}"),
-            })?;
+            .map_err(|e| Error::db("failed to record install").with_source(e))?;
 
         match previous_store_key.as_deref() {
             Some(previous) if previous == store_key => {}
             other => {
                 if let Some(previous) = other {
-                    self.tx
-                        .execute(
-                            "UPDATE store_refs SET refcount = refcount - 1 WHERE store_key = ?1",
-                            params![previous],
-                        )
-                        .map_err(|e| Error::StoreCorruption {
-                            message: format!("failed to decrement previous store ref: {e}"),
-                        })?;
+                    self.release_store_ref(previous)?;
                 }
 
                 self.tx
@@ -227,15 +1475,63 @@ impl<'a> InstallTransaction<'a> {
                          ON CONFLICT(store_key) DO UPDATE SET refcount = refcount + 1",
                         params![store_key],
                     )
-                    .map_err(|e| Error::StoreCorruption {
-                        message: format!("failed to increment store ref: {e}"),
-                    })?;
+                    .map_err(|e| Error::db("failed to increment store ref").with_source(e))?;
             }
         }
 
         Ok(())
     }
 
+    /// Keep a superseded keg around, holding a reference to its store entry
+    /// so `gc` leaves it alone.
+    pub fn retain_keg(&self, name: &str, version: &str, store_key: &str) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        let inserted = self
+            .tx
+            .execute(
+                "INSERT OR IGNORE INTO retained_kegs (name, version, store_key, retained_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![name, version, store_key, now],
+            )
+            .map_err(|e| Error::db("failed to record retained keg").with_source(e))?;
+
+        if inserted > 0 {
+            self.tx
+                .execute(
+                    "INSERT INTO store_refs (store_key, refcount) VALUES (?1, 1)
+                     ON CONFLICT(store_key) DO UPDATE SET refcount = refcount + 1",
+                    params![store_key],
+                )
+                .map_err(|e| Error::db("failed to increment store ref").with_source(e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Forget a retained keg and drop its store reference. Returns the store
+    /// key if the version was retained.
+    pub fn release_retained(&self, name: &str, version: &str) -> Result<Option<String>, Error> {
+        let store_key: Option<String> = self
+            .tx
+            .query_row(
+                "DELETE FROM retained_kegs WHERE name = ?1 AND version = ?2 RETURNING store_key",
+                params![name, version],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| Error::db("failed to remove retained keg").with_source(e))?;
+
+        if let Some(ref key) = store_key {
+            self.release_store_ref(key)?;
+        }
+
+        Ok(store_key)
+    }
+
     pub fn record_linked_file(
         &self,
         name: &str,
@@ -249,9 +1545,74 @@ impl<'a> InstallTransaction<'a> {
                  VALUES (?1, ?2, ?3, ?4)",
                 params![name, version, linked_path, target_path],
             )
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to record linked file: {e}"),
-            })?;
+            .map_err(|e| Error::db("failed to record linked file").with_source(e))?;
+
+        Ok(())
+    }
+
+    /// Remember an artifact of the cask `name` placed outside its keg, such
+    /// as an app bundle or a pkg receipt, so uninstalling can remove it.
+    pub fn record_cask_artifact(&self, name: &str, kind: &str, path: &str) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "INSERT OR IGNORE INTO cask_artifacts (name, kind, path) VALUES (?1, ?2, ?3)",
+                params![name, kind, path],
+            )
+            .map_err(|e| Error::db("failed to record cask artifact").with_source(e))?;
+
+        Ok(())
+    }
+
+    pub fn remove_linked_file(&self, name: &str, linked_path: &str) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "DELETE FROM keg_files WHERE name = ?1 AND linked_path = ?2",
+                params![name, linked_path],
+            )
+            .map_err(|e| Error::db("failed to remove linked file record").with_source(e))?;
+
+        Ok(())
+    }
+
+    /// Forget who linked or was seen owning `linked_path`, after the file
+    /// there was overwritten.
+    pub fn forget_prefix_path(&self, linked_path: &str) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "DELETE FROM keg_files WHERE linked_path = ?1",
+                params![linked_path],
+            )
+            .map_err(|e| Error::db("failed to remove linked file record").with_source(e))?;
+        self.tx
+            .execute(
+                "DELETE FROM prefix_owners WHERE path = ?1",
+                params![linked_path],
+            )
+            .map_err(|e| Error::db("failed to remove prefix owner").with_source(e))?;
+
+        Ok(())
+    }
+
+    pub fn record_lazy_shim(
+        &self,
+        name: &str,
+        version: &str,
+        shim_path: &str,
+    ) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "INSERT OR REPLACE INTO lazy_shims (name, version, shim_path) VALUES (?1, ?2, ?3)",
+                params![name, version, shim_path],
+            )
+            .map_err(|e| Error::db("failed to record lazy shim").with_source(e))?;
+
+        Ok(())
+    }
+
+    pub fn clear_lazy_shims(&self, name: &str) -> Result<(), Error> {
+        self.tx
+            .execute("DELETE FROM lazy_shims WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to remove lazy shims").with_source(e))?;
 
         Ok(())
     }
@@ -270,36 +1631,84 @@ impl<'a> InstallTransaction<'a> {
         // Remove installed keg record
         self.tx
             .execute("DELETE FROM installed_kegs WHERE name = ?1", params![name])
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to remove install record: {e}"),
-            })?;
+            .map_err(|e| Error::db("failed to remove install record").with_source(e))?;
 
         // Remove linked files records
         self.tx
             .execute("DELETE FROM keg_files WHERE name = ?1", params![name])
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to remove keg files records: {e}"),
-            })?;
+            .map_err(|e| Error::db("failed to remove keg files records").with_source(e))?;
+        self.tx
+            .execute("DELETE FROM link_exclusions WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to remove link exclusions").with_source(e))?;
+
+        self.clear_lazy_shims(name)?;
+
+        self.tx
+            .execute("DELETE FROM keg_metadata WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to remove keg metadata").with_source(e))?;
+        self.tx
+            .execute("DELETE FROM keg_sizes WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to remove keg sizes").with_source(e))?;
+        self.tx
+            .execute("DELETE FROM keg_manifest WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to remove keg manifest").with_source(e))?;
+
+        self.tx
+            .execute(
+                "DELETE FROM keg_dependencies WHERE name = ?1",
+                params![name],
+            )
+            .map_err(|e| Error::db("failed to remove dependencies").with_source(e))?;
+
+        self.tx
+            .execute("DELETE FROM pinned WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to remove pin").with_source(e))?;
+
+        self.tx
+            .execute("DELETE FROM install_notes WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to remove install note").with_source(e))?;
+
+        self.tx
+            .execute("DELETE FROM cask_artifacts WHERE name = ?1", params![name])
+            .map_err(|e| Error::db("failed to remove cask artifacts").with_source(e))?;
 
         // Decrement store ref if we had one
         if let Some(ref key) = store_key {
-            self.tx
-                .execute(
-                    "UPDATE store_refs SET refcount = refcount - 1 WHERE store_key = ?1",
-                    params![key],
-                )
-                .map_err(|e| Error::StoreCorruption {
-                    message: format!("failed to decrement store ref: {e}"),
-                })?;
+            self.release_store_ref(key)?;
         }
 
         Ok(store_key)
     }
 
+    /// Drop one reference to `store_key`, noting when it became unreferenced
+    /// so GC can leave freshly released entries alone for a while.
+    fn release_store_ref(&self, store_key: &str) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        self.tx
+            .execute(
+                "UPDATE store_refs SET refcount = refcount - 1 WHERE store_key = ?1",
+                params![store_key],
+            )
+            .map_err(|e| Error::db("failed to decrement store ref").with_source(e))?;
+        self.tx
+            .execute(
+                "INSERT OR REPLACE INTO store_released (store_key, released_at)
+                 SELECT store_key, ?2 FROM store_refs WHERE store_key = ?1 AND refcount <= 0",
+                params![store_key, now],
+            )
+            .map_err(|e| Error::db("failed to record store release").with_source(e))?;
+
+        Ok(())
+    }
+
     pub fn commit(self) -> Result<(), Error> {
-        self.tx.commit().map_err(|e| Error::StoreCorruption {
-            message: format!("failed to commit transaction: {e}"),
-        })
+        self.tx
+            .commit()
+            .map_err(|e| Error::db("failed to commit transaction").with_source(e))
     }
 
     // Transaction is rolled back automatically when dropped without commit
@@ -309,6 +1718,114 @@ impl<'a> InstallTransaction<'a> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn download_stats_accumulate_per_host_and_reset() {
+        let db = Database::in_memory().unwrap();
+        db.record_download("ghcr.io", 1000, 10).unwrap();
+        db.record_download("ghcr.io", 3000, 30).unwrap();
+        db.record_download("mirror.example", 500, 5).unwrap();
+        db.record_cache_hit("blob", 700).unwrap();
+        db.record_cache_hit("store", 200).unwrap();
+        db.record_cache_hit("store", 100).unwrap();
+
+        let stats = db.download_stats().unwrap();
+        assert_eq!(
+            stats.hosts[0],
+            HostStats {
+                host: "ghcr.io".to_string(),
+                downloads: 2,
+                bytes: 4000,
+                millis: 40,
+            }
+        );
+        assert_eq!(stats.hosts[1].host, "mirror.example");
+        assert_eq!((stats.cache_hits, stats.cache_bytes), (1, 700));
+        assert_eq!((stats.store_hits, stats.store_bytes), (2, 300));
+
+        db.reset_download_stats().unwrap();
+        assert_eq!(db.download_stats().unwrap(), DownloadStats::default());
+    }
+
+    #[test]
+    fn pins_are_listed_and_cleared_on_uninstall() {
+        let mut db = Database::in_memory().unwrap();
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("node", "22.1.0", "nodekey").unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert!(db.pin("node").unwrap());
+        assert!(!db.pin("node").unwrap());
+        assert!(db.is_pinned("node"));
+        assert_eq!(db.pinned().unwrap(), ["node"]);
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("node").unwrap();
+            tx.commit().unwrap();
+        }
+        assert!(!db.is_pinned("node"));
+        assert!(!db.unpin("node").unwrap());
+    }
+
+    #[test]
+    fn install_queue_keeps_order_and_ignores_duplicates() {
+        let db = Database::in_memory().unwrap();
+        assert!(db.queue_install("wget").unwrap());
+        assert!(db.queue_install("jq").unwrap());
+        assert!(!db.queue_install("wget").unwrap());
+        assert_eq!(db.queued_installs().unwrap(), ["wget", "jq"]);
+
+        let removed = db
+            .dequeue_installs(&["wget".to_string(), "ripgrep".to_string()])
+            .unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(db.queued_installs().unwrap(), ["jq"]);
+    }
+
+    #[test]
+    fn dependents_are_installed_formulas_with_an_edge() {
+        let mut db = Database::in_memory().unwrap();
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("jq", "1.7.1", "jqkey").unwrap();
+            tx.record_install("oniguruma", "6.9.9", "onigkey").unwrap();
+            tx.record_install("ripgrep", "14.1.0", "rgkey").unwrap();
+            tx.commit().unwrap();
+        }
+        db.set_dependencies("jq", &["oniguruma".to_string()])
+            .unwrap();
+        db.set_dependencies("ripgrep", &["pcre2".to_string()])
+            .unwrap();
+
+        assert_eq!(db.dependents(&["oniguruma"]).unwrap(), ["jq"]);
+        assert!(db.dependents(&["jq"]).unwrap().is_empty());
+        assert_eq!(db.dependencies("ripgrep").unwrap(), ["pcre2"]);
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("jq").unwrap();
+            tx.commit().unwrap();
+        }
+        assert!(db.dependents(&["oniguruma"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn recent_upgrade_history_is_newest_first_and_bounded() {
+        let db = Database::in_memory().unwrap();
+        db.record_upgrade_change("jq", "1.6", "1.7", "license: MIT → BSD")
+            .unwrap();
+        db.record_upgrade_change("wget", "1.24", "1.25", "new dependencies: libidn2")
+            .unwrap();
+        db.record_upgrade_change("jq", "1.7", "1.8", "now deprecated")
+            .unwrap();
+
+        let recent = db.recent_upgrade_history(2).unwrap();
+        let changes: Vec<_> = recent.iter().map(|r| r.change.as_str()).collect();
+        assert_eq!(changes, ["now deprecated", "new dependencies: libidn2"]);
+    }
+
     #[test]
     fn install_and_list() {
         let mut db = Database::in_memory().unwrap();
@@ -367,6 +1884,92 @@ mod tests {
         assert!(db.get_installed("bar").is_some());
     }
 
+    #[test]
+    fn install_notes_are_replaced_and_dropped_on_uninstall() {
+        let mut db = Database::in_memory().unwrap();
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("jq", "1.7.1", "abc123").unwrap();
+            tx.commit().unwrap();
+        }
+
+        db.set_install_note("jq", "first").unwrap();
+        db.set_install_note("jq", "needed for deploy scripts")
+            .unwrap();
+        assert_eq!(
+            db.install_note("jq").as_deref(),
+            Some("needed for deploy scripts")
+        );
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("jq").unwrap();
+            tx.commit().unwrap();
+        }
+        assert!(db.install_note("jq").is_none());
+    }
+
+    #[test]
+    fn retained_kegs_hold_store_refs_until_released() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "old").unwrap();
+            tx.record_install("foo", "2.0.0", "new").unwrap();
+            tx.retain_keg("foo", "1.0.0", "old").unwrap();
+            // Retaining twice must not double-count the reference.
+            tx.retain_keg("foo", "1.0.0", "old").unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert_eq!(db.get_store_refcount("old"), 1);
+        assert_eq!(db.get_store_refcount("new"), 1);
+        let retained = db.retained_kegs("foo").unwrap();
+        assert_eq!(retained.len(), 1);
+        assert_eq!(retained[0].version, "1.0.0");
+
+        {
+            let tx = db.transaction().unwrap();
+            assert_eq!(
+                tx.release_retained("foo", "1.0.0").unwrap().as_deref(),
+                Some("old")
+            );
+            assert_eq!(tx.release_retained("foo", "1.0.0").unwrap(), None);
+            tx.commit().unwrap();
+        }
+
+        assert_eq!(db.get_store_refcount("old"), 0);
+        assert!(db.list_retained().unwrap().is_empty());
+    }
+
+    #[test]
+    fn sets_keep_member_order_and_can_be_replaced() {
+        let mut db = Database::in_memory().unwrap();
+        let members = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        db.save_set("backend", &members(&["redis", "jq", "postgresql"]))
+            .unwrap();
+        db.save_set("tools", &members(&["ripgrep"])).unwrap();
+        assert_eq!(
+            db.get_set("backend").unwrap(),
+            Some(members(&["redis", "jq", "postgresql"]))
+        );
+
+        db.save_set("backend", &members(&["jq", "jq"])).unwrap();
+        assert_eq!(
+            db.list_sets().unwrap(),
+            vec![
+                ("backend".to_string(), members(&["jq"])),
+                ("tools".to_string(), members(&["ripgrep"])),
+            ]
+        );
+
+        assert!(db.delete_set("backend").unwrap());
+        assert!(!db.delete_set("backend").unwrap());
+        assert_eq!(db.get_set("backend").unwrap(), None);
+    }
+
     #[test]
     fn get_unreferenced_store_keys() {
         let mut db = Database::in_memory().unwrap();
@@ -408,6 +2011,47 @@ mod tests {
             .unwrap();
             tx.commit().unwrap();
         }
+        assert!(db.is_linked("foo"));
+
+        // Excluding the file forgets its link.
+        db.record_link_exclusions("foo", &[PathBuf::from("/opt/homebrew/bin")])
+            .unwrap();
+        assert!(!db.is_linked("foo"));
+        assert_eq!(db.link_exclusions("foo").unwrap(), ["/opt/homebrew/bin"]);
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_linked_file(
+                "foo",
+                "1.0.0",
+                "/opt/homebrew/bin/foo",
+                "/opt/zerobrew/cellar/foo/1.0.0/bin/foo",
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
+
+        db.record_prefix_owners(&[
+            ConflictedLink {
+                path: "/opt/homebrew/bin/foo".into(),
+                owned_by: Some("Homebrew's foo".to_string()),
+            },
+            ConflictedLink {
+                path: "/opt/homebrew/bin/bar".into(),
+                owned_by: None,
+            },
+        ])
+        .unwrap();
+        // What zb linked wins over what was observed before.
+        assert_eq!(
+            db.prefix_owner(Path::new("/opt/homebrew/bin/foo"))
+                .as_deref(),
+            Some("foo")
+        );
+        assert_eq!(
+            db.prefix_owner(Path::new("/opt/homebrew/bin/bar"))
+                .as_deref(),
+            Some("unknown")
+        );
 
         // Verify via uninstall that removes records
         {
@@ -417,6 +2061,13 @@ mod tests {
         }
 
         assert!(db.get_installed("foo").is_none());
+        assert!(!db.is_linked("foo"));
+        assert!(db.link_exclusions("foo").unwrap().is_empty());
+        assert_eq!(
+            db.prefix_owner(Path::new("/opt/homebrew/bin/foo"))
+                .as_deref(),
+            Some("Homebrew's foo")
+        );
     }
 
     #[test]
@@ -478,8 +2129,52 @@ mod tests {
         }
 
         assert_eq!(db.get_unreferenced_store_keys().unwrap(), vec!["gc_key"]);
+        assert!(db.store_released_at("gc_key").is_some());
+        db.record_store_source("gc_key", "https://example.com/gc_key")
+            .unwrap();
+        assert_eq!(
+            db.store_source("gc_key").as_deref(),
+            Some("https://example.com/gc_key")
+        );
         db.delete_store_ref("gc_key").unwrap();
         assert!(db.get_unreferenced_store_keys().unwrap().is_empty());
+        assert!(db.store_released_at("gc_key").is_none());
+        assert!(db.store_source("gc_key").is_none());
+    }
+
+    #[test]
+    fn taps_index_their_formulas() {
+        let mut db = Database::in_memory().unwrap();
+        db.record_tap(
+            "hashicorp/tap",
+            "https://github.com/hashicorp/homebrew-tap",
+            &["terraform".to_string(), "vault".to_string()],
+        )
+        .unwrap();
+        db.record_tap("acme/tools", "/srv/taps/acme", &["vault".to_string()])
+            .unwrap();
+
+        let taps = db.list_taps().unwrap();
+        assert_eq!(taps.len(), 2);
+        assert_eq!(taps[1].name, "hashicorp/tap");
+        assert_eq!(taps[1].formulas, 2);
+        assert_eq!(
+            db.taps_with_formula("vault").unwrap(),
+            ["acme/tools", "hashicorp/tap"]
+        );
+
+        // Re-recording replaces the formula list.
+        db.record_tap(
+            "hashicorp/tap",
+            "https://github.com/hashicorp/homebrew-tap",
+            &["terraform".to_string()],
+        )
+        .unwrap();
+        assert_eq!(db.tap_formulas("hashicorp/tap").unwrap(), ["terraform"]);
+
+        assert!(db.remove_tap("acme/tools").unwrap());
+        assert!(!db.remove_tap("acme/tools").unwrap());
+        assert!(db.taps_with_formula("vault").unwrap().is_empty());
     }
 
     #[test]
@@ -503,7 +2198,7 @@ mod tests {
 
         let tx = db.transaction().unwrap();
         let err = tx.record_install("foo", "1.1.0", "newkey").unwrap_err();
-        assert!(matches!(err, Error::StoreCorruption { .. }));
+        assert!(matches!(err, Error::Db { .. }));
         assert!(
             err.to_string()
                 .contains("failed to query previous store key")
@@ -1,7 +1,12 @@
+pub mod audit;
 pub mod blob;
 pub mod db;
 pub mod store;
 
+pub use audit::{OrphanAction, RefcountFix, StoreAudit};
 pub use blob::{BlobCache, BlobWriter};
-pub use db::{Database, InstallTransaction, InstalledKeg};
+pub use db::{
+    Database, DownloadStats, HostStats, InstallTransaction, InstalledKeg, KegFileState,
+    LicenseAcceptance, TapInfo, UpgradeRecord,
+};
 pub use store::Store;
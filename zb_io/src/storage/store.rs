@@ -1,12 +1,18 @@
 use std::fs::{self, File};
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use fs4::fs_std::FileExt;
 
 use crate::extraction::extract::extract_archive;
 use zb_core::Error;
 
+/// Suffix of a compacted entry's archive, next to where its directory was.
+const ARCHIVE_SUFFIX: &str = ".tar.zst";
+const ARCHIVE_LEVEL: i32 = 19;
+
+#[derive(Clone)]
 pub struct Store {
     store_dir: PathBuf,
     locks_dir: PathBuf,
@@ -30,36 +36,165 @@ impl Store {
         self.store_dir.join(store_key)
     }
 
+    /// Whether the entry exists, unpacked or compacted into an archive.
     pub fn has_entry(&self, store_key: &str) -> bool {
-        self.entry_path(store_key).exists()
+        self.entry_path(store_key).exists() || self.is_archived(store_key)
+    }
+
+    fn archive_path(&self, store_key: &str) -> PathBuf {
+        self.store_dir.join(format!("{store_key}{ARCHIVE_SUFFIX}"))
+    }
+
+    /// Whether the entry was compacted and has to be re-extracted before use.
+    pub fn is_archived(&self, store_key: &str) -> bool {
+        !self.entry_path(store_key).exists() && self.archive_path(store_key).is_file()
+    }
+
+    /// The unpacked entry, re-extracting it first if it was compacted. Marks
+    /// the entry as used.
+    pub fn unpacked_entry(&self, store_key: &str) -> Result<PathBuf, Error> {
+        let entry_path = self.entry_path(store_key);
+        if !entry_path.exists() && self.archive_path(store_key).exists() {
+            let _lock = self.lock_entry(store_key)?;
+            if !entry_path.exists() {
+                self.restore_archive(store_key)?;
+            }
+        }
+        touch(&entry_path);
+        Ok(entry_path)
+    }
+
+    /// When the unpacked entry was last materialized from, or `None` if it
+    /// isn't unpacked.
+    pub fn last_used(&self, store_key: &str) -> Option<SystemTime> {
+        fs::metadata(self.entry_path(store_key))
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    /// Bytes the entry takes on disk, unpacked and archived copies included.
+    pub fn disk_usage(&self, store_key: &str) -> u64 {
+        let archived = fs::metadata(self.archive_path(store_key))
+            .map(|m| m.len())
+            .unwrap_or(0);
+        tree_size(&self.entry_path(store_key)) + archived
+    }
+
+    /// Replace the unpacked entry with a zstd-compressed tarball of it.
+    /// Returns the unpacked and archived sizes in bytes.
+    pub fn archive_entry(&self, store_key: &str) -> Result<(u64, u64), Error> {
+        let entry_path = self.entry_path(store_key);
+        let _lock = self.lock_entry(store_key)?;
+
+        let unpacked = tree_size(&entry_path);
+
+        let tmp_path = self.store_dir.join(format!(
+            ".{store_key}{ARCHIVE_SUFFIX}.tmp.{}",
+            std::process::id()
+        ));
+        if let Err(e) = write_archive(&entry_path, &tmp_path) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+        let archived = fs::metadata(&tmp_path).map(|m| m.len()).unwrap_or(0);
+
+        fs::rename(&tmp_path, self.archive_path(store_key)).map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            Error::unpack("failed to move store archive into place").with_source(e)
+        })?;
+        fs::remove_dir_all(&entry_path)
+            .map_err(|e| Error::unpack("failed to remove compacted store entry").with_source(e))?;
+
+        Ok((unpacked, archived))
+    }
+
+    /// Unpack a compacted entry back into place. Callers hold the entry lock.
+    fn restore_archive(&self, store_key: &str) -> Result<(), Error> {
+        let archive_path = self.archive_path(store_key);
+        self.unpack_into_place(store_key, |dest| extract_archive(&archive_path, dest))?;
+        let _ = fs::remove_file(archive_path);
+        Ok(())
+    }
+
+    fn lock_path(&self, store_key: &str) -> PathBuf {
+        self.locks_dir.join(format!("{store_key}.lock"))
+    }
+
+    /// Take the exclusive per-entry lock; it is released when the file is dropped.
+    pub(crate) fn lock_entry(&self, store_key: &str) -> Result<File, Error> {
+        let lock_file = File::create(self.lock_path(store_key))
+            .map_err(|e| Error::unpack("failed to create lock file").with_source(e))?;
+
+        lock_file
+            .lock_exclusive()
+            .map_err(|e| Error::unpack("failed to acquire lock").with_source(e))?;
+
+        Ok(lock_file)
+    }
+
+    /// Take the store-wide lock: shared for installs, which add references,
+    /// and exclusive for GC, which removes unreferenced entries. Released
+    /// when the file is dropped.
+    pub(crate) fn lock_store(&self, exclusive: bool) -> Result<File, Error> {
+        let lock_file = File::create(self.locks_dir.join("store.lock"))
+            .map_err(|e| Error::unpack("failed to create store lock file").with_source(e))?;
+
+        let locked = if exclusive {
+            lock_file.lock_exclusive()
+        } else {
+            lock_file.lock_shared()
+        };
+        locked.map_err(|e| Error::unpack("failed to acquire store lock").with_source(e))?;
+
+        Ok(lock_file)
     }
 
     pub fn ensure_entry(&self, store_key: &str, blob_path: &Path) -> Result<PathBuf, Error> {
+        self.ensure_entry_with(store_key, |dest| extract_archive(blob_path, dest))
+    }
+
+    /// Like [`Store::ensure_entry`], but `unpack` fills the new entry's
+    /// directory, for downloads that aren't plain archives.
+    pub fn ensure_entry_with(
+        &self,
+        store_key: &str,
+        unpack: impl FnOnce(&Path) -> Result<(), Error>,
+    ) -> Result<PathBuf, Error> {
         let entry_path = self.entry_path(store_key);
 
         // Fast path: already exists
         if entry_path.exists() {
+            touch(&entry_path);
             return Ok(entry_path);
         }
 
         // Acquire exclusive lock for this store_key
-        let lock_path = self.locks_dir.join(format!("{store_key}.lock"));
-        let lock_file = File::create(&lock_path).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create lock file: {e}"),
-        })?;
-
-        lock_file
-            .lock_exclusive()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to acquire lock: {e}"),
-            })?;
+        let _lock = self.lock_entry(store_key)?;
 
         // Double-check after acquiring lock (another process may have created it)
         if entry_path.exists() {
-            // Lock will be released when lock_file is dropped
+            // Lock will be released when _lock is dropped
             return Ok(entry_path);
         }
 
+        if self.archive_path(store_key).exists() {
+            self.restore_archive(store_key)?;
+        } else {
+            self.unpack_into_place(store_key, unpack)?;
+        }
+
+        // Lock will be released when _lock is dropped
+        Ok(entry_path)
+    }
+
+    /// Unpack into a temp directory and rename it to the entry path.
+    fn unpack_into_place(
+        &self,
+        store_key: &str,
+        unpack: impl FnOnce(&Path) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let entry_path = self.entry_path(store_key);
+
         // Unpack to a temp directory first
         let tmp_dir = self
             .store_dir
@@ -71,12 +206,11 @@ impl Store {
             let _ = fs::remove_dir_all(&tmp_dir);
         }
 
-        fs::create_dir_all(&tmp_dir).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create temp directory: {e}"),
-        })?;
+        fs::create_dir_all(&tmp_dir)
+            .map_err(|e| Error::unpack("failed to create temp directory").with_source(e))?;
 
         // Extract the archive
-        if let Err(e) = extract_archive(blob_path, &tmp_dir) {
+        if let Err(e) = unpack(&tmp_dir) {
             // Clean up temp directory on failure
             let _ = fs::remove_dir_all(&tmp_dir);
             return Err(e);
@@ -86,49 +220,107 @@ impl Store {
         if let Err(e) = fs::rename(&tmp_dir, &entry_path) {
             // Clean up temp directory on failure
             let _ = fs::remove_dir_all(&tmp_dir);
-            return Err(Error::StoreCorruption {
-                message: format!("failed to rename store entry: {e}"),
-            });
+            return Err(Error::unpack("failed to rename store entry").with_source(e));
         }
 
-        // Lock will be released when lock_file is dropped
-        Ok(entry_path)
+        Ok(())
+    }
+
+    /// Keys of every entry on disk, skipping in-progress extractions.
+    pub fn entry_keys(&self) -> Result<Vec<String>, Error> {
+        let entries = fs::read_dir(&self.store_dir)
+            .map_err(|e| Error::unpack("failed to read store directory").with_source(e))?;
+
+        let mut keys: Vec<String> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                if entry.path().is_dir() {
+                    Some(name)
+                } else {
+                    name.strip_suffix(ARCHIVE_SUFFIX).map(str::to_string)
+                }
+            })
+            .filter(|name| !name.starts_with('.'))
+            .collect();
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
     }
 
     /// Remove a store entry. This should only be called when the refcount is 0.
     pub fn remove_entry(&self, store_key: &str) -> Result<(), Error> {
         let entry_path = self.entry_path(store_key);
 
-        if !entry_path.exists() {
+        if !self.has_entry(store_key) {
             return Ok(());
         }
 
         // Acquire exclusive lock for this store_key
-        let lock_path = self.locks_dir.join(format!("{store_key}.lock"));
-        let lock_file = File::create(&lock_path).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create lock file: {e}"),
-        })?;
+        let _lock = self.lock_entry(store_key)?;
 
-        lock_file
-            .lock_exclusive()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to acquire lock: {e}"),
-            })?;
+        let archive_path = self.archive_path(store_key);
+        if archive_path.exists() {
+            fs::remove_file(&archive_path)
+                .map_err(|e| Error::unpack("failed to remove store archive").with_source(e))?;
+        }
 
         // Remove the directory
         if entry_path.exists() {
-            fs::remove_dir_all(&entry_path).map_err(|e| Error::StoreCorruption {
-                message: format!("failed to remove store entry: {e}"),
-            })?;
+            fs::remove_dir_all(&entry_path)
+                .map_err(|e| Error::unpack("failed to remove store entry").with_source(e))?;
         }
 
         // Clean up the lock file
-        let _ = fs::remove_file(&lock_path);
+        let _ = fs::remove_file(self.lock_path(store_key));
 
         Ok(())
     }
 }
 
+/// Total size of the files under `dir`.
+fn tree_size(dir: &Path) -> u64 {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Record a use of the entry in its mtime, which compaction goes by.
+fn touch(entry_path: &Path) {
+    if let Ok(dir) = File::open(entry_path) {
+        let _ = dir.set_modified(SystemTime::now());
+    }
+}
+
+fn write_archive(entry_path: &Path, archive_path: &Path) -> Result<(), Error> {
+    let archive_error =
+        |e: io::Error| Error::unpack("failed to archive store entry").with_source(e);
+
+    let file = File::create(archive_path).map_err(archive_error)?;
+    let encoder = zstd::Encoder::new(file, ARCHIVE_LEVEL).map_err(archive_error)?;
+    let mut builder = tar::Builder::new(encoder);
+    builder.follow_symlinks(false);
+    for child in fs::read_dir(entry_path).map_err(archive_error)? {
+        let child = child.map_err(archive_error)?;
+        let path = child.path();
+        if child.file_type().map_err(archive_error)?.is_dir() {
+            builder.append_dir_all(child.file_name(), &path)
+        } else {
+            builder.append_path_with_name(&path, child.file_name())
+        }
+        .map_err(archive_error)?;
+    }
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .and_then(|file| file.sync_all())
+        .map_err(archive_error)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,6 +428,45 @@ mod tests {
         assert_eq!(content, "concurrent test");
     }
 
+    #[test]
+    fn archived_entry_is_restored_on_next_use() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let blob_path = tmp.path().join("test.tar.gz");
+        fs::write(&blob_path, create_test_tarball(b"compact me")).unwrap();
+        let entry = store.ensure_entry("compactme", &blob_path).unwrap();
+        fs::create_dir_all(entry.join("lib")).unwrap();
+        std::os::unix::fs::symlink("../test.txt", entry.join("lib/link.txt")).unwrap();
+
+        let (unpacked, archived) = store.archive_entry("compactme").unwrap();
+        assert_eq!(unpacked, 10);
+        assert!(archived > 0);
+        assert!(!entry.exists());
+        assert!(store.is_archived("compactme"));
+        assert!(store.has_entry("compactme"));
+        assert_eq!(store.entry_keys().unwrap(), ["compactme"]);
+
+        // The blob is gone too; the archive alone is enough to restore it.
+        fs::remove_file(&blob_path).unwrap();
+        let restored = store.ensure_entry("compactme", &blob_path).unwrap();
+        assert_eq!(
+            fs::read_to_string(restored.join("lib/link.txt")).unwrap(),
+            "compact me"
+        );
+        assert!(
+            fs::symlink_metadata(restored.join("lib/link.txt"))
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert!(!store.is_archived("compactme"));
+
+        store.archive_entry("compactme").unwrap();
+        store.remove_entry("compactme").unwrap();
+        assert!(!store.has_entry("compactme"));
+    }
+
     #[test]
     fn has_entry_returns_correct_state() {
         let tmp = TempDir::new().unwrap();
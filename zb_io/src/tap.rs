@@ -0,0 +1,262 @@
+use crate::network::tap_formula::TapFormulaRef;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use zb_core::Error;
+
+/// Tap checkouts under `<root>/taps`, laid out like Homebrew's
+/// `Library/Taps`: `<owner>/homebrew-<repo>`.
+pub struct Taps {
+    dir: PathBuf,
+    github_token: Option<String>,
+}
+
+/// `owner/repo` for a tap given as `owner/repo` or `owner/homebrew-repo`.
+pub fn normalize_tap_name(name: &str) -> Result<String, Error> {
+    let invalid = || Error::InvalidArgument {
+        message: format!("invalid tap name '{name}'; expected 'owner/repo'"),
+    };
+    let (owner, repo) = name.split_once('/').ok_or_else(invalid)?;
+    let repo = repo.strip_prefix("homebrew-").unwrap_or(repo);
+    let valid = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            && !part.starts_with('.')
+    };
+    if !valid(owner) || !valid(repo) {
+        return Err(invalid());
+    }
+    Ok(format!(
+        "{}/{}",
+        owner.to_ascii_lowercase(),
+        repo.to_ascii_lowercase()
+    ))
+}
+
+/// The GitHub repository a tap is cloned from when no remote is given.
+pub fn default_remote(tap: &str) -> String {
+    let (owner, repo) = tap.split_once('/').unwrap_or((tap, ""));
+    format!("https://github.com/{owner}/homebrew-{repo}")
+}
+
+impl Taps {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            github_token: None,
+        }
+    }
+
+    /// Authenticate to github.com with `token`, so private taps can be cloned.
+    pub fn with_github_token(mut self, token: Option<String>) -> Self {
+        self.github_token = token;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Checkout of the normalized tap `tap`.
+    pub fn path(&self, tap: &str) -> PathBuf {
+        let (owner, repo) = tap.split_once('/').unwrap_or((tap, ""));
+        self.dir.join(owner).join(format!("homebrew-{repo}"))
+    }
+
+    pub fn is_tapped(&self, tap: &str) -> bool {
+        self.path(tap).join(".git").exists()
+    }
+
+    /// Shallow-clone `remote` as `tap`, replacing a checkout left behind by
+    /// an interrupted clone.
+    pub async fn clone(&self, tap: &str, remote: &str) -> Result<PathBuf, Error> {
+        let path = self.path(tap);
+        if path.exists() {
+            std::fs::remove_dir_all(&path).map_err(|e| Error::FileError {
+                message: format!("failed to remove {}: {e}", path.display()),
+            })?;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| Error::FileError {
+                message: format!("failed to create {}: {e}", parent.display()),
+            })?;
+        }
+
+        let mut git = self.git();
+        git.args(["clone", "--depth", "1", "--quiet", remote])
+            .arg(&path);
+        if let Err(e) = git_output(git, &format!("clone {remote}")).await {
+            let _ = std::fs::remove_dir_all(&path);
+            return Err(e);
+        }
+        Ok(path)
+    }
+
+    /// Bring the checkout of `tap` up to date with its remote's default
+    /// branch, discarding local changes.
+    pub async fn update(&self, tap: &str) -> Result<(), Error> {
+        let path = self.path(tap);
+        let mut fetch = self.git();
+        fetch
+            .arg("-C")
+            .arg(&path)
+            .args(["fetch", "--depth", "1", "--quiet", "origin", "HEAD"]);
+        git_output(fetch, &format!("fetch {tap}")).await?;
+
+        let mut reset = self.git();
+        reset
+            .arg("-C")
+            .arg(&path)
+            .args(["reset", "--hard", "--quiet", "FETCH_HEAD"]);
+        git_output(reset, &format!("update {tap}")).await?;
+        Ok(())
+    }
+
+    /// Delete the checkout of `tap`, and its owner directory once empty.
+    pub fn remove(&self, tap: &str) -> Result<(), Error> {
+        let path = self.path(tap);
+        if path.exists() {
+            std::fs::remove_dir_all(&path).map_err(|e| Error::FileError {
+                message: format!("failed to remove {}: {e}", path.display()),
+            })?;
+        }
+        if let Some(owner) = path.parent() {
+            let _ = std::fs::remove_dir(owner);
+        }
+        Ok(())
+    }
+
+    /// Names of the formulas in the checkout of `tap`, sorted. Like Homebrew,
+    /// formulas come from `Formula/` when the tap has one, else from
+    /// `HomebrewFormula/`, else from the top level.
+    pub fn formula_names(&self, tap: &str) -> Result<Vec<String>, Error> {
+        let path = self.path(tap);
+        let dir = ["Formula", "HomebrewFormula"]
+            .iter()
+            .map(|dir| path.join(dir))
+            .find(|dir| dir.is_dir());
+        let mut names = Vec::new();
+        match dir {
+            Some(dir) => collect_ruby_files(&dir, true, &mut names)?,
+            None => collect_ruby_files(&path, false, &mut names)?,
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// A git command that answers github.com credential prompts with the
+    /// token. The token is passed in the environment, not on the command line.
+    fn git(&self) -> Command {
+        let mut git = Command::new("git");
+        git.env("GIT_TERMINAL_PROMPT", "0");
+        if let Some(token) = &self.github_token {
+            git.env("GIT_CONFIG_COUNT", "1")
+                .env("GIT_CONFIG_KEY_0", "credential.https://github.com.helper")
+                .env(
+                    "GIT_CONFIG_VALUE_0",
+                    "!f() { echo username=x-access-token; echo \"password=$ZEROBREW_TAP_TOKEN\"; }; f",
+                )
+                .env("ZEROBREW_TAP_TOKEN", token);
+        }
+        git
+    }
+
+    /// The file defining `formula` in the checkout of `tap`, if any.
+    pub fn formula_file(&self, tap: &str, formula: &str) -> Option<PathBuf> {
+        let (owner, repo) = tap.split_once('/')?;
+        let spec = TapFormulaRef {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            formula: formula.to_string(),
+        };
+        let path = self.path(tap);
+        spec.candidate_paths()
+            .iter()
+            .map(|candidate| path.join(candidate))
+            .find(|candidate| candidate.is_file())
+    }
+}
+
+fn collect_ruby_files(dir: &Path, recurse: bool, names: &mut Vec<String>) -> Result<(), Error> {
+    let entries = std::fs::read_dir(dir).map_err(|e| Error::FileError {
+        message: format!("failed to read {}: {e}", dir.display()),
+    })?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            // Sharded layouts keep formulas one level down (`Formula/j/jq.rb`).
+            if recurse && !entry.file_name().to_string_lossy().starts_with('.') {
+                collect_ruby_files(&path, false, names)?;
+            }
+        } else if path.extension().is_some_and(|ext| ext == "rb")
+            && let Some(stem) = path.file_stem()
+        {
+            names.push(stem.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+async fn git_output(mut git: Command, action: &str) -> Result<(), Error> {
+    let output = git.output().await.map_err(|e| Error::ExecutionError {
+        message: format!("failed to run git, which taps need: {e}"),
+    })?;
+    if !output.status.success() {
+        return Err(Error::NetworkFailure {
+            message: format!(
+                "failed to {action}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn tap_names_are_normalized() {
+        assert_eq!(
+            normalize_tap_name("HashiCorp/homebrew-tap").unwrap(),
+            "hashicorp/tap"
+        );
+        assert_eq!(normalize_tap_name("acme/tools").unwrap(), "acme/tools");
+        assert!(normalize_tap_name("acme").is_err());
+        assert!(normalize_tap_name("acme/tools/jq").is_err());
+        assert!(normalize_tap_name("../tools").is_err());
+        assert_eq!(
+            default_remote("hashicorp/tap"),
+            "https://github.com/hashicorp/homebrew-tap"
+        );
+    }
+
+    #[test]
+    fn formula_names_follow_the_tap_layout() {
+        let tmp = TempDir::new().unwrap();
+        let taps = Taps::new(tmp.path().to_path_buf());
+
+        let sharded = taps.path("acme/tools");
+        fs::create_dir_all(sharded.join("Formula/j")).unwrap();
+        fs::write(sharded.join("Formula/j/jq.rb"), "").unwrap();
+        fs::write(sharded.join("Formula/yq.rb"), "").unwrap();
+        fs::write(sharded.join("Formula/README.md"), "").unwrap();
+        fs::write(sharded.join("setup.rb"), "").unwrap();
+        assert_eq!(taps.formula_names("acme/tools").unwrap(), ["jq", "yq"]);
+        assert_eq!(
+            taps.formula_file("acme/tools", "jq").unwrap(),
+            sharded.join("Formula/j/jq.rb")
+        );
+
+        let flat = taps.path("acme/flat");
+        fs::create_dir_all(&flat).unwrap();
+        fs::write(flat.join("hello.rb"), "").unwrap();
+        assert_eq!(taps.formula_names("acme/flat").unwrap(), ["hello"]);
+        assert!(taps.formula_file("acme/flat", "jq").is_none());
+    }
+}
@@ -0,0 +1,60 @@
+//! Throwaway zerobrew installs for integration tests of crates embedding
+//! `zb_io`. Enabled with the `test-support` feature.
+
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+use zb_core::Error;
+
+use crate::{ApiClient, BlobCache, Cellar, Database, Installer, Linker, Store};
+
+/// An [`Installer`] wired to a fresh root and prefix under a temporary
+/// directory, fetching formulas and bottles from `api_base_url` (usually a
+/// mock server). Everything is deleted when it is dropped.
+pub struct TestPrefix {
+    pub installer: Installer,
+    /// zerobrew root: store, cache, database.
+    pub root: PathBuf,
+    /// Where kegs are linked, as `/opt/homebrew` would be.
+    pub prefix: PathBuf,
+    dir: TempDir,
+}
+
+impl TestPrefix {
+    /// A prefix whose installer fetches formulas from `api_base_url` and
+    /// casks from `<api_base_url>/cask`.
+    pub fn new(api_base_url: impl Into<String>) -> Result<Self, Error> {
+        let api_base_url = api_base_url.into();
+        let io_error = |what: &str, e: std::io::Error| Error::FileError {
+            message: format!("failed to create test {what}: {e}"),
+        };
+
+        let dir = TempDir::new().map_err(|e| io_error("directory", e))?;
+        let root = dir.path().join("zerobrew");
+        let prefix = dir.path().join("homebrew");
+        std::fs::create_dir_all(root.join("db")).map_err(|e| io_error("root", e))?;
+
+        let installer = Installer::new(
+            ApiClient::with_base_url(api_base_url.clone())
+                .with_cask_base_url(format!("{api_base_url}/cask")),
+            BlobCache::new(&root.join("cache")).map_err(|e| io_error("blob cache", e))?,
+            Store::new(&root).map_err(|e| io_error("store", e))?,
+            Cellar::new(&root).map_err(|e| io_error("cellar", e))?,
+            Linker::new(&prefix).map_err(|e| io_error("prefix", e))?,
+            Database::open(&root.join("db/zb.sqlite3"))?,
+            prefix.clone(),
+        );
+
+        Ok(Self {
+            installer,
+            root,
+            prefix,
+            dir,
+        })
+    }
+
+    /// The temporary directory holding both the root and the prefix.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
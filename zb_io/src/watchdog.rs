@@ -0,0 +1,348 @@
+use std::io::Read;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use zb_core::Error;
+
+use crate::config::DownloadConfig;
+
+/// Default time a download may go without receiving any bytes before it is aborted.
+const DEFAULT_DOWNLOAD_STALL_SECS: u64 = 60;
+
+/// Default wall-clock limit for helper tools (codesign, install_name_tool, brew, ...).
+const DEFAULT_COMMAND_TIMEOUT_SECS: u64 = 120;
+
+/// Number of extra attempts made for a stalled download under `StallPolicy::Retry`.
+const DEFAULT_STALL_RETRIES: usize = 2;
+
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// What to do with a plan item whose download stalled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallPolicy {
+    /// Retry the download up to the given number of additional times.
+    Retry(usize),
+    /// Fail the item immediately and let the rest of the plan continue.
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    pub download_stall: Duration,
+    pub command_timeout: Duration,
+    pub stall_policy: StallPolicy,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            download_stall: Duration::from_secs(DEFAULT_DOWNLOAD_STALL_SECS),
+            command_timeout: Duration::from_secs(DEFAULT_COMMAND_TIMEOUT_SECS),
+            stall_policy: StallPolicy::Retry(DEFAULT_STALL_RETRIES),
+        }
+    }
+}
+
+impl WatchdogConfig {
+    /// Build a config from `ZEROBREW_DOWNLOAD_STALL_TIMEOUT`, `ZEROBREW_COMMAND_TIMEOUT`
+    /// (both in seconds) and `ZEROBREW_STALL_POLICY` (`retry`, `retry:<n>` or `skip`).
+    pub fn from_env() -> Self {
+        Self::from_values(
+            std::env::var("ZEROBREW_DOWNLOAD_STALL_TIMEOUT")
+                .ok()
+                .as_deref(),
+            std::env::var("ZEROBREW_COMMAND_TIMEOUT").ok().as_deref(),
+            std::env::var("ZEROBREW_STALL_POLICY").ok().as_deref(),
+        )
+    }
+
+    /// Build a config from the `[download]` settings, which already carry
+    /// their environment overrides.
+    pub fn from_config(download: &DownloadConfig) -> Self {
+        Self::from_values(
+            Some(&download.stall_timeout_secs.to_string()),
+            Some(&download.command_timeout_secs.to_string()),
+            Some(&download.stall_policy),
+        )
+    }
+
+    fn from_values(
+        download_stall: Option<&str>,
+        command_timeout: Option<&str>,
+        stall_policy: Option<&str>,
+    ) -> Self {
+        let defaults = Self::default();
+        Self {
+            download_stall: parse_secs(download_stall).unwrap_or(defaults.download_stall),
+            command_timeout: parse_secs(command_timeout).unwrap_or(defaults.command_timeout),
+            stall_policy: stall_policy
+                .and_then(parse_stall_policy)
+                .unwrap_or(defaults.stall_policy),
+        }
+    }
+
+    /// Number of attempts a stalled download gets in total.
+    pub fn download_attempts(&self) -> usize {
+        match self.stall_policy {
+            StallPolicy::Retry(retries) => retries + 1,
+            StallPolicy::Skip => 1,
+        }
+    }
+}
+
+fn parse_secs(value: Option<&str>) -> Option<Duration> {
+    let secs = value?.trim().parse::<u64>().ok()?;
+    if secs == 0 {
+        return None;
+    }
+    Some(Duration::from_secs(secs))
+}
+
+fn parse_stall_policy(value: &str) -> Option<StallPolicy> {
+    let value = value.trim().to_ascii_lowercase();
+    match value.as_str() {
+        "skip" => Some(StallPolicy::Skip),
+        "retry" => Some(StallPolicy::Retry(DEFAULT_STALL_RETRIES)),
+        other => other
+            .strip_prefix("retry:")
+            .and_then(|n| n.parse::<usize>().ok())
+            .map(StallPolicy::Retry),
+    }
+}
+
+static COMMAND_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(DEFAULT_COMMAND_TIMEOUT_SECS);
+
+/// Timeout applied to helper tools, as last set by [`set_command_timeout`].
+pub fn command_timeout() -> Duration {
+    Duration::from_secs(COMMAND_TIMEOUT_SECS.load(Ordering::Relaxed))
+}
+
+/// Apply `watchdog`'s helper tool timeout to every later [`command_timeout`].
+/// The tools run deep inside relocation, services and plugins, so the limit
+/// is process-wide rather than passed to each of them.
+pub(crate) fn set_command_timeout(watchdog: &WatchdogConfig) {
+    COMMAND_TIMEOUT_SECS.store(watchdog.command_timeout.as_secs(), Ordering::Relaxed);
+}
+
+/// Await the next item of a download stream, failing if nothing arrives within `stall`.
+pub(crate) async fn next_or_stall<S>(
+    stream: &mut S,
+    stall: Duration,
+) -> Result<Option<S::Item>, Error>
+where
+    S: futures_util::Stream + Unpin,
+{
+    use futures_util::StreamExt;
+
+    tokio::time::timeout(stall, stream.next())
+        .await
+        .map_err(|_| Error::Timeout {
+            message: format!(
+                "download stalled: no data received for {}",
+                format_duration(stall)
+            ),
+        })
+}
+
+/// Run a command to completion, killing it if it is still running after `timeout`.
+///
+/// stdout and stderr are captured on background threads so a chatty child can't
+/// block on a full pipe while we wait for it.
+pub fn output_with_timeout(command: &mut Command, timeout: Duration) -> Result<Output, Error> {
+    run_with_timeout(command, None, timeout)
+}
+
+/// Like [`output_with_timeout`], but feeds `input` to the child's stdin.
+pub fn output_with_input(
+    command: &mut Command,
+    input: &[u8],
+    timeout: Duration,
+) -> Result<Output, Error> {
+    run_with_timeout(command, Some(input), timeout)
+}
+
+fn run_with_timeout(
+    command: &mut Command,
+    input: Option<&[u8]>,
+    timeout: Duration,
+) -> Result<Output, Error> {
+    let program = command.get_program().to_string_lossy().into_owned();
+
+    let stdin = if input.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    };
+    let mut child = command
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::ExecutionError {
+            message: format!("failed to execute '{program}': {e}"),
+        })?;
+
+    // Written from a thread for the same reason the output is read from one:
+    // a child that doesn't drain stdin must not wedge us past the deadline.
+    if let (Some(input), Some(mut pipe)) = (input, child.stdin.take()) {
+        let input = input.to_vec();
+        std::thread::spawn(move || {
+            use std::io::Write;
+            let _ = pipe.write_all(&input);
+        });
+    }
+
+    let stdout = child.stdout.take().map(spawn_reader);
+    let stderr = child.stderr.take().map(spawn_reader);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(Error::Timeout {
+                    message: format!(
+                        "'{program}' did not finish within {}",
+                        format_duration(timeout)
+                    ),
+                });
+            }
+            Ok(None) => std::thread::sleep(COMMAND_POLL_INTERVAL),
+            Err(e) => {
+                return Err(Error::ExecutionError {
+                    message: format!("failed waiting for '{program}': {e}"),
+                });
+            }
+        }
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.map(join_reader).unwrap_or_default(),
+        stderr: stderr.map(join_reader).unwrap_or_default(),
+    })
+}
+
+fn spawn_reader<R: Read + Send + 'static>(mut reader: R) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = reader.read_to_end(&mut buf);
+        buf
+    })
+}
+
+fn join_reader(handle: std::thread::JoinHandle<Vec<u8>>) -> Vec<u8> {
+    handle.join().unwrap_or_default()
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration.as_secs() > 0 {
+        format!("{}s", duration.as_secs())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_values_uses_defaults_for_missing_or_invalid_input() {
+        let config = WatchdogConfig::from_values(None, Some("abc"), Some("sometimes"));
+        assert_eq!(config, WatchdogConfig::default());
+
+        let config = WatchdogConfig::from_values(Some("0"), None, None);
+        assert_eq!(config.download_stall, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn from_values_parses_overrides() {
+        let config = WatchdogConfig::from_values(Some("15"), Some("30"), Some("retry:5"));
+        assert_eq!(config.download_stall, Duration::from_secs(15));
+        assert_eq!(config.command_timeout, Duration::from_secs(30));
+        assert_eq!(config.stall_policy, StallPolicy::Retry(5));
+        assert_eq!(config.download_attempts(), 6);
+
+        let config = WatchdogConfig::from_values(None, None, Some("SKIP"));
+        assert_eq!(config.stall_policy, StallPolicy::Skip);
+        assert_eq!(config.download_attempts(), 1);
+    }
+
+    #[test]
+    fn from_config_reads_the_download_section() {
+        let download = crate::Config::parse(
+            "[download]\nstall_timeout_secs = 15\nstall_policy = \"retry:5\"\ncommand_timeout_secs = 300\n",
+        )
+        .unwrap()
+        .download;
+        let config = WatchdogConfig::from_config(&download);
+        assert_eq!(config.download_stall, Duration::from_secs(15));
+        assert_eq!(config.stall_policy, StallPolicy::Retry(5));
+        assert_eq!(config.command_timeout, Duration::from_secs(300));
+
+        let config = WatchdogConfig::from_config(&DownloadConfig::default());
+        assert_eq!(
+            config.download_stall,
+            WatchdogConfig::default().download_stall
+        );
+        assert_eq!(config.stall_policy, WatchdogConfig::default().stall_policy);
+        assert_eq!(
+            config.command_timeout,
+            WatchdogConfig::default().command_timeout
+        );
+    }
+
+    #[test]
+    fn output_with_input_feeds_stdin() {
+        let output =
+            output_with_input(&mut Command::new("cat"), b"payload", Duration::from_secs(5))
+                .unwrap();
+        assert_eq!(output.stdout, b"payload");
+    }
+
+    #[tokio::test]
+    async fn next_or_stall_times_out_on_silent_stream() {
+        let mut stream = futures_util::stream::pending::<u8>();
+        let err = next_or_stall(&mut stream, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Timeout { .. }));
+        assert!(err.to_string().contains("stalled"));
+    }
+
+    #[tokio::test]
+    async fn next_or_stall_yields_ready_items() {
+        let mut stream = futures_util::stream::iter([1u8, 2]);
+        let first = next_or_stall(&mut stream, Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert_eq!(first, Some(1));
+    }
+
+    #[test]
+    fn output_with_timeout_captures_output() {
+        let output = output_with_timeout(
+            Command::new("sh").args(["-c", "echo hi"]),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "hi");
+    }
+
+    #[test]
+    fn output_with_timeout_kills_hung_command() {
+        let start = Instant::now();
+        let err = output_with_timeout(
+            Command::new("sh").args(["-c", "sleep 10"]),
+            Duration::from_millis(100),
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Timeout { .. }));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}